@@ -0,0 +1,118 @@
+//! Boolean backends compared on `sample_data/pinhole_lab.gdml` and on a
+//! drilling chain whose holes share their end faces with the plate.
+//!
+//! Run with `cargo bench --bench csg`. Each line gives the best of a few
+//! runs and the integrity report of the result: a backend that is fast but
+//! leaves holes in the mesh has not done the job.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use gdml_studio_backend::eval::engine::EvalEngine;
+use gdml_studio_backend::gdml::model::Solid;
+use gdml_studio_backend::gdml::parser::parse_gdml;
+use gdml_studio_backend::mesh::csg::{transform_mesh, BooleanBackend};
+use gdml_studio_backend::mesh::integrity::{analyze, MeshReport};
+use gdml_studio_backend::mesh::primitives::{box_mesh, tube_mesh};
+use gdml_studio_backend::mesh::quality::MeshQuality;
+use gdml_studio_backend::mesh::tessellator::tessellate_all_solids;
+use gdml_studio_backend::mesh::types::TriangleMesh;
+
+const BACKENDS: [BooleanBackend; 2] = [BooleanBackend::Bsp, BooleanBackend::Exact];
+
+const RUNS: usize = 5;
+
+/// The fastest of [`RUNS`] calls of `f`, and its last result.
+fn best_of<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (best, result.unwrap())
+}
+
+fn describe(report: &MeshReport) -> String {
+    let problems = report.problems();
+    format!(
+        "{:>6} tris  {:>14.3} mm^3  {}",
+        report.triangles,
+        report.volume,
+        if problems.is_empty() {
+            "closed".to_string()
+        } else {
+            problems.join(", ")
+        }
+    )
+}
+
+fn pinhole_lab() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../sample_data/pinhole_lab.gdml");
+    let doc = parse_gdml(&path).expect("pinhole_lab.gdml parses");
+    let mut engine = EvalEngine::new();
+    engine.evaluate_all(&doc.defines).expect("defines evaluate");
+    let booleans: Vec<&str> = doc
+        .solids
+        .solids
+        .iter()
+        .filter(|s| matches!(s, Solid::Boolean(_)))
+        .map(|s| s.name())
+        .collect();
+
+    println!("pinhole_lab.gdml, all solids");
+    for segments in [16, 32, 64, 128] {
+        for backend in BACKENDS {
+            let (time, (meshes, _)) = best_of(|| {
+                tessellate_all_solids(&doc.solids, &engine, &MeshQuality::fixed(segments), backend)
+                    .expect("tessellation succeeds")
+            });
+            println!("  segments {:>3}  {:<5?} {:>9.2?}", segments, backend, time);
+            for name in &booleans {
+                println!("    {:<12} {}", name, describe(&analyze(&meshes[*name])));
+            }
+        }
+    }
+}
+
+/// A 100 mm plate with `holes` bores right through it, drilled one after
+/// the other: every bore's end caps lie in the plate's faces.
+fn drilled_plate(backend: BooleanBackend, holes: usize) -> TriangleMesh {
+    let mut plate = box_mesh::tessellate_box(100.0, 100.0, 10.0);
+    let bore = tube_mesh::tessellate_tube(0.0, 3.0, 10.0, 0.0, 2.0 * std::f64::consts::PI, 24);
+    let side = (holes as f64).sqrt().ceil() as usize;
+    let pitch = 100.0 / side as f64;
+    for i in 0..holes {
+        let at = [
+            (i % side) as f64 * pitch - 50.0 + pitch / 2.0,
+            (i / side) as f64 * pitch - 50.0 + pitch / 2.0,
+            0.0,
+        ];
+        let (mesh, _note) = backend.subtract(&plate, &transform_mesh(&bore, at, [0.0; 3]));
+        plate = mesh;
+    }
+    plate
+}
+
+fn drilling_chain() {
+    println!("plate drilled through, one bore after another");
+    for holes in [4, 16, 36] {
+        for backend in BACKENDS {
+            let (time, plate) = best_of(|| drilled_plate(backend, holes));
+            println!(
+                "  {:>2} holes  {:<5?} {:>9.2?}  {}",
+                holes,
+                backend,
+                time,
+                describe(&analyze(&plate))
+            );
+        }
+    }
+}
+
+fn main() {
+    pinhole_lab();
+    println!();
+    drilling_chain();
+}
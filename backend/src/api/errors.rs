@@ -22,6 +22,13 @@ impl ApiError {
         }
     }
 
+    pub fn forbidden(msg: &str) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: msg.to_string(),
+        }
+    }
+
     pub fn internal(msg: &str) -> Self {
         Self {
             status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,23 +1,59 @@
 use axum::extract::{Query, State};
-use axum::response::Json;
+use axum::http::header;
+use axum::response::{IntoResponse, Json, Response};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use super::errors::ApiError;
 use crate::config;
+use crate::eval::dimensions;
 use crate::eval::engine::EvalEngine;
+use crate::eval::trace;
+use crate::gdml::constraints;
+use crate::gdml::geant4;
 use crate::gdml::materials as nist;
+use crate::gdml::mcnp;
 use crate::gdml::model::*;
+use crate::gdml::modular;
 use crate::gdml::parser;
+use crate::gdml::prune;
+use crate::gdml::references::{self, ItemId};
+use crate::gdml::rename;
+use crate::gdml::schema;
+use crate::gdml::structure::{include_basename, normalize_include_path};
+use crate::gdml::surfaces;
+use crate::gdml::tgeo;
+use crate::gdml::units;
+use crate::mesh::bake;
+use crate::mesh::bounds;
+use crate::mesh::bvh::SceneIndex;
+use crate::mesh::clearance;
+use crate::mesh::csg::BooleanBackend;
+use crate::mesh::drawing;
+use crate::mesh::edges;
+use crate::mesh::integrity::{self, RepairOptions};
+use crate::mesh::lod::{self, Lods};
+use crate::mesh::quality::MeshQuality;
+use crate::mesh::section;
 use crate::mesh::tessellator;
+use crate::mesh::types::TriangleMesh;
 use crate::state::app_state::{LoadedDocument, SharedState};
+use crate::state::local_files::{self, FileSet, FileWatcher, LocalSource};
 
 #[derive(Deserialize)]
 pub struct UploadFileRequest {
     pub filename: String,
     pub content: String,
     pub segments: Option<u32>,
+    /// Chord and angle tolerances and per-solid segment counts; the
+    /// configured default when absent.
+    pub quality: Option<MeshQuality>,
+    /// Boolean implementation to tessellate with; the configured default
+    /// when absent.
+    pub booleans: Option<BooleanBackend>,
 }
 
 #[derive(Deserialize)]
@@ -25,6 +61,8 @@ pub struct UploadFilesRequest {
     pub files: HashMap<String, String>,
     pub main_file: String,
     pub segments: Option<u32>,
+    pub quality: Option<MeshQuality>,
+    pub booleans: Option<BooleanBackend>,
 }
 
 fn definitions_equivalent<T: Serialize>(existing: &T, incoming: &T) -> Result<bool, ApiError> {
@@ -102,28 +140,16 @@ fn merge_by_name<T, F>(
     }
 }
 
-/// Merge a child GdmlDocument into the main document, resolving file_ref physvols.
+/// Merge an included document's definitions into the main document.
+///
+/// The child's own `<file>` references must already be resolved; placements
+/// that referenced this file are pointed at it by the caller.
 fn merge_child_into_main(
     main_doc: &mut GdmlDocument,
     child_doc: &GdmlDocument,
     file_ref_name: &str,
-    volname: &Option<String>,
     warnings: &mut Vec<String>,
 ) -> Result<(), ApiError> {
-    // Determine the child's target volume (volname or its world_ref)
-    let child_world = volname
-        .as_deref()
-        .unwrap_or(&child_doc.setup.world_ref)
-        .to_string();
-
-    if child_world.is_empty() {
-        warnings.push(format!(
-            "Child file '{}' has no world reference and no volname specified",
-            file_ref_name
-        ));
-        return Ok(());
-    }
-
     // Collect existing names to detect duplicates
     let existing_solids: HashSet<String> = main_doc
         .solids
@@ -193,6 +219,20 @@ fn merge_child_into_main(
         file_ref_name,
         |item| item.name.as_str(),
     )?;
+    merge_named_items(
+        &mut main_doc.defines.scales,
+        &child_doc.defines.scales,
+        "scale",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
+    merge_named_items(
+        &mut main_doc.defines.matrices,
+        &child_doc.defines.matrices,
+        "matrix",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
 
     // Merge elements, materials, solids and volumes by name.
     //
@@ -202,6 +242,13 @@ fn merge_child_into_main(
     // Flattening into one namespace means the first definition wins -- and doing
     // that silently rendered the second module with the first module's geometry.
     // Until modules are properly namespaced, at least say so.
+    merge_named_items(
+        &mut main_doc.materials.isotopes,
+        &child_doc.materials.isotopes,
+        "isotope",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
     merge_by_name(
         &mut main_doc.materials.elements,
         &child_doc.materials.elements,
@@ -238,6 +285,27 @@ fn merge_child_into_main(
         file_ref_name,
         warnings,
     );
+    merge_named_items(
+        &mut main_doc.solids.optical_surfaces,
+        &child_doc.solids.optical_surfaces,
+        "opticalsurface",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
+    merge_named_items(
+        &mut main_doc.structure.skin_surfaces,
+        &child_doc.structure.skin_surfaces,
+        "skinsurface",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
+    merge_named_items(
+        &mut main_doc.structure.border_surfaces,
+        &child_doc.structure.border_surfaces,
+        "bordersurface",
+        file_ref_name,
+        |item| item.name.as_str(),
+    )?;
 
     // Preserved-verbatim elements and the child's own parse warnings were not
     // carried over at all, so a child's <opticalsurface> vanished on save and its
@@ -249,17 +317,6 @@ fn merge_child_into_main(
         .skipped_unsupported
         .extend(child_doc.skipped_unsupported.iter().cloned());
 
-    // Now resolve file_ref physvols: replace file_ref with volume_ref pointing to child_world
-    for vol in &mut main_doc.structure.volumes {
-        for pv in &mut vol.physvols {
-            if let Some(ref fref) = pv.file_ref {
-                if fref.name == file_ref_name && &fref.volname == volname {
-                    pv.volume_ref = child_world.clone();
-                    pv.file_ref = None;
-                }
-            }
-        }
-    }
     Ok(())
 }
 
@@ -276,51 +333,199 @@ fn collect_file_refs(doc: &GdmlDocument) -> Vec<(String, Option<String>)> {
     refs
 }
 
-/// Resolve all file references recursively in breadth-first order.
-/// Newly discovered file_ref nodes from merged children are also processed.
-fn resolve_all_file_refs(
-    main_doc: &mut GdmlDocument,
-    child_docs: &HashMap<String, GdmlDocument>,
-) -> Result<Vec<String>, ApiError> {
-    let mut warnings = Vec::new();
-    let mut pending = VecDeque::new();
-    let mut queued: HashSet<(String, Option<String>)> = HashSet::new();
-    let mut processed: HashSet<(String, Option<String>)> = HashSet::new();
-
-    for fref in collect_file_refs(main_doc) {
-        if queued.insert(fref.clone()) {
-            pending.push_back(fref);
+/// Find the supplied document for a normalised include path.
+///
+/// An exact path match wins. A browser upload only carries bare file names, so
+/// failing that a unique basename match is accepted; two uploaded files sharing
+/// a basename are ambiguous and reported rather than guessed between.
+fn find_child_doc<'a>(
+    child_docs: &'a HashMap<String, GdmlDocument>,
+    path: &str,
+) -> Result<Option<&'a GdmlDocument>, String> {
+    if let Some((_, doc)) = child_docs
+        .iter()
+        .find(|(key, _)| normalize_include_path("", key) == path)
+    {
+        return Ok(Some(doc));
+    }
+    let basename = include_basename(path);
+    let candidates: Vec<(&String, &GdmlDocument)> = child_docs
+        .iter()
+        .filter(|(key, _)| include_basename(key) == basename)
+        .collect();
+    match candidates.as_slice() {
+        [] => Ok(None),
+        [(_, doc)] => Ok(Some(doc)),
+        many => {
+            let mut names: Vec<&str> = many.iter().map(|(k, _)| k.as_str()).collect();
+            names.sort_unstable();
+            Err(format!(
+                "Referenced file '{}' matches several provided files ({}); \
+                 provide them with their directories",
+                path,
+                names.join(", ")
+            ))
         }
     }
+}
+
+/// State threaded through a recursive include walk.
+struct IncludeWalk<'a> {
+    child_docs: &'a HashMap<String, GdmlDocument>,
+    /// Files currently being expanded, outermost first, for cycle detection.
+    stack: Vec<String>,
+    /// Files whose definitions are already merged. A file included twice --
+    /// typically with a different `volname` each time -- is merged once and
+    /// then only referenced.
+    merged: HashSet<String>,
+    provenance: Provenance,
+    warnings: Vec<String>,
+}
 
-    while let Some((ref_name, volname)) = pending.pop_front() {
-        let key = (ref_name.clone(), volname.clone());
-        queued.remove(&key);
-        if !processed.insert(key.clone()) {
+/// Resolve every `<file>` placement in an included file's structure, in the
+/// context of that file's path.
+fn resolve_placements(
+    main_doc: &mut GdmlDocument,
+    structure: &mut StructureSection,
+    doc_path: &str,
+    walk: &mut IncludeWalk,
+) -> Result<(), ApiError> {
+    for pv in structure
+        .volumes
+        .iter_mut()
+        .flat_map(|v| v.physvols.iter_mut())
+    {
+        let Some(fref) = pv.file_ref.clone() else {
             continue;
+        };
+        if let Some(target) = include_file(main_doc, doc_path, &fref, walk)? {
+            pv.volume_ref = target;
+            pv.file_ref = None;
+            pv.included = Some(fref);
         }
+    }
+    Ok(())
+}
 
-        if let Some(child_doc) = child_docs.get(&ref_name) {
-            merge_child_into_main(main_doc, child_doc, &ref_name, &volname, &mut warnings)?;
-        } else {
-            warnings.push(format!(
-                "Referenced file '{}' was not provided in the upload",
-                ref_name
+/// Merge the file `fref` points at (once) and return the volume it places.
+fn include_file(
+    main_doc: &mut GdmlDocument,
+    including: &str,
+    fref: &FileRef,
+    walk: &mut IncludeWalk,
+) -> Result<Option<String>, ApiError> {
+    let path = normalize_include_path(including, &fref.name);
+    if let Some(pos) = walk.stack.iter().position(|p| *p == path) {
+        let mut chain = walk.stack[pos..].to_vec();
+        chain.push(path);
+        return Err(ApiError::bad_request(&format!(
+            "Include cycle detected: {}",
+            chain.join(" -> ")
+        )));
+    }
+
+    let child_doc = match find_child_doc(walk.child_docs, &path) {
+        Ok(Some(doc)) => doc,
+        Ok(None) => {
+            walk.warnings.push(format!(
+                "Referenced file '{}' (included from '{}') was not provided",
+                path, including
             ));
+            return Ok(None);
         }
+        Err(message) => {
+            walk.warnings.push(message);
+            return Ok(None);
+        }
+    };
 
-        // A merge may introduce more unresolved file_ref nodes.
-        for discovered in collect_file_refs(main_doc) {
-            if processed.contains(&discovered) {
-                continue;
-            }
-            if queued.insert(discovered.clone()) {
-                pending.push_back(discovered);
-            }
+    // Determine the child's target volume (volname or its world_ref)
+    let target = fref
+        .volname
+        .clone()
+        .unwrap_or_else(|| child_doc.setup.world_ref.clone());
+    if target.is_empty() {
+        walk.warnings.push(format!(
+            "Child file '{}' has no world reference and no volname specified",
+            path
+        ));
+        return Ok(None);
+    }
+
+    if walk.merged.insert(path.clone()) {
+        let mut child = child_doc.clone();
+        walk.stack.push(path.clone());
+        resolve_placements(main_doc, &mut child.structure, &path, walk)?;
+        walk.stack.pop();
+
+        walk.provenance.files.push(IncludedFile {
+            path: path.clone(),
+            included_from: Some(including.to_string()),
+            world: child.setup.world_ref.clone(),
+        });
+        walk.provenance.record_document(&child, &path);
+        merge_child_into_main(main_doc, &child, &path, &mut walk.warnings)?;
+    }
+
+    if fref.volname.is_some() && walk.provenance.source_of("volume", &target) != Some(&path) {
+        walk.warnings.push(format!(
+            "Volume '{}' requested from '{}' is not defined in that file",
+            target, path
+        ));
+    }
+    Ok(Some(target))
+}
+
+/// Resolve all file references recursively.
+///
+/// Each reference is resolved relative to the file it appears in, so modules
+/// may include sub-modules from their own or sibling directories. The same file
+/// may be included any number of times; an include cycle is an error. The
+/// merged document records which file every item came from in `provenance`.
+fn resolve_all_file_refs(
+    main_doc: &mut GdmlDocument,
+    child_docs: &HashMap<String, GdmlDocument>,
+) -> Result<Vec<String>, ApiError> {
+    let main_path = normalize_include_path("", &main_doc.filename);
+    let mut walk = IncludeWalk {
+        child_docs,
+        stack: vec![main_path.clone()],
+        merged: HashSet::new(),
+        provenance: Provenance::default(),
+        warnings: Vec::new(),
+    };
+    walk.provenance.files.push(IncludedFile {
+        path: main_path.clone(),
+        included_from: None,
+        world: main_doc.setup.world_ref.clone(),
+    });
+    walk.provenance.record_document(main_doc, &main_path);
+
+    // The main file's placements are resolved in place. Included volumes are
+    // only ever appended, so the indices collected up front stay valid.
+    let main_refs: Vec<(usize, usize, FileRef)> = main_doc
+        .structure
+        .volumes
+        .iter()
+        .enumerate()
+        .flat_map(|(vi, v)| {
+            v.physvols
+                .iter()
+                .enumerate()
+                .filter_map(move |(pi, pv)| pv.file_ref.clone().map(|f| (vi, pi, f)))
+        })
+        .collect();
+    for (vi, pi, fref) in main_refs {
+        if let Some(target) = include_file(main_doc, &main_path, &fref, &mut walk)? {
+            let pv = &mut main_doc.structure.volumes[vi].physvols[pi];
+            pv.volume_ref = target;
+            pv.file_ref = None;
+            pv.included = Some(fref);
         }
     }
 
-    Ok(warnings)
+    main_doc.provenance = Some(walk.provenance);
+    Ok(walk.warnings)
 }
 
 fn ensure_material_ref_exists(doc: &GdmlDocument, candidate: &str) -> Result<(), ApiError> {
@@ -398,16 +603,74 @@ fn raw_unknown_warnings(doc: &GdmlDocument) -> Vec<String> {
     warnings
 }
 
-pub async fn upload_file(
-    State(state): State<SharedState>,
-    Json(req): Json<UploadFileRequest>,
-) -> Result<Json<Value>, ApiError> {
-    if !req.filename.ends_with(".gdml") {
-        return Err(ApiError::bad_request("Only .gdml files are supported"));
+/// Check every material `<property ref="..">` against the evaluated matrices.
+fn material_property_warnings(doc: &GdmlDocument, engine: &EvalEngine) -> Vec<String> {
+    doc.materials
+        .materials
+        .iter()
+        .flat_map(|mat| {
+            let owner = format!("Material \"{}\"", mat.name);
+            mat.properties
+                .iter()
+                .filter_map(|prop| surfaces::property_warning(&owner, prop, engine))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Schema issues in one uploaded file, as load warnings. The parser accepts
+/// all of these; Geant4 may not.
+fn schema_warnings(filename: &str, content: &str) -> Vec<String> {
+    schema::validate(content)
+        .iter()
+        .map(|issue| format!("{}, {}", filename, issue))
+        .collect()
+}
+
+/// Topology problems of the `<tessellated>` solids, as load warnings. Their
+/// facets come straight from the file, so a hole or a flipped facet is in the
+/// geometry Geant4 gets; those of the primitives and booleans are ours and
+/// are left to `/api/document/mesh-integrity`.
+fn tessellated_mesh_warnings(
+    solids: &SolidSection,
+    meshes: &HashMap<String, TriangleMesh>,
+) -> Vec<String> {
+    solids
+        .solids
+        .iter()
+        .filter(|s| matches!(s, Solid::Tessellated(_)))
+        .filter_map(|s| {
+            let report = integrity::analyze(meshes.get(s.name())?);
+            (!report.is_clean()).then(|| {
+                format!(
+                    "Tessellated solid \"{}\": {}. Geant4 needs a closed surface with \
+                     every facet facing outward.",
+                    s.name(),
+                    report.problems().join(", ")
+                )
+            })
+        })
+        .collect()
+}
+
+/// Tessellation settings for a request: its own `quality` or the configured
+/// tolerances, at its own `segments` or the configured count.
+fn mesh_quality(segments: Option<u32>, quality: Option<MeshQuality>) -> MeshQuality {
+    MeshQuality {
+        segments: segments.unwrap_or_else(config::mesh_segments),
+        ..quality.unwrap_or_else(config::mesh_quality)
     }
+}
 
+/// Parse, evaluate and tessellate a single GDML file.
+fn load_single_document(
+    filename: &str,
+    content: &str,
+    quality: &MeshQuality,
+    booleans: Option<BooleanBackend>,
+) -> Result<LoadedDocument, ApiError> {
     // Parse GDML from uploaded content
-    let doc = parser::parse_gdml_from_bytes(req.content.as_bytes(), req.filename.clone())
+    let doc = parser::parse_gdml_from_bytes(content.as_bytes(), filename.to_string())
         .map_err(|e| ApiError::bad_request(&format!("Parse error: {}", e)))?;
 
     // Check for unresolved file references
@@ -431,74 +694,67 @@ pub async fn upload_file(
     // Expand <loop> for the preview. The parsed `doc` keeps its loops verbatim
     // so the export stays faithful; geometry is built from the twin.
     let mut loop_warnings = Vec::new();
-    let render = build_render_document(&req.content, &req.filename, &engine, &mut loop_warnings);
+    let render = build_render_document(content, filename, &engine, &mut loop_warnings);
     let geometry = render.as_ref().unwrap_or(&doc);
 
     // Tessellate solids
-    let segments = req.segments.unwrap_or_else(config::mesh_segments);
+    let booleans = booleans.unwrap_or_else(config::boolean_backend);
     let (meshes, mut warnings) =
-        tessellator::tessellate_all_solids(&geometry.solids, &engine, segments)
+        tessellator::tessellate_all_solids(&geometry.solids, &engine, quality, booleans)
             .map_err(|e| ApiError::internal(&format!("Tessellation error: {}", e)))?;
     warnings.append(&mut loop_warnings);
     // Surface non-fatal expression-evaluation failures (treated as 0) to the user.
     warnings.extend(engine.take_warnings());
     warnings.extend(raw_unknown_warnings(&doc));
+    warnings.extend(material_property_warnings(&doc, &engine));
+    warnings.extend(surfaces::check_surfaces(&doc, &engine));
+    warnings.extend(constraints::solid_warnings(&doc.solids, &engine));
+    warnings.extend(tessellated_mesh_warnings(&geometry.solids, &meshes));
     if doc.setup.world_ref.is_empty() {
         warnings.push("No world volume reference found (<setup>/<world> missing or empty); the geometry may not display.".to_string());
     }
+    warnings.extend(schema_warnings(filename, content));
     warnings.extend(extra_warnings);
 
-    let summary = json!({
-        "filename": doc.filename,
-        "defines_count": doc.defines.constants.len() + doc.defines.quantities.len()
-            + doc.defines.variables.len() + doc.defines.expressions.len(),
-        "positions_count": doc.defines.positions.len(),
-        "rotations_count": doc.defines.rotations.len(),
-        "materials_count": doc.materials.materials.len(),
-        "elements_count": doc.materials.elements.len(),
-        "solids_count": doc.solids.solids.len(),
-        "volumes_count": doc.structure.volumes.len(),
-        "meshes_count": meshes.len(),
-        "world_ref": doc.setup.world_ref,
-        "warnings": warnings,
-    });
-
-    let mut state_w = state.write().await;
-    state_w.loaded = Some(LoadedDocument {
+    let mut loaded = LoadedDocument {
         document: doc,
         render,
         engine,
         meshes,
+        quality: quality.clone(),
+        booleans,
+        spatial: SceneIndex::default(),
+        lods: HashMap::new(),
+        edges: HashMap::new(),
         warnings,
-        file_path: req.filename,
-    });
-
-    Ok(Json(summary))
+        file_path: filename.to_string(),
+        local: None,
+    };
+    reindex(&mut loaded, &[]);
+    Ok(loaded)
 }
 
-pub async fn upload_files(
-    State(state): State<SharedState>,
-    Json(req): Json<UploadFilesRequest>,
-) -> Result<Json<Value>, ApiError> {
-    if !req.main_file.ends_with(".gdml") {
-        return Err(ApiError::bad_request("Only .gdml files are supported"));
-    }
-
-    let main_content = req
-        .files
-        .get(&req.main_file)
+/// Parse a main file and merge every `<file>` it reaches from `files`, then
+/// evaluate and tessellate the merged document.
+fn load_multi_document(
+    files: &HashMap<String, String>,
+    main_file: &str,
+    quality: &MeshQuality,
+    booleans: Option<BooleanBackend>,
+) -> Result<LoadedDocument, ApiError> {
+    let main_content = files
+        .get(main_file)
         .ok_or_else(|| ApiError::bad_request("Main file not found in uploaded files"))?;
 
     // Parse the main file
     let mut main_doc =
-        parser::parse_gdml_from_bytes(main_content.as_bytes(), req.main_file.clone()).map_err(
-            |e| ApiError::bad_request(&format!("Parse error in {}: {}", req.main_file, e)),
-        )?;
+        parser::parse_gdml_from_bytes(main_content.as_bytes(), main_file.to_string())
+            .map_err(|e| ApiError::bad_request(&format!("Parse error in {}: {}", main_file, e)))?;
 
     // Parse all other files into a lookup map
     let mut child_docs: HashMap<String, GdmlDocument> = HashMap::new();
-    for (name, content) in &req.files {
-        if name != &req.main_file {
+    for (name, content) in files {
+        if name != main_file {
             match parser::parse_gdml_from_bytes(content.as_bytes(), name.clone()) {
                 Ok(doc) => {
                     child_docs.insert(name.clone(), doc);
@@ -527,8 +783,7 @@ pub async fn upload_files(
     // and a loop's bounds may reference defines that only exist after the
     // merge. Rather than expand each file against a partial symbol table and be
     // wrong in the cross-file case, say so and render unexpanded.
-    let loop_files: Vec<&str> = req
-        .files
+    let loop_files: Vec<&str> = files
         .iter()
         .filter(|(_, c)| c.contains("<loop"))
         .map(|(n, _)| n.as_str())
@@ -542,56 +797,120 @@ pub async fn upload_files(
     }
 
     // Tessellate solids
-    let segments = req.segments.unwrap_or_else(config::mesh_segments);
+    let booleans = booleans.unwrap_or_else(config::boolean_backend);
     let (meshes, mut warnings) =
-        tessellator::tessellate_all_solids(&main_doc.solids, &engine, segments)
+        tessellator::tessellate_all_solids(&main_doc.solids, &engine, quality, booleans)
             .map_err(|e| ApiError::internal(&format!("Tessellation error: {}", e)))?;
     warnings.append(&mut loop_warnings);
     // Surface non-fatal expression-evaluation failures (treated as 0) to the user.
     warnings.extend(engine.take_warnings());
     warnings.extend(raw_unknown_warnings(&main_doc));
+    warnings.extend(material_property_warnings(&main_doc, &engine));
+    warnings.extend(surfaces::check_surfaces(&main_doc, &engine));
+    warnings.extend(constraints::solid_warnings(&main_doc.solids, &engine));
+    warnings.extend(tessellated_mesh_warnings(&main_doc.solids, &meshes));
     if main_doc.setup.world_ref.is_empty() {
         warnings.push("No world volume reference found (<setup>/<world> missing or empty); the geometry may not display.".to_string());
     }
+    let mut names: Vec<&String> = files.keys().collect();
+    names.sort();
+    for name in names {
+        warnings.extend(schema_warnings(name, &files[name]));
+    }
     warnings.extend(merge_warnings);
 
-    let summary = json!({
-        "filename": main_doc.filename,
-        "defines_count": main_doc.defines.constants.len() + main_doc.defines.quantities.len()
-            + main_doc.defines.variables.len() + main_doc.defines.expressions.len(),
-        "positions_count": main_doc.defines.positions.len(),
-        "rotations_count": main_doc.defines.rotations.len(),
-        "materials_count": main_doc.materials.materials.len(),
-        "elements_count": main_doc.materials.elements.len(),
-        "solids_count": main_doc.solids.solids.len(),
-        "volumes_count": main_doc.structure.volumes.len(),
-        "meshes_count": meshes.len(),
-        "world_ref": main_doc.setup.world_ref,
-        "warnings": warnings,
-    });
-
-    let mut state_w = state.write().await;
-    state_w.loaded = Some(LoadedDocument {
+    let mut loaded = LoadedDocument {
         document: main_doc,
         render: None,
         engine,
         meshes,
+        quality: quality.clone(),
+        booleans,
+        spatial: SceneIndex::default(),
+        lods: HashMap::new(),
+        edges: HashMap::new(),
         warnings,
-        file_path: req.main_file,
-    });
+        file_path: main_file.to_string(),
+        local: None,
+    };
+    reindex(&mut loaded, &[]);
+    Ok(loaded)
+}
 
-    Ok(Json(summary))
+/// Bring `loaded.spatial`, `loaded.lods` and `loaded.edges` in line with the
+/// document after an edit, indexing the facets of `remeshed` solids and
+/// building their levels of detail and feature edges again.
+fn reindex(loaded: &mut LoadedDocument, remeshed: &[&str]) {
+    let scene_graph = build_scene_graph(
+        loaded.geometry(),
+        &loaded.document.materials,
+        &loaded.engine,
+        &mut Vec::new(),
+    );
+    loaded
+        .spatial
+        .update(&scene_graph, &loaded.meshes, remeshed);
+    let geometry = loaded.render.as_ref().unwrap_or(&loaded.document);
+    lod::update(
+        &mut loaded.lods,
+        &geometry.solids,
+        &loaded.engine,
+        &loaded.quality,
+        &loaded.meshes,
+        remeshed,
+    );
+    edges::update(&mut loaded.edges, &loaded.meshes, remeshed);
 }
 
-pub async fn get_summary(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
-    let state_r = state.read().await;
-    let loaded = state_r
-        .loaded
-        .as_ref()
-        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+/// Tessellate the solids in `names` again with the document's current values,
+/// replacing their meshes. A solid that no longer tessellates loses its mesh,
+/// as it would on load. Returns the tessellation warnings.
+///
+/// Only the named solids and those they are built from are tessellated; the
+/// components' meshes are needed to rebuild a boolean but are not replaced.
+fn remesh_solids(loaded: &mut LoadedDocument, names: &[String]) -> Vec<String> {
+    if names.is_empty() {
+        return Vec::new();
+    }
+    let geometry = loaded.geometry();
+    let needed = references::reachable_from(
+        &references::collect_references(geometry),
+        names.iter().map(|n| ItemId::new("solid", n)),
+        |r| r.to.kind == "solid",
+    );
+    let section = SolidSection {
+        solids: geometry
+            .solids
+            .solids
+            .iter()
+            .filter(|s| needed.contains(&ItemId::new("solid", s.name())))
+            .cloned()
+            .collect(),
+        optical_surfaces: Vec::new(),
+    };
+    let (mut meshes, mut warnings) = match tessellator::tessellate_all_solids(
+        &section,
+        &loaded.engine,
+        &loaded.quality,
+        loaded.booleans,
+    ) {
+        Ok(result) => result,
+        Err(e) => (HashMap::new(), vec![format!("Tessellation error: {}", e)]),
+    };
+    // Boolean fallbacks are reported through the engine.
+    warnings.extend(loaded.engine.take_warnings());
+    for name in names {
+        match meshes.remove(name) {
+            Some(mesh) => loaded.meshes.insert(name.clone(), mesh),
+            None => loaded.meshes.remove(name),
+        };
+    }
+    warnings
+}
 
+fn document_summary(loaded: &LoadedDocument) -> Value {
     let doc = &loaded.document;
-    Ok(Json(json!({
+    json!({
         "filename": doc.filename,
         "defines_count": doc.defines.constants.len() + doc.defines.quantities.len()
             + doc.defines.variables.len() + doc.defines.expressions.len(),
@@ -604,7 +923,53 @@ pub async fn get_summary(State(state): State<SharedState>) -> Result<Json<Value>
         "meshes_count": loaded.meshes.len(),
         "world_ref": doc.setup.world_ref,
         "warnings": loaded.warnings,
-    })))
+    })
+}
+
+pub async fn upload_file(
+    State(state): State<SharedState>,
+    Json(req): Json<UploadFileRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if !req.filename.ends_with(".gdml") {
+        return Err(ApiError::bad_request("Only .gdml files are supported"));
+    }
+
+    let quality = mesh_quality(req.segments, req.quality);
+    let loaded = load_single_document(&req.filename, &req.content, &quality, req.booleans)?;
+    let summary = document_summary(&loaded);
+
+    let mut state_w = state.write().await;
+    state_w.loaded = Some(loaded);
+
+    Ok(Json(summary))
+}
+
+pub async fn upload_files(
+    State(state): State<SharedState>,
+    Json(req): Json<UploadFilesRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if !req.main_file.ends_with(".gdml") {
+        return Err(ApiError::bad_request("Only .gdml files are supported"));
+    }
+
+    let quality = mesh_quality(req.segments, req.quality);
+    let loaded = load_multi_document(&req.files, &req.main_file, &quality, req.booleans)?;
+    let summary = document_summary(&loaded);
+
+    let mut state_w = state.write().await;
+    state_w.loaded = Some(loaded);
+
+    Ok(Json(summary))
+}
+
+pub async fn get_summary(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    Ok(Json(document_summary(loaded)))
 }
 
 /// Scene graph without the mesh buffers.
@@ -630,13 +995,88 @@ pub async fn get_scene(State(state): State<SharedState>) -> Result<Json<Value>,
         &mut scene_warnings,
     );
 
+    let solid_bounds =
+        bounds::solid_bounds(&loaded.geometry().solids, &loaded.engine, &loaded.meshes);
+    let scene_bounds = bounds::scene_bounds(&scene_graph, &solid_bounds);
+
     Ok(Json(json!({
         "scene_graph": scene_graph,
+        "bounds": {
+            "solids": solid_bounds,
+            "instances": scene_bounds.instances,
+            "world": scene_bounds.world,
+        },
         "warnings": scene_warnings,
     })))
 }
 
-pub async fn get_meshes(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+#[derive(Deserialize, Default)]
+pub struct BoundsQuery {
+    /// Only this instance path's box.
+    pub instance: Option<String>,
+    /// Only this solid's local box.
+    pub solid: Option<String>,
+}
+
+/// Bounding boxes: each solid's local box, each placed node's oriented and
+/// axis-aligned box and the world's extent, or one of them on request.
+pub async fn get_bounds(
+    State(state): State<SharedState>,
+    Query(query): Query<BoundsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let mut solid_bounds =
+        bounds::solid_bounds(&loaded.geometry().solids, &loaded.engine, &loaded.meshes);
+    if let Some(name) = &query.solid {
+        let found = solid_bounds
+            .remove(name)
+            .ok_or_else(|| ApiError::not_found(&format!("No bounds for solid '{}'", name)))?;
+        return Ok(Json(json!(found)));
+    }
+
+    let mut scene_warnings = Vec::new();
+    let scene_graph = build_scene_graph(
+        loaded.geometry(),
+        &loaded.document.materials,
+        &loaded.engine,
+        &mut scene_warnings,
+    );
+    let scene_bounds = bounds::scene_bounds(&scene_graph, &solid_bounds);
+    if let Some(id) = &query.instance {
+        let found = scene_bounds
+            .instances
+            .into_iter()
+            .find(|b| &b.instance_id == id)
+            .ok_or_else(|| ApiError::not_found(&format!("No bounds for instance '{}'", id)))?;
+        return Ok(Json(json!(found)));
+    }
+
+    Ok(Json(json!({
+        "solids": solid_bounds,
+        "instances": scene_bounds.instances,
+        "world": scene_bounds.world,
+        "warnings": scene_warnings,
+    })))
+}
+
+#[derive(Deserialize, Default)]
+pub struct MeshesQuery {
+    /// Also send coarser levels of detail for each solid.
+    #[serde(default)]
+    pub lod: bool,
+    /// Crease angle in degrees for the feature edges; 30 when absent.
+    pub edge_angle: Option<f64>,
+}
+
+pub async fn get_meshes(
+    State(state): State<SharedState>,
+    Query(query): Query<MeshesQuery>,
+) -> Result<Json<Value>, ApiError> {
     let state_r = state.read().await;
     let loaded = state_r
         .loaded
@@ -667,8 +1107,17 @@ pub async fn get_meshes(State(state): State<SharedState>) -> Result<Json<Value>,
         positions: &'a [f32],
         normals: &'a [f32],
         indices: &'a [u32],
+        /// Feature edges, as pairs of indices into `positions`.
+        edges: Cow<'a, [u32]>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lod: Option<&'a Lods>,
     }
 
+    // Edges at the default angle are kept with the meshes; another angle is
+    // worked out for this request only.
+    let edge_angle = query
+        .edge_angle
+        .filter(|a| *a != edges::DEFAULT_CREASE_ANGLE);
     let meshes: HashMap<&str, MeshRef> = loaded
         .meshes
         .iter()
@@ -679,6 +1128,14 @@ pub async fn get_meshes(State(state): State<SharedState>) -> Result<Json<Value>,
                     positions: &mesh.positions,
                     normals: &mesh.normals,
                     indices: &mesh.indices,
+                    edges: match (edge_angle, loaded.edges.get(name)) {
+                        (None, Some(cached)) => Cow::Borrowed(cached.as_slice()),
+                        (angle, _) => Cow::Owned(edges::feature_edges(
+                            mesh,
+                            angle.unwrap_or(edges::DEFAULT_CREASE_ANGLE),
+                        )),
+                    },
+                    lod: loaded.lods.get(name).filter(|_| query.lod),
                 },
             )
         })
@@ -693,8 +1150,45 @@ pub async fn get_meshes(State(state): State<SharedState>) -> Result<Json<Value>,
     Ok(Json(body))
 }
 
-pub async fn get_defines(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
-    let state_r = state.read().await;
+#[derive(Deserialize, Default)]
+pub struct SceneBakeQuery {
+    /// Solids of at most this many facets are merged; 256 when absent.
+    pub merge_triangles: Option<usize>,
+}
+
+/// The scene as instanced and merged draw batches, for geometries with too
+/// many placements to draw one by one.
+pub async fn bake_scene(
+    State(state): State<SharedState>,
+    Query(query): Query<SceneBakeQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let mut scene_warnings = Vec::new();
+    let scene_graph = build_scene_graph(
+        loaded.geometry(),
+        &loaded.document.materials,
+        &loaded.engine,
+        &mut scene_warnings,
+    );
+    let merge_triangles = query
+        .merge_triangles
+        .unwrap_or(bake::DEFAULT_MERGE_TRIANGLES);
+    let baked = bake::bake(&scene_graph, &loaded.meshes, merge_triangles);
+
+    Ok(Json(json!({
+        "instanced": baked.instanced,
+        "merged": baked.merged,
+        "warnings": scene_warnings,
+    })))
+}
+
+pub async fn get_defines(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
     let loaded = state_r
         .loaded
         .as_ref()
@@ -774,8 +1268,304 @@ pub async fn get_solids(State(state): State<SharedState>) -> Result<Json<Value>,
         .as_ref()
         .ok_or_else(|| ApiError::not_found("No document loaded"))?;
 
+    // Each solid carries what Geant4's constructor would make of its
+    // parameters; `warnings` lists the same issues by name.
+    let engine = &loaded.engine;
+    let solids = loaded
+        .document
+        .solids
+        .solids
+        .iter()
+        .map(|solid| {
+            let mut value = serde_json::to_value(solid)
+                .map_err(|e| ApiError::internal(&format!("Failed to serialize solid: {}", e)))?;
+            if let Some(obj) = value.as_object_mut() {
+                obj.insert(
+                    "issues".to_string(),
+                    json!(constraints::check_solid(solid, engine)),
+                );
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<Value>, ApiError>>()?;
+    Ok(Json(json!({
+        "solids": solids,
+        "warnings": constraints::solid_warnings(&loaded.document.solids, engine),
+    })))
+}
+
+#[derive(Deserialize, Default)]
+pub struct MeshIntegrityQuery {
+    pub solid: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MeshRepairRequest {
+    /// Solids to repair; all of them when absent.
+    pub solids: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub options: RepairOptions,
+}
+
+/// The meshed solids named by `names`, or all of them, in document order.
+fn meshed_solids<'a>(
+    loaded: &'a LoadedDocument,
+    names: Option<&[String]>,
+) -> Result<Vec<&'a str>, ApiError> {
+    if let Some(missing) = names
+        .unwrap_or_default()
+        .iter()
+        .find(|n| !loaded.meshes.contains_key(n.as_str()))
+    {
+        return Err(ApiError::not_found(&format!(
+            "No mesh for solid \"{}\"",
+            missing
+        )));
+    }
+    Ok(loaded
+        .geometry()
+        .solids
+        .solids
+        .iter()
+        .map(|s| s.name())
+        .filter(|n| loaded.meshes.contains_key(*n))
+        .filter(|n| names.is_none_or(|names| names.iter().any(|m| m == n)))
+        .collect())
+}
+
+/// Topology report of each solid's mesh: open and non-manifold edges, flipped
+/// and degenerate facets, self-intersections and duplicate vertices.
+pub async fn get_mesh_integrity(
+    State(state): State<SharedState>,
+    Query(query): Query<MeshIntegrityQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let names = query.solid.map(|n| vec![n]);
+    let solids: Vec<Value> = meshed_solids(loaded, names.as_deref())?
+        .into_iter()
+        .map(|name| {
+            let report = integrity::analyze(&loaded.meshes[name]);
+            json!({
+                "name": name,
+                "problems": report.problems(),
+                "report": report,
+            })
+        })
+        .collect();
+    let with_problems = solids
+        .iter()
+        .filter(|s| s["problems"].as_array().is_some_and(|p| !p.is_empty()))
+        .count();
+    Ok(Json(json!({
+        "count": solids.len(),
+        "with_problems": with_problems,
+        "solids": solids,
+    })))
+}
+
+/// Weld, re-orient and drop degenerate facets in the meshes of the given
+/// solids. Only the preview meshes change; the document does not, and a
+/// reload tessellates afresh.
+pub async fn repair_meshes(
+    State(state): State<SharedState>,
+    Json(req): Json<MeshRepairRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let names: Vec<String> = meshed_solids(loaded, req.solids.as_deref())?
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut repaired = Vec::new();
+    for name in &names {
+        let Some(mesh) = loaded.meshes.get_mut(name) else {
+            continue;
+        };
+        let before = integrity::analyze(mesh);
+        *mesh = integrity::repair(mesh, &req.options);
+        let after = integrity::analyze(mesh);
+        repaired.push(json!({
+            "name": name,
+            "before": before,
+            "after": after,
+        }));
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    reindex(loaded, &names);
+    Ok(Json(json!({
+        "ok": true,
+        "repaired": repaired,
+    })))
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionFormat {
+    #[default]
+    Json,
+    Svg,
+    Dxf,
+}
+
+#[derive(Deserialize)]
+pub struct SectionRequest {
+    /// A point on the plane, in mm; the world origin when absent.
+    #[serde(default)]
+    pub origin: [f64; 3],
+    /// The plane's normal; +z when absent.
+    #[serde(default = "default_section_normal")]
+    pub normal: [f64; 3],
+    #[serde(default)]
+    pub format: SectionFormat,
+}
+
+fn default_section_normal() -> [f64; 3] {
+    [0.0, 0.0, 1.0]
+}
+
+/// Cut the placed geometry with a plane: the outlines of every instance as
+/// JSON, or the whole section drawn as SVG or DXF.
+pub async fn section_plane(
+    State(state): State<SharedState>,
+    Json(req): Json<SectionRequest>,
+) -> Result<Response, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let geometry = loaded.geometry();
+    let mut warnings = Vec::new();
+    let scene_graph = build_scene_graph(
+        geometry,
+        &loaded.document.materials,
+        &loaded.engine,
+        &mut warnings,
+    );
+    let cut = section::section(&scene_graph, &loaded.meshes, req.origin, req.normal)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+
+    let (body, content_type, extension) = match req.format {
+        SectionFormat::Json => {
+            return Ok(Json(json!({
+                "section": cut,
+                "warnings": warnings,
+            }))
+            .into_response());
+        }
+        SectionFormat::Svg => (drawing::to_svg(&cut), "image/svg+xml", "svg"),
+        SectionFormat::Dxf => (drawing::to_dxf(&cut), "application/dxf", "dxf"),
+    };
+    let stem = loaded
+        .document
+        .filename
+        .strip_suffix(".gdml")
+        .unwrap_or(&loaded.document.filename);
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}-section.{}\"",
+                    stem.replace('"', ""),
+                    extension
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct PickRequest {
+    /// Where the ray starts, in mm.
+    pub origin: [f64; 3],
+    /// Need not be of unit length.
+    pub direction: [f64; 3],
+    /// How far along the ray to look, in mm; without limit when absent.
+    pub max_distance: Option<f64>,
+}
+
+/// Cast a ray into the placed scene and report the first surface it meets,
+/// with the instance, volume and material there; `hit` is null on a miss.
+pub async fn pick_ray(
+    State(state): State<SharedState>,
+    Json(req): Json<PickRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    if !req.origin.iter().all(|c| c.is_finite())
+        || !req.direction.iter().all(|c| c.is_finite())
+        || req.direction == [0.0; 3]
+    {
+        return Err(ApiError::bad_request(
+            "The ray needs a finite origin and a non-zero direction",
+        ));
+    }
+    let max_distance = req.max_distance.unwrap_or(f64::INFINITY);
+    let Some(hit) = loaded
+        .spatial
+        .ray(&loaded.meshes, req.origin, req.direction, max_distance)
+    else {
+        return Ok(Json(json!({ "hit": null })));
+    };
+    Ok(Json(json!({ "hit": hit })))
+}
+
+#[derive(Deserialize, Default)]
+pub struct ClearanceQuery {
+    /// Report surfaces closer than this, in mm; 1 when absent.
+    pub threshold: Option<f64>,
+}
+
+/// Sibling volumes, and daughters and the inside of their mother, that come
+/// closer than the threshold, closest first, with the closest points.
+pub async fn get_clearances(
+    State(state): State<SharedState>,
+    Query(query): Query<ClearanceQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let threshold = query.threshold.unwrap_or(clearance::DEFAULT_CLEARANCE);
+    if !threshold.is_finite() || threshold < 0.0 {
+        return Err(ApiError::bad_request(
+            "The threshold must be a distance of 0 mm or more",
+        ));
+    }
+    let mut scene_warnings = Vec::new();
+    let scene_graph = build_scene_graph(
+        loaded.geometry(),
+        &loaded.document.materials,
+        &loaded.engine,
+        &mut scene_warnings,
+    );
+    let report = clearance::clearances(&scene_graph, &loaded.meshes, &loaded.spatial, threshold);
+
     Ok(Json(json!({
-        "solids": loaded.document.solids.solids,
+        "threshold": threshold,
+        "clearances": report.clearances,
+        "pairs_checked": report.pairs_checked,
+        "warnings": scene_warnings,
     })))
 }
 
@@ -792,6 +1582,170 @@ pub async fn get_structure(State(state): State<SharedState>) -> Result<Json<Valu
     })))
 }
 
+/// Which file every item of the loaded document came from.
+///
+/// A single-file document reports everything as coming from that file, so the
+/// client does not have to special-case it.
+pub async fn get_provenance(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &loaded.document;
+    let provenance = doc.provenance.clone().unwrap_or_else(|| {
+        let mut single = Provenance::default();
+        single.files.push(IncludedFile {
+            path: doc.filename.clone(),
+            included_from: None,
+            world: doc.setup.world_ref.clone(),
+        });
+        single.record_document(doc, &doc.filename);
+        single
+    });
+
+    Ok(Json(json!({
+        "files": provenance.files,
+        "items": provenance.items,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct ReferencesQuery {
+    pub name: String,
+    /// Restrict to one kind (`solid`, `material`, `constant`...) when the
+    /// name is shared.
+    pub kind: Option<String>,
+}
+
+/// GET /api/document/references?name=..[&kind=..] — everything that refers
+/// to the items called `name`, with where the reference sits.
+///
+/// `referrers` are the direct references, each with the attribute holding it
+/// and the file declaring the referrer; `dependents` is everything affected
+/// transitively, e.g. the volumes whose solids use a constant. A name that is
+/// referenced but never defined is reported with `defined: false`.
+pub async fn get_references(
+    State(state): State<SharedState>,
+    Query(query): Query<ReferencesQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &loaded.document;
+    let mut declared = Provenance::default();
+    declared.record_document(doc, &doc.filename);
+    let source = |kind: &str, name: &str| -> String {
+        doc.provenance
+            .as_ref()
+            .and_then(|p| p.source_of(kind, name))
+            .unwrap_or(&doc.filename)
+            .to_string()
+    };
+
+    let index = references::ReferenceIndex::build(doc);
+    let mut items: BTreeSet<ItemId> = declared
+        .items
+        .iter()
+        .filter(|(_, names)| names.contains_key(&query.name))
+        .filter_map(|(kind, _)| references::item_kind(kind))
+        .map(|kind| ItemId::new(kind, &query.name))
+        .collect();
+    if surfaces::physvol_names(doc).contains(&query.name) {
+        items.insert(ItemId::new("physvol", &query.name));
+    }
+    items.extend(index.targets_named(&query.name).cloned());
+    items.retain(|id| query.kind.as_deref().is_none_or(|k| k == id.kind));
+    if items.is_empty() {
+        return Err(ApiError::not_found(&format!(
+            "Nothing named '{}' is defined or referenced",
+            query.name
+        )));
+    }
+
+    let items: Vec<Value> = items
+        .iter()
+        .map(|id| {
+            let defined = declared.source_of(id.kind, &id.name).is_some() || id.kind == "physvol";
+            let referrers: Vec<Value> = index
+                .referrers(id)
+                .iter()
+                .map(|r| {
+                    json!({
+                        "kind": r.from.kind,
+                        "name": r.from.name,
+                        "attribute": r.attribute,
+                        "file": source(r.from.kind, &r.from.name),
+                    })
+                })
+                .collect();
+            json!({
+                "kind": id.kind,
+                "name": id.name,
+                "defined": defined,
+                "file": defined.then(|| source(id.kind, &id.name)),
+                "referrers": referrers,
+                "dependents": index.dependents(id),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({ "name": query.name, "items": items })))
+}
+
+/// GET /api/document/diagnostics/units — dimensional analysis of every define
+/// and attribute expression.
+///
+/// Computed on request rather than at load: Geant4 accepts all of these files,
+/// so they are findings to review, not load warnings.
+pub async fn get_unit_diagnostics(
+    State(state): State<SharedState>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let diagnostics = dimensions::check_document(&loaded.document);
+    Ok(Json(json!({
+        "count": diagnostics.len(),
+        "diagnostics": diagnostics,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct EvaluateExpressionRequest {
+    pub expression: String,
+}
+
+/// POST /api/document/evaluate — evaluate an expression against the loaded
+/// document's defines and explain the value: the identifiers it used, the
+/// defines it depends on in evaluation order, and why any of it failed.
+///
+/// Read-only, and a failure is part of the answer rather than an error status:
+/// the point is to see why something evaluates to the fallback 0.
+pub async fn evaluate_expression(
+    State(state): State<SharedState>,
+    Json(req): Json<EvaluateExpressionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    if req.expression.trim().is_empty() {
+        return Err(ApiError::bad_request("Expression is empty"));
+    }
+    let traced = trace::trace_expression(&loaded.document, &loaded.engine, &req.expression);
+    Ok(Json(json!(traced)))
+}
+
 // ─── Scene graph builder ─────────────────────────────────────────────────────
 
 /// Build the loop-expanded twin of a freshly parsed document.
@@ -850,20 +1804,20 @@ fn build_scene_graph(
         .map(|v| (v.name.as_str(), v))
         .collect();
 
-    // Build material name → density (g/cm³) lookup
+    // Build material name → density (g/cm³) lookup. `<D>` is an expression
+    // in `unit` (default g/cm3, as in G4GDMLReadMaterials::DRead); `<Dref>`
+    // names a define that is already in internal units.
     let density_map: HashMap<&str, f64> = materials
         .materials
         .iter()
         .filter_map(|m| {
-            let d = m.density.as_ref()?;
-            let val = d.value.parse::<f64>().ok()?;
-            // Convert to g/cm³ if unit is specified
-            let density = match d.unit.as_deref() {
-                Some("kg/m3") | Some("kg/m³") => val / 1000.0,
-                Some("mg/cm3") | Some("mg/cm³") => val / 1000.0,
-                _ => val, // default g/cm³
+            let internal = if let Some(d) = m.density.as_ref() {
+                let val = engine.eval_expr(&d.value).ok()?;
+                units::apply_unit(val, d.unit.as_deref().unwrap_or("g/cm3"))
+            } else {
+                engine.context.get(m.density_ref.as_deref()?)?
             };
-            Some((m.name.as_str(), density))
+            Some((m.name.as_str(), units::in_unit(internal, "g/cm3")))
         })
         .collect();
 
@@ -1390,24 +2344,6 @@ fn ensure_element_name_available(
     Ok(())
 }
 
-fn cascade_component_ref_rename(materials: &mut [Material], old_name: &str, new_name: &str) {
-    if old_name == new_name {
-        return;
-    }
-    for mat in materials {
-        for component in &mut mat.components {
-            match component {
-                MaterialComponent::Fraction { ref_name, .. }
-                | MaterialComponent::Composite { ref_name, .. } => {
-                    if ref_name == old_name {
-                        *ref_name = new_name.to_string();
-                    }
-                }
-            }
-        }
-    }
-}
-
 fn is_name_referenced_in_material_components(materials: &[Material], name: &str) -> bool {
     materials.iter().any(|m| {
         m.components.iter().any(|c| match c {
@@ -1446,24 +2382,15 @@ pub async fn update_material(
         .position(|m| m.name == old_name)
         .ok_or_else(|| ApiError::not_found(&format!("Material '{}' not found", req.name)))?;
 
+    // Cascade the rename to volumes, mixture components and everything else
+    // naming the material before the edited definition replaces it.
+    rename::rename(&mut loaded.document, "material", &old_name, &new_name)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
     loaded.document.materials.materials[mat_idx] = req.material;
+    // The index carries each instance's material and density.
+    reindex(loaded, &[]);
 
-    if old_name != new_name {
-        // Cascade rename to volumes
-        for vol in &mut loaded.document.structure.volumes {
-            if vol.material_ref == old_name {
-                vol.material_ref = new_name.clone();
-            }
-        }
-
-        // Cascade rename to material component references.
-        cascade_component_ref_rename(
-            &mut loaded.document.materials.materials,
-            old_name.as_str(),
-            new_name.as_str(),
-        );
-    }
-
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
@@ -1486,6 +2413,7 @@ pub async fn add_material(
     validate_material_components(&loaded.document, &req.material, None)?;
 
     loaded.document.materials.materials.push(req.material);
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
@@ -1543,7 +2471,11 @@ pub async fn delete_material(
             req.name
         )));
     }
+    if let Some(provenance) = loaded.document.provenance.as_mut() {
+        provenance.forget("material", &req.name);
+    }
 
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
@@ -1577,16 +2509,11 @@ pub async fn update_element(
         .position(|e| e.name == old_name)
         .ok_or_else(|| ApiError::not_found(&format!("Element '{}' not found", req.name)))?;
 
+    rename::rename(&mut loaded.document, "element", &old_name, &new_name)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
     loaded.document.materials.elements[el_idx] = req.element;
 
-    if old_name != new_name {
-        cascade_component_ref_rename(
-            &mut loaded.document.materials.materials,
-            old_name.as_str(),
-            new_name.as_str(),
-        );
-    }
-
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
@@ -1608,6 +2535,7 @@ pub async fn add_element(
     ensure_element_name_available(&loaded.document, &req.element.name, None)?;
 
     loaded.document.materials.elements.push(req.element);
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
@@ -1652,21 +2580,201 @@ pub async fn delete_element(
             req.name
         )));
     }
+    if let Some(provenance) = loaded.document.provenance.as_mut() {
+        provenance.forget("element", &req.name);
+    }
 
+    loaded.mark_edited();
     Ok(Json(json!({ "ok": true })))
 }
 
-// ─── Volume material ref ────────────────────────────────────────────────────
-
-#[derive(Deserialize)]
-pub struct UpdateMaterialRefRequest {
-    pub volume_name: String,
-    pub material_ref: String,
-}
+// ─── Matrix CRUD ────────────────────────────────────────────────────────────
 
-pub async fn update_volume_material_ref(
+/// The `<property ref="..">` bindings that name matrix `name`, as
+/// (`"material"` or `"opticalsurface"`, owner name, property name).
+fn matrix_users(doc: &GdmlDocument, name: &str) -> Vec<(&'static str, String, String)> {
+    let owners = doc
+        .materials
+        .materials
+        .iter()
+        .map(|m| ("material", &m.name, &m.properties))
+        .chain(
+            doc.solids
+                .optical_surfaces
+                .iter()
+                .map(|s| ("opticalsurface", &s.name, &s.properties)),
+        );
+    owners
+        .flat_map(|(kind, owner, properties)| {
+            properties
+                .iter()
+                .filter(|p| p.ref_name.as_deref() == Some(name))
+                .map(move |p| (kind, owner.clone(), p.name.clone()))
+        })
+        .collect()
+}
+
+/// Reject a matrix Geant4 would refuse to load: `G4GDMLReadDefine::MatrixRead`
+/// needs a positive coldim, and `G4GDMLEvaluator::DefineMatrix` a table of
+/// more than one entry whose size is a multiple of it.
+fn validate_matrix(engine: &EvalEngine, matrix: &Matrix) -> Result<(), ApiError> {
+    if matrix.name.trim().is_empty() {
+        return Err(ApiError::bad_request("Matrix name must not be empty"));
+    }
+    let coldim = engine
+        .eval_expr(&matrix.coldim)
+        .map_err(|e| ApiError::bad_request(&format!("Matrix '{}' coldim: {}", matrix.name, e)))?;
+    if coldim < 1.0 || coldim.fract() != 0.0 {
+        return Err(ApiError::bad_request(&format!(
+            "Matrix '{}' coldim must be a positive integer, got {}",
+            matrix.name, coldim
+        )));
+    }
+    let mut count = 0usize;
+    for entry in matrix.entries() {
+        engine.eval_expr(entry).map_err(|e| {
+            ApiError::bad_request(&format!(
+                "Matrix '{}' value '{}': {}",
+                matrix.name, entry, e
+            ))
+        })?;
+        count += 1;
+    }
+    if count == 0 {
+        return Err(ApiError::bad_request(&format!(
+            "Matrix '{}' has no values",
+            matrix.name
+        )));
+    }
+    if count == 1 {
+        return Err(ApiError::bad_request(&format!(
+            "Matrix '{}' has a single value; Geant4 refuses it, so define a constant instead",
+            matrix.name
+        )));
+    }
+    if !count.is_multiple_of(coldim as usize) {
+        return Err(ApiError::bad_request(&format!(
+            "Matrix '{}' has {} values, which is not a multiple of coldim {}",
+            matrix.name, count, coldim
+        )));
+    }
+    Ok(())
+}
+
+fn ensure_matrix_name_available(
+    doc: &GdmlDocument,
+    candidate: &str,
+    excluding_matrix: Option<&str>,
+) -> Result<(), ApiError> {
+    // Matrices share the evaluator's namespace with every other define, so a
+    // matrix named like a constant would shadow it.
+    if Some(candidate) != excluding_matrix
+        && rename::names_in_namespace(doc, "matrix").contains(candidate)
+    {
+        return Err(ApiError::bad_request(&format!(
+            "'{}' is already used by another define",
+            candidate
+        )));
+    }
+    Ok(())
+}
+
+/// Re-evaluate the defines after a matrix edit, so `m[i,j]` and the table
+/// values the client reads back are current. Returns the evaluation warnings.
+fn reevaluate_defines(loaded: &mut LoadedDocument) -> Result<Vec<String>, ApiError> {
+    loaded
+        .engine
+        .evaluate_all(&loaded.document.defines)
+        .map_err(|e| ApiError::bad_request(&format!("Expression evaluation error: {}", e)))?;
+    Ok(loaded.engine.take_warnings())
+}
+
+/// Make `document`, a copy of the loaded one with a matrix edit applied, the
+/// loaded document, once its defines evaluate. On failure nothing changes.
+/// `renamed_from` is the matrix's old name when the edit renamed it, so the
+/// render document follows.
+///
+/// Solids whose expressions read `matrix` as `m[i,j]` or `m_i_j` are
+/// tessellated again and the indexes brought up to date. Returns the
+/// evaluation and tessellation warnings.
+fn commit_matrix_edit(
+    loaded: &mut LoadedDocument,
+    document: GdmlDocument,
+    renamed_from: Option<&str>,
+    matrix: &str,
+) -> Result<Vec<String>, ApiError> {
+    let mut engine = EvalEngine::new();
+    engine
+        .evaluate_all(&document.defines)
+        .map_err(|e| ApiError::bad_request(&format!("Expression evaluation error: {}", e)))?;
+    loaded.document = document;
+    loaded.engine = engine;
+    if let (Some(old_name), Some(render)) = (renamed_from, loaded.render.as_mut()) {
+        let _ = rename::rename(render, "matrix", old_name, matrix);
+    }
+
+    let index = references::ReferenceIndex::build(&loaded.document);
+    let solids: Vec<String> = index
+        .dependents(&ItemId::new("matrix", matrix))
+        .into_iter()
+        .filter(|id| id.kind == "solid")
+        .map(|id| id.name)
+        .collect();
+    let mut warnings = loaded.engine.take_warnings();
+    warnings.extend(remesh_solids(loaded, &solids));
+    let remeshed: Vec<&str> = solids.iter().map(String::as_str).collect();
+    reindex(loaded, &remeshed);
+    Ok(warnings)
+}
+
+/// GET /api/document/matrices — every `<matrix>` with its evaluated rows and
+/// the material properties that use it.
+pub async fn get_matrices(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &loaded.document;
+    let matrices: Vec<Value> = doc
+        .defines
+        .matrices
+        .iter()
+        .map(|m| {
+            let rows: Option<Vec<&[f64]>> = loaded
+                .engine
+                .matrix_values
+                .get(&m.name)
+                .map(|v| v.values.chunks(v.coldim).collect());
+            let used_by: Vec<Value> = matrix_users(doc, &m.name)
+                .into_iter()
+                .map(|(kind, owner, property)| {
+                    json!({"kind": kind, "name": owner, "property": property})
+                })
+                .collect();
+            json!({
+                "name": m.name,
+                "coldim": m.coldim,
+                "values": m.values,
+                "rows": rows,
+                "used_by": used_by,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({ "matrices": matrices })))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMatrixRequest {
+    pub name: String,
+    pub matrix: Matrix,
+}
+
+pub async fn update_matrix(
     State(state): State<SharedState>,
-    Json(req): Json<UpdateMaterialRefRequest>,
+    Json(req): Json<UpdateMatrixRequest>,
 ) -> Result<Json<Value>, ApiError> {
     let mut state_w = state.write().await;
     let loaded = state_w
@@ -1674,663 +2782,3613 @@ pub async fn update_volume_material_ref(
         .as_mut()
         .ok_or_else(|| ApiError::not_found("No document loaded"))?;
 
-    ensure_material_ref_exists(&loaded.document, &req.material_ref)?;
+    let old_name = req.name.clone();
+    let new_name = req.matrix.name.clone();
+    ensure_matrix_name_available(&loaded.document, &new_name, Some(old_name.as_str()))?;
+    validate_matrix(&loaded.engine, &req.matrix)?;
 
-    let vol = loaded
+    let idx = loaded
         .document
-        .structure
-        .volumes
-        .iter_mut()
-        .find(|v| v.name == req.volume_name)
-        .ok_or_else(|| ApiError::not_found(&format!("Volume '{}' not found", req.volume_name)))?;
+        .defines
+        .matrices
+        .iter()
+        .position(|m| m.name == old_name)
+        .ok_or_else(|| ApiError::not_found(&format!("Matrix '{}' not found", req.name)))?;
+
+    // Edited on a copy, so a table the evaluator rejects leaves the document
+    // and the engine as they were.
+    let mut document = loaded.document.clone();
+    let renamed_from = if old_name != new_name {
+        // Property bindings, `m[i,j]` and `m_i_j` in every expression, and the
+        // bookkeeping that follows a define all move with the matrix.
+        rename::rename(&mut document, "matrix", &old_name, &new_name)
+            .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+        Some(old_name.as_str())
+    } else {
+        None
+    };
+    document.defines.matrices[idx] = req.matrix;
 
-    vol.material_ref = req.material_ref;
-    Ok(Json(json!({ "ok": true })))
+    let warnings = commit_matrix_edit(loaded, document, renamed_from, &new_name)?;
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true, "warnings": warnings })))
 }
 
-// ─── Export ─────────────────────────────────────────────────────────────────
+#[derive(Deserialize)]
+pub struct AddMatrixRequest {
+    pub matrix: Matrix,
+}
 
-pub async fn export_gdml(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+pub async fn add_matrix(
+    State(state): State<SharedState>,
+    Json(req): Json<AddMatrixRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    ensure_matrix_name_available(&loaded.document, &req.matrix.name, None)?;
+    validate_matrix(&loaded.engine, &req.matrix)?;
+
+    // Declared last, so every define its values may use is already in scope.
+    let name = req.matrix.name.clone();
+    let mut document = loaded.document.clone();
+    if !document.order.define_slots.is_empty() {
+        document.order.define_slots.push(DefineSlot {
+            kind: DefineKind::Matrix,
+            name: name.clone(),
+        });
+    }
+    document.defines.matrices.push(req.matrix);
+
+    // Solids reading `m_i_j` before the matrix existed now get its values.
+    let warnings = commit_matrix_edit(loaded, document, None, &name)?;
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true, "warnings": warnings })))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteMatrixRequest {
+    pub name: String,
+}
+
+pub async fn delete_matrix(
+    State(state): State<SharedState>,
+    Json(req): Json<DeleteMatrixRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    // Property bindings by property name; expressions reading `m[i,j]` or
+    // `m_i_j` through the reference index, since they would evaluate to 0.
+    let mut users: Vec<String> = matrix_users(&loaded.document, &req.name)
+        .into_iter()
+        .map(|(_, owner, property)| format!("{}/{}", owner, property))
+        .collect();
+    let id = ItemId::new("matrix", &req.name);
+    let index = references::ReferenceIndex::build(&loaded.document);
+    users.extend(
+        index
+            .referrers(&id)
+            .iter()
+            .filter(|r| r.from != id && !r.attribute.starts_with("property["))
+            .map(|r| format!("{} {}/{}", r.from.kind, r.from.name, r.attribute)),
+    );
+    if !users.is_empty() {
+        return Err(ApiError::bad_request(&format!(
+            "Matrix '{}' is still referenced by {}",
+            req.name,
+            users.join(", ")
+        )));
+    }
+
+    let mut document = loaded.document.clone();
+    let before = document.defines.matrices.len();
+    document.defines.matrices.retain(|m| m.name != req.name);
+    if document.defines.matrices.len() == before {
+        return Err(ApiError::not_found(&format!(
+            "Matrix '{}' not found",
+            req.name
+        )));
+    }
+    document
+        .order
+        .define_slots
+        .retain(|slot| !(slot.kind == DefineKind::Matrix && slot.name == req.name));
+    if let Some(names) = document.materials_define.as_mut() {
+        names.retain(|n| *n != req.name);
+    }
+    if let Some(provenance) = document.provenance.as_mut() {
+        provenance.forget("matrix", &req.name);
+    }
+
+    let warnings = commit_matrix_edit(loaded, document, None, &req.name)?;
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true, "warnings": warnings })))
+}
+
+// ─── Optical surfaces ───────────────────────────────────────────────────────
+
+/// GET /api/document/surfaces — optical, skin and border surfaces with what
+/// each one resolves to, plus every problem `surfaces::check_surfaces` finds.
+pub async fn get_surfaces(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
     let state_r = state.read().await;
     let loaded = state_r
         .loaded
         .as_ref()
         .ok_or_else(|| ApiError::not_found("No document loaded"))?;
 
-    let xml = nist::serialize_gdml(&loaded.document)
-        .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+    let doc = &loaded.document;
+    let optical: Vec<Value> = doc
+        .solids
+        .optical_surfaces
+        .iter()
+        .map(|s| {
+            let skins = doc
+                .structure
+                .skin_surfaces
+                .iter()
+                .filter(|k| k.surface_property == s.name)
+                .map(|k| json!({"kind": "skinsurface", "name": k.name}));
+            let borders = doc
+                .structure
+                .border_surfaces
+                .iter()
+                .filter(|b| b.surface_property == s.name)
+                .map(|b| json!({"kind": "bordersurface", "name": b.name}));
+            json!({
+                "name": s.name,
+                "model": s.model,
+                "finish": s.finish,
+                "type": s.r#type,
+                "value": s.value,
+                "properties": s.properties,
+                "geant4": {
+                    "model": surfaces::model_name(&s.model),
+                    "finish": surfaces::finish_name(&s.finish),
+                    "type": surfaces::type_name(&s.r#type),
+                    "value": s.value.as_deref().map_or(Some(1.0), |v| loaded.engine.eval_expr(v).ok()),
+                },
+                "used_by": skins.chain(borders).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    // Where each border placement sits, so a client can show "Crystal_PV in
+    // Module -> Wrapping_PV in Module" without walking the tree itself.
+    let placement = |name: &str| -> Value {
+        for vol in &doc.structure.volumes {
+            for pv in &vol.physvols {
+                let pv_name = match &pv.name {
+                    Some(n) if !n.is_empty() => n.clone(),
+                    _ => format!("{}_PV", pv.volume_ref),
+                };
+                if pv_name == name {
+                    return json!({"physvol": name, "volume": pv.volume_ref, "mother": vol.name});
+                }
+            }
+        }
+        json!({"physvol": name, "volume": null, "mother": null})
+    };
+    let border: Vec<Value> = doc
+        .structure
+        .border_surfaces
+        .iter()
+        .map(|b| {
+            json!({
+                "name": b.name,
+                "surface_property": b.surface_property,
+                "physvol_refs": b.physvol_refs,
+                "placements": b.physvol_refs.iter().map(|r| placement(r)).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
 
     Ok(Json(json!({
-        "gdml": xml,
-        "filename": loaded.document.filename,
+        "optical_surfaces": optical,
+        "skin_surfaces": doc.structure.skin_surfaces,
+        "border_surfaces": border,
+        "issues": surfaces::check_surfaces(doc, &loaded.engine),
     })))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::extract::State;
-    use axum::http::StatusCode;
-    use std::collections::HashMap;
+/// Reject a name already used by another item of the same kind.
+fn ensure_surface_name_available<'a>(
+    mut names: impl Iterator<Item = &'a str>,
+    label: &str,
+    candidate: &str,
+    excluding: Option<&str>,
+) -> Result<(), ApiError> {
+    if candidate.trim().is_empty() {
+        return Err(ApiError::bad_request(&format!(
+            "{} name must not be empty",
+            label
+        )));
+    }
+    if names.any(|n| n == candidate && Some(n) != excluding) {
+        return Err(ApiError::bad_request(&format!(
+            "{} '{}' already exists",
+            label, candidate
+        )));
+    }
+    Ok(())
+}
 
-    fn base_doc(filename: &str, world_ref: &str) -> GdmlDocument {
-        GdmlDocument {
-            filename: filename.to_string(),
-            root_attributes: Vec::new(),
-            materials_define: None,
-            order: Default::default(),
-            defines: DefineSection::default(),
-            materials: MaterialSection::default(),
-            solids: SolidSection::default(),
-            structure: StructureSection::default(),
-            setup: SetupSection {
-                name: "Default".to_string(),
-                version: "1.0".to_string(),
-                world_ref: world_ref.to_string(),
-            },
-            setups: Vec::new(),
-            raw_unknown: Vec::new(),
-            skipped_unsupported: Vec::new(),
+/// An optical surface must use enumerators Geant4 recognises -- its reader
+/// falls back silently otherwise -- and bind matrices that exist.
+fn validate_optical_surface(engine: &EvalEngine, surface: &OpticalSurface) -> Result<(), ApiError> {
+    let checks = [
+        (
+            "model",
+            &surface.model,
+            surfaces::model_name(&surface.model),
+        ),
+        (
+            "finish",
+            &surface.finish,
+            surfaces::finish_name(&surface.finish),
+        ),
+        (
+            "type",
+            &surface.r#type,
+            surfaces::type_name(&surface.r#type),
+        ),
+    ];
+    for (attribute, value, resolved) in checks {
+        if resolved.is_none() {
+            return Err(ApiError::bad_request(&format!(
+                "Optical surface '{}' has unknown {} '{}'",
+                surface.name, attribute, value
+            )));
         }
     }
-
-    fn material(name: &str) -> Material {
-        Material {
-            name: name.to_string(),
-            formula: None,
-            z: None,
-            state: None,
-            density: None,
-            density_ref: None,
-            temperature: None,
-            pressure: None,
-            mee: None,
-            rl: None,
-            al: None,
-            properties: Vec::new(),
-            atom_value: None,
-            atom_unit: None,
-            atom_type: None,
-            components: Vec::new(),
+    if let Some(value) = &surface.value {
+        engine.eval_expr(value).map_err(|e| {
+            ApiError::bad_request(&format!("Optical surface '{}' value: {}", surface.name, e))
+        })?;
+    }
+    for prop in &surface.properties {
+        if let Some(r) = &prop.ref_name {
+            if !engine.matrix_values.contains_key(r) {
+                return Err(ApiError::bad_request(&format!(
+                    "Optical surface '{}' property {} references unknown matrix '{}'",
+                    surface.name, prop.name, r
+                )));
+            }
         }
     }
+    Ok(())
+}
 
-    fn volume(name: &str, material_ref: &str) -> Volume {
-        Volume {
-            name: name.to_string(),
-            material_ref: material_ref.to_string(),
-            solid_ref: "Solid".to_string(),
-            physvols: Vec::new(),
-            auxiliaries: Vec::new(),
-            replica: None,
-            body_comments: Vec::new(),
-            loops: Vec::new(),
-        }
+fn ensure_optical_surface_exists(doc: &GdmlDocument, name: &str) -> Result<(), ApiError> {
+    if doc.solids.optical_surfaces.iter().any(|s| s.name == name) {
+        Ok(())
+    } else {
+        Err(ApiError::bad_request(&format!(
+            "Optical surface '{}' does not exist",
+            name
+        )))
     }
+}
 
-    fn file_ref_physvol(file: &str, volname: Option<&str>) -> PhysVol {
-        PhysVol {
-            name: None,
-            volume_ref: String::new(),
-            copynumber: None,
-            file_ref: Some(FileRef {
-                name: file.to_string(),
-                volname: volname.map(|s| s.to_string()),
-            }),
-            position: None,
-            rotation: None,
-        }
+fn validate_skin_surface(doc: &GdmlDocument, surface: &SkinSurface) -> Result<(), ApiError> {
+    ensure_optical_surface_exists(doc, &surface.surface_property)?;
+    if !doc
+        .structure
+        .volumes
+        .iter()
+        .any(|v| v.name == surface.volume_ref)
+    {
+        return Err(ApiError::bad_request(&format!(
+            "Volume '{}' does not exist",
+            surface.volume_ref
+        )));
     }
+    Ok(())
+}
 
-    #[test]
-    fn resolves_nested_file_refs_recursively() {
-        let mut main = base_doc("main.gdml", "MainWorld");
-        let mut main_world = volume("MainWorld", "Vacuum");
-        main_world
-            .physvols
+fn validate_border_surface(doc: &GdmlDocument, surface: &BorderSurface) -> Result<(), ApiError> {
+    ensure_optical_surface_exists(doc, &surface.surface_property)?;
+    if surface.physvol_refs.len() != 2 {
+        return Err(ApiError::bad_request(&format!(
+            "Border surface '{}' needs exactly two placements, got {}",
+            surface.name,
+            surface.physvol_refs.len()
+        )));
+    }
+    let placements = surfaces::physvol_names(doc);
+    for r in &surface.physvol_refs {
+        if !placements.contains(r) {
+            return Err(ApiError::bad_request(&format!(
+                "Placement '{}' does not exist",
+                r
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct UpdateOpticalSurfaceRequest {
+    pub name: String,
+    pub surface: OpticalSurface,
+}
+
+pub async fn update_optical_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<UpdateOpticalSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let old_name = req.name.clone();
+    let new_name = req.surface.name.clone();
+    let doc = &mut loaded.document;
+    ensure_surface_name_available(
+        doc.solids.optical_surfaces.iter().map(|s| s.name.as_str()),
+        "Optical surface",
+        &new_name,
+        Some(old_name.as_str()),
+    )?;
+    validate_optical_surface(&loaded.engine, &req.surface)?;
+
+    let idx = doc
+        .solids
+        .optical_surfaces
+        .iter()
+        .position(|s| s.name == old_name)
+        .ok_or_else(|| ApiError::not_found(&format!("Optical surface '{}' not found", req.name)))?;
+    doc.solids.optical_surfaces[idx] = req.surface;
+
+    if old_name != new_name {
+        // Cascade rename to the skin and border surfaces that apply it.
+        for skin in &mut doc.structure.skin_surfaces {
+            if skin.surface_property == old_name {
+                skin.surface_property = new_name.clone();
+            }
+        }
+        for border in &mut doc.structure.border_surfaces {
+            if border.surface_property == old_name {
+                border.surface_property = new_name.clone();
+            }
+        }
+        if let Some(provenance) = doc.provenance.as_mut() {
+            provenance.rename("opticalsurface", &old_name, &new_name);
+        }
+    }
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct AddOpticalSurfaceRequest {
+    pub surface: OpticalSurface,
+}
+
+pub async fn add_optical_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<AddOpticalSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    ensure_surface_name_available(
+        loaded
+            .document
+            .solids
+            .optical_surfaces
+            .iter()
+            .map(|s| s.name.as_str()),
+        "Optical surface",
+        &req.surface.name,
+        None,
+    )?;
+    validate_optical_surface(&loaded.engine, &req.surface)?;
+
+    loaded.document.solids.optical_surfaces.push(req.surface);
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteSurfaceRequest {
+    pub name: String,
+}
+
+pub async fn delete_optical_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<DeleteSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    let users: Vec<&str> = doc
+        .structure
+        .skin_surfaces
+        .iter()
+        .filter(|s| s.surface_property == req.name)
+        .map(|s| s.name.as_str())
+        .chain(
+            doc.structure
+                .border_surfaces
+                .iter()
+                .filter(|s| s.surface_property == req.name)
+                .map(|s| s.name.as_str()),
+        )
+        .collect();
+    if !users.is_empty() {
+        return Err(ApiError::bad_request(&format!(
+            "Optical surface '{}' is still used by {}",
+            req.name,
+            users.join(", ")
+        )));
+    }
+
+    let before = doc.solids.optical_surfaces.len();
+    doc.solids.optical_surfaces.retain(|s| s.name != req.name);
+    if doc.solids.optical_surfaces.len() == before {
+        return Err(ApiError::not_found(&format!(
+            "Optical surface '{}' not found",
+            req.name
+        )));
+    }
+    if let Some(provenance) = doc.provenance.as_mut() {
+        provenance.forget("opticalsurface", &req.name);
+    }
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSkinSurfaceRequest {
+    pub name: String,
+    pub surface: SkinSurface,
+}
+
+pub async fn update_skin_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<UpdateSkinSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    ensure_surface_name_available(
+        doc.structure.skin_surfaces.iter().map(|s| s.name.as_str()),
+        "Skin surface",
+        &req.surface.name,
+        Some(req.name.as_str()),
+    )?;
+    validate_skin_surface(doc, &req.surface)?;
+
+    let idx = doc
+        .structure
+        .skin_surfaces
+        .iter()
+        .position(|s| s.name == req.name)
+        .ok_or_else(|| ApiError::not_found(&format!("Skin surface '{}' not found", req.name)))?;
+    if req.name != req.surface.name {
+        if let Some(provenance) = doc.provenance.as_mut() {
+            provenance.rename("skinsurface", &req.name, &req.surface.name);
+        }
+    }
+    doc.structure.skin_surfaces[idx] = req.surface;
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct AddSkinSurfaceRequest {
+    pub surface: SkinSurface,
+}
+
+pub async fn add_skin_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<AddSkinSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    ensure_surface_name_available(
+        doc.structure.skin_surfaces.iter().map(|s| s.name.as_str()),
+        "Skin surface",
+        &req.surface.name,
+        None,
+    )?;
+    validate_skin_surface(doc, &req.surface)?;
+
+    doc.structure.skin_surfaces.push(req.surface);
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+pub async fn delete_skin_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<DeleteSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    let before = doc.structure.skin_surfaces.len();
+    doc.structure.skin_surfaces.retain(|s| s.name != req.name);
+    if doc.structure.skin_surfaces.len() == before {
+        return Err(ApiError::not_found(&format!(
+            "Skin surface '{}' not found",
+            req.name
+        )));
+    }
+    if let Some(provenance) = doc.provenance.as_mut() {
+        provenance.forget("skinsurface", &req.name);
+    }
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBorderSurfaceRequest {
+    pub name: String,
+    pub surface: BorderSurface,
+}
+
+pub async fn update_border_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<UpdateBorderSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    ensure_surface_name_available(
+        doc.structure
+            .border_surfaces
+            .iter()
+            .map(|s| s.name.as_str()),
+        "Border surface",
+        &req.surface.name,
+        Some(req.name.as_str()),
+    )?;
+    validate_border_surface(doc, &req.surface)?;
+
+    let idx = doc
+        .structure
+        .border_surfaces
+        .iter()
+        .position(|s| s.name == req.name)
+        .ok_or_else(|| ApiError::not_found(&format!("Border surface '{}' not found", req.name)))?;
+    if req.name != req.surface.name {
+        if let Some(provenance) = doc.provenance.as_mut() {
+            provenance.rename("bordersurface", &req.name, &req.surface.name);
+        }
+    }
+    doc.structure.border_surfaces[idx] = req.surface;
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+#[derive(Deserialize)]
+pub struct AddBorderSurfaceRequest {
+    pub surface: BorderSurface,
+}
+
+pub async fn add_border_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<AddBorderSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    ensure_surface_name_available(
+        doc.structure
+            .border_surfaces
+            .iter()
+            .map(|s| s.name.as_str()),
+        "Border surface",
+        &req.surface.name,
+        None,
+    )?;
+    validate_border_surface(doc, &req.surface)?;
+
+    doc.structure.border_surfaces.push(req.surface);
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+pub async fn delete_border_surface(
+    State(state): State<SharedState>,
+    Json(req): Json<DeleteSurfaceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &mut loaded.document;
+    let before = doc.structure.border_surfaces.len();
+    doc.structure.border_surfaces.retain(|s| s.name != req.name);
+    if doc.structure.border_surfaces.len() == before {
+        return Err(ApiError::not_found(&format!(
+            "Border surface '{}' not found",
+            req.name
+        )));
+    }
+    if let Some(provenance) = doc.provenance.as_mut() {
+        provenance.forget("bordersurface", &req.name);
+    }
+
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+// ─── Rename ─────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    /// `constant`, `solid`, `volume`, `physvol`... see
+    /// [`rename::RENAMEABLE_KINDS`].
+    pub kind: String,
+    pub name: String,
+    pub new_name: String,
+}
+
+/// POST /api/document/rename — rename any define, solid, volume, placement,
+/// isotope, element or material and rewrite every reference to it.
+///
+/// The loop-expanded render document gets the same rename so the preview
+/// keeps matching, and meshes are re-keyed for a solid; nothing is
+/// re-tessellated, because no value changes.
+pub async fn rename_item(
+    State(state): State<SharedState>,
+    Json(req): Json<RenameRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    if rename::RENAMEABLE_KINDS.contains(&req.kind.as_str())
+        && !rename::exists(&loaded.document, &req.kind, &req.name)
+    {
+        return Err(ApiError::not_found(&format!(
+            "No {} named '{}'",
+            req.kind, req.name
+        )));
+    }
+    let updated = rename::rename(&mut loaded.document, &req.kind, &req.name, &req.new_name)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    if let Some(render) = loaded.render.as_mut() {
+        // Expanded loops may have made the rename ambiguous there; the next
+        // load rebuilds it from the source either way.
+        let _ = rename::rename(render, &req.kind, &req.name, &req.new_name);
+    }
+    if req.kind == "solid" {
+        if let Some(mesh) = loaded.meshes.remove(&req.name) {
+            loaded.meshes.insert(req.new_name.clone(), mesh);
+        }
+        if let Some(levels) = loaded.lods.remove(&req.name) {
+            loaded.lods.insert(req.new_name.clone(), levels);
+        }
+        if let Some(edges) = loaded.edges.remove(&req.name) {
+            loaded.edges.insert(req.new_name.clone(), edges);
+        }
+    }
+
+    let warnings = if rename::is_define(&req.kind) {
+        reevaluate_defines(loaded)?
+    } else {
+        Vec::new()
+    };
+    // Instance paths are made of volume and physvol names, and the index
+    // keys facets by solid name.
+    reindex(loaded, &[]);
+    loaded.mark_edited();
+    Ok(Json(json!({
+        "ok": true,
+        "references_updated": updated,
+        "warnings": warnings,
+    })))
+}
+
+// ─── Unused definitions ─────────────────────────────────────────────────────
+
+#[derive(Deserialize, Default)]
+pub struct PruneRequest {
+    /// The volume to keep everything for; the main `<setup>`'s world when
+    /// omitted.
+    pub world: Option<String>,
+}
+
+fn unused_item_list(doc: &GdmlDocument, items: &[ItemId]) -> Vec<Value> {
+    items
+        .iter()
+        .map(|id| {
+            let file = doc
+                .provenance
+                .as_ref()
+                .and_then(|p| p.source_of(id.kind, &id.name))
+                .unwrap_or(&doc.filename);
+            json!({ "kind": id.kind, "name": id.name, "file": file })
+        })
+        .collect()
+}
+
+/// GET /api/document/unused[?world=..] — every define, material, element,
+/// isotope, solid and volume unreachable from the world volume, the
+/// document's surfaces and its preserved-verbatim elements.
+pub async fn get_unused(
+    State(state): State<SharedState>,
+    Query(query): Query<PruneRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let doc = &loaded.document;
+    let world = query.world.unwrap_or_else(|| doc.setup.world_ref.clone());
+    let unused =
+        prune::unused_items(doc, &world).map_err(|e| ApiError::not_found(&e.to_string()))?;
+    Ok(Json(json!({
+        "world": world,
+        "count": unused.len(),
+        "items": unused_item_list(doc, &unused),
+    })))
+}
+
+/// POST /api/document/prune — remove what `GET /api/document/unused` reports,
+/// so the next export no longer carries it.
+///
+/// The render document loses the same items and meshes of removed solids are
+/// dropped. Nothing left in use changes, so nothing is re-tessellated.
+pub async fn prune_unused(
+    State(state): State<SharedState>,
+    Json(req): Json<PruneRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let world = req
+        .world
+        .unwrap_or_else(|| loaded.document.setup.world_ref.clone());
+    let removed = prune::unused_items(&loaded.document, &world)
+        .map_err(|e| ApiError::not_found(&e.to_string()))?;
+    let items = unused_item_list(&loaded.document, &removed);
+
+    prune::remove_items(&mut loaded.document, &removed, &world);
+    if let Some(render) = loaded.render.as_mut() {
+        prune::remove_items(render, &removed, &world);
+    }
+    for id in removed.iter().filter(|id| id.kind == "solid") {
+        loaded.meshes.remove(&id.name);
+    }
+    let warnings = if removed.iter().any(|id| rename::is_define(id.kind)) {
+        reevaluate_defines(loaded)?
+    } else {
+        Vec::new()
+    };
+    reindex(loaded, &[]);
+    loaded.mark_edited();
+    Ok(Json(json!({
+        "ok": true,
+        "world": world,
+        "count": removed.len(),
+        "removed": items,
+        "warnings": warnings,
+    })))
+}
+
+// ─── Volume material ref ────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct UpdateMaterialRefRequest {
+    pub volume_name: String,
+    pub material_ref: String,
+}
+
+pub async fn update_volume_material_ref(
+    State(state): State<SharedState>,
+    Json(req): Json<UpdateMaterialRefRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    ensure_material_ref_exists(&loaded.document, &req.material_ref)?;
+
+    let vol = loaded
+        .document
+        .structure
+        .volumes
+        .iter_mut()
+        .find(|v| v.name == req.volume_name)
+        .ok_or_else(|| ApiError::not_found(&format!("Volume '{}' not found", req.volume_name)))?;
+
+    vol.material_ref = req.material_ref;
+    reindex(loaded, &[]);
+    loaded.mark_edited();
+    Ok(Json(json!({ "ok": true })))
+}
+
+// ─── Export ─────────────────────────────────────────────────────────────────
+
+pub async fn export_gdml(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let xml = nist::serialize_gdml(&loaded.document)
+        .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+    let issues = schema::validate(&xml);
+
+    Ok(Json(json!({
+        "gdml": xml,
+        "filename": loaded.document.filename,
+        "schema_issues": issues,
+    })))
+}
+
+/// GET /api/document/validate — check the document as it would be exported
+/// against the GDML schema rules.
+///
+/// Positions refer to the exported text, which is what Geant4 would read; the
+/// uploaded file's own issues were reported as load warnings.
+pub async fn validate_document(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let xml = nist::serialize_gdml(&loaded.document)
+        .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+    let issues = schema::validate(&xml);
+    Ok(Json(json!({
+        "valid": issues.is_empty(),
+        "count": issues.len(),
+        "issues": issues,
+    })))
+}
+
+/// The document as one GDML file per module, zipped.
+///
+/// A document loaded from a single file has nothing to split and is rejected;
+/// use `split-module` first to carve a subtree out into its own file.
+pub async fn export_modular(State(state): State<SharedState>) -> Result<Response, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let modules = modular::split_into_modules(&loaded.document)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    let (archive, notes) = modular::write_modules_zip(&modules)
+        .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+    for note in &notes {
+        tracing::warn!("Modular export: {}", note);
+    }
+
+    let stem = loaded
+        .document
+        .filename
+        .strip_suffix(".gdml")
+        .unwrap_or(&loaded.document.filename);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.zip\"", stem.replace('"', "")),
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// POST /api/document/export-geant4 — the geometry as a Geant4
+/// `DetectorConstruction` class.
+///
+/// Built from the loop-expanded geometry, since the C++ has no `<loop>` to
+/// fall back on. Anything the generated code leaves out is listed in
+/// `warnings`.
+pub async fn export_geant4(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let generated = geant4::generate_detector_construction(loaded.geometry(), &loaded.engine)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    Ok(Json(json!({
+        "files": {
+            "DetectorConstruction.hh": generated.header,
+            "DetectorConstruction.cc": generated.source,
+        },
+        "warnings": generated.warnings,
+    })))
+}
+
+/// POST /api/document/export-root — the geometry as a ROOT macro that builds
+/// it with `TGeoManager`.
+pub async fn export_root(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let generated = tgeo::generate_root_macro(loaded.geometry(), &loaded.engine)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    Ok(Json(json!({
+        "files": { generated.file_name: generated.source },
+        "warnings": generated.warnings,
+    })))
+}
+
+/// POST /api/document/export-mcnp — the geometry as MCNP cell, surface and
+/// material cards.
+///
+/// Only the quadric solids translate; the rest, and every placement that
+/// uses them, are listed in `warnings`.
+pub async fn export_mcnp(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let state_r = state.read().await;
+    let loaded = state_r
+        .loaded
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+
+    let generated = mcnp::generate_mcnp_deck(loaded.geometry(), &loaded.engine)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    Ok(Json(json!({
+        "files": { generated.file_name: generated.deck },
+        "warnings": generated.warnings,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct SplitModuleRequest {
+    pub volume: String,
+    /// Path of the new file, relative to the main file's directory.
+    pub path: String,
+}
+
+pub async fn split_module(
+    State(state): State<SharedState>,
+    Json(req): Json<SplitModuleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let path = normalize_include_path("", &req.path);
+    if !path.ends_with(".gdml") {
+        return Err(ApiError::bad_request("Only .gdml files are supported"));
+    }
+    if path.starts_with('/') {
+        return Err(ApiError::bad_request(
+            "The new file must be given relative to the main file",
+        ));
+    }
+
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+    let moved = modular::split_subtree(&mut loaded.document, &req.volume, &path)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+
+    loaded.mark_edited();
+    Ok(Json(
+        json!({ "ok": true, "path": path, "volumes_moved": moved }),
+    ))
+}
+
+// ─── Local files ────────────────────────────────────────────────────────────
+
+/// How long to wait after a change notification before reloading. Editors and
+/// generator scripts tend to touch several files (or one file several times)
+/// per save; this collapses a burst into a single reload.
+const LOCAL_RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(250);
+
+fn local_root() -> Result<PathBuf, ApiError> {
+    config::local_fs_root().ok_or_else(|| {
+        ApiError::forbidden("Local filesystem mode is disabled; set GDML_FS_ROOT to enable it")
+    })
+}
+
+/// Load a file set read from disk through the same pipeline as an upload.
+///
+/// A file without `<file>` references goes through the single-file path so its
+/// `<loop>` elements are expanded for the preview exactly as on upload.
+fn load_file_set(
+    set: &FileSet,
+    quality: &MeshQuality,
+    booleans: Option<BooleanBackend>,
+) -> Result<LoadedDocument, ApiError> {
+    if set.contents.len() == 1 && set.missing.is_empty() {
+        load_single_document(
+            &set.main_name,
+            &set.contents[&set.main_name],
+            quality,
+            booleans,
+        )
+    } else {
+        load_multi_document(&set.contents, &set.main_name, quality, booleans)
+    }
+}
+
+fn open_local_document(
+    root: &Path,
+    path: &Path,
+    quality: &MeshQuality,
+    booleans: Option<BooleanBackend>,
+) -> Result<LoadedDocument, ApiError> {
+    let main_path = local_files::resolve_under_root(root, path)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    if main_path.extension().and_then(|e| e.to_str()) != Some("gdml") {
+        return Err(ApiError::bad_request("Only .gdml files are supported"));
+    }
+    let set = local_files::read_file_set(root, &main_path)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+    let mut loaded = load_file_set(&set, quality, booleans)?;
+    loaded.local = Some(LocalSource {
+        main_path,
+        fingerprints: set.fingerprints(),
+        booleans,
+        revision: 0,
+        dirty: false,
+        last_error: None,
+        watcher: None,
+    });
+    Ok(loaded)
+}
+
+/// Start watching `files` and reload the document whenever one of them changes.
+///
+/// The reload task ends on its own once the returned watcher is dropped, which
+/// happens when the document is replaced by another open or upload.
+fn spawn_local_watch(state: &SharedState, files: &[PathBuf]) -> Result<FileWatcher, ApiError> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let watcher =
+        local_files::watch_files(files, tx).map_err(|e| ApiError::internal(&e.to_string()))?;
+    let state = state.clone();
+    tokio::spawn(async move {
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(LOCAL_RELOAD_DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+            reload_local_document(&state).await;
+        }
+    });
+    Ok(watcher)
+}
+
+/// Re-read the open file set and swap in a fresh document if anything changed.
+///
+/// A file that no longer parses keeps the previous document loaded and records
+/// the error instead, so saving a half-edited file in an external editor does
+/// not blank the viewer. Likewise a document with unsaved API edits is kept and
+/// the change on disk is reported as a conflict rather than loaded over them.
+async fn reload_local_document(state: &SharedState) {
+    let Some(root) = config::local_fs_root() else {
+        return;
+    };
+    reload_local_document_under(state, &root).await;
+}
+
+/// What a look at the open file set on disk found.
+enum DiskChange {
+    Unchanged,
+    /// The files changed while the document has unsaved edits.
+    Conflict,
+    Reloaded(Box<LoadedDocument>, HashMap<PathBuf, u64>),
+}
+
+async fn reload_local_document_under(state: &SharedState, root: &Path) {
+    let (main_path, fingerprints, quality, booleans, dirty) = {
+        let state_r = state.read().await;
+        let Some(loaded) = state_r.loaded.as_ref() else {
+            return;
+        };
+        let Some(local) = loaded.local.as_ref() else {
+            return;
+        };
+        (
+            local.main_path.clone(),
+            local.fingerprints.clone(),
+            loaded.quality.clone(),
+            local.booleans,
+            local.dirty,
+        )
+    };
+
+    let change = local_files::read_file_set(root, &main_path)
+        .map_err(|e| e.to_string())
+        .and_then(|set| {
+            if set.fingerprints() == fingerprints {
+                return Ok(DiskChange::Unchanged);
+            }
+            if dirty {
+                return Ok(DiskChange::Conflict);
+            }
+            load_file_set(&set, &quality, booleans)
+                .map(|loaded| DiskChange::Reloaded(Box::new(loaded), set.fingerprints()))
+                .map_err(|e| e.message)
+        });
+
+    let mut state_w = state.write().await;
+    let Some(loaded) = state_w.loaded.as_mut() else {
+        return;
+    };
+    let Some(local) = loaded.local.as_mut() else {
+        return;
+    };
+    if local.main_path != main_path {
+        return;
+    }
+    // An edit may have landed while the files were being read and parsed.
+    let change = match change {
+        Ok(DiskChange::Reloaded(..)) if local.dirty => Ok(DiskChange::Conflict),
+        other => other,
+    };
+    match change {
+        Ok(DiskChange::Unchanged) => {}
+        Ok(DiskChange::Conflict) => {
+            tracing::warn!(
+                "{} changed on disk while it has unsaved edits; keeping the edits",
+                main_path.display()
+            );
+            local.last_error = Some(format!(
+                "'{}' changed on disk, but the document has unsaved edits, so it was \
+                 not reloaded. Save to overwrite the files on disk, or open the file \
+                 again to discard the edits.",
+                main_path.display()
+            ));
+        }
+        Ok(DiskChange::Reloaded(fresh, new_fingerprints)) => {
+            let mut fresh = *fresh;
+            let mut watcher = local.watcher.take();
+            let same_files = new_fingerprints.len() == fingerprints.len()
+                && new_fingerprints
+                    .keys()
+                    .all(|p| fingerprints.contains_key(p));
+            if !same_files {
+                let files: Vec<PathBuf> = new_fingerprints.keys().cloned().collect();
+                match spawn_local_watch(state, &files) {
+                    Ok(w) => watcher = Some(w),
+                    Err(e) => tracing::warn!("Could not re-watch local files: {}", e.message),
+                }
+            }
+            fresh.local = Some(LocalSource {
+                main_path,
+                fingerprints: new_fingerprints,
+                booleans,
+                revision: local.revision + 1,
+                dirty: false,
+                last_error: None,
+                watcher,
+            });
+            tracing::info!("Reloaded {} after a change on disk", fresh.file_path);
+            *loaded = fresh;
+        }
+        Err(message) => {
+            tracing::warn!("Reload of {} failed: {}", main_path.display(), message);
+            local.last_error = Some(message);
+        }
+    }
+}
+
+fn local_status(loaded: Option<&LocalSource>) -> Value {
+    match loaded {
+        Some(local) => json!({
+            "path": local.main_path,
+            "files": local.files(),
+            "revision": local.revision,
+            "watching": local.watcher.is_some(),
+            "dirty": local.dirty,
+            "last_error": local.last_error,
+        }),
+        None => Value::Null,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OpenLocalFileRequest {
+    pub path: String,
+    pub segments: Option<u32>,
+    pub quality: Option<MeshQuality>,
+    pub booleans: Option<BooleanBackend>,
+}
+
+pub async fn open_local_file(
+    State(state): State<SharedState>,
+    Json(req): Json<OpenLocalFileRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let root = local_root()?;
+    let quality = mesh_quality(req.segments, req.quality);
+    let mut loaded = open_local_document(&root, Path::new(&req.path), &quality, req.booleans)?;
+    if let Some(local) = loaded.local.as_mut() {
+        match spawn_local_watch(&state, &local.files()) {
+            Ok(watcher) => local.watcher = Some(watcher),
+            // Still usable without automatic reload, so don't fail the open.
+            Err(e) => loaded.warnings.push(format!(
+                "Changes on disk will not be picked up automatically: {}",
+                e.message
+            )),
+        }
+    }
+    let mut summary = document_summary(&loaded);
+    summary["local"] = local_status(loaded.local.as_ref());
+
+    let mut state_w = state.write().await;
+    state_w.loaded = Some(loaded);
+
+    Ok(Json(summary))
+}
+
+pub async fn save_local_file(State(state): State<SharedState>) -> Result<Json<Value>, ApiError> {
+    let root = local_root()?;
+    let mut state_w = state.write().await;
+    let loaded = state_w
+        .loaded
+        .as_mut()
+        .ok_or_else(|| ApiError::not_found("No document loaded"))?;
+    let local = loaded.local.as_mut().ok_or_else(|| {
+        ApiError::bad_request("The document was uploaded, not opened from disk; use Export")
+    })?;
+
+    // A merged document is written back module by module: writing it over the
+    // main file would inline every child into it and orphan the children.
+    let outputs: Vec<(PathBuf, String)> = if loaded.document.provenance.is_some() {
+        let main_dir = local
+            .main_path
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .to_path_buf();
+        let modules = modular::split_into_modules(&loaded.document)
+            .map_err(|e| ApiError::internal(&e.to_string()))?;
+        let mut outputs = Vec::new();
+        for module in &modules {
+            let path = local_files::module_target(&root, &main_dir, &module.path)
+                .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+            let xml = nist::serialize_gdml(&module.document)
+                .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+            outputs.push((path, xml));
+        }
+        outputs
+    } else {
+        let xml = nist::serialize_gdml(&loaded.document)
+            .map_err(|e| ApiError::internal(&format!("Serialization error: {}", e)))?;
+        vec![(local.main_path.clone(), xml)]
+    };
+
+    let watched_before = local.fingerprints.len();
+    let mut files = Vec::new();
+    for (path, xml) in &outputs {
+        let backup = local_files::save_with_backup(path, xml)
+            .map_err(|e| ApiError::internal(&e.to_string()))?;
+        // Our own write will come back as a change notification; recording
+        // what we wrote makes the watcher recognise it and skip the reload.
+        local
+            .fingerprints
+            .insert(path.clone(), local_files::fingerprint(xml.as_bytes()));
+        files.push(json!({ "path": path, "backup": backup }));
+    }
+    // The files now hold the edits, and whatever the disk held before is gone.
+    local.dirty = false;
+    local.last_error = None;
+    // A subtree split into a new file adds to the set being watched.
+    if local.fingerprints.len() != watched_before {
+        match spawn_local_watch(&state, &local.files()) {
+            Ok(watcher) => local.watcher = Some(watcher),
+            Err(e) => tracing::warn!("Could not re-watch local files: {}", e.message),
+        }
+    }
+
+    Ok(Json(json!({
+        "ok": true,
+        "path": local.main_path,
+        "files": files,
+    })))
+}
+
+pub async fn get_local_status(State(state): State<SharedState>) -> Json<Value> {
+    let root = config::local_fs_root();
+    let state_r = state.read().await;
+    let local = state_r.loaded.as_ref().and_then(|l| l.local.as_ref());
+    Json(json!({
+        "enabled": root.is_some(),
+        "root": root,
+        "document": local_status(local),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use std::collections::HashMap;
+
+    fn base_doc(filename: &str, world_ref: &str) -> GdmlDocument {
+        GdmlDocument {
+            filename: filename.to_string(),
+            root_attributes: Vec::new(),
+            materials_define: None,
+            order: Default::default(),
+            defines: DefineSection::default(),
+            materials: MaterialSection::default(),
+            solids: SolidSection::default(),
+            structure: StructureSection::default(),
+            setup: SetupSection {
+                name: "Default".to_string(),
+                version: "1.0".to_string(),
+                world_ref: world_ref.to_string(),
+            },
+            setups: Vec::new(),
+            raw_unknown: Vec::new(),
+            skipped_unsupported: Vec::new(),
+            provenance: None,
+        }
+    }
+
+    fn material(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            formula: None,
+            z: None,
+            state: None,
+            density: None,
+            density_ref: None,
+            temperature: None,
+            pressure: None,
+            mee: None,
+            rl: None,
+            al: None,
+            properties: Vec::new(),
+            atom_value: None,
+            atom_unit: None,
+            atom_type: None,
+            components: Vec::new(),
+        }
+    }
+
+    fn volume(name: &str, material_ref: &str) -> Volume {
+        Volume {
+            name: name.to_string(),
+            material_ref: material_ref.to_string(),
+            solid_ref: "Solid".to_string(),
+            physvols: Vec::new(),
+            auxiliaries: Vec::new(),
+            replica: None,
+            body_comments: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    /// Shared state with `src` loaded, as if it had just been uploaded.
+    async fn loaded_state(src: &str, quality: &MeshQuality) -> SharedState {
+        let loaded = load_single_document("test.gdml", src, quality, None)
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let state = crate::state::app_state::create_shared_state();
+        state.write().await.loaded = Some(loaded);
+        state
+    }
+
+    fn file_ref_physvol(file: &str, volname: Option<&str>) -> PhysVol {
+        PhysVol {
+            name: None,
+            volume_ref: String::new(),
+            copynumber: None,
+            file_ref: Some(FileRef {
+                name: file.to_string(),
+                volname: volname.map(|s| s.to_string()),
+            }),
+            included: None,
+            position: None,
+            rotation: None,
+        }
+    }
+
+    #[test]
+    fn resolves_nested_file_refs_recursively() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world
+            .physvols
             .push(file_ref_physvol("child.gdml", None));
         main.structure.volumes.push(main_world);
 
         let mut child = base_doc("child.gdml", "ChildWorld");
-        let mut child_world = volume("ChildWorld", "Vacuum");
-        child_world
+        let mut child_world = volume("ChildWorld", "Vacuum");
+        child_world
+            .physvols
+            .push(file_ref_physvol("grand.gdml", None));
+        child.structure.volumes.push(child_world);
+
+        let mut grand = base_doc("grand.gdml", "GrandWorld");
+        grand.structure.volumes.push(volume("GrandWorld", "Vacuum"));
+
+        let mut child_docs = HashMap::new();
+        child_docs.insert("child.gdml".to_string(), child);
+        child_docs.insert("grand.gdml".to_string(), grand);
+
+        let warnings = match resolve_all_file_refs(&mut main, &child_docs) {
+            Ok(warnings) => warnings,
+            Err(err) => panic!("resolve_all_file_refs should succeed: {}", err.message),
+        };
+        assert!(warnings.is_empty());
+        assert!(collect_file_refs(&main).is_empty());
+
+        let main_world = main
+            .structure
+            .volumes
+            .iter()
+            .find(|v| v.name == "MainWorld")
+            .unwrap();
+        assert_eq!(main_world.physvols[0].volume_ref, "ChildWorld");
+        assert!(main_world.physvols[0].file_ref.is_none());
+
+        let child_world = main
+            .structure
+            .volumes
+            .iter()
+            .find(|v| v.name == "ChildWorld")
+            .unwrap();
+        assert_eq!(child_world.physvols[0].volume_ref, "GrandWorld");
+        assert!(child_world.physvols[0].file_ref.is_none());
+    }
+
+    #[tokio::test]
+    async fn material_rename_cascades_to_components_and_volumes() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        let mut world = volume("World", "Steel");
+        world.physvols.push(PhysVol {
+            name: Some("pv_leaf".to_string()),
+            volume_ref: "Leaf".to_string(),
+            copynumber: None,
+            file_ref: None,
+            included: None,
+            position: None,
+            rotation: None,
+        });
+        doc.structure.volumes.push(world);
+        doc.structure.volumes.push(volume("Leaf", "Steel"));
+
+        let steel = material("Steel");
+        let mut alloy = material("Alloy");
+        alloy.components.push(MaterialComponent::Composite {
+            n: "1".to_string(),
+            ref_name: "Steel".to_string(),
+        });
+        doc.materials.materials.push(steel);
+        doc.materials.materials.push(alloy);
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let req = UpdateMaterialRequest {
+            name: "Steel".to_string(),
+            material: material("SteelRenamed"),
+        };
+        let res = update_material(State(state.clone()), Json(req)).await;
+        assert!(res.is_ok(), "update_material should succeed");
+
+        let r = state.read().await;
+        let loaded = r.loaded.as_ref().unwrap();
+        assert!(loaded
+            .document
+            .structure
+            .volumes
+            .iter()
+            .all(|v| v.material_ref != "Steel"));
+        assert!(loaded
+            .document
+            .structure
+            .volumes
+            .iter()
+            .any(|v| v.material_ref == "SteelRenamed"));
+        assert!(loaded.document.materials.materials.iter().any(|m| {
+            m.components.iter().any(|c| match c {
+                MaterialComponent::Fraction { ref_name, .. }
+                | MaterialComponent::Composite { ref_name, .. } => ref_name == "SteelRenamed",
+            })
+        }));
+    }
+
+    #[tokio::test]
+    async fn material_rename_rejects_name_collisions() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        doc.structure.volumes.push(volume("World", "A"));
+        doc.materials.materials.push(material("A"));
+        doc.materials.materials.push(material("B"));
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let req = UpdateMaterialRequest {
+            name: "A".to_string(),
+            material: material("B"),
+        };
+        let err = update_material(State(state.clone()), Json(req))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deleting_material_referenced_by_components_is_blocked() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        doc.structure.volumes.push(volume("World", "Alloy"));
+        doc.materials.materials.push(material("Steel"));
+        let mut alloy = material("Alloy");
+        alloy.components.push(MaterialComponent::Fraction {
+            n: "1.0".to_string(),
+            ref_name: "Steel".to_string(),
+        });
+        doc.materials.materials.push(alloy);
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let err = delete_material(
+            State(state.clone()),
+            Json(DeleteMaterialRequest {
+                name: "Steel".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn add_material_rejects_unknown_component_reference() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        doc.structure.volumes.push(volume("World", "Air"));
+        doc.materials.materials.push(material("Air"));
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let mut invalid = material("Mixture");
+        invalid.components.push(MaterialComponent::Fraction {
+            n: "1.0".to_string(),
+            ref_name: "MissingRef".to_string(),
+        });
+
+        let err = add_material(
+            State(state.clone()),
+            Json(AddMaterialRequest { material: invalid }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn update_material_rejects_direct_self_reference() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        doc.structure.volumes.push(volume("World", "Steel"));
+        doc.materials.materials.push(material("Steel"));
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let mut invalid = material("Steel");
+        invalid.components.push(MaterialComponent::Composite {
+            n: "1".to_string(),
+            ref_name: "Steel".to_string(),
+        });
+
+        let err = update_material(
+            State(state.clone()),
+            Json(UpdateMaterialRequest {
+                name: "Steel".to_string(),
+                material: invalid,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn update_volume_material_ref_rejects_unknown_material() {
+        let state = crate::state::app_state::create_shared_state();
+        let mut doc = base_doc("test.gdml", "World");
+        doc.structure.volumes.push(volume("World", "Steel"));
+        doc.materials.materials.push(material("Steel"));
+
+        {
+            let mut w = state.write().await;
+            w.loaded = Some(LoadedDocument {
+                document: doc,
+                render: None,
+                engine: EvalEngine::new(),
+                meshes: HashMap::new(),
+                quality: MeshQuality::default(),
+                booleans: BooleanBackend::default(),
+                spatial: SceneIndex::default(),
+                lods: HashMap::new(),
+                edges: HashMap::new(),
+                warnings: Vec::new(),
+                file_path: "test.gdml".to_string(),
+                local: None,
+            });
+        }
+
+        let err = update_volume_material_ref(
+            State(state.clone()),
+            Json(UpdateMaterialRefRequest {
+                volume_name: "World".to_string(),
+                material_ref: "Missing".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn nested_includes_resolve_relative_to_the_including_file() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world
+            .physvols
+            .push(file_ref_physvol("modules/tracker.gdml", None));
+        main.structure.volumes.push(main_world);
+
+        // tracker.gdml pulls the same sub-module in twice, once per volume,
+        // from a sibling directory of its own.
+        let mut tracker = base_doc("tracker.gdml", "Tracker");
+        let mut tracker_vol = volume("Tracker", "Vacuum");
+        tracker_vol.physvols.push(file_ref_physvol(
+            "../common/layers.gdml",
+            Some("InnerLayer"),
+        ));
+        tracker_vol.physvols.push(file_ref_physvol(
+            "../common/layers.gdml",
+            Some("OuterLayer"),
+        ));
+        tracker.structure.volumes.push(tracker_vol);
+        tracker.materials.materials.push(material("TrackerGas"));
+
+        let mut layers = base_doc("layers.gdml", "InnerLayer");
+        layers
+            .structure
+            .volumes
+            .push(volume("InnerLayer", "Silicon"));
+        layers
+            .structure
+            .volumes
+            .push(volume("OuterLayer", "Silicon"));
+        layers.materials.materials.push(material("Silicon"));
+
+        let mut child_docs = HashMap::new();
+        child_docs.insert("modules/tracker.gdml".to_string(), tracker);
+        child_docs.insert("common/layers.gdml".to_string(), layers);
+
+        let warnings = resolve_all_file_refs(&mut main, &child_docs)
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        let tracker = main
+            .structure
+            .volumes
+            .iter()
+            .find(|v| v.name == "Tracker")
+            .unwrap();
+        let placed: Vec<&str> = tracker
+            .physvols
+            .iter()
+            .map(|pv| pv.volume_ref.as_str())
+            .collect();
+        assert_eq!(placed, ["InnerLayer", "OuterLayer"]);
+        assert_eq!(
+            tracker.physvols[1].included.as_ref().unwrap().name,
+            "../common/layers.gdml"
+        );
+        // Included twice, merged once.
+        assert_eq!(main.structure.volumes.len(), 4);
+
+        let provenance = main.provenance.as_ref().unwrap();
+        let files: Vec<&str> = provenance.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            files,
+            ["main.gdml", "common/layers.gdml", "modules/tracker.gdml"]
+        );
+        assert_eq!(
+            provenance.source_of("volume", "MainWorld"),
+            Some("main.gdml")
+        );
+        assert_eq!(
+            provenance.source_of("material", "TrackerGas"),
+            Some("modules/tracker.gdml")
+        );
+        assert_eq!(
+            provenance.source_of("volume", "OuterLayer"),
+            Some("common/layers.gdml")
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_rejected_with_the_chain() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world.physvols.push(file_ref_physvol("a.gdml", None));
+        main.structure.volumes.push(main_world);
+
+        let mut a = base_doc("a.gdml", "A");
+        let mut a_vol = volume("A", "Vacuum");
+        a_vol.physvols.push(file_ref_physvol("b.gdml", None));
+        a.structure.volumes.push(a_vol);
+
+        let mut b = base_doc("b.gdml", "B");
+        let mut b_vol = volume("B", "Vacuum");
+        b_vol.physvols.push(file_ref_physvol("a.gdml", None));
+        b.structure.volumes.push(b_vol);
+
+        let mut child_docs = HashMap::new();
+        child_docs.insert("a.gdml".to_string(), a);
+        child_docs.insert("b.gdml".to_string(), b);
+
+        let err = match resolve_all_file_refs(&mut main, &child_docs) {
+            Ok(_) => panic!("an include cycle must be rejected"),
+            Err(err) => err,
+        };
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(
+            err.message.contains("a.gdml -> b.gdml -> a.gdml"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn flat_uploads_match_includes_by_basename() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world
+            .physvols
+            .push(file_ref_physvol("./parts/child.gdml", None));
+        main.structure.volumes.push(main_world);
+
+        let mut child = base_doc("child.gdml", "ChildWorld");
+        child.structure.volumes.push(volume("ChildWorld", "Vacuum"));
+        let mut child_docs = HashMap::new();
+        child_docs.insert("child.gdml".to_string(), child);
+
+        let warnings = resolve_all_file_refs(&mut main, &child_docs)
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            main.structure.volumes[0].physvols[0].volume_ref,
+            "ChildWorld"
+        );
+    }
+
+    #[test]
+    fn resolve_all_file_refs_deduplicates_identical_define_names() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world
             .physvols
-            .push(file_ref_physvol("grand.gdml", None));
-        child.structure.volumes.push(child_world);
+            .push(file_ref_physvol("child.gdml", None));
+        main.structure.volumes.push(main_world);
+        main.defines.constants.push(Constant {
+            name: "A".to_string(),
+            value: "1".to_string(),
+        });
 
-        let mut grand = base_doc("grand.gdml", "GrandWorld");
-        grand.structure.volumes.push(volume("GrandWorld", "Vacuum"));
+        let mut child = base_doc("child.gdml", "ChildWorld");
+        child.structure.volumes.push(volume("ChildWorld", "Vacuum"));
+        child.defines.constants.push(Constant {
+            name: "A".to_string(),
+            value: "1".to_string(),
+        });
 
         let mut child_docs = HashMap::new();
         child_docs.insert("child.gdml".to_string(), child);
-        child_docs.insert("grand.gdml".to_string(), grand);
 
         let warnings = match resolve_all_file_refs(&mut main, &child_docs) {
             Ok(warnings) => warnings,
             Err(err) => panic!("resolve_all_file_refs should succeed: {}", err.message),
         };
         assert!(warnings.is_empty());
-        assert!(collect_file_refs(&main).is_empty());
+        assert_eq!(main.defines.constants.len(), 1);
+    }
 
-        let main_world = main
-            .structure
-            .volumes
-            .iter()
-            .find(|v| v.name == "MainWorld")
-            .unwrap();
-        assert_eq!(main_world.physvols[0].volume_ref, "ChildWorld");
-        assert!(main_world.physvols[0].file_ref.is_none());
+    #[test]
+    fn resolve_all_file_refs_rejects_conflicting_define_names() {
+        let mut main = base_doc("main.gdml", "MainWorld");
+        let mut main_world = volume("MainWorld", "Vacuum");
+        main_world
+            .physvols
+            .push(file_ref_physvol("child.gdml", None));
+        main.structure.volumes.push(main_world);
+        main.defines.constants.push(Constant {
+            name: "A".to_string(),
+            value: "1".to_string(),
+        });
 
-        let child_world = main
-            .structure
-            .volumes
-            .iter()
-            .find(|v| v.name == "ChildWorld")
-            .unwrap();
-        assert_eq!(child_world.physvols[0].volume_ref, "GrandWorld");
-        assert!(child_world.physvols[0].file_ref.is_none());
+        let mut child = base_doc("child.gdml", "ChildWorld");
+        child.structure.volumes.push(volume("ChildWorld", "Vacuum"));
+        child.defines.constants.push(Constant {
+            name: "A".to_string(),
+            value: "2".to_string(),
+        });
+
+        let mut child_docs = HashMap::new();
+        child_docs.insert("child.gdml".to_string(), child);
+
+        let err = match resolve_all_file_refs(&mut main, &child_docs) {
+            Ok(_) => panic!("resolve_all_file_refs should reject conflicting defines"),
+            Err(err) => err,
+        };
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn repeated_logical_volume_instances_have_unique_instance_ids() {
+        let mut doc = base_doc("test.gdml", "World");
+        let mut world = volume("World", "Vacuum");
+        world.physvols.push(PhysVol {
+            name: Some("pv_0".to_string()),
+            volume_ref: "Leaf".to_string(),
+            copynumber: None,
+            file_ref: None,
+            included: None,
+            position: None,
+            rotation: None,
+        });
+        world.physvols.push(PhysVol {
+            name: Some("pv_1".to_string()),
+            volume_ref: "Leaf".to_string(),
+            copynumber: None,
+            file_ref: None,
+            included: None,
+            position: None,
+            rotation: None,
+        });
+        doc.structure.volumes.push(world);
+        doc.structure.volumes.push(volume("Leaf", "Vacuum"));
+
+        let engine = EvalEngine::new();
+        let mut warnings = Vec::new();
+        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
+        assert_eq!(graph.children.len(), 2);
+        assert_eq!(graph.children[0].volume_name, graph.children[1].volume_name);
+        assert_ne!(graph.children[0].instance_id, graph.children[1].instance_id);
+    }
+
+    #[test]
+    fn scene_warnings_are_deduped_with_counts() {
+        // Warnings are raised per placement, so a replicavol nested inside a
+        // 16x16 replica reported the same line 256 times. pinhole_lab.gdml
+        // produced 277 warnings of which 4 were distinct.
+        let mut w = vec![
+            "alpha".to_string(),
+            "beta".to_string(),
+            "alpha".to_string(),
+            "alpha".to_string(),
+        ];
+        dedupe_warnings(&mut w);
+        assert_eq!(w, vec!["alpha (x3)".to_string(), "beta".to_string()]);
+    }
+
+    #[test]
+    fn scene_warnings_are_capped() {
+        let mut w: Vec<String> = (0..150).map(|i| format!("w{i}")).collect();
+        dedupe_warnings(&mut w);
+        assert_eq!(w.len(), 101, "100 warnings plus the overflow note");
+        assert!(w.last().unwrap().contains("50 more"));
     }
 
-    #[tokio::test]
-    async fn material_rename_cascades_to_components_and_volumes() {
-        let state = crate::state::app_state::create_shared_state();
+    #[test]
+    fn replica_positions_are_centered_in_mother() {
+        let mut doc = base_doc("test.gdml", "World");
+        let mut world = volume("World", "Vacuum");
+        world.replica = Some(crate::gdml::model::ReplicaVol {
+            volume_ref: "Slice".to_string(),
+            curvilinear_axis: None,
+            number: "4".to_string(),
+            direction: [Some("1".to_string()), None, None],
+            width: "10".to_string(),
+            width_unit: Some("mm".to_string()),
+            offset: "0".to_string(),
+            offset_unit: None,
+        });
+        doc.structure.volumes.push(world);
+        doc.structure.volumes.push(volume("Slice", "Vacuum"));
+
+        let engine = EvalEngine::new();
+        let mut warnings = Vec::new();
+        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
+        // Geant4 centers the stack: 4 slices of width 10 -> x = -15, -5, +5, +15
+        let xs: Vec<f64> = graph.children.iter().map(|c| c.position[0]).collect();
+        assert_eq!(xs, vec![-15.0, -5.0, 5.0, 15.0]);
+        assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+    }
+    /// Build a scene whose world replicates "Slice" along `axis`.
+    fn replica_scene(
+        curvilinear: Option<&str>,
+        direction: [Option<String>; 3],
+        number: &str,
+        width: &str,
+        width_unit: Option<&str>,
+        offset: &str,
+        offset_unit: Option<&str>,
+    ) -> (SceneNode, Vec<String>) {
         let mut doc = base_doc("test.gdml", "World");
-        let mut world = volume("World", "Steel");
-        world.physvols.push(PhysVol {
-            name: Some("pv_leaf".to_string()),
-            volume_ref: "Leaf".to_string(),
-            copynumber: None,
-            file_ref: None,
-            position: None,
-            rotation: None,
+        let mut world = volume("World", "Vacuum");
+        world.replica = Some(crate::gdml::model::ReplicaVol {
+            volume_ref: "Slice".to_string(),
+            curvilinear_axis: curvilinear.map(|s| s.to_string()),
+            number: number.to_string(),
+            direction,
+            width: width.to_string(),
+            width_unit: width_unit.map(|s| s.to_string()),
+            offset: offset.to_string(),
+            offset_unit: offset_unit.map(|s| s.to_string()),
         });
         doc.structure.volumes.push(world);
-        doc.structure.volumes.push(volume("Leaf", "Steel"));
+        doc.structure.volumes.push(volume("Slice", "Vacuum"));
 
-        let steel = material("Steel");
-        let mut alloy = material("Alloy");
-        alloy.components.push(MaterialComponent::Composite {
-            n: "1".to_string(),
-            ref_name: "Steel".to_string(),
-        });
-        doc.materials.materials.push(steel);
-        doc.materials.materials.push(alloy);
+        let engine = EvalEngine::new();
+        let mut warnings = Vec::new();
+        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
+        (graph, warnings)
+    }
 
-        {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
+    #[test]
+    fn phi_replicas_are_rotated_not_stacked_along_z() {
+        // G4ReplicaNavigation.cc:682 --
+        //   val = -(offset + width*(replicaNo + 0.5)); rotateZ(val)
+        // Four 90-degree slices with no offset sit at -45, -135, -225, -315
+        // degrees in the stored (inverse) convention SceneNode::rotation uses.
+        let (graph, warnings) = replica_scene(
+            Some("phi"),
+            [const { None }; 3],
+            "4",
+            "90",
+            Some("deg"),
+            "0",
+            Some("deg"),
+        );
+
+        let d = std::f64::consts::PI / 180.0;
+        let rz: Vec<f64> = graph.children.iter().map(|c| c.rotation[2]).collect();
+        let want = [-45.0 * d, -135.0 * d, -225.0 * d, -315.0 * d];
+        assert_eq!(rz.len(), 4);
+        for (got, exp) in rz.iter().zip(want.iter()) {
+            assert!((got - exp).abs() < 1e-9, "rotation {got}, expected {exp}");
+        }
+        // Nothing may be displaced -- a phi replica is a pure rotation.
+        for c in &graph.children {
+            assert_eq!(c.position, [0.0, 0.0, 0.0], "phi replica was translated");
         }
+        assert!(
+            warnings.is_empty(),
+            "phi replication should no longer warn: {warnings:?}"
+        );
+    }
 
-        let req = UpdateMaterialRequest {
-            name: "Steel".to_string(),
-            material: material("SteelRenamed"),
-        };
-        let res = update_material(State(state.clone()), Json(req)).await;
-        assert!(res.is_ok(), "update_material should succeed");
+    #[test]
+    fn phi_replica_offset_shifts_every_slice() {
+        // Unlike the Cartesian axes, kPhi really does read `offset`.
+        let (graph, _) = replica_scene(
+            Some("phi"),
+            [const { None }; 3],
+            "2",
+            "90",
+            Some("deg"),
+            "10",
+            Some("deg"),
+        );
+        let d = std::f64::consts::PI / 180.0;
+        let rz: Vec<f64> = graph.children.iter().map(|c| c.rotation[2]).collect();
+        for (got, exp) in rz.iter().zip([-55.0 * d, -145.0 * d].iter()) {
+            assert!((got - exp).abs() < 1e-9, "rotation {got}, expected {exp}");
+        }
+    }
 
-        let r = state.read().await;
-        let loaded = r.loaded.as_ref().unwrap();
-        assert!(loaded
-            .document
-            .structure
-            .volumes
-            .iter()
-            .all(|v| v.material_ref != "Steel"));
+    #[test]
+    fn rho_replication_draws_one_copy_and_says_so() {
+        // ComputeTransformation has "No setup required for radial case": there
+        // is no placement transform, because each slice is a different solid.
+        let (graph, warnings) = replica_scene(
+            Some("rho"),
+            [const { None }; 3],
+            "5",
+            "10",
+            Some("mm"),
+            "0",
+            None,
+        );
+        assert_eq!(
+            graph.children.len(),
+            1,
+            "five coincident copies show exactly what one shows"
+        );
+        assert_eq!(graph.children[0].position, [0.0, 0.0, 0.0]);
+        assert!(
+            warnings.iter().any(|w| w.contains("kRho")),
+            "radial replication must be reported: {warnings:?}"
+        );
+    }
+
+    #[test]
+    fn cartesian_replica_offset_is_reported_as_inert() {
+        // ComputeTransformation's kXAxis case never reads `offset`, so a file
+        // carrying one gets a stack that ignores it -- in Geant4 too.
+        let (_, warnings) = replica_scene(
+            None,
+            [Some("1".to_string()), None, None],
+            "2",
+            "10",
+            Some("mm"),
+            "5",
+            Some("mm"),
+        );
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("Geant4 ignores it here too")),
+            "expected the offset note: {warnings:?}"
+        );
+    }
+
+    #[test]
+    fn local_open_merges_file_references_next_to_the_main_file() {
+        let root = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join("sample_data");
+        let loaded = open_local_document(
+            &root,
+            Path::new("test_modular_mother.gdml"),
+            &MeshQuality::fixed(8),
+            None,
+        )
+        .unwrap_or_else(|e| panic!("{}", e.message));
+
+        let local = loaded.local.as_ref().unwrap();
+        assert_eq!(
+            local.fingerprints.len(),
+            2,
+            "mother and child are both tracked"
+        );
+        assert!(local.main_path.ends_with("test_modular_mother.gdml"));
         assert!(loaded
             .document
             .structure
             .volumes
             .iter()
-            .any(|v| v.material_ref == "SteelRenamed"));
-        assert!(loaded.document.materials.materials.iter().any(|m| {
-            m.components.iter().any(|c| match c {
-                MaterialComponent::Fraction { ref_name, .. }
-                | MaterialComponent::Composite { ref_name, .. } => ref_name == "SteelRenamed",
-            })
-        }));
+            .all(|v| v.physvols.iter().all(|pv| pv.file_ref.is_none())));
+
+        let scratch = local_files::scratch_dir("open-escape");
+        let inner = scratch.join("inner");
+        std::fs::create_dir_all(&inner).unwrap();
+        std::fs::write(scratch.join("outside.gdml"), "<gdml/>").unwrap();
+        let err = open_local_document(
+            &inner,
+            Path::new("../outside.gdml"),
+            &MeshQuality::default(),
+            None,
+        )
+        .err()
+        .unwrap();
+        std::fs::remove_dir_all(&scratch).ok();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(
+            err.message.contains("outside the local file root"),
+            "{}",
+            err.message
+        );
     }
 
     #[tokio::test]
-    async fn material_rename_rejects_name_collisions() {
+    async fn a_change_on_disk_does_not_overwrite_unsaved_edits() {
+        let gdml = |size: &str| {
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define><constant name="w" value="{size}"/></define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids><box name="WorldBox" x="w" y="w" z="w"/></solids>
+  <structure>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#
+            )
+        };
+        let root = local_files::scratch_dir("reload-conflict");
+        let main = root.join("world.gdml");
+        std::fs::write(&main, gdml("100")).unwrap();
+        let loaded =
+            open_local_document(&root, Path::new("world.gdml"), &MeshQuality::fixed(8), None)
+                .unwrap_or_else(|e| panic!("{}", e.message));
         let state = crate::state::app_state::create_shared_state();
-        let mut doc = base_doc("test.gdml", "World");
-        doc.structure.volumes.push(volume("World", "A"));
-        doc.materials.materials.push(material("A"));
-        doc.materials.materials.push(material("B"));
+        state.write().await.loaded = Some(loaded);
 
+        // Unedited: a change on disk is picked up.
+        std::fs::write(&main, gdml("200")).unwrap();
+        reload_local_document_under(&state, &root).await;
         {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert_eq!(loaded.engine.context.get("w"), Some(200.0));
+            assert_eq!(loaded.local.as_ref().unwrap().revision, 1);
         }
 
-        let req = UpdateMaterialRequest {
-            name: "A".to_string(),
-            material: material("B"),
+        // Edited through the API: the edit is kept and the change reported.
+        let rename = RenameRequest {
+            kind: "constant".to_string(),
+            name: "w".to_string(),
+            new_name: "width".to_string(),
         };
-        let err = update_material(State(state.clone()), Json(req))
+        let res = rename_item(State(state.clone()), Json(rename))
             .await
-            .unwrap_err();
-        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["ok"], true);
+        std::fs::write(&main, gdml("300")).unwrap();
+        reload_local_document_under(&state, &root).await;
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert_eq!(loaded.engine.context.get("width"), Some(200.0));
+            let local = loaded.local.as_ref().unwrap();
+            assert!(local.dirty);
+            assert_eq!(local.revision, 1);
+            let status = local_status(Some(local));
+            assert!(
+                status["last_error"]
+                    .as_str()
+                    .is_some_and(|e| e.contains("unsaved edits")),
+                "{status}"
+            );
+        }
+        std::fs::remove_dir_all(&root).ok();
     }
 
-    #[tokio::test]
-    async fn deleting_material_referenced_by_components_is_blocked() {
-        let state = crate::state::app_state::create_shared_state();
-        let mut doc = base_doc("test.gdml", "World");
-        doc.structure.volumes.push(volume("World", "Alloy"));
-        doc.materials.materials.push(material("Steel"));
-        let mut alloy = material("Alloy");
-        alloy.components.push(MaterialComponent::Fraction {
-            n: "1.0".to_string(),
-            ref_name: "Steel".to_string(),
-        });
-        doc.materials.materials.push(alloy);
+    #[test]
+    fn modular_export_restores_file_references_and_reloads_identically() {
+        let samples = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join("sample_data");
+        let mut files = HashMap::new();
+        for name in ["test_modular_mother.gdml", "test_modular_child.gdml"] {
+            files.insert(
+                name.to_string(),
+                std::fs::read_to_string(samples.join(name)).unwrap(),
+            );
+        }
+        let loaded = load_multi_document(
+            &files,
+            "test_modular_mother.gdml",
+            &MeshQuality::fixed(8),
+            None,
+        )
+        .unwrap_or_else(|e| panic!("{}", e.message));
+
+        let modules = modular::split_into_modules(&loaded.document).unwrap();
+        let written: HashMap<String, String> = modules
+            .iter()
+            .map(|m| (m.path.clone(), nist::serialize_gdml(&m.document).unwrap()))
+            .collect();
+        let mother = &written["test_modular_mother.gdml"];
+        let child = &written["test_modular_child.gdml"];
+        assert!(mother.contains(r#"<file name="test_modular_child.gdml" volname="ChildWorld"/>"#));
+        assert!(!mother.contains("CopperBox"));
+        assert!(child.contains("CopperBox") && !child.contains("Aluminium"));
+
+        let reloaded = load_multi_document(
+            &written,
+            "test_modular_mother.gdml",
+            &MeshQuality::fixed(8),
+            None,
+        )
+        .unwrap_or_else(|e| panic!("{}", e.message));
+        let names = |doc: &GdmlDocument| {
+            let mut v: Vec<String> = doc
+                .structure
+                .volumes
+                .iter()
+                .map(|v| v.name.clone())
+                .collect();
+            v.sort();
+            v
+        };
+        assert_eq!(names(&reloaded.document), names(&loaded.document));
+        assert_eq!(reloaded.meshes.len(), loaded.meshes.len());
+    }
 
+    #[tokio::test]
+    async fn matrix_edits_cascade_to_properties_and_are_validated() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <constant name="N_AIR" value="1.0003"/>
+    <matrix name="RINDEX_W" coldim="2" values="1.5*eV 1.33 3.0*eV 1.34"/>
+    <matrix name="SCALE" coldim="1" values="2 3"/>
+    <constant name="N_BLUE" value="RINDEX_W[2,2]"/>
+    <constant name="WALL" value="SCALE_0*mm"/>
+    <matrix name="WIDE" coldim="3" values="1 2 3"/>
+  </define>
+  <materials>
+    <material name="Water" state="liquid">
+      <property name="RINDEX" ref="RINDEX_W"/>
+      <property name="ABSLENGTH" ref="ABS_W"/>
+      <property name="WLSABSLENGTH" ref="WIDE"/>
+      <D value="1"/><atom value="18"/>
+    </material>
+  </materials>
+  <solids><box name="WorldBox" x="10" y="10" z="10"/></solids>
+  <structure>
+    <volume name="World"><materialref ref="Water"/><solidref ref="WorldBox"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
         {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(loaded
+                .warnings
+                .iter()
+                .any(|w| w.contains("\"ABS_W\", which does not exist")));
+            assert!(loaded.warnings.iter().any(|w| w.contains("coldim=3")));
         }
 
-        let err = delete_material(
+        // An unbalanced table is refused.
+        let bad = Matrix {
+            name: "ABS_W".to_string(),
+            coldim: "2".to_string(),
+            values: "1.5*eV 1*m 3.0*eV".to_string(),
+        };
+        let err = add_matrix(State(state.clone()), Json(AddMatrixRequest { matrix: bad }))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("unbalanced matrix accepted"));
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        // So is a single value, which Geant4 wants as a constant.
+        let single = Matrix {
+            name: "ABS_W".to_string(),
+            coldim: "1".to_string(),
+            values: "1*m".to_string(),
+        };
+        let err = add_matrix(
             State(state.clone()),
-            Json(DeleteMaterialRequest {
-                name: "Steel".to_string(),
-            }),
+            Json(AddMatrixRequest { matrix: single }),
         )
         .await
-        .unwrap_err();
-        assert_eq!(err.status, StatusCode::BAD_REQUEST);
-    }
+        .err()
+        .unwrap_or_else(|| panic!("single-value matrix accepted"));
+        assert!(err.message.contains("single value"), "{}", err.message);
+
+        // And one that would shadow a constant in the evaluator.
+        let shadow = Matrix {
+            name: "N_AIR".to_string(),
+            coldim: "1".to_string(),
+            values: "1.0003".to_string(),
+        };
+        let err = add_matrix(
+            State(state.clone()),
+            Json(AddMatrixRequest { matrix: shadow }),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("matrix named like a constant accepted"));
+        assert!(err.message.contains("another define"), "{}", err.message);
+
+        let abs = Matrix {
+            name: "ABS_W".to_string(),
+            coldim: "2".to_string(),
+            values: "1.5*eV 1*m 3.0*eV 2*m".to_string(),
+        };
+        let res = add_matrix(State(state.clone()), Json(AddMatrixRequest { matrix: abs }))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["warnings"], json!([]));
 
-    #[tokio::test]
-    async fn add_material_rejects_unknown_component_reference() {
-        let state = crate::state::app_state::create_shared_state();
-        let mut doc = base_doc("test.gdml", "World");
-        doc.structure.volumes.push(volume("World", "Air"));
-        doc.materials.materials.push(material("Air"));
+        let renamed = Matrix {
+            name: "RINDEX_WATER".to_string(),
+            coldim: "2".to_string(),
+            values: "1.5*eV 1.33 3.0*eV 1.35".to_string(),
+        };
+        let req = UpdateMatrixRequest {
+            name: "RINDEX_W".to_string(),
+            matrix: renamed,
+        };
+        let res = update_matrix(State(state.clone()), Json(req))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["ok"], true);
 
         {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
+            let r = state.read().await;
+            let loaded = r.loaded.as_ref().unwrap();
+            let water = &loaded.document.materials.materials[0];
+            assert_eq!(
+                water.properties[0].ref_name.as_deref(),
+                Some("RINDEX_WATER")
+            );
+            assert_eq!(
+                loaded.engine.matrix_values["RINDEX_WATER"].get(1, 1),
+                Some(1.35)
+            );
+            assert_eq!(loaded.engine.matrix_values["ABS_W"].get(1, 1), Some(2000.0));
+            // Expressions indexing the matrix follow it too.
+            assert_eq!(
+                loaded.document.defines.constants[1].value,
+                "RINDEX_WATER[2,2]"
+            );
+            assert_eq!(loaded.engine.context.get("N_BLUE"), Some(1.35));
         }
 
-        let mut invalid = material("Mixture");
-        invalid.components.push(MaterialComponent::Fraction {
-            n: "1.0".to_string(),
-            ref_name: "MissingRef".to_string(),
-        });
+        let err = delete_matrix(
+            State(state.clone()),
+            Json(DeleteMatrixRequest {
+                name: "RINDEX_WATER".to_string(),
+            }),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("delete of a referenced matrix accepted"));
+        assert!(err.message.contains("Water/RINDEX"));
 
-        let err = add_material(
+        // So is one only an expression reads, through a generated entry name.
+        let err = delete_matrix(
             State(state.clone()),
-            Json(AddMaterialRequest { material: invalid }),
+            Json(DeleteMatrixRequest {
+                name: "SCALE".to_string(),
+            }),
         )
         .await
-        .unwrap_err();
-        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        .err()
+        .unwrap_or_else(|| panic!("delete of a matrix used in an expression accepted"));
+        assert!(
+            err.message.contains("constant WALL/value"),
+            "{}",
+            err.message
+        );
     }
 
     #[tokio::test]
-    async fn update_material_rejects_direct_self_reference() {
-        let state = crate::state::app_state::create_shared_state();
-        let mut doc = base_doc("test.gdml", "World");
-        doc.structure.volumes.push(volume("World", "Steel"));
-        doc.materials.materials.push(material("Steel"));
+    async fn matrix_edits_remesh_the_solids_that_read_them() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <matrix name="SIZE" coldim="1" values="10 0"/>
+    <constant name="HALF" value="SIZE_0/2"/>
+  </define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <tube name="Rod" rmax="HALF" z="SIZE[1]" deltaphi="360" aunit="deg"/>
+    <box name="Plate" x="20" y="20" z="2"/>
+    <union name="Cross"><first ref="Rod"/><second ref="Plate"/></union>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+  </solids>
+  <structure>
+    <volume name="CrossVol"><materialref ref="Vacuum"/><solidref ref="Cross"/></volume>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="CrossVol"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(16)).await;
+        let max_z = |mesh: &TriangleMesh| {
+            mesh.positions
+                .chunks(3)
+                .map(|p| p[2])
+                .fold(f32::MIN, f32::max)
+        };
+        let plate_before = {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!((max_z(&loaded.meshes["Rod"]) - 5.0).abs() < 1e-4);
+            loaded.meshes["Plate"].clone()
+        };
+
+        let req = UpdateMatrixRequest {
+            name: "SIZE".to_string(),
+            matrix: Matrix {
+                name: "LENGTH".to_string(),
+                coldim: "1".to_string(),
+                values: "40 0".to_string(),
+            },
+        };
+        let res = update_matrix(State(state.clone()), Json(req))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["warnings"], json!([]));
+
+        let s = state.read().await;
+        let loaded = s.loaded.as_ref().unwrap();
+        assert_eq!(loaded.engine.context.get("HALF"), Some(20.0));
+        // The tube reads the matrix, the union is built from the tube, and
+        // the plate reads neither, so it keeps its mesh.
+        assert!((max_z(&loaded.meshes["Rod"]) - 20.0).abs() < 1e-4);
+        assert!((max_z(&loaded.meshes["Cross"]) - 20.0).abs() < 1e-4);
+        assert_eq!(loaded.meshes["Plate"].positions, plate_before.positions);
+        assert!(loaded.lods.contains_key("Cross") && loaded.edges.contains_key("Cross"));
+        let hit = loaded
+            .spatial
+            .ray(
+                &loaded.meshes,
+                [1.0, 0.5, 100.0],
+                [0.0, 0.0, -1.0],
+                f64::INFINITY,
+            )
+            .expect("the lengthened rod is hit from above");
+        assert!((hit.distance - 80.0).abs() < 1e-3, "{}", hit.distance);
+    }
 
+    #[tokio::test]
+    async fn optical_surface_edits_cascade_and_references_are_checked() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <opticalsurface name="Paint" model="glisur" finish="Rough_LUT" type="dielectric_metal"/>
+  </solids>
+  <structure>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/></volume>
+    <skinsurface name="Coat" surfaceproperty="Paint"><volumeref ref="World"/></skinsurface>
+    <bordersurface name="Edge" surfaceproperty="Paint"><physvolref ref="nowhere"/></bordersurface>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
         {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(loaded
+                .warnings
+                .iter()
+                .any(|w| w.contains("finish Rough_LUT, which belongs to the DAVIS model")));
+            assert!(loaded.warnings.iter().any(|w| w.contains("\"nowhere\"")));
+            assert!(loaded.warnings.iter().any(|w| w.contains("1 physvolref")));
         }
 
-        let mut invalid = material("Steel");
-        invalid.components.push(MaterialComponent::Composite {
-            n: "1".to_string(),
-            ref_name: "Steel".to_string(),
-        });
+        let mut renamed = OpticalSurface {
+            name: "WhitePaint".to_string(),
+            model: "unified".to_string(),
+            finish: "Groundy".to_string(),
+            r#type: "dielectric_metal".to_string(),
+            value: None,
+            properties: Vec::new(),
+        };
+        let req = UpdateOpticalSurfaceRequest {
+            name: "Paint".to_string(),
+            surface: renamed.clone(),
+        };
+        let err = update_optical_surface(State(state.clone()), Json(req))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("unknown finish accepted"));
+        assert!(err.message.contains("unknown finish"));
+
+        renamed.finish = "ground".to_string();
+        let req = UpdateOpticalSurfaceRequest {
+            name: "Paint".to_string(),
+            surface: renamed,
+        };
+        let res = update_optical_surface(State(state.clone()), Json(req))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["ok"], true);
 
-        let err = update_material(
+        let res = get_surfaces(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["skin_surfaces"][0]["surface_property"], "WhitePaint");
+        assert_eq!(
+            res.0["optical_surfaces"][0]["used_by"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let err = delete_optical_surface(
             State(state.clone()),
-            Json(UpdateMaterialRequest {
-                name: "Steel".to_string(),
-                material: invalid,
+            Json(DeleteSurfaceRequest {
+                name: "WhitePaint".to_string(),
             }),
         )
         .await
-        .unwrap_err();
-        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        .err()
+        .unwrap_or_else(|| panic!("delete of a used optical surface accepted"));
+        assert!(err.message.contains("Coat, Edge"));
+
+        let border = BorderSurface {
+            name: "Edge2".to_string(),
+            surface_property: "WhitePaint".to_string(),
+            physvol_refs: vec!["nowhere".to_string(), "World_PV".to_string()],
+        };
+        let err = add_border_surface(
+            State(state.clone()),
+            Json(AddBorderSurfaceRequest { surface: border }),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("border surface with unknown placement accepted"));
+        assert!(err.message.contains("'nowhere'"));
     }
 
     #[tokio::test]
-    async fn update_volume_material_ref_rejects_unknown_material() {
-        let state = crate::state::app_state::create_shared_state();
-        let mut doc = base_doc("test.gdml", "World");
-        doc.structure.volumes.push(volume("World", "Steel"));
-        doc.materials.materials.push(material("Steel"));
-
-        {
-            let mut w = state.write().await;
-            w.loaded = Some(LoadedDocument {
-                document: doc,
-                render: None,
-                engine: EvalEngine::new(),
-                meshes: HashMap::new(),
-                warnings: Vec::new(),
-                file_path: "test.gdml".to_string(),
-            });
-        }
-
-        let err = update_volume_material_ref(
+    async fn evaluate_traces_the_loaded_document() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <quantity name="wall" type="length" value="2" unit="cm"/>
+    <constant name="gap" value="wal/2"/>
+  </define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids><box name="WorldBox" x="100" y="100" z="100"/></solids>
+  <structure>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+
+        let res = evaluate_expression(
             State(state.clone()),
-            Json(UpdateMaterialRefRequest {
-                volume_name: "World".to_string(),
-                material_ref: "Missing".to_string(),
+            Json(EvaluateExpressionRequest {
+                expression: "wall + gap*mm".to_string(),
             }),
         )
         .await
-        .unwrap_err();
+        .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["value"], 20.0);
+        assert_eq!(res.0["dependencies"].as_array().unwrap().len(), 2);
+        assert!(res.0["errors"][0]
+            .as_str()
+            .unwrap()
+            .starts_with("constant \"gap\" = \"wal/2\" could not be evaluated"));
+
+        let err = evaluate_expression(
+            State(state.clone()),
+            Json(EvaluateExpressionRequest {
+                expression: " ".to_string(),
+            }),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("empty expression accepted"));
         assert_eq!(err.status, StatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn resolve_all_file_refs_deduplicates_identical_define_names() {
-        let mut main = base_doc("main.gdml", "MainWorld");
-        let mut main_world = volume("MainWorld", "Vacuum");
-        main_world
-            .physvols
-            .push(file_ref_physvol("child.gdml", None));
-        main.structure.volumes.push(main_world);
-        main.defines.constants.push(Constant {
-            name: "A".to_string(),
-            value: "1".to_string(),
-        });
-
-        let mut child = base_doc("child.gdml", "ChildWorld");
-        child.structure.volumes.push(volume("ChildWorld", "Vacuum"));
-        child.defines.constants.push(Constant {
-            name: "A".to_string(),
-            value: "1".to_string(),
-        });
-
-        let mut child_docs = HashMap::new();
-        child_docs.insert("child.gdml".to_string(), child);
-
-        let warnings = match resolve_all_file_refs(&mut main, &child_docs) {
-            Ok(warnings) => warnings,
-            Err(err) => panic!("resolve_all_file_refs should succeed: {}", err.message),
+    #[tokio::test]
+    async fn references_report_referrers_and_blast_radius() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <constant name="half" value="5"/>
+    <constant name="full" value="2*half"/>
+    <variable name="i" value="0"/>
+  </define>
+  <materials>
+    <element name="H" formula="H" Z="1"><atom value="1.008"/></element>
+    <material name="Gas" state="gas"><D value="1e-3"/><fraction n="1" ref="H"/></material>
+  </materials>
+  <solids>
+    <box name="Cell" x="full" y="full" z="half"/>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+  </solids>
+  <structure>
+    <volume name="CellVol"><materialref ref="Gas"/><solidref ref="Cell"/></volume>
+    <volume name="World">
+      <materialref ref="Gas"/><solidref ref="WorldBox"/>
+      <loop for="i" from="0" to="2" step="1">
+        <physvol><volumeref ref="CellVol"/><position name="p" x="i*half"/></physvol>
+      </loop>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+
+        let query = |name: &str| ReferencesQuery {
+            name: name.to_string(),
+            kind: None,
         };
-        assert!(warnings.is_empty());
-        assert_eq!(main.defines.constants.len(), 1);
-    }
-
-    #[test]
-    fn resolve_all_file_refs_rejects_conflicting_define_names() {
-        let mut main = base_doc("main.gdml", "MainWorld");
-        let mut main_world = volume("MainWorld", "Vacuum");
-        main_world
-            .physvols
-            .push(file_ref_physvol("child.gdml", None));
-        main.structure.volumes.push(main_world);
-        main.defines.constants.push(Constant {
-            name: "A".to_string(),
-            value: "1".to_string(),
-        });
+        let res = get_references(State(state.clone()), Query(query("half")))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let item = &res.0["items"][0];
+        assert_eq!(item["kind"], "constant");
+        assert_eq!(item["file"], "test.gdml");
+        let referrers: Vec<String> = item["referrers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| format!("{} {}: {}", r["kind"], r["name"], r["attribute"]))
+            .map(|s| s.replace('"', ""))
+            .collect();
+        assert_eq!(
+            referrers,
+            [
+                "constant full: value",
+                "solid Cell: z",
+                "volume World: loop[0]/position/x",
+            ]
+        );
+        let dependents: Vec<&str> = item["dependents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(dependents, ["full", "Default", "Cell", "CellVol", "World"]);
 
-        let mut child = base_doc("child.gdml", "ChildWorld");
-        child.structure.volumes.push(volume("ChildWorld", "Vacuum"));
-        child.defines.constants.push(Constant {
-            name: "A".to_string(),
-            value: "2".to_string(),
-        });
+        let res = get_references(State(state.clone()), Query(query("CellVol")))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(
+            res.0["items"][0]["referrers"][0]["attribute"],
+            "loop[0]/volumeref"
+        );
+        let res = get_references(State(state.clone()), Query(query("H")))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(
+            res.0["items"][0]["referrers"][0]["attribute"],
+            "fraction[0]"
+        );
 
-        let mut child_docs = HashMap::new();
-        child_docs.insert("child.gdml".to_string(), child);
+        let err = get_references(State(state.clone()), Query(query("Nope")))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("unknown name answered"));
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
 
-        let err = match resolve_all_file_refs(&mut main, &child_docs) {
-            Ok(_) => panic!("resolve_all_file_refs should reject conflicting defines"),
-            Err(err) => err,
+    #[tokio::test]
+    async fn rename_rewrites_references_and_keeps_state_in_step() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define><constant name="w" value="10"/><position name="up" z="w"/></define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="Cell" x="w" y="w" z="w"/>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+  </solids>
+  <structure>
+    <volume name="CellVol"><materialref ref="Vacuum"/><solidref ref="Cell"/></volume>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="CellVol"/><positionref ref="up"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+
+        let req = |kind: &str, name: &str, new_name: &str| RenameRequest {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            new_name: new_name.to_string(),
         };
+        let res = rename_item(State(state.clone()), Json(req("constant", "w", "width")))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["references_updated"], 4);
+        // Marked, so a level of detail built again instead of moved shows.
+        let marked_edges = {
+            let mut s = state.write().await;
+            let loaded = s.loaded.as_mut().unwrap();
+            loaded.lods.get_mut("Cell").unwrap().min_screen_size = -1.0;
+            loaded.edges.get_mut("Cell").unwrap().push(u32::MAX);
+            loaded.edges["Cell"].clone()
+        };
+        let res = rename_item(State(state.clone()), Json(req("solid", "Cell", "CellBox")))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["ok"], true);
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert_eq!(loaded.engine.context.get("width"), Some(10.0));
+            assert_eq!(loaded.engine.position_values["up"], [0.0, 0.0, 10.0]);
+            assert!(loaded.meshes.contains_key("CellBox"));
+            assert_eq!(loaded.lods["CellBox"].min_screen_size, -1.0);
+            assert_eq!(loaded.edges["CellBox"], marked_edges);
+            assert!(!loaded.lods.contains_key("Cell") && !loaded.edges.contains_key("Cell"));
+            assert_eq!(loaded.document.structure.volumes[0].solid_ref, "CellBox");
+        }
+
+        let err = rename_item(State(state.clone()), Json(req("volume", "Nope", "X")))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("rename of a missing volume accepted"));
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        let err = rename_item(
+            State(state.clone()),
+            Json(req("volume", "CellVol", "World")),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("colliding rename accepted"));
         assert_eq!(err.status, StatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn repeated_logical_volume_instances_have_unique_instance_ids() {
-        let mut doc = base_doc("test.gdml", "World");
-        let mut world = volume("World", "Vacuum");
-        world.physvols.push(PhysVol {
-            name: Some("pv_0".to_string()),
-            volume_ref: "Leaf".to_string(),
-            copynumber: None,
-            file_ref: None,
-            position: None,
-            rotation: None,
-        });
-        world.physvols.push(PhysVol {
-            name: Some("pv_1".to_string()),
-            volume_ref: "Leaf".to_string(),
-            copynumber: None,
-            file_ref: None,
-            position: None,
-            rotation: None,
-        });
-        doc.structure.volumes.push(world);
-        doc.structure.volumes.push(volume("Leaf", "Vacuum"));
+    #[tokio::test]
+    async fn prune_removes_what_the_world_does_not_use() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define><constant name="w" value="10"/><constant name="spare" value="1"/></define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+    <material name="Lead"><D value="11.35"/><atom value="207.2"/></material>
+  </materials>
+  <solids>
+    <box name="Cell" x="w" y="w" z="w"/>
+    <box name="Brick" x="spare" y="1" z="1"/>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+  </solids>
+  <structure>
+    <volume name="CellVol"><materialref ref="Vacuum"/><solidref ref="Cell"/></volume>
+    <volume name="BrickVol"><materialref ref="Lead"/><solidref ref="Brick"/></volume>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="CellVol"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+
+        let res = get_unused(State(state.clone()), Query(PruneRequest::default()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["world"], "World");
+        let names: Vec<&str> = res.0["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["spare", "Lead", "Brick", "BrickVol"]);
 
-        let engine = EvalEngine::new();
-        let mut warnings = Vec::new();
-        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
-        assert_eq!(graph.children.len(), 2);
-        assert_eq!(graph.children[0].volume_name, graph.children[1].volume_name);
-        assert_ne!(graph.children[0].instance_id, graph.children[1].instance_id);
-    }
+        let res = prune_unused(State(state.clone()), Json(PruneRequest::default()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["count"], 4);
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(!loaded.meshes.contains_key("Brick"));
+            assert_eq!(loaded.engine.context.get("spare"), None);
+            assert_eq!(loaded.document.materials.materials.len(), 1);
+            let gdml = nist::serialize_gdml(&loaded.document).unwrap();
+            assert!(!gdml.contains("BrickVol"));
+        }
 
-    #[test]
-    fn scene_warnings_are_deduped_with_counts() {
-        // Warnings are raised per placement, so a replicavol nested inside a
-        // 16x16 replica reported the same line 256 times. pinhole_lab.gdml
-        // produced 277 warnings of which 4 were distinct.
-        let mut w = vec![
-            "alpha".to_string(),
-            "beta".to_string(),
-            "alpha".to_string(),
-            "alpha".to_string(),
-        ];
-        dedupe_warnings(&mut w);
-        assert_eq!(w, vec!["alpha (x3)".to_string(), "beta".to_string()]);
+        let err = get_unused(
+            State(state.clone()),
+            Query(PruneRequest {
+                world: Some("Nope".to_string()),
+            }),
+        )
+        .await
+        .err()
+        .unwrap_or_else(|| panic!("missing world accepted"));
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
     }
 
-    #[test]
-    fn scene_warnings_are_capped() {
-        let mut w: Vec<String> = (0..150).map(|i| format!("w{i}")).collect();
-        dedupe_warnings(&mut w);
-        assert_eq!(w.len(), 101, "100 warnings plus the overflow note");
-        assert!(w.last().unwrap().contains("50 more"));
+    #[tokio::test]
+    async fn schema_issues_are_reported_on_load_and_export() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100" lunit="micron"/>
+  </solids>
+  <structure>
+    <volume name="World"><materialref ref="Vacuum"/><solidref ref="WorldBox"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(loaded.warnings.iter().any(
+                |w| w == "test.gdml, line 7, column 50: lunit=\"micron\" is not a length unit"
+            ));
+        }
+
+        // The writer keeps the unit as written, so the export has it too.
+        let res = validate_document(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["valid"], false);
+        assert_eq!(res.0["issues"][0]["attribute"], "lunit");
+        let res = export_gdml(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["schema_issues"].as_array().map(Vec::len), Some(1));
     }
 
-    #[test]
-    fn replica_positions_are_centered_in_mother() {
-        let mut doc = base_doc("test.gdml", "World");
-        let mut world = volume("World", "Vacuum");
-        world.replica = Some(crate::gdml::model::ReplicaVol {
-            volume_ref: "Slice".to_string(),
-            curvilinear_axis: None,
-            number: "4".to_string(),
-            direction: [Some("1".to_string()), None, None],
-            width: "10".to_string(),
-            width_unit: Some("mm".to_string()),
-            offset: "0".to_string(),
-            offset_unit: None,
-        });
-        doc.structure.volumes.push(world);
-        doc.structure.volumes.push(volume("Slice", "Vacuum"));
+    #[tokio::test]
+    async fn solids_report_what_geant4_would_reject() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <tube name="Pipe" rmin="8" rmax="5" z="20" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Pipe"><materialref ref="Vacuum"/><solidref ref="Pipe"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Pipe"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let expected = "Solid \"Pipe\": rmin (8 mm) must be less than rmax (5 mm). \
+                        Geant4 will abort when building it.";
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(loaded.warnings.iter().any(|w| w == expected));
+        }
 
-        let engine = EvalEngine::new();
-        let mut warnings = Vec::new();
-        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
-        // Geant4 centers the stack: 4 slices of width 10 -> x = -15, -5, +5, +15
-        let xs: Vec<f64> = graph.children.iter().map(|c| c.position[0]).collect();
-        assert_eq!(xs, vec![-15.0, -5.0, 5.0, 15.0]);
-        assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+        let res = get_solids(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["solids"][0]["issues"], json!([]));
+        assert_eq!(res.0["solids"][1]["issues"][0]["severity"], "error");
+        assert_eq!(res.0["warnings"], json!([expected]));
     }
-    /// Build a scene whose world replicates "Slice" along `axis`.
-    fn replica_scene(
-        curvilinear: Option<&str>,
-        direction: [Option<String>; 3],
-        number: &str,
-        width: &str,
-        width_unit: Option<&str>,
-        offset: &str,
-        offset_unit: Option<&str>,
-    ) -> (SceneNode, Vec<String>) {
-        let mut doc = base_doc("test.gdml", "World");
-        let mut world = volume("World", "Vacuum");
-        world.replica = Some(crate::gdml::model::ReplicaVol {
-            volume_ref: "Slice".to_string(),
-            curvilinear_axis: curvilinear.map(|s| s.to_string()),
-            number: number.to_string(),
-            direction,
-            width: width.to_string(),
-            width_unit: width_unit.map(|s| s.to_string()),
-            offset: offset.to_string(),
-            offset_unit: offset_unit.map(|s| s.to_string()),
-        });
-        doc.structure.volumes.push(world);
-        doc.structure.volumes.push(volume("Slice", "Vacuum"));
 
-        let engine = EvalEngine::new();
-        let mut warnings = Vec::new();
-        let graph = build_scene_graph(&doc, &doc.materials, &engine, &mut warnings);
-        (graph, warnings)
+    #[tokio::test]
+    async fn tessellated_meshes_are_checked_and_repaired() {
+        // A tetrahedron with its last facet wound the wrong way round.
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <position name="a" x="0" y="0" z="0"/>
+    <position name="b" x="10" y="0" z="0"/>
+    <position name="c" x="0" y="10" z="0"/>
+    <position name="d" x="0" y="0" z="10"/>
+  </define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <tessellated name="Tet">
+      <triangular vertex1="a" vertex2="c" vertex3="b"/>
+      <triangular vertex1="a" vertex2="b" vertex3="d"/>
+      <triangular vertex1="a" vertex2="d" vertex3="c"/>
+      <triangular vertex1="b" vertex2="d" vertex3="c"/>
+    </tessellated>
+  </solids>
+  <structure>
+    <volume name="Tet"><materialref ref="Vacuum"/><solidref ref="Tet"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Tet"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(8)).await;
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert!(loaded.warnings.iter().any(|w| w
+                == "Tessellated solid \"Tet\": 1 flipped facet. Geant4 needs a closed surface \
+                    with every facet facing outward."));
+        }
+
+        let res = get_mesh_integrity(State(state.clone()), Query(MeshIntegrityQuery::default()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["count"], 2);
+        assert_eq!(res.0["with_problems"], 1);
+        assert_eq!(res.0["solids"][1]["report"]["watertight"], true);
+        assert_eq!(res.0["solids"][1]["report"]["flipped_triangles"], 1);
+
+        let req: MeshRepairRequest = serde_json::from_value(json!({ "solids": ["Tet"] })).unwrap();
+        let res = repair_meshes(State(state.clone()), Json(req))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["repaired"][0]["after"]["consistently_oriented"], true);
+        let volume = res.0["repaired"][0]["after"]["volume"].as_f64().unwrap();
+        assert!((volume - 1000.0 / 6.0).abs() < 1e-3, "volume {}", volume);
+
+        let query = MeshIntegrityQuery {
+            solid: Some("Nope".to_string()),
+        };
+        let err = get_mesh_integrity(State(state.clone()), Query(query))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("an unknown solid should be refused"));
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
     }
 
-    #[test]
-    fn phi_replicas_are_rotated_not_stacked_along_z() {
-        // G4ReplicaNavigation.cc:682 --
-        //   val = -(offset + width*(replicaNo + 0.5)); rotateZ(val)
-        // Four 90-degree slices with no offset sit at -45, -135, -225, -315
-        // degrees in the stored (inverse) convention SceneNode::rotation uses.
-        let (graph, warnings) = replica_scene(
-            Some("phi"),
-            [const { None }; 3],
-            "4",
-            "90",
-            Some("deg"),
-            "0",
-            Some("deg"),
+    #[tokio::test]
+    async fn meshes_carry_levels_of_detail_on_request() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <tube name="Pipe" rmin="5" rmax="8" z="20" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Pipe"><materialref ref="Vacuum"/><solidref ref="Pipe"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Pipe"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(32)).await;
+
+        let res = get_meshes(State(state.clone()), Query(MeshesQuery::default()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert!(res.0["meshes"]["Pipe"].get("lod").is_none());
+        // Four rims of 32 steps; the wall's own facet edges are not outlined.
+        assert_eq!(
+            res.0["meshes"]["Pipe"]["edges"].as_array().map(Vec::len),
+            Some(2 * 4 * 32)
         );
-
-        let d = std::f64::consts::PI / 180.0;
-        let rz: Vec<f64> = graph.children.iter().map(|c| c.rotation[2]).collect();
-        let want = [-45.0 * d, -135.0 * d, -225.0 * d, -315.0 * d];
-        assert_eq!(rz.len(), 4);
-        for (got, exp) in rz.iter().zip(want.iter()) {
-            assert!((got - exp).abs() < 1e-9, "rotation {got}, expected {exp}");
-        }
-        // Nothing may be displaced -- a phi replica is a pure rotation.
-        for c in &graph.children {
-            assert_eq!(c.position, [0.0, 0.0, 0.0], "phi replica was translated");
-        }
-        assert!(
-            warnings.is_empty(),
-            "phi replication should no longer warn: {warnings:?}"
+        assert_eq!(
+            res.0["meshes"]["WorldBox"]["edges"]
+                .as_array()
+                .map(Vec::len),
+            Some(24)
         );
+        // Past the cached default angle, the box's right-angled edges are no
+        // longer creases.
+        let res = get_meshes(
+            State(state.clone()),
+            Query(MeshesQuery {
+                edge_angle: Some(100.0),
+                ..MeshesQuery::default()
+            }),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["meshes"]["WorldBox"]["edges"], json!([]));
+
+        let res = get_meshes(
+            State(state.clone()),
+            Query(MeshesQuery {
+                lod: true,
+                ..MeshesQuery::default()
+            }),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e.message));
+        let lod = &res.0["meshes"]["Pipe"]["lod"];
+        assert_eq!(lod["min_screen_size"], 0.5);
+        let levels = lod["levels"].as_array().unwrap();
+        assert_eq!(levels.len(), 3, "16, 8 and 4 segments");
+        assert_eq!(levels[2]["min_screen_size"], 0.0);
+        assert!(levels[0]["indices"]
+            .as_array()
+            .is_some_and(|i| !i.is_empty()));
+        assert_eq!(res.0["meshes"]["WorldBox"]["lod"]["levels"], json!([]));
+
+        // The levels and edges are built with the meshes and follow them
+        // through edits.
+        let req = RenameRequest {
+            kind: "solid".to_string(),
+            name: "Pipe".to_string(),
+            new_name: "Hose".to_string(),
+        };
+        let res = rename_item(State(state.clone()), Json(req))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["ok"], true);
+        let s = state.read().await;
+        let lods = &s.loaded.as_ref().unwrap().lods;
+        assert!(!lods.contains_key("Pipe"));
+        assert_eq!(lods["Hose"].levels.len(), 3);
+        let edges = &s.loaded.as_ref().unwrap().edges;
+        assert!(!edges.contains_key("Pipe"));
+        assert_eq!(edges["Hose"].len(), 2 * 4 * 32);
     }
 
-    #[test]
-    fn phi_replica_offset_shifts_every_slice() {
-        // Unlike the Cartesian axes, kPhi really does read `offset`.
-        let (graph, _) = replica_scene(
-            Some("phi"),
-            [const { None }; 3],
-            "2",
-            "90",
-            Some("deg"),
-            "10",
-            Some("deg"),
+    #[tokio::test]
+    async fn section_cuts_placed_solids_and_draws_them() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+    <material name="Iron"><D value="7.87"/><atom value="55.85"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+    <tube name="Pipe" rmin="5" rmax="8" z="20" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Pipe"><materialref ref="Iron"/><solidref ref="Pipe"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Pipe"/><position name="p" x="100"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(16)).await;
+        let request = |format: &str, normal: [f64; 3]| -> SectionRequest {
+            serde_json::from_value(json!({ "format": format, "normal": normal })).unwrap()
+        };
+
+        let res = section_plane(State(state.clone()), Json(request("json", [0.0, 0.0, 1.0])))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let instances = body["section"]["instances"].as_array().unwrap();
+        assert_eq!(instances.len(), 1, "the world is not drawn");
+        assert_eq!(instances[0]["material_name"], "Iron");
+        assert_eq!(instances[0]["polylines"].as_array().map(Vec::len), Some(2));
+        let x = instances[0]["polylines"][0]["points"][0][0]
+            .as_f64()
+            .unwrap();
+        assert!((92.0..=108.0).contains(&x), "placed at x=100: {}", x);
+
+        let res = section_plane(State(state.clone()), Json(request("svg", [0.0, 0.0, 1.0])))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "image/svg+xml");
+        assert_eq!(
+            res.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"test-section.svg\""
         );
-        let d = std::f64::consts::PI / 180.0;
-        let rz: Vec<f64> = graph.children.iter().map(|c| c.rotation[2]).collect();
-        for (got, exp) in rz.iter().zip([-55.0 * d, -145.0 * d].iter()) {
-            assert!((got - exp).abs() < 1e-9, "rotation {got}, expected {exp}");
-        }
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(">Iron</text>"));
+
+        let err = section_plane(State(state.clone()), Json(request("dxf", [0.0; 3])))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("a zero normal should be refused"));
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn rho_replication_draws_one_copy_and_says_so() {
-        // ComputeTransformation has "No setup required for radial case": there
-        // is no placement transform, because each slice is a different solid.
-        let (graph, warnings) = replica_scene(
-            Some("rho"),
-            [const { None }; 3],
-            "5",
-            "10",
-            Some("mm"),
-            "0",
-            None,
-        );
-        assert_eq!(
-            graph.children.len(),
-            1,
-            "five coincident copies show exactly what one shows"
+    #[tokio::test]
+    async fn baked_scene_resolves_picks_to_instances() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <variable name="i" value="0"/>
+  </define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+    <material name="Iron"><D value="7.87"/><atom value="55.85"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+    <box name="Cell" x="5" y="5" z="5"/>
+    <tube name="Pipe" rmin="5" rmax="8" z="20" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Cell"><materialref ref="Iron"/><solidref ref="Cell"/></volume>
+    <volume name="Pipe"><materialref ref="Iron"/><solidref ref="Pipe"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <loop for="i" from="0" to="9" step="1">
+        <physvol><volumeref ref="Cell"/><position name="p" x="10*i"/></physvol>
+      </loop>
+      <physvol><volumeref ref="Pipe"/><position name="q" y="100"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(64)).await;
+
+        let res = bake_scene(State(state.clone()), Query(SceneBakeQuery::default()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let merged = res.0["merged"].as_array().unwrap();
+        assert_eq!(merged.len(), 1, "ten cells in one buffer");
+        assert_eq!(merged[0]["material_name"], "Iron");
+        let ids = merged[0]["instance_ids"].as_array().unwrap();
+        assert_eq!(ids.len(), 10);
+        // The last facet belongs to the last cell placed.
+        let last = merged[0]["triangle_instances"][119].as_u64().unwrap() as usize;
+        assert_eq!(ids[last], "/World/physvol[9]:Cell");
+
+        let instanced = res.0["instanced"].as_array().unwrap();
+        assert_eq!(instanced.len(), 1);
+        assert_eq!(instanced[0]["solid_name"], "Pipe");
+        assert_eq!(instanced[0]["matrices"][13], 100.0);
+    }
+
+    #[tokio::test]
+    async fn bounds_cover_turned_instances_and_the_world() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Iron" Z="26"><D value="7.87"/><atom value="55.85"/></material>
+    <material name="Vacuum" Z="1"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+    <tube name="Pipe" rmax="10" z="40" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Pipe"><materialref ref="Iron"/><solidref ref="Pipe"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol>
+        <volumeref ref="Pipe"/>
+        <position name="p" x="100"/><rotation name="r" y="90" unit="deg"/>
+      </physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        // Seven segments leave the mesh short of the tube's radius.
+        let state = loaded_state(src, &MeshQuality::fixed(7)).await;
+        let query = |instance: Option<&str>, solid: Option<&str>| BoundsQuery {
+            instance: instance.map(str::to_string),
+            solid: solid.map(str::to_string),
+        };
+
+        let all = get_bounds(State(state.clone()), Query(query(None, None)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(all.0["solids"]["Pipe"]["source"], "analytic");
+        assert_eq!(all.0["solids"]["Pipe"]["min"], json!([-10.0, -10.0, -20.0]));
+        assert_eq!(all.0["world"]["max"], json!([500.0, 500.0, 500.0]));
+        let instances = all.0["instances"].as_array().unwrap();
+        assert_eq!(instances.len(), 2, "the world volume and the pipe");
+        let pipe = instances
+            .iter()
+            .find(|b| b["solid_name"] == "Pipe")
+            .unwrap();
+
+        // Turned about y, the pipe's length lies along x.
+        let id = pipe["instance_id"].as_str().unwrap();
+        let one = get_bounds(State(state.clone()), Query(query(Some(id), None)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let near = |v: &Value, want: [f64; 3]| {
+            (0..3).all(|i| (v[i].as_f64().unwrap() - want[i]).abs() < 1e-9)
+        };
+        assert!(
+            near(&one.0["aabb"]["min"], [80.0, -10.0, -10.0]),
+            "{}",
+            one.0
         );
-        assert_eq!(graph.children[0].position, [0.0, 0.0, 0.0]);
         assert!(
-            warnings.iter().any(|w| w.contains("kRho")),
-            "radial replication must be reported: {warnings:?}"
+            near(&one.0["aabb"]["max"], [120.0, 10.0, 10.0]),
+            "{}",
+            one.0
         );
+        assert!(near(&one.0["obb"]["center"], [100.0, 0.0, 0.0]));
+
+        let scene = get_scene(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(scene.0["bounds"]["instances"], all.0["instances"]);
+
+        let err = get_bounds(State(state.clone()), Query(query(None, Some("Nope"))))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("an unknown solid has no bounds"));
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
     }
 
-    #[test]
-    fn cartesian_replica_offset_is_reported_as_inert() {
-        // ComputeTransformation's kXAxis case never reads `offset`, so a file
-        // carrying one gets a stack that ignores it -- in Geant4 too.
-        let (_, warnings) = replica_scene(
-            None,
-            [Some("1".to_string()), None, None],
-            "2",
-            "10",
-            Some("mm"),
-            "5",
-            Some("mm"),
-        );
+    #[tokio::test]
+    async fn rays_pick_instances_and_follow_edits() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Iron" Z="26"><D value="7.87"/><atom value="55.85"/></material>
+    <material name="Lead" Z="82"><D value="11.35"/><atom value="207.2"/></material>
+    <material name="Vacuum" Z="1"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+    <box name="Plate" x="2" y="100" z="100"/>
+  </solids>
+  <structure>
+    <volume name="Plate"><materialref ref="Iron"/><solidref ref="Plate"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol name="Front"><volumeref ref="Plate"/><position name="p1" x="100"/></physvol>
+      <physvol name="Back"><volumeref ref="Plate"/><position name="p2" x="200"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(16)).await;
+        {
+            let s = state.read().await;
+            let loaded = s.loaded.as_ref().unwrap();
+            assert_eq!(
+                loaded.spatial.instance_count(),
+                2,
+                "the world is not picked"
+            );
+        }
+        let ray = |direction: [f64; 3], max_distance: Option<f64>| PickRequest {
+            origin: [0.0, 10.0, 0.0],
+            direction,
+            max_distance,
+        };
+
+        let res = pick_ray(State(state.clone()), Json(ray([2.0, 0.0, 0.0], None)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let hit = &res.0["hit"];
         assert!(
-            warnings
+            hit["instance_id"].as_str().unwrap().contains("Front"),
+            "{}",
+            hit
+        );
+        assert_eq!(hit["distance"], 99.0);
+        assert_eq!(hit["point"], json!([99.0, 10.0, 0.0]));
+        assert_eq!(hit["normal"], json!([-1.0, 0.0, 0.0]));
+        assert_eq!(hit["material_name"], "Iron");
+
+        let res = pick_ray(State(state.clone()), Json(ray([1.0, 0.0, 0.0], Some(50.0))))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert!(res.0["hit"].is_null());
+        let err = pick_ray(State(state.clone()), Json(ray([0.0; 3], None)))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("a zero direction should be refused"));
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        // Renamed, the solid is still found, and material edits reach the
+        // instances in the index.
+        let rename = RenameRequest {
+            kind: "solid".to_string(),
+            name: "Plate".to_string(),
+            new_name: "Shield".to_string(),
+        };
+        let renamed = rename_item(State(state.clone()), Json(rename))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(renamed.0["ok"], true);
+        let material = UpdateMaterialRefRequest {
+            volume_name: "Plate".to_string(),
+            material_ref: "Lead".to_string(),
+        };
+        let updated = update_volume_material_ref(State(state.clone()), Json(material))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(updated.0["ok"], true);
+        let res = pick_ray(State(state.clone()), Json(ray([1.0, 0.0, 0.0], None)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["hit"]["solid_name"], "Shield");
+        assert_eq!(res.0["hit"]["material_name"], "Lead");
+        assert_eq!(res.0["hit"]["volume_name"], "Plate");
+        assert_eq!(res.0["hit"]["density"], 11.35);
+
+        let mut lead = {
+            let s = state.read().await;
+            let doc = &s.loaded.as_ref().unwrap().document;
+            doc.materials
+                .materials
                 .iter()
-                .any(|w| w.contains("Geant4 ignores it here too")),
-            "expected the offset note: {warnings:?}"
+                .find(|m| m.name == "Lead")
+                .cloned()
+                .unwrap()
+        };
+        lead.name = "Tungsten".to_string();
+        lead.density.as_mut().unwrap().value = "19.3".to_string();
+        let updated = update_material(
+            State(state.clone()),
+            Json(UpdateMaterialRequest {
+                name: "Lead".to_string(),
+                material: lead,
+            }),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(updated.0["ok"], true);
+        let res = pick_ray(State(state.clone()), Json(ray([1.0, 0.0, 0.0], None)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["hit"]["material_name"], "Tungsten");
+        assert_eq!(res.0["hit"]["density"], 19.3);
+    }
+
+    #[tokio::test]
+    async fn clearances_report_a_pmt_close_to_its_housing() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Glass" Z="14"><D value="2.2"/><atom value="28.09"/></material>
+    <material name="Vacuum" Z="1"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1000" y="1000" z="1000"/>
+    <tube name="HousingTube" rmax="30" z="100" deltaphi="360" aunit="deg"/>
+    <box name="PmtBox" x="20" y="20" z="99.4"/>
+  </solids>
+  <structure>
+    <volume name="Pmt"><materialref ref="Glass"/><solidref ref="PmtBox"/></volume>
+    <volume name="Housing">
+      <materialref ref="Vacuum"/><solidref ref="HousingTube"/>
+      <physvol name="pmt"><volumeref ref="Pmt"/><position name="p" z="0.2"/></physvol>
+    </volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol name="housing"><volumeref ref="Housing"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(32)).await;
+        let query = |threshold: f64| ClearanceQuery {
+            threshold: Some(threshold),
+        };
+
+        // The PMT ends 0.1 mm short of the housing's top cap.
+        let res = get_clearances(State(state.clone()), Query(query(0.5)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let found = res.0["clearances"].as_array().unwrap();
+        assert_eq!(found.len(), 1, "{}", res.0);
+        assert_eq!(found[0]["kind"], "mother");
+        assert!(found[0]["first"].as_str().unwrap().ends_with("(pmt):Pmt"));
+        let distance = found[0]["distance"].as_f64().unwrap();
+        assert!((distance - 0.1).abs() < 1e-4, "{}", distance);
+        let z = found[0]["second_point"][2].as_f64().unwrap();
+        assert!((z - 50.0).abs() < 1e-4);
+
+        let res = get_clearances(State(state.clone()), Query(query(0.05)))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        assert_eq!(res.0["clearances"], json!([]));
+        let err = get_clearances(State(state.clone()), Query(query(-1.0)))
+            .await
+            .err()
+            .unwrap_or_else(|| panic!("a negative threshold should be refused"));
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn geant4_export_builds_the_loaded_world() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <materials>
+    <material name="Lead" Z="82"><D value="11.35"/><atom value="207.2"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1" y="1" z="1" lunit="m"/>
+    <orb name="Ball" r="5" lunit="cm"/>
+  </solids>
+  <structure>
+    <volume name="Shot"><materialref ref="Lead"/><solidref ref="Ball"/></volume>
+    <volume name="World">
+      <materialref ref="G4_AIR"/><solidref ref="WorldBox"/>
+      <physvol copynumber="3"><volumeref ref="Shot"/><position name="p" x="10" unit="cm"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(16)).await;
+
+        let res = export_geant4(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let cc = res.0["files"]["DetectorConstruction.cc"].as_str().unwrap();
+        assert!(cc.contains(
+            "auto mat_Lead = new G4Material(\"Lead\", 82, 207.2*g/mole, 11.35*g/cm3, kStateUndefined);"
+        ));
+        assert!(cc.contains("auto solid_Ball = new G4Orb(\"Ball\", 50*mm);"));
+        assert!(cc.contains(
+            "new G4PVPlacement(nullptr, G4ThreeVector(100*mm, 0*mm, 0*mm), lv_Shot, \"Shot_PV\", \
+             lv_World, false, 3, checkOverlaps);"
+        ));
+        assert!(res.0["files"]["DetectorConstruction.hh"]
+            .as_str()
+            .unwrap()
+            .contains("G4VPhysicalVolume* Construct() override;"));
+    }
+
+    #[tokio::test]
+    async fn root_and_mcnp_exports_cover_the_loaded_world() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <solids>
+    <box name="WorldBox" x="1" y="1" z="1" lunit="m"/>
+    <orb name="Ball" r="5" lunit="cm"/>
+    <torus name="Ring" rmax="1" rtor="20" deltaphi="360" lunit="cm" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Shot"><materialref ref="G4_Pb"/><solidref ref="Ball"/></volume>
+    <volume name="Donut"><materialref ref="G4_Pb"/><solidref ref="Ring"/></volume>
+    <volume name="World">
+      <materialref ref="G4_AIR"/><solidref ref="WorldBox"/>
+      <physvol copynumber="3"><volumeref ref="Shot"/><position name="p" x="10" unit="cm"/></physvol>
+      <physvol><volumeref ref="Donut"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let state = loaded_state(src, &MeshQuality::fixed(16)).await;
+
+        let root = export_root(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let macro_source = root.0["files"]["test.C"].as_str().unwrap();
+        assert!(macro_source
+            .contains("vol_World->AddNode(vol_Shot, 3, new TGeoTranslation(10, 0, 0));"));
+        assert!(
+            macro_source.contains("auto shape_Ring = new TGeoTorus(\"Ring\", 20, 0, 1, 0, 360);")
         );
+
+        let mcnp = export_mcnp(State(state.clone()))
+            .await
+            .unwrap_or_else(|e| panic!("{}", e.message));
+        let deck = mcnp.0["files"]["test.mcnp"].as_str().unwrap();
+        assert!(deck.contains("s 10 0 0 5"));
+        assert!(deck.contains("m1 82000 -1"));
+        let warnings = mcnp.0["warnings"].as_array().unwrap();
+        assert!(warnings
+            .iter()
+            .any(|w| w.as_str().unwrap().contains("\"Ring\"")));
     }
 }
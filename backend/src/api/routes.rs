@@ -8,13 +8,40 @@ pub fn create_router(state: SharedState) -> Router {
     Router::new()
         .route("/api/files/upload", post(handlers::upload_file))
         .route("/api/files/upload-multi", post(handlers::upload_files))
+        // Local filesystem mode (opt-in via GDML_FS_ROOT)
+        .route("/api/files/local-status", get(handlers::get_local_status))
+        .route("/api/files/open", post(handlers::open_local_file))
+        .route("/api/files/save", post(handlers::save_local_file))
         .route("/api/document/summary", get(handlers::get_summary))
         .route("/api/document/meshes", get(handlers::get_meshes))
         .route("/api/document/scene", get(handlers::get_scene))
+        .route("/api/document/scene/bake", get(handlers::bake_scene))
+        .route("/api/document/bounds", get(handlers::get_bounds))
         .route("/api/document/defines", get(handlers::get_defines))
         .route("/api/document/materials", get(handlers::get_materials))
         .route("/api/document/solids", get(handlers::get_solids))
+        .route(
+            "/api/document/mesh-integrity",
+            get(handlers::get_mesh_integrity),
+        )
+        .route("/api/document/mesh-repair", post(handlers::repair_meshes))
+        .route("/api/document/section", post(handlers::section_plane))
+        .route("/api/document/pick", post(handlers::pick_ray))
+        .route("/api/document/clearance", get(handlers::get_clearances))
         .route("/api/document/structure", get(handlers::get_structure))
+        .route("/api/document/provenance", get(handlers::get_provenance))
+        .route("/api/document/references", get(handlers::get_references))
+        .route("/api/document/rename", post(handlers::rename_item))
+        .route("/api/document/unused", get(handlers::get_unused))
+        .route("/api/document/prune", post(handlers::prune_unused))
+        .route(
+            "/api/document/diagnostics/units",
+            get(handlers::get_unit_diagnostics),
+        )
+        .route(
+            "/api/document/evaluate",
+            post(handlers::evaluate_expression),
+        )
         // NIST database
         .route("/api/nist/materials", get(handlers::get_nist_materials))
         .route("/api/nist/material", get(handlers::get_nist_material))
@@ -38,6 +65,55 @@ pub fn create_router(state: SharedState) -> Router {
             "/api/document/elements/delete",
             post(handlers::delete_element),
         )
+        // Matrix CRUD
+        .route("/api/document/matrices", get(handlers::get_matrices))
+        .route(
+            "/api/document/matrices/update",
+            put(handlers::update_matrix),
+        )
+        .route("/api/document/matrices/add", post(handlers::add_matrix))
+        .route(
+            "/api/document/matrices/delete",
+            post(handlers::delete_matrix),
+        )
+        // Optical, skin and border surfaces
+        .route("/api/document/surfaces", get(handlers::get_surfaces))
+        .route(
+            "/api/document/surfaces/optical/update",
+            put(handlers::update_optical_surface),
+        )
+        .route(
+            "/api/document/surfaces/optical/add",
+            post(handlers::add_optical_surface),
+        )
+        .route(
+            "/api/document/surfaces/optical/delete",
+            post(handlers::delete_optical_surface),
+        )
+        .route(
+            "/api/document/surfaces/skin/update",
+            put(handlers::update_skin_surface),
+        )
+        .route(
+            "/api/document/surfaces/skin/add",
+            post(handlers::add_skin_surface),
+        )
+        .route(
+            "/api/document/surfaces/skin/delete",
+            post(handlers::delete_skin_surface),
+        )
+        .route(
+            "/api/document/surfaces/border/update",
+            put(handlers::update_border_surface),
+        )
+        .route(
+            "/api/document/surfaces/border/add",
+            post(handlers::add_border_surface),
+        )
+        .route(
+            "/api/document/surfaces/border/delete",
+            post(handlers::delete_border_surface),
+        )
         // Volume material ref
         .route(
             "/api/document/structure/material-ref",
@@ -45,5 +121,17 @@ pub fn create_router(state: SharedState) -> Router {
         )
         // Export
         .route("/api/document/export", post(handlers::export_gdml))
+        .route("/api/document/validate", get(handlers::validate_document))
+        .route(
+            "/api/document/export-modular",
+            post(handlers::export_modular),
+        )
+        .route("/api/document/export-geant4", post(handlers::export_geant4))
+        .route("/api/document/export-root", post(handlers::export_root))
+        .route("/api/document/export-mcnp", post(handlers::export_mcnp))
+        .route(
+            "/api/document/structure/split-module",
+            post(handlers::split_module),
+        )
         .with_state(state)
 }
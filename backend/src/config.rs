@@ -1,3 +1,8 @@
+use std::path::PathBuf;
+
+use crate::mesh::csg::BooleanBackend;
+use crate::mesh::quality::MeshQuality;
+
 pub const DEFAULT_PORT: u16 = 4001;
 pub const DEFAULT_HOST: &str = "127.0.0.1";
 pub const DEFAULT_MESH_SEGMENTS: u32 = 32;
@@ -15,3 +20,35 @@ pub fn mesh_segments() -> u32 {
         .and_then(|s| s.parse().ok())
         .unwrap_or(DEFAULT_MESH_SEGMENTS)
 }
+
+/// Tolerances for documents that do not set their own: `GDML_CHORD_TOLERANCE`
+/// in mm and `GDML_MAX_ANGLE` in degrees. Neither is set by default, which
+/// keeps the fixed segment count.
+pub fn mesh_quality() -> MeshQuality {
+    let var = |name: &str| std::env::var(name).ok().and_then(|s| s.parse().ok());
+    MeshQuality {
+        chord_tolerance: var("GDML_CHORD_TOLERANCE"),
+        max_angle: var("GDML_MAX_ANGLE"),
+        ..MeshQuality::fixed(mesh_segments())
+    }
+}
+
+/// Boolean implementation for documents that do not ask for one: `bsp` (the
+/// default) or `exact`.
+pub fn boolean_backend() -> BooleanBackend {
+    std::env::var("GDML_BOOLEAN_BACKEND")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Directory the backend may open, save and watch GDML files under.
+///
+/// Unset (the default) keeps the browser-only upload/download workflow and the
+/// backend never touches the filesystem.
+pub fn local_fs_root() -> Option<PathBuf> {
+    std::env::var("GDML_FS_ROOT")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
@@ -21,14 +21,24 @@ pub fn topological_sort(entries: &[DefineEntry], known: &HashSet<String>) -> Res
     for (i, entry) in entries.iter().enumerate() {
         let refs = extract_identifiers(&entry.expression);
         for ref_name in &refs {
+            // A define shadows a built-in of the same name (`L`, `T`, `g`... are
+            // all CLHEP units), so look it up among the defines first.
+            if let Some(&j) = name_to_idx.get(ref_name.as_str()) {
+                if j != i {
+                    adj[j].push(i);
+                    in_degree[i] += 1;
+                }
+                continue;
+            }
             if known.contains(ref_name.as_str()) {
                 continue; // built-in, no dependency
             }
-            if let Some(&j) = name_to_idx.get(ref_name.as_str()) {
+            if let Some(j) = matrix_of_element(ref_name, &name_to_idx) {
                 if j != i {
                     adj[j].push(i);
                     in_degree[i] += 1;
                 }
+                continue;
             }
             // If not found, it might be a number or we'll handle the error at eval time
         }
@@ -68,6 +78,24 @@ pub fn topological_sort(entries: &[DefineEntry], known: &HashSet<String>) -> Res
     Ok(order)
 }
 
+/// The entry a generated matrix element name (`m_1`, `m_0_1`) belongs to, so
+/// a define reading one is evaluated after the matrix. The longest matching
+/// name wins, since `a_b` and `a` can both be matrices.
+fn matrix_of_element(word: &str, name_to_idx: &HashMap<&str, usize>) -> Option<usize> {
+    let mut end = word.len();
+    while let Some(cut) = word[..end].rfind('_') {
+        let index = &word[cut + 1..end];
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        if let Some(&j) = name_to_idx.get(&word[..cut]) {
+            return Some(j);
+        }
+        end = cut;
+    }
+    None
+}
+
 /// Extract potential identifier references from an expression string.
 /// Identifiers are sequences of [a-zA-Z_][a-zA-Z0-9_]* that aren't purely numeric.
 pub fn extract_identifiers(expr: &str) -> Vec<String> {
@@ -98,6 +126,39 @@ pub fn extract_identifiers(expr: &str) -> Vec<String> {
     ids
 }
 
+/// Rewrite whole identifiers in `expr`: each `[A-Za-z_][A-Za-z0-9_]*` run for
+/// which `replace` returns a string is swapped for it, everything else is
+/// copied through.
+///
+/// Word boundaries are the point: renaming `i` must not touch `imax`, `mini`
+/// or the `i` inside `sin`, and a run starting with a digit (`1e5`) is a
+/// number, not an identifier.
+pub fn replace_identifiers(expr: &str, replace: impl Fn(&str) -> Option<String>) -> String {
+    let bytes = expr.as_bytes();
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut out = String::with_capacity(expr.len());
+    let mut i = 0usize;
+    while i < bytes.len() {
+        if is_ident(bytes[i]) {
+            let start = i;
+            while i < bytes.len() && is_ident(bytes[i]) {
+                i += 1;
+            }
+            let word = &expr[start..i];
+            match replace(word).filter(|_| !bytes[start].is_ascii_digit()) {
+                Some(r) => out.push_str(&r),
+                None => out.push_str(word),
+            }
+            continue;
+        }
+        // Copy one whole UTF-8 character.
+        let ch_len = expr[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+        out.push_str(&expr[i..i + ch_len]);
+        i += ch_len;
+    }
+    out
+}
+
 fn is_builtin_function(name: &str) -> bool {
     matches!(
         name,
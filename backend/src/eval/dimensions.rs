@@ -0,0 +1,1576 @@
+//! Dimensional analysis of GDML expressions.
+//!
+//! Geant4 never type-checks units: `G4GDMLEvaluator` turns every expression
+//! into a bare double in internal units and the reader multiplies by `lunit` /
+//! `aunit` / `unit` without looking at what it got. So `rmax="10*deg"` builds a
+//! 0.17 mm tube, a length constant used as `startphi` turns the solid by its
+//! size in millimetres, and `x="5*cm" lunit="cm"` is half a metre. None of it
+//! fails; it just renders wrong.
+//!
+//! This pass infers a dimension for every define and every solid, placement
+//! and material attribute by propagating units through the arithmetic, and
+//! reports what does not fit as [`UnitDiagnostic`]s. It never changes how
+//! anything evaluates.
+//!
+//! A bare number is dimensionless and matches anything: `r + 5` where `r` is
+//! a length is ordinary GDML -- the 5 is in the attribute's unit. Angles are
+//! their own dimension so that a length used as an angle is caught, but
+//! combine freely with plain numbers (`pi/2 + 10*deg`).
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use super::context::EvalContext;
+use super::dependency::{topological_sort, DefineEntry};
+use crate::gdml::model::*;
+use crate::gdml::units::{self, UnitKind};
+
+// Exponent slots, in Geant4's internal base units.
+const LENGTH: usize = 0;
+const TIME: usize = 1;
+const ENERGY: usize = 2;
+const CHARGE: usize = 3;
+const TEMPERATURE: usize = 4;
+const AMOUNT: usize = 5;
+const LUMINOUS: usize = 6;
+const ANGLE: usize = 7;
+
+const BASE_SYMBOLS: [&str; 8] = ["mm", "ns", "MeV", "eplus", "K", "mol", "cd", "rad"];
+
+/// Exponents of mm, ns, MeV, eplus, kelvin, mole, candela and radian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Dimension([i8; 8]);
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0; 8]);
+
+    const fn of(exponents: &[(usize, i8)]) -> Self {
+        let mut d = [0i8; 8];
+        let mut i = 0;
+        while i < exponents.len() {
+            d[exponents[i].0] = exponents[i].1;
+            i += 1;
+        }
+        Dimension(d)
+    }
+
+    pub fn is_dimensionless(self) -> bool {
+        self == Self::NONE
+    }
+
+    /// Everything but the angle exponent. Two values whose physical parts
+    /// agree differ at most by a factor of radians.
+    fn physical(self) -> Self {
+        let mut d = self.0;
+        d[ANGLE] = 0;
+        Dimension(d)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let mut d = self.0;
+        for (a, b) in d.iter_mut().zip(other.0) {
+            *a += b;
+        }
+        Dimension(d)
+    }
+
+    fn div(self, other: Self) -> Self {
+        let mut d = self.0;
+        for (a, b) in d.iter_mut().zip(other.0) {
+            *a -= b;
+        }
+        Dimension(d)
+    }
+
+    fn powi(self, n: i8) -> Self {
+        Dimension(self.0.map(|e| e * n))
+    }
+
+    fn sqrt(self) -> Option<Self> {
+        if self.0.iter().all(|e| e % 2 == 0) {
+            Some(Dimension(self.0.map(|e| e / 2)))
+        } else {
+            None
+        }
+    }
+
+    /// A readable name: the unit category when there is one, otherwise the
+    /// product of base units.
+    pub fn describe(self) -> String {
+        if self.is_dimensionless() {
+            return "dimensionless".to_string();
+        }
+        if let Some(kind) = ALL_KINDS.iter().find(|k| kind_dimension(**k) == self) {
+            return kind_label(*kind).to_string();
+        }
+        self.base_units()
+    }
+
+    /// The product of internal base units a value of this dimension is
+    /// expressed in (`mm`, `MeV*ns^2*mm^-2`), or `""` for a plain number.
+    pub fn base_units(self) -> String {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .zip(BASE_SYMBOLS)
+            .filter(|(e, _)| **e != 0)
+            .map(|(e, s)| {
+                if *e == 1 {
+                    s.to_string()
+                } else {
+                    format!("{}^{}", s, e)
+                }
+            })
+            .collect();
+        parts.join("*")
+    }
+}
+
+const ALL_KINDS: [UnitKind; 25] = [
+    UnitKind::Length,
+    UnitKind::Surface,
+    UnitKind::Volume,
+    UnitKind::Angle,
+    UnitKind::SolidAngle,
+    UnitKind::Time,
+    UnitKind::Frequency,
+    UnitKind::Velocity,
+    UnitKind::ElectricCharge,
+    UnitKind::Energy,
+    UnitKind::Mass,
+    UnitKind::Density,
+    UnitKind::MolarMass,
+    UnitKind::Power,
+    UnitKind::Force,
+    UnitKind::Pressure,
+    UnitKind::ElectricCurrent,
+    UnitKind::ElectricPotential,
+    UnitKind::MagneticFlux,
+    UnitKind::MagneticFluxDensity,
+    UnitKind::Temperature,
+    UnitKind::AmountOfSubstance,
+    UnitKind::Activity,
+    UnitKind::Dose,
+    UnitKind::LuminousIntensity,
+];
+
+const MASS: Dimension = Dimension::of(&[(ENERGY, 1), (TIME, 2), (LENGTH, -2)]);
+
+/// The dimension every unit of `kind` carries.
+pub fn kind_dimension(kind: UnitKind) -> Dimension {
+    match kind {
+        UnitKind::Length => Dimension::of(&[(LENGTH, 1)]),
+        UnitKind::Surface => Dimension::of(&[(LENGTH, 2)]),
+        UnitKind::Volume => Dimension::of(&[(LENGTH, 3)]),
+        UnitKind::Angle => Dimension::of(&[(ANGLE, 1)]),
+        UnitKind::SolidAngle => Dimension::of(&[(ANGLE, 2)]),
+        UnitKind::Time => Dimension::of(&[(TIME, 1)]),
+        UnitKind::Frequency | UnitKind::Activity => Dimension::of(&[(TIME, -1)]),
+        UnitKind::Velocity => Dimension::of(&[(LENGTH, 1), (TIME, -1)]),
+        UnitKind::ElectricCharge => Dimension::of(&[(CHARGE, 1)]),
+        UnitKind::Energy => Dimension::of(&[(ENERGY, 1)]),
+        UnitKind::Mass => MASS,
+        UnitKind::Density => MASS.div(Dimension::of(&[(LENGTH, 3)])),
+        UnitKind::MolarMass => MASS.div(Dimension::of(&[(AMOUNT, 1)])),
+        UnitKind::Power => Dimension::of(&[(ENERGY, 1), (TIME, -1)]),
+        UnitKind::Force => Dimension::of(&[(ENERGY, 1), (LENGTH, -1)]),
+        UnitKind::Pressure => Dimension::of(&[(ENERGY, 1), (LENGTH, -3)]),
+        UnitKind::ElectricCurrent => Dimension::of(&[(CHARGE, 1), (TIME, -1)]),
+        UnitKind::ElectricPotential => Dimension::of(&[(ENERGY, 1), (CHARGE, -1)]),
+        UnitKind::MagneticFlux => Dimension::of(&[(ENERGY, 1), (TIME, 1), (CHARGE, -1)]),
+        UnitKind::MagneticFluxDensity => {
+            Dimension::of(&[(ENERGY, 1), (TIME, 1), (CHARGE, -1), (LENGTH, -2)])
+        }
+        UnitKind::Temperature => Dimension::of(&[(TEMPERATURE, 1)]),
+        UnitKind::AmountOfSubstance => Dimension::of(&[(AMOUNT, 1)]),
+        UnitKind::Dose => Dimension::of(&[(LENGTH, 2), (TIME, -2)]),
+        UnitKind::LuminousIntensity => Dimension::of(&[(LUMINOUS, 1)]),
+    }
+}
+
+/// `describe` with its indefinite article, for messages.
+fn with_article(description: &str) -> String {
+    let article = if description.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{} {}", article, description)
+}
+
+fn kind_label(kind: UnitKind) -> &'static str {
+    match kind {
+        UnitKind::Length => "length",
+        UnitKind::Surface => "surface",
+        UnitKind::Volume => "volume",
+        UnitKind::Angle => "angle",
+        UnitKind::SolidAngle => "solid angle",
+        UnitKind::Time => "time",
+        UnitKind::Frequency => "frequency",
+        UnitKind::Velocity => "velocity",
+        UnitKind::ElectricCharge => "electric charge",
+        UnitKind::Energy => "energy",
+        UnitKind::Mass => "mass",
+        UnitKind::Density => "density",
+        UnitKind::MolarMass => "molar mass",
+        UnitKind::Power => "power",
+        UnitKind::Force => "force",
+        UnitKind::Pressure => "pressure",
+        UnitKind::ElectricCurrent => "electric current",
+        UnitKind::ElectricPotential => "electric potential",
+        UnitKind::MagneticFlux => "magnetic flux",
+        UnitKind::MagneticFluxDensity => "magnetic flux density",
+        UnitKind::Temperature => "temperature",
+        UnitKind::AmountOfSubstance => "amount of substance",
+        UnitKind::Activity => "activity",
+        UnitKind::Dose => "dose",
+        UnitKind::LuminousIntensity => "luminous intensity",
+    }
+}
+
+/// Map a `<quantity type="...">` to the kind its unit should have. `type` is
+/// free text that Geant4 ignores, so only the spellings in common use are
+/// checked.
+fn quantity_type_kind(ty: &str) -> Option<UnitKind> {
+    Some(match ty.trim().to_ascii_lowercase().as_str() {
+        "length" => UnitKind::Length,
+        "angle" => UnitKind::Angle,
+        "area" | "surface" => UnitKind::Surface,
+        "volume" => UnitKind::Volume,
+        "time" => UnitKind::Time,
+        "frequency" => UnitKind::Frequency,
+        "energy" => UnitKind::Energy,
+        "mass" => UnitKind::Mass,
+        "density" => UnitKind::Density,
+        "pressure" => UnitKind::Pressure,
+        "temperature" => UnitKind::Temperature,
+        _ => return None,
+    })
+}
+
+/// Dimensions of the evaluator's built-in identifiers that are not a plain
+/// `G4UnitsTable` entry.
+fn builtin_dimension(name: &str) -> Option<Dimension> {
+    let e = |x: &[(usize, i8)]| Some(Dimension::of(x));
+    let length = Dimension::of(&[(LENGTH, 1)]);
+    let velocity = Dimension::of(&[(LENGTH, 1), (TIME, -1)]);
+    match name {
+        "millimeter2" | "centimeter2" | "meter2" | "kilometer2" => e(&[(LENGTH, 2)]),
+        "millimeter3" | "centimeter3" | "meter3" | "kilometer3" => e(&[(LENGTH, 3)]),
+        "ohm" => e(&[(ENERGY, 1), (TIME, 1), (CHARGE, -2)]),
+        "farad" | "millifarad" | "microfarad" | "picofarad" | "F" => {
+            e(&[(CHARGE, 2), (ENERGY, -1)])
+        }
+        "henry" | "H" => e(&[(ENERGY, 1), (TIME, 2), (CHARGE, -2)]),
+        "lumen" | "lm" => e(&[(LUMINOUS, 1), (ANGLE, 2)]),
+        "lux" | "lx" => e(&[(LUMINOUS, 1), (ANGLE, 2), (LENGTH, -2)]),
+        // Physical constants.
+        "Avogadro" => e(&[(AMOUNT, -1)]),
+        "c_light" => Some(velocity),
+        "c_squared" => Some(velocity.powi(2)),
+        "h_Planck" | "hbar_Planck" => e(&[(ENERGY, 1), (TIME, 1)]),
+        "hbarc" | "elm_coupling" => e(&[(ENERGY, 1), (LENGTH, 1)]),
+        "hbarc_squared" => e(&[(ENERGY, 2), (LENGTH, 2)]),
+        "electron_charge" => e(&[(CHARGE, 1)]),
+        "e_squared" => e(&[(CHARGE, 2)]),
+        "electron_mass_c2" | "proton_mass_c2" | "neutron_mass_c2" | "amu_c2" => e(&[(ENERGY, 1)]),
+        "amu" => Some(MASS),
+        "mu0" => e(&[(ENERGY, 1), (TIME, 2), (CHARGE, -2), (LENGTH, -1)]),
+        "epsilon0" => e(&[(CHARGE, 2), (ENERGY, -1), (LENGTH, -1)]),
+        "classic_electr_radius" | "electron_Compton_length" | "Bohr_radius" => Some(length),
+        "alpha_rcl2" => e(&[(LENGTH, 2)]),
+        "twopi_mc2_rcl2" => e(&[(ENERGY, 1), (LENGTH, 2)]),
+        "Bohr_magneton" | "nuclear_magneton" => e(&[(CHARGE, 1), (LENGTH, 2), (TIME, -1)]),
+        "k_Boltzmann" => e(&[(ENERGY, 1), (TEMPERATURE, -1)]),
+        "STP_Temperature" => Some(kind_dimension(UnitKind::Temperature)),
+        "STP_Pressure" => Some(kind_dimension(UnitKind::Pressure)),
+        "kGasThreshold" | "universe_mean_density" => Some(kind_dimension(UnitKind::Density)),
+        _ => None,
+    }
+}
+
+/// One attribute whose units do not add up.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitDiagnostic {
+    /// GDML tag of the offending element, e.g. `tube`, `quantity`, `physvol`.
+    pub element: String,
+    /// Its `name`, or the enclosing volume's for an unnamed `<physvol>`.
+    pub name: String,
+    /// The attribute, with a path for nested elements (`zplane[2]/rmax`,
+    /// `physvol[0]/position/x`).
+    pub attribute: String,
+    pub expression: String,
+    /// Inferred dimension of the expression, when it is known.
+    pub found: Option<String>,
+    /// What the attribute should hold.
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+/// What an attribute expects its expression to be.
+#[derive(Clone, Copy)]
+enum Expect<'a> {
+    /// A quantity of this kind, which Geant4 multiplies by the unit attribute.
+    Unit(UnitKind, UnitAttr<'a>),
+    /// A plain number: counts, scale factors, normal components.
+    Dimensionless,
+}
+
+/// The unit attribute an expression is multiplied by, and its name for
+/// messages (`lunit`, `aunit`, `unit`).
+#[derive(Clone, Copy)]
+struct UnitAttr<'a> {
+    attribute: &'static str,
+    value: Option<&'a str>,
+}
+
+/// The element and attribute an expression sits in, for reporting.
+struct Site<'a> {
+    element: &'a str,
+    name: &'a str,
+    attribute: String,
+}
+
+struct Checker {
+    builtins: HashMap<String, Dimension>,
+    defines: HashMap<String, Dimension>,
+    out: Vec<UnitDiagnostic>,
+}
+
+/// Check every define and every dimensioned attribute in `doc`.
+pub fn check_document(doc: &GdmlDocument) -> Vec<UnitDiagnostic> {
+    let mut c = Checker::new();
+    c.defines_section(&doc.defines);
+    c.materials_section(&doc.materials);
+    for solid in &doc.solids.solids {
+        c.solid(solid);
+    }
+    for vol in &doc.structure.volumes {
+        c.volume(vol);
+    }
+    c.out
+}
+
+/// Infers free-standing expressions -- ones that are not an attribute of
+/// anything -- against a document's defines.
+pub struct ExpressionDimensions {
+    checker: Checker,
+}
+
+impl ExpressionDimensions {
+    pub fn new(defines: &DefineSection) -> Self {
+        let mut checker = Checker::new();
+        checker.defines_section(defines);
+        checker.out.clear();
+        Self { checker }
+    }
+
+    /// The dimension of `expr` when it can be inferred, and the mismatches
+    /// found inside it (`1*cm + 2*s`).
+    pub fn infer(&mut self, expr: &str) -> (Option<Dimension>, Vec<String>) {
+        let site = Site {
+            element: "expression",
+            name: "",
+            attribute: "value".to_string(),
+        };
+        let dim = self.checker.infer(&site, expr);
+        let issues = self.checker.out.drain(..).map(|d| d.message).collect();
+        (dim, issues)
+    }
+}
+
+impl Checker {
+    fn new() -> Self {
+        let mut builtins = HashMap::new();
+        for name in EvalContext::new().values.into_keys() {
+            let dim = builtin_dimension(&name)
+                .or_else(|| units::unit_kind(&name).map(kind_dimension))
+                .unwrap_or(Dimension::NONE);
+            builtins.insert(name, dim);
+        }
+        Self {
+            builtins,
+            defines: HashMap::new(),
+            out: Vec::new(),
+        }
+    }
+
+    fn report(
+        &mut self,
+        site: &Site,
+        expression: &str,
+        found: Option<Dimension>,
+        expected: Option<String>,
+        message: String,
+    ) {
+        self.out.push(UnitDiagnostic {
+            element: site.element.to_string(),
+            name: site.name.to_string(),
+            attribute: site.attribute.clone(),
+            expression: expression.trim().to_string(),
+            found: found.map(Dimension::describe),
+            expected,
+            message,
+        });
+    }
+
+    /// Infer `expr`, reporting any mismatch inside it against `site`.
+    fn infer(&mut self, site: &Site, expr: &str) -> Option<Dimension> {
+        let mut parser = Parser::new(expr, |name: &str| {
+            self.defines
+                .get(name)
+                .or_else(|| self.builtins.get(name))
+                .copied()
+        });
+        let dim = parser.parse();
+        let issues = std::mem::take(&mut parser.issues);
+        for issue in issues {
+            self.report(site, expr, None, None, issue);
+        }
+        dim
+    }
+
+    /// Check an attribute against what it should hold.
+    fn check(&mut self, site: &Site, expr: &str, expect: Expect) {
+        let Some(found) = self.infer(site, expr) else {
+            return;
+        };
+        match expect {
+            Expect::Dimensionless => {
+                if !found.physical().is_dimensionless() {
+                    self.report(
+                        site,
+                        expr,
+                        Some(found),
+                        Some("dimensionless".to_string()),
+                        format!(
+                            "`{}` should be a plain number but `{}` is {}",
+                            site.attribute,
+                            expr.trim(),
+                            with_article(&found.describe())
+                        ),
+                    );
+                }
+            }
+            Expect::Unit(kind, unit) => {
+                let want = kind_dimension(kind);
+                if found.is_dimensionless() {
+                    return;
+                }
+                let matches = if kind == UnitKind::Angle {
+                    found.physical().is_dimensionless()
+                } else {
+                    found == want
+                };
+                if !matches {
+                    self.report(
+                        site,
+                        expr,
+                        Some(found),
+                        Some(kind_label(kind).to_string()),
+                        format!(
+                            "`{}` expects {} but `{}` is {}",
+                            site.attribute,
+                            with_article(kind_label(kind)),
+                            expr.trim(),
+                            with_article(&found.describe())
+                        ),
+                    );
+                    return;
+                }
+                // Already carries its unit: Geant4 multiplies by the unit
+                // attribute regardless, so anything but a factor of 1 applies
+                // the unit twice.
+                let Some(unit_value) = unit.value else {
+                    return;
+                };
+                let factor = units::unit_factor(unit_value).map(|(_, f)| f);
+                if factor.is_some_and(|f| (f - 1.0).abs() > 1e-12) {
+                    self.report(
+                        site,
+                        expr,
+                        Some(found),
+                        Some(kind_label(kind).to_string()),
+                        format!(
+                            "`{}` already has units, and Geant4 multiplies it by {}=\"{}\" \
+                             as well, applying the unit twice",
+                            expr.trim(),
+                            unit.attribute,
+                            unit_value
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Report a unit attribute naming a unit of the wrong category, which
+    /// Geant4 rejects with "unit category mismatch".
+    fn check_unit_attr(&mut self, site: &Site, kind: UnitKind, unit: Option<&str>) {
+        let Some(unit) = unit else { return };
+        if let Some(found) = units::unit_kind(unit) {
+            if found != kind {
+                self.report(
+                    site,
+                    unit,
+                    Some(kind_dimension(found)),
+                    Some(kind_label(kind).to_string()),
+                    format!(
+                        "`{}` must be {} unit, but \"{}\" is {} unit",
+                        site.attribute,
+                        with_article(kind_label(kind)),
+                        unit,
+                        with_article(kind_label(found))
+                    ),
+                );
+            }
+        }
+    }
+
+    // ─── Defines ────────────────────────────────────────────────────────────
+
+    fn defines_section(&mut self, defines: &DefineSection) {
+        // Dimensions propagate like values, so walk the defines in the order
+        // the evaluator does.
+        let mut entries: Vec<(DefineEntry, &'static str, Option<&Quantity>)> = Vec::new();
+        for c in &defines.constants {
+            entries.push((entry(&c.name, &c.value), "constant", None));
+        }
+        for q in &defines.quantities {
+            entries.push((entry(&q.name, &q.value), "quantity", Some(q)));
+        }
+        for v in &defines.variables {
+            entries.push((entry(&v.name, &v.value), "variable", None));
+        }
+        for e in &defines.expressions {
+            entries.push((entry(&e.name, &e.value), "expression", None));
+        }
+        let plain: Vec<DefineEntry> = entries.iter().map(|(e, _, _)| e.clone()).collect();
+        let known: HashSet<String> = self.builtins.keys().cloned().collect();
+        let order = topological_sort(&plain, &known).unwrap_or_else(|_| (0..plain.len()).collect());
+
+        for idx in order {
+            let (e, element, quantity) = &entries[idx];
+            let site = Site {
+                element,
+                name: &e.name,
+                attribute: "value".to_string(),
+            };
+            let dim = match quantity {
+                Some(q) => self.quantity(q),
+                None => self.infer(&site, &e.expression),
+            };
+            match dim {
+                Some(d) => {
+                    self.defines.insert(e.name.clone(), d);
+                }
+                None => {
+                    self.defines.remove(&e.name);
+                }
+            }
+        }
+
+        for p in &defines.positions {
+            self.xyz("position", &p.name, "", p, UnitKind::Length);
+        }
+        for r in &defines.rotations {
+            self.xyz("rotation", &r.name, "", r, UnitKind::Angle);
+        }
+        for s in &defines.scales {
+            for (axis, v) in [("x", &s.x), ("y", &s.y), ("z", &s.z)] {
+                if let Some(v) = v {
+                    let site = Site {
+                        element: "scale",
+                        name: &s.name,
+                        attribute: axis.to_string(),
+                    };
+                    self.check(&site, v, Expect::Dimensionless);
+                }
+            }
+        }
+    }
+
+    fn quantity(&mut self, q: &Quantity) -> Option<Dimension> {
+        let site = Site {
+            element: "quantity",
+            name: &q.name,
+            attribute: "value".to_string(),
+        };
+        let value = self.infer(&site, &q.value);
+        let unit_kind = q.unit.as_deref().and_then(units::unit_kind);
+
+        if let (Some(ty), Some(unit), Some(kind)) =
+            (q.r#type.as_deref(), q.unit.as_deref(), unit_kind)
+        {
+            if let Some(want) = quantity_type_kind(ty) {
+                if want != kind {
+                    let site = Site {
+                        attribute: "unit".to_string(),
+                        ..site
+                    };
+                    self.report(
+                        &site,
+                        unit,
+                        Some(kind_dimension(kind)),
+                        Some(kind_label(want).to_string()),
+                        format!(
+                            "quantity of type \"{}\" is given in \"{}\", {} unit",
+                            ty,
+                            unit,
+                            with_article(kind_label(kind))
+                        ),
+                    );
+                }
+            }
+        }
+
+        let Some(kind) = unit_kind else {
+            return value;
+        };
+        let value = value?;
+        if !value.is_dimensionless() {
+            self.report(
+                &site,
+                &q.value,
+                Some(value),
+                Some("dimensionless".to_string()),
+                format!(
+                    "`{}` already has units, and Geant4 multiplies it by unit=\"{}\" as well",
+                    q.value.trim(),
+                    q.unit.as_deref().unwrap_or_default()
+                ),
+            );
+        }
+        Some(value.mul(kind_dimension(kind)))
+    }
+
+    /// `x`/`y`/`z` of a position or rotation, inline or named.
+    fn xyz<T: Xyz>(&mut self, element: &str, name: &str, prefix: &str, item: &T, kind: UnitKind) {
+        let unit = item.unit();
+        let site = Site {
+            element,
+            name,
+            attribute: format!("{}unit", prefix),
+        };
+        self.check_unit_attr(&site, kind, unit);
+        for (axis, v) in item.components() {
+            if let Some(v) = v {
+                let site = Site {
+                    element,
+                    name,
+                    attribute: format!("{}{}", prefix, axis),
+                };
+                let expect = Expect::Unit(
+                    kind,
+                    UnitAttr {
+                        attribute: "unit",
+                        value: unit,
+                    },
+                );
+                self.check(&site, v, expect);
+            }
+        }
+    }
+
+    // ─── Materials ──────────────────────────────────────────────────────────
+
+    fn materials_section(&mut self, materials: &MaterialSection) {
+        for iso in &materials.isotopes {
+            self.atom("isotope", &iso.name, &iso.atom_value, &iso.atom_unit);
+        }
+        for el in &materials.elements {
+            self.atom("element", &el.name, &el.atom_value, &el.atom_unit);
+        }
+        for mat in &materials.materials {
+            if let Some(d) = &mat.density {
+                self.property(
+                    &mat.name,
+                    "D",
+                    &d.value,
+                    d.unit.as_deref(),
+                    UnitKind::Density,
+                );
+            }
+            for (attribute, value, kind) in [
+                ("T", &mat.temperature, UnitKind::Temperature),
+                ("P", &mat.pressure, UnitKind::Pressure),
+                ("MEE", &mat.mee, UnitKind::Energy),
+                ("RL", &mat.rl, UnitKind::Length),
+                ("AL", &mat.al, UnitKind::Length),
+            ] {
+                if let Some(v) = value {
+                    self.property(&mat.name, attribute, &v.value, v.unit.as_deref(), kind);
+                }
+            }
+            self.atom("material", &mat.name, &mat.atom_value, &mat.atom_unit);
+            if let Some(dref) = &mat.density_ref {
+                // <Dref> takes the define as-is, already in internal units.
+                let want = kind_dimension(UnitKind::Density);
+                if let Some(found) = self.defines.get(dref).copied() {
+                    if found != want {
+                        let site = Site {
+                            element: "material",
+                            name: &mat.name,
+                            attribute: "Dref".to_string(),
+                        };
+                        self.report(
+                            &site,
+                            dref,
+                            Some(found),
+                            Some("density".to_string()),
+                            format!(
+                                "Dref \"{}\" is {}; Geant4 reads it as a density in internal \
+                                 units, so it needs to carry g/cm3 or similar",
+                                dref,
+                                if found.is_dimensionless() {
+                                    "a plain number".to_string()
+                                } else {
+                                    with_article(&found.describe())
+                                }
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn property(
+        &mut self,
+        name: &str,
+        attribute: &str,
+        value: &str,
+        unit: Option<&str>,
+        kind: UnitKind,
+    ) {
+        let site = Site {
+            element: "material",
+            name,
+            attribute: attribute.to_string(),
+        };
+        self.check_unit_attr(
+            &Site {
+                attribute: format!("{}/unit", attribute),
+                ..site
+            },
+            kind,
+            unit,
+        );
+        let expect = Expect::Unit(
+            kind,
+            UnitAttr {
+                attribute: "unit",
+                // An absent unit is Geant4's default for the element, which is
+                // never 1 in internal units (g/cm3, pascal, eV...).
+                value: Some(unit.unwrap_or(match kind {
+                    UnitKind::Density => "g/cm3",
+                    UnitKind::Pressure => "pascal",
+                    UnitKind::Energy => "eV",
+                    UnitKind::Temperature => "K",
+                    _ => "mm",
+                })),
+            },
+        );
+        self.check(&site, value, expect);
+    }
+
+    fn atom(&mut self, element: &str, name: &str, value: &Option<String>, unit: &Option<String>) {
+        let Some(value) = value else { return };
+        let site = Site {
+            element,
+            name,
+            attribute: "atom".to_string(),
+        };
+        self.check_unit_attr(
+            &Site {
+                attribute: "atom/unit".to_string(),
+                ..site
+            },
+            UnitKind::MolarMass,
+            unit.as_deref(),
+        );
+        let expect = Expect::Unit(
+            UnitKind::MolarMass,
+            UnitAttr {
+                attribute: "unit",
+                value: Some(unit.as_deref().unwrap_or("g/mole")),
+            },
+        );
+        self.check(&site, value, expect);
+    }
+
+    // ─── Solids ─────────────────────────────────────────────────────────────
+
+    fn solid(&mut self, solid: &Solid) {
+        let Ok(serde_json::Value::Object(map)) = serde_json::to_value(solid) else {
+            return;
+        };
+        let element = map
+            .get("type")
+            .and_then(|t| t.as_str())
+            .map(|t| t.to_ascii_lowercase())
+            .unwrap_or_default();
+        let lunit = map
+            .get("lunit")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let aunit = map
+            .get("aunit")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let name = solid.name().to_string();
+        let units = SolidUnits {
+            lunit: lunit.as_deref(),
+            aunit: aunit.as_deref(),
+        };
+        let site = |attribute: &str| Site {
+            element: &element,
+            name: &name,
+            attribute: attribute.to_string(),
+        };
+        self.check_unit_attr(&site("lunit"), UnitKind::Length, units.lunit);
+        self.check_unit_attr(&site("aunit"), UnitKind::Angle, units.aunit);
+        self.solid_fields(&element, &name, &map, "", units);
+    }
+
+    fn solid_fields(
+        &mut self,
+        element: &str,
+        name: &str,
+        map: &serde_json::Map<String, serde_json::Value>,
+        path: &str,
+        units: SolidUnits,
+    ) {
+        use serde_json::Value;
+        for (key, value) in map {
+            let attribute = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}/{}", path, key)
+            };
+            match value {
+                Value::String(expr) => {
+                    let Some(expect) = solid_field_expectation(element, key, units) else {
+                        continue;
+                    };
+                    let site = Site {
+                        element,
+                        name,
+                        attribute,
+                    };
+                    self.check(&site, expr, expect);
+                }
+                Value::Array(items) => {
+                    for (i, item) in items.iter().enumerate() {
+                        if let Value::Object(m) = item {
+                            let p = format!("{}[{}]", attribute, i);
+                            self.solid_fields(element, name, m, &p, units);
+                        }
+                    }
+                }
+                Value::Object(m) => {
+                    // Inline <position>/<rotation> inside a boolean or
+                    // multi-union node carry their own unit.
+                    if let Some(inline) = m.get("Inline") {
+                        if let Ok(p) = serde_json::from_value::<Position>(inline.clone()) {
+                            let kind = if key.contains("rotation") {
+                                UnitKind::Angle
+                            } else {
+                                UnitKind::Length
+                            };
+                            let prefix = format!("{}/", attribute);
+                            self.xyz(element, name, &prefix, &p, kind);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // ─── Structure ──────────────────────────────────────────────────────────
+
+    fn volume(&mut self, vol: &Volume) {
+        for (i, pv) in vol.physvols.iter().enumerate() {
+            let name = pv.name.as_deref().unwrap_or(&vol.name);
+            if let Some(PlacementPos::Inline(p)) = &pv.position {
+                let prefix = format!("physvol[{}]/position/", i);
+                self.xyz("physvol", name, &prefix, p, UnitKind::Length);
+            }
+            if let Some(PlacementRot::Inline(r)) = &pv.rotation {
+                let prefix = format!("physvol[{}]/rotation/", i);
+                self.xyz("physvol", name, &prefix, r, UnitKind::Angle);
+            }
+            if let Some(copy) = &pv.copynumber {
+                let site = Site {
+                    element: "physvol",
+                    name,
+                    attribute: format!("physvol[{}]/copynumber", i),
+                };
+                self.check(&site, copy, Expect::Dimensionless);
+            }
+        }
+        if let Some(rep) = &vol.replica {
+            let site = |attribute: &str| Site {
+                element: "replicavol",
+                name: &vol.name,
+                attribute: attribute.to_string(),
+            };
+            self.check(&site("number"), &rep.number, Expect::Dimensionless);
+            // Width and offset are lengths along x/y/z/rho and angles along
+            // phi.
+            let kind = if rep.curvilinear_axis.as_deref() == Some("phi") {
+                UnitKind::Angle
+            } else {
+                UnitKind::Length
+            };
+            for (attribute, expr, unit) in [
+                ("width", &rep.width, rep.width_unit.as_deref()),
+                ("offset", &rep.offset, rep.offset_unit.as_deref()),
+            ] {
+                let unit_site = site(&format!("{}/unit", attribute));
+                self.check_unit_attr(&unit_site, kind, unit);
+                let expect = Expect::Unit(
+                    kind,
+                    UnitAttr {
+                        attribute: "unit",
+                        value: unit,
+                    },
+                );
+                self.check(&site(attribute), expr, expect);
+            }
+        }
+    }
+}
+
+fn entry(name: &str, expression: &str) -> DefineEntry {
+    DefineEntry {
+        name: name.to_string(),
+        expression: expression.to_string(),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SolidUnits<'a> {
+    lunit: Option<&'a str>,
+    aunit: Option<&'a str>,
+}
+
+/// What a solid attribute holds, by field name -- the same approach
+/// `references.rs` takes, so a newly modelled solid is covered as long as it
+/// uses the usual attribute names. `None` for fields that are not
+/// expressions. An elcone's `dx`/`dy` are slopes, not the half-lengths the
+/// names mean elsewhere, and a `reflectedSolid` carries a rotation `rx`..`rz`
+/// and scale factors `sx`..`sz` next to its translation `dx`..`dz`.
+fn solid_field_expectation<'a>(
+    element: &str,
+    key: &str,
+    units: SolidUnits<'a>,
+) -> Option<Expect<'a>> {
+    let angle = || {
+        Expect::Unit(
+            UnitKind::Angle,
+            UnitAttr {
+                attribute: "aunit",
+                value: units.aunit,
+            },
+        )
+    };
+    Some(match key {
+        "name" | "type" | "operation" | "lunit" | "aunit" | "unit" | "scale_name" | "first_ref"
+        | "second_ref" | "solid_ref" | "scale_ref" | "vertex1" | "vertex2" | "vertex3"
+        | "vertex4" => return None,
+        "startphi" | "deltaphi" | "starttheta" | "deltatheta" | "alpha" | "alpha1" | "alpha2"
+        | "alph" | "theta" | "phi" | "inst" | "outst" | "twistedangle" | "phi_twist" | "totphi" => {
+            angle()
+        }
+        "numsides" | "nseg" | "z_order" | "scaling_factor" | "scale_x" | "scale_y" | "scale_z"
+        | "low_x" | "low_y" | "low_z" | "high_x" | "high_y" | "high_z" => Expect::Dimensionless,
+        "dx" | "dy" if element == "elcone" => Expect::Dimensionless,
+        "rx" | "ry" | "rz" if element == "reflected" => angle(),
+        "sx" | "sy" | "sz" if element == "reflected" => Expect::Dimensionless,
+        _ => Expect::Unit(
+            UnitKind::Length,
+            UnitAttr {
+                attribute: "lunit",
+                value: units.lunit,
+            },
+        ),
+    })
+}
+
+/// Positions and rotations share a shape.
+trait Xyz {
+    fn components(&self) -> [(&'static str, &Option<String>); 3];
+    fn unit(&self) -> Option<&str>;
+}
+
+impl Xyz for Position {
+    fn components(&self) -> [(&'static str, &Option<String>); 3] {
+        [("x", &self.x), ("y", &self.y), ("z", &self.z)]
+    }
+    fn unit(&self) -> Option<&str> {
+        self.unit.as_deref()
+    }
+}
+
+impl Xyz for Rotation {
+    fn components(&self) -> [(&'static str, &Option<String>); 3] {
+        [("x", &self.x), ("y", &self.y), ("z", &self.z)]
+    }
+    fn unit(&self) -> Option<&str> {
+        self.unit.as_deref()
+    }
+}
+
+// ─── Expression parser ──────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+/// Split an expression into tokens with their byte spans, or `None` for
+/// anything the evaluator would not accept either.
+fn tokenize(expr: &str) -> Option<Vec<(Token, usize, usize)>> {
+    let bytes = expr.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        let start = i;
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            out.push((Token::Num(expr[start..i].parse().ok()?), start, i));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            out.push((Token::Ident(expr[start..i].to_string()), start, i));
+            continue;
+        }
+        let token = match c {
+            '*' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 1;
+                Token::Op('^')
+            }
+            '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            _ => return None,
+        };
+        i += 1;
+        out.push((token, start, i));
+    }
+    Some(out)
+}
+
+/// An inferred sub-expression: its dimension when known, its value when it is
+/// a literal (needed for exponents), and where it sits in the source.
+#[derive(Clone, Copy)]
+struct Inferred {
+    dim: Option<Dimension>,
+    literal: Option<f64>,
+    start: usize,
+    end: usize,
+}
+
+/// Recursive-descent over the CLHEP evaluator's grammar, computing dimensions
+/// instead of values. Mismatches found on the way are collected in `issues`;
+/// anything the parser does not understand just yields an unknown dimension.
+struct Parser<'a, F: Fn(&str) -> Option<Dimension>> {
+    source: &'a str,
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+    lookup: F,
+    issues: Vec<String>,
+}
+
+impl<'a, F: Fn(&str) -> Option<Dimension>> Parser<'a, F> {
+    fn new(source: &'a str, lookup: F) -> Self {
+        Self {
+            source,
+            tokens: Vec::new(),
+            pos: 0,
+            lookup,
+            issues: Vec::new(),
+        }
+    }
+
+    fn parse(&mut self) -> Option<Dimension> {
+        self.tokens = tokenize(self.source)?;
+        if self.tokens.is_empty() {
+            return None;
+        }
+        let value = self.expr()?;
+        if self.pos != self.tokens.len() {
+            return None;
+        }
+        value.dim
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _, _)| t)
+    }
+
+    fn text(&self, start: usize, end: usize) -> &str {
+        self.source[start..end].trim()
+    }
+
+    fn expr(&mut self) -> Option<Inferred> {
+        let mut left = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            let right = self.term()?;
+            let dim = self.add(left, right, op);
+            left = Inferred {
+                dim,
+                literal: match (left.literal, right.literal) {
+                    (Some(a), Some(b)) if op == '+' => Some(a + b),
+                    (Some(a), Some(b)) => Some(a - b),
+                    _ => None,
+                },
+                start: left.start,
+                end: right.end,
+            };
+        }
+        Some(left)
+    }
+
+    /// Dimension of `a + b`, reporting a mismatch.
+    fn add(&mut self, a: Inferred, b: Inferred, op: char) -> Option<Dimension> {
+        let (da, db) = (a.dim?, b.dim?);
+        if da.is_dimensionless() {
+            return Some(db);
+        }
+        if db.is_dimensionless() || da == db {
+            return Some(da);
+        }
+        if da.physical() == db.physical() {
+            return Some(if da.0[ANGLE] != 0 { da } else { db });
+        }
+        let verb = if op == '+' { "adds" } else { "subtracts" };
+        self.issues.push(format!(
+            "`{}` {} {} and {}",
+            self.text(a.start, b.end),
+            verb,
+            with_article(&da.describe()),
+            with_article(&db.describe())
+        ));
+        None
+    }
+
+    fn term(&mut self) -> Option<Inferred> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            let right = self.unary()?;
+            let dim = match (left.dim, right.dim) {
+                (Some(a), Some(b)) if op == '*' => Some(a.mul(b)),
+                (Some(a), Some(b)) => Some(a.div(b)),
+                _ => None,
+            };
+            left = Inferred {
+                dim,
+                literal: match (left.literal, right.literal) {
+                    (Some(a), Some(b)) if op == '*' => Some(a * b),
+                    (Some(a), Some(b)) if b != 0.0 => Some(a / b),
+                    _ => None,
+                },
+                start: left.start,
+                end: right.end,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Inferred> {
+        if let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            let start = self.tokens[self.pos].1;
+            self.pos += 1;
+            let inner = self.unary()?;
+            return Some(Inferred {
+                literal: inner.literal.map(|v| if op == '-' { -v } else { v }),
+                start,
+                ..inner
+            });
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Option<Inferred> {
+        let base = self.primary()?;
+        if self.peek() != Some(&Token::Op('^')) {
+            return Some(base);
+        }
+        self.pos += 1;
+        let exponent = self.unary()?;
+        Some(Inferred {
+            dim: self.raise(base, exponent),
+            literal: match (base.literal, exponent.literal) {
+                (Some(a), Some(b)) => Some(a.powf(b)),
+                _ => None,
+            },
+            start: base.start,
+            end: exponent.end,
+        })
+    }
+
+    fn raise(&mut self, base: Inferred, exponent: Inferred) -> Option<Dimension> {
+        let d = base.dim?;
+        if d.is_dimensionless() {
+            return Some(d);
+        }
+        let n = exponent.literal?;
+        if n.fract() == 0.0 && n.abs() <= 16.0 {
+            Some(d.powi(n as i8))
+        } else if n == 0.5 {
+            d.sqrt()
+        } else {
+            None
+        }
+    }
+
+    fn primary(&mut self) -> Option<Inferred> {
+        let (token, start, end) = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match token {
+            Token::Num(v) => Some(Inferred {
+                dim: Some(Dimension::NONE),
+                literal: Some(v),
+                start,
+                end,
+            }),
+            Token::Open => {
+                let inner = self.expr()?;
+                let (_, _, close_end) = self.tokens.get(self.pos)?.clone();
+                if self.peek() != Some(&Token::Close) {
+                    return None;
+                }
+                self.pos += 1;
+                Some(Inferred {
+                    start,
+                    end: close_end,
+                    ..inner
+                })
+            }
+            Token::Ident(name) => {
+                if self.peek() == Some(&Token::Open) {
+                    return self.call(&name, start);
+                }
+                Some(Inferred {
+                    dim: (self.lookup)(&name),
+                    literal: None,
+                    start,
+                    end,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, name: &str, start: usize) -> Option<Inferred> {
+        self.pos += 1; // (
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::Close) {
+            loop {
+                args.push(self.expr()?);
+                match self.peek() {
+                    Some(Token::Comma) => self.pos += 1,
+                    Some(Token::Close) => break,
+                    _ => return None,
+                }
+            }
+        }
+        let end = self.tokens.get(self.pos)?.2;
+        self.pos += 1; // )
+        let text = self.text(start, end).to_string();
+
+        let dim = match (name, args.as_slice()) {
+            ("sin" | "cos" | "tan", [a]) => {
+                self.require_plain(&text, *a, true);
+                Some(Dimension::NONE)
+            }
+            ("asin" | "acos" | "atan", [a]) => {
+                self.require_plain(&text, *a, false);
+                Some(kind_dimension(UnitKind::Angle))
+            }
+            ("atan2", [y, x]) => {
+                self.add(*y, *x, '-');
+                Some(kind_dimension(UnitKind::Angle))
+            }
+            ("exp" | "log" | "log10" | "ln" | "sinh" | "cosh" | "tanh", [a]) => {
+                self.require_plain(&text, *a, false);
+                Some(Dimension::NONE)
+            }
+            ("sqrt", [a]) => a.dim.and_then(Dimension::sqrt),
+            ("pow", [a, b]) => self.raise(*a, *b),
+            ("abs" | "fabs" | "floor" | "ceil" | "round", [a]) => a.dim,
+            ("min" | "max", [a, b]) => self.add(*a, *b, '-'),
+            _ => None,
+        };
+        Some(Inferred {
+            dim,
+            literal: None,
+            start,
+            end,
+        })
+    }
+
+    /// Arguments of transcendental functions must be plain numbers (or, for
+    /// the trigonometric ones, angles).
+    fn require_plain(&mut self, call: &str, arg: Inferred, angle_ok: bool) {
+        let Some(d) = arg.dim else { return };
+        let ok = if angle_ok {
+            d.physical().is_dimensionless()
+        } else {
+            d.is_dimensionless()
+        };
+        if !ok {
+            self.issues.push(format!(
+                "`{}` takes {} but `{}` is {}",
+                call,
+                if angle_ok {
+                    "an angle"
+                } else {
+                    "a plain number"
+                },
+                self.text(arg.start, arg.end),
+                with_article(&d.describe())
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn check(body: &str) -> Vec<UnitDiagnostic> {
+        let xml = format!(
+            r#"<?xml version="1.0"?><gdml>{}<setup name="Default" version="1.0"><world ref="World"/></setup></gdml>"#,
+            body
+        );
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "t.gdml".to_string()).unwrap();
+        check_document(&doc)
+    }
+
+    fn find<'a>(d: &'a [UnitDiagnostic], attribute: &str) -> &'a UnitDiagnostic {
+        d.iter()
+            .find(|x| x.attribute == attribute)
+            .unwrap_or_else(|| panic!("no diagnostic for {attribute}: {d:?}"))
+    }
+
+    #[test]
+    fn dimensions_propagate_through_arithmetic() {
+        let infer = |expr: &str| {
+            let mut p = Parser::new(expr, |name: &str| match name {
+                "cm" => Some(kind_dimension(UnitKind::Length)),
+                "deg" => Some(kind_dimension(UnitKind::Angle)),
+                "g" => Some(kind_dimension(UnitKind::Mass)),
+                "cm3" => Some(kind_dimension(UnitKind::Volume)),
+                _ => None,
+            });
+            (p.parse(), p.issues)
+        };
+        let length = kind_dimension(UnitKind::Length);
+        assert_eq!(infer("2*cm + 5").0, Some(length));
+        assert_eq!(infer("(3*cm)^2").0, Some(kind_dimension(UnitKind::Surface)));
+        assert_eq!(infer("sqrt(4*cm**2)").0, Some(length));
+        assert_eq!(
+            infer("1.032*g/cm3").0,
+            Some(kind_dimension(UnitKind::Density))
+        );
+        assert_eq!(infer("sin(30*deg)*cm").0, Some(length));
+        assert_eq!(infer("unknown*cm").0, None);
+
+        let (dim, issues) = infer("10*cm + 90*deg");
+        assert_eq!(dim, None);
+        assert_eq!(issues, vec!["`10*cm + 90*deg` adds a length and an angle"]);
+        let (_, issues) = infer("exp(2*cm)");
+        assert_eq!(issues.len(), 1);
+    }
+
+    #[test]
+    fn attribute_mismatches_name_the_element_and_attribute() {
+        let d = check(
+            r#"<define>
+                 <constant name="r" value="2*cm"/>
+                 <constant name="bad" value="r + 10*deg"/>
+                 <quantity name="rho" type="density" value="1" unit="mm"/>
+               </define>
+               <solids>
+                 <tube name="t" rmax="10*deg" z="r" startphi="r" deltaphi="360" aunit="deg" lunit="mm"/>
+                 <box name="b" x="5*cm" y="10" z="10" lunit="cm"/>
+               </solids>"#,
+        );
+
+        let rmax = find(&d, "rmax");
+        assert_eq!((rmax.element.as_str(), rmax.name.as_str()), ("tube", "t"));
+        assert_eq!(rmax.found.as_deref(), Some("angle"));
+        assert_eq!(rmax.expected.as_deref(), Some("length"));
+
+        let startphi = find(&d, "startphi");
+        assert_eq!(startphi.found.as_deref(), Some("length"));
+
+        let lunit = find(&d, "x");
+        assert_eq!(lunit.element, "box");
+        assert!(lunit.message.contains("twice"), "{}", lunit.message);
+
+        let unit = d.iter().find(|x| x.name == "rho").unwrap();
+        assert_eq!(unit.attribute, "unit");
+        assert_eq!(unit.expected.as_deref(), Some("density"));
+
+        let sum = d.iter().find(|x| x.name == "bad").unwrap();
+        assert_eq!(sum.element, "constant");
+        assert!(sum.message.contains("adds a length and an angle"));
+
+        // z="r" is a length in a length attribute with a unit factor of 1, and
+        // deltaphi is a bare number: neither is reported.
+        assert!(d
+            .iter()
+            .all(|x| x.attribute != "z" && x.attribute != "deltaphi"));
+        assert_eq!(d.len(), 5, "{d:?}");
+    }
+
+    #[test]
+    fn placements_and_materials_are_checked() {
+        let d = check(
+            r#"<define><quantity name="dens" value="2.7" unit="g/cm3"/><constant name="k" value="3"/></define>
+               <materials>
+                 <material name="Al"><D value="dens"/><atom value="26.98"/></material>
+                 <material name="X"><Dref ref="k"/><atom value="1"/></material>
+                 <material name="Y"><T value="293.15" unit="cm"/><D value="1"/><atom value="1"/></material>
+               </materials>
+               <solids><box name="B" x="1" y="1" z="1"/></solids>
+               <structure>
+                 <volume name="World"><materialref ref="Al"/><solidref ref="B"/>
+                   <physvol name="p"><volumeref ref="World"/>
+                     <position name="p_pos" x="1*deg" y="0" z="0"/>
+                     <rotation name="p_rot" x="90*deg" y="0" z="0" unit="deg"/>
+                   </physvol>
+                 </volume>
+               </structure>"#,
+        );
+        assert_eq!(find(&d, "D").name, "Al");
+        assert_eq!(find(&d, "Dref").name, "X");
+        assert_eq!(find(&d, "T/unit").expected.as_deref(), Some("temperature"));
+        assert_eq!(find(&d, "physvol[0]/position/x").name, "p");
+        assert!(find(&d, "physvol[0]/rotation/x").message.contains("twice"));
+        assert_eq!(d.len(), 5, "{d:?}");
+    }
+
+    #[test]
+    fn elcone_slopes_are_plain_numbers() {
+        let d = check(
+            r#"<solids>
+                 <elcone name="e" dx="0.5*mm" dy="0.25" zmax="10*mm" zcut="5" lunit="cm"/>
+               </solids>"#,
+        );
+        let dx = find(&d, "dx");
+        assert_eq!(dx.element, "elcone");
+        assert_eq!(dx.expected.as_deref(), Some("dimensionless"));
+        assert!(d.iter().all(|x| x.attribute != "dy"));
+        // zmax is still a length, given in mm on top of lunit="cm".
+        assert!(find(&d, "zmax").message.contains("twice"));
+        assert_eq!(d.len(), 2, "{d:?}");
+    }
+
+    #[test]
+    fn quantities_and_unit_attributes_must_agree() {
+        let d = check(
+            r#"<define>
+                 <quantity name="len" type="length" value="3" unit="g/cm3"/>
+                 <quantity name="twice" type="length" value="2*cm" unit="mm"/>
+                 <quantity name="ok" type="angle" value="30" unit="deg"/>
+               </define>
+               <solids>
+                 <box name="b" x="1" y="1" z="1" lunit="deg"/>
+                 <tube name="t" rmax="1" z="1" deltaphi="90" aunit="mm"/>
+                 <cone name="c" rmax1="1" rmax2="1" z="1" deltaphi="1" aunit="rad" lunit="m"/>
+               </solids>"#,
+        );
+        let len = d.iter().find(|x| x.name == "len").unwrap();
+        assert_eq!(len.attribute, "unit");
+        assert_eq!(len.expected.as_deref(), Some("length"));
+        assert_eq!(len.found.as_deref(), Some("density"));
+
+        let twice = d.iter().find(|x| x.name == "twice").unwrap();
+        assert_eq!(twice.attribute, "value");
+        assert!(
+            twice.message.contains("already has units"),
+            "{}",
+            twice.message
+        );
+
+        let lunit = find(&d, "lunit");
+        assert_eq!((lunit.element.as_str(), lunit.name.as_str()), ("box", "b"));
+        assert_eq!(lunit.expected.as_deref(), Some("length"));
+        let aunit = find(&d, "aunit");
+        assert_eq!((aunit.element.as_str(), aunit.name.as_str()), ("tube", "t"));
+        assert_eq!(aunit.expected.as_deref(), Some("angle"));
+
+        assert!(d.iter().all(|x| x.name != "ok" && x.name != "c"));
+        assert_eq!(d.len(), 4, "{d:?}");
+    }
+
+    #[test]
+    fn matrix_entries_do_not_trip_the_checker() {
+        // Property tables mix energies and plain numbers, and an entry's
+        // dimension is not tracked: using one is never reported.
+        let d = check(
+            r#"<define>
+                 <matrix name="rindex" coldim="2" values="2.0*eV 1.33 3.0*eV 1.34"/>
+                 <constant name="n" value="rindex[1,2]"/>
+               </define>
+               <solids>
+                 <box name="b" x="rindex[2,2]*cm" y="n*mm" z="rindex_1_1" lunit="mm"/>
+               </solids>"#,
+        );
+        assert!(d.is_empty(), "{d:?}");
+    }
+
+    #[test]
+    fn reflected_solids_take_a_rotation_and_scale_factors() {
+        let d = check(
+            r#"<solids>
+                 <box name="b" x="10" y="10" z="10"/>
+                 <reflectedSolid name="r" solid="b" sx="1" sy="1" sz="-1*mm"
+                   rx="0" ry="0" rz="30*deg" dx="5*cm" dy="0" dz="0" aunit="rad" lunit="mm"/>
+               </solids>"#,
+        );
+        // rz is an angle and aunit="rad" has a factor of 1; dx is a length
+        // and lunit="mm" too. Only the scale factor carrying a unit is wrong.
+        let sz = find(&d, "sz");
+        assert_eq!(sz.element, "reflected");
+        assert_eq!(sz.expected.as_deref(), Some("dimensionless"));
+        assert_eq!(d.len(), 1, "{d:?}");
+    }
+}
@@ -4,7 +4,7 @@ use std::collections::{HashMap, HashSet};
 
 use super::context::EvalContext;
 use super::dependency::{extract_identifiers, topological_sort, DefineEntry};
-use crate::gdml::model::{DefineSection, Position, Rotation};
+use crate::gdml::model::{DefineSection, Matrix, Position, Rotation};
 use crate::gdml::units;
 
 pub struct EvalEngine {
@@ -14,6 +14,8 @@ pub struct EvalEngine {
     pub rotation_values: HashMap<String, [f64; 3]>,
     pub length_symbols: HashSet<String>,
     pub angle_symbols: HashSet<String>,
+    /// Evaluated `<matrix>` tables, by name.
+    pub matrix_values: HashMap<String, MatrixValues>,
     /// Non-fatal evaluation warnings collected during value resolution
     /// (e.g. expressions that failed and were treated as 0). Behind a `Mutex`
     /// rather than a `RefCell` so `EvalEngine` stays `Sync` (it lives inside
@@ -21,6 +23,38 @@ pub struct EvalEngine {
     warnings: std::sync::Mutex<Vec<String>>,
 }
 
+/// A `<matrix>` with every entry evaluated, stored row-major as Geant4's
+/// `G4GDMLMatrix` does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixValues {
+    pub coldim: usize,
+    pub values: Vec<f64>,
+}
+
+impl MatrixValues {
+    pub fn rows(&self) -> usize {
+        self.values.len() / self.coldim
+    }
+
+    /// Whether the entries are named with one index, `name_i`: Geant4 does
+    /// that for a single column and for a single row alike.
+    pub fn is_vector(&self) -> bool {
+        is_vector(self.coldim, self.values.len())
+    }
+
+    /// The entry at 0-based row `r`, column `c`.
+    pub fn get(&self, r: usize, c: usize) -> Option<f64> {
+        if c >= self.coldim {
+            return None;
+        }
+        self.values.get(r * self.coldim + c).copied()
+    }
+}
+
+fn is_vector(coldim: usize, size: usize) -> bool {
+    coldim == 1 || size == coldim
+}
+
 impl Default for EvalEngine {
     fn default() -> Self {
         Self::new()
@@ -36,6 +70,7 @@ impl EvalEngine {
             scale_values: HashMap::new(),
             length_symbols: HashSet::new(),
             angle_symbols: HashSet::new(),
+            matrix_values: HashMap::new(),
             warnings: std::sync::Mutex::new(Vec::new()),
         }
     }
@@ -48,6 +83,7 @@ impl EvalEngine {
         self.rotation_values.clear();
         self.length_symbols.clear();
         self.angle_symbols.clear();
+        self.matrix_values.clear();
         if let Ok(mut w) = self.warnings.lock() {
             w.clear();
         }
@@ -79,6 +115,15 @@ impl EvalEngine {
                 expression: e.value.clone(),
             });
         }
+        // Matrices take part in the sort so that `m[i,j]` in a define is
+        // evaluated after `m`, and `m`'s entries after the defines they use.
+        let scalar_count = entries.len();
+        for m in &defines.matrices {
+            entries.push(DefineEntry {
+                name: m.name.clone(),
+                expression: format!("{} {}", m.coldim, m.values),
+            });
+        }
 
         // Build known set (builtins)
         let known: HashSet<String> = self.context.values.keys().cloned().collect();
@@ -97,12 +142,12 @@ impl EvalEngine {
         // is applied unconditionally. So a quantity's kind comes from its unit
         // when the type is absent or unrecognised; gating purely on `type` left
         // `<quantity name="w" value="5" unit="cm"/>` unconverted, giving 5 mm
-        // where Geant4 gives 50.
+        // where Geant4 gives 50. The kind only decides which symbol set the
+        // quantity joins; the conversion itself is `units::apply_unit`.
         let quantity_kind = |q: &crate::gdml::model::Quantity| -> Option<units::UnitKind> {
             match q.r#type.as_deref() {
                 Some("length") => Some(units::UnitKind::Length),
                 Some("angle") => Some(units::UnitKind::Angle),
-                Some("density") => None,
                 _ => q.unit.as_deref().and_then(units::unit_kind),
             }
         };
@@ -122,6 +167,10 @@ impl EvalEngine {
 
         // Evaluate in order
         for idx in order {
+            if idx >= scalar_count {
+                self.define_matrix(&defines.matrices[idx - scalar_count]);
+                continue;
+            }
             let entry = &entries[idx];
             let refs = extract_identifiers(&entry.expression);
             let is_length_symbol = length_quantity_names.contains(entry.name.as_str())
@@ -153,22 +202,13 @@ impl EvalEngine {
                 }
             };
 
-            // Apply unit conversion for quantities
-            let final_value = if let Some(unit) = quantity_units.get(&entry.name) {
-                let qty = defines.quantities.iter().find(|q| q.name == entry.name);
-                if let Some(q) = qty {
-                    match quantity_kind(q) {
-                        Some(units::UnitKind::Length) => units::length_to_mm(value, unit),
-                        Some(units::UnitKind::Angle) => units::angle_to_rad(value, unit),
-                        // type="density" and anything with an unrecognised unit
-                        // are carried through as written.
-                        None => value,
-                    }
-                } else {
-                    value
-                }
-            } else {
-                value
+            // Apply unit conversion for quantities. Every unit in Geant4's
+            // table converts to internal units -- a `type="density"` quantity in
+            // g/cm3 included -- and anything unrecognised is carried through as
+            // written.
+            let final_value = match quantity_units.get(&entry.name) {
+                Some(unit) => units::apply_unit(value, unit),
+                None => value,
             };
 
             self.context.set(&entry.name, final_value);
@@ -204,6 +244,107 @@ impl EvalEngine {
         Ok(())
     }
 
+    /// `G4GDMLEvaluator::DefineMatrix`: evaluate every entry and define it as a
+    /// constant, `name_i` for a single column or a single row and `name_i_j`
+    /// otherwise.
+    ///
+    /// Geant4 stops with a fatal error on an empty, single-element or
+    /// unbalanced matrix; here that is a warning, and the ragged tail of an
+    /// unbalanced one is dropped.
+    fn define_matrix(&mut self, m: &Matrix) {
+        let coldim = self.resolve_value(&m.coldim).round();
+        if !coldim.is_finite() || coldim < 1.0 {
+            self.record_warning(format!(
+                "Matrix \"{}\" has coldim=\"{}\"; it must be at least 1. \
+                 Geant4 will not load this file.",
+                m.name, m.coldim
+            ));
+            return;
+        }
+        let coldim = coldim as usize;
+        let mut values: Vec<f64> = m.entries().map(|e| self.resolve_value(e)).collect();
+        if values.is_empty() {
+            self.record_warning(format!(
+                "Matrix \"{}\" is empty. Geant4 will not load this file.",
+                m.name
+            ));
+        } else if values.len() == 1 {
+            self.record_warning(format!(
+                "Matrix \"{}\" has only one element; Geant4 asks for a constant \
+                 instead and will not load this file.",
+                m.name
+            ));
+        } else if !values.len().is_multiple_of(coldim) {
+            self.record_warning(format!(
+                "Matrix \"{}\" has {} values, which is not a multiple of coldim={}. \
+                 Geant4 will not load this file.",
+                m.name,
+                values.len(),
+                coldim
+            ));
+            values.truncate(values.len() - values.len() % coldim);
+        }
+        let vector = is_vector(coldim, values.len());
+        for (k, v) in values.iter().enumerate() {
+            let element = if vector {
+                format!("{}_{}", m.name, k)
+            } else {
+                format!("{}_{}_{}", m.name, k / coldim, k % coldim)
+            };
+            self.context.set(&element, *v);
+        }
+        self.matrix_values
+            .insert(m.name.clone(), MatrixValues { coldim, values });
+    }
+
+    /// `G4GDMLEvaluator::SolveBrackets`: `Slice[i]` -> `Slice_<int(i) - 1>`,
+    /// and `m[i,j]` -> `m_<i-1>_<j-1>`.
+    ///
+    /// The `- 1` is Geant4's, not a typo: a name indexed from 1 in the file
+    /// becomes a suffix counting from 0. Used both for generated loop names and
+    /// for matrix element access inside expressions. An index that does not
+    /// evaluate, is not a finite number, or is too far below 1 to take 1 from
+    /// is an error.
+    pub fn solve_brackets(&self, name: &str) -> Result<String> {
+        if !name.contains('[') {
+            return Ok(name.to_string());
+        }
+        let mut out = String::with_capacity(name.len());
+        let mut rest = name;
+        while let Some(open) = rest.find('[') {
+            let Some(close_rel) = rest[open + 1..].find(']') else {
+                // Unbalanced: leave the remainder alone rather than mangle it.
+                out.push_str(rest);
+                return Ok(out);
+            };
+            let close = open + 1 + close_rel;
+            out.push_str(&rest[..open]);
+            for part in rest[open + 1..close].split(',') {
+                let index = part.trim();
+                // Not `resolve_value`: an index it cannot evaluate would become
+                // 0, and the element `_-1` would then be reported missing
+                // instead of the index.
+                let value = self
+                    .eval_expr(index)
+                    .with_context(|| {
+                        format!("index `{}` in `{}` cannot be evaluated", index, name)
+                    })?
+                    .round();
+                let idx = (value as i64)
+                    .checked_sub(1)
+                    .filter(|_| f64::is_finite(value))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("index `{}` in `{}` is not an element index", index, name)
+                    })?;
+                out.push('_');
+                out.push_str(&idx.to_string());
+            }
+            rest = &rest[close + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
     pub fn eval_expr(&self, expr: &str) -> Result<f64> {
         let expr = expr.trim();
         if expr.is_empty() {
@@ -220,12 +361,17 @@ impl EvalEngine {
             return Ok(v);
         }
 
-        // Build evalexpr context with all known values
+        // Matrix element access, `m[i,j]`, names the constant `m_<i-1>_<j-1>`.
+        let expr = &self.solve_brackets(expr)?;
+
+        // Build an evalexpr context from the identifiers the expression uses.
+        // The unit and constant tables alone are a few hundred entries, and
+        // copying all of them for every attribute of every solid is wasted work.
         let mut eval_context: HashMapContext = HashMapContext::new();
-        for (name, value) in &self.context.values {
-            eval_context
-                .set_value(name.clone(), Value::Float(*value))
-                .ok();
+        for name in extract_identifiers(expr) {
+            if let Some(value) = self.context.get(&name) {
+                eval_context.set_value(name, Value::Float(value)).ok();
+            }
         }
 
         // Rewrite GDML/CLHEP conventions for evalexpr:
@@ -484,7 +630,7 @@ fn rewrite_math_functions(expr: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::gdml::model::{Constant, DefineSection, Quantity};
+    use crate::gdml::model::{Constant, DefineSection, Matrix, Quantity};
 
     fn quantity(name: &str, value: &str, unit: Option<&str>, ty: Option<&str>) -> Quantity {
         Quantity {
@@ -537,6 +683,49 @@ mod tests {
         assert!((engine.eval_expr("degrees").unwrap() - std::f64::consts::PI).abs() < 1e-12);
     }
 
+    #[test]
+    fn clhep_material_units_and_constants_evaluate_as_in_geant4() {
+        // Densities, energies, temperatures and pressures used to be unknown
+        // identifiers, so these defines silently became 0.
+        let mut defines = DefineSection::default();
+        defines.constants.push(constant("rho", "1.032*g/cm3"));
+        defines.constants.push(constant("gap", "2.5*eV"));
+        defines.constants.push(constant("room", "293.15*kelvin"));
+        defines.constants.push(constant("p0", "1*atmosphere"));
+        defines
+            .quantities
+            .push(quantity("rho_q", "1.032", Some("g/cm3"), Some("density")));
+
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&defines).unwrap();
+        assert!(engine.take_warnings().is_empty());
+
+        let g_cm3 = units::apply_unit(1.0, "g/cm3");
+        let rho = engine.eval_expr("rho").unwrap();
+        assert!((rho / g_cm3 - 1.032).abs() < 1e-12);
+        assert!((engine.eval_expr("rho_q").unwrap() - rho).abs() <= 1e-9 * rho);
+        assert!((engine.eval_expr("gap").unwrap() - 2.5e-6).abs() < 1e-18);
+        assert_eq!(engine.eval_expr("room").unwrap(), 293.15);
+        assert_eq!(
+            engine.eval_expr("p0").unwrap(),
+            engine.eval_expr("STP_Pressure").unwrap()
+        );
+        assert!((engine.eval_expr("c_light").unwrap() - 299.792458).abs() < 1e-9);
+    }
+
+    #[test]
+    fn defines_shadow_builtin_units_of_the_same_name() {
+        // `L` is CLHEP's litre. A document defining its own `L` must have it
+        // evaluated before anything that refers to it.
+        let mut defines = DefineSection::default();
+        defines.constants.push(constant("half", "L/2"));
+        defines.constants.push(constant("L", "100"));
+
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&defines).unwrap();
+        assert_eq!(engine.eval_expr("half").unwrap(), 50.0);
+    }
+
     #[test]
     fn unevaluable_define_warns_instead_of_failing_the_document() {
         // One bad expression used to abort the whole load with a 500. The same
@@ -618,4 +807,96 @@ mod tests {
         // a variable that merely looks like a function name is left alone
         assert_eq!(rewrite_math_functions("sinphi*2"), "sinphi*2");
     }
+
+    #[test]
+    fn generated_matrix_entry_names_are_evaluated_after_the_matrix() {
+        // Constants sort before matrices unless something links them, and
+        // `M_1_1` is not the name of any define.
+        let mut defines = DefineSection::default();
+        defines.constants.push(constant("a", "M_1_1"));
+        defines.constants.push(constant("b", "M_0_1 + M[2,2]"));
+        defines.matrices.push(Matrix {
+            name: "M".to_string(),
+            coldim: "2".to_string(),
+            values: "1 2 3 4".to_string(),
+        });
+
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&defines).unwrap();
+        assert!(engine.take_warnings().is_empty());
+        assert_eq!(engine.eval_expr("a").unwrap(), 4.0);
+        assert_eq!(engine.eval_expr("b").unwrap(), 6.0);
+    }
+
+    fn matrix_engine(coldim: &str, values: &str) -> EvalEngine {
+        let mut defines = DefineSection::default();
+        defines.matrices.push(Matrix {
+            name: "M".to_string(),
+            coldim: coldim.to_string(),
+            values: values.to_string(),
+        });
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&defines).unwrap();
+        engine
+    }
+
+    #[test]
+    fn a_single_row_is_indexed_like_a_single_column() {
+        // 1x3: one row, so `M[2]`, not `M[1,2]`, as in Geant4.
+        let row = matrix_engine("3", "1 2 3");
+        assert!(row.take_warnings().is_empty());
+        assert!(row.matrix_values["M"].is_vector());
+        assert_eq!(row.eval_expr("M_1").unwrap(), 2.0);
+        assert_eq!(row.eval_expr("M[3]").unwrap(), 3.0);
+        assert_eq!(row.context.get("M_0_1"), None);
+
+        // 3x1
+        let column = matrix_engine("1", "1 2 3");
+        assert!(column.take_warnings().is_empty());
+        assert_eq!(column.eval_expr("M[2]").unwrap(), 2.0);
+        assert_eq!(column.context.get("M_0_0"), None);
+
+        // 1x1 is refused by Geant4, which wants a constant instead.
+        let single = matrix_engine("1", "7");
+        let warnings = single.take_warnings();
+        assert!(
+            warnings.iter().any(|w| w.contains("only one element")),
+            "{warnings:?}"
+        );
+    }
+
+    #[test]
+    fn a_coldim_that_is_not_a_number_is_refused() {
+        for coldim in ["sqrt(-1)", "0.0/0.0"] {
+            let engine = matrix_engine(coldim, "1 2 3 4");
+            let warnings = engine.take_warnings();
+            assert!(
+                warnings.iter().any(|w| w.contains("must be at least 1")),
+                "{coldim}: {warnings:?}"
+            );
+            assert!(!engine.matrix_values.contains_key("M"));
+        }
+    }
+
+    #[test]
+    fn an_index_that_cannot_be_counted_from_one_is_an_error() {
+        let engine = matrix_engine("2", "1 2 3 4");
+        for expr in ["M[-1e300]", "M[1, 0.0/0.0]"] {
+            let error = engine.eval_expr(expr).unwrap_err().to_string();
+            assert!(error.contains("not an element index"), "{expr}: {error}");
+        }
+        assert_eq!(engine.eval_expr("M[2,1]").unwrap(), 3.0);
+
+        // The index is quoted as written, not expanded to 300 digits.
+        let error = engine.eval_expr("M[-1e300]").unwrap_err().to_string();
+        assert!(error.contains("index `-1e300`"), "{error}");
+        assert!(error.len() < 100, "{error}");
+
+        // An undefined index is reported, not the element `M_-1` it would
+        // fall back to.
+        let error = format!("{:#}", engine.eval_expr("M[k]").unwrap_err());
+        assert!(error.contains("index `k` in `M[k]`"), "{error}");
+        assert!(!error.contains("M_-1"), "{error}");
+        assert!(engine.take_warnings().is_empty());
+    }
 }
@@ -1,3 +1,5 @@
 pub mod context;
 pub mod dependency;
+pub mod dimensions;
 pub mod engine;
+pub mod trace;
@@ -0,0 +1,446 @@
+//! Evaluate one expression against a loaded document and explain the result.
+//!
+//! `EvalEngine::resolve_value` turns anything it cannot evaluate into 0 with a
+//! one-line warning, and a define that fails is stored as 0 without a trace,
+//! so a solid that comes out flat can be several defines away from the typo
+//! behind it. [`trace_expression`] evaluates an arbitrary expression the way an
+//! attribute would be, and reports every identifier it touched, the defines it
+//! depends on transitively in evaluation order, and which of them failed.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::dependency::{extract_identifiers, topological_sort, DefineEntry};
+use super::dimensions::{Dimension, ExpressionDimensions};
+use super::engine::EvalEngine;
+use crate::gdml::model::*;
+use crate::gdml::units;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpressionTrace {
+    pub expression: String,
+    /// The expression after `m[i,j]` matrix access is rewritten to the
+    /// element's name, when it contains any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewritten: Option<String>,
+    /// In internal units (mm, rad, MeV, ns...); `None` when evaluation fails,
+    /// where an attribute would silently get 0.
+    pub value: Option<f64>,
+    /// Inferred dimension (`length`, `density`...), when it can be inferred.
+    pub dimension: Option<String>,
+    /// The internal unit `value` is expressed in; `None` for plain numbers.
+    pub unit: Option<String>,
+    pub identifiers: Vec<IdentifierUse>,
+    /// Every define the expression depends on, directly or through other
+    /// defines, in the order the evaluator computes them.
+    pub dependencies: Vec<DependencyStep>,
+    /// Why the expression, or a define it depends on, does not evaluate.
+    pub errors: Vec<String>,
+    /// Unit mismatches inside the expression, as the dimensions pass reports
+    /// them. They do not stop evaluation.
+    pub unit_issues: Vec<String>,
+}
+
+/// An identifier the expression refers to directly.
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentifierUse {
+    pub name: String,
+    /// `constant`, `quantity`, `variable`, `expression`, `matrix`, `matrix
+    /// element`, `unit`, `builtin` (a CLHEP physical constant) or `undefined`.
+    pub kind: &'static str,
+    pub value: Option<f64>,
+    pub dimension: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyStep {
+    pub name: String,
+    /// `constant`, `quantity`, `variable`, `expression` or `matrix`.
+    pub kind: &'static str,
+    pub expression: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// The value the evaluator stored; a matrix has none of its own.
+    pub value: Option<f64>,
+    pub dimension: Option<String>,
+}
+
+/// A define the evaluator knows by name, as it was entered for the sort.
+struct Define<'a> {
+    kind: &'static str,
+    expression: String,
+    unit: Option<&'a str>,
+}
+
+fn document_defines(defines: &DefineSection) -> Vec<(String, Define<'_>)> {
+    let mut out = Vec::new();
+    let scalar = |kind, expression: &String| Define {
+        kind,
+        expression: expression.clone(),
+        unit: None,
+    };
+    for c in &defines.constants {
+        out.push((c.name.clone(), scalar("constant", &c.value)));
+    }
+    for q in &defines.quantities {
+        out.push((
+            q.name.clone(),
+            Define {
+                kind: "quantity",
+                expression: q.value.clone(),
+                unit: q.unit.as_deref(),
+            },
+        ));
+    }
+    for v in &defines.variables {
+        out.push((v.name.clone(), scalar("variable", &v.value)));
+    }
+    for e in &defines.expressions {
+        out.push((e.name.clone(), scalar("expression", &e.value)));
+    }
+    // Entered as the evaluator enters them, so `m[i,j]` depends on `m` and
+    // `m` on whatever its coldim and entries use.
+    for m in &defines.matrices {
+        out.push((
+            m.name.clone(),
+            Define {
+                kind: "matrix",
+                expression: format!("{} {}", m.coldim, m.values),
+                unit: None,
+            },
+        ));
+    }
+    out
+}
+
+/// The matrix a generated element name (`m_0_1`, or `m_3` for a single row or
+/// column) belongs to, with its zero-based indices. The longest matching matrix name wins, since `a_b` and
+/// `a` can both be matrices.
+fn matrix_element<'a>(name: &str, matrices: &'a [Matrix]) -> Option<(&'a Matrix, Vec<usize>)> {
+    matrices
+        .iter()
+        .filter_map(|m| {
+            let suffix = name.strip_prefix(m.name.as_str())?.strip_prefix('_')?;
+            let indices: Option<Vec<usize>> = suffix.split('_').map(|p| p.parse().ok()).collect();
+            Some((m, indices?))
+        })
+        .max_by_key(|(m, _)| m.name.len())
+}
+
+/// Check that every `[..]` index in `expr` evaluates, so the trace can name
+/// the index at fault and say where the brackets are unbalanced.
+fn check_indices(engine: &EvalEngine, expr: &str) -> Result<(), String> {
+    let mut rest = expr;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|c| open + c) else {
+            return Err(format!("`[` at `{}` is never closed", &rest[open..]));
+        };
+        for part in rest[open + 1..close].split(',') {
+            if let Err(e) = engine.eval_expr(part) {
+                return Err(format!(
+                    "index `{}` in `{}` cannot be evaluated: {}",
+                    part.trim(),
+                    rest[..=close].trim(),
+                    e
+                ));
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+    Ok(())
+}
+
+/// Evaluate `expr` as an attribute would be, but with the reason instead of a
+/// 0 when it fails.
+fn evaluate(engine: &EvalEngine, expr: &str) -> Result<f64, String> {
+    check_indices(engine, expr)?;
+    engine.eval_expr(expr).map_err(|e| e.to_string())
+}
+
+/// Evaluate `expr` in the context of `doc`, whose defines `engine` holds, and
+/// explain the result.
+pub fn trace_expression(doc: &GdmlDocument, engine: &EvalEngine, expr: &str) -> ExpressionTrace {
+    let defines = document_defines(&doc.defines);
+    let index: HashMap<&str, usize> = defines
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.as_str(), i))
+        .collect();
+    let matrices = &doc.defines.matrices;
+    let mut dims = ExpressionDimensions::new(&doc.defines);
+    let describe = |d: Option<Dimension>| d.map(Dimension::describe);
+
+    let mut errors = Vec::new();
+    let value = match evaluate(engine, expr) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+    let resolved = if expr.contains('[') && check_indices(engine, expr).is_ok() {
+        engine.solve_brackets(expr).ok()
+    } else {
+        None
+    };
+    let (dimension, unit_issues) = dims.infer(resolved.as_deref().unwrap_or(expr));
+
+    // The identifiers as written (the matrix and its indices in `m[i,j]`) and
+    // as the evaluator sees them after bracket rewriting, with a define
+    // shadowing a unit or constant of the same name.
+    let mut seen = HashSet::new();
+    let mut identifiers = Vec::new();
+    let mut roots = Vec::new();
+    let names = extract_identifiers(expr)
+        .into_iter()
+        .chain(resolved.iter().flat_map(|r| extract_identifiers(r)));
+    for name in names {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let (kind, root) = if let Some(&i) = index.get(name.as_str()) {
+            (defines[i].1.kind, Some(i))
+        } else if let Some((m, indices)) = matrix_element(&name, matrices) {
+            let root = index.get(m.name.as_str()).copied();
+            if engine.context.get(&name).is_none() {
+                let rows = engine.matrix_values.get(&m.name);
+                errors.push(format!(
+                    "`{}` is {} of matrix \"{}\" (counting from 1), which has {}.",
+                    name,
+                    match indices.as_slice() {
+                        [r, c] => format!("row {}, column {}", r + 1, c + 1),
+                        _ => format!(
+                            "entry {}",
+                            indices.first().map(|i| i + 1).unwrap_or_default()
+                        ),
+                    },
+                    m.name,
+                    match rows {
+                        // Geant4 indexes a single row by entry, like a column.
+                        Some(v) if v.is_vector() => format!(
+                            "{} entries, named `{}_<i>` and read as `{}[i]`",
+                            v.values.len(),
+                            m.name,
+                            m.name
+                        ),
+                        Some(v) => format!("{} rows of {}", v.rows(), v.coldim),
+                        None => "no evaluated entries".to_string(),
+                    }
+                ));
+            }
+            ("matrix element", root)
+        } else if engine.context.get(&name).is_some() {
+            if units::unit_kind(&name).is_some() {
+                ("unit", None)
+            } else {
+                ("builtin", None)
+            }
+        } else {
+            errors.push(format!(
+                "`{}` is not a define, a matrix element, a unit or a constant. Geant4 \
+                 refuses the expression; this viewer evaluates it as 0.",
+                name
+            ));
+            ("undefined", None)
+        };
+        roots.extend(root);
+        let value = engine.context.get(&name);
+        let dimension = if kind == "undefined" || kind == "matrix element" {
+            None
+        } else {
+            describe(dims.infer(&name).0)
+        };
+        identifiers.push(IdentifierUse {
+            name,
+            kind,
+            value,
+            dimension,
+        });
+    }
+
+    // Everything reachable from the direct references.
+    let mut closure: Vec<usize> = Vec::new();
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut queue: VecDeque<usize> = roots.into_iter().collect();
+    while let Some(i) = queue.pop_front() {
+        if !visited.insert(i) {
+            continue;
+        }
+        closure.push(i);
+        for name in extract_identifiers(&defines[i].1.expression) {
+            let target = index.get(name.as_str()).copied().or_else(|| {
+                matrix_element(&name, matrices)
+                    .and_then(|(m, _)| index.get(m.name.as_str()).copied())
+            });
+            queue.extend(target.filter(|t| !visited.contains(t)));
+        }
+    }
+    closure.sort_unstable();
+    let entries: Vec<DefineEntry> = closure
+        .iter()
+        .map(|&i| DefineEntry {
+            name: defines[i].0.clone(),
+            expression: defines[i].1.expression.clone(),
+        })
+        .collect();
+    // The document loaded, so its defines have no cycle; the fallback only
+    // keeps a trace coming if that ever stops being true.
+    let order = topological_sort(&entries, &HashSet::new())
+        .unwrap_or_else(|_| (0..entries.len()).collect());
+
+    let mut dependencies = Vec::with_capacity(order.len());
+    for k in order {
+        let (name, define) = &defines[closure[k]];
+        if define.kind != "matrix" {
+            if let Err(e) = evaluate(engine, &define.expression) {
+                errors.push(format!(
+                    "{} \"{}\" = \"{}\" could not be evaluated and was treated as 0: {}",
+                    define.kind,
+                    name,
+                    define.expression.trim(),
+                    e
+                ));
+            }
+        }
+        dependencies.push(DependencyStep {
+            name: name.clone(),
+            kind: define.kind,
+            expression: define.expression.trim().to_string(),
+            unit: define.unit.map(str::to_string),
+            value: engine.context.get(name).filter(|_| define.kind != "matrix"),
+            dimension: if define.kind == "matrix" {
+                None
+            } else {
+                describe(dims.infer(name).0)
+            },
+        });
+    }
+
+    ExpressionTrace {
+        expression: expr.to_string(),
+        rewritten: resolved.filter(|r| r != expr),
+        value,
+        unit: dimension
+            .filter(|d| !d.is_dimensionless())
+            .map(Dimension::base_units),
+        dimension: describe(dimension),
+        identifiers,
+        dependencies,
+        errors,
+        unit_issues,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn trace(defines: &str, expr: &str) -> ExpressionTrace {
+        let xml = format!(
+            r#"<?xml version="1.0"?><gdml><define>{}</define><setup name="Default" version="1.0"><world ref="World"/></setup></gdml>"#,
+            defines
+        );
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "t.gdml".to_string()).unwrap();
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&doc.defines).unwrap();
+        trace_expression(&doc, &engine, expr)
+    }
+
+    #[test]
+    fn dependencies_are_transitive_and_in_evaluation_order() {
+        let t = trace(
+            r#"<constant name="half" value="w/2"/>
+               <quantity name="w" type="length" value="4" unit="cm"/>
+               <constant name="unused" value="7"/>"#,
+            "half + 1*mm",
+        );
+        assert_eq!(t.value, Some(21.0));
+        assert_eq!(t.dimension.as_deref(), Some("length"));
+        assert_eq!(t.unit.as_deref(), Some("mm"));
+        let names: Vec<&str> = t.dependencies.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["w", "half"]);
+        assert_eq!(t.dependencies[0].unit.as_deref(), Some("cm"));
+        let kinds: Vec<(&str, &str)> = t
+            .identifiers
+            .iter()
+            .map(|i| (i.name.as_str(), i.kind))
+            .collect();
+        assert_eq!(kinds, [("half", "constant"), ("mm", "unit")]);
+        assert!(t.errors.is_empty(), "{:?}", t.errors);
+    }
+
+    #[test]
+    fn silent_zeros_are_traced_to_their_cause() {
+        let t = trace(
+            r#"<constant name="r" value="rr*2"/><constant name="d" value="r+1"/>"#,
+            "d*cm",
+        );
+        // `d` evaluated, to 1, because `r` silently became 0.
+        assert_eq!(t.value, Some(10.0));
+        assert!(
+            t.errors
+                .iter()
+                .any(|e| e.starts_with("constant \"r\" = \"rr*2\" could not be evaluated")),
+            "{:?}",
+            t.errors
+        );
+
+        let t = trace("", "2*radius");
+        assert_eq!(t.value, None);
+        assert_eq!(t.identifiers[0].kind, "undefined");
+        assert!(t
+            .errors
+            .iter()
+            .any(|e| e.starts_with("`radius` is not a define")));
+    }
+
+    #[test]
+    fn matrix_access_is_rewritten_and_range_checked() {
+        let m = r#"<matrix name="m" coldim="2" values="1 2 3 4"/><constant name="i" value="2"/>"#;
+        let t = trace(m, "m[i,1]");
+        assert_eq!(t.value, Some(3.0));
+        assert_eq!(t.rewritten.as_deref(), Some("m_1_0"));
+        let names: Vec<&str> = t.dependencies.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["i", "m"]);
+
+        let t = trace(m, "m[3,1]");
+        assert_eq!(t.value, None);
+        assert!(
+            t.errors
+                .iter()
+                .any(|e| e.contains("row 3, column 1 of matrix \"m\"") && e.contains("2 rows of 2")),
+            "{:?}",
+            t.errors
+        );
+
+        let t = trace(m, "m[k,1]");
+        assert!(t.errors[0].starts_with("index `k` in `m[k,1]` cannot be evaluated"));
+    }
+
+    #[test]
+    fn a_single_row_matrix_is_read_by_entry() {
+        let m = r#"<matrix name="r" coldim="2" values="1.5 1.33"/>"#;
+        let t = trace(m, "r[2]");
+        assert_eq!(t.value, Some(1.33));
+        assert_eq!(t.rewritten.as_deref(), Some("r_1"));
+
+        let t = trace(m, "r[1,2]");
+        assert_eq!(t.value, None);
+        assert!(
+            t.errors
+                .iter()
+                .any(|e| e.contains("row 1, column 2 of matrix \"r\"")
+                    && e.contains("2 entries, named `r_<i>`")),
+            "{:?}",
+            t.errors
+        );
+    }
+
+    #[test]
+    fn unit_mismatches_are_reported_without_blocking_the_value() {
+        let t = trace("", "1*cm + 2*s");
+        assert!(t.value.is_some());
+        assert_eq!(t.unit_issues.len(), 1, "{:?}", t.unit_issues);
+    }
+}
@@ -0,0 +1,1208 @@
+//! Geant4's parameter checks for each solid.
+//!
+//! A solid can parse, evaluate and even tessellate here and still stop Geant4
+//! at construction: the constructors of `G4Tubs`, `G4Polycone`, `G4Trap` and
+//! the rest validate their arguments and raise a `FatalException` ("Invalid
+//! radii", "Side face is not planar", "Degenerate tetrahedron") on the first
+//! bad one. This module repeats those checks against the evaluated parameters
+//! so they show up before a simulation run does.
+//!
+//! Values are resolved the way the tessellator resolves them (`lunit`/`aunit`
+//! applied unless the expression already carries a unit) and then scaled the
+//! way `G4GDMLReadSolids` passes them on: full lengths in GDML are halved for
+//! the constructors that take half-lengths. The tolerance is Geant4's default
+//! `kCarTolerance` of 1e-9 mm. Parameters that do
+//! not evaluate are skipped; the load already reports them.
+//!
+//! Besides the fatal checks, a few parameters Geant4 accepts but silently
+//! changes (a `deltaphi` beyond a full turn, a `deltatheta` running past the
+//! south pole) are reported as warnings.
+
+use serde::Serialize;
+use std::f64::consts::PI;
+
+use super::model::*;
+use super::units;
+use crate::eval::engine::EvalEngine;
+
+const CAR_TOLERANCE: f64 = 1e-9;
+const TWO_PI: f64 = 2.0 * PI;
+/// A full turn is often written rounded up (`6.2832`, `360.001`); Geant4 caps
+/// it silently and so do we, only larger overshoots are reported.
+const ROUNDING: f64 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Geant4 raises a `FatalException` when constructing the solid.
+    Error,
+    /// Geant4 builds the solid, but not as written.
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SolidIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The issues of every solid in `solids` that has any, in document order.
+pub fn check_solids(solids: &SolidSection, engine: &EvalEngine) -> Vec<(String, Vec<SolidIssue>)> {
+    solids
+        .solids
+        .iter()
+        .map(|s| (s.name().to_string(), check_solid(s, engine)))
+        .filter(|(_, issues)| !issues.is_empty())
+        .collect()
+}
+
+/// [`check_solids`] as load warnings, one per issue.
+pub fn solid_warnings(solids: &SolidSection, engine: &EvalEngine) -> Vec<String> {
+    check_solids(solids, engine)
+        .into_iter()
+        .flat_map(|(name, issues)| {
+            issues.into_iter().map(move |i| match i.severity {
+                Severity::Error => format!(
+                    "Solid \"{}\": {}. Geant4 will abort when building it.",
+                    name, i.message
+                ),
+                Severity::Warning => format!("Solid \"{}\": {}.", name, i.message),
+            })
+        })
+        .collect()
+}
+
+/// What Geant4's constructor for `solid` would reject or silently change.
+/// Boolean, scaled, reflected and multi-union solids are checked through
+/// their operands, which are solids of their own.
+pub fn check_solid(solid: &Solid, engine: &EvalEngine) -> Vec<SolidIssue> {
+    let mut c = Checker {
+        engine,
+        lunit: "mm",
+        aunit: "rad",
+        out: Vec::new(),
+    };
+    match solid {
+        Solid::Box(s) => c.box_solid(s),
+        Solid::Tube(s) => c.tube(s),
+        Solid::CutTube(s) => c.cut_tube(s),
+        Solid::Cone(s) => c.cone(s),
+        Solid::Sphere(s) => c.sphere(s),
+        Solid::Orb(s) => c.orb(s),
+        Solid::Torus(s) => c.torus(s),
+        Solid::Trd(s) => c.trd(s),
+        Solid::Para(s) => c.para(s),
+        Solid::Trap(s) => c.trap(s),
+        Solid::Polycone(s) => c.polycone(&s.zplanes, &s.deltaphi, None, (&s.lunit, &s.aunit)),
+        Solid::Polyhedra(s) => c.polycone(
+            &s.zplanes,
+            &s.deltaphi,
+            Some(&s.numsides),
+            (&s.lunit, &s.aunit),
+        ),
+        Solid::GenericPolycone(s) => {
+            c.generic_polycone(&s.rzpoints, &s.deltaphi, None, (&s.lunit, &s.aunit))
+        }
+        Solid::GenericPolyhedra(s) => c.generic_polycone(
+            &s.rzpoints,
+            &s.deltaphi,
+            Some(&s.numsides),
+            (&s.lunit, &s.aunit),
+        ),
+        Solid::Xtru(s) => c.xtru(s),
+        Solid::Ellipsoid(s) => c.ellipsoid(s),
+        Solid::Eltube(s) => c.eltube(s),
+        Solid::Elcone(s) => c.elcone(s),
+        Solid::Paraboloid(s) => c.paraboloid(s),
+        Solid::Hype(s) => c.hype(s),
+        Solid::Tet(s) => c.tet(s),
+        Solid::Tessellated(s) => c.tessellated(s),
+        Solid::Arb8(s) => c.arb8(s),
+        Solid::TwistedBox(s) => {
+            c.units(&s.lunit, &s.aunit);
+            c.twist(&s.phi_twist);
+            c.half_lengths("G4TwistedBox", &[("x", &s.x), ("y", &s.y), ("z", &s.z)]);
+        }
+        Solid::TwistedTrd(s) => {
+            c.units(&s.lunit, &s.aunit);
+            c.twist(&s.phi_twist);
+            c.half_lengths(
+                "G4TwistedTrd",
+                &[
+                    ("x1", &s.x1),
+                    ("x2", &s.x2),
+                    ("y1", &s.y1),
+                    ("y2", &s.y2),
+                    ("z", &s.z),
+                ],
+            );
+        }
+        Solid::TwistedTrap(s) => {
+            c.units(&s.lunit, &s.aunit);
+            c.twist(&s.phi_twist);
+            c.half_lengths(
+                "G4TwistedTrap",
+                &[
+                    ("z", &s.z),
+                    ("y1", &s.y1),
+                    ("x1", &s.x1),
+                    ("x2", &s.x2),
+                    ("y2", &s.y2),
+                    ("x3", &s.x3),
+                    ("x4", &s.x4),
+                ],
+            );
+        }
+        Solid::TwistedTubs(s) => c.twisted_tubs(s),
+        Solid::Scaled(_) | Solid::Reflected(_) | Solid::MultiUnion(_) | Solid::Boolean(_) => {}
+    }
+    c.out
+}
+
+fn mm(v: f64) -> String {
+    format!("{} mm", round(v))
+}
+
+fn deg(v: f64) -> String {
+    format!("{}°", round(v.to_degrees()))
+}
+
+fn round(v: f64) -> f64 {
+    if v == 0.0 || !v.is_finite() {
+        return v;
+    }
+    let scale = 10f64.powi(6 - v.abs().log10().ceil() as i32);
+    (v * scale).round() / scale
+}
+
+struct Checker<'a> {
+    engine: &'a EvalEngine,
+    lunit: &'a str,
+    aunit: &'a str,
+    out: Vec<SolidIssue>,
+}
+
+impl<'a> Checker<'a> {
+    fn units(&mut self, lunit: &'a Option<String>, aunit: &'a Option<String>) {
+        self.lunit = lunit.as_deref().unwrap_or("mm");
+        self.aunit = aunit.as_deref().unwrap_or("rad");
+    }
+
+    fn error(&mut self, message: String) {
+        self.out.push(SolidIssue {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.out.push(SolidIssue {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    fn value(&self, expr: &str) -> Option<f64> {
+        self.engine.eval_expr(expr).ok().filter(|v| v.is_finite())
+    }
+
+    /// A length in mm, as `resolve_with_lunit` in the tessellator reads it.
+    fn len(&self, expr: &str) -> Option<f64> {
+        let v = self.value(expr)?;
+        if self.engine.expression_uses_length_symbols(expr) {
+            Some(v)
+        } else {
+            Some(v * units::length_factor(self.lunit)?)
+        }
+    }
+
+    fn opt_len(&self, expr: &Option<String>) -> Option<f64> {
+        expr.as_deref().map_or(Some(0.0), |e| self.len(e))
+    }
+
+    /// An angle in radians, as `resolve_with_aunit` reads it.
+    fn angle(&self, expr: &str) -> Option<f64> {
+        let v = self.value(expr)?;
+        if self.engine.expression_uses_angle_symbols(expr) {
+            Some(v)
+        } else {
+            Some(v * units::angle_factor(self.aunit)?)
+        }
+    }
+
+    fn opt_angle(&self, expr: &Option<String>, default: f64) -> Option<f64> {
+        expr.as_deref().map_or(Some(default), |e| self.angle(e))
+    }
+
+    /// `G4CSGSolid`'s `CheckDPhiAngle`, shared by tubs, cons, sphere, cut tube
+    /// and torus: a non-positive opening is fatal, one of a full turn or more
+    /// becomes exactly a full turn.
+    fn csg_dphi(&mut self, deltaphi: &Option<String>) {
+        let Some(dphi) = self.opt_angle(deltaphi, TWO_PI) else {
+            return;
+        };
+        if dphi <= 0.0 {
+            self.error(format!("deltaphi ({}) must be positive", deg(dphi)));
+        } else if dphi > TWO_PI * (1.0 + ROUNDING) {
+            self.warning(format!(
+                "deltaphi ({}) exceeds 360°; Geant4 makes it a full turn",
+                deg(dphi)
+            ));
+        }
+    }
+
+    /// `G4Polycone`/`G4Polyhedra`: any opening that is not in (0, 2π] is read
+    /// as a full turn rather than rejected.
+    fn polycone_dphi(&mut self, deltaphi: &Option<String>) {
+        let Some(dphi) = self.opt_angle(deltaphi, TWO_PI) else {
+            return;
+        };
+        if dphi <= 0.0 || dphi > TWO_PI * (1.0 + ROUNDING) {
+            self.warning(format!(
+                "deltaphi ({}) is outside (0°, 360°]; Geant4 makes it a full turn",
+                deg(dphi)
+            ));
+        }
+    }
+
+    /// Radii `rmin < rmax`, with `rmin >= 0`: the "Invalid radii" check of
+    /// `G4Tubs` and `G4CutTubs`.
+    fn radii(&mut self, rmin: f64, rmax: f64) {
+        if rmin < 0.0 {
+            self.error(format!("rmin ({}) is negative", mm(rmin)));
+        } else if rmin >= rmax {
+            self.error(format!(
+                "rmin ({}) must be less than rmax ({})",
+                mm(rmin),
+                mm(rmax)
+            ));
+        }
+    }
+
+    /// Dimensions Geant4 rejects as "too small or negative" below twice the
+    /// surface tolerance. `values` are the GDML attributes, full lengths.
+    fn half_lengths(&mut self, class: &str, values: &[(&str, &String)]) {
+        for (attr, expr) in values {
+            let Some(v) = self.len(expr) else { continue };
+            if v * 0.5 < 2.0 * CAR_TOLERANCE {
+                self.error(format!(
+                    "{} ({}) must be positive; {} rejects it as too small or negative",
+                    attr,
+                    mm(v),
+                    class
+                ));
+            }
+        }
+    }
+
+    fn twist(&mut self, phi_twist: &str) {
+        // `G4VTwistedFaceted`: "Invalid twist angle" unless |phi| < 90°.
+        if let Some(t) = self.angle(phi_twist) {
+            if t.abs() >= PI / 2.0 {
+                self.error(format!(
+                    "PhiTwist ({}) must be less than 90° in magnitude",
+                    deg(t)
+                ));
+            }
+        }
+    }
+
+    fn box_solid(&mut self, s: &'a BoxSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        self.half_lengths("G4Box", &[("x", &s.x), ("y", &s.y), ("z", &s.z)]);
+    }
+
+    fn tube(&mut self, s: &'a TubeSolid) {
+        self.units(&s.lunit, &s.aunit);
+        if let (Some(rmin), Some(rmax)) = (self.opt_len(&s.rmin), self.len(&s.rmax)) {
+            self.radii(rmin, rmax);
+        }
+        if let Some(z) = self.len(&s.z) {
+            if z <= 0.0 {
+                self.error(format!("z ({}) must be positive", mm(z)));
+            }
+        }
+        self.csg_dphi(&s.deltaphi);
+    }
+
+    fn cut_tube(&mut self, s: &'a CutTubeSolid) {
+        self.units(&s.lunit, &s.aunit);
+        let rmax = self.len(&s.rmax);
+        if let (Some(rmin), Some(rmax)) = (self.opt_len(&s.rmin), rmax) {
+            self.radii(rmin, rmax);
+        }
+        let dz = self.len(&s.z).map(|z| z * 0.5);
+        if let Some(dz) = dz.filter(|dz| *dz <= 0.0) {
+            self.error(format!("z ({}) must be positive", mm(2.0 * dz)));
+        }
+        self.csg_dphi(&s.deltaphi);
+
+        // The normals are direction cosines; units do not apply.
+        let normal = |x: &Option<String>, y: &Option<String>, z: &Option<String>| {
+            let c = |e: &Option<String>| e.as_deref().map_or(Some(0.0), |e| self.value(e));
+            Some([c(x)?, c(y)?, c(z)?])
+        };
+        let (Some(low), Some(high)) = (
+            normal(&s.low_x, &s.low_y, &s.low_z),
+            normal(&s.high_x, &s.high_y, &s.high_z),
+        ) else {
+            return;
+        };
+        // `G4CutTubs`: "Invalid low or high normal Z component".
+        if low[2] >= 0.0 || high[2] <= 0.0 {
+            self.error(format!(
+                "the low cut normal must point to -z and the high one to +z \
+                 (lowZ = {}, highZ = {})",
+                round(low[2]),
+                round(high[2])
+            ));
+            return;
+        }
+        // `G4CutTubs::IsCrossingCutPlanes`: the two planes may not meet
+        // inside the tube, checked around its outer edge.
+        let (Some(rmax), Some(dz)) = (rmax, dz) else {
+            return;
+        };
+        let startphi = self.opt_angle(&s.startphi, 0.0).unwrap_or(0.0);
+        let dphi = self.opt_angle(&s.deltaphi, TWO_PI).unwrap_or(TWO_PI);
+        let dphi = if dphi <= 0.0 || dphi > TWO_PI {
+            TWO_PI
+        } else {
+            dphi
+        };
+        let steps = 360;
+        let crosses = (0..=steps).any(|i| {
+            let phi = startphi + dphi * i as f64 / steps as f64;
+            let (x, y) = (rmax * phi.cos(), rmax * phi.sin());
+            let z_low = -dz - (low[0] * x + low[1] * y) / low[2];
+            let z_high = dz - (high[0] * x + high[1] * y) / high[2];
+            z_low >= z_high
+        });
+        if crosses {
+            self.error("the low and high cut planes cross inside the tube".to_string());
+        }
+    }
+
+    fn cone(&mut self, s: &'a ConeSolid) {
+        self.units(&s.lunit, &s.aunit);
+        let r = (
+            self.opt_len(&s.rmin1),
+            self.len(&s.rmax1),
+            self.opt_len(&s.rmin2),
+            self.len(&s.rmax2),
+        );
+        if let (Some(rmin1), Some(rmax1), Some(rmin2), Some(rmax2)) = r {
+            // `G4Cons`: "Invalid radii" -- only when an end is inverted and
+            // `rmin2` is negative as well; otherwise it builds what it can.
+            if (rmin1 >= rmax1 || rmin2 >= rmax2 || rmin1 < 0.0) && rmin2 < 0.0 {
+                self.error(format!(
+                    "invalid radii: rmin1 = {}, rmax1 = {}, rmin2 = {}, rmax2 = {}",
+                    mm(rmin1),
+                    mm(rmax1),
+                    mm(rmin2),
+                    mm(rmax2)
+                ));
+            } else {
+                for (end, rmin, rmax) in [(1, rmin1, rmax1), (2, rmin2, rmax2)] {
+                    if rmin >= rmax && rmax > 0.0 {
+                        self.warning(format!(
+                            "rmin{} ({}) is not less than rmax{} ({}); the cone's wall \
+                             is inverted at that end",
+                            end,
+                            mm(rmin),
+                            end,
+                            mm(rmax)
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(z) = self.len(&s.z) {
+            if z < 0.0 {
+                self.error(format!("z ({}) is negative", mm(z)));
+            }
+        }
+        self.csg_dphi(&s.deltaphi);
+    }
+
+    fn sphere(&mut self, s: &'a SphereSolid) {
+        self.units(&s.lunit, &s.aunit);
+        if let (Some(rmin), Some(rmax)) = (self.opt_len(&s.rmin), self.len(&s.rmax)) {
+            if rmin < 0.0 || rmin >= rmax || rmax < 1.1 * CAR_TOLERANCE {
+                self.error(format!(
+                    "invalid radii: rmin = {}, rmax = {}",
+                    mm(rmin),
+                    mm(rmax)
+                ));
+            }
+        }
+        self.csg_dphi(&s.deltaphi);
+        // `G4Sphere::CheckThetaAngles`.
+        let (Some(stheta), Some(dtheta)) = (
+            self.opt_angle(&s.starttheta, 0.0),
+            self.opt_angle(&s.deltatheta, PI),
+        ) else {
+            return;
+        };
+        if !(0.0..=PI).contains(&stheta) {
+            self.error(format!(
+                "starttheta ({}) must be between 0° and 180°",
+                deg(stheta)
+            ));
+        } else if dtheta <= 0.0 {
+            self.error(format!("deltatheta ({}) must be positive", deg(dtheta)));
+        } else if stheta + dtheta > PI * (1.0 + ROUNDING) {
+            self.warning(format!(
+                "starttheta + deltatheta ({}) runs past 180°; Geant4 stops at the pole",
+                deg(stheta + dtheta)
+            ));
+        }
+    }
+
+    fn orb(&mut self, s: &'a OrbSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        if let Some(r) = self.len(&s.r) {
+            if r < 10.0 * CAR_TOLERANCE {
+                self.error(format!("r ({}) must be positive", mm(r)));
+            }
+        }
+    }
+
+    fn torus(&mut self, s: &'a TorusSolid) {
+        self.units(&s.lunit, &s.aunit);
+        let (Some(rmin), Some(rmax), Some(rtor)) =
+            (self.opt_len(&s.rmin), self.len(&s.rmax), self.len(&s.rtor))
+        else {
+            return;
+        };
+        // `G4Torus::SetAllParameters`.
+        if rtor < rmax + 1e3 * CAR_TOLERANCE {
+            self.error(format!(
+                "rtor ({}) must exceed rmax ({}), or the tube crosses the axis",
+                mm(rtor),
+                mm(rmax)
+            ));
+        }
+        if rmin < 0.0 || rmin >= rmax - 1e2 * CAR_TOLERANCE {
+            self.error(format!(
+                "invalid radii: rmin = {}, rmax = {}",
+                mm(rmin),
+                mm(rmax)
+            ));
+        }
+        self.csg_dphi(&s.deltaphi);
+    }
+
+    fn trd(&mut self, s: &'a TrdSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        let v = [&s.x1, &s.x2, &s.y1, &s.y2, &s.z].map(|e| self.len(e).map(|v| v * 0.5));
+        let [Some(x1), Some(x2), Some(y1), Some(y2), Some(z)] = v else {
+            return;
+        };
+        // `G4Trd::CheckParameters`: an end may shrink to a line, not both.
+        let dmin = 2.0 * CAR_TOLERANCE;
+        if x1 < 0.0
+            || x2 < 0.0
+            || y1 < 0.0
+            || y2 < 0.0
+            || z < dmin
+            || (x1 < dmin && x2 < dmin)
+            || (y1 < dmin && y2 < dmin)
+        {
+            self.error(format!(
+                "dimensions are too small or negative: x1 = {}, x2 = {}, y1 = {}, y2 = {}, z = {}",
+                mm(2.0 * x1),
+                mm(2.0 * x2),
+                mm(2.0 * y1),
+                mm(2.0 * y2),
+                mm(2.0 * z)
+            ));
+        }
+    }
+
+    fn para(&mut self, s: &'a ParaSolid) {
+        self.units(&s.lunit, &s.aunit);
+        self.half_lengths("G4Para", &[("x", &s.x), ("y", &s.y), ("z", &s.z)]);
+    }
+
+    fn trap(&mut self, s: &'a TrapSolid) {
+        self.units(&s.lunit, &s.aunit);
+        let lengths = [&s.z, &s.y1, &s.x1, &s.x2, &s.y2, &s.x3, &s.x4];
+        let halves = lengths.map(|e| self.len(e).map(|v| v * 0.5));
+        let [Some(dz), Some(dy1), Some(dx1), Some(dx2), Some(dy2), Some(dx3), Some(dx4)] = halves
+        else {
+            return;
+        };
+        // `G4Trap::CheckParameters`: "Invalid Length Parameters".
+        let names = ["z", "y1", "x1", "x2", "y2", "x3", "x4"];
+        let bad: Vec<String> = names
+            .iter()
+            .zip([dz, dy1, dx1, dx2, dy2, dx3, dx4])
+            .filter(|(_, v)| *v <= 0.0)
+            .map(|(n, v)| format!("{} = {}", n, mm(2.0 * v)))
+            .collect();
+        if !bad.is_empty() {
+            self.error(format!("lengths must be positive: {}", bad.join(", ")));
+            return;
+        }
+        let angles = [&s.theta, &s.phi, &s.alpha1, &s.alpha2].map(|e| self.opt_angle(e, 0.0));
+        let [Some(theta), Some(phi), Some(alpha1), Some(alpha2)] = angles else {
+            return;
+        };
+
+        // `G4Trap::MakePlanes` builds the eight corners and then each side
+        // face through `MakePlane`, which is fatal when a corner lies more
+        // than 1000 * kCarTolerance off the plane of the other three.
+        let (tx, ty) = (theta.tan() * phi.cos(), theta.tan() * phi.sin());
+        let (ta1, ta2) = (alpha1.tan(), alpha2.tan());
+        let (zx, zy) = (dz * tx, dz * ty);
+        let pt = [
+            [-zx - dy1 * ta1 - dx1, -zy - dy1, -dz],
+            [-zx - dy1 * ta1 + dx1, -zy - dy1, -dz],
+            [-zx + dy1 * ta1 - dx2, -zy + dy1, -dz],
+            [-zx + dy1 * ta1 + dx2, -zy + dy1, -dz],
+            [zx - dy2 * ta2 - dx3, zy - dy2, dz],
+            [zx - dy2 * ta2 + dx3, zy - dy2, dz],
+            [zx + dy2 * ta2 - dx4, zy + dy2, dz],
+            [zx + dy2 * ta2 + dx4, zy + dy2, dz],
+        ];
+        for (side, [a, b, c, d]) in [("-x", [0, 4, 6, 2]), ("+x", [1, 3, 7, 5])] {
+            let off = plane_deviation(pt[a], pt[b], pt[c], pt[d]);
+            if off > 1000.0 * CAR_TOLERANCE {
+                self.error(format!(
+                    "the {} side face is not planar (a corner is {} off the plane); \
+                     x1/x2 and x3/x4 must change in the same ratio",
+                    side,
+                    mm(off)
+                ));
+            }
+        }
+    }
+
+    fn polycone(
+        &mut self,
+        zplanes: &'a [ZPlane],
+        deltaphi: &Option<String>,
+        numsides: Option<&str>,
+        (lunit, aunit): (&'a Option<String>, &'a Option<String>),
+    ) {
+        self.units(lunit, aunit);
+        self.polycone_dphi(deltaphi);
+        if let Some(n) = numsides {
+            self.numsides(n);
+        }
+        if zplanes.len() < 2 {
+            self.error(format!(
+                "{} z-plane(s); at least 2 are needed",
+                zplanes.len()
+            ));
+            return;
+        }
+        let planes: Option<Vec<(f64, f64, f64)>> = zplanes
+            .iter()
+            .map(|p| Some((self.opt_len(&p.rmin)?, self.len(&p.rmax)?, self.len(&p.z)?)))
+            .collect();
+        let Some(planes) = planes else { return };
+        for (i, (rmin, rmax, z)) in planes.iter().enumerate() {
+            // G4Polycone: "Cannot create a Polycone with rInner > rOuter".
+            if rmin > rmax {
+                self.error(format!(
+                    "z-plane {} (z = {}) has rmin ({}) greater than rmax ({})",
+                    i + 1,
+                    mm(*z),
+                    mm(*rmin),
+                    mm(*rmax)
+                ));
+            }
+            if *rmin < 0.0 || *rmax < 0.0 {
+                self.error(format!("z-plane {} has a negative radius", i + 1));
+            }
+        }
+        for (i, pair) in planes.windows(2).enumerate() {
+            let ((rmin_a, rmax_a, za), (rmin_b, rmax_b, zb)) = (pair[0], pair[1]);
+            // Two planes at one z form a step; its rings have to overlap.
+            if za == zb && (rmin_a > rmax_b || rmin_b > rmax_a) {
+                self.error(format!(
+                    "z-planes {} and {} share z = {} but their rings do not touch, so \
+                     the segments are not contiguous",
+                    i + 1,
+                    i + 2,
+                    mm(za)
+                ));
+            }
+        }
+        // The planes become one r-z outline, which crosses itself as soon as
+        // z turns back ("Supplied r,z coordinates cross").
+        let rising = planes[planes.len() - 1].2 >= planes[0].2;
+        if let Some(i) = planes.windows(2).position(|p| {
+            if rising {
+                p[1].2 < p[0].2
+            } else {
+                p[1].2 > p[0].2
+            }
+        }) {
+            self.error(format!(
+                "z-planes are not in order: z = {} follows z = {} (plane {})",
+                mm(planes[i + 1].2),
+                mm(planes[i].2),
+                i + 2
+            ));
+        }
+    }
+
+    fn numsides(&mut self, numsides: &str) {
+        if let Some(n) = self.value(numsides) {
+            if n < 1.0 {
+                self.error(format!("numsides ({}) must be at least 1", round(n)));
+            }
+        }
+    }
+
+    fn generic_polycone(
+        &mut self,
+        points: &'a [RZPoint],
+        deltaphi: &Option<String>,
+        numsides: Option<&str>,
+        (lunit, aunit): (&'a Option<String>, &'a Option<String>),
+    ) {
+        self.units(lunit, aunit);
+        self.polycone_dphi(deltaphi);
+        if let Some(n) = numsides {
+            self.numsides(n);
+        }
+        let rz: Option<Vec<(f64, f64)>> = points
+            .iter()
+            .map(|p| Some((self.len(&p.r)?, self.len(&p.z)?)))
+            .collect();
+        let Some(mut rz) = rz else { return };
+        // `G4ReduciblePolygon` checks, in the order the constructor runs them.
+        if let Some((r, z)) = rz.iter().find(|(r, _)| *r < 0.0) {
+            self.error(format!(
+                "r must not be negative (r = {} at z = {})",
+                mm(*r),
+                mm(*z)
+            ));
+            return;
+        }
+        rz.dedup_by(|a, b| (a.0 - b.0).hypot(a.1 - b.1) <= CAR_TOLERANCE);
+        if rz.len() > 1
+            && (rz[0].0 - rz[rz.len() - 1].0).hypot(rz[0].1 - rz[rz.len() - 1].1) <= CAR_TOLERANCE
+        {
+            rz.pop();
+        }
+        if rz.len() < 3 {
+            self.error(format!(
+                "only {} distinct r-z point(s); at least 3 are needed",
+                rz.len()
+            ));
+        } else if let Some((i, j)) = self_intersection(&rz) {
+            self.error(format!(
+                "the r-z outline crosses itself (edges {} and {})",
+                i + 1,
+                j + 1
+            ));
+        }
+    }
+
+    fn xtru(&mut self, s: &'a XtruSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        // `G4ExtrudedSolid`'s constructor checks.
+        if s.vertices.len() < 3 {
+            self.error(format!(
+                "{} polygon vertices; at least 3 are needed",
+                s.vertices.len()
+            ));
+        }
+        if s.sections.len() < 2 {
+            self.error(format!(
+                "{} section(s); at least 2 are needed",
+                s.sections.len()
+            ));
+        }
+        let zs: Option<Vec<f64>> = s
+            .sections
+            .iter()
+            .map(|sec| self.len(&sec.z_position))
+            .collect();
+        if let Some(zs) = zs {
+            for (i, pair) in zs.windows(2).enumerate() {
+                if pair[1] < pair[0] {
+                    self.error(format!(
+                        "sections must be ordered by z: section {} (z = {}) follows z = {}",
+                        i + 2,
+                        mm(pair[1]),
+                        mm(pair[0])
+                    ));
+                } else if pair[1] - pair[0] < CAR_TOLERANCE * 0.5 {
+                    self.error(format!(
+                        "sections {} and {} share z = {}",
+                        i + 1,
+                        i + 2,
+                        mm(pair[0])
+                    ));
+                }
+            }
+        }
+        for (i, sec) in s.sections.iter().enumerate() {
+            if let Some(f) = self.value(&sec.scaling_factor) {
+                if f <= 0.0 {
+                    self.error(format!(
+                        "section {} has scalingFactor {}; it must be positive",
+                        i + 1,
+                        round(f)
+                    ));
+                }
+            }
+        }
+        let polygon: Option<Vec<(f64, f64)>> = s
+            .vertices
+            .iter()
+            .map(|v| Some((self.len(&v.x)?, self.len(&v.y)?)))
+            .collect();
+        if let Some(polygon) = polygon.filter(|p| p.len() >= 3) {
+            // Triangulating a crossing polygon fails ("Making facets failed").
+            if let Some((i, j)) = self_intersection(&polygon) {
+                self.error(format!(
+                    "the polygon crosses itself (edges {} and {})",
+                    i + 1,
+                    j + 1
+                ));
+            } else if polygon_area(&polygon).abs() < CAR_TOLERANCE {
+                self.error("the polygon has no area".to_string());
+            }
+        }
+    }
+
+    fn ellipsoid(&mut self, s: &'a EllipsoidSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        let axes = [("ax", &s.ax), ("by", &s.by), ("cz", &s.cz)];
+        for (attr, expr) in axes {
+            if let Some(v) = self.len(expr) {
+                if v < 2.0 * CAR_TOLERANCE {
+                    self.error(format!("{} ({}) must be positive", attr, mm(v)));
+                }
+            }
+        }
+        // `G4Ellipsoid::CheckParameters`: a cut of 0 means none, and the
+        // bottom one must stay below the top one.
+        let (Some(cz), Some(z1), Some(z2)) = (
+            self.len(&s.cz),
+            self.opt_len(&s.zcut1),
+            self.opt_len(&s.zcut2),
+        ) else {
+            return;
+        };
+        let bottom = if z1 == 0.0 { -cz } else { z1.max(-cz) };
+        let top = if z2 == 0.0 { cz } else { z2.min(cz) };
+        if bottom >= top {
+            self.error(format!(
+                "the z cuts leave nothing: zcut1 = {}, zcut2 = {}",
+                mm(z1),
+                mm(z2)
+            ));
+        }
+    }
+
+    fn eltube(&mut self, s: &'a EltubeSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        for (attr, expr) in [("dx", &s.dx), ("dy", &s.dy), ("dz", &s.dz)] {
+            if let Some(v) = self.len(expr) {
+                if v < 2.0 * CAR_TOLERANCE {
+                    self.error(format!("{} ({}) must be positive", attr, mm(v)));
+                }
+            }
+        }
+    }
+
+    fn elcone(&mut self, s: &'a ElconeSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        // dx and dy are slopes, not lengths.
+        for (attr, expr) in [("dx", &s.dx), ("dy", &s.dy)] {
+            if let Some(v) = self.value(expr) {
+                if v <= 0.0 {
+                    self.error(format!("{} ({}) must be positive", attr, round(v)));
+                }
+            }
+        }
+        if let Some(zmax) = self.len(&s.zmax) {
+            if zmax <= 0.0 {
+                self.error(format!("zmax ({}) must be positive", mm(zmax)));
+            }
+        }
+        if let Some(zcut) = self.len(&s.zcut) {
+            if zcut <= 0.0 {
+                self.error(format!("zcut ({}) must be positive", mm(zcut)));
+            }
+        }
+    }
+
+    fn paraboloid(&mut self, s: &'a ParaboloidSolid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        let (Some(rlo), Some(rhi), Some(dz)) =
+            (self.len(&s.rlo), self.len(&s.rhi), self.len(&s.dz))
+        else {
+            return;
+        };
+        if dz <= 0.0 || rhi <= rlo || rlo < 0.0 {
+            self.error(format!(
+                "needs dz > 0 and 0 <= rlo < rhi: rlo = {}, rhi = {}, dz = {}",
+                mm(rlo),
+                mm(rhi),
+                mm(dz)
+            ));
+        }
+    }
+
+    fn hype(&mut self, s: &'a HypeSolid) {
+        self.units(&s.lunit, &s.aunit);
+        let (Some(rmin), Some(rmax), Some(z)) =
+            (self.opt_len(&s.rmin), self.len(&s.rmax), self.len(&s.z))
+        else {
+            return;
+        };
+        let dz = z * 0.5;
+        if dz <= 0.0 {
+            self.error(format!("z ({}) must be positive", mm(z)));
+        }
+        if rmin < 0.0 || rmax < 0.0 {
+            self.error(format!(
+                "radii must not be negative: rmin = {}, rmax = {}",
+                mm(rmin),
+                mm(rmax)
+            ));
+            return;
+        }
+        if rmin >= rmax {
+            self.error(format!(
+                "rmin ({}) must be less than rmax ({})",
+                mm(rmin),
+                mm(rmax)
+            ));
+            return;
+        }
+        let (Some(inst), Some(outst)) =
+            (self.opt_angle(&s.inst, 0.0), self.opt_angle(&s.outst, 0.0))
+        else {
+            return;
+        };
+        let end_in = rmin * rmin + (inst.tan() * dz).powi(2);
+        let end_out = rmax * rmax + (outst.tan() * dz).powi(2);
+        if end_in > end_out {
+            self.error(format!(
+                "the inner surface ({} at the ends) passes outside the outer one ({})",
+                mm(end_in.sqrt()),
+                mm(end_out.sqrt())
+            ));
+        }
+    }
+
+    fn tet(&mut self, s: &'a TetSolid) {
+        let p: Option<Vec<[f64; 3]>> = [&s.vertex1, &s.vertex2, &s.vertex3, &s.vertex4]
+            .iter()
+            .map(|name| self.engine.position_values.get(name.as_str()).copied())
+            .collect();
+        let Some(p) = p else { return };
+        // `G4Tet::CheckDegeneracy`: the height over the largest face must be
+        // at least 4 * kCarTolerance.
+        let (e1, e2, e3) = (sub(p[1], p[0]), sub(p[2], p[0]), sub(p[3], p[0]));
+        let vol = dot(cross(e1, e2), e3);
+        let faces = [
+            cross(e1, e2),
+            cross(e2, e3),
+            cross(e3, e1),
+            cross(sub(p[2], p[1]), sub(p[3], p[1])),
+        ];
+        let max_area2 = faces.iter().map(|n| dot(*n, *n)).fold(0.0, f64::max);
+        let hmin = 4.0 * CAR_TOLERANCE;
+        if vol * vol <= max_area2 * hmin * hmin {
+            self.error(format!(
+                "the four vertices are coplanar (volume {} mm3), a degenerate tetrahedron",
+                round(vol.abs() / 6.0)
+            ));
+        }
+    }
+
+    fn tessellated(&mut self, s: &'a TessellatedSolid) {
+        let mut degenerate = Vec::new();
+        for (i, facet) in s.facets.iter().enumerate() {
+            let (names, relative) = match facet {
+                TessellatedFacet::Triangular {
+                    vertex1,
+                    vertex2,
+                    vertex3,
+                    r#type,
+                } => (vec![vertex1, vertex2, vertex3], r#type),
+                TessellatedFacet::Quadrangular {
+                    vertex1,
+                    vertex2,
+                    vertex3,
+                    vertex4,
+                    r#type,
+                } => (vec![vertex1, vertex2, vertex3, vertex4], r#type),
+            };
+            let p: Option<Vec<[f64; 3]>> = names
+                .iter()
+                .map(|n| self.engine.position_values.get(n.as_str()).copied())
+                .collect();
+            let Some(mut p) = p else { continue };
+            if relative.as_deref() == Some("RELATIVE") {
+                for k in 1..p.len() {
+                    p[k] = add(p[0], p[k]);
+                }
+            }
+            // `G4TriangularFacet`: a facet whose area is below tolerance is
+            // "not defined" and refused.
+            let n = if p.len() == 3 {
+                cross(sub(p[1], p[0]), sub(p[2], p[0]))
+            } else {
+                cross(sub(p[2], p[0]), sub(p[3], p[1]))
+            };
+            if dot(n, n).sqrt() * 0.5 < CAR_TOLERANCE {
+                degenerate.push(i + 1);
+            }
+        }
+        if !degenerate.is_empty() {
+            let shown: Vec<String> = degenerate.iter().take(10).map(|i| i.to_string()).collect();
+            self.error(format!(
+                "{} facet(s) have no area (facet {}{})",
+                degenerate.len(),
+                shown.join(", "),
+                if degenerate.len() > shown.len() {
+                    ", ..."
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+
+    fn arb8(&mut self, s: &'a Arb8Solid) {
+        self.lunit = s.lunit.as_deref().unwrap_or("mm");
+        if let Some(dz) = self.len(&s.dz) {
+            if dz < CAR_TOLERANCE {
+                self.error(format!("dz ({}) must be positive", mm(dz)));
+            }
+        }
+        let coords = [
+            [&s.v1x, &s.v1y],
+            [&s.v2x, &s.v2y],
+            [&s.v3x, &s.v3y],
+            [&s.v4x, &s.v4y],
+            [&s.v5x, &s.v5y],
+            [&s.v6x, &s.v6y],
+            [&s.v7x, &s.v7y],
+            [&s.v8x, &s.v8y],
+        ];
+        let v: Option<Vec<(f64, f64)>> = coords
+            .iter()
+            .map(|[x, y]| Some((self.len(x)?, self.len(y)?)))
+            .collect();
+        let Some(v) = v else { return };
+        // `G4GenericTrap::CheckOrder`: "Malformed polygon with crossing
+        // edges". Collapsed corners are allowed, so repeats are dropped first.
+        for (face, range) in [("-dz", 0..4), ("+dz", 4..8)] {
+            let mut quad: Vec<(f64, f64)> = v[range].to_vec();
+            quad.dedup_by(|a, b| (a.0 - b.0).hypot(a.1 - b.1) <= CAR_TOLERANCE);
+            if quad.len() == 4 && self_intersection(&quad).is_some() {
+                self.error(format!("the vertices of the {} face cross", face));
+            }
+        }
+    }
+
+    fn twisted_tubs(&mut self, s: &'a TwistedTubsSolid) {
+        self.units(&s.lunit, &s.aunit);
+        // `G4TwistedTubs`: "Invalid end-inner-radius!".
+        let zlen = self.opt_len(&s.zlen).unwrap_or(0.0);
+        let (attr, inner) = if zlen != 0.0 {
+            ("endinnerrad", &s.endinnerrad)
+        } else {
+            ("midinnerrad", &s.midinnerrad)
+        };
+        if let Some(r) = self.opt_len(inner) {
+            if r < f64::MIN_POSITIVE {
+                self.error(format!("{} ({}) must be positive", attr, mm(r)));
+            }
+        }
+        if let Some(nseg) = s.nseg.as_deref().and_then(|e| self.value(e)) {
+            if s.totphi.is_some() && nseg < 1.0 {
+                self.error(format!("nseg ({}) must be at least 1", round(nseg)));
+            }
+        }
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// How far the farthest of four corners lies from the plane `G4Trap::MakePlane`
+/// fits through them: normal from the two diagonals, through their centroid.
+fn plane_deviation(p1: [f64; 3], p2: [f64; 3], p3: [f64; 3], p4: [f64; 3]) -> f64 {
+    let n = cross(sub(p4, p2), sub(p1, p3));
+    let len = dot(n, n).sqrt();
+    if len == 0.0 {
+        return 0.0;
+    }
+    let n = [n[0] / len, n[1] / len, n[2] / len];
+    let c = [
+        (p1[0] + p2[0] + p3[0] + p4[0]) / 4.0,
+        (p1[1] + p2[1] + p3[1] + p4[1]) / 4.0,
+        (p1[2] + p2[2] + p3[2] + p4[2]) / 4.0,
+    ];
+    [p1, p2, p3, p4]
+        .iter()
+        .map(|p| dot(n, sub(*p, c)).abs())
+        .fold(0.0, f64::max)
+}
+
+fn polygon_area(p: &[(f64, f64)]) -> f64 {
+    let n = p.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (p[i], p[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f64>()
+        * 0.5
+}
+
+/// The first pair of non-adjacent edges of the closed polygon `p` that
+/// intersect, by index of their first vertex.
+fn self_intersection(p: &[(f64, f64)]) -> Option<(usize, usize)> {
+    let n = p.len();
+    let orient = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+        (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+    };
+    let on_segment = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+        c.0 >= a.0.min(b.0) && c.0 <= a.0.max(b.0) && c.1 >= a.1.min(b.1) && c.1 <= a.1.max(b.1)
+    };
+    for i in 0..n {
+        for j in i + 1..n {
+            // Edges sharing a vertex always touch there.
+            if j == i + 1 || (i == 0 && j == n - 1) {
+                continue;
+            }
+            let (a, b) = (p[i], p[(i + 1) % n]);
+            let (c, d) = (p[j], p[(j + 1) % n]);
+            let (o1, o2) = (orient(a, b, c), orient(a, b, d));
+            let (o3, o4) = (orient(c, d, a), orient(c, d, b));
+            let proper = o1 * o2 < 0.0 && o3 * o4 < 0.0;
+            let touching = (o1 == 0.0 && on_segment(a, b, c))
+                || (o2 == 0.0 && on_segment(a, b, d))
+                || (o3 == 0.0 && on_segment(c, d, a))
+                || (o4 == 0.0 && on_segment(c, d, b));
+            if proper || touching {
+                return Some((i, j));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn issues(solids: &str) -> Vec<(String, Vec<SolidIssue>)> {
+        let src = format!(
+            r#"<?xml version="1.0"?>
+<gdml>
+  <define>
+    <position name="a" x="0" y="0" z="0"/>
+    <position name="b" x="1" y="0" z="0"/>
+    <position name="c" x="0" y="1" z="0"/>
+    <position name="d" x="1" y="1" z="0"/>
+  </define>
+  <solids>{}</solids>
+  <structure/>
+  <setup name="Default" version="1.0"><world ref="W"/></setup>
+</gdml>"#,
+            solids
+        );
+        let doc = parse_gdml_from_bytes(src.as_bytes(), "t.gdml".to_string()).unwrap();
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&doc.defines).unwrap();
+        check_solids(&doc.solids, &engine)
+    }
+
+    fn messages(solids: &str) -> Vec<String> {
+        issues(solids)
+            .into_iter()
+            .flat_map(|(name, list)| {
+                list.into_iter()
+                    .map(move |i| format!("{} {:?}: {}", name, i.severity, i.message))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn csg_radii_and_angles() {
+        let m = messages(
+            r#"
+    <tube name="ok" rmin="1" rmax="2" z="4" deltaphi="360" aunit="deg"/>
+    <tube name="inverted" rmin="3" rmax="2" z="4" deltaphi="360" aunit="deg"/>
+    <tube name="wide" rmax="2" z="4" deltaphi="400" aunit="deg"/>
+    <sphere name="pole" rmax="5" deltaphi="360" starttheta="90" deltatheta="120" aunit="deg"/>
+    <torus name="axis" rmax="5" rtor="4" deltaphi="360" aunit="deg"/>
+    <twistedbox name="twist" PhiTwist="90" x="1" y="1" z="1" aunit="deg"/>"#,
+        );
+        assert_eq!(
+            m,
+            [
+                "inverted Error: rmin (3 mm) must be less than rmax (2 mm)",
+                "wide Warning: deltaphi (400°) exceeds 360°; Geant4 makes it a full turn",
+                "pole Warning: starttheta + deltatheta (210°) runs past 180°; Geant4 stops at the pole",
+                "axis Error: rtor (4 mm) must exceed rmax (5 mm), or the tube crosses the axis",
+                "twist Error: PhiTwist (90°) must be less than 90° in magnitude",
+            ]
+        );
+    }
+
+    #[test]
+    fn polycone_trap_tet_and_xtru_shapes() {
+        let m = messages(
+            r#"
+    <polycone name="zigzag" deltaphi="6.2832">
+      <zplane rmax="1" z="0"/><zplane rmax="1" z="5"/><zplane rmax="1" z="2"/>
+    </polycone>
+    <trap name="regular" z="2" y1="1" x1="1" x2="1" y2="1" x3="1" x4="1"/>
+    <trap name="warped" z="2" y1="1" x1="1" x2="2" y2="1" x3="1" x4="1"/>
+    <tet name="flat" vertex1="a" vertex2="b" vertex3="c" vertex4="d"/>
+    <xtru name="bowtie">
+      <twoDimVertex x="0" y="0"/><twoDimVertex x="1" y="1"/>
+      <twoDimVertex x="1" y="0"/><twoDimVertex x="0" y="1"/>
+      <section zOrder="0" zPosition="0" xOffset="0" yOffset="0" scalingFactor="1"/>
+      <section zOrder="1" zPosition="1" xOffset="0" yOffset="0" scalingFactor="1"/>
+    </xtru>"#,
+        );
+        assert_eq!(m.len(), 5, "{:#?}", m);
+        assert_eq!(
+            m[0],
+            "zigzag Error: z-planes are not in order: z = 2 mm follows z = 5 mm (plane 3)"
+        );
+        assert!(m[1].starts_with("warped Error: the -x side face is not planar"));
+        assert!(m[2].starts_with("warped Error: the +x side face is not planar"));
+        assert!(m[3].starts_with("flat Error: the four vertices are coplanar"));
+        assert_eq!(
+            m[4],
+            "bowtie Error: the polygon crosses itself (edges 1 and 3)"
+        );
+    }
+}
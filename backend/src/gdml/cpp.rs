@@ -0,0 +1,149 @@
+//! What the generators that write C++ -- the Geant4 `DetectorConstruction`
+//! and the ROOT macro -- have in common: the function body they append to,
+//! the identifiers declared in it, and generating each material and solid
+//! once, on first use, in dependency order.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::materials::find_nist_material;
+use super::model::{GdmlDocument, Material, Solid};
+
+/// The statements of the generated function and the identifiers they
+/// declare.
+#[derive(Default)]
+pub(super) struct FunctionBody {
+    pub text: String,
+    identifiers: HashSet<String>,
+}
+
+impl FunctionBody {
+    /// One statement, indented into the function; an empty one leaves a
+    /// blank line.
+    pub fn line(&mut self, text: &str) {
+        if text.is_empty() {
+            self.text.push('\n');
+        } else {
+            let _ = writeln!(self.text, "  {}", text);
+        }
+    }
+
+    /// A comment heading the statements that follow, after a blank line.
+    pub fn section(&mut self, title: &str) {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.line(&format!("// {}", title));
+    }
+
+    /// A fresh C++ identifier for `name`: non-identifier characters become
+    /// `_`, and a clash with an earlier one gets a numeric suffix.
+    pub fn identifier(&mut self, prefix: &str, name: &str) -> String {
+        let mut base = String::from(prefix);
+        for c in name.chars() {
+            let c = if c.is_ascii_alphanumeric() { c } else { '_' };
+            if !(c == '_' && base.ends_with('_')) {
+                base.push(c);
+            }
+        }
+        while base.ends_with('_') && base.len() > prefix.len() {
+            base.pop();
+        }
+        let mut candidate = base.clone();
+        let mut n = 2;
+        while !self.identifiers.insert(candidate.clone()) {
+            candidate = format!("{}_{}", base, n);
+            n += 1;
+        }
+        candidate
+    }
+}
+
+/// A generator writing one C++ function that builds the document. It says
+/// how a single material or solid is written; walking the references and
+/// remembering what was already written is shared.
+pub(super) trait Emitter<'a> {
+    fn doc(&self) -> &'a GdmlDocument;
+    fn body(&mut self) -> &mut FunctionBody;
+    fn warnings(&mut self) -> &mut Vec<String>;
+    /// Document name → C++ variable, `None` once it failed.
+    fn materials(&mut self) -> &mut HashMap<String, Option<String>>;
+    fn solids(&mut self) -> &mut HashMap<String, Option<String>>;
+
+    fn define_material(&mut self, m: &'a Material) -> Option<String>;
+    /// A material the document references but does not define.
+    fn undefined_material(&mut self, name: &str) -> Option<String>;
+    fn define_solid(&mut self, solid: &'a Solid) -> Option<String>;
+
+    fn line(&mut self, text: &str) {
+        self.body().line(text);
+    }
+
+    fn section(&mut self, title: &str) {
+        self.body().section(title);
+    }
+
+    fn identifier(&mut self, prefix: &str, name: &str) -> String {
+        self.body().identifier(prefix, name)
+    }
+
+    /// The variable holding material `name`, generating it (and the materials
+    /// it is mixed from) on first use.
+    fn material(&mut self, name: &str) -> Option<String> {
+        if let Some(done) = self.materials().get(name) {
+            return done.clone();
+        }
+        // Marked failed while in progress, so a material that contains itself
+        // stops here instead of recursing.
+        self.materials().insert(name.to_string(), None);
+        let doc = self.doc();
+        let id = match doc.materials.materials.iter().find(|m| m.name == name) {
+            Some(m) => self.define_material(m),
+            None => self.undefined_material(name),
+        };
+        self.materials().insert(name.to_string(), id.clone());
+        id
+    }
+
+    /// The variable holding solid `name`, generating it (and its operands) on
+    /// first use.
+    fn solid(&mut self, name: &str) -> Option<String> {
+        if let Some(done) = self.solids().get(name) {
+            return done.clone();
+        }
+        self.solids().insert(name.to_string(), None);
+        let doc = self.doc();
+        let id = match doc.solids.solids.iter().find(|s| s.name() == name) {
+            Some(s) => self.define_solid(s),
+            None => {
+                self.warnings()
+                    .push(format!("Solid \"{}\" is referenced but not defined.", name));
+                None
+            }
+        };
+        self.solids().insert(name.to_string(), id.clone());
+        id
+    }
+
+    /// An operand of a composite solid, reporting the composite when the
+    /// operand could not be generated.
+    fn operand(&mut self, owner: &str, name: &str) -> Option<String> {
+        let id = self.solid(name);
+        if id.is_none() {
+            self.warnings().push(format!(
+                "Solid \"{}\" was left out because its operand \"{}\" could not be generated.",
+                owner, name
+            ));
+        }
+        id
+    }
+}
+
+/// Whether a mixture component `ref` names an element rather than a
+/// material. `G4GDMLReadMaterials::MixtureRead` tries elements first; an
+/// undefined name is an element unless it is a NIST material.
+pub(super) fn is_element(doc: &GdmlDocument, name: &str) -> bool {
+    let m = &doc.materials;
+    m.elements.iter().any(|e| e.name == name)
+        || (!m.materials.iter().any(|x| x.name == name) && find_nist_material(name).is_none())
+}
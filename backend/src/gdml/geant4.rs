@@ -0,0 +1,1610 @@
+//! Geant4 C++ for a document: a `DetectorConstruction` that builds the same
+//! geometry in code, for users who would rather compile their detector than
+//! ship a GDML file next to it.
+//!
+//! The generated `Construct()` follows `G4GDMLRead*` step for step, so the
+//! compiled geometry matches what `G4GDMLParser::Read` would build from the
+//! exported file:
+//!
+//! - elements and materials come first; a name the document references but
+//!   does not define is looked up in `G4NistManager`, as the GDML reader's
+//!   `GetElement`/`GetMaterial` fall back to;
+//! - every value is evaluated here and written as a literal with its unit, so
+//!   the C++ does not depend on the document's defines. Lengths and angles are
+//!   resolved the way the tessellator resolves them;
+//! - full lengths are halved for the constructors that take half-lengths;
+//! - rotations are GDML frame rotations, `rotateX`, `rotateY`, `rotateZ` in
+//!   that order, inverted into a `G4Transform3D` as `G4GDMLReadStructure` does.
+//!
+//! What has no Geant4 counterpart here is left out and reported: optical
+//! surfaces and properties, auxiliaries other than `color`, and any solid,
+//! material or volume that cannot be built, together with whatever uses it.
+
+use anyhow::{bail, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use super::cpp::{is_element, Emitter, FunctionBody};
+use super::model::*;
+use super::units;
+use crate::eval::engine::EvalEngine;
+
+/// A `DetectorConstruction` class: its header, its source and what could not
+/// be carried over.
+#[derive(Debug, Clone)]
+pub struct Geant4Source {
+    pub header: String,
+    pub source: String,
+    pub warnings: Vec<String>,
+}
+
+/// Generate `DetectorConstruction.hh` and `DetectorConstruction.cc` for `doc`.
+///
+/// Fails only when the world volume itself cannot be built, since
+/// `Construct()` would have nothing to return.
+pub fn generate_detector_construction(
+    doc: &GdmlDocument,
+    engine: &EvalEngine,
+) -> Result<Geant4Source> {
+    let mut g = Generator::new(doc, engine);
+    g.section("Isotopes and elements");
+    for iso in &doc.materials.isotopes {
+        g.isotope(iso);
+    }
+    for el in &doc.materials.elements {
+        g.element(&el.name);
+    }
+    g.section("Materials");
+    for m in &doc.materials.materials {
+        g.material(&m.name);
+    }
+    for v in &doc.structure.volumes {
+        g.material(&v.material_ref);
+    }
+    g.section("Solids");
+    for s in &doc.solids.solids {
+        g.solid(s.name());
+    }
+    g.section("Logical volumes");
+    for v in &doc.structure.volumes {
+        g.logical_volume(v);
+    }
+    g.section("Placements");
+    for v in &doc.structure.volumes {
+        g.placements(v);
+    }
+
+    let world = &doc.setup.world_ref;
+    let Some(world_lv) = g.volumes.get(world).cloned() else {
+        bail!(
+            "The world volume \"{}\" could not be generated; see the warnings for why",
+            world
+        );
+    };
+    g.line(&format!(
+        "auto worldPV = new G4PVPlacement(nullptr, G4ThreeVector(), {}, {}, nullptr, false, 0, \
+         checkOverlaps);",
+        world_lv,
+        quote(&format!("{}_PV", world))
+    ));
+    g.line("return worldPV;");
+    g.warnings.extend(unsupported_parts(doc));
+
+    Ok(Geant4Source {
+        header: HEADER.to_string(),
+        source: g.finish(),
+        warnings: g.warnings,
+    })
+}
+
+const HEADER: &str = "\
+#ifndef DetectorConstruction_h
+#define DetectorConstruction_h 1
+
+#include \"G4VUserDetectorConstruction.hh\"
+
+class G4VPhysicalVolume;
+
+class DetectorConstruction : public G4VUserDetectorConstruction
+{
+  public:
+    DetectorConstruction() = default;
+    ~DetectorConstruction() override = default;
+
+    G4VPhysicalVolume* Construct() override;
+};
+
+#endif
+";
+
+/// `name` as a C++ string literal.
+pub(super) fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A C++ floating-point literal: twelve significant digits, which hides the
+/// round-off of unit conversions without losing anything a file states.
+pub(super) fn num(v: f64) -> String {
+    if !v.is_finite() || v == 0.0 {
+        return "0".to_string();
+    }
+    let a = v.abs();
+    if !(1e-5..1e15).contains(&a) {
+        let text = format!("{:.11e}", v);
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        return format!("{}e{}", mantissa, exponent);
+    }
+    let scale = 10f64.powi(11 - a.log10().floor() as i32);
+    let rounded = (v * scale).round() / scale;
+    if rounded == 0.0 {
+        "0".to_string()
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+/// [`num`] that always reads as a `double`, for arguments where an integer
+/// literal would pick a different overload.
+pub(super) fn real(v: f64) -> String {
+    let n = num(v);
+    if n.contains(['.', 'e']) {
+        n
+    } else {
+        format!("{}.", n)
+    }
+}
+
+pub(super) fn int(v: f64) -> i64 {
+    if v.is_finite() {
+        v.round() as i64
+    } else {
+        0
+    }
+}
+
+fn mm(v: f64) -> String {
+    format!("{}*mm", num(v))
+}
+
+fn deg(radians: f64) -> String {
+    format!("{}*deg", num(radians.to_degrees()))
+}
+
+fn vector(v: [f64; 3]) -> String {
+    if v == [0.0; 3] {
+        "G4ThreeVector()".to_string()
+    } else {
+        format!("G4ThreeVector({}, {}, {})", mm(v[0]), mm(v[1]), mm(v[2]))
+    }
+}
+
+pub(super) fn list(values: impl IntoIterator<Item = String>) -> String {
+    values.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+/// A `<position>` or `<positionref>` in mm, as the tessellator resolves it.
+pub(super) fn placement_position(engine: &EvalEngine, pos: &Option<PlacementPos>) -> [f64; 3] {
+    match pos {
+        Some(PlacementPos::Inline(p)) => {
+            let v = Values::new(engine, &p.unit, &None);
+            [v.opt_len(&p.x), v.opt_len(&p.y), v.opt_len(&p.z)]
+        }
+        Some(PlacementPos::Ref(name)) => engine
+            .position_values
+            .get(name)
+            .copied()
+            .unwrap_or([0.0; 3]),
+        None => [0.0; 3],
+    }
+}
+
+/// A `<rotation>` or `<rotationref>` in radians.
+pub(super) fn placement_rotation(engine: &EvalEngine, rot: &Option<PlacementRot>) -> [f64; 3] {
+    match rot {
+        Some(PlacementRot::Inline(r)) => {
+            let v = Values::new(engine, &None, &r.unit);
+            [
+                v.opt_angle(&r.x, 0.0),
+                v.opt_angle(&r.y, 0.0),
+                v.opt_angle(&r.z, 0.0),
+            ]
+        }
+        Some(PlacementRot::Ref(name)) => engine
+            .rotation_values
+            .get(name)
+            .copied()
+            .unwrap_or([0.0; 3]),
+        None => [0.0; 3],
+    }
+}
+
+/// Molar mass in g/mole from an `<atom>` value and unit.
+pub(super) fn molar_mass(
+    engine: &EvalEngine,
+    value: &Option<String>,
+    unit: &Option<String>,
+) -> Option<f64> {
+    let v = engine.resolve_value(value.as_deref()?);
+    let internal = units::apply_unit(v, unit.as_deref().unwrap_or("g/mole"));
+    Some(units::in_unit(internal, "g/mole"))
+}
+
+/// A material's density in g/cm3, from `<D>` or `<Dref>`.
+pub(super) fn density(engine: &EvalEngine, m: &Material) -> Option<f64> {
+    let internal = match (&m.density, &m.density_ref) {
+        (Some(d), _) => engine
+            .eval_expr(&d.value)
+            .ok()
+            .map(|v| units::apply_unit(v, d.unit.as_deref().unwrap_or("g/cm3"))),
+        (None, Some(r)) => engine.context.get(r),
+        (None, None) => None,
+    }?;
+    Some(units::in_unit(internal, "g/cm3"))
+}
+
+/// The values of one element, in mm and radians.
+pub(super) struct Values<'a> {
+    engine: &'a EvalEngine,
+    lunit: &'a str,
+    aunit: &'a str,
+}
+
+impl<'a> Values<'a> {
+    pub(super) fn new(
+        engine: &'a EvalEngine,
+        lunit: &'a Option<String>,
+        aunit: &'a Option<String>,
+    ) -> Self {
+        Self {
+            engine,
+            lunit: lunit.as_deref().unwrap_or("mm"),
+            aunit: aunit.as_deref().unwrap_or("rad"),
+        }
+    }
+
+    pub(super) fn number(&self, expr: &str) -> f64 {
+        self.engine.resolve_value(expr)
+    }
+
+    pub(super) fn len(&self, expr: &str) -> f64 {
+        let v = self.engine.resolve_value(expr);
+        if self.engine.expression_uses_length_symbols(expr) {
+            v
+        } else {
+            units::length_to_mm(v, self.lunit)
+        }
+    }
+
+    pub(super) fn opt_len(&self, expr: &Option<String>) -> f64 {
+        expr.as_deref().map_or(0.0, |e| self.len(e))
+    }
+
+    pub(super) fn angle(&self, expr: &str) -> f64 {
+        let v = self.engine.resolve_value(expr);
+        if self.engine.expression_uses_angle_symbols(expr) {
+            v
+        } else {
+            units::angle_to_rad(v, self.aunit)
+        }
+    }
+
+    pub(super) fn opt_angle(&self, expr: &Option<String>, default: f64) -> f64 {
+        expr.as_deref().map_or(default, |e| self.angle(e))
+    }
+
+    /// An opening angle; absent, non-positive or a full turn and more all
+    /// sweep the complete revolution, as in `resolve_delta_phi`.
+    pub(super) fn dphi(&self, expr: &Option<String>) -> f64 {
+        let raw = self.opt_angle(expr, 2.0 * PI);
+        if !raw.is_finite() || raw <= 0.0 || raw > 2.0 * PI * (1.0 - f64::EPSILON) {
+            2.0 * PI
+        } else {
+            raw
+        }
+    }
+}
+
+struct Generator<'a> {
+    doc: &'a GdmlDocument,
+    engine: &'a EvalEngine,
+    body: FunctionBody,
+    includes: BTreeSet<&'static str>,
+    uses_nist: bool,
+    uses_rotation: bool,
+    /// Document name → C++ variable, `None` once it failed.
+    isotopes: HashMap<String, String>,
+    elements: HashMap<String, Option<String>>,
+    materials: HashMap<String, Option<String>>,
+    solids: HashMap<String, Option<String>>,
+    volumes: HashMap<String, String>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(doc: &'a GdmlDocument, engine: &'a EvalEngine) -> Self {
+        Self {
+            doc,
+            engine,
+            body: FunctionBody::default(),
+            includes: BTreeSet::new(),
+            uses_nist: false,
+            uses_rotation: false,
+            isotopes: HashMap::new(),
+            elements: HashMap::new(),
+            materials: HashMap::new(),
+            solids: HashMap::new(),
+            volumes: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// `x, y, z` rotations as a `G4Transform3D` with translation `t`, the way
+    /// the GDML reader turns a rotation and position into a transform.
+    fn transform(&mut self, t: [f64; 3], r: [f64; 3]) -> String {
+        self.includes.insert("G4Transform3D.hh");
+        if r == [0.0; 3] {
+            return format!("G4Transform3D(G4RotationMatrix(), {})", vector(t));
+        }
+        self.uses_rotation = true;
+        format!(
+            "G4Transform3D(rotation({}, {}, {}).inverse(), {})",
+            deg(r[0]),
+            deg(r[1]),
+            deg(r[2]),
+            vector(t)
+        )
+    }
+
+    // ─── Materials ───────────────────────────────────────────────────────────
+
+    fn isotope(&mut self, iso: &Isotope) {
+        let (Some(z), Some(n), Some(a)) = (
+            iso.z.as_deref(),
+            iso.n.as_deref(),
+            molar_mass(self.engine, &iso.atom_value, &iso.atom_unit),
+        ) else {
+            self.warnings.push(format!(
+                "Isotope \"{}\" needs Z, N and an atom value; it was not generated.",
+                iso.name
+            ));
+            return;
+        };
+        let (z, n) = (self.engine.resolve_value(z), self.engine.resolve_value(n));
+        let id = self.identifier("iso_", &iso.name);
+        self.includes.insert("G4Isotope.hh");
+        self.line(&format!(
+            "auto {} = new G4Isotope({}, {}, {}, {}*g/mole);",
+            id,
+            quote(&iso.name),
+            int(z),
+            int(n),
+            num(a)
+        ));
+        self.isotopes.insert(iso.name.clone(), id);
+    }
+
+    /// The variable holding element `name`, generating it on first use.
+    fn element(&mut self, name: &str) -> Option<String> {
+        if let Some(done) = self.elements.get(name) {
+            return done.clone();
+        }
+        self.elements.insert(name.to_string(), None);
+        let id = match self.doc.materials.elements.iter().find(|e| e.name == name) {
+            Some(el) => self.define_element(el),
+            None => {
+                self.uses_nist = true;
+                let id = self.identifier("el_", name);
+                self.line(&format!(
+                    "auto {} = nist->FindOrBuildElement({});",
+                    id,
+                    quote(name)
+                ));
+                Some(id)
+            }
+        };
+        self.elements.insert(name.to_string(), id.clone());
+        id
+    }
+
+    fn define_element(&mut self, el: &Element) -> Option<String> {
+        self.includes.insert("G4Element.hh");
+        let symbol = quote(el.formula.as_deref().unwrap_or(&el.name));
+        if !el.fractions.is_empty() {
+            let isotopes: Vec<(String, f64)> = el
+                .fractions
+                .iter()
+                .filter_map(|f| {
+                    let iso = self.isotopes.get(&f.ref_name)?.clone();
+                    Some((iso, self.engine.resolve_value(&f.n)))
+                })
+                .collect();
+            if isotopes.len() != el.fractions.len() {
+                self.warnings.push(format!(
+                    "Element \"{}\" references an isotope that was not generated; it was \
+                     left out.",
+                    el.name
+                ));
+                return None;
+            }
+            let id = self.identifier("el_", &el.name);
+            self.line(&format!(
+                "auto {} = new G4Element({}, {}, {});",
+                id,
+                quote(&el.name),
+                symbol,
+                isotopes.len()
+            ));
+            for (iso, n) in isotopes {
+                self.line(&format!("{}->AddIsotope({}, {});", id, iso, num(n)));
+            }
+            return Some(id);
+        }
+        let (Some(z), Some(a)) = (
+            el.z.as_deref(),
+            molar_mass(self.engine, &el.atom_value, &el.atom_unit),
+        ) else {
+            self.warnings.push(format!(
+                "Element \"{}\" has neither Z and an atom value nor isotope fractions; it was \
+                 left out.",
+                el.name
+            ));
+            return None;
+        };
+        let id = self.identifier("el_", &el.name);
+        self.line(&format!(
+            "auto {} = new G4Element({}, {}, {}, {}*g/mole);",
+            id,
+            quote(&el.name),
+            symbol,
+            num(self.engine.resolve_value(z)),
+            num(a)
+        ));
+        Some(id)
+    }
+
+    // ─── Solids ──────────────────────────────────────────────────────────────
+
+    /// `auto id = new Class("name", args);`
+    fn construct(&mut self, class: &'static str, name: &str, args: &[String]) -> String {
+        let id = self.identifier("solid_", name);
+        self.include(class);
+        let mut call = quote(name);
+        for a in args {
+            call.push_str(", ");
+            call.push_str(a);
+        }
+        self.line(&format!("auto {} = new {}({});", id, class, call));
+        id
+    }
+
+    fn include(&mut self, class: &'static str) {
+        let header = match class {
+            "G4Box" => "G4Box.hh",
+            "G4Tubs" => "G4Tubs.hh",
+            "G4Cons" => "G4Cons.hh",
+            "G4Sphere" => "G4Sphere.hh",
+            "G4Orb" => "G4Orb.hh",
+            "G4Torus" => "G4Torus.hh",
+            "G4Trd" => "G4Trd.hh",
+            "G4Trap" => "G4Trap.hh",
+            "G4Para" => "G4Para.hh",
+            "G4CutTubs" => "G4CutTubs.hh",
+            "G4Polycone" => "G4Polycone.hh",
+            "G4GenericPolycone" => "G4GenericPolycone.hh",
+            "G4Polyhedra" => "G4Polyhedra.hh",
+            "G4ExtrudedSolid" => "G4ExtrudedSolid.hh",
+            "G4TessellatedSolid" => "G4TessellatedSolid.hh",
+            "G4Ellipsoid" => "G4Ellipsoid.hh",
+            "G4EllipticalTube" => "G4EllipticalTube.hh",
+            "G4EllipticalCone" => "G4EllipticalCone.hh",
+            "G4Paraboloid" => "G4Paraboloid.hh",
+            "G4Hype" => "G4Hype.hh",
+            "G4Tet" => "G4Tet.hh",
+            "G4GenericTrap" => "G4GenericTrap.hh",
+            "G4TwistedTubs" => "G4TwistedTubs.hh",
+            "G4TwistedBox" => "G4TwistedBox.hh",
+            "G4TwistedTrap" => "G4TwistedTrap.hh",
+            "G4TwistedTrd" => "G4TwistedTrd.hh",
+            "G4ScaledSolid" => "G4ScaledSolid.hh",
+            "G4ReflectedSolid" => "G4ReflectedSolid.hh",
+            "G4MultiUnion" => "G4MultiUnion.hh",
+            "G4DisplacedSolid" => "G4DisplacedSolid.hh",
+            "G4UnionSolid" => "G4UnionSolid.hh",
+            "G4SubtractionSolid" => "G4SubtractionSolid.hh",
+            "G4IntersectionSolid" => "G4IntersectionSolid.hh",
+            _ => return,
+        };
+        self.includes.insert(header);
+    }
+
+    /// `const G4double id_suffix[] = {...};`, returning the array's name.
+    fn array(&mut self, id: &str, suffix: &str, values: Vec<String>) -> String {
+        let name = format!("{}_{}", id, suffix);
+        self.line(&format!(
+            "const G4double {}[] = {{{}}};",
+            name,
+            list(values)
+        ));
+        name
+    }
+}
+
+impl<'a> Emitter<'a> for Generator<'a> {
+    fn doc(&self) -> &'a GdmlDocument {
+        self.doc
+    }
+
+    fn body(&mut self) -> &mut FunctionBody {
+        &mut self.body
+    }
+
+    fn warnings(&mut self) -> &mut Vec<String> {
+        &mut self.warnings
+    }
+
+    fn materials(&mut self) -> &mut HashMap<String, Option<String>> {
+        &mut self.materials
+    }
+
+    fn solids(&mut self) -> &mut HashMap<String, Option<String>> {
+        &mut self.solids
+    }
+
+    fn define_material(&mut self, m: &Material) -> Option<String> {
+        let Some(density) = density(self.engine, m) else {
+            self.warnings.push(format!(
+                "Material \"{}\" has no density that evaluates; it was left out.",
+                m.name
+            ));
+            return None;
+        };
+        let density = format!("{}*g/cm3", num(density));
+
+        let state = match m.state.as_deref() {
+            Some("solid") => "kStateSolid",
+            Some("liquid") => "kStateLiquid",
+            Some("gas") => "kStateGas",
+            _ => "kStateUndefined",
+        };
+        let physical = |p: &PropertyValue, unit: &str, cpp: &str| {
+            let v = self.engine.resolve_value(&p.value);
+            let internal = units::apply_unit(v, p.unit.as_deref().unwrap_or(unit));
+            format!("{}*{}", num(units::in_unit(internal, unit)), cpp)
+        };
+        let mut conditions = String::new();
+        if m.temperature.is_some() || m.pressure.is_some() {
+            let t = m
+                .temperature
+                .as_ref()
+                .map_or("NTP_Temperature".to_string(), |t| {
+                    physical(t, "K", "kelvin")
+                });
+            let p = m.pressure.as_ref().map_or("STP_Pressure".to_string(), |p| {
+                physical(p, "pascal", "pascal")
+            });
+            conditions = format!(", {}, {}", t, p);
+        }
+
+        self.includes.insert("G4Material.hh");
+        let id = if let Some(z) = &m.z {
+            let Some(a) = molar_mass(self.engine, &m.atom_value, &m.atom_unit) else {
+                self.warnings.push(format!(
+                    "Material \"{}\" gives Z but no atom value; it was left out.",
+                    m.name
+                ));
+                return None;
+            };
+            let id = self.identifier("mat_", &m.name);
+            self.line(&format!(
+                "auto {} = new G4Material({}, {}, {}*g/mole, {}, {}{});",
+                id,
+                quote(&m.name),
+                num(self.engine.resolve_value(z)),
+                num(a),
+                density,
+                state,
+                conditions
+            ));
+            id
+        } else if !m.components.is_empty() {
+            let mut adds = Vec::new();
+            for c in &m.components {
+                let (n, ref_name, by_atoms) = match c {
+                    MaterialComponent::Fraction { n, ref_name } => (n, ref_name, false),
+                    MaterialComponent::Composite { n, ref_name } => (n, ref_name, true),
+                };
+                let n = self.engine.resolve_value(n);
+                let add = if by_atoms || is_element(self.doc, ref_name) {
+                    self.element(ref_name).map(|el| {
+                        if by_atoms {
+                            format!("AddElement({}, {})", el, int(n))
+                        } else {
+                            // `AddElement(el, 1)` would be read as one atom.
+                            format!("AddElement({}, {})", el, real(n))
+                        }
+                    })
+                } else {
+                    self.material(ref_name)
+                        .map(|mat| format!("AddMaterial({}, {})", mat, num(n)))
+                };
+                match add {
+                    Some(add) => adds.push(add),
+                    None => {
+                        self.warnings.push(format!(
+                            "Material \"{}\" uses \"{}\", which was not generated; it was left \
+                             out.",
+                            m.name, ref_name
+                        ));
+                        return None;
+                    }
+                }
+            }
+            let id = self.identifier("mat_", &m.name);
+            self.line(&format!(
+                "auto {} = new G4Material({}, {}, {}, {}{});",
+                id,
+                quote(&m.name),
+                density,
+                adds.len(),
+                state,
+                conditions
+            ));
+            for add in adds {
+                self.line(&format!("{}->{};", id, add));
+            }
+            id
+        } else {
+            self.warnings.push(format!(
+                "Material \"{}\" has neither Z nor components; it was left out.",
+                m.name
+            ));
+            return None;
+        };
+
+        if let Some(mee) = &m.mee {
+            let v = self.engine.resolve_value(&mee.value);
+            let internal = units::apply_unit(v, mee.unit.as_deref().unwrap_or("eV"));
+            self.line(&format!(
+                "{}->GetIonisation()->SetMeanExcitationEnergy({}*eV);",
+                id,
+                num(units::in_unit(internal, "eV"))
+            ));
+        }
+        Some(id)
+    }
+
+    fn undefined_material(&mut self, name: &str) -> Option<String> {
+        self.uses_nist = true;
+        let id = self.identifier("mat_", name);
+        self.line(&format!(
+            "auto {} = nist->FindOrBuildMaterial({});",
+            id,
+            quote(name)
+        ));
+        Some(id)
+    }
+
+    fn define_solid(&mut self, solid: &Solid) -> Option<String> {
+        let engine = self.engine;
+        let name = solid.name();
+        Some(match solid {
+            Solid::Box(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.x, &s.y, &s.z].map(|e| mm(0.5 * v.len(e)));
+                self.construct("G4Box", name, &args)
+            }
+            Solid::Tube(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(v.opt_len(&s.rmin)),
+                    mm(v.len(&s.rmax)),
+                    mm(0.5 * v.len(&s.z)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                ];
+                self.construct("G4Tubs", name, &args)
+            }
+            Solid::Cone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(v.opt_len(&s.rmin1)),
+                    mm(v.len(&s.rmax1)),
+                    mm(v.opt_len(&s.rmin2)),
+                    mm(v.len(&s.rmax2)),
+                    mm(0.5 * v.len(&s.z)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                ];
+                self.construct("G4Cons", name, &args)
+            }
+            Solid::Sphere(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(v.opt_len(&s.rmin)),
+                    mm(v.len(&s.rmax)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    deg(v.opt_angle(&s.starttheta, 0.0)),
+                    deg(v.opt_angle(&s.deltatheta, PI)),
+                ];
+                self.construct("G4Sphere", name, &args)
+            }
+            Solid::Orb(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                self.construct("G4Orb", name, &[mm(v.len(&s.r))])
+            }
+            Solid::Torus(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(v.opt_len(&s.rmin)),
+                    mm(v.len(&s.rmax)),
+                    mm(v.len(&s.rtor)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                ];
+                self.construct("G4Torus", name, &args)
+            }
+            Solid::Trd(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.x1, &s.x2, &s.y1, &s.y2, &s.z].map(|e| mm(0.5 * v.len(e)));
+                self.construct("G4Trd", name, &args)
+            }
+            Solid::Para(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(0.5 * v.len(&s.x)),
+                    mm(0.5 * v.len(&s.y)),
+                    mm(0.5 * v.len(&s.z)),
+                    deg(v.opt_angle(&s.alpha, 0.0)),
+                    deg(v.opt_angle(&s.theta, 0.0)),
+                    deg(v.opt_angle(&s.phi, 0.0)),
+                ];
+                self.construct("G4Para", name, &args)
+            }
+            Solid::Trap(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let half = |e: &String| mm(0.5 * v.len(e));
+                let args = [
+                    half(&s.z),
+                    deg(v.opt_angle(&s.theta, 0.0)),
+                    deg(v.opt_angle(&s.phi, 0.0)),
+                    half(&s.y1),
+                    half(&s.x1),
+                    half(&s.x2),
+                    deg(v.opt_angle(&s.alpha1, 0.0)),
+                    half(&s.y2),
+                    half(&s.x3),
+                    half(&s.x4),
+                    deg(v.opt_angle(&s.alpha2, 0.0)),
+                ];
+                self.construct("G4Trap", name, &args)
+            }
+            Solid::CutTube(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let normal = |x: &Option<String>, y: &Option<String>, z: &Option<String>, dz| {
+                    let c = |e: &Option<String>, d: f64| e.as_deref().map_or(d, |e| v.number(e));
+                    format!(
+                        "G4ThreeVector({}, {}, {})",
+                        num(c(x, 0.0)),
+                        num(c(y, 0.0)),
+                        num(c(z, dz))
+                    )
+                };
+                let args = [
+                    mm(v.opt_len(&s.rmin)),
+                    mm(v.len(&s.rmax)),
+                    mm(0.5 * v.len(&s.z)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    normal(&s.low_x, &s.low_y, &s.low_z, -1.0),
+                    normal(&s.high_x, &s.high_y, &s.high_z, 1.0),
+                ];
+                self.construct("G4CutTubs", name, &args)
+            }
+            Solid::Polycone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let id = self.identifier("solid_", name);
+                let z = self.array(
+                    &id,
+                    "z",
+                    s.zplanes.iter().map(|p| mm(v.len(&p.z))).collect(),
+                );
+                let rmin = self.array(
+                    &id,
+                    "rmin",
+                    s.zplanes.iter().map(|p| mm(v.opt_len(&p.rmin))).collect(),
+                );
+                let rmax = self.array(
+                    &id,
+                    "rmax",
+                    s.zplanes.iter().map(|p| mm(v.len(&p.rmax))).collect(),
+                );
+                self.include("G4Polycone");
+                self.line(&format!(
+                    "auto {} = new G4Polycone({}, {}, {}, {}, {}, {}, {});",
+                    id,
+                    quote(name),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    s.zplanes.len(),
+                    z,
+                    rmin,
+                    rmax
+                ));
+                id
+            }
+            Solid::Polyhedra(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let id = self.identifier("solid_", name);
+                let z = self.array(
+                    &id,
+                    "z",
+                    s.zplanes.iter().map(|p| mm(v.len(&p.z))).collect(),
+                );
+                let rmin = self.array(
+                    &id,
+                    "rmin",
+                    s.zplanes.iter().map(|p| mm(v.opt_len(&p.rmin))).collect(),
+                );
+                let rmax = self.array(
+                    &id,
+                    "rmax",
+                    s.zplanes.iter().map(|p| mm(v.len(&p.rmax))).collect(),
+                );
+                self.include("G4Polyhedra");
+                self.line(&format!(
+                    "auto {} = new G4Polyhedra({}, {}, {}, {}, {}, {}, {}, {});",
+                    id,
+                    quote(name),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    int(v.number(&s.numsides)),
+                    s.zplanes.len(),
+                    z,
+                    rmin,
+                    rmax
+                ));
+                id
+            }
+            Solid::GenericPolycone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let id = self.identifier("solid_", name);
+                let r = self.array(
+                    &id,
+                    "r",
+                    s.rzpoints.iter().map(|p| mm(v.len(&p.r))).collect(),
+                );
+                let z = self.array(
+                    &id,
+                    "z",
+                    s.rzpoints.iter().map(|p| mm(v.len(&p.z))).collect(),
+                );
+                self.include("G4GenericPolycone");
+                self.line(&format!(
+                    "auto {} = new G4GenericPolycone({}, {}, {}, {}, {}, {});",
+                    id,
+                    quote(name),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    s.rzpoints.len(),
+                    r,
+                    z
+                ));
+                id
+            }
+            Solid::GenericPolyhedra(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let id = self.identifier("solid_", name);
+                let r = self.array(
+                    &id,
+                    "r",
+                    s.rzpoints.iter().map(|p| mm(v.len(&p.r))).collect(),
+                );
+                let z = self.array(
+                    &id,
+                    "z",
+                    s.rzpoints.iter().map(|p| mm(v.len(&p.z))).collect(),
+                );
+                self.include("G4Polyhedra");
+                self.line(&format!(
+                    "auto {} = new G4Polyhedra({}, {}, {}, {}, {}, {}, {});",
+                    id,
+                    quote(name),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    int(v.number(&s.numsides)),
+                    s.rzpoints.len(),
+                    r,
+                    z
+                ));
+                id
+            }
+            Solid::Xtru(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let id = self.identifier("solid_", name);
+                let polygon = list(
+                    s.vertices
+                        .iter()
+                        .map(|p| format!("G4TwoVector({}, {})", mm(v.len(&p.x)), mm(v.len(&p.y)))),
+                );
+                let sections = list(s.sections.iter().map(|z| {
+                    format!(
+                        "G4ExtrudedSolid::ZSection({}, G4TwoVector({}, {}), {})",
+                        mm(v.len(&z.z_position)),
+                        mm(v.len(&z.x_offset)),
+                        mm(v.len(&z.y_offset)),
+                        num(v.number(&z.scaling_factor))
+                    )
+                }));
+                self.include("G4ExtrudedSolid");
+                self.line(&format!(
+                    "auto {} = new G4ExtrudedSolid({}, std::vector<G4TwoVector>{{{}}}, \
+                     std::vector<G4ExtrudedSolid::ZSection>{{{}}});",
+                    id,
+                    quote(name),
+                    polygon,
+                    sections
+                ));
+                id
+            }
+            Solid::Tessellated(s) => {
+                let vertex = |n: &String| engine.position_values.get(n).copied().map(vector);
+                let mut facets = Vec::new();
+                for f in &s.facets {
+                    let (class, corners, kind) = match f {
+                        TessellatedFacet::Triangular {
+                            vertex1,
+                            vertex2,
+                            vertex3,
+                            r#type,
+                        } => ("G4TriangularFacet", vec![vertex1, vertex2, vertex3], r#type),
+                        TessellatedFacet::Quadrangular {
+                            vertex1,
+                            vertex2,
+                            vertex3,
+                            vertex4,
+                            r#type,
+                        } => (
+                            "G4QuadrangularFacet",
+                            vec![vertex1, vertex2, vertex3, vertex4],
+                            r#type,
+                        ),
+                    };
+                    let Some(corners) = corners.into_iter().map(vertex).collect::<Option<Vec<_>>>()
+                    else {
+                        self.warnings.push(format!(
+                            "Tessellated solid \"{}\" uses a vertex that is not defined; it was \
+                             left out.",
+                            name
+                        ));
+                        return None;
+                    };
+                    let kind = match kind.as_deref() {
+                        Some("RELATIVE") => "RELATIVE",
+                        _ => "ABSOLUTE",
+                    };
+                    facets.push(format!("new {}({}, {})", class, list(corners), kind));
+                }
+                let id = self.construct("G4TessellatedSolid", name, &[]);
+                self.includes.insert("G4TriangularFacet.hh");
+                self.includes.insert("G4QuadrangularFacet.hh");
+                for f in facets {
+                    self.line(&format!("{}->AddFacet({});", id, f));
+                }
+                self.line(&format!("{}->SetSolidClosed(true);", id));
+                id
+            }
+            Solid::Ellipsoid(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let cz = v.len(&s.cz);
+                let cut = |e: &Option<String>, d: f64| e.as_deref().map_or(d, |e| v.len(e));
+                let args = [
+                    mm(v.len(&s.ax)),
+                    mm(v.len(&s.by)),
+                    mm(cz),
+                    mm(cut(&s.zcut1, -cz)),
+                    mm(cut(&s.zcut2, cz)),
+                ];
+                self.construct("G4Ellipsoid", name, &args)
+            }
+            Solid::Eltube(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.dx, &s.dy, &s.dz].map(|e| mm(v.len(e)));
+                self.construct("G4EllipticalTube", name, &args)
+            }
+            Solid::Elcone(s) => {
+                // dx and dy are slopes, not lengths: lunit does not apply.
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [
+                    num(v.number(&s.dx)),
+                    num(v.number(&s.dy)),
+                    mm(v.len(&s.zmax)),
+                    mm(v.len(&s.zcut)),
+                ];
+                self.construct("G4EllipticalCone", name, &args)
+            }
+            Solid::Paraboloid(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.dz, &s.rlo, &s.rhi].map(|e| mm(v.len(e)));
+                self.construct("G4Paraboloid", name, &args)
+            }
+            Solid::Hype(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    mm(v.opt_len(&s.rmin)),
+                    mm(v.len(&s.rmax)),
+                    deg(v.opt_angle(&s.inst, 0.0)),
+                    deg(v.opt_angle(&s.outst, 0.0)),
+                    mm(0.5 * v.len(&s.z)),
+                ];
+                self.construct("G4Hype", name, &args)
+            }
+            Solid::Tet(s) => {
+                let corners = [&s.vertex1, &s.vertex2, &s.vertex3, &s.vertex4]
+                    .map(|n| engine.position_values.get(n).copied().map(vector));
+                let Some(args) = corners.into_iter().collect::<Option<Vec<_>>>() else {
+                    self.warnings.push(format!(
+                        "Tet \"{}\" uses a vertex that is not defined; it was left out.",
+                        name
+                    ));
+                    return None;
+                };
+                self.construct("G4Tet", name, &args)
+            }
+            Solid::Arb8(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let corners = [
+                    (&s.v1x, &s.v1y),
+                    (&s.v2x, &s.v2y),
+                    (&s.v3x, &s.v3y),
+                    (&s.v4x, &s.v4y),
+                    (&s.v5x, &s.v5y),
+                    (&s.v6x, &s.v6y),
+                    (&s.v7x, &s.v7y),
+                    (&s.v8x, &s.v8y),
+                ]
+                .map(|(x, y)| format!("G4TwoVector({}, {})", mm(v.len(x)), mm(v.len(y))));
+                let args = [
+                    mm(v.len(&s.dz)),
+                    format!("std::vector<G4TwoVector>{{{}}}", list(corners)),
+                ];
+                self.construct("G4GenericTrap", name, &args)
+            }
+            Solid::TwistedBox(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    deg(v.angle(&s.phi_twist)),
+                    mm(0.5 * v.len(&s.x)),
+                    mm(0.5 * v.len(&s.y)),
+                    mm(0.5 * v.len(&s.z)),
+                ];
+                self.construct("G4TwistedBox", name, &args)
+            }
+            Solid::TwistedTrd(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let mut args: Vec<String> = [&s.x1, &s.x2, &s.y1, &s.y2, &s.z]
+                    .map(|e| mm(0.5 * v.len(e)))
+                    .to_vec();
+                args.push(deg(v.angle(&s.phi_twist)));
+                self.construct("G4TwistedTrd", name, &args)
+            }
+            Solid::TwistedTrap(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let half = |e: &String| mm(0.5 * v.len(e));
+                let args = [
+                    deg(v.angle(&s.phi_twist)),
+                    half(&s.z),
+                    deg(v.angle(&s.theta)),
+                    deg(v.angle(&s.phi)),
+                    half(&s.y1),
+                    half(&s.x1),
+                    half(&s.x2),
+                    half(&s.y2),
+                    half(&s.x3),
+                    half(&s.x4),
+                    deg(v.angle(&s.alph)),
+                ];
+                self.construct("G4TwistedTrap", name, &args)
+            }
+            Solid::TwistedTubs(s) => {
+                // The four constructors `G4GDMLReadSolids::TwistedtubsRead`
+                // chooses between: end radii with a half-length when `zlen` is
+                // non-zero, mid radii with explicit z ends otherwise, each
+                // either segmented or with a plain opening angle.
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let mut args = vec![deg(v.angle(&s.twistedangle))];
+                let zlen = v.opt_len(&s.zlen);
+                if zlen != 0.0 {
+                    args.extend([
+                        mm(v.opt_len(&s.endinnerrad)),
+                        mm(v.opt_len(&s.endouterrad)),
+                        mm(zlen),
+                    ]);
+                } else {
+                    args.extend([
+                        mm(v.opt_len(&s.midinnerrad)),
+                        mm(v.opt_len(&s.midouterrad)),
+                        mm(v.opt_len(&s.negative_endz)),
+                        mm(v.opt_len(&s.positive_endz)),
+                    ]);
+                }
+                let nseg = s.nseg.as_deref().map_or(0, |e| int(v.number(e)));
+                if nseg >= 1 {
+                    args.push(nseg.to_string());
+                    args.push(deg(v.opt_angle(&s.totphi, 2.0 * PI)));
+                } else {
+                    args.push(deg(v.opt_angle(&s.phi, 2.0 * PI)));
+                }
+                self.construct("G4TwistedTubs", name, &args)
+            }
+            Solid::Scaled(s) => {
+                let [sx, sy, sz] = match &s.scale_ref {
+                    Some(r) => match engine.scale_values.get(r) {
+                        Some(values) => *values,
+                        None => {
+                            self.warnings.push(format!(
+                                "Scaled solid \"{}\" references scale \"{}\", which is not \
+                                 defined; it was left out.",
+                                name, r
+                            ));
+                            return None;
+                        }
+                    },
+                    None => [&s.scale_x, &s.scale_y, &s.scale_z].map(|e| engine.resolve_value(e)),
+                };
+                let inner = self.operand(name, &s.solid_ref)?;
+                self.includes.insert("G4Transform3D.hh");
+                let args = [
+                    inner,
+                    format!("G4Scale3D({}, {}, {})", num(sx), num(sy), num(sz)),
+                ];
+                self.construct("G4ScaledSolid", name, &args)
+            }
+            Solid::Reflected(s) => {
+                let inner = self.operand(name, &s.solid_ref)?;
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let t = [&s.dx, &s.dy, &s.dz].map(|e| v.len(e));
+                let r = [&s.rx, &s.ry, &s.rz].map(|e| v.angle(e));
+                let scale = [&s.sx, &s.sy, &s.sz].map(|e| num(v.number(e)));
+                let transform = format!("{} * G4Scale3D({})", self.transform(t, r), list(scale));
+                self.construct("G4ReflectedSolid", name, &[inner, transform])
+            }
+            Solid::MultiUnion(s) => {
+                let mut nodes = Vec::new();
+                for node in &s.nodes {
+                    let operand = self.operand(name, &node.solid_ref)?;
+                    let t = placement_position(engine, &node.position);
+                    let r = placement_rotation(engine, &node.rotation);
+                    nodes.push((operand, self.transform(t, r)));
+                }
+                let id = self.construct("G4MultiUnion", name, &[]);
+                for (operand, transform) in nodes {
+                    self.line(&format!("{}->AddNode(*{}, {});", id, operand, transform));
+                }
+                self.line(&format!("{}->Voxelize();", id));
+                id
+            }
+            Solid::Boolean(s) => {
+                let mut first = self.operand(name, &s.first_ref)?;
+                let second = self.operand(name, &s.second_ref)?;
+                let first_t = placement_position(engine, &s.first_position);
+                let first_r = placement_rotation(engine, &s.first_rotation);
+                if first_t != [0.0; 3] || first_r != [0.0; 3] {
+                    let transform = self.transform(first_t, first_r);
+                    first = self.construct(
+                        "G4DisplacedSolid",
+                        &format!("displaced_{}", s.first_ref),
+                        &[first, transform],
+                    );
+                }
+                let class = match s.operation {
+                    BooleanOp::Union => "G4UnionSolid",
+                    BooleanOp::Subtraction => "G4SubtractionSolid",
+                    BooleanOp::Intersection => "G4IntersectionSolid",
+                };
+                let t = placement_position(engine, &s.position);
+                let r = placement_rotation(engine, &s.rotation);
+                let transform = self.transform(t, r);
+                self.construct(class, name, &[first, second, transform])
+            }
+        })
+    }
+}
+
+impl<'a> Generator<'a> {
+    // ─── Structure ───────────────────────────────────────────────────────────
+
+    fn logical_volume(&mut self, v: &Volume) {
+        let solid = self.solids.get(&v.solid_ref).cloned().flatten();
+        let material = self.materials.get(&v.material_ref).cloned().flatten();
+        let (Some(solid), Some(material)) = (solid, material) else {
+            self.warnings.push(format!(
+                "Volume \"{}\" was left out because its solid or material could not be \
+                 generated.",
+                v.name
+            ));
+            return;
+        };
+        self.includes.insert("G4LogicalVolume.hh");
+        let id = self.identifier("lv_", &v.name);
+        self.line(&format!(
+            "auto {} = new G4LogicalVolume({}, {}, {});",
+            id,
+            solid,
+            material,
+            quote(&v.name)
+        ));
+        if let Some(aux) = v.auxiliaries.iter().find(|a| a.auxtype == "color") {
+            match parse_colour(&aux.auxvalue) {
+                Some([r, g, b, a]) => {
+                    self.includes.insert("G4VisAttributes.hh");
+                    self.includes.insert("G4Colour.hh");
+                    self.line(&format!(
+                        "{}->SetVisAttributes(G4VisAttributes(G4Colour({}, {}, {}, {})));",
+                        id,
+                        num(r),
+                        num(g),
+                        num(b),
+                        num(a)
+                    ));
+                }
+                None => self.warnings.push(format!(
+                    "Volume \"{}\": color \"{}\" is not RRGGBB or RRGGBBAA hex; no vis \
+                     attributes were set.",
+                    v.name, aux.auxvalue
+                )),
+            }
+        }
+        self.volumes.insert(v.name.clone(), id);
+    }
+
+    fn placements(&mut self, v: &Volume) {
+        let Some(mother) = self.volumes.get(&v.name).cloned() else {
+            return;
+        };
+        let engine = self.engine;
+        for pv in &v.physvols {
+            let Some(daughter) = self.volumes.get(&pv.volume_ref).cloned() else {
+                self.warnings.push(match &pv.file_ref {
+                    Some(file) => format!(
+                        "Volume \"{}\": the placement of file \"{}\" was left out; load the \
+                         included file with the document to resolve it.",
+                        v.name, file.name
+                    ),
+                    None => format!(
+                        "Volume \"{}\": the placement of \"{}\" was left out because that \
+                         volume was not generated.",
+                        v.name, pv.volume_ref
+                    ),
+                });
+                continue;
+            };
+            let name = match pv.name.as_deref() {
+                Some(n) if !n.is_empty() => n.to_string(),
+                _ => format!("{}_PV", pv.volume_ref),
+            };
+            let copy = pv
+                .copynumber
+                .as_deref()
+                .map_or(0, |c| int(engine.resolve_value(c)));
+            let t = placement_position(engine, &pv.position);
+            let r = placement_rotation(engine, &pv.rotation);
+            let frame = if r == [0.0; 3] {
+                format!("nullptr, {}", vector(t))
+            } else {
+                self.transform(t, r)
+            };
+            self.includes.insert("G4PVPlacement.hh");
+            self.line(&format!(
+                "new G4PVPlacement({}, {}, {}, {}, false, {}, checkOverlaps);",
+                frame,
+                daughter,
+                quote(&name),
+                mother,
+                copy
+            ));
+        }
+        if let Some(rep) = &v.replica {
+            self.replica(v, &mother, rep);
+        }
+    }
+
+    fn replica(&mut self, v: &Volume, mother: &str, rep: &ReplicaVol) {
+        let Some(daughter) = self.volumes.get(&rep.volume_ref).cloned() else {
+            self.warnings.push(format!(
+                "Volume \"{}\": the replica of \"{}\" was left out because that volume was not \
+                 generated.",
+                v.name, rep.volume_ref
+            ));
+            return;
+        };
+        let engine = self.engine;
+        let (axis, angular) = match rep.curvilinear_axis.as_deref() {
+            Some("phi") => ("kPhi", true),
+            Some("rho") => ("kRho", false),
+            _ => {
+                let nonzero = rep.direction.iter().position(|d| {
+                    d.as_deref()
+                        .is_some_and(|e| engine.resolve_value(e).abs() > 0.0)
+                });
+                (
+                    match nonzero {
+                        Some(0) => "kXAxis",
+                        Some(1) => "kYAxis",
+                        _ => "kZAxis",
+                    },
+                    false,
+                )
+            }
+        };
+        // `G4GDMLReadStructure` multiplies by the unit when one is given and
+        // otherwise takes the value as internal units, mm or rad.
+        let value = |expr: &str, unit: &Option<String>| {
+            let v = engine.resolve_value(expr);
+            let internal = unit.as_deref().map_or(v, |u| units::apply_unit(v, u));
+            if angular {
+                deg(internal)
+            } else {
+                mm(internal)
+            }
+        };
+        self.includes.insert("G4PVReplica.hh");
+        self.line(&format!(
+            "new G4PVReplica({}, {}, {}, {}, {}, {}, {});",
+            quote(&format!("{}_PV", rep.volume_ref)),
+            daughter,
+            mother,
+            axis,
+            int(engine.resolve_value(&rep.number)),
+            value(&rep.width, &rep.width_unit),
+            value(&rep.offset, &rep.offset_unit)
+        ));
+    }
+
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "// Geometry of {}.", self.doc.filename);
+        out.push_str("\n#include \"DetectorConstruction.hh\"\n\n");
+        for header in [
+            "G4SystemOfUnits.hh",
+            "G4PhysicalConstants.hh",
+            "G4ThreeVector.hh",
+            "G4TwoVector.hh",
+            "G4RotationMatrix.hh",
+        ] {
+            self.includes.insert(header);
+        }
+        if self.uses_nist {
+            self.includes.insert("G4NistManager.hh");
+        }
+        for header in &self.includes {
+            let _ = writeln!(out, "#include \"{}\"", header);
+        }
+        out.push_str("\nG4VPhysicalVolume* DetectorConstruction::Construct()\n{\n");
+        out.push_str("  const G4bool checkOverlaps = false;\n");
+        if self.uses_nist {
+            out.push_str("  auto nist = G4NistManager::Instance();\n");
+        }
+        if self.uses_rotation {
+            out.push_str(
+                "  // A GDML rotation: the frame turned about x, then y, then z.\n  \
+                 auto rotation = [](G4double x, G4double y, G4double z) {\n    \
+                 G4RotationMatrix rot;\n    \
+                 rot.rotateX(x);\n    \
+                 rot.rotateY(y);\n    \
+                 rot.rotateZ(z);\n    \
+                 rot.rectify();\n    \
+                 return rot;\n  };\n",
+            );
+        }
+        out.push('\n');
+        out.push_str(&self.body.text);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// What the document carries that none of the generated formats have room
+/// for: optical surfaces, material property tables and auxiliaries other
+/// than `color`.
+pub(super) fn unsupported_parts(doc: &GdmlDocument) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let surfaces = doc.solids.optical_surfaces.len()
+        + doc.structure.skin_surfaces.len()
+        + doc.structure.border_surfaces.len();
+    if surfaces > 0 {
+        warnings.push(format!(
+            "{} optical surface definitions are not generated.",
+            surfaces
+        ));
+    }
+    if doc
+        .materials
+        .materials
+        .iter()
+        .any(|m| !m.properties.is_empty())
+    {
+        warnings.push("Material property tables are not generated.".to_string());
+    }
+    let auxiliaries: BTreeSet<&str> = doc
+        .structure
+        .volumes
+        .iter()
+        .flat_map(|v| &v.auxiliaries)
+        .map(|a| a.auxtype.as_str())
+        .filter(|t| *t != "color")
+        .collect();
+    if !auxiliaries.is_empty() {
+        warnings.push(format!(
+            "Auxiliaries other than color are not generated: {}.",
+            auxiliaries.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    warnings
+}
+
+/// A `color` auxiliary value, `RRGGBB` or `RRGGBBAA` hex with an optional
+/// `#`, as red, green, blue and alpha in 0..1.
+pub(super) fn parse_colour(value: &str) -> Option<[f64; 4]> {
+    let hex = value.trim().trim_start_matches('#');
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .map(|c| c as f64 / 255.0)
+    };
+    Some([
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        if hex.len() == 8 { channel(6)? } else { 1.0 },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn generate(xml: &str) -> Geant4Source {
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "detector.gdml".to_string()).unwrap();
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&doc.defines).unwrap();
+        generate_detector_construction(&doc, &engine).unwrap()
+    }
+
+    #[test]
+    fn literals_are_valid_and_rounded() {
+        assert_eq!(num(50.0), "50");
+        assert_eq!(num(-0.5), "-0.5");
+        assert_eq!(num(89.99999999999999), "90");
+        assert_eq!(num(1e-9), "1e-9");
+        assert_eq!(num(7.499999999999999e-6), "7.5e-6");
+        assert_eq!(num(1.234567890123456e20), "1.23456789012e20");
+        assert_eq!(num(-2e15), "-2e15");
+        assert_eq!(num(f64::NAN), "0");
+        assert_eq!(real(1.0), "1.");
+        assert_eq!(real(0.25), "0.25");
+        assert_eq!(deg(PI / 2.0), "90*deg");
+        assert_eq!(quote("a\"b"), "\"a\\\"b\"");
+        assert_eq!(
+            parse_colour("#FF000080"),
+            Some([1.0, 0.0, 0.0, 128.0 / 255.0])
+        );
+        assert_eq!(parse_colour("red"), None);
+    }
+
+    #[test]
+    fn a_detector_becomes_construct_calls() {
+        let out = generate(
+            r#"<gdml>
+  <define>
+    <constant name="HALF" value="50"/>
+    <position name="shift" x="0" y="0" z="2" unit="cm"/>
+  </define>
+  <materials>
+    <element name="Hydrogen" formula="H" Z="1"><atom value="1.008"/></element>
+    <material name="Water" state="liquid">
+      <D value="1"/>
+      <composite n="2" ref="Hydrogen"/>
+      <composite n="1" ref="O"/>
+    </material>
+    <material name="Mix"><D value="1.1"/><fraction n="0.5" ref="Water"/><fraction n="0.5" ref="G4_AIR"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="2*HALF" y="100" z="100" lunit="cm"/>
+    <tube name="Can" rmax="10" z="40" deltaphi="360" aunit="deg"/>
+    <box name="Cut" x="5" y="5" z="5"/>
+    <subtraction name="Holed">
+      <first ref="Can"/><second ref="Cut"/>
+      <rotation name="r" x="90" unit="deg"/>
+    </subtraction>
+  </solids>
+  <structure>
+    <volume name="Target">
+      <materialref ref="Mix"/><solidref ref="Holed"/>
+      <auxiliary auxtype="color" auxvalue="FF0000"/>
+      <auxiliary auxtype="SensDet" auxvalue="Tracker"/>
+    </volume>
+    <volume name="World">
+      <materialref ref="G4_Galactic"/><solidref ref="WorldBox"/>
+      <physvol name="target_pv" copynumber="7">
+        <volumeref ref="Target"/><positionref ref="shift"/>
+        <rotation name="t" z="30" unit="deg"/>
+      </physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#,
+        );
+        let cc = &out.source;
+        for expected in [
+            "auto el_Hydrogen = new G4Element(\"Hydrogen\", \"H\", 1, 1.008*g/mole);",
+            "auto el_O = nist->FindOrBuildElement(\"O\");",
+            "auto mat_Water = new G4Material(\"Water\", 1*g/cm3, 2, kStateLiquid);",
+            "mat_Water->AddElement(el_Hydrogen, 2);",
+            "auto mat_G4_AIR = nist->FindOrBuildMaterial(\"G4_AIR\");",
+            "mat_Mix->AddMaterial(mat_Water, 0.5);",
+            "mat_Mix->AddMaterial(mat_G4_AIR, 0.5);",
+            "auto mat_G4_Galactic = nist->FindOrBuildMaterial(\"G4_Galactic\");",
+            "new G4Box(\"WorldBox\", 500*mm, 500*mm, 500*mm);",
+            "new G4Tubs(\"Can\", 0*mm, 10*mm, 20*mm, 0*deg, 360*deg);",
+            "new G4SubtractionSolid(\"Holed\", solid_Can, solid_Cut, \
+             G4Transform3D(rotation(90*deg, 0*deg, 0*deg).inverse(), G4ThreeVector()));",
+            "lv_Target->SetVisAttributes(G4VisAttributes(G4Colour(1, 0, 0, 1)));",
+            "new G4PVPlacement(G4Transform3D(rotation(0*deg, 0*deg, 30*deg).inverse(), \
+             G4ThreeVector(0*mm, 0*mm, 20*mm)), lv_Target, \"target_pv\", lv_World, false, 7, \
+             checkOverlaps);",
+            "new G4PVPlacement(nullptr, G4ThreeVector(), lv_World, \"World_PV\", nullptr",
+            "#include \"G4SubtractionSolid.hh\"",
+            "#include \"G4NistManager.hh\"",
+        ] {
+            assert!(cc.contains(expected), "missing `{}` in:\n{}", expected, cc);
+        }
+        // Declarations come before their first use.
+        assert!(cc.find("auto mat_Water").unwrap() < cc.find("auto mat_Mix").unwrap());
+        assert!(cc.find("auto solid_Cut").unwrap() < cc.find("auto solid_Holed").unwrap());
+        assert!(out.header.contains("class DetectorConstruction"));
+        assert!(out.warnings.iter().any(|w| w.contains("SensDet")));
+    }
+
+    #[test]
+    fn replicas_and_missing_parts_are_reported() {
+        let out = generate(
+            r#"<gdml>
+  <materials/>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="Slab" x="10" y="100" z="100"/>
+    <tessellated name="Broken"><triangular vertex1="nowhere" vertex2="a" vertex3="b"/></tessellated>
+  </solids>
+  <structure>
+    <volume name="Layer"><materialref ref="G4_Si"/><solidref ref="Slab"/></volume>
+    <volume name="Ghost"><materialref ref="G4_Si"/><solidref ref="Broken"/></volume>
+    <volume name="World">
+      <materialref ref="G4_AIR"/><solidref ref="WorldBox"/>
+      <replicavol number="10">
+        <volumeref ref="Layer"/>
+        <replicate_along_axis>
+          <direction x="1"/>
+          <width value="1" unit="cm"/>
+          <offset value="0" unit="mm"/>
+        </replicate_along_axis>
+      </replicavol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#,
+        );
+        assert!(out.source.contains(
+            "new G4PVReplica(\"Layer_PV\", lv_Layer, lv_World, kXAxis, 10, 10*mm, 0*mm);"
+        ));
+        assert!(!out.source.contains("lv_Ghost"));
+        assert!(out.warnings.iter().any(|w| w.contains("\"Broken\"")));
+        assert!(out.warnings.iter().any(|w| w.contains("\"Ghost\"")));
+    }
+}
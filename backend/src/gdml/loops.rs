@@ -33,6 +33,7 @@ use quick_xml::events::{BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 use std::io::Cursor;
 
+use crate::eval::dependency::replace_identifiers;
 use crate::eval::engine::EvalEngine;
 
 /// Guard against a pathological `from`/`to`/`step`. Geant4 has no cap; this is
@@ -50,60 +51,9 @@ fn substitute_identifier(expr: &str, var: &str, value: i64) -> String {
     if var.is_empty() || !expr.contains(var) {
         return expr.to_string();
     }
-    let bytes = expr.as_bytes();
-    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
-    let mut out = String::with_capacity(expr.len());
-    let mut i = 0usize;
-    while i < bytes.len() {
-        if bytes[i..].starts_with(var.as_bytes()) {
-            let before_ok = i == 0 || !is_ident(bytes[i - 1]);
-            let after = i + var.len();
-            let after_ok = after >= bytes.len() || !is_ident(bytes[after]);
-            if before_ok && after_ok {
-                // Parenthesised so a negative value cannot re-associate:
-                // "2*i" with i = -3 must be 2*(-3), not 2*-3.
-                out.push('(');
-                out.push_str(&value.to_string());
-                out.push(')');
-                i = after;
-                continue;
-            }
-        }
-        // Copy one whole UTF-8 character.
-        let ch_len = expr[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
-        out.push_str(&expr[i..i + ch_len]);
-        i += ch_len;
-    }
-    out
-}
-
-/// `G4GDMLEvaluator::SolveBrackets`: `Slice[i]` -> `Slice_<int(i) - 1>`.
-///
-/// The `- 1` is Geant4's, not a typo: a name indexed from 1 in the file becomes
-/// a suffix counting from 0.
-fn solve_brackets(name: &str, engine: &EvalEngine) -> String {
-    if !name.contains('[') {
-        return name.to_string();
-    }
-    let mut out = String::with_capacity(name.len());
-    let mut rest = name;
-    while let Some(open) = rest.find('[') {
-        let Some(close_rel) = rest[open + 1..].find(']') else {
-            // Unbalanced: leave the remainder alone rather than mangle it.
-            out.push_str(rest);
-            return out;
-        };
-        let close = open + 1 + close_rel;
-        out.push_str(&rest[..open]);
-        for part in rest[open + 1..close].split(',') {
-            let idx = engine.resolve_value(part.trim()).round() as i64 - 1;
-            out.push('_');
-            out.push_str(&idx.to_string());
-        }
-        rest = &rest[close + 1..];
-    }
-    out.push_str(rest);
-    out
+    // Parenthesised so a negative value cannot re-associate: "2*i" with
+    // i = -3 must be 2*(-3), not 2*-3.
+    replace_identifiers(expr, |word| (word == var).then(|| format!("({})", value)))
 }
 
 /// The values a loop's variable takes, in order.
@@ -153,8 +103,9 @@ fn rewrite_element(
         let substituted = apply_bindings(&raw, bindings);
         // `name` and `ref` go through GenerateName in Geant4, which is where
         // SolveBrackets is applied; value attributes only need the variable.
+        // A name whose index is not a number is kept as written.
         let final_value = if key == "name" || key == "ref" {
-            solve_brackets(&substituted, engine)
+            engine.solve_brackets(&substituted).unwrap_or(substituted)
         } else {
             substituted
         };
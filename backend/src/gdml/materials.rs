@@ -222,8 +222,8 @@ fn write_defines(
         !nested.iter().any(|n| n == name)
     })?;
 
-    // Preserved-verbatim define-section elements (<matrix>, <loop>) go last so
-    // anything they reference is already defined.
+    // Preserved-verbatim define-section elements (<loop>) go last so anything
+    // they reference is already defined.
     for raw in raws {
         write_raw(writer, raw)?;
     }
@@ -297,6 +297,9 @@ fn write_define_items(
     for s in &defines.scales {
         write_scale(writer, order, include, s)?;
     }
+    for m in &defines.matrices {
+        write_matrix(writer, order, include, m)?;
+    }
     Ok(())
 }
 
@@ -311,6 +314,7 @@ fn cursor_index(kind: DefineKind) -> usize {
         DefineKind::Position => 4,
         DefineKind::Rotation => 5,
         DefineKind::Scale => 6,
+        DefineKind::Matrix => 7,
     }
 }
 
@@ -328,7 +332,8 @@ fn write_defines_in_source_order(
         + defines.expressions.len()
         + defines.positions.len()
         + defines.rotations.len()
-        + defines.scales.len();
+        + defines.scales.len()
+        + defines.matrices.len();
     if order.define_slots.len() != total {
         return Ok(false);
     }
@@ -338,7 +343,7 @@ fn write_defines_in_source_order(
     // Check that fully before writing anything: a mismatch means the document
     // was assembled some other way, and a half-written block would be worse
     // than the grouped fallback.
-    let mut cursor = [0usize; 7];
+    let mut cursor = [0usize; 8];
     for slot in &order.define_slots {
         let i = cursor_index(slot.kind);
         let n = cursor[i];
@@ -351,13 +356,14 @@ fn write_defines_in_source_order(
             DefineKind::Position => defines.positions.get(n).map(|x| &x.name),
             DefineKind::Rotation => defines.rotations.get(n).map(|x| &x.name),
             DefineKind::Scale => defines.scales.get(n).map(|x| &x.name),
+            DefineKind::Matrix => defines.matrices.get(n).map(|x| &x.name),
         };
         if found != Some(&slot.name) {
             return Ok(false);
         }
     }
 
-    let mut cursor = [0usize; 7];
+    let mut cursor = [0usize; 8];
     for slot in &order.define_slots {
         let i = cursor_index(slot.kind);
         let n = cursor[i];
@@ -372,6 +378,7 @@ fn write_defines_in_source_order(
             DefineKind::Position => write_position(writer, order, include, &defines.positions[n])?,
             DefineKind::Rotation => write_rotation(writer, order, include, &defines.rotations[n])?,
             DefineKind::Scale => write_scale(writer, order, include, &defines.scales[n])?,
+            DefineKind::Matrix => write_matrix(writer, order, include, &defines.matrices[n])?,
         }
     }
     Ok(true)
@@ -533,6 +540,24 @@ fn write_scale(
     Ok(())
 }
 
+fn write_matrix(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    order: &DocumentOrder,
+    include: &dyn Fn(&str) -> bool,
+    m: &Matrix,
+) -> Result<()> {
+    if !include(&m.name) {
+        return Ok(());
+    }
+    write_comments(writer, order, "define", Some(&m.name))?;
+    let mut elem = BytesStart::new("matrix");
+    elem.push_attribute(("name", m.name.as_str()));
+    elem.push_attribute(("coldim", m.coldim.as_str()));
+    elem.push_attribute(("values", m.values.as_str()));
+    writer.write_event(Event::Empty(elem))?;
+    Ok(())
+}
+
 fn write_materials(
     writer: &mut Writer<Cursor<Vec<u8>>>,
     materials: &MaterialSection,
@@ -1518,7 +1543,12 @@ fn write_solids(
         }
     }
 
-    // Preserved-verbatim solids-section elements (<opticalsurface>).
+    for surface in &solids.optical_surfaces {
+        write_comments(writer, order, "solids", Some(&surface.name))?;
+        write_optical_surface(writer, surface)?;
+    }
+
+    // Preserved-verbatim solids-section elements, e.g. a <loop>.
     for raw in raws {
         write_raw(writer, raw)?;
     }
@@ -1736,17 +1766,73 @@ fn write_structure(
         writer.write_event(Event::End(BytesEnd::new("volume")))?;
     }
 
-    // Preserved-verbatim structure-section elements (<assembly>, <skinsurface>,
-    // <bordersurface>). Surfaces reference volumes, so they go last.
+    // Preserved-verbatim structure-section elements (<assembly>, <loop>).
     for raw in raws {
         write_raw(writer, raw)?;
     }
 
+    // Surfaces look their volumes and placements up by name as they are read
+    // (G4GDMLReadStructure::SkinSurfaceRead / BorderSurfaceRead), so they go
+    // after everything that can define one.
+    for surface in &structure.skin_surfaces {
+        write_comments(writer, order, "structure", Some(&surface.name))?;
+        let mut elem = BytesStart::new("skinsurface");
+        elem.push_attribute(("name", surface.name.as_str()));
+        elem.push_attribute(("surfaceproperty", surface.surface_property.as_str()));
+        writer.write_event(Event::Start(elem))?;
+        let mut vref = BytesStart::new("volumeref");
+        vref.push_attribute(("ref", surface.volume_ref.as_str()));
+        writer.write_event(Event::Empty(vref))?;
+        writer.write_event(Event::End(BytesEnd::new("skinsurface")))?;
+    }
+    for surface in &structure.border_surfaces {
+        write_comments(writer, order, "structure", Some(&surface.name))?;
+        let mut elem = BytesStart::new("bordersurface");
+        elem.push_attribute(("name", surface.name.as_str()));
+        elem.push_attribute(("surfaceproperty", surface.surface_property.as_str()));
+        writer.write_event(Event::Start(elem))?;
+        for r in &surface.physvol_refs {
+            let mut pref = BytesStart::new("physvolref");
+            pref.push_attribute(("ref", r.as_str()));
+            writer.write_event(Event::Empty(pref))?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("bordersurface")))?;
+    }
+
     write_comments(writer, order, "structure", None)?;
     writer.write_event(Event::End(BytesEnd::new("structure")))?;
     Ok(())
 }
 
+fn write_optical_surface(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    surface: &OpticalSurface,
+) -> Result<()> {
+    let mut elem = BytesStart::new("opticalsurface");
+    elem.push_attribute(("name", surface.name.as_str()));
+    elem.push_attribute(("model", surface.model.as_str()));
+    elem.push_attribute(("finish", surface.finish.as_str()));
+    elem.push_attribute(("type", surface.r#type.as_str()));
+    if let Some(ref v) = surface.value {
+        elem.push_attribute(("value", v.as_str()));
+    }
+    if surface.properties.is_empty() {
+        writer.write_event(Event::Empty(elem))?;
+        return Ok(());
+    }
+    writer.write_event(Event::Start(elem))?;
+    for prop in &surface.properties {
+        let mut e = BytesStart::new("property");
+        e.push_attribute(("name", prop.name.as_str()));
+        if let Some(ref r) = prop.ref_name {
+            e.push_attribute(("ref", r.as_str()));
+        }
+        writer.write_event(Event::Empty(e))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("opticalsurface")))?;
+    Ok(())
+}
+
 fn write_setup(writer: &mut Writer<Cursor<Vec<u8>>>, setup: &SetupSection) -> Result<()> {
     let mut elem = BytesStart::new("setup");
     elem.push_attribute(("name", setup.name.as_str()));
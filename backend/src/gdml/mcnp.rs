@@ -0,0 +1,1084 @@
+//! MCNP cell and surface cards for a document, for transport codes that
+//! describe geometry as regions bounded by quadric surfaces.
+//!
+//! Only solids whose surfaces are planes, spheres, cylinders and cones can be
+//! written this way: boxes, trapezoids (`trd`), tubes, cones, spheres, orbs,
+//! and booleans and multi-unions of those. Everything else is reported.
+//!
+//! The hierarchy is flattened: each placed volume becomes one cell, its solid
+//! minus the solids of its daughters, with every placement composed into the
+//! surfaces themselves so no `TR` cards are needed. A surface is kept as a
+//! general quadric while it is moved into place and written as the simplest
+//! card that describes it — `PX`, `SO`, `C/Z`, `K/Z` and so on, or `GQ` when
+//! nothing simpler fits — and surfaces shared between cells are written once.
+//!
+//! Materials are weight fractions by natural element (`ZZZ000`), or by
+//! nuclide where the document gives isotopes; lengths are in cm.
+
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use super::geant4::{
+    density, int, molar_mass, num, placement_position, placement_rotation, Values,
+};
+use super::materials::find_nist_material;
+use super::model::*;
+use super::units;
+use crate::eval::engine::EvalEngine;
+
+/// Cells beyond this are not written; a replicated, flattened hierarchy can
+/// grow past anything MCNP would want to track.
+const MAX_CELLS: usize = 100_000;
+
+/// An MCNP input deck and what could not be carried over into it.
+#[derive(Debug, Clone)]
+pub struct McnpDeck {
+    pub file_name: String,
+    pub deck: String,
+    pub warnings: Vec<String>,
+}
+
+/// Generate the cell, surface and material cards for `doc`.
+///
+/// Fails only when the world solid has no quadric form, since every other
+/// cell lives inside it.
+pub fn generate_mcnp_deck(doc: &GdmlDocument, engine: &EvalEngine) -> Result<McnpDeck> {
+    let mut g = Generator::new(doc, engine);
+    let world = &doc.setup.world_ref;
+    let Some(world_vol) = doc.structure.volumes.iter().find(|v| &v.name == world) else {
+        bail!("The world volume \"{}\" is not defined", world);
+    };
+    let Some(world_region) = g.region(&world_vol.solid_ref, &Frame::IDENTITY) else {
+        bail!(
+            "The world solid \"{}\" cannot be written as quadric surfaces",
+            world_vol.solid_ref
+        );
+    };
+    g.cell(world_vol, world_region.clone(), world, 0);
+    let outside = g.cells.len() + 1;
+    g.cells.push(format!(
+        "c outside the world\n{}",
+        card(
+            &[
+                outside.to_string(),
+                "0".to_string(),
+                world_region.complement().expression(),
+                "imp:n=0".to_string(),
+            ]
+            .join(" ")
+        )
+    ));
+    if g.truncated {
+        g.warnings.push(format!(
+            "The flattened geometry has more than {} cells; the rest were not written.",
+            MAX_CELLS
+        ));
+    }
+
+    let stem = doc
+        .filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(&doc.filename);
+    let stem = stem.strip_suffix(".gdml").unwrap_or(stem);
+    Ok(McnpDeck {
+        file_name: format!("{}.mcnp", stem),
+        deck: g.finish(),
+        warnings: g.warnings,
+    })
+}
+
+/// One MCNP card: wrapped at 80 columns, continuation lines indented five.
+fn card(text: &str) -> String {
+    let mut out = String::new();
+    let mut line = String::new();
+    for word in text.split(' ').filter(|w| !w.is_empty()) {
+        if !line.trim().is_empty() && line.len() + 1 + word.len() > 80 {
+            out.push_str(&line);
+            out.push('\n');
+            line = "     ".to_string();
+        } else if !line.trim().is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    out.push_str(&line);
+    out
+}
+
+/// A comment card, cut to MCNP's 80 columns.
+fn comment(text: &str) -> String {
+    format!("c {}", text).chars().take(80).collect()
+}
+
+// ─── Geometry ────────────────────────────────────────────────────────────────
+
+/// A rotation followed by a translation (cm), local to global.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    /// Row-major.
+    r: [[f64; 3]; 3],
+    t: [f64; 3],
+}
+
+impl Frame {
+    const IDENTITY: Frame = Frame {
+        r: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        t: [0.0; 3],
+    };
+
+    /// A placement from a position in mm and a GDML rotation in radians,
+    /// applied inverted, Rx(-x)·Ry(-y)·Rz(-z), as Geant4's reader does.
+    fn placement(position: [f64; 3], rotation: [f64; 3]) -> Frame {
+        let [x, y, z] = rotation.map(|a| -a);
+        let (sx, cx) = x.sin_cos();
+        let (sy, cy) = y.sin_cos();
+        let (sz, cz) = z.sin_cos();
+        let rx = [[1.0, 0.0, 0.0], [0.0, cx, -sx], [0.0, sx, cx]];
+        let ry = [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]];
+        let rz = [[cz, -sz, 0.0], [sz, cz, 0.0], [0.0, 0.0, 1.0]];
+        Frame {
+            r: mul(mul(rx, ry), rz),
+            t: position.map(|v| v / 10.0),
+        }
+    }
+
+    /// This frame applied after `inner`.
+    fn then(&self, inner: &Frame) -> Frame {
+        let t = self.rotate(inner.t);
+        Frame {
+            r: mul(self.r, inner.r),
+            t: [0, 1, 2].map(|i| t[i] + self.t[i]),
+        }
+    }
+
+    fn rotate(&self, v: [f64; 3]) -> [f64; 3] {
+        self.r.map(|row| dot(row, v))
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn mul(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+/// `xᵀ·a·x + b·x + c`, negative inside.
+#[derive(Debug, Clone, Copy)]
+struct Quadric {
+    a: [[f64; 3]; 3],
+    b: [f64; 3],
+    c: f64,
+}
+
+impl Quadric {
+    /// The half-space `n·x < d`.
+    fn plane(n: [f64; 3], d: f64) -> Self {
+        Self {
+            a: [[0.0; 3]; 3],
+            b: n,
+            c: -d,
+        }
+    }
+
+    /// `x² + y² + s·z² + 2·k·z + c`, a sphere, cylinder or cone about z.
+    fn about_z(s: f64, k: f64, c: f64) -> Self {
+        Self {
+            a: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, s]],
+            b: [0.0, 0.0, 2.0 * k],
+            c,
+        }
+    }
+
+    /// A radial bound about z whose radius runs linearly from `r1` at `-h` to
+    /// `r2` at `+h`: a cylinder when they agree, a cone otherwise.
+    fn radial(r1: f64, r2: f64, h: f64) -> Self {
+        let slope = (r2 - r1) / (2.0 * h);
+        let mid = 0.5 * (r1 + r2);
+        Self::about_z(-slope * slope, -mid * slope, -mid * mid)
+    }
+
+    /// The same surface in the frame `f` maps into: with `x = Rᵀ(y - t)`,
+    /// `a' = R·a·Rᵀ`, `b' = R·b - 2·a'·t` and `c' = tᵀ·a'·t - R·b·t + c`.
+    fn moved(&self, f: &Frame) -> Self {
+        let rt = [0, 1, 2].map(|i| [f.r[0][i], f.r[1][i], f.r[2][i]]);
+        let a = mul(mul(f.r, self.a), rt);
+        let rb = f.rotate(self.b);
+        let at = a.map(|row| dot(row, f.t));
+        Self {
+            a,
+            b: [0, 1, 2].map(|i| rb[i] - 2.0 * at[i]),
+            c: dot(f.t, at) - dot(rb, f.t) + self.c,
+        }
+    }
+
+    /// `A..K` of MCNP's `GQ` card.
+    fn coefficients(&self) -> [f64; 10] {
+        let (a, b) = (self.a, self.b);
+        [
+            a[0][0],
+            a[1][1],
+            a[2][2],
+            a[0][1] + a[1][0],
+            a[1][2] + a[2][1],
+            a[0][2] + a[2][0],
+            b[0],
+            b[1],
+            b[2],
+            self.c,
+        ]
+    }
+}
+
+/// An MCNP surface card body for `GQ` coefficients, in the simplest form
+/// that fits, and the coefficients' canonical scale so equal surfaces compare
+/// equal. The `bool` is set when the sign had to flip, which flips the sense.
+fn surface_card(q: [f64; 10]) -> Option<(String, bool)> {
+    let quadratic = q[..6].iter().fold(0.0_f64, |m, v| m.max(v.abs()));
+    let scale = if quadratic > 0.0 {
+        quadratic
+    } else {
+        (q[6] * q[6] + q[7] * q[7] + q[8] * q[8]).sqrt()
+    };
+    if !scale.is_finite() || scale == 0.0 {
+        return None;
+    }
+    let first = q[..9].iter().find(|v| v.abs() > 1e-12 * scale)?;
+    let flipped = *first < 0.0;
+    let s = if flipped { -scale } else { scale };
+    let q = q.map(|v| {
+        let v = v / s;
+        if v.abs() < 1e-12 {
+            0.0
+        } else {
+            v
+        }
+    });
+    let [a, b, c, d, e, f, g, h, j, k] = q;
+    let zero = |vs: &[f64]| vs.iter().all(|v| *v == 0.0);
+    let body = if quadratic == 0.0 {
+        match (g != 0.0, h != 0.0, j != 0.0) {
+            (true, false, false) => format!("px {}", num(-k / g)),
+            (false, true, false) => format!("py {}", num(-k / h)),
+            (false, false, true) => format!("pz {}", num(-k / j)),
+            _ => format!("p {} {} {} {}", num(g), num(h), num(j), num(-k)),
+        }
+    } else if zero(&[d, e, f]) && a == b && b == c {
+        let centre = [g, h, j].map(|v| -v / (2.0 * a));
+        let r = (dot(centre, centre) - k / a).sqrt();
+        if centre == [0.0; 3] {
+            format!("so {}", num(r))
+        } else {
+            format!(
+                "s {} {} {} {}",
+                num(centre[0]),
+                num(centre[1]),
+                num(centre[2]),
+                num(r)
+            )
+        }
+    } else if let Some(body) = about_axis([a, b, c], [g, h, j], k).filter(|_| zero(&[d, e, f])) {
+        body
+    } else {
+        format!(
+            "gq {}",
+            q.iter().map(|v| num(*v)).collect::<Vec<_>>().join(" ")
+        )
+    };
+    Some((body, flipped))
+}
+
+/// A cylinder (`C/X`) or cone (`K/X`) parallel to a coordinate axis, given
+/// diagonal quadratic terms `a`, linear terms `b` and constant `k`.
+fn about_axis(a: [f64; 3], b: [f64; 3], k: f64) -> Option<String> {
+    let names = ["x", "y", "z"];
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        // The card lists the other two coordinates in x, y, z order.
+        let (u, v) = (u.min(v), u.max(v));
+        if a[u] != a[v] || a[u] <= 0.0 {
+            continue;
+        }
+        let (pu, pv) = (-b[u] / (2.0 * a[u]), -b[v] / (2.0 * a[u]));
+        if a[axis] == 0.0 && b[axis] == 0.0 {
+            let r = (pu * pu + pv * pv - k / a[u]).sqrt();
+            return Some(if pu == 0.0 && pv == 0.0 {
+                format!("c{} {}", names[axis], num(r))
+            } else {
+                format!("c/{} {} {} {}", names[axis], num(pu), num(pv), num(r))
+            });
+        }
+        if a[axis] < 0.0 {
+            let t2 = -a[axis] / a[u];
+            let apex = b[axis] / (2.0 * a[u] * t2);
+            return Some(if pu == 0.0 && pv == 0.0 {
+                format!("k{} {} {}", names[axis], num(apex), num(t2))
+            } else {
+                format!(
+                    "k/{} {} {} {} {}",
+                    names[axis],
+                    num(pu),
+                    num(pv),
+                    num(apex),
+                    num(t2)
+                )
+            });
+        }
+    }
+    None
+}
+
+/// A region of space as a boolean of surface senses.
+#[derive(Debug, Clone, PartialEq)]
+enum Region {
+    /// Surface number and whether the region is its negative side.
+    Side(usize, bool),
+    All(Vec<Region>),
+    Any(Vec<Region>),
+}
+
+impl Region {
+    /// Everything outside, pushed down to the surfaces by De Morgan, since
+    /// MCNP's `#` needs parentheses around anything but a cell.
+    fn complement(&self) -> Region {
+        match self {
+            Region::Side(s, negative) => Region::Side(*s, !negative),
+            Region::All(parts) => Region::Any(parts.iter().map(Region::complement).collect()),
+            Region::Any(parts) => Region::All(parts.iter().map(Region::complement).collect()),
+        }
+    }
+
+    fn all(parts: Vec<Region>) -> Region {
+        let mut flat = Vec::new();
+        for p in parts {
+            match p {
+                Region::All(inner) => flat.extend(inner),
+                p => flat.push(p),
+            }
+        }
+        if flat.len() == 1 {
+            flat.pop().unwrap()
+        } else {
+            Region::All(flat)
+        }
+    }
+
+    fn any(parts: Vec<Region>) -> Region {
+        let mut flat = Vec::new();
+        for p in parts {
+            match p {
+                Region::Any(inner) => flat.extend(inner),
+                p => flat.push(p),
+            }
+        }
+        if flat.len() == 1 {
+            flat.pop().unwrap()
+        } else {
+            Region::Any(flat)
+        }
+    }
+
+    /// The cell-card geometry: intersection by juxtaposition, union by `:`.
+    fn expression(&self) -> String {
+        match self {
+            Region::Side(s, true) => format!("-{}", s),
+            Region::Side(s, false) => s.to_string(),
+            Region::All(parts) => parts
+                .iter()
+                .map(|p| match p {
+                    Region::Any(_) => format!("({})", p.expression()),
+                    p => p.expression(),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Region::Any(parts) => parts
+                .iter()
+                .map(|p| match p {
+                    Region::All(_) => format!("({})", p.expression()),
+                    p => p.expression(),
+                })
+                .collect::<Vec<_>>()
+                .join(":"),
+        }
+    }
+}
+
+// ─── Materials ───────────────────────────────────────────────────────────────
+
+/// Weight fractions by ZAID.
+type Composition = Vec<(u32, f64)>;
+
+fn add_scaled(into: &mut Composition, parts: &[(u32, f64)], weight: f64) {
+    for (zaid, w) in parts {
+        match into.iter_mut().find(|(z, _)| z == zaid) {
+            Some(entry) => entry.1 += w * weight,
+            None => into.push((*zaid, w * weight)),
+        }
+    }
+}
+
+fn normalised(mut parts: Composition) -> Option<Composition> {
+    let total: f64 = parts.iter().map(|(_, w)| w).sum();
+    if !total.is_finite() || total <= 0.0 {
+        return None;
+    }
+    for (_, w) in &mut parts {
+        *w /= total;
+    }
+    Some(parts)
+}
+
+// ─── Generator ───────────────────────────────────────────────────────────────
+
+struct Generator<'a> {
+    doc: &'a GdmlDocument,
+    engine: &'a EvalEngine,
+    surfaces: Vec<String>,
+    surface_numbers: HashMap<String, usize>,
+    cells: Vec<String>,
+    /// Material name → number on its `m` card, `None` when it is written as
+    /// void.
+    materials: HashMap<String, Option<usize>>,
+    material_cards: Vec<String>,
+    reported: HashSet<String>,
+    truncated: bool,
+    warnings: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(doc: &'a GdmlDocument, engine: &'a EvalEngine) -> Self {
+        Self {
+            doc,
+            engine,
+            surfaces: Vec::new(),
+            surface_numbers: HashMap::new(),
+            cells: Vec::new(),
+            materials: HashMap::new(),
+            material_cards: Vec::new(),
+            reported: HashSet::new(),
+            truncated: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Warn once per subject, however many placements run into it.
+    fn report(&mut self, subject: &str, warning: String) {
+        if self.reported.insert(subject.to_string()) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// The inside of local surface `q` placed by `frame`.
+    fn inside(&mut self, q: Quadric, frame: &Frame) -> Region {
+        let Some((body, flipped)) = surface_card(q.moved(frame).coefficients()) else {
+            // A degenerate surface (a zero radius, say) bounds nothing.
+            return Region::All(Vec::new());
+        };
+        let next = self.surfaces.len() + 1;
+        let number = *self.surface_numbers.entry(body.clone()).or_insert(next);
+        if number == next {
+            self.surfaces.push(card(&format!("{} {}", number, body)));
+        }
+        Region::Side(number, !flipped)
+    }
+
+    fn outside(&mut self, q: Quadric, frame: &Frame) -> Region {
+        self.inside(q, frame).complement()
+    }
+
+    /// `-h < z < h`.
+    fn slab(&mut self, h: f64, frame: &Frame) -> Vec<Region> {
+        vec![
+            self.inside(Quadric::plane([0.0, 0.0, 1.0], h), frame),
+            self.inside(Quadric::plane([0.0, 0.0, -1.0], h), frame),
+        ]
+    }
+
+    /// The wedge `phi..phi + dphi` about z, or nothing for a full turn.
+    fn wedge(&mut self, phi: f64, dphi: f64, frame: &Frame) -> Option<Region> {
+        if dphi >= 2.0 * PI {
+            return None;
+        }
+        let end = phi + dphi;
+        let sides = vec![
+            self.inside(Quadric::plane([phi.sin(), -phi.cos(), 0.0], 0.0), frame),
+            self.inside(Quadric::plane([-end.sin(), end.cos(), 0.0], 0.0), frame),
+        ];
+        Some(if dphi <= PI {
+            Region::all(sides)
+        } else {
+            Region::any(sides)
+        })
+    }
+
+    /// Points whose polar angle exceeds `theta`.
+    fn beyond_theta(&mut self, theta: f64, frame: &Frame) -> Region {
+        let below = self.inside(Quadric::plane([0.0, 0.0, 1.0], 0.0), frame);
+        if (theta - 0.5 * PI).abs() < 1e-12 {
+            return below;
+        }
+        let cone = Quadric::about_z(-theta.tan().powi(2), 0.0, 0.0);
+        if theta < 0.5 * PI {
+            let outside = self.outside(cone, frame);
+            Region::any(vec![below, outside])
+        } else {
+            let inside = self.inside(cone, frame);
+            Region::all(vec![below, inside])
+        }
+    }
+
+    /// The region solid `name` fills when placed by `frame`, or `None`, with
+    /// a warning, when it has no quadric form.
+    fn region(&mut self, name: &str, frame: &Frame) -> Option<Region> {
+        let doc = self.doc;
+        let Some(solid) = doc.solids.solids.iter().find(|s| s.name() == name) else {
+            self.report(
+                name,
+                format!("Solid \"{}\" is referenced but not defined.", name),
+            );
+            return None;
+        };
+        let engine = self.engine;
+        // Lengths in cm from here on.
+        let len = |v: &Values, e: &String| v.len(e) / 10.0;
+        let opt_len = |v: &Values, e: &Option<String>| v.opt_len(e) / 10.0;
+        Some(match solid {
+            Solid::Box(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let [hx, hy, hz] = [&s.x, &s.y, &s.z].map(|e| 0.5 * len(&v, e));
+                let mut faces = Vec::new();
+                for (axis, h) in [hx, hy, hz].into_iter().enumerate() {
+                    for sign in [1.0, -1.0] {
+                        let mut n = [0.0; 3];
+                        n[axis] = sign;
+                        faces.push(self.inside(Quadric::plane(n, h), frame));
+                    }
+                }
+                Region::all(faces)
+            }
+            Solid::Trd(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let [x1, x2, y1, y2, hz] =
+                    [&s.x1, &s.x2, &s.y1, &s.y2, &s.z].map(|e| 0.5 * len(&v, e));
+                // Each slanted face passes through the half-widths at -hz
+                // and +hz.
+                let mut faces = self.slab(hz, frame);
+                for (axis, w1, w2) in [(0, x1, x2), (1, y1, y2)] {
+                    for sign in [1.0, -1.0] {
+                        let mut n = [0.0, 0.0, -(w2 - w1)];
+                        n[axis] = sign * 2.0 * hz;
+                        faces.push(self.inside(Quadric::plane(n, hz * (w1 + w2)), frame));
+                    }
+                }
+                Region::all(faces)
+            }
+            Solid::Tube(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let (rmin, rmax, hz) =
+                    (opt_len(&v, &s.rmin), len(&v, &s.rmax), 0.5 * len(&v, &s.z));
+                let mut parts = self.slab(hz, frame);
+                parts.push(self.inside(Quadric::about_z(0.0, 0.0, -rmax * rmax), frame));
+                if rmin > 0.0 {
+                    parts.push(self.outside(Quadric::about_z(0.0, 0.0, -rmin * rmin), frame));
+                }
+                let phi = v.opt_angle(&s.startphi, 0.0);
+                parts.extend(self.wedge(phi, v.dphi(&s.deltaphi), frame));
+                Region::all(parts)
+            }
+            Solid::Cone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let hz = 0.5 * len(&v, &s.z);
+                let (rmin1, rmin2) = (opt_len(&v, &s.rmin1), opt_len(&v, &s.rmin2));
+                let (rmax1, rmax2) = (len(&v, &s.rmax1), len(&v, &s.rmax2));
+                let mut parts = self.slab(hz, frame);
+                parts.push(self.inside(Quadric::radial(rmax1, rmax2, hz), frame));
+                if rmin1 > 0.0 || rmin2 > 0.0 {
+                    parts.push(self.outside(Quadric::radial(rmin1, rmin2, hz), frame));
+                }
+                let phi = v.opt_angle(&s.startphi, 0.0);
+                parts.extend(self.wedge(phi, v.dphi(&s.deltaphi), frame));
+                Region::all(parts)
+            }
+            Solid::Sphere(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let (rmin, rmax) = (opt_len(&v, &s.rmin), len(&v, &s.rmax));
+                let mut parts = vec![self.inside(Quadric::about_z(1.0, 0.0, -rmax * rmax), frame)];
+                if rmin > 0.0 {
+                    parts.push(self.outside(Quadric::about_z(1.0, 0.0, -rmin * rmin), frame));
+                }
+                let phi = v.opt_angle(&s.startphi, 0.0);
+                parts.extend(self.wedge(phi, v.dphi(&s.deltaphi), frame));
+                let theta = v.opt_angle(&s.starttheta, 0.0);
+                let end = theta + v.opt_angle(&s.deltatheta, PI);
+                if theta > 0.0 {
+                    parts.push(self.beyond_theta(theta, frame));
+                }
+                if end < PI {
+                    parts.push(self.beyond_theta(end, frame).complement());
+                }
+                Region::all(parts)
+            }
+            Solid::Orb(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let r = len(&v, &s.r);
+                self.inside(Quadric::about_z(1.0, 0.0, -r * r), frame)
+            }
+            Solid::Boolean(s) => {
+                let first = frame.then(&Frame::placement(
+                    placement_position(engine, &s.first_position),
+                    placement_rotation(engine, &s.first_rotation),
+                ));
+                let second = frame.then(&Frame::placement(
+                    placement_position(engine, &s.position),
+                    placement_rotation(engine, &s.rotation),
+                ));
+                let a = self.operand(name, &s.first_ref, &first)?;
+                let b = self.operand(name, &s.second_ref, &second)?;
+                match s.operation {
+                    BooleanOp::Union => Region::any(vec![a, b]),
+                    BooleanOp::Subtraction => Region::all(vec![a, b.complement()]),
+                    BooleanOp::Intersection => Region::all(vec![a, b]),
+                }
+            }
+            Solid::MultiUnion(s) => {
+                let mut nodes = Vec::new();
+                for node in &s.nodes {
+                    let placed = frame.then(&Frame::placement(
+                        placement_position(engine, &node.position),
+                        placement_rotation(engine, &node.rotation),
+                    ));
+                    nodes.push(self.operand(name, &node.solid_ref, &placed)?);
+                }
+                Region::any(nodes)
+            }
+            _ => {
+                self.report(
+                    name,
+                    format!(
+                        "Solid \"{}\" is not bounded by planes, spheres, cylinders and cones \
+                         alone, so it has no MCNP form.",
+                        name
+                    ),
+                );
+                return None;
+            }
+        })
+    }
+
+    /// An operand of a boolean, reporting the boolean when the operand has no
+    /// quadric form.
+    fn operand(&mut self, owner: &str, name: &str, frame: &Frame) -> Option<Region> {
+        let region = self.region(name, frame);
+        if region.is_none() {
+            self.report(
+                owner,
+                format!(
+                    "Solid \"{}\" has no MCNP form because its operand \"{}\" has none.",
+                    owner, name
+                ),
+            );
+        }
+        region
+    }
+
+    /// Write the cell of volume `v`, filling `region` minus its daughters,
+    /// and the cells of everything placed inside it.
+    fn cell(&mut self, v: &Volume, region: Region, path: &str, depth: usize) {
+        if self.cells.len() >= MAX_CELLS {
+            self.truncated = true;
+            return;
+        }
+        // Reserve the number first, so a mother is numbered before its
+        // daughters.
+        let index = self.cells.len();
+        self.cells.push(String::new());
+        let mut daughters = Vec::new();
+        if depth < 64 {
+            for (child, frame, child_path) in self.daughters(v, path) {
+                let Some(child_region) = self.region(&child.solid_ref, &frame) else {
+                    self.report(
+                        &format!("volume:{}", child.name),
+                        format!(
+                            "Volume \"{}\" was left out with everything inside it; where it \
+                             is placed, its mother's material fills the space.",
+                            child.name
+                        ),
+                    );
+                    continue;
+                };
+                daughters.push(child_region.complement());
+                self.cell(child, child_region, &child_path, depth + 1);
+            }
+        }
+        let mut geometry = vec![region];
+        geometry.extend(daughters);
+        let geometry = Region::all(geometry).expression();
+        let fill = match self.material(&v.material_ref) {
+            Some((number, rho)) => format!("{} {}", number, num(-rho)),
+            None => "0".to_string(),
+        };
+        self.cells[index] = format!(
+            "{}\n{}",
+            comment(path),
+            card(&format!("{} {} {} imp:n=1", index + 1, fill, geometry))
+        );
+    }
+
+    /// The volumes placed directly in `v`, each with its frame and path.
+    fn daughters(&mut self, v: &Volume, path: &str) -> Vec<(&'a Volume, Frame, String)> {
+        let doc = self.doc;
+        let engine = self.engine;
+        let find = |name: &str| doc.structure.volumes.iter().find(|x| x.name == name);
+        let mut out = Vec::new();
+        for pv in &v.physvols {
+            let Some(child) = find(&pv.volume_ref) else {
+                self.report(
+                    &format!("placement:{}", pv.volume_ref),
+                    match &pv.file_ref {
+                        Some(file) => format!(
+                            "The placement of file \"{}\" was left out; load the included file \
+                             with the document to resolve it.",
+                            file.name
+                        ),
+                        None => format!(
+                            "The placement of \"{}\" was left out because that volume is not \
+                             defined.",
+                            pv.volume_ref
+                        ),
+                    },
+                );
+                continue;
+            };
+            let frame = Frame::placement(
+                placement_position(engine, &pv.position),
+                placement_rotation(engine, &pv.rotation),
+            );
+            out.push((child, frame, format!("{}/{}", path, child.name)));
+        }
+        let Some(rep) = &v.replica else {
+            return out;
+        };
+        let Some(child) = find(&rep.volume_ref) else {
+            return out;
+        };
+        // Copies go where G4ReplicaNavigation::ComputeTransformation puts
+        // them; unit-less values are mm or rad, as Geant4 reads them.
+        let value = |expr: &str, unit: &Option<String>| {
+            let v = engine.resolve_value(expr);
+            unit.as_deref().map_or(v, |u| units::apply_unit(v, u))
+        };
+        let n = int(engine.resolve_value(&rep.number)).clamp(0, MAX_CELLS as i64);
+        let width = value(&rep.width, &rep.width_unit);
+        match rep.curvilinear_axis.as_deref() {
+            Some("rho") => self.report(
+                &format!("replica:{}", v.name),
+                format!(
+                    "Volume \"{}\": the radial replica of \"{}\" slices the solid itself; it \
+                     was left out.",
+                    v.name, rep.volume_ref
+                ),
+            ),
+            Some("phi") => {
+                let offset = value(&rep.offset, &rep.offset_unit);
+                for i in 0..n {
+                    let turn = -(offset + width * (i as f64 + 0.5));
+                    let frame = Frame::placement([0.0; 3], [0.0, 0.0, turn]);
+                    out.push((child, frame, format!("{}/{}[{}]", path, child.name, i)));
+                }
+            }
+            _ => {
+                let axis = rep
+                    .direction
+                    .iter()
+                    .position(|d| {
+                        d.as_deref()
+                            .is_some_and(|e| engine.resolve_value(e).abs() > 0.0)
+                    })
+                    .unwrap_or(2);
+                for i in 0..n {
+                    let mut t = [0.0; 3];
+                    t[axis] = -0.5 * width * (n - 1) as f64 + width * i as f64;
+                    let frame = Frame::placement(t, [0.0; 3]);
+                    out.push((child, frame, format!("{}/{}[{}]", path, child.name, i)));
+                }
+            }
+        }
+        out
+    }
+
+    // ─── Materials ───────────────────────────────────────────────────────────
+
+    /// The `m` card number and density (g/cm3) of material `name`, writing
+    /// the card on first use; `None` for void.
+    fn material(&mut self, name: &str) -> Option<(usize, f64)> {
+        let rho = match self.doc.materials.materials.iter().find(|m| m.name == name) {
+            Some(m) => density(self.engine, m),
+            None => find_nist_material(name).map(|n| n.density),
+        };
+        if let Some(number) = self.materials.get(name) {
+            return number.zip(rho);
+        }
+        let number = match rho {
+            // G4_Galactic and the like.
+            Some(rho) if rho < 1e-10 => None,
+            Some(_) => match self.composition(name, 0) {
+                Some(parts) => {
+                    let number = self.material_cards.len() + 1;
+                    let mut text = format!("m{}", number);
+                    for (zaid, w) in parts {
+                        let _ = write!(text, " {} {}", zaid, num(-w));
+                    }
+                    self.material_cards
+                        .push(format!("{}\n{}", comment(name), card(&text)));
+                    Some(number)
+                }
+                None => {
+                    self.warnings.push(format!(
+                        "Material \"{}\" has no composition to write; its cells are void.",
+                        name
+                    ));
+                    None
+                }
+            },
+            None => {
+                self.warnings.push(format!(
+                    "Material \"{}\" has no density that evaluates; its cells are void.",
+                    name
+                ));
+                None
+            }
+        };
+        self.materials.insert(name.to_string(), number);
+        number.zip(rho)
+    }
+
+    /// Weight fractions of material `name`, from the document or the NIST
+    /// table.
+    fn composition(&self, name: &str, depth: usize) -> Option<Composition> {
+        if depth > 16 {
+            return None;
+        }
+        let engine = self.engine;
+        let Some(m) = self.doc.materials.materials.iter().find(|m| m.name == name) else {
+            let nist = find_nist_material(name)?;
+            if let Some(z) = nist.z {
+                return Some(vec![(z * 1000, 1.0)]);
+            }
+            let mut parts = Composition::new();
+            for c in &nist.components {
+                let z = find_nist_material(&c.ref_name)?.z?;
+                add_scaled(&mut parts, &[(z * 1000, 1.0)], c.n);
+            }
+            return normalised(parts);
+        };
+        if let Some(z) = &m.z {
+            return Some(vec![(int(engine.resolve_value(z)) as u32 * 1000, 1.0)]);
+        }
+        let mut parts = Composition::new();
+        for c in &m.components {
+            match c {
+                MaterialComponent::Composite { n, ref_name } => {
+                    let (a, el) = self.element(ref_name)?;
+                    add_scaled(&mut parts, &el, engine.resolve_value(n) * a);
+                }
+                MaterialComponent::Fraction { n, ref_name } => {
+                    let n = engine.resolve_value(n);
+                    let is_element = self
+                        .doc
+                        .materials
+                        .elements
+                        .iter()
+                        .any(|e| &e.name == ref_name)
+                        || (!self
+                            .doc
+                            .materials
+                            .materials
+                            .iter()
+                            .any(|x| &x.name == ref_name)
+                            && find_nist_material(ref_name).is_none());
+                    if is_element {
+                        add_scaled(&mut parts, &self.element(ref_name)?.1, n);
+                    } else {
+                        add_scaled(&mut parts, &self.composition(ref_name, depth + 1)?, n);
+                    }
+                }
+            }
+        }
+        normalised(parts)
+    }
+
+    /// Molar mass (g/mole) and weight fractions of element `name`. One the
+    /// document does not define is taken from the NIST table by symbol.
+    fn element(&self, name: &str) -> Option<(f64, Composition)> {
+        let engine = self.engine;
+        let Some(el) = self.doc.materials.elements.iter().find(|e| e.name == name) else {
+            if let Some(nist) = find_nist_material(&format!("G4_{}", name)) {
+                return Some((nist.atom_value?, vec![(nist.z? * 1000, 1.0)]));
+            }
+            // Some files name a single-element material where an element
+            // belongs ("Oxygen" with Z="8"); ROOT's table accepts such names
+            // too.
+            let m = self
+                .doc
+                .materials
+                .materials
+                .iter()
+                .find(|m| m.name == name)?;
+            let z = int(engine.resolve_value(m.z.as_deref()?)) as u32;
+            let a = molar_mass(engine, &m.atom_value, &m.atom_unit)?;
+            return Some((a, vec![(z * 1000, 1.0)]));
+        };
+        if el.fractions.is_empty() {
+            let z = int(engine.resolve_value(el.z.as_deref()?)) as u32;
+            let a = molar_mass(engine, &el.atom_value, &el.atom_unit)?;
+            return Some((a, vec![(z * 1000, 1.0)]));
+        }
+        // Isotope abundances are by atom; weigh each by its molar mass.
+        let mut parts = Composition::new();
+        let (mut mass, mut atoms) = (0.0, 0.0);
+        for f in &el.fractions {
+            let iso = self
+                .doc
+                .materials
+                .isotopes
+                .iter()
+                .find(|i| i.name == f.ref_name)?;
+            let z = int(engine.resolve_value(iso.z.as_deref()?)) as u32;
+            let n = int(engine.resolve_value(iso.n.as_deref()?)) as u32;
+            let a = molar_mass(engine, &iso.atom_value, &iso.atom_unit)?;
+            let abundance = engine.resolve_value(&f.n);
+            parts.push((z * 1000 + n, abundance * a));
+            mass += abundance * a;
+            atoms += abundance;
+        }
+        Some((mass / atoms, normalised(parts)?))
+    }
+
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        let title = format!("Geometry of {}", self.doc.filename);
+        out.push_str(&title.chars().take(80).collect::<String>());
+        out.push('\n');
+        out.push_str("c Cells: each placed volume, its solid minus its daughters.\n");
+        out.push_str("c Importances are set for neutrons; add others to match MODE.\n");
+        for c in &self.cells {
+            out.push_str(c);
+            out.push('\n');
+        }
+        out.push('\n');
+        for s in &self.surfaces {
+            out.push_str(s);
+            out.push('\n');
+        }
+        out.push('\n');
+        for m in &self.material_cards {
+            out.push_str(m);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn generate(xml: &str) -> McnpDeck {
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "detector.gdml".to_string()).unwrap();
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&doc.defines).unwrap();
+        generate_mcnp_deck(&doc, &engine).unwrap()
+    }
+
+    #[test]
+    fn moved_surfaces_take_their_simplest_card() {
+        // A cylinder about z, turned onto y and shifted along x.
+        let cylinder = Quadric::about_z(0.0, 0.0, -4.0);
+        let frame = Frame::placement([10.0, 0.0, 0.0], [PI / 2.0, 0.0, 0.0]);
+        assert_eq!(
+            surface_card(cylinder.moved(&frame).coefficients()),
+            Some(("c/y 1 0 2".to_string(), false))
+        );
+        // The plane x > 3 is the positive side of `px 3`.
+        let plane = Quadric::plane([-1.0, 0.0, 0.0], -3.0);
+        assert_eq!(
+            surface_card(plane.coefficients()),
+            Some(("px 3".to_string(), true))
+        );
+        let sphere = Quadric::about_z(1.0, 0.0, -1.0);
+        let shifted = Frame::placement([0.0, 0.0, 20.0], [0.0; 3]);
+        assert_eq!(
+            surface_card(sphere.moved(&shifted).coefficients())
+                .unwrap()
+                .0,
+            "s 0 0 2 1"
+        );
+        // A frustum of radius 1 at z=-1 and 3 at z=1 has its apex at z=-2.
+        let cone = Quadric::radial(1.0, 3.0, 1.0);
+        assert_eq!(surface_card(cone.coefficients()).unwrap().0, "kz -2 1");
+        assert_eq!(card(&"1 ".repeat(50)).lines().count(), 2);
+    }
+
+    #[test]
+    fn a_detector_becomes_cells_surfaces_and_materials() {
+        let out = generate(
+            r#"<gdml>
+  <materials>
+    <material name="Water" state="liquid">
+      <D value="1"/>
+      <composite n="2" ref="H"/>
+      <composite n="1" ref="O"/>
+    </material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="200" y="200" z="200"/>
+    <tube name="Pipe" rmin="10" rmax="20" z="100"/>
+    <orb name="Ball" r="5"/>
+    <union name="Both">
+      <first ref="Pipe"/><second ref="Ball"/>
+      <position name="p" z="60"/>
+    </union>
+    <torus name="Ring" rmax="5" rtor="50" deltaphi="360" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Target"><materialref ref="Water"/><solidref ref="Both"/></volume>
+    <volume name="Donut"><materialref ref="G4_Fe"/><solidref ref="Ring"/></volume>
+    <volume name="World">
+      <materialref ref="G4_Galactic"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Target"/></physvol>
+      <physvol><volumeref ref="Donut"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#,
+        );
+        let deck = &out.deck;
+        assert_eq!(out.file_name, "detector.mcnp");
+        for expected in [
+            "1 px 10",
+            "2 px -10",
+            "7 pz 5",
+            "9 cz 2",
+            "10 cz 1",
+            "11 s 0 0 6 0.5",
+            "c World\n1 0 -1 2 -3 4 -5 6 (7:-8:9:-10) 11 imp:n=1",
+            "c World/Target\n2 1 -1 (-7 8 -9 10):-11 imp:n=1",
+            "3 0 1:-2:3:-4:5:-6 imp:n=0",
+            "m1 1000 -0.11190674438 8000 -0.88809325562",
+        ] {
+            assert!(
+                deck.contains(expected),
+                "missing `{}` in:\n{}",
+                expected,
+                deck
+            );
+        }
+        assert!(out.warnings.iter().any(|w| w.contains("\"Ring\"")));
+        assert!(out.warnings.iter().any(|w| w.contains("\"Donut\"")));
+    }
+}
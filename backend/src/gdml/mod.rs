@@ -1,8 +1,19 @@
+pub mod constraints;
+mod cpp;
 pub mod defines;
+pub mod geant4;
 pub mod loops;
 pub mod materials;
+pub mod mcnp;
 pub mod model;
+pub mod modular;
 pub mod parser;
+pub mod prune;
+pub mod references;
+pub mod rename;
+pub mod schema;
 pub mod solids;
 pub mod structure;
+pub mod surfaces;
+pub mod tgeo;
 pub mod units;
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GdmlDocument {
@@ -47,6 +47,163 @@ pub struct GdmlDocument {
     /// warnings: these are NOT preserved and will be missing from a save.
     #[serde(default)]
     pub skipped_unsupported: Vec<String>,
+    /// Which file each item came from, for a document assembled from `<file>`
+    /// includes. `None` for a document read from a single file, where
+    /// everything trivially came from `filename`.
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+/// Where the items of a merged multi-file document were defined.
+///
+/// The merge flattens every included file into one namespace, which is what the
+/// evaluator and mesher need, but loses the module boundaries. This keeps them
+/// so the user can see which module owns what, and so the document can be split
+/// back into its files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Every file that was merged, main file first, in merge order.
+    pub files: Vec<IncludedFile>,
+    /// Item kind (`"solid"`, `"volume"`, `"material"`, ...) → item name → path
+    /// of the file that defined it. When two files define the same name the
+    /// first one merged wins, matching the merge itself.
+    pub items: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// One file merged into a multi-file document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncludedFile {
+    /// Normalised path, relative to the main file's directory.
+    pub path: String,
+    /// The file whose `<file>` element pulled this one in; `None` for the main
+    /// file.
+    pub included_from: Option<String>,
+    /// The file's own `<setup>` world volume.
+    pub world: String,
+}
+
+impl Provenance {
+    /// Record every named item in `doc` as coming from `file`, unless an
+    /// earlier file already claimed the name.
+    pub fn record_document(&mut self, doc: &GdmlDocument, file: &str) {
+        let d = &doc.defines;
+        let m = &doc.materials;
+        let named: [(&str, Vec<&str>); 16] = [
+            (
+                "constant",
+                d.constants.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "quantity",
+                d.quantities.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "variable",
+                d.variables.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "expression",
+                d.expressions.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "position",
+                d.positions.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "rotation",
+                d.rotations.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            ("scale", d.scales.iter().map(|x| x.name.as_str()).collect()),
+            (
+                "matrix",
+                d.matrices.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "opticalsurface",
+                doc.solids
+                    .optical_surfaces
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect(),
+            ),
+            (
+                "skinsurface",
+                doc.structure
+                    .skin_surfaces
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect(),
+            ),
+            (
+                "bordersurface",
+                doc.structure
+                    .border_surfaces
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect(),
+            ),
+            (
+                "isotope",
+                m.isotopes.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "element",
+                m.elements.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "material",
+                m.materials.iter().map(|x| x.name.as_str()).collect(),
+            ),
+            (
+                "solid",
+                doc.solids.solids.iter().map(|x| x.name()).collect(),
+            ),
+            (
+                "volume",
+                doc.structure
+                    .volumes
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect(),
+            ),
+        ];
+        for (kind, names) in named {
+            let by_name = self.items.entry(kind.to_string()).or_default();
+            for name in names {
+                by_name
+                    .entry(name.to_string())
+                    .or_insert_with(|| file.to_string());
+            }
+        }
+    }
+
+    /// The file that defined `name`, if it came from an include.
+    pub fn source_of(&self, kind: &str, name: &str) -> Option<&str> {
+        self.items.get(kind)?.get(name).map(String::as_str)
+    }
+
+    /// Follow a rename so the item stays attributed to its file.
+    pub fn rename(&mut self, kind: &str, old_name: &str, new_name: &str) {
+        if let Some(by_name) = self.items.get_mut(kind) {
+            if let Some(file) = by_name.remove(old_name) {
+                by_name.insert(new_name.to_string(), file);
+            }
+        }
+    }
+
+    /// Re-attribute an item to another file, e.g. when a subtree is split out.
+    pub fn record_move(&mut self, kind: &str, name: &str, file: &str) {
+        self.items
+            .entry(kind.to_string())
+            .or_default()
+            .insert(name.to_string(), file.to_string());
+    }
+
+    pub fn forget(&mut self, kind: &str, name: &str) {
+        if let Some(by_name) = self.items.get_mut(kind) {
+            by_name.remove(name);
+        }
+    }
 }
 
 /// One run of XML comments, anchored to the element that followed it.
@@ -118,6 +275,7 @@ pub enum DefineKind {
     Position,
     Rotation,
     Scale,
+    Matrix,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,6 +310,10 @@ pub struct DefineSection {
     /// against.
     #[serde(default)]
     pub scales: Vec<Scale>,
+    /// `<matrix>` tables — RINDEX, ABSLENGTH and the other optical property
+    /// data that material `<property ref="..">` bindings point at.
+    #[serde(default)]
+    pub matrices: Vec<Matrix>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -201,6 +363,28 @@ pub struct Scale {
     pub z: Option<String>,
 }
 
+/// A `<matrix name=".." coldim=".." values=".."/>` define.
+///
+/// `values` is kept as the source wrote it — whitespace-separated expressions,
+/// often laid out one row per line — so an untouched table round-trips
+/// verbatim. `G4GDMLEvaluator::DefineMatrix` stores the table row-major and
+/// also defines every entry as a constant: `name_i` when `coldim` is 1,
+/// `name_i_j` otherwise (0-based), which is what `name[i,j]` in an expression
+/// resolves to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Matrix {
+    pub name: String,
+    pub coldim: String,
+    pub values: String,
+}
+
+impl Matrix {
+    /// The individual entry expressions, in row-major order.
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.values.split_whitespace()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rotation {
     pub name: String,
@@ -323,6 +507,32 @@ pub enum MaterialComponent {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SolidSection {
     pub solids: Vec<Solid>,
+    /// `<opticalsurface>` elements. GDML puts them in `<solids>`, though they
+    /// describe a boundary rather than a shape, so they are kept apart from
+    /// `solids` and never tessellated.
+    #[serde(default)]
+    pub optical_surfaces: Vec<OpticalSurface>,
+}
+
+/// `<opticalsurface name model finish type value>` with its `<property>`
+/// children, read by `G4GDMLReadSolids::OpticalSurfaceRead` into a
+/// `G4OpticalSurface`.
+///
+/// `model`, `finish` and `type` are kept as written: Geant4 accepts either the
+/// enumerator name or its number (`"unified"` or `"1"`), and an untouched file
+/// should round-trip. [`super::surfaces`] maps them to the Geant4 enumerators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpticalSurface {
+    pub name: String,
+    pub model: String,
+    pub finish: String,
+    pub r#type: String,
+    /// Polish for the glisur model, sigma_alpha otherwise. Optional in the
+    /// schema; Geant4 uses 1.0 when it is absent.
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub properties: Vec<MaterialProperty>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -871,6 +1081,32 @@ pub struct MultiUnionSolid {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StructureSection {
     pub volumes: Vec<Volume>,
+    #[serde(default)]
+    pub skin_surfaces: Vec<SkinSurface>,
+    #[serde(default)]
+    pub border_surfaces: Vec<BorderSurface>,
+}
+
+/// `<skinsurface name surfaceproperty><volumeref ref/></skinsurface>`: the
+/// optical surface covers every boundary of one logical volume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkinSurface {
+    pub name: String,
+    /// Name of the `<opticalsurface>` applied.
+    pub surface_property: String,
+    pub volume_ref: String,
+}
+
+/// `<bordersurface name surfaceproperty>` with two `<physvolref>`: the optical
+/// surface applies to photons crossing from the first placement into the
+/// second. Order matters -- the reverse crossing is a different border.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BorderSurface {
+    pub name: String,
+    pub surface_property: String,
+    /// The `<physvolref>` names in source order. Geant4 needs exactly two; any
+    /// other count is kept as written so the file is not silently changed.
+    pub physvol_refs: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -937,6 +1173,11 @@ pub struct PhysVol {
     #[serde(default)]
     pub copynumber: Option<String>,
     pub file_ref: Option<FileRef>,
+    /// The `<file>` reference this placement was resolved from when the
+    /// document was merged. `volume_ref` then names the included volume; this
+    /// keeps the original reference so a modular export can write it back.
+    #[serde(default)]
+    pub included: Option<FileRef>,
     pub position: Option<PlacementPos>,
     pub rotation: Option<PlacementRot>,
 }
@@ -0,0 +1,525 @@
+//! Split a merged multi-file document back into its files.
+//!
+//! A merge flattens every `<file>` include into one document and records, in
+//! [`Provenance`], which file each item came from. Splitting reverses that:
+//! each file gets back the volumes it defined, placements that came from a
+//! `<file>` element become `<file>` elements again, and everything those
+//! volumes need is written alongside them.
+//!
+//! Geant4 reads each included file with its own reader
+//! (`G4GDMLReadStructure::FileRead`), so a module cannot see the defines,
+//! materials or solids of the file that includes it. A split file therefore
+//! carries the full closure of what its volumes reference -- a material shared
+//! by two modules is written into both, as it was in the sources.
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use super::model::*;
+use super::prune::retain_items;
+use super::references::{collect_references, item_kind, reachable_from, ItemId, INCLUDE_ATTRIBUTE};
+
+/// One file of a split document.
+pub struct ModuleFile {
+    /// Path relative to the main file's directory.
+    pub path: String,
+    pub document: GdmlDocument,
+}
+
+/// Split `doc` into one document per file recorded in its provenance, main
+/// file first.
+pub fn split_into_modules(doc: &GdmlDocument) -> Result<Vec<ModuleFile>> {
+    let Some(provenance) = doc.provenance.as_ref() else {
+        bail!("The document was not assembled from multiple files");
+    };
+    let main_path = provenance
+        .files
+        .first()
+        .map(|f| f.path.clone())
+        .unwrap_or_else(|| doc.filename.clone());
+    let owner = |kind: &str, name: &str| -> String {
+        provenance
+            .source_of(kind, name)
+            .unwrap_or(&main_path)
+            .to_string()
+    };
+
+    let references = collect_references(doc);
+    // Skin and border surfaces find their volume, placements and optical
+    // surface through Geant4's global stores rather than the per-file reader,
+    // so they may name things in another file; following those edges would
+    // copy a module's volumes into the file holding the surface.
+    let within_module = |r: &super::references::Reference| {
+        r.attribute != INCLUDE_ATTRIBUTE && !matches!(r.from.kind, "skinsurface" | "bordersurface")
+    };
+
+    // Items every volume (in any file) reaches. Something owned by a file but
+    // used by none of the volumes -- an unused material, a stray constant --
+    // stays in the file that defined it; anything used goes wherever it is
+    // used.
+    let all_volumes: Vec<ItemId> = doc
+        .structure
+        .volumes
+        .iter()
+        .map(|v| ItemId::new("volume", &v.name))
+        .collect();
+    let used_by_volumes = reachable_from(&references, all_volumes, within_module);
+
+    let mut modules = Vec::new();
+    for file in &provenance.files {
+        let is_main = file.path == main_path;
+        let mut roots: Vec<ItemId> = doc
+            .structure
+            .volumes
+            .iter()
+            .filter(|v| owner("volume", &v.name) == file.path)
+            .map(|v| ItemId::new("volume", &v.name))
+            .collect();
+        if is_main {
+            roots.push(ItemId::new("volume", &doc.setup.world_ref));
+        } else {
+            roots.push(ItemId::new("volume", &file.world));
+        }
+        for (kind, names) in &provenance.items {
+            for (name, source) in names {
+                if *source != file.path {
+                    continue;
+                }
+                if let Some(id) = item_id(kind, name) {
+                    if !used_by_volumes.contains(&id) {
+                        roots.push(id);
+                    }
+                }
+            }
+        }
+        if is_main {
+            // Items added after the load have no provenance; they belong to
+            // the main file unless a module's volumes pull them in.
+            roots.extend(unattributed_items(doc, provenance, &used_by_volumes));
+        }
+
+        let keep = reachable_from(&references, roots, within_module);
+        let mut module = filter_document(doc, &keep);
+        module.filename = file_name(&file.path);
+        if !is_main {
+            module.setup = SetupSection {
+                name: "Default".to_string(),
+                version: "1.0".to_string(),
+                world_ref: file.world.clone(),
+            };
+            module.setups = Vec::new();
+            module.order = DocumentOrder::default();
+            module.materials_define = None;
+            // Preserved-verbatim elements are not attributed to a file; they
+            // stay with the main file.
+            module.raw_unknown = Vec::new();
+            module.skipped_unsupported = Vec::new();
+        }
+        module.provenance = None;
+        modules.push(ModuleFile {
+            path: file.path.clone(),
+            document: module,
+        });
+    }
+    Ok(modules)
+}
+
+fn file_name(path: &str) -> String {
+    super::structure::include_basename(path).to_string()
+}
+
+/// Map a provenance kind back to an [`ItemId`] with a static kind string.
+fn item_id(kind: &str, name: &str) -> Option<ItemId> {
+    item_kind(kind).map(|k| ItemId::new(k, name))
+}
+
+fn unattributed_items(
+    doc: &GdmlDocument,
+    provenance: &Provenance,
+    used_by_volumes: &HashSet<ItemId>,
+) -> Vec<ItemId> {
+    let mut all = Provenance::default();
+    all.record_document(doc, "");
+    let mut out = Vec::new();
+    for (kind, names) in &all.items {
+        for name in names.keys() {
+            if provenance.source_of(kind, name).is_some() {
+                continue;
+            }
+            if let Some(id) = item_id(kind, name) {
+                if !used_by_volumes.contains(&id) || kind == "volume" {
+                    out.push(id);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A copy of `doc` holding only the items in `keep`, in their original order,
+/// with `<file>`-resolved placements turned back into `<file>` elements.
+fn filter_document(doc: &GdmlDocument, keep: &HashSet<ItemId>) -> GdmlDocument {
+    let mut out = doc.clone();
+    retain_items(&mut out, |kind, name| {
+        keep.contains(&ItemId::new(kind, name))
+    });
+    for vol in &mut out.structure.volumes {
+        for pv in &mut vol.physvols {
+            if let Some(fref) = pv.included.take() {
+                pv.file_ref = Some(fref);
+                pv.volume_ref = String::new();
+            }
+        }
+    }
+    out
+}
+
+/// Serialise `modules` into a zip archive laid out as the include paths expect.
+///
+/// Paths are relative to the main file's directory. When an include climbs
+/// above it (`../shared/x.gdml`) the main file is nested under `_/` so the
+/// relative references written in the files still resolve inside the archive.
+/// Returns the archive and notes about anything that could not be placed
+/// faithfully.
+pub fn write_modules_zip(modules: &[ModuleFile]) -> Result<(Vec<u8>, Vec<String>)> {
+    let mut notes = Vec::new();
+    let depth = modules
+        .iter()
+        .map(|m| m.path.split('/').take_while(|s| *s == "..").count())
+        .max()
+        .unwrap_or(0);
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for module in modules {
+        let entry = if let Some(stripped) = module.path.strip_prefix('/') {
+            notes.push(format!(
+                "'{}' is included by absolute path; it was stored as '_absolute/{}' and \
+                 the reference to it must be adjusted by hand",
+                module.path, stripped
+            ));
+            format!("_absolute/{}", stripped)
+        } else {
+            let ups = module.path.split('/').take_while(|s| *s == "..").count();
+            let rest: Vec<&str> = module.path.split('/').skip(ups).collect();
+            let mut parts = vec!["_"; depth - ups];
+            parts.extend(rest);
+            parts.join("/")
+        };
+        let xml = super::materials::serialize_gdml(&module.document)?;
+        zip.start_file(entry, options)?;
+        zip.write_all(xml.as_bytes())?;
+    }
+    if !notes.is_empty() {
+        zip.start_file("EXPORT_NOTES.txt", options)?;
+        zip.write_all(notes.join("\n").as_bytes())?;
+    }
+    Ok((zip.finish()?.into_inner(), notes))
+}
+
+/// Move the subtree under `volume` into a new module file at `path`.
+///
+/// The volume and every volume beneath it that belongs to the same file are
+/// re-attributed to the new file, and each placement of `volume` becomes a
+/// `<file>` include naming `path` relative to the file of its mother. A volume
+/// that is already the world of an included file is refused: that file's
+/// include would still name it. Nothing is written here: the split shows up
+/// on the next modular export. Returns the number of volumes moved.
+pub fn split_subtree(doc: &mut GdmlDocument, volume: &str, path: &str) -> Result<usize> {
+    if !doc.structure.volumes.iter().any(|v| v.name == volume) {
+        bail!("Volume '{}' not found", volume);
+    }
+    if volume == doc.setup.world_ref {
+        bail!("The world volume cannot be moved into a child file");
+    }
+    if let Some(file) = doc
+        .provenance
+        .iter()
+        .flat_map(|p| &p.files)
+        .find(|f| f.world == volume)
+    {
+        bail!(
+            "Volume '{}' is already the world of '{}'; it cannot be moved into another file",
+            volume,
+            file.path
+        );
+    }
+
+    let mut provenance = doc.provenance.take().unwrap_or_else(|| {
+        let mut single = Provenance::default();
+        single.files.push(IncludedFile {
+            path: doc.filename.clone(),
+            included_from: None,
+            world: doc.setup.world_ref.clone(),
+        });
+        single.record_document(doc, &doc.filename);
+        single
+    });
+    if provenance.files.iter().any(|f| f.path == path) {
+        doc.provenance = Some(provenance);
+        bail!("A file named '{}' is already part of the document", path);
+    }
+    let main_path = provenance
+        .files
+        .first()
+        .map(|f| f.path.clone())
+        .unwrap_or_default();
+    let source = provenance
+        .source_of("volume", volume)
+        .unwrap_or(&main_path)
+        .to_string();
+
+    // Walk the daughters, stopping at placements that already cross into
+    // another file.
+    let references = collect_references(doc);
+    let subtree = reachable_from(&references, [ItemId::new("volume", volume)], |r| {
+        r.attribute != INCLUDE_ATTRIBUTE && r.to.kind == "volume"
+    });
+    let mut moved = 0;
+    for item in &subtree {
+        if provenance
+            .source_of("volume", &item.name)
+            .unwrap_or(&main_path)
+            == source
+        {
+            provenance.record_move("volume", &item.name, path);
+            moved += 1;
+        }
+    }
+
+    // The `<file>` element is written into the file of the mother placing
+    // the volume, so the name is relative to that file.
+    let mut included_from = None;
+    for vol in &mut doc.structure.volumes {
+        let mother_file = provenance
+            .source_of("volume", &vol.name)
+            .unwrap_or(&main_path)
+            .to_string();
+        for pv in &mut vol.physvols {
+            if pv.volume_ref == volume && pv.included.is_none() && pv.file_ref.is_none() {
+                pv.included = Some(FileRef {
+                    name: relative_include(&mother_file, path),
+                    volname: None,
+                });
+                included_from.get_or_insert_with(|| mother_file.clone());
+            }
+        }
+    }
+    provenance.files.push(IncludedFile {
+        path: path.to_string(),
+        included_from: Some(included_from.unwrap_or(source)),
+        world: volume.to_string(),
+    });
+    doc.provenance = Some(provenance);
+    Ok(moved)
+}
+
+/// The `<file name>` an includer at `from` must write to reach `to`, both
+/// given relative to the main file's directory.
+fn relative_include(from: &str, to: &str) -> String {
+    let from_dir: Vec<&str> = match from.rsplit_once('/') {
+        Some((dir, _)) => dir.split('/').collect(),
+        None => Vec::new(),
+    };
+    let to_parts: Vec<&str> = to.split('/').collect();
+    let common = from_dir
+        .iter()
+        .zip(&to_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts: Vec<&str> = vec![".."; from_dir.len() - common];
+    parts.extend(&to_parts[common..]);
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser;
+    use crate::gdml::structure::normalize_include_path;
+
+    #[test]
+    fn relative_includes_round_trip_through_normalisation() {
+        for (from, to) in [
+            ("main.gdml", "child.gdml"),
+            ("main.gdml", "modules/child.gdml"),
+            ("modules/a.gdml", "modules/b.gdml"),
+            ("modules/a.gdml", "common/c.gdml"),
+        ] {
+            let written = relative_include(from, to);
+            assert_eq!(normalize_include_path(from, &written), to, "{from} -> {to}");
+        }
+    }
+
+    #[test]
+    fn zip_layout_keeps_parent_relative_includes_resolvable() {
+        let doc = parser::parse_gdml_from_bytes(b"<gdml/>", "main.gdml".into()).unwrap();
+        let modules = vec![
+            ModuleFile {
+                path: "main.gdml".into(),
+                document: doc.clone(),
+            },
+            ModuleFile {
+                path: "../shared/part.gdml".into(),
+                document: doc,
+            },
+        ];
+        let (bytes, notes) = write_modules_zip(&modules).unwrap();
+        assert!(notes.is_empty());
+        let archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["_/main.gdml", "shared/part.gdml"]);
+    }
+
+    #[test]
+    fn split_subtree_moves_daughters_and_restores_file_reference() {
+        let xml = r#"<gdml>
+  <materials><material name="Air" Z="1"><D value="1"/><atom value="1"/></material></materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="ModBox" x="10" y="10" z="10"/>
+    <box name="CellBox" x="1" y="1" z="1"/>
+  </solids>
+  <structure>
+    <volume name="Cell"><materialref ref="Air"/><solidref ref="CellBox"/></volume>
+    <volume name="Module"><materialref ref="Air"/><solidref ref="ModBox"/>
+      <physvol><volumeref ref="Cell"/></physvol></volume>
+    <volume name="World"><materialref ref="Air"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Module"/></physvol></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let mut doc = parser::parse_gdml_from_bytes(xml.as_bytes(), "det.gdml".into()).unwrap();
+        let moved = split_subtree(&mut doc, "Module", "modules/module.gdml").unwrap();
+        assert_eq!(moved, 2);
+
+        let modules = split_into_modules(&doc).unwrap();
+        assert_eq!(modules.len(), 2);
+        let main = &modules[0].document;
+        let child = &modules[1].document;
+        assert_eq!(modules[1].path, "modules/module.gdml");
+
+        let world = main
+            .structure
+            .volumes
+            .iter()
+            .find(|v| v.name == "World")
+            .unwrap();
+        let fref = world.physvols[0].file_ref.as_ref().unwrap();
+        assert_eq!(fref.name, "modules/module.gdml");
+        assert!(main.structure.volumes.iter().all(|v| v.name != "Cell"));
+        assert!(main.solids.solids.iter().all(|s| s.name() != "CellBox"));
+
+        let names: Vec<&str> = child
+            .structure
+            .volumes
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(names, ["Cell", "Module"]);
+        assert_eq!(child.setup.world_ref, "Module");
+        // A module is read by its own Geant4 reader, so it carries its own copy
+        // of the material.
+        assert_eq!(child.materials.materials.len(), 1);
+        assert_eq!(main.materials.materials.len(), 1);
+    }
+
+    /// `main.gdml` with `Module` (and `Cell` inside it) merged in from
+    /// `modules/module.gdml`, as the include walk leaves it.
+    fn merged_with_module(world_places: &str, module_places: &str) -> GdmlDocument {
+        let xml = format!(
+            r#"<gdml>
+  <materials><material name="Air" Z="1"><D value="1"/><atom value="1"/></material></materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="ModBox" x="10" y="10" z="10"/>
+    <box name="CellBox" x="1" y="1" z="1"/>
+  </solids>
+  <structure>
+    <volume name="Cell"><materialref ref="Air"/><solidref ref="CellBox"/></volume>
+    <volume name="Module"><materialref ref="Air"/><solidref ref="ModBox"/>
+      <physvol><volumeref ref="{module_places}"/></physvol></volume>
+    <volume name="World"><materialref ref="Air"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="{world_places}"/></physvol></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#
+        );
+        let mut doc = parser::parse_gdml_from_bytes(xml.as_bytes(), "main.gdml".into()).unwrap();
+        let mut provenance = Provenance::default();
+        provenance.files.push(IncludedFile {
+            path: "main.gdml".into(),
+            included_from: None,
+            world: "World".into(),
+        });
+        provenance.files.push(IncludedFile {
+            path: "modules/module.gdml".into(),
+            included_from: Some("main.gdml".into()),
+            world: "Module".into(),
+        });
+        provenance.record_document(&doc, "main.gdml");
+        provenance.record_move("volume", "Module", "modules/module.gdml");
+        provenance.record_move("solid", "ModBox", "modules/module.gdml");
+        let world = doc
+            .structure
+            .volumes
+            .iter_mut()
+            .find(|v| v.name == "World")
+            .unwrap();
+        world.physvols[0].included = Some(FileRef {
+            name: "modules/module.gdml".into(),
+            volname: None,
+        });
+        doc.provenance = Some(provenance);
+        doc
+    }
+
+    #[test]
+    fn split_subtree_refuses_the_world_of_an_included_file() {
+        let mut doc = merged_with_module("Module", "Cell");
+        let error = split_subtree(&mut doc, "Module", "sub/new.gdml").unwrap_err();
+        assert!(error.to_string().contains("modules/module.gdml"), "{error}");
+        // Nothing was moved or added.
+        let provenance = doc.provenance.as_ref().unwrap();
+        assert_eq!(provenance.files.len(), 2);
+        assert_eq!(
+            provenance.source_of("volume", "Module"),
+            Some("modules/module.gdml")
+        );
+    }
+
+    #[test]
+    fn split_subtree_writes_the_include_relative_to_the_placing_file() {
+        // `Cell` is defined in the main file but placed from the module, so
+        // the new `<file>` element lives in `modules/module.gdml`.
+        let mut doc = merged_with_module("Module", "Cell");
+        assert_eq!(
+            split_subtree(&mut doc, "Cell", "modules/cell.gdml").unwrap(),
+            1
+        );
+        let added = doc.provenance.as_ref().unwrap().files.last().unwrap();
+        assert_eq!(added.included_from.as_deref(), Some("modules/module.gdml"));
+
+        let modules = split_into_modules(&doc).unwrap();
+        let module = modules
+            .iter()
+            .find(|m| m.path == "modules/module.gdml")
+            .unwrap();
+        let placement = &module
+            .document
+            .structure
+            .volumes
+            .iter()
+            .find(|v| v.name == "Module")
+            .unwrap()
+            .physvols[0];
+        assert_eq!(placement.file_ref.as_ref().unwrap().name, "cell.gdml");
+        assert_eq!(
+            normalize_include_path(&module.path, "cell.gdml"),
+            "modules/cell.gdml"
+        );
+    }
+}
@@ -158,6 +158,18 @@ pub fn parse_gdml_from_bytes(raw: &[u8], filename: String) -> Result<GdmlDocumen
                         );
                         parse_scale(e, &mut defines);
                     }
+                    b"matrix"
+                        if section == Section::Define || section == Section::MaterialsDefine =>
+                    {
+                        note_define(
+                            section,
+                            &mut order,
+                            &mut materials_define,
+                            DefineKind::Matrix,
+                            e,
+                        );
+                        parse_matrix(e, &mut defines);
+                    }
                     b"rotation"
                         if section == Section::Define || section == Section::MaterialsDefine =>
                     {
@@ -315,6 +327,39 @@ pub fn parse_gdml_from_bytes(raw: &[u8], filename: String) -> Result<GdmlDocumen
                             &mut skipped_unsupported,
                         )?;
                     }
+                    b"opticalsurface" if section == Section::Solids => {
+                        let mut surface = parse_optical_surface(e);
+                        for child in read_surface_body(&mut reader, b"opticalsurface")? {
+                            if child.tag == "property" {
+                                surface.properties.push(MaterialProperty {
+                                    name: child.name.unwrap_or_default(),
+                                    ref_name: child.ref_name,
+                                    values: None,
+                                });
+                            }
+                        }
+                        solids.optical_surfaces.push(surface);
+                    }
+                    b"skinsurface" if section == Section::Structure => {
+                        let mut surface = parse_skin_surface(e);
+                        for child in read_surface_body(&mut reader, b"skinsurface")? {
+                            if child.tag == "volumeref" {
+                                surface.volume_ref = child.ref_name.unwrap_or_default();
+                            }
+                        }
+                        structure.skin_surfaces.push(surface);
+                    }
+                    b"bordersurface" if section == Section::Structure => {
+                        let mut surface = parse_border_surface(e);
+                        for child in read_surface_body(&mut reader, b"bordersurface")? {
+                            if child.tag == "physvolref" {
+                                surface
+                                    .physvol_refs
+                                    .push(child.ref_name.unwrap_or_default());
+                            }
+                        }
+                        structure.border_surfaces.push(surface);
+                    }
                     // Recognized-but-uninterpreted constructs: preserve verbatim so
                     // they survive a load -> save round-trip (and warn the user).
                     b"opticalsurface" | b"skinsurface" | b"bordersurface" | b"userinfo"
@@ -410,6 +455,18 @@ pub fn parse_gdml_from_bytes(raw: &[u8], filename: String) -> Result<GdmlDocumen
                         );
                         parse_scale(e, &mut defines);
                     }
+                    b"matrix"
+                        if section == Section::Define || section == Section::MaterialsDefine =>
+                    {
+                        note_define(
+                            section,
+                            &mut order,
+                            &mut materials_define,
+                            DefineKind::Matrix,
+                            e,
+                        );
+                        parse_matrix(e, &mut defines);
+                    }
                     b"rotation"
                         if section == Section::Define || section == Section::MaterialsDefine =>
                     {
@@ -512,6 +569,17 @@ pub fn parse_gdml_from_bytes(raw: &[u8], filename: String) -> Result<GdmlDocumen
                     b"reflectedSolid" if section == Section::Solids => {
                         parse_reflected_solid(e, &mut solids);
                     }
+                    b"opticalsurface" if section == Section::Solids => {
+                        solids.optical_surfaces.push(parse_optical_surface(e));
+                    }
+                    // Without children these reference nothing; kept so the
+                    // validation can say so rather than the element vanishing.
+                    b"skinsurface" if section == Section::Structure => {
+                        structure.skin_surfaces.push(parse_skin_surface(e));
+                    }
+                    b"bordersurface" if section == Section::Structure => {
+                        structure.border_surfaces.push(parse_border_surface(e));
+                    }
                     // Self-closing recognized-but-uninterpreted constructs: preserve verbatim.
                     b"opticalsurface" | b"skinsurface" | b"bordersurface" | b"userinfo"
                     | b"loop" | b"assembly" | b"matrix" => {
@@ -660,6 +728,7 @@ pub fn parse_gdml_from_bytes(raw: &[u8], filename: String) -> Result<GdmlDocumen
         root_attributes,
         materials_define,
         skipped_unsupported,
+        provenance: None,
     })
 }
 
@@ -887,6 +956,14 @@ fn parse_scale(e: &BytesStart, defines: &mut DefineSection) {
     });
 }
 
+fn parse_matrix(e: &BytesStart, defines: &mut DefineSection) {
+    defines.matrices.push(Matrix {
+        name: get_attr(e, "name").unwrap_or_default(),
+        coldim: get_attr(e, "coldim").unwrap_or_else(|| "1".to_string()),
+        values: get_attr(e, "values").unwrap_or_default(),
+    });
+}
+
 fn parse_rotation(e: &BytesStart, defines: &mut DefineSection) {
     defines.rotations.push(Rotation {
         name: get_attr(e, "name").unwrap_or_default(),
@@ -2074,6 +2151,7 @@ fn read_physvol_body(
         volume_ref,
         copynumber: None,
         file_ref,
+        included: None,
         position,
         rotation,
     })
@@ -2569,6 +2647,76 @@ fn read_replicavol_body(reader: &mut Reader<&[u8]>, number: String) -> Result<Re
 
 // ─── Setup parser ────────────────────────────────────────────────────────────
 
+fn parse_optical_surface(e: &BytesStart) -> OpticalSurface {
+    OpticalSurface {
+        name: get_attr(e, "name").unwrap_or_default(),
+        model: get_attr(e, "model").unwrap_or_default(),
+        finish: get_attr(e, "finish").unwrap_or_default(),
+        r#type: get_attr(e, "type").unwrap_or_default(),
+        value: get_attr(e, "value"),
+        properties: Vec::new(),
+    }
+}
+
+fn parse_skin_surface(e: &BytesStart) -> SkinSurface {
+    SkinSurface {
+        name: get_attr(e, "name").unwrap_or_default(),
+        surface_property: get_attr(e, "surfaceproperty").unwrap_or_default(),
+        volume_ref: String::new(),
+    }
+}
+
+fn parse_border_surface(e: &BytesStart) -> BorderSurface {
+    BorderSurface {
+        name: get_attr(e, "name").unwrap_or_default(),
+        surface_property: get_attr(e, "surfaceproperty").unwrap_or_default(),
+        physvol_refs: Vec::new(),
+    }
+}
+
+/// A direct child of a surface element: its tag and `name`/`ref` attributes.
+struct SurfaceChild {
+    tag: String,
+    name: Option<String>,
+    ref_name: Option<String>,
+}
+
+/// Read the children of `<opticalsurface>`, `<skinsurface>` or
+/// `<bordersurface>` up to the closing `tag`. Each of them holds only
+/// attribute-bearing children (`<property>`, `<volumeref>`, `<physvolref>`).
+fn read_surface_body(reader: &mut Reader<&[u8]>, tag: &[u8]) -> Result<Vec<SurfaceChild>> {
+    let mut children = Vec::new();
+    let mut buf = Vec::new();
+    let child = |inner: &BytesStart| SurfaceChild {
+        tag: String::from_utf8_lossy(inner.local_name().as_ref()).to_string(),
+        name: get_attr(inner, "name"),
+        ref_name: get_attr(inner, "ref"),
+    };
+
+    loop {
+        buf.clear();
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Empty(ref inner)) => children.push(child(inner)),
+            Ok(Event::Start(ref inner)) => {
+                children.push(child(inner));
+                reader.read_to_end(inner.to_end().name())?;
+            }
+            Ok(Event::End(ref inner)) if inner.local_name().as_ref() == tag => break,
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "XML error in {}: {}",
+                    String::from_utf8_lossy(tag),
+                    e
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(children)
+}
+
 fn read_setup_body(reader: &mut Reader<&[u8]>) -> Result<String> {
     let mut world_ref = String::new();
     let mut buf = Vec::new();
@@ -0,0 +1,267 @@
+//! Find and remove definitions nothing uses.
+//!
+//! Exporters (`G4GDMLWriteDefine`, CAD converters, generator scripts) tend to
+//! write every position, rotation, solid and material they ever created,
+//! whether or not a placed volume ends up using it. Geant4 builds all of them
+//! regardless, so they cost load time and make the file harder to read
+//! without changing the geometry.
+//!
+//! An item is in use when it can be reached from the world volume by following
+//! references forward ([`collect_references`]): the world's solid and material,
+//! its daughters, their placements' positions, the defines in every expression
+//! on the way, the elements and isotopes of each material, and so on. Optical,
+//! skin and border surfaces and preserved-verbatim elements (`<assembly>`, a
+//! loop outside a volume) are roots as well, since what they name is used even
+//! when no placed volume reaches it. A border surface also keeps the volume its
+//! placements live in.
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+
+use super::model::*;
+use super::references::{collect_references, item_kind, reachable_from, ItemId};
+
+/// Every define, material, element, isotope, solid and volume that is
+/// unreachable from `world` and the document's surfaces and raw elements,
+/// sorted by kind, then name.
+pub fn unused_items(doc: &GdmlDocument, world: &str) -> Result<Vec<ItemId>> {
+    if !doc.structure.volumes.iter().any(|v| v.name == world) {
+        bail!("World volume \"{}\" not found", world);
+    }
+
+    let mut roots = vec![ItemId::new("volume", world)];
+    for s in &doc.solids.optical_surfaces {
+        roots.push(ItemId::new("opticalsurface", &s.name));
+    }
+    for s in &doc.structure.skin_surfaces {
+        roots.push(ItemId::new("skinsurface", &s.name));
+    }
+    let border_refs: HashSet<&str> = doc
+        .structure
+        .border_surfaces
+        .iter()
+        .flat_map(|b| b.physvol_refs.iter().map(String::as_str))
+        .collect();
+    for s in &doc.structure.border_surfaces {
+        roots.push(ItemId::new("bordersurface", &s.name));
+    }
+    for vol in &doc.structure.volumes {
+        let holds_border_placement = vol.physvols.iter().any(|pv| match &pv.name {
+            Some(n) if !n.is_empty() => border_refs.contains(n.as_str()),
+            _ => border_refs.contains(format!("{}_PV", pv.volume_ref).as_str()),
+        });
+        if holds_border_placement {
+            roots.push(ItemId::new("volume", &vol.name));
+        }
+    }
+    for (i, raw) in doc.raw_unknown.iter().enumerate() {
+        roots.push(ItemId::new("raw", &format!("{}[{}]", raw.tag, i)));
+    }
+
+    let used = reachable_from(&collect_references(doc), roots, |_| true);
+
+    let mut declared = Provenance::default();
+    declared.record_document(doc, "");
+    let mut unused: Vec<ItemId> = declared
+        .items
+        .iter()
+        .flat_map(|(kind, names)| names.keys().map(move |name| (kind, name)))
+        .filter_map(|(kind, name)| item_kind(kind).map(|k| ItemId::new(k, name)))
+        .filter(|id| !is_surface(id.kind) && !used.contains(id))
+        .collect();
+    unused.sort();
+    Ok(unused)
+}
+
+/// Remove everything [`unused_items`] reports and return it.
+pub fn prune(doc: &mut GdmlDocument, world: &str) -> Result<Vec<ItemId>> {
+    let removed = unused_items(doc, world)?;
+    remove_items(doc, &removed, world);
+    Ok(removed)
+}
+
+/// Remove `removed` from `doc`, as found by [`unused_items`] for `world`.
+///
+/// A `<setup>` whose world was removed goes with it, except the main one,
+/// which is pointed at `world` instead: choosing a world other than the main
+/// setup's is how a document holding several detectors is cut down to one.
+pub fn remove_items(doc: &mut GdmlDocument, removed: &[ItemId], world: &str) {
+    let gone: HashSet<&ItemId> = removed.iter().collect();
+    retain_items(doc, |kind, name| !gone.contains(&ItemId::new(kind, name)));
+
+    let removed_volume = |name: &str| gone.contains(&ItemId::new("volume", name));
+    if removed_volume(&doc.setup.world_ref) {
+        doc.setup.world_ref = world.to_string();
+    }
+    doc.setups.retain(|s| !removed_volume(&s.world_ref));
+    if let Some(provenance) = doc.provenance.as_mut() {
+        for id in removed {
+            provenance.forget(id.kind, &id.name);
+        }
+    }
+}
+
+fn is_surface(kind: &str) -> bool {
+    matches!(kind, "opticalsurface" | "skinsurface" | "bordersurface")
+}
+
+/// Keep only the defines, materials, solids, surfaces and volumes for which
+/// `keep(kind, name)` holds, in their original order. The declaration-order
+/// manifest and the nested `<materials><define>` block follow, so what
+/// survives is written back where it was.
+pub fn retain_items(doc: &mut GdmlDocument, keep: impl Fn(&'static str, &str) -> bool) {
+    let d = &mut doc.defines;
+    d.constants.retain(|x| keep("constant", &x.name));
+    d.quantities.retain(|x| keep("quantity", &x.name));
+    d.variables.retain(|x| keep("variable", &x.name));
+    d.expressions.retain(|x| keep("expression", &x.name));
+    d.positions.retain(|x| keep("position", &x.name));
+    d.rotations.retain(|x| keep("rotation", &x.name));
+    d.scales.retain(|x| keep("scale", &x.name));
+    d.matrices.retain(|x| keep("matrix", &x.name));
+
+    let m = &mut doc.materials;
+    m.isotopes.retain(|x| keep("isotope", &x.name));
+    m.elements.retain(|x| keep("element", &x.name));
+    m.materials.retain(|x| keep("material", &x.name));
+
+    doc.solids.solids.retain(|s| keep("solid", s.name()));
+    doc.solids
+        .optical_surfaces
+        .retain(|s| keep("opticalsurface", &s.name));
+    doc.structure
+        .skin_surfaces
+        .retain(|s| keep("skinsurface", &s.name));
+    doc.structure
+        .border_surfaces
+        .retain(|s| keep("bordersurface", &s.name));
+    doc.structure.volumes.retain(|v| keep("volume", &v.name));
+
+    // If the manifest no longer lines up the writer falls back to grouped
+    // order on its own.
+    doc.order.define_slots.retain(|slot| {
+        let kind = match slot.kind {
+            DefineKind::Constant => "constant",
+            DefineKind::Quantity => "quantity",
+            DefineKind::Variable => "variable",
+            DefineKind::Expression => "expression",
+            DefineKind::Position => "position",
+            DefineKind::Rotation => "rotation",
+            DefineKind::Scale => "scale",
+            DefineKind::Matrix => "matrix",
+        };
+        keep(kind, &slot.name)
+    });
+    if let Some(names) = doc.materials_define.as_mut() {
+        let d = &doc.defines;
+        let declared: HashSet<&str> = (d.constants.iter().map(|x| &x.name))
+            .chain(d.quantities.iter().map(|x| &x.name))
+            .chain(d.variables.iter().map(|x| &x.name))
+            .chain(d.expressions.iter().map(|x| &x.name))
+            .chain(d.positions.iter().map(|x| &x.name))
+            .chain(d.rotations.iter().map(|x| &x.name))
+            .chain(d.scales.iter().map(|x| &x.name))
+            .chain(d.matrices.iter().map(|x| &x.name))
+            .map(String::as_str)
+            .collect();
+        names.retain(|n| declared.contains(n.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    const DOC: &str = r#"<?xml version="1.0"?>
+<gdml>
+  <define>
+    <constant name="r" value="10"/>
+    <constant name="unused_r" value="2*r"/>
+    <position name="p" x="r"/>
+    <position name="stray"/>
+    <matrix name="RI" coldim="2" values="1 1.5"/>
+    <matrix name="ABS" coldim="1" values="3"/>
+  </define>
+  <materials>
+    <isotope name="U235" Z="92" N="235"><atom value="235.04"/></isotope>
+    <element name="Enr"><fraction ref="U235" n="1"/></element>
+    <element name="H" formula="H" Z="1"><atom value="1.008"/></element>
+    <material name="Vac" Z="1"><D value="1e-25"/><atom value="1.008"/></material>
+    <material name="Fuel"><D value="19"/><fraction n="1" ref="Enr"/></material>
+    <material name="Gas"><D value="0.001"/><fraction n="1" ref="H"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="CellBox" x="r" y="r" z="r"/>
+    <box name="SpareBox" x="1" y="1" z="1"/>
+    <opticalsurface name="S" model="glisur" finish="polished" type="dielectric_metal" value="ABS_0">
+      <property name="REFLECTIVITY" ref="RI"/>
+    </opticalsurface>
+  </solids>
+  <structure>
+    <volume name="Cell"><materialref ref="Gas"/><solidref ref="CellBox"/></volume>
+    <volume name="Spare"><materialref ref="Fuel"/><solidref ref="SpareBox"/></volume>
+    <volume name="World">
+      <materialref ref="Vac"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Cell"/><positionref ref="p"/></physvol>
+    </volume>
+    <skinsurface name="Skin" surfaceproperty="S"><volumeref ref="Cell"/></skinsurface>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+
+    fn load() -> GdmlDocument {
+        parse_gdml_from_bytes(DOC.as_bytes(), "t.gdml".to_string()).unwrap()
+    }
+
+    fn names(items: &[ItemId]) -> Vec<String> {
+        items
+            .iter()
+            .map(|i| format!("{}:{}", i.kind, i.name))
+            .collect()
+    }
+
+    #[test]
+    fn unused_items_are_those_the_world_and_surfaces_do_not_reach() {
+        let doc = load();
+        let unused = unused_items(&doc, "World").unwrap();
+        assert_eq!(
+            names(&unused),
+            [
+                "constant:unused_r",
+                "element:Enr",
+                "isotope:U235",
+                "material:Fuel",
+                "position:stray",
+                "solid:SpareBox",
+                "volume:Spare",
+            ]
+        );
+        assert!(unused_items(&doc, "Nowhere").is_err());
+    }
+
+    #[test]
+    fn prune_removes_the_unused_and_repoints_the_main_setup() {
+        let mut doc = load();
+        let removed = prune(&mut doc, "World").unwrap();
+        assert_eq!(removed.len(), 7);
+        assert!(doc.materials.isotopes.is_empty());
+        assert_eq!(doc.defines.positions.len(), 1);
+        assert!(doc.order.define_slots.iter().all(|s| s.name != "stray"));
+        assert!(unused_items(&doc, "World").unwrap().is_empty());
+
+        // Cutting the document down to a volume other than the main world.
+        let mut doc = load();
+        prune(&mut doc, "Spare").unwrap();
+        assert_eq!(doc.setup.world_ref, "Spare");
+        let volumes: Vec<&str> = doc
+            .structure
+            .volumes
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        // Cell stays: the skin surface is applied to it.
+        assert_eq!(volumes, ["Cell", "Spare"]);
+    }
+}
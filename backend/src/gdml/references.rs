@@ -0,0 +1,710 @@
+//! Who refers to what in a GDML document.
+//!
+//! Every cross-reference is collected as one edge: from the item holding the
+//! reference to the item it names, with the attribute it sits in. Typed refs
+//! (`<materialref>`, `first`/`second`, `<positionref>`, tessellated vertices...)
+//! map directly; expressions contribute an edge for every identifier that names
+//! a scalar define (`constant`, `quantity`, `variable`, `expression`) or a
+//! `matrix`, indexed as `name[i,j]` or read through a generated element name
+//! (`name_i_j`). `<loop>` bodies, which stay XML, are read
+//! attribute by attribute.
+//!
+//! Solids are walked through their serde representation rather than one match
+//! arm per variant, so a newly modelled solid is covered without touching this
+//! file: its string fields are expressions unless their name says otherwise.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::model::*;
+use crate::eval::dependency::extract_identifiers;
+
+/// Kinds whose names may appear inside an expression.
+pub const SCALAR_DEFINE_KINDS: [&str; 4] = ["constant", "quantity", "variable", "expression"];
+
+/// One named item, identified by the same kind strings [`Provenance`] uses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct ItemId {
+    pub kind: &'static str,
+    pub name: String,
+}
+
+impl ItemId {
+    pub fn new(kind: &'static str, name: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+        }
+    }
+}
+
+/// The kinds [`Provenance`] records, as the `&'static str` an [`ItemId`]
+/// holds. `None` for anything else.
+pub fn item_kind(kind: &str) -> Option<&'static str> {
+    const KINDS: [&str; 16] = [
+        "constant",
+        "quantity",
+        "variable",
+        "expression",
+        "position",
+        "rotation",
+        "scale",
+        "matrix",
+        "isotope",
+        "element",
+        "material",
+        "solid",
+        "volume",
+        "opticalsurface",
+        "skinsurface",
+        "bordersurface",
+    ];
+    KINDS.iter().find(|k| **k == kind).copied()
+}
+
+/// The kind of item a `ref` attribute on `tag` names. A mixture component
+/// may name an element or a material; elements take precedence, as in
+/// `G4GDMLReadMaterials::MixtureRead`.
+pub(crate) fn ref_kind(
+    tag: &str,
+    value: &str,
+    element_names: &HashSet<&str>,
+) -> Option<&'static str> {
+    Some(match tag {
+        "materialref" => "material",
+        "solidref" | "first" | "second" => "solid",
+        "volumeref" | "world" => "volume",
+        "positionref" => "position",
+        "rotationref" => "rotation",
+        "scaleref" => "scale",
+        "physvolref" => "physvol",
+        "fraction" | "composite" if element_names.contains(value) => "element",
+        "fraction" | "composite" => "material",
+        _ => return None,
+    })
+}
+
+/// `from` refers to `to` through `attribute`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reference {
+    pub from: ItemId,
+    pub to: ItemId,
+    /// Where in `from` the reference sits, e.g. `solidref`, `rmax`,
+    /// `physvol[2]/position/x`.
+    pub attribute: String,
+}
+
+/// Attribute name used for a placement resolved from `<file>`. The placed
+/// volume lives in another file, so walks that follow module boundaries must
+/// not cross this edge.
+pub const INCLUDE_ATTRIBUTE: &str = "file";
+
+struct Collector<'a> {
+    scalar_kinds: HashMap<&'a str, &'static str>,
+    matrix_names: Vec<&'a str>,
+    element_names: HashSet<&'a str>,
+    out: Vec<Reference>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(doc: &'a GdmlDocument) -> Self {
+        let d = &doc.defines;
+        let mut scalar_kinds = HashMap::new();
+        // Later kinds win on a name clash, mirroring the evaluator, which
+        // evaluates expressions last. Matrices come first: their bare name is
+        // never a value in Geant4, only `name[i,j]` is.
+        for (kind, names) in [
+            (
+                "matrix",
+                d.matrices
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "constant",
+                d.constants.iter().map(|c| c.name.as_str()).collect(),
+            ),
+            (
+                "quantity",
+                d.quantities.iter().map(|c| c.name.as_str()).collect(),
+            ),
+            (
+                "variable",
+                d.variables.iter().map(|c| c.name.as_str()).collect(),
+            ),
+            (
+                "expression",
+                d.expressions.iter().map(|c| c.name.as_str()).collect(),
+            ),
+        ] {
+            for name in names {
+                scalar_kinds.insert(name, kind);
+            }
+        }
+        let element_names = doc
+            .materials
+            .elements
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        Self {
+            scalar_kinds,
+            matrix_names: d.matrices.iter().map(|m| m.name.as_str()).collect(),
+            element_names,
+            out: Vec::new(),
+        }
+    }
+
+    fn edge(&mut self, from: &ItemId, kind: &'static str, name: &str, attribute: &str) {
+        if name.is_empty() {
+            return;
+        }
+        self.out.push(Reference {
+            from: from.clone(),
+            to: ItemId::new(kind, name),
+            attribute: attribute.to_string(),
+        });
+    }
+
+    fn expr(&mut self, from: &ItemId, expr: &str, attribute: &str) {
+        let mut seen = HashSet::new();
+        for ident in extract_identifiers(expr) {
+            if !seen.insert(ident.clone()) {
+                continue;
+            }
+            if let Some(kind) = self.scalar_kinds.get(ident.as_str()).copied() {
+                self.edge(from, kind, &ident, attribute);
+            } else if let Some(matrix) = self.matrix_of_element(&ident) {
+                self.edge(from, "matrix", matrix, attribute);
+            }
+        }
+    }
+
+    /// The matrix whose generated element constant (`m_0_1`, `m_3`) is
+    /// `ident`. The longest matching name wins, since `a_b` and `a` can both
+    /// be matrices.
+    fn matrix_of_element(&self, ident: &str) -> Option<&'a str> {
+        self.matrix_names
+            .iter()
+            .copied()
+            .filter(|m| {
+                ident
+                    .strip_prefix(m)
+                    .and_then(|s| s.strip_prefix('_'))
+                    .is_some_and(|s| {
+                        s.split('_')
+                            .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+                    })
+            })
+            .max_by_key(|m| m.len())
+    }
+
+    fn opt_expr(&mut self, from: &ItemId, expr: &Option<String>, attribute: &str) {
+        if let Some(e) = expr {
+            self.expr(from, e, attribute);
+        }
+    }
+
+    /// Walk a serialised solid or placement, classifying each string leaf by
+    /// the field it sits in.
+    fn walk(&mut self, from: &ItemId, value: &Value, key: &str, path: &str) {
+        match value {
+            Value::String(s) => match key {
+                "name" | "type" | "operation" | "lunit" | "aunit" | "unit" | "scale_name" => {}
+                "first_ref" | "second_ref" | "solid_ref" => self.edge(from, "solid", s, path),
+                "scale_ref" => self.edge(from, "scale", s, path),
+                "vertex1" | "vertex2" | "vertex3" | "vertex4" => {
+                    self.edge(from, "position", s, path)
+                }
+                _ => self.expr(from, s, path),
+            },
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.walk(from, item, key, &format!("{}[{}]", path, i));
+                }
+            }
+            Value::Object(map) => {
+                // PlacementPos / PlacementRot serialise as {"Ref": name} or
+                // {"Inline": {...}}; which define kind a Ref names depends on
+                // the field holding it.
+                if let Some(Value::String(name)) = map.get("Ref") {
+                    let kind = if key.contains("rotation") {
+                        "rotation"
+                    } else {
+                        "position"
+                    };
+                    self.edge(from, kind, name, path);
+                    return;
+                }
+                // Tessellated facets are externally tagged enums.
+                for (k, v) in map {
+                    let child_key = if k == "Inline" || k == "Triangular" || k == "Quadrangular" {
+                        key
+                    } else {
+                        k.as_str()
+                    };
+                    let child_path = if path.is_empty() {
+                        k.clone()
+                    } else if k == "Inline" || k == "Triangular" || k == "Quadrangular" {
+                        path.to_string()
+                    } else {
+                        format!("{}/{}", path, k)
+                    };
+                    self.walk(from, v, child_key, &child_path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// References inside a `<loop>`, which is kept as XML.
+    ///
+    /// Each attribute is read as the body's element reads it once expanded:
+    /// `ref` names an item of the kind its tag implies, and anything but a name
+    /// or unit is an expression (`for` included -- it names the loop
+    /// variable). A name built with brackets (`Slice[i]`) exists only after
+    /// expansion, so it yields no edge here.
+    fn raw_xml(&mut self, from: &ItemId, xml: &str, path: &str) {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            let e = match reader.read_event() {
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) => e,
+                Ok(Event::Eof) | Err(_) => break,
+                Ok(_) => continue,
+            };
+            let tag = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+            for attr in e.attributes().flatten() {
+                let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                let raw = String::from_utf8_lossy(&attr.value);
+                let value = quick_xml::escape::unescape(&raw)
+                    .map(|c| c.into_owned())
+                    .unwrap_or_else(|_| raw.into_owned());
+                let at = if path.is_empty() {
+                    tag.clone()
+                } else {
+                    format!("{}/{}", path, tag)
+                };
+                match key.as_str() {
+                    "name" | "type" | "lunit" | "aunit" | "unit" => {}
+                    "ref" => {
+                        if value.contains('[') {
+                            continue;
+                        }
+                        if let Some(kind) = ref_kind(&tag, &value, &self.element_names) {
+                            self.edge(from, kind, &value, &at);
+                        }
+                    }
+                    _ => self.expr(from, &value, &format!("{}/{}", at, key)),
+                }
+            }
+        }
+    }
+
+    fn placement<T: Serialize>(
+        &mut self,
+        from: &ItemId,
+        value: &Option<T>,
+        field: &str,
+        path: &str,
+    ) {
+        if let Some(v) = value {
+            if let Ok(v) = serde_json::to_value(v) {
+                self.walk(from, &v, field, path);
+            }
+        }
+    }
+}
+
+/// Every reference in `doc`, in document order.
+pub fn collect_references(doc: &GdmlDocument) -> Vec<Reference> {
+    let mut c = Collector::new(doc);
+    let d = &doc.defines;
+
+    for k in &d.constants {
+        c.expr(&ItemId::new("constant", &k.name), &k.value, "value");
+    }
+    for q in &d.quantities {
+        c.expr(&ItemId::new("quantity", &q.name), &q.value, "value");
+    }
+    for v in &d.variables {
+        c.expr(&ItemId::new("variable", &v.name), &v.value, "value");
+    }
+    for e in &d.expressions {
+        c.expr(&ItemId::new("expression", &e.name), &e.value, "value");
+    }
+    for p in &d.positions {
+        let from = ItemId::new("position", &p.name);
+        c.opt_expr(&from, &p.x, "x");
+        c.opt_expr(&from, &p.y, "y");
+        c.opt_expr(&from, &p.z, "z");
+    }
+    for r in &d.rotations {
+        let from = ItemId::new("rotation", &r.name);
+        c.opt_expr(&from, &r.x, "x");
+        c.opt_expr(&from, &r.y, "y");
+        c.opt_expr(&from, &r.z, "z");
+    }
+    for s in &d.scales {
+        let from = ItemId::new("scale", &s.name);
+        c.opt_expr(&from, &s.x, "x");
+        c.opt_expr(&from, &s.y, "y");
+        c.opt_expr(&from, &s.z, "z");
+    }
+    for mx in &d.matrices {
+        let from = ItemId::new("matrix", &mx.name);
+        c.expr(&from, &mx.coldim, "coldim");
+        c.expr(&from, &mx.values, "values");
+    }
+
+    let m = &doc.materials;
+    for iso in &m.isotopes {
+        let from = ItemId::new("isotope", &iso.name);
+        c.opt_expr(&from, &iso.n, "N");
+        c.opt_expr(&from, &iso.z, "Z");
+        c.opt_expr(&from, &iso.atom_value, "atom");
+    }
+    for el in &m.elements {
+        let from = ItemId::new("element", &el.name);
+        c.opt_expr(&from, &el.z, "Z");
+        c.opt_expr(&from, &el.atom_value, "atom");
+        for (i, f) in el.fractions.iter().enumerate() {
+            c.edge(&from, "isotope", &f.ref_name, &format!("fraction[{}]", i));
+            c.expr(&from, &f.n, &format!("fraction[{}]/n", i));
+        }
+    }
+    for mat in &m.materials {
+        let from = ItemId::new("material", &mat.name);
+        c.opt_expr(&from, &mat.z, "Z");
+        c.opt_expr(&from, &mat.atom_value, "atom");
+        if let Some(density) = &mat.density {
+            c.expr(&from, &density.value, "D");
+        }
+        if let Some(dref) = &mat.density_ref {
+            if let Some(kind) = c.scalar_kinds.get(dref.as_str()).copied() {
+                c.edge(&from, kind, dref, "Dref");
+            }
+        }
+        for (field, value) in [
+            ("T", &mat.temperature),
+            ("P", &mat.pressure),
+            ("MEE", &mat.mee),
+            ("RL", &mat.rl),
+            ("AL", &mat.al),
+        ] {
+            if let Some(v) = value {
+                c.expr(&from, &v.value, field);
+            }
+        }
+        for (i, comp) in mat.components.iter().enumerate() {
+            let (tag, n, ref_name) = match comp {
+                MaterialComponent::Fraction { n, ref_name } => ("fraction", n, ref_name),
+                MaterialComponent::Composite { n, ref_name } => ("composite", n, ref_name),
+            };
+            // A component may name an element or another material; elements
+            // take precedence, as in G4GDMLReadMaterials::MixtureRead.
+            let kind = if c.element_names.contains(ref_name.as_str()) {
+                "element"
+            } else {
+                "material"
+            };
+            c.edge(&from, kind, ref_name, &format!("{}[{}]", tag, i));
+            c.expr(&from, n, &format!("{}[{}]/n", tag, i));
+        }
+        for (i, prop) in mat.properties.iter().enumerate() {
+            if let Some(r) = &prop.ref_name {
+                c.edge(&from, "matrix", r, &format!("property[{}]", i));
+            }
+        }
+    }
+
+    for solid in &doc.solids.solids {
+        let from = ItemId::new("solid", solid.name());
+        if let Ok(value) = serde_json::to_value(solid) {
+            c.walk(&from, &value, "", "");
+        }
+    }
+
+    for vol in &doc.structure.volumes {
+        let from = ItemId::new("volume", &vol.name);
+        c.edge(&from, "material", &vol.material_ref, "materialref");
+        c.edge(&from, "solid", &vol.solid_ref, "solidref");
+        for (i, pv) in vol.physvols.iter().enumerate() {
+            let at = format!("physvol[{}]", i);
+            let attribute = if pv.included.is_some() {
+                INCLUDE_ATTRIBUTE.to_string()
+            } else {
+                format!("{}/volumeref", at)
+            };
+            if pv.file_ref.is_none() {
+                c.edge(&from, "volume", &pv.volume_ref, &attribute);
+            }
+            c.opt_expr(&from, &pv.copynumber, &format!("{}/copynumber", at));
+            c.placement(&from, &pv.position, "position", &format!("{}/position", at));
+            c.placement(&from, &pv.rotation, "rotation", &format!("{}/rotation", at));
+        }
+        if let Some(rep) = &vol.replica {
+            c.edge(&from, "volume", &rep.volume_ref, "replicavol/volumeref");
+            c.expr(&from, &rep.number, "replicavol/number");
+            c.expr(&from, &rep.width, "replicavol/width");
+            c.expr(&from, &rep.offset, "replicavol/offset");
+        }
+        for (i, lp) in vol.loops.iter().enumerate() {
+            c.raw_xml(&from, &lp.xml, &format!("loop[{}]", i));
+        }
+    }
+
+    // Preserved-verbatim elements (`<assembly>`, a loop outside a volume...)
+    // belong to no modelled item; each stands for itself as `raw`.
+    for (i, raw) in doc.raw_unknown.iter().enumerate() {
+        let from = ItemId::new("raw", &format!("{}[{}]", raw.tag, i));
+        c.raw_xml(&from, &raw.xml, "");
+    }
+
+    for os in &doc.solids.optical_surfaces {
+        let from = ItemId::new("opticalsurface", &os.name);
+        c.opt_expr(&from, &os.value, "value");
+        for (i, prop) in os.properties.iter().enumerate() {
+            if let Some(r) = &prop.ref_name {
+                c.edge(&from, "matrix", r, &format!("property[{}]", i));
+            }
+        }
+    }
+    for skin in &doc.structure.skin_surfaces {
+        let from = ItemId::new("skinsurface", &skin.name);
+        c.edge(
+            &from,
+            "opticalsurface",
+            &skin.surface_property,
+            "surfaceproperty",
+        );
+        c.edge(&from, "volume", &skin.volume_ref, "volumeref");
+    }
+    for border in &doc.structure.border_surfaces {
+        let from = ItemId::new("bordersurface", &border.name);
+        c.edge(
+            &from,
+            "opticalsurface",
+            &border.surface_property,
+            "surfaceproperty",
+        );
+        for (i, r) in border.physvol_refs.iter().enumerate() {
+            c.edge(&from, "physvol", r, &format!("physvolref[{}]", i));
+        }
+    }
+
+    for setup in std::iter::once(&doc.setup).chain(&doc.setups) {
+        let from = ItemId::new("setup", &setup.name);
+        c.edge(&from, "volume", &setup.world_ref, "world");
+    }
+
+    c.out
+}
+
+/// Everything reachable from `roots` by following references forward,
+/// roots included. Edges for which `follow` returns false are not crossed.
+pub fn reachable_from(
+    references: &[Reference],
+    roots: impl IntoIterator<Item = ItemId>,
+    follow: impl Fn(&Reference) -> bool,
+) -> HashSet<ItemId> {
+    let mut outgoing: HashMap<&ItemId, Vec<&Reference>> = HashMap::new();
+    for r in references {
+        outgoing.entry(&r.from).or_default().push(r);
+    }
+    let mut seen: HashSet<ItemId> = HashSet::new();
+    let mut queue: VecDeque<ItemId> = VecDeque::new();
+    for root in roots {
+        if seen.insert(root.clone()) {
+            queue.push_back(root);
+        }
+    }
+    while let Some(item) = queue.pop_front() {
+        let Some(edges) = outgoing.get(&item) else {
+            continue;
+        };
+        for r in edges {
+            if follow(r) && seen.insert(r.to.clone()) {
+                queue.push_back(r.to.clone());
+            }
+        }
+    }
+    seen
+}
+
+/// [`collect_references`] turned around: for each item, what refers to it.
+pub struct ReferenceIndex {
+    incoming: HashMap<ItemId, Vec<Reference>>,
+}
+
+impl ReferenceIndex {
+    pub fn build(doc: &GdmlDocument) -> Self {
+        let mut incoming: HashMap<ItemId, Vec<Reference>> = HashMap::new();
+        for r in collect_references(doc) {
+            incoming.entry(r.to.clone()).or_default().push(r);
+        }
+        Self { incoming }
+    }
+
+    /// The references naming `item` directly, in document order.
+    pub fn referrers(&self, item: &ItemId) -> &[Reference] {
+        self.incoming
+            .get(item)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Every item that depends on `item`, directly or through others: what a
+    /// change to it can affect. `item` itself is not included.
+    pub fn dependents(&self, item: &ItemId) -> BTreeSet<ItemId> {
+        let mut seen = BTreeSet::new();
+        let mut queue = VecDeque::from([item.clone()]);
+        while let Some(next) = queue.pop_front() {
+            for r in self.referrers(&next) {
+                if r.from != *item && seen.insert(r.from.clone()) {
+                    queue.push_back(r.from.clone());
+                }
+            }
+        }
+        seen
+    }
+
+    /// Every referenced item called `name`, whatever its kind. A name can be
+    /// a solid and a volume at once, or a reference to something that does
+    /// not exist.
+    pub fn targets_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ItemId> {
+        self.incoming.keys().filter(move |id| id.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn index(body: &str) -> ReferenceIndex {
+        let xml = format!(
+            r#"<?xml version="1.0"?><gdml>{}<setup name="Default" version="1.0"><world ref="World"/></setup></gdml>"#,
+            body
+        );
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "t.gdml".to_string()).unwrap();
+        ReferenceIndex::build(&doc)
+    }
+
+    const DOC: &str = r#"
+      <define>
+        <constant name="half" value="5"/>
+        <constant name="full" value="2*half"/>
+        <matrix name="M" coldim="2" values="1 2 3 4"/>
+        <matrix name="M_a" coldim="1" values="half"/>
+        <constant name="corner" value="M_1_1 + M[1,2] + M_a_0"/>
+        <position name="up" z="full"/>
+      </define>
+      <materials>
+        <element name="H" formula="H" Z="1"><atom value="1.008"/></element>
+        <material name="Gas" state="gas">
+          <property name="RINDEX" ref="M"/>
+          <D value="1e-3"/><fraction n="1" ref="H"/>
+        </material>
+      </materials>
+      <solids>
+        <box name="Cell" x="full" y="corner" z="1"/>
+        <box name="WorldBox" x="100" y="100" z="100"/>
+        <union name="Pair"><first ref="Cell"/><second ref="Cell"/><positionref ref="up"/></union>
+        <opticalsurface name="S" model="glisur" finish="polished" type="dielectric_dielectric"/>
+      </solids>
+      <structure>
+        <volume name="CellVol"><materialref ref="Gas"/><solidref ref="Cell"/></volume>
+        <volume name="World"><materialref ref="Gas"/><solidref ref="WorldBox"/>
+          <physvol><volumeref ref="CellVol"/><positionref ref="up"/></physvol>
+        </volume>
+        <bordersurface name="Edge" surfaceproperty="S">
+          <physvolref ref="CellVol_PV"/><physvolref ref="World"/>
+        </bordersurface>
+      </structure>"#;
+
+    fn from(index: &ReferenceIndex, kind: &'static str, name: &str) -> Vec<String> {
+        index
+            .referrers(&ItemId::new(kind, name))
+            .iter()
+            .map(|r| format!("{} {} {}", r.from.kind, r.from.name, r.attribute))
+            .collect()
+    }
+
+    #[test]
+    fn expressions_refer_to_defines_and_matrix_entries() {
+        let i = index(DOC);
+        assert_eq!(
+            from(&i, "constant", "half"),
+            ["constant full value", "matrix M_a values"]
+        );
+        assert_eq!(
+            from(&i, "constant", "full"),
+            ["position up z", "solid Cell x"]
+        );
+        // `M_1_1` and `M[1,2]` each name M; `M_a_0` is an entry of the longer
+        // matrix name, not of M.
+        assert_eq!(
+            from(&i, "matrix", "M"),
+            [
+                "constant corner value",
+                "constant corner value",
+                "material Gas property[0]"
+            ]
+        );
+        assert_eq!(from(&i, "matrix", "M_a"), ["constant corner value"]);
+
+        let dependents = i.dependents(&ItemId::new("constant", "half"));
+        for (kind, name) in [("solid", "Pair"), ("volume", "World"), ("matrix", "M_a")] {
+            assert!(
+                dependents.contains(&ItemId::new(kind, name)),
+                "{kind} {name}"
+            );
+        }
+        assert!(!dependents.contains(&ItemId::new("matrix", "M")));
+    }
+
+    #[test]
+    fn typed_references_name_what_they_point_at() {
+        let i = index(DOC);
+        assert_eq!(from(&i, "element", "H"), ["material Gas fraction[0]"]);
+        assert_eq!(
+            from(&i, "material", "Gas"),
+            ["volume CellVol materialref", "volume World materialref"]
+        );
+        assert_eq!(
+            from(&i, "solid", "Cell"),
+            [
+                "solid Pair first_ref",
+                "solid Pair second_ref",
+                "volume CellVol solidref"
+            ]
+        );
+        assert_eq!(
+            from(&i, "position", "up"),
+            ["solid Pair position", "volume World physvol[0]/position"]
+        );
+        assert_eq!(
+            from(&i, "volume", "CellVol"),
+            ["volume World physvol[0]/volumeref"]
+        );
+        assert_eq!(
+            from(&i, "opticalsurface", "S"),
+            ["bordersurface Edge surfaceproperty"]
+        );
+        // An unnamed placement is known by its generated `<volume>_PV`; a
+        // physvolref names a placement as written either way.
+        assert_eq!(
+            from(&i, "physvol", "CellVol_PV"),
+            ["bordersurface Edge physvolref[0]"]
+        );
+        assert_eq!(
+            from(&i, "physvol", "World"),
+            ["bordersurface Edge physvolref[1]"]
+        );
+        assert!(i.targets_named("World").any(|t| t.kind == "volume"));
+    }
+}
@@ -0,0 +1,853 @@
+//! Renaming an item and every reference to it.
+//!
+//! A reference in GDML is either a name in a typed slot (`<solidref>`,
+//! `first`/`second`, `<positionref>`, a mixture component, a `<physvolref>`)
+//! or an identifier inside an expression. The first kind is rewritten where it
+//! names exactly the old item; the second with word boundaries, so renaming
+//! `r` leaves `rmax` and `sqrt(r)`'s `sqrt` alone. `<loop>` bodies and other
+//! preserved-verbatim XML are rewritten attribute by attribute, and left
+//! byte-for-byte untouched when nothing in them refers to the item.
+//!
+//! Solids are rewritten through their serde representation, classifying each
+//! field the way [`references`](super::references) does, so a newly modelled
+//! solid is covered without touching this file.
+
+use anyhow::{bail, Result};
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use super::model::*;
+use super::references::ref_kind;
+use super::surfaces::physvol_names;
+use crate::eval::context::EvalContext;
+use crate::eval::dependency::replace_identifiers;
+
+/// Every kind [`rename`] accepts.
+pub const RENAMEABLE_KINDS: [&str; 14] = [
+    "constant",
+    "quantity",
+    "variable",
+    "expression",
+    "matrix",
+    "position",
+    "rotation",
+    "scale",
+    "isotope",
+    "element",
+    "material",
+    "solid",
+    "volume",
+    "physvol",
+];
+
+/// Kinds whose names live in the expression evaluator.
+const EVALUATOR_KINDS: [&str; 5] = ["constant", "quantity", "variable", "expression", "matrix"];
+
+fn define_kind(kind: &str) -> Option<DefineKind> {
+    Some(match kind {
+        "constant" => DefineKind::Constant,
+        "quantity" => DefineKind::Quantity,
+        "variable" => DefineKind::Variable,
+        "expression" => DefineKind::Expression,
+        "matrix" => DefineKind::Matrix,
+        "position" => DefineKind::Position,
+        "rotation" => DefineKind::Rotation,
+        "scale" => DefineKind::Scale,
+        _ => return None,
+    })
+}
+
+/// Whether `kind` is declared in `<define>`, so renaming it changes what the
+/// evaluator holds.
+pub fn is_define(kind: &str) -> bool {
+    define_kind(kind).is_some()
+}
+
+/// The section a kind's elements, and so their comment anchors, sit in.
+fn section_of(kind: &str) -> &'static str {
+    match kind {
+        "isotope" | "element" | "material" => "materials",
+        "solid" => "solids",
+        "volume" | "physvol" => "structure",
+        _ => "define",
+    }
+}
+
+/// The names `kind` already uses, which a rename must not collide with.
+///
+/// The evaluator kinds share one namespace, since Geant4 defines all of them
+/// as evaluator variables, and materials and elements share one because a
+/// mixture component may name either.
+pub fn names_in_namespace<'a>(doc: &'a GdmlDocument, kind: &str) -> HashSet<&'a str> {
+    let d = &doc.defines;
+    let m = &doc.materials;
+    let mut names: HashSet<&str> = HashSet::new();
+    match kind {
+        "constant" | "quantity" | "variable" | "expression" | "matrix" => {
+            names.extend(d.constants.iter().map(|x| x.name.as_str()));
+            names.extend(d.quantities.iter().map(|x| x.name.as_str()));
+            names.extend(d.variables.iter().map(|x| x.name.as_str()));
+            names.extend(d.expressions.iter().map(|x| x.name.as_str()));
+            names.extend(d.matrices.iter().map(|x| x.name.as_str()));
+        }
+        "position" => names.extend(d.positions.iter().map(|x| x.name.as_str())),
+        "rotation" => names.extend(d.rotations.iter().map(|x| x.name.as_str())),
+        "scale" => names.extend(d.scales.iter().map(|x| x.name.as_str())),
+        "isotope" => names.extend(m.isotopes.iter().map(|x| x.name.as_str())),
+        "element" | "material" => {
+            names.extend(m.elements.iter().map(|x| x.name.as_str()));
+            names.extend(m.materials.iter().map(|x| x.name.as_str()));
+        }
+        "solid" => names.extend(doc.solids.solids.iter().map(|s| s.name())),
+        "volume" => names.extend(doc.structure.volumes.iter().map(|v| v.name.as_str())),
+        _ => {}
+    }
+    names
+}
+
+/// Whether `doc` has an item of `kind` called `name`.
+pub fn exists(doc: &GdmlDocument, kind: &str, name: &str) -> bool {
+    let d = &doc.defines;
+    let m = &doc.materials;
+    match kind {
+        "constant" => d.constants.iter().any(|x| x.name == name),
+        "quantity" => d.quantities.iter().any(|x| x.name == name),
+        "variable" => d.variables.iter().any(|x| x.name == name),
+        "expression" => d.expressions.iter().any(|x| x.name == name),
+        "matrix" => d.matrices.iter().any(|x| x.name == name),
+        "element" => m.elements.iter().any(|x| x.name == name),
+        "material" => m.materials.iter().any(|x| x.name == name),
+        "physvol" => physvol_names(doc).contains(name),
+        _ => names_in_namespace(doc, kind).contains(name),
+    }
+}
+
+/// Rename the item of `kind` called `old` to `new` and rewrite every
+/// reference to it. Returns how many references were rewritten.
+pub fn rename(doc: &mut GdmlDocument, kind: &str, old: &str, new: &str) -> Result<usize> {
+    let Some(kind) = RENAMEABLE_KINDS.iter().find(|k| **k == kind).copied() else {
+        bail!(
+            "Cannot rename a '{}'; renameable kinds are {}",
+            kind,
+            RENAMEABLE_KINDS.join(", ")
+        );
+    };
+    if !exists(doc, kind, old) {
+        bail!("No {} named '{}'", kind, old);
+    }
+    if new == old {
+        return Ok(0);
+    }
+    check_new_name(doc, kind, new)?;
+
+    let mut r = Renamer {
+        kind,
+        old,
+        new,
+        element_names: doc
+            .materials
+            .elements
+            .iter()
+            .map(|e| e.name.clone())
+            .collect(),
+        count: 0,
+    };
+    r.declaration(doc);
+    r.defines(&mut doc.defines);
+    r.materials(&mut doc.materials);
+    r.solids(&mut doc.solids);
+    r.structure(&mut doc.structure);
+    for setup in std::iter::once(&mut doc.setup).chain(doc.setups.iter_mut()) {
+        if kind == "volume" {
+            r.name(&mut setup.world_ref);
+        }
+    }
+    for raw in &mut doc.raw_unknown {
+        r.xml(&mut raw.xml);
+    }
+
+    // Bookkeeping that follows the item rather than a reference to it.
+    let section = section_of(kind);
+    for anchor in &mut doc.order.anchors {
+        if anchor.section == section && anchor.before.as_deref() == Some(old) {
+            anchor.before = Some(new.to_string());
+        }
+    }
+    if let Some(dk) = define_kind(kind) {
+        for slot in &mut doc.order.define_slots {
+            if slot.kind == dk && slot.name == old {
+                slot.name = new.to_string();
+            }
+        }
+        if let Some(names) = doc.materials_define.as_mut() {
+            for name in names.iter_mut().filter(|n| *n == old) {
+                *name = new.to_string();
+            }
+        }
+    }
+    if let Some(provenance) = doc.provenance.as_mut() {
+        provenance.rename(kind, old, new);
+        if kind == "volume" {
+            for file in provenance.files.iter_mut().filter(|f| f.world == old) {
+                file.world = new.to_string();
+            }
+        }
+    }
+
+    Ok(r.count)
+}
+
+fn check_new_name(doc: &GdmlDocument, kind: &str, new: &str) -> Result<()> {
+    if new.is_empty() {
+        bail!("The new name is empty");
+    }
+    if new
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '[' | ']' | '"' | '<' | '>' | '&'))
+    {
+        bail!(
+            "'{}' is not a usable name: it may not contain whitespace, brackets, quotes, \
+             '<', '>' or '&'",
+            new
+        );
+    }
+    if EVALUATOR_KINDS.contains(&kind) {
+        // Used inside expressions, so it has to read as one identifier.
+        let mut chars = new.chars();
+        let first_ok = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+        if !first_ok || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!(
+                "'{}' is not a valid identifier; a {} name must match [A-Za-z_][A-Za-z0-9_]*",
+                new,
+                kind
+            );
+        }
+        if EvalContext::new().get(new).is_some() {
+            bail!(
+                "'{}' is a built-in unit or constant; a {} of that name would shadow it in \
+                 every expression",
+                new,
+                kind
+            );
+        }
+    }
+    let taken = if kind == "physvol" {
+        physvol_names(doc).contains(new)
+    } else {
+        names_in_namespace(doc, kind).contains(new)
+    };
+    if taken {
+        bail!(
+            "'{}' is already used by another {}",
+            new,
+            namespace_label(kind)
+        );
+    }
+    Ok(())
+}
+
+fn namespace_label(kind: &str) -> &str {
+    match kind {
+        "constant" | "quantity" | "variable" | "expression" | "matrix" => "define",
+        "element" | "material" => "material or element",
+        "physvol" => "placement",
+        other => other,
+    }
+}
+
+struct Renamer<'a> {
+    kind: &'static str,
+    old: &'a str,
+    new: &'a str,
+    element_names: HashSet<String>,
+    count: usize,
+}
+
+impl Renamer<'_> {
+    /// A slot holding a name of the kind being renamed.
+    fn name(&mut self, slot: &mut String) {
+        if slot == self.old {
+            *slot = self.new.to_string();
+            self.count += 1;
+        }
+    }
+
+    fn opt_name(&mut self, slot: &mut Option<String>) {
+        if let Some(s) = slot {
+            self.name(s);
+        }
+    }
+
+    /// The replacement for one identifier in an expression, if it refers to
+    /// the item. A matrix is also referred to by its generated element names:
+    /// `m_0_1`, or `m_3` for a single row or column. Either shape follows, so
+    /// a name the evaluator leaves undefined still names the same matrix.
+    fn identifier(&self, word: &str) -> Option<String> {
+        if word == self.old {
+            return Some(self.new.to_string());
+        }
+        if self.kind == "matrix" {
+            let suffix = word.strip_prefix(self.old)?.strip_prefix('_')?;
+            if suffix
+                .split('_')
+                .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+            {
+                return Some(format!("{}_{}", self.new, suffix));
+            }
+        }
+        None
+    }
+
+    fn expr(&mut self, slot: &mut String) {
+        if !EVALUATOR_KINDS.contains(&self.kind) || !slot.contains(self.old) {
+            return;
+        }
+        let rewritten = replace_identifiers(slot, |w| self.identifier(w));
+        if rewritten != *slot {
+            *slot = rewritten;
+            self.count += 1;
+        }
+    }
+
+    fn opt_expr(&mut self, slot: &mut Option<String>) {
+        if let Some(s) = slot {
+            self.expr(s);
+        }
+    }
+
+    fn xyz(&mut self, x: &mut Option<String>, y: &mut Option<String>, z: &mut Option<String>) {
+        self.opt_expr(x);
+        self.opt_expr(y);
+        self.opt_expr(z);
+    }
+
+    /// An auxiliary whose value is exactly the old name (`SensDet`-style
+    /// tags naming a volume, a material...) is taken to refer to it.
+    fn auxiliaries(&mut self, auxiliaries: &mut [Auxiliary]) {
+        for aux in auxiliaries {
+            self.name(&mut aux.auxvalue);
+            self.auxiliaries(&mut aux.children);
+        }
+    }
+
+    /// The item's own `name`.
+    fn declaration(&mut self, doc: &mut GdmlDocument) {
+        let (old, new) = (self.old, self.new.to_string());
+        let set = |name: &mut String| {
+            if name == old {
+                *name = new.clone();
+            }
+        };
+        let d = &mut doc.defines;
+        let m = &mut doc.materials;
+        match self.kind {
+            "constant" => d.constants.iter_mut().for_each(|x| set(&mut x.name)),
+            "quantity" => d.quantities.iter_mut().for_each(|x| set(&mut x.name)),
+            "variable" => d.variables.iter_mut().for_each(|x| set(&mut x.name)),
+            "expression" => d.expressions.iter_mut().for_each(|x| set(&mut x.name)),
+            "matrix" => d.matrices.iter_mut().for_each(|x| set(&mut x.name)),
+            "position" => d.positions.iter_mut().for_each(|x| set(&mut x.name)),
+            "rotation" => d.rotations.iter_mut().for_each(|x| set(&mut x.name)),
+            "scale" => d.scales.iter_mut().for_each(|x| set(&mut x.name)),
+            "isotope" => m.isotopes.iter_mut().for_each(|x| set(&mut x.name)),
+            "element" => m.elements.iter_mut().for_each(|x| set(&mut x.name)),
+            "material" => m.materials.iter_mut().for_each(|x| set(&mut x.name)),
+            "solid" => {
+                for solid in &mut doc.solids.solids {
+                    if solid.name() == old {
+                        // Internally tagged, so `name` is a top-level field.
+                        if let Ok(mut v) = serde_json::to_value(&*solid) {
+                            v["name"] = Value::String(new.clone());
+                            if let Ok(renamed) = serde_json::from_value(v) {
+                                *solid = renamed;
+                            }
+                        }
+                    }
+                }
+            }
+            "volume" => doc
+                .structure
+                .volumes
+                .iter_mut()
+                .for_each(|x| set(&mut x.name)),
+            "physvol" => {
+                // An unnamed placement is known by its generated name; giving
+                // it the new one makes that explicit.
+                for vol in &mut doc.structure.volumes {
+                    for pv in &mut vol.physvols {
+                        let current = match &pv.name {
+                            Some(n) if !n.is_empty() => n.clone(),
+                            _ => format!("{}_PV", pv.volume_ref),
+                        };
+                        if current == old {
+                            pv.name = Some(new.clone());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn defines(&mut self, d: &mut DefineSection) {
+        for c in &mut d.constants {
+            self.expr(&mut c.value);
+        }
+        for q in &mut d.quantities {
+            self.expr(&mut q.value);
+        }
+        for v in &mut d.variables {
+            self.expr(&mut v.value);
+        }
+        for e in &mut d.expressions {
+            self.expr(&mut e.value);
+        }
+        for p in &mut d.positions {
+            self.xyz(&mut p.x, &mut p.y, &mut p.z);
+        }
+        for r in &mut d.rotations {
+            self.xyz(&mut r.x, &mut r.y, &mut r.z);
+        }
+        for s in &mut d.scales {
+            self.xyz(&mut s.x, &mut s.y, &mut s.z);
+        }
+        for mx in &mut d.matrices {
+            self.expr(&mut mx.coldim);
+            self.expr(&mut mx.values);
+        }
+    }
+
+    fn properties(&mut self, properties: &mut [MaterialProperty]) {
+        for prop in properties {
+            if self.kind == "matrix" {
+                self.opt_name(&mut prop.ref_name);
+            }
+            self.opt_expr(&mut prop.values);
+        }
+    }
+
+    fn materials(&mut self, m: &mut MaterialSection) {
+        for iso in &mut m.isotopes {
+            self.opt_expr(&mut iso.n);
+            self.opt_expr(&mut iso.z);
+            self.opt_expr(&mut iso.atom_value);
+        }
+        for el in &mut m.elements {
+            self.opt_expr(&mut el.z);
+            self.opt_expr(&mut el.atom_value);
+            for f in &mut el.fractions {
+                if self.kind == "isotope" {
+                    self.name(&mut f.ref_name);
+                }
+                self.expr(&mut f.n);
+            }
+        }
+        for mat in &mut m.materials {
+            self.opt_expr(&mut mat.z);
+            self.opt_expr(&mut mat.atom_value);
+            if let Some(density) = &mut mat.density {
+                self.expr(&mut density.value);
+            }
+            if EVALUATOR_KINDS.contains(&self.kind) {
+                self.opt_name(&mut mat.density_ref);
+            }
+            for v in [
+                &mut mat.temperature,
+                &mut mat.pressure,
+                &mut mat.mee,
+                &mut mat.rl,
+                &mut mat.al,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                self.expr(&mut v.value);
+            }
+            for comp in &mut mat.components {
+                let (MaterialComponent::Fraction { n, ref_name }
+                | MaterialComponent::Composite { n, ref_name }) = comp;
+                // Elements take precedence over materials of the same name.
+                let names = if self.element_names.contains(ref_name.as_str()) {
+                    "element"
+                } else {
+                    "material"
+                };
+                if names == self.kind {
+                    self.name(ref_name);
+                }
+                self.expr(n);
+            }
+            self.properties(&mut mat.properties);
+        }
+    }
+
+    fn solids(&mut self, s: &mut SolidSection) {
+        for solid in &mut s.solids {
+            let Ok(mut value) = serde_json::to_value(&*solid) else {
+                continue;
+            };
+            let before = self.count;
+            self.walk(&mut value, "");
+            if self.count != before {
+                match serde_json::from_value(value) {
+                    Ok(renamed) => *solid = renamed,
+                    Err(_) => self.count = before,
+                }
+            }
+        }
+        for os in &mut s.optical_surfaces {
+            self.opt_expr(&mut os.value);
+            self.properties(&mut os.properties);
+        }
+    }
+
+    /// Rewrite a serialised solid, classifying each string leaf by the field
+    /// it sits in as `references::Collector::walk` does.
+    fn walk(&mut self, value: &mut Value, key: &str) {
+        match value {
+            Value::String(s) => {
+                let target = match key {
+                    "name" | "type" | "operation" | "lunit" | "aunit" | "unit" | "scale_name" => {
+                        return
+                    }
+                    "first_ref" | "second_ref" | "solid_ref" => "solid",
+                    "scale_ref" => "scale",
+                    "vertex1" | "vertex2" | "vertex3" | "vertex4" => "position",
+                    _ => {
+                        self.expr(s);
+                        return;
+                    }
+                };
+                if target == self.kind {
+                    self.name(s);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.walk(item, key);
+                }
+            }
+            Value::Object(map) => {
+                if let Some(Value::String(name)) = map.get_mut("Ref") {
+                    let target = if key.contains("rotation") {
+                        "rotation"
+                    } else {
+                        "position"
+                    };
+                    if target == self.kind {
+                        self.name(name);
+                    }
+                    return;
+                }
+                for (k, v) in map.iter_mut() {
+                    let child_key = if k == "Inline" || k == "Triangular" || k == "Quadrangular" {
+                        key.to_string()
+                    } else {
+                        k.clone()
+                    };
+                    self.walk(v, &child_key);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn placement_pos(&mut self, pos: &mut Option<PlacementPos>) {
+        match pos {
+            Some(PlacementPos::Ref(name)) if self.kind == "position" => self.name(name),
+            Some(PlacementPos::Inline(p)) => self.xyz(&mut p.x, &mut p.y, &mut p.z),
+            _ => {}
+        }
+    }
+
+    fn placement_rot(&mut self, rot: &mut Option<PlacementRot>) {
+        match rot {
+            Some(PlacementRot::Ref(name)) if self.kind == "rotation" => self.name(name),
+            Some(PlacementRot::Inline(r)) => self.xyz(&mut r.x, &mut r.y, &mut r.z),
+            _ => {}
+        }
+    }
+
+    fn structure(&mut self, s: &mut StructureSection) {
+        // Placements of the renamed volume that have no name of their own are
+        // known to border surfaces by the generated `<volume>_PV`, which
+        // changes with it.
+        let generated: Option<(String, String)> = (self.kind == "volume"
+            && s.volumes.iter().flat_map(|v| &v.physvols).any(|pv| {
+                pv.volume_ref == self.old && pv.name.as_deref().is_none_or(str::is_empty)
+            }))
+        .then(|| (format!("{}_PV", self.old), format!("{}_PV", self.new)));
+
+        for vol in &mut s.volumes {
+            match self.kind {
+                "material" => self.name(&mut vol.material_ref),
+                "solid" => self.name(&mut vol.solid_ref),
+                _ => {}
+            }
+            for pv in &mut vol.physvols {
+                if self.kind == "volume" {
+                    self.name(&mut pv.volume_ref);
+                    for file in [&mut pv.file_ref, &mut pv.included].into_iter().flatten() {
+                        self.opt_name(&mut file.volname);
+                    }
+                }
+                self.opt_expr(&mut pv.copynumber);
+                self.placement_pos(&mut pv.position);
+                self.placement_rot(&mut pv.rotation);
+            }
+            if let Some(rep) = &mut vol.replica {
+                if self.kind == "volume" {
+                    self.name(&mut rep.volume_ref);
+                }
+                self.expr(&mut rep.number);
+                self.expr(&mut rep.width);
+                self.expr(&mut rep.offset);
+                for d in &mut rep.direction {
+                    self.opt_expr(d);
+                }
+            }
+            self.auxiliaries(&mut vol.auxiliaries);
+            for lp in &mut vol.loops {
+                self.xml(&mut lp.xml);
+            }
+        }
+
+        for skin in &mut s.skin_surfaces {
+            if self.kind == "volume" {
+                self.name(&mut skin.volume_ref);
+            }
+        }
+        for border in &mut s.border_surfaces {
+            for r in &mut border.physvol_refs {
+                match (&generated, self.kind) {
+                    (Some((old_pv, new_pv)), _) if r == old_pv => {
+                        *r = new_pv.clone();
+                        self.count += 1;
+                    }
+                    (_, "physvol") => self.name(r),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Rewrite preserved XML. Returns it unchanged, byte for byte, unless an
+    /// attribute or `<expression>` body in it refers to the item.
+    fn xml(&mut self, xml: &mut String) {
+        if !xml.contains(self.old) {
+            return;
+        }
+        let before = self.count;
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(false);
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        loop {
+            let ev = match reader.read_event() {
+                Ok(Event::Eof) => break,
+                Ok(Event::Start(e)) => Event::Start(self.xml_element(&e)),
+                Ok(Event::Empty(e)) => Event::Empty(self.xml_element(&e)),
+                Ok(Event::Text(t)) => {
+                    let mut text = String::from_utf8_lossy(&t).into_owned();
+                    self.expr(&mut text);
+                    Event::Text(BytesText::from_escaped(text))
+                }
+                Ok(ev) => ev,
+                // Not well-formed on its own; leave it exactly as it was.
+                Err(_) => {
+                    self.count = before;
+                    return;
+                }
+            };
+            if writer.write_event(ev).is_err() {
+                self.count = before;
+                return;
+            }
+        }
+        if self.count != before {
+            match String::from_utf8(writer.into_inner().into_inner()) {
+                Ok(out) => *xml = out,
+                Err(_) => self.count = before,
+            }
+        }
+    }
+
+    fn xml_element(&mut self, e: &BytesStart) -> BytesStart<'static> {
+        let tag = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let before = self.count;
+        let mut out = BytesStart::new(tag.clone());
+        for attr in e.attributes().flatten() {
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            let raw = String::from_utf8_lossy(&attr.value);
+            let mut value = quick_xml::escape::unescape(&raw)
+                .map(|c| c.into_owned())
+                .unwrap_or_else(|_| raw.into_owned());
+            match key.as_str() {
+                "name" if tag == "physvol" && self.kind == "physvol" => self.name(&mut value),
+                "name" | "type" | "lunit" | "aunit" | "unit" => {}
+                "ref" => {
+                    let names: HashSet<&str> =
+                        self.element_names.iter().map(String::as_str).collect();
+                    let target = ref_kind(&tag, &value, &names);
+                    // Inside an `<element>` a fraction names an isotope.
+                    if target == Some(self.kind) || (self.kind == "isotope" && tag == "fraction") {
+                        self.name(&mut value);
+                    }
+                }
+                _ => self.expr(&mut value),
+            }
+            out.push_attribute((key.as_str(), value.as_str()));
+        }
+        if self.count == before {
+            e.clone().into_owned()
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+    use crate::gdml::references::collect_references;
+
+    const SRC: &str = r#"<?xml version="1.0"?>
+<gdml>
+  <define>
+    <constant name="r" value="5"/>
+    <constant name="rmax" value="r*2 + sqrt(r)"/>
+    <position name="shift" x="r" y="0" z="0"/>
+    <matrix name="m" coldim="2" values="1 r 2 4"/>
+    <constant name="first" value="m[1,2] + m_1_1"/>
+  </define>
+  <materials>
+    <element name="H" formula="H" Z="1"><atom value="1.008"/></element>
+    <material name="Gas" state="gas"><D value="1e-3*r"/><fraction n="1" ref="H"/></material>
+  </materials>
+  <solids>
+    <tube name="Pipe" rmax="rmax" z="r" deltaphi="360" aunit="deg"/>
+    <box name="Block" x="10" y="10" z="10"/>
+    <union name="Both"><first ref="Pipe"/><second ref="Block"/><positionref ref="shift"/></union>
+  </solids>
+  <structure>
+    <volume name="Inner"><materialref ref="Gas"/><solidref ref="Both"/>
+      <auxiliary auxtype="SensDet" auxvalue="Inner"/></volume>
+    <volume name="World"><materialref ref="Gas"/><solidref ref="Block"/>
+      <physvol><volumeref ref="Inner"/><positionref ref="shift"/></physvol>
+      <loop for="i" from="0" to="r" step="1">
+        <physvol name="copy"><volumeref ref="Inner"/><position name="p" x="i*r"/></physvol>
+      </loop>
+    </volume>
+    <bordersurface name="Edge" surfaceproperty="S"><physvolref ref="Inner_PV"/><physvolref ref="copy"/></bordersurface>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+
+    fn doc() -> GdmlDocument {
+        parse_gdml_from_bytes(SRC.as_bytes(), "t.gdml".to_string()).unwrap()
+    }
+
+    #[test]
+    fn defines_are_renamed_inside_expressions_on_word_boundaries() {
+        let mut d = doc();
+        let n = rename(&mut d, "constant", "r", "radius").unwrap();
+        assert_eq!(d.defines.constants[0].name, "radius");
+        assert_eq!(d.defines.constants[1].value, "radius*2 + sqrt(radius)");
+        assert_eq!(d.defines.positions[0].x.as_deref(), Some("radius"));
+        assert_eq!(d.defines.matrices[0].values, "1 radius 2 4");
+        assert_eq!(
+            d.materials.materials[0].density.as_ref().unwrap().value,
+            "1e-3*radius"
+        );
+        let xml = &d.structure.volumes[1].loops[0].xml;
+        assert!(
+            xml.contains(r#"to="radius""#) && xml.contains(r#"x="i*radius""#),
+            "{xml}"
+        );
+        // `rmax` the constant and `rmax` the tube attribute are untouched.
+        assert!(d.defines.constants.iter().any(|c| c.name == "rmax"));
+        assert_eq!(n, 7);
+        // Nothing still names the old define.
+        assert!(!collect_references(&d).iter().any(|r| r.to.name == "r"));
+    }
+
+    #[test]
+    fn matrices_follow_into_bracket_and_element_references() {
+        let mut d = doc();
+        rename(&mut d, "matrix", "m", "table").unwrap();
+        assert_eq!(d.defines.constants[2].value, "table[1,2] + table_1_1");
+    }
+
+    #[test]
+    fn single_row_matrix_entries_follow_a_rename() {
+        let src = r#"<?xml version="1.0"?>
+<gdml>
+  <define>
+    <matrix name="row" coldim="2" values="1.5 1.33"/>
+    <constant name="n" value="row[2] * row_0"/>
+  </define>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+        let mut d = parse_gdml_from_bytes(src.as_bytes(), "t.gdml".to_string()).unwrap();
+        rename(&mut d, "matrix", "row", "rindex").unwrap();
+        assert_eq!(d.defines.constants[0].value, "rindex[2] * rindex_0");
+    }
+
+    #[test]
+    fn entities_are_renamed_in_every_slot() {
+        let mut d = doc();
+        rename(&mut d, "solid", "Pipe", "Tube").unwrap();
+        rename(&mut d, "position", "shift", "offset").unwrap();
+        let Solid::Boolean(b) = &d.solids.solids[2] else {
+            panic!("not a boolean")
+        };
+        assert_eq!(d.solids.solids[0].name(), "Tube");
+        assert_eq!(b.first_ref, "Tube");
+        assert!(matches!(&b.position, Some(PlacementPos::Ref(p)) if p == "offset"));
+        assert!(
+            matches!(&d.structure.volumes[1].physvols[0].position, Some(PlacementPos::Ref(p)) if p == "offset")
+        );
+
+        rename(&mut d, "volume", "Inner", "Core").unwrap();
+        let world = &d.structure.volumes[1];
+        assert_eq!(world.physvols[0].volume_ref, "Core");
+        assert!(world.loops[0].xml.contains(r#"<volumeref ref="Core"/>"#));
+        assert_eq!(d.structure.volumes[0].auxiliaries[0].auxvalue, "Core");
+        // The unnamed placement's generated name moved with the volume.
+        assert_eq!(d.structure.border_surfaces[0].physvol_refs[0], "Core_PV");
+
+        rename(&mut d, "physvol", "copy", "replica").unwrap();
+        assert!(d.structure.volumes[1].loops[0]
+            .xml
+            .contains(r#"name="replica""#));
+        assert_eq!(d.structure.border_surfaces[0].physvol_refs[1], "replica");
+
+        rename(&mut d, "volume", "World", "Hall").unwrap();
+        assert_eq!(d.setup.world_ref, "Hall");
+        rename(&mut d, "element", "H", "Hydrogen").unwrap();
+        assert!(matches!(
+            &d.materials.materials[0].components[0],
+            MaterialComponent::Fraction { ref_name, .. } if ref_name == "Hydrogen"
+        ));
+    }
+
+    #[test]
+    fn collisions_and_bad_names_are_rejected() {
+        let mut d = doc();
+        assert!(rename(&mut d, "constant", "r", "rmax").is_err());
+        assert!(rename(&mut d, "constant", "r", "m").is_err());
+        assert!(rename(&mut d, "constant", "r", "mm").is_err());
+        assert!(rename(&mut d, "constant", "r", "2r").is_err());
+        assert!(rename(&mut d, "material", "Gas", "H").is_err());
+        assert!(rename(&mut d, "solid", "Pipe", "Block").is_err());
+        assert!(rename(&mut d, "physvol", "copy", "Inner_PV").is_err());
+        assert!(rename(&mut d, "solid", "Nope", "X").is_err());
+        assert!(rename(&mut d, "setup", "Default", "X").is_err());
+        assert_eq!(rename(&mut d, "solid", "Pipe", "Pipe").unwrap(), 0);
+    }
+}
@@ -0,0 +1,950 @@
+//! Structural validation against the GDML 3.1 schema.
+//!
+//! The parser is lenient on purpose: it skips what it does not know, defaults
+//! missing attributes to 0 and lets a later definition shadow an earlier one.
+//! Geant4 is not. `G4GDMLRead` validates against `gdml.xsd` through Xerces
+//! when the schema can be found, and even without it `G4GDMLReadSolids` and
+//! friends raise a `FatalException` on an unknown tag or attribute. This pass
+//! reads the raw XML again and reports what the schema rules out:
+//!
+//! - an element where its parent does not allow it (`<box>` in `<structure>`);
+//! - a missing required attribute or child (`<tube>` without `rmax`, a
+//!   `<volume>` without `<solidref>`), or an attribute the element does not
+//!   have;
+//! - an attribute value of the wrong type: `lunit`/`aunit`/`unit` naming a
+//!   unit of another kind or none at all, an enumeration such as `state`
+//!   outside its values, a non-integer `coldim`, an empty expression;
+//! - a name declared twice in the same namespace, which the XSD's `xs:ID`
+//!   rejects and Geant4's stores resolve to whichever comes first.
+//!
+//! The rules are the schema's, written out as a table rather than loaded from
+//! the XSD: no XSD engine is available in Rust, and the subset GDML uses is
+//! small. Positions are those of the `quick_xml` reader, as 1-based line and
+//! column.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::units::{self, UnitKind};
+
+/// One violation of the schema, located in the source text.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaIssue {
+    pub line: usize,
+    pub column: usize,
+    /// The tag of the offending element.
+    pub element: String,
+    /// The attribute at fault, if it is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribute: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// What an element may contain.
+#[derive(Clone, Copy)]
+enum Children {
+    /// Only these tags (none for an empty slice).
+    Only(&'static [&'static str]),
+    /// Anything; the content is not checked.
+    Any,
+}
+
+/// The schema's definition of one element.
+struct Rule {
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+    children: Children,
+    /// Each group must be matched by at least one child.
+    required_children: &'static [&'static [&'static str]],
+}
+
+const NONE: Children = Children::Only(&[]);
+
+const fn leaf(required: &'static [&'static str], optional: &'static [&'static str]) -> Rule {
+    Rule {
+        required,
+        optional,
+        children: NONE,
+        required_children: &[],
+    }
+}
+
+const REF: Rule = leaf(&["ref"], &[]);
+const VECTOR: Rule = leaf(&[], &["name", "x", "y", "z", "unit", "type"]);
+const VALUE: Rule = leaf(&["value"], &["unit", "type"]);
+
+/// Every tag allowed directly inside `<solids>`, except `<loop>`.
+const SOLIDS: &[&str] = &[
+    "box",
+    "tube",
+    "cutTube",
+    "cone",
+    "sphere",
+    "orb",
+    "torus",
+    "trd",
+    "trap",
+    "para",
+    "ellipsoid",
+    "eltube",
+    "elcone",
+    "paraboloid",
+    "hype",
+    "tet",
+    "arb8",
+    "twistedbox",
+    "twistedtrap",
+    "twistedtrd",
+    "twistedtubs",
+    "polycone",
+    "genericPolycone",
+    "polyhedra",
+    "genericPolyhedra",
+    "xtru",
+    "tessellated",
+    "union",
+    "subtraction",
+    "intersection",
+    "multiUnion",
+    "scaledSolid",
+    "reflectedSolid",
+    "opticalsurface",
+    "loop",
+];
+
+const BOOLEAN: Rule = Rule {
+    required: &["name"],
+    optional: &[],
+    children: Children::Only(&[
+        "first",
+        "second",
+        "position",
+        "positionref",
+        "rotation",
+        "rotationref",
+        "firstposition",
+        "firstpositionref",
+        "firstrotation",
+        "firstrotationref",
+    ]),
+    required_children: &[&["first"], &["second"]],
+};
+
+fn rule(tag: &str) -> Option<Rule> {
+    Some(match tag {
+        "gdml" => Rule {
+            required: &[],
+            optional: &["version"],
+            children: Children::Only(&[
+                "define",
+                "materials",
+                "solids",
+                "structure",
+                "userinfo",
+                "setup",
+            ]),
+            required_children: &[&["solids"], &["structure"], &["setup"]],
+        },
+        "define" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(&[
+                "constant",
+                "variable",
+                "expression",
+                "quantity",
+                "position",
+                "rotation",
+                "scale",
+                "matrix",
+                "loop",
+            ]),
+            required_children: &[],
+        },
+        "materials" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(&["define", "isotope", "element", "material", "loop"]),
+            required_children: &[],
+        },
+        "solids" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(SOLIDS),
+            required_children: &[],
+        },
+        "structure" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(&[
+                "volume",
+                "assembly",
+                "skinsurface",
+                "bordersurface",
+                "loop",
+            ]),
+            required_children: &[],
+        },
+        "userinfo" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(&["auxiliary"]),
+            required_children: &[],
+        },
+        "setup" => Rule {
+            required: &["name", "version"],
+            optional: &[],
+            children: Children::Only(&["world"]),
+            required_children: &[&["world"]],
+        },
+        "world" => REF,
+        "loop" => leaf(&["for", "from", "to", "step"], &[]),
+
+        // ── define ──
+        "constant" | "variable" => leaf(&["name", "value"], &[]),
+        "expression" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Any,
+            required_children: &[],
+        },
+        "quantity" => leaf(&["name", "value"], &["type", "unit"]),
+        "position" | "rotation" | "scale" | "firstposition" | "firstrotation" => VECTOR,
+        "matrix" => leaf(&["name", "coldim", "values"], &[]),
+
+        // ── materials ──
+        "isotope" => Rule {
+            required: &["name", "Z", "N"],
+            optional: &["formula", "state"],
+            children: Children::Only(&["atom"]),
+            required_children: &[&["atom"]],
+        },
+        "element" => Rule {
+            required: &["name"],
+            optional: &["formula", "Z", "N"],
+            children: Children::Only(&["atom", "fraction"]),
+            required_children: &[&["atom", "fraction"]],
+        },
+        "material" => Rule {
+            required: &["name"],
+            optional: &["formula", "Z", "state"],
+            children: Children::Only(&[
+                "D",
+                "Dref",
+                "T",
+                "Tref",
+                "P",
+                "Pref",
+                "MEE",
+                "MEEref",
+                "atom",
+                "fraction",
+                "composite",
+                "property",
+            ]),
+            required_children: &[&["D", "Dref"]],
+        },
+        "atom" | "D" | "T" | "P" | "MEE" => VALUE,
+        "fraction" | "composite" => leaf(&["n", "ref"], &[]),
+        "property" => leaf(&["name", "ref"], &[]),
+
+        // ── solids ──
+        "box" => leaf(&["name", "x", "y", "z"], &[]),
+        "tube" => leaf(&["name", "rmax", "z", "deltaphi"], &["rmin", "startphi"]),
+        "cutTube" => leaf(
+            &[
+                "name", "rmax", "z", "deltaphi", "lowX", "lowY", "lowZ", "highX", "highY", "highZ",
+            ],
+            &["rmin", "startphi"],
+        ),
+        "cone" => leaf(
+            &["name", "rmax1", "rmax2", "z", "deltaphi"],
+            &["rmin1", "rmin2", "startphi"],
+        ),
+        "sphere" => leaf(
+            &["name", "rmax", "deltaphi", "deltatheta"],
+            &["rmin", "startphi", "starttheta"],
+        ),
+        "orb" => leaf(&["name", "r"], &[]),
+        "torus" => leaf(&["name", "rmax", "rtor", "deltaphi"], &["rmin", "startphi"]),
+        "trd" => leaf(&["name", "x1", "x2", "y1", "y2", "z"], &[]),
+        "trap" => leaf(
+            &["name", "z", "y1", "x1", "x2", "y2", "x3", "x4"],
+            &["theta", "phi", "alpha1", "alpha2"],
+        ),
+        "para" => leaf(&["name", "x", "y", "z"], &["alpha", "theta", "phi"]),
+        "ellipsoid" => leaf(&["name", "ax", "by", "cz"], &["zcut1", "zcut2"]),
+        "eltube" => leaf(&["name", "dx", "dy", "dz"], &[]),
+        "elcone" => leaf(&["name", "dx", "dy", "zmax", "zcut"], &[]),
+        "paraboloid" => leaf(&["name", "rlo", "rhi", "dz"], &[]),
+        "hype" => leaf(&["name", "rmax", "z"], &["rmin", "inst", "outst"]),
+        "tet" => leaf(&["name", "vertex1", "vertex2", "vertex3", "vertex4"], &[]),
+        "arb8" => leaf(
+            &[
+                "name", "dz", "v1x", "v1y", "v2x", "v2y", "v3x", "v3y", "v4x", "v4y", "v5x", "v5y",
+                "v6x", "v6y", "v7x", "v7y", "v8x", "v8y",
+            ],
+            &[],
+        ),
+        "twistedbox" => leaf(&["name", "PhiTwist", "x", "y", "z"], &[]),
+        "twistedtrap" => leaf(
+            &["name", "PhiTwist", "z", "y1", "x1", "x2", "y2", "x3", "x4"],
+            &["Theta", "Phi", "Alph"],
+        ),
+        "twistedtrd" => leaf(&["name", "PhiTwist", "x1", "x2", "y1", "y2", "z"], &[]),
+        "twistedtubs" => leaf(
+            &["name", "twistedangle", "endinnerrad", "endouterrad", "zlen"],
+            &[
+                "phi",
+                "midinnerrad",
+                "midouterrad",
+                "negativeEndz",
+                "positiveEndz",
+                "nseg",
+                "totphi",
+            ],
+        ),
+        "polycone" | "polyhedra" => Rule {
+            required: if tag == "polycone" {
+                &["name", "deltaphi"]
+            } else {
+                &["name", "deltaphi", "numsides"]
+            },
+            optional: &["startphi"],
+            children: Children::Only(&["zplane"]),
+            required_children: &[&["zplane"]],
+        },
+        "genericPolycone" | "genericPolyhedra" => Rule {
+            required: if tag == "genericPolycone" {
+                &["name", "deltaphi"]
+            } else {
+                &["name", "deltaphi", "numsides"]
+            },
+            optional: &["startphi"],
+            children: Children::Only(&["rzpoint"]),
+            required_children: &[&["rzpoint"]],
+        },
+        "zplane" => leaf(&["rmax", "z"], &["rmin"]),
+        "rzpoint" => leaf(&["r", "z"], &[]),
+        "xtru" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&["twoDimVertex", "section"]),
+            required_children: &[&["twoDimVertex"], &["section"]],
+        },
+        "twoDimVertex" => leaf(&["x", "y"], &[]),
+        "section" => leaf(
+            &["zOrder", "zPosition"],
+            &["xOffset", "yOffset", "scalingFactor"],
+        ),
+        "tessellated" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&["triangular", "quadrangular"]),
+            required_children: &[&["triangular", "quadrangular"]],
+        },
+        "triangular" => leaf(&["vertex1", "vertex2", "vertex3"], &["type"]),
+        "quadrangular" => leaf(&["vertex1", "vertex2", "vertex3", "vertex4"], &["type"]),
+        "union" | "subtraction" | "intersection" => BOOLEAN,
+        "multiUnion" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&["multiUnionNode"]),
+            required_children: &[],
+        },
+        "multiUnionNode" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&[
+                "solid",
+                "position",
+                "positionref",
+                "rotation",
+                "rotationref",
+            ]),
+            required_children: &[&["solid"]],
+        },
+        "scaledSolid" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&["solidref", "scale", "scaleref"]),
+            required_children: &[&["solidref"], &["scale", "scaleref"]],
+        },
+        "reflectedSolid" => leaf(
+            &["name", "solid"],
+            &["sx", "sy", "sz", "rx", "ry", "rz", "dx", "dy", "dz"],
+        ),
+        "opticalsurface" => Rule {
+            required: &["name"],
+            optional: &["model", "finish", "type", "value"],
+            children: Children::Only(&["property"]),
+            required_children: &[],
+        },
+        "first" | "second" | "solid" | "positionref" | "rotationref" | "scaleref"
+        | "firstpositionref" | "firstrotationref" | "solidref" | "materialref" | "volumeref"
+        | "physvolref" | "Dref" | "Tref" | "Pref" | "MEEref" => REF,
+
+        // ── structure ──
+        "volume" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&[
+                "materialref",
+                "solidref",
+                "physvol",
+                "replicavol",
+                "divisionvol",
+                "paramvol",
+                "auxiliary",
+                "loop",
+            ]),
+            required_children: &[&["materialref"], &["solidref"]],
+        },
+        "assembly" => Rule {
+            required: &["name"],
+            optional: &[],
+            children: Children::Only(&["physvol", "auxiliary", "loop"]),
+            required_children: &[],
+        },
+        "physvol" => Rule {
+            required: &[],
+            optional: &["name", "copynumber"],
+            children: Children::Only(&[
+                "volumeref",
+                "file",
+                "position",
+                "positionref",
+                "rotation",
+                "rotationref",
+                "scale",
+                "scaleref",
+            ]),
+            required_children: &[&["volumeref", "file"]],
+        },
+        "file" => leaf(&["name"], &["volname"]),
+        "replicavol" => Rule {
+            required: &["number"],
+            optional: &[],
+            children: Children::Only(&["volumeref", "replicate_along_axis"]),
+            required_children: &[&["volumeref"], &["replicate_along_axis"]],
+        },
+        "replicate_along_axis" => Rule {
+            required: &[],
+            optional: &[],
+            children: Children::Only(&["direction", "width", "offset"]),
+            required_children: &[&["direction"], &["width"], &["offset"]],
+        },
+        "direction" => leaf(&[], &["x", "y", "z", "rho", "phi"]),
+        "width" | "offset" => leaf(&["value"], &["unit"]),
+        "divisionvol" => Rule {
+            required: &["axis", "number", "width", "offset"],
+            optional: &["unit"],
+            children: Children::Only(&["volumeref"]),
+            required_children: &[&["volumeref"]],
+        },
+        "paramvol" => Rule {
+            required: &["ncopies"],
+            optional: &[],
+            children: Children::Any,
+            required_children: &[],
+        },
+        "auxiliary" => Rule {
+            required: &["auxtype", "auxvalue"],
+            optional: &["auxunit"],
+            children: Children::Only(&["auxiliary"]),
+            required_children: &[],
+        },
+        "skinsurface" => Rule {
+            required: &["name", "surfaceproperty"],
+            optional: &[],
+            children: Children::Only(&["volumeref"]),
+            required_children: &[&["volumeref"]],
+        },
+        "bordersurface" => Rule {
+            required: &["name", "surfaceproperty"],
+            optional: &[],
+            children: Children::Only(&["physvolref"]),
+            required_children: &[&["physvolref"]],
+        },
+        _ => return None,
+    })
+}
+
+/// Attributes every solid takes besides its own parameters: `lunit` and
+/// `aunit` come from the XSD's `SolidType`, which they all extend.
+fn solid_common(tag: &str, attr: &str) -> bool {
+    SOLIDS.contains(&tag) && tag != "opticalsurface" && matches!(attr, "lunit" | "aunit")
+}
+
+/// The namespace a declaration's `name` lives in, if it is one. Geant4 keeps
+/// each of these in its own store; the evaluator's defines share one.
+fn namespace(parent: &str, tag: &str) -> Option<&'static str> {
+    Some(match (parent, tag) {
+        ("define", "constant" | "variable" | "expression" | "quantity" | "matrix") => "define",
+        ("define", "position") => "position",
+        ("define", "rotation") => "rotation",
+        ("define", "scale") => "scale",
+        ("materials", "isotope") => "isotope",
+        ("materials", "element") => "element",
+        ("materials", "material") => "material",
+        ("solids", "opticalsurface") => "optical surface",
+        ("solids", t) if SOLIDS.contains(&t) && t != "loop" => "solid",
+        ("structure", "volume" | "assembly") => "volume",
+        ("structure", "skinsurface") => "skin surface",
+        ("structure", "bordersurface") => "border surface",
+        ("gdml", "setup") => "setup",
+        _ => return None,
+    })
+}
+
+/// The unit kinds a `unit` attribute on `tag` may name; empty for any.
+fn unit_kinds(tag: &str) -> &'static [UnitKind] {
+    use UnitKind::*;
+    match tag {
+        "position" | "firstposition" => &[Length],
+        "rotation" | "firstrotation" => &[Angle],
+        "D" => &[Density],
+        "T" => &[Temperature],
+        "P" => &[Pressure],
+        "MEE" => &[Energy],
+        "atom" => &[MolarMass],
+        // Replicas and divisions along phi are measured in angles.
+        "width" | "offset" | "divisionvol" => &[Length, Angle],
+        _ => &[],
+    }
+}
+
+/// What is wrong with `value` for `attr` on `tag`, if anything.
+fn check_value(tag: &str, attr: &str, value: &str) -> Option<String> {
+    let one_of = |allowed: &[&str]| {
+        (!allowed.contains(&value)).then(|| {
+            format!(
+                "{}=\"{}\" must be one of {}",
+                attr,
+                value,
+                allowed.join(", ")
+            )
+        })
+    };
+    match attr {
+        "lunit" => units::length_factor(value)
+            .is_none()
+            .then(|| format!("lunit=\"{}\" is not a length unit", value)),
+        "aunit" => units::angle_factor(value)
+            .is_none()
+            .then(|| format!("aunit=\"{}\" is not an angle unit", value)),
+        "unit" | "auxunit" => {
+            let kinds = unit_kinds(tag);
+            match units::unit_kind(value) {
+                None => Some(format!("{}=\"{}\" is not a unit", attr, value)),
+                Some(kind) if !kinds.is_empty() && !kinds.contains(&kind) => Some(format!(
+                    "{}=\"{}\" is a {} unit; <{}> takes {}",
+                    attr,
+                    value,
+                    kind.category().to_lowercase(),
+                    tag,
+                    kinds
+                        .iter()
+                        .map(|k| k.category().to_lowercase())
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                )),
+                Some(_) => None,
+            }
+        }
+        "state" => one_of(&["solid", "liquid", "gas", "undefined"]),
+        "type" if matches!(tag, "triangular" | "quadrangular") => one_of(&["ABSOLUTE", "RELATIVE"]),
+        "axis" => one_of(&["kXAxis", "kYAxis", "kZAxis", "kRho", "kPhi"]),
+        "coldim" => match value.trim().parse::<u32>() {
+            Ok(n) if n > 0 => None,
+            _ => Some(format!("coldim=\"{}\" must be a positive integer", value)),
+        },
+        "formula" | "version" | "volname" => None,
+        _ if value.trim().is_empty() => Some(format!("{} is empty", attr)),
+        _ => None,
+    }
+}
+
+/// An element being read, with what it has contained so far.
+struct Frame {
+    tag: String,
+    /// The rule its children are checked against: its own, or for a `<loop>`
+    /// that of the element the loop sits in, whose children it produces.
+    context: String,
+    offset: usize,
+    children: Vec<String>,
+    /// Inside content the schema leaves open, or an element already reported
+    /// as misplaced; nothing below is checked.
+    unchecked: bool,
+    in_loop: bool,
+}
+
+struct Validator<'a> {
+    xml: &'a str,
+    line_starts: Vec<usize>,
+    issues: Vec<SchemaIssue>,
+    /// Namespace → name → offset of the first declaration.
+    declared: HashMap<&'static str, HashMap<String, usize>>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(xml: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(xml.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            xml,
+            line_starts,
+            issues: Vec::new(),
+            declared: HashMap::new(),
+        }
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&s| s <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.xml[start..offset.min(self.xml.len())].chars().count() + 1;
+        (line, column)
+    }
+
+    fn report(&mut self, offset: usize, element: &str, attribute: Option<&str>, message: String) {
+        let (line, column) = self.position(offset);
+        self.issues.push(SchemaIssue {
+            line,
+            column,
+            element: element.to_string(),
+            attribute: attribute.map(str::to_string),
+            message,
+        });
+    }
+
+    /// Where `attr` sits inside the start tag beginning at `offset`, so the
+    /// column points at the attribute rather than the element.
+    fn attribute_offset(&self, offset: usize, attr: &str) -> usize {
+        let tag = &self.xml[offset..];
+        let end = tag.find('>').unwrap_or(tag.len());
+        let tag = &tag[..end];
+        let mut from = 0;
+        while let Some(i) = tag[from..].find(attr) {
+            let at = from + i;
+            let before = tag[..at].chars().next_back();
+            let after = tag[at + attr.len()..].trim_start().starts_with('=');
+            if before.is_some_and(char::is_whitespace) && after {
+                return offset + at;
+            }
+            from = at + attr.len();
+        }
+        offset
+    }
+
+    fn open(&mut self, stack: &[Frame], e: &BytesStart, offset: usize) -> Frame {
+        let tag = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+        let parent = stack.last();
+        let in_loop = parent.is_some_and(|p| p.in_loop || p.tag == "loop");
+        let mut frame = Frame {
+            context: tag.clone(),
+            tag: tag.clone(),
+            offset,
+            children: Vec::new(),
+            unchecked: false,
+            in_loop,
+        };
+
+        match parent {
+            None if tag != "gdml" => {
+                self.report(
+                    offset,
+                    &tag,
+                    None,
+                    format!("the root element is <{}>, not <gdml>", tag),
+                );
+                frame.unchecked = true;
+                return frame;
+            }
+            Some(p) if p.unchecked => {
+                frame.unchecked = true;
+                return frame;
+            }
+            Some(p) => {
+                let allowed = rule(&p.context).map(|r| r.children);
+                if let Some(Children::Only(allowed)) = allowed {
+                    if !allowed.contains(&tag.as_str()) {
+                        let msg = if allowed.is_empty() {
+                            format!("<{}> cannot contain <{}>", p.context, tag)
+                        } else {
+                            format!(
+                                "<{}> is not allowed in <{}>; expected one of {}",
+                                tag,
+                                p.context,
+                                allowed
+                                    .iter()
+                                    .map(|t| format!("<{}>", t))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                        };
+                        self.report(offset, &tag, None, msg);
+                        frame.unchecked = true;
+                        return frame;
+                    }
+                }
+            }
+            None => {}
+        }
+
+        if tag == "loop" {
+            if let Some(p) = parent {
+                frame.context = p.context.clone();
+            }
+        }
+        let Some(rule) = rule(&tag) else {
+            frame.unchecked = true;
+            return frame;
+        };
+        if matches!(rule.children, Children::Any) {
+            frame.unchecked = true;
+        }
+
+        let mut attrs: Vec<(String, String)> = Vec::new();
+        for attr in e.attributes() {
+            let attr = match attr {
+                Ok(a) => a,
+                Err(err) => {
+                    self.report(offset, &tag, None, format!("malformed attribute: {}", err));
+                    continue;
+                }
+            };
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            let raw = String::from_utf8_lossy(&attr.value);
+            let value = quick_xml::escape::unescape(&raw)
+                .map(|c| c.into_owned())
+                .unwrap_or_else(|_| raw.into_owned());
+            attrs.push((key, value));
+        }
+
+        for required in rule.required {
+            if !attrs.iter().any(|(k, _)| k == required) {
+                self.report(
+                    offset,
+                    &tag,
+                    Some(required),
+                    format!("<{}> is missing required attribute \"{}\"", tag, required),
+                );
+            }
+        }
+        for (key, value) in &attrs {
+            // xmlns and xsi:noNamespaceSchemaLocation on the root, and any
+            // other namespaced attribute, are the schema machinery's own.
+            if key.starts_with("xmlns") || key.contains(':') {
+                continue;
+            }
+            let at = self.attribute_offset(offset, key);
+            let known = rule.required.contains(&key.as_str())
+                || rule.optional.contains(&key.as_str())
+                || solid_common(&tag, key);
+            if !known {
+                self.report(
+                    at,
+                    &tag,
+                    Some(key),
+                    format!("<{}> has no attribute \"{}\"", tag, key),
+                );
+                continue;
+            }
+            if let Some(msg) = check_value(&tag, key, value) {
+                self.report(at, &tag, Some(key), msg);
+            }
+        }
+
+        // A loop body's names are patterns (`Slice[i]`) until it is expanded.
+        if !in_loop {
+            let ns = parent.and_then(|p| namespace(&p.context, &tag));
+            let name = attrs.iter().find(|(k, _)| k == "name").map(|(_, v)| v);
+            if let (Some(ns), Some(name)) = (ns, name) {
+                let first = *self
+                    .declared
+                    .entry(ns)
+                    .or_default()
+                    .entry(name.clone())
+                    .or_insert(offset);
+                if first != offset {
+                    let (first, _) = self.position(first);
+                    let at = self.attribute_offset(offset, "name");
+                    self.report(
+                        at,
+                        &tag,
+                        Some("name"),
+                        format!("{} \"{}\" is already declared at line {}", ns, name, first),
+                    );
+                }
+            }
+        }
+        frame
+    }
+
+    fn close(&mut self, frame: Frame) {
+        if frame.unchecked || frame.tag == "loop" {
+            return;
+        }
+        let Some(rule) = rule(&frame.tag) else {
+            return;
+        };
+        for group in rule.required_children {
+            // Children a loop generates are unknown until it is expanded.
+            let satisfied = frame
+                .children
+                .iter()
+                .any(|c| c == "loop" || group.contains(&c.as_str()));
+            if !satisfied {
+                let wanted: Vec<String> = group.iter().map(|t| format!("<{}>", t)).collect();
+                self.report(
+                    frame.offset,
+                    &frame.tag,
+                    None,
+                    format!("<{}> must contain {}", frame.tag, wanted.join(" or ")),
+                );
+            }
+        }
+        if frame.tag == "bordersurface" {
+            let refs = frame.children.iter().filter(|c| *c == "physvolref").count();
+            if refs != 2 {
+                self.report(
+                    frame.offset,
+                    &frame.tag,
+                    None,
+                    format!(
+                        "<bordersurface> must contain exactly two <physvolref>, not {}",
+                        refs
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Check `xml` against the GDML schema rules. Issues come in document order;
+/// XML that is not well-formed yields one issue where reading stopped.
+pub fn validate(xml: &str) -> Vec<SchemaIssue> {
+    let mut v = Validator::new(xml);
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Frame> = Vec::new();
+    loop {
+        let offset = reader.buffer_position() as usize;
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let frame = v.open(&stack, &e, offset);
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(frame.tag.clone());
+                }
+                stack.push(frame);
+            }
+            Ok(Event::Empty(e)) => {
+                let frame = v.open(&stack, &e, offset);
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(frame.tag.clone());
+                }
+                v.close(frame);
+            }
+            Ok(Event::End(_)) => {
+                if let Some(frame) = stack.pop() {
+                    v.close(frame);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(err) => {
+                let at = reader.error_position() as usize;
+                v.report(at, "", None, format!("not well-formed XML: {}", err));
+                return v.issues;
+            }
+        }
+    }
+    v.issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_misplaced_and_incomplete_elements_with_positions() {
+        let xml = r#"<gdml>
+  <solids>
+    <box name="B" x="1" y="1"/>
+    <tube name="T" rmax="2" z="4" deltaphi="360" aunit="mm" colour="red"/>
+    <volume name="V"/>
+  </solids>
+  <structure>
+    <volume name="V"><materialref ref="M"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="V"/></setup>
+</gdml>"#;
+        let issues = validate(xml);
+        let found: Vec<(usize, usize, &str)> = issues
+            .iter()
+            .map(|i| (i.line, i.column, i.message.as_str()))
+            .collect();
+        assert_eq!(found.len(), 5);
+        assert_eq!(
+            found[0],
+            (3, 5, "<box> is missing required attribute \"z\"")
+        );
+        assert_eq!(found[1], (4, 50, "aunit=\"mm\" is not an angle unit"));
+        assert_eq!(found[2], (4, 61, "<tube> has no attribute \"colour\""));
+        assert_eq!((found[3].0, found[3].1), (5, 5));
+        assert!(found[3]
+            .2
+            .starts_with("<volume> is not allowed in <solids>"));
+        assert_eq!(found[4], (8, 5, "<volume> must contain <solidref>"));
+    }
+
+    #[test]
+    fn duplicate_names_and_loop_bodies() {
+        let xml = r#"<gdml>
+  <define>
+    <constant name="n" value="3"/>
+    <variable name="i" value="0"/>
+    <position name="n"/>
+    <quantity name="n" value="1" unit="mm"/>
+  </define>
+  <materials>
+    <material name="M" state="plasma"><atom value="1"/></material>
+  </materials>
+  <solids>
+    <loop for="i" from="0" to="n" step="1"><box name="B[i]" x="1" y="1" z="1"/></loop>
+  </solids>
+  <structure/>
+  <setup name="Default" version="1.0"><world ref="W"/></setup>
+</gdml>"#;
+        let messages: Vec<String> = validate(xml).into_iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 6, column 15: define \"n\" is already declared at line 3",
+                "line 9, column 24: state=\"plasma\" must be one of solid, liquid, gas, undefined",
+                "line 9, column 5: <material> must contain <D> or <Dref>",
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_xml_stops_with_one_issue() {
+        let issues = validate("<gdml>\n  <solids>\n</gdml>");
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.starts_with("not well-formed XML"));
+        assert_eq!(issues[0].line, 3);
+    }
+}
@@ -1,2 +1,94 @@
 // Structure parsing is handled inline by parser.rs
-// This module is reserved for future structure-specific utilities
+// This module holds structure-specific utilities that do not belong to parsing.
+
+/// Resolve a `<file name="...">` reference against the file that contains it.
+///
+/// Geant4's `G4GDMLReadStructure::FileRead` hands the name straight to the
+/// XML parser, so it is resolved against the process working directory. That
+/// breaks as soon as a module includes a sub-module from its own directory, so
+/// the reference is taken relative to the including file instead -- the
+/// convention modular detector repositories are written against.
+///
+/// The result is a lexically normalised, `/`-separated path: `.` segments are
+/// dropped, `..` consumes the previous segment where there is one, and Windows
+/// separators are accepted. Leading `..` segments are kept so a sibling
+/// directory of the main file stays distinguishable.
+pub fn normalize_include_path(including_file: &str, reference: &str) -> String {
+    let reference = reference.replace('\\', "/");
+    let including = including_file.replace('\\', "/");
+    let absolute = reference.starts_with('/');
+
+    let mut segments: Vec<&str> = Vec::new();
+    if !absolute {
+        if let Some((dir, _)) = including.rsplit_once('/') {
+            segments.extend(dir.split('/'));
+        }
+    }
+    segments.extend(reference.split('/'));
+
+    let mut normalized: Vec<&str> = Vec::new();
+    for segment in segments {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if normalized.last().is_some_and(|s| *s != "..") {
+                    normalized.pop();
+                } else if !absolute {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    let joined = normalized.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
+
+/// The last path segment, used to match a reference against a flat upload
+/// where the browser only supplied bare file names.
+pub fn include_basename(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_resolve_relative_to_the_including_file() {
+        assert_eq!(
+            normalize_include_path("main.gdml", "child.gdml"),
+            "child.gdml"
+        );
+        assert_eq!(
+            normalize_include_path("modules/tracker.gdml", "layers/layer.gdml"),
+            "modules/layers/layer.gdml"
+        );
+        assert_eq!(
+            normalize_include_path("modules/tracker.gdml", "../common/support.gdml"),
+            "common/support.gdml"
+        );
+        assert_eq!(
+            normalize_include_path("main.gdml", "../shared/./part.gdml"),
+            "../shared/part.gdml"
+        );
+    }
+
+    #[test]
+    fn separators_and_absolute_paths_are_normalised() {
+        assert_eq!(
+            normalize_include_path("a\\b.gdml", "c\\d.gdml"),
+            "a/c/d.gdml"
+        );
+        assert_eq!(
+            normalize_include_path("modules/x.gdml", "/opt/geo/../geo/y.gdml"),
+            "/opt/geo/y.gdml"
+        );
+        assert_eq!(include_basename("modules/layers/layer.gdml"), "layer.gdml");
+    }
+}
@@ -0,0 +1,325 @@
+//! Optical surfaces: the Geant4 enumerators behind `<opticalsurface>` and the
+//! checks that `<skinsurface>` / `<bordersurface>` point at something real.
+//!
+//! `G4GDMLReadSolids::OpticalSurfaceRead` maps each of `model`, `finish` and
+//! `type` through a chain of string comparisons that accepts the enumerator
+//! name or its number, and ends in a bare `else` -- so a misspelt finish is not
+//! an error there, it silently becomes the last enumerator (`Detector_LUT`).
+//! The tables below mirror those chains, in enumerator order, so the index of
+//! a name is its number.
+
+use std::collections::HashSet;
+
+use super::model::*;
+use crate::eval::engine::EvalEngine;
+
+/// `G4OpticalSurfaceModel`.
+pub const MODELS: [&str; 5] = ["glisur", "unified", "LUT", "DAVIS", "dichroic"];
+
+/// `G4OpticalSurfaceFinish`. 0-5 are the glisur/unified finishes, 6-29 the
+/// LUT model's measured surfaces and 30-38 the DAVIS model's.
+pub const FINISHES: [&str; 39] = [
+    "polished",
+    "polishedfrontpainted",
+    "polishedbackpainted",
+    "ground",
+    "groundfrontpainted",
+    "groundbackpainted",
+    "polishedlumirrorair",
+    "polishedlumirrorglue",
+    "polishedair",
+    "polishedteflonair",
+    "polishedtioair",
+    "polishedtyvekair",
+    "polishedvm2000air",
+    "polishedvm2000glue",
+    "etchedlumirrorair",
+    "etchedlumirrorglue",
+    "etchedair",
+    "etchedteflonair",
+    "etchedtioair",
+    "etchedtyvekair",
+    "etchedvm2000air",
+    "etchedvm2000glue",
+    "groundlumirrorair",
+    "groundlumirrorglue",
+    "groundair",
+    "groundteflonair",
+    "groundtioair",
+    "groundtyvekair",
+    "groundvm2000air",
+    "groundvm2000glue",
+    "Rough_LUT",
+    "RoughTeflon_LUT",
+    "RoughESR_LUT",
+    "RoughESRGrease_LUT",
+    "Polished_LUT",
+    "PolishedTeflon_LUT",
+    "PolishedESR_LUT",
+    "PolishedESRGrease_LUT",
+    "Detector_LUT",
+];
+
+/// `G4SurfaceType`, as far as the GDML reader knows it.
+pub const TYPES: [&str; 7] = [
+    "dielectric_metal",
+    "dielectric_dielectric",
+    "dielectric_LUT",
+    "dielectric_LUTDAVIS",
+    "dielectric_dichroic",
+    "firsov",
+    "x_ray",
+];
+
+/// The enumerator `value` names, by name or by number, as Geant4 compares
+/// them: exactly, so `"Unified"` and `"1.0"` are not recognised.
+fn lookup(table: &[&'static str], value: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .enumerate()
+        .find(|(i, name)| **name == value || i.to_string() == value)
+        .map(|(_, name)| *name)
+}
+
+pub fn model_name(value: &str) -> Option<&'static str> {
+    lookup(&MODELS, value)
+}
+
+pub fn finish_name(value: &str) -> Option<&'static str> {
+    lookup(&FINISHES, value)
+}
+
+pub fn type_name(value: &str) -> Option<&'static str> {
+    lookup(&TYPES, value)
+}
+
+/// The model a finish was measured or defined for.
+fn finish_model(finish: &str) -> &'static str {
+    match FINISHES.iter().position(|f| *f == finish) {
+        Some(0..=5) => "glisur or unified",
+        Some(6..=29) => "LUT",
+        _ => "DAVIS",
+    }
+}
+
+/// Check a `<property ref="..">` of `owner` (`Material "Water"`, `Optical
+/// surface "Wrap"`) against the evaluated matrices.
+///
+/// The GDML readers' `PropertyRead` stops with a fatal error when the matrix
+/// does not exist. Otherwise a one-column matrix becomes a constant property
+/// from its first entry and anything else a property vector built from columns
+/// 0 and 1 (energy, value); extra columns are ignored, so a table with coldim
+/// 3 loads but not as its author meant. An empty matrix has already been
+/// reported by the engine, so it gets no second warning here.
+pub fn property_warning(
+    owner: &str,
+    prop: &MaterialProperty,
+    engine: &EvalEngine,
+) -> Option<String> {
+    let ref_name = prop.ref_name.as_deref()?;
+    let Some(matrix) = engine.matrix_values.get(ref_name) else {
+        return Some(format!(
+            "{} property {} refers to matrix \"{}\", which does not exist. \
+             Geant4 will not load this file.",
+            owner, prop.name, ref_name
+        ));
+    };
+    if matrix.values.is_empty() {
+        return None;
+    }
+    match matrix.coldim {
+        2 => None,
+        1 if matrix.values.len() == 1 => None,
+        1 => Some(format!(
+            "{} property {} uses the one-column matrix \"{}\", so Geant4 reads it as a \
+             constant and only its first value ({}) is used.",
+            owner, prop.name, ref_name, matrix.values[0]
+        )),
+        n => Some(format!(
+            "{} property {} uses matrix \"{}\" with coldim={}; a property table needs \
+             coldim=2 (energy, value) and Geant4 ignores the other columns.",
+            owner, prop.name, ref_name, n
+        )),
+    }
+}
+
+/// Every name a `<physvolref>` can resolve to.
+///
+/// A placement without a `name` is called `<logical volume>_PV` by Geant4
+/// (`G4GDMLReadStructure::GeneratePhysvolName`). Placements inside a preserved
+/// `<assembly>` or `<loop>` are not modelled, so any `name=".."` in their XML
+/// counts too rather than being reported as missing.
+pub fn physvol_names(doc: &GdmlDocument) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for vol in &doc.structure.volumes {
+        for pv in &vol.physvols {
+            match &pv.name {
+                Some(n) if !n.is_empty() => names.insert(n.clone()),
+                _ => names.insert(format!("{}_PV", pv.volume_ref)),
+            };
+        }
+    }
+    let raws = doc
+        .raw_unknown
+        .iter()
+        .chain(doc.structure.volumes.iter().flat_map(|v| v.loops.iter()));
+    for raw in raws {
+        for chunk in raw.xml.split("name=\"").skip(1) {
+            if let Some(end) = chunk.find('"') {
+                names.insert(chunk[..end].to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Everything about the document's surfaces that Geant4 would reject or
+/// silently read differently from how it is written.
+pub fn check_surfaces(doc: &GdmlDocument, engine: &EvalEngine) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    for s in &doc.solids.optical_surfaces {
+        let owner = format!("Optical surface \"{}\"", s.name);
+        let model = model_name(&s.model);
+        let finish = finish_name(&s.finish);
+        if model.is_none() {
+            issues.push(format!(
+                "{} has model=\"{}\", which Geant4 does not recognise; it reads it as \
+                 dichroic.",
+                owner, s.model
+            ));
+        }
+        if finish.is_none() {
+            issues.push(format!(
+                "{} has finish=\"{}\", which Geant4 does not recognise; it reads it as \
+                 Detector_LUT.",
+                owner, s.finish
+            ));
+        }
+        if type_name(&s.r#type).is_none() {
+            issues.push(format!(
+                "{} has type=\"{}\", which Geant4 does not recognise; it reads it as x_ray.",
+                owner, s.r#type
+            ));
+        }
+        if let (Some(model), Some(finish)) = (model, finish) {
+            let wants = finish_model(finish);
+            let fits = match wants {
+                "glisur or unified" => matches!(model, "glisur" | "unified" | "dichroic"),
+                other => other == model,
+            };
+            if !fits {
+                issues.push(format!(
+                    "{} uses finish {}, which belongs to the {} model, with model {}.",
+                    owner, finish, wants, model
+                ));
+            }
+        }
+        if let Some(value) = &s.value {
+            if engine.eval_expr(value).is_err() {
+                issues.push(format!(
+                    "{} has value=\"{}\", which cannot be evaluated.",
+                    owner, value
+                ));
+            }
+        }
+        for prop in &s.properties {
+            issues.extend(property_warning(&owner, prop, engine));
+        }
+    }
+
+    let optical: HashSet<&str> = doc
+        .solids
+        .optical_surfaces
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect();
+    let missing_property = |kind: &str, name: &str, property: &str| {
+        format!(
+            "{} \"{}\" uses optical surface \"{}\", which does not exist. Geant4 will not \
+             load this file.",
+            kind, name, property
+        )
+    };
+
+    for s in &doc.structure.skin_surfaces {
+        if !optical.contains(s.surface_property.as_str()) {
+            issues.push(missing_property(
+                "Skin surface",
+                &s.name,
+                &s.surface_property,
+            ));
+        }
+        if !doc.structure.volumes.iter().any(|v| v.name == s.volume_ref) {
+            issues.push(format!(
+                "Skin surface \"{}\" covers volume \"{}\", which does not exist. Geant4 \
+                 will not load this file.",
+                s.name, s.volume_ref
+            ));
+        }
+    }
+
+    let placements = physvol_names(doc);
+    for s in &doc.structure.border_surfaces {
+        if !optical.contains(s.surface_property.as_str()) {
+            issues.push(missing_property(
+                "Border surface",
+                &s.name,
+                &s.surface_property,
+            ));
+        }
+        if s.physvol_refs.len() != 2 {
+            issues.push(format!(
+                "Border surface \"{}\" has {} physvolref elements; it needs exactly two, \
+                 the placement a photon leaves and the one it enters.",
+                s.name,
+                s.physvol_refs.len()
+            ));
+        }
+        for r in &s.physvol_refs {
+            if !placements.contains(r) {
+                issues.push(format!(
+                    "Border surface \"{}\" refers to placement \"{}\", which does not \
+                     exist. Geant4 will not load this file.",
+                    s.name, r
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enumerators_resolve_by_name_or_number_only() {
+        assert_eq!(model_name("unified"), Some("unified"));
+        assert_eq!(model_name("1"), Some("unified"));
+        assert_eq!(model_name("Unified"), None);
+        assert_eq!(finish_name("38"), Some("Detector_LUT"));
+        assert_eq!(finish_name("groundbackpainted"), Some("groundbackpainted"));
+        assert_eq!(type_name("dielectric_metal"), Some("dielectric_metal"));
+        assert_eq!(type_name("1.0"), None);
+    }
+
+    #[test]
+    fn an_empty_one_column_matrix_is_left_to_the_engine_warning() {
+        let mut engine = EvalEngine::new();
+        engine.matrix_values.insert(
+            "RINDEX".to_string(),
+            crate::eval::engine::MatrixValues {
+                coldim: 1,
+                values: Vec::new(),
+            },
+        );
+        let prop = MaterialProperty {
+            name: "RINDEX".to_string(),
+            ref_name: Some("RINDEX".to_string()),
+            values: None,
+        };
+        assert_eq!(property_warning("Material \"Water\"", &prop, &engine), None);
+    }
+}
@@ -0,0 +1,1394 @@
+//! A ROOT macro for a document: the same geometry built with `TGeoManager`,
+//! for colleagues who analyse in ROOT and would rather not go through
+//! `TGeoManager::Import` on a GDML file.
+//!
+//! The macro follows ROOT's own conventions rather than Geant4's:
+//!
+//! - lengths are in cm, angles in degrees and densities in g/cm3;
+//! - every material gets a `TGeoMedium`, numbered in order of first use;
+//! - a NIST name the document references but does not define is built from
+//!   the bundled NIST table, since ROOT has no `FindOrBuildMaterial`;
+//! - a placement is a `TGeoCombiTrans` whose rotation is the daughter-to-mother
+//!   matrix, the inverse of the GDML frame rotation, as `TGDMLParse` builds it;
+//! - replicas become a loop of `AddNode` calls at the positions Geant4 gives
+//!   each copy.
+//!
+//! Solids ROOT has no shape for (generic polycones and polyhedra, twisted and
+//! reflected solids) are reported and left out, together with the volumes
+//! that use them.
+
+use anyhow::{bail, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use super::cpp::{is_element, Emitter, FunctionBody};
+use super::geant4::{
+    density, int, list, molar_mass, num, parse_colour, placement_position, placement_rotation,
+    quote, real, unsupported_parts, Values,
+};
+use super::materials::find_nist_material;
+use super::model::*;
+use super::units;
+use crate::eval::engine::EvalEngine;
+
+/// A ROOT macro and what could not be carried over into it.
+#[derive(Debug, Clone)]
+pub struct RootMacro {
+    /// `<name>.C`, matching the function inside so `root <name>.C` runs it.
+    pub file_name: String,
+    pub source: String,
+    pub warnings: Vec<String>,
+}
+
+/// Generate a macro that builds `doc` with `TGeoManager` and closes the
+/// geometry.
+///
+/// Fails only when the world volume itself cannot be built.
+pub fn generate_root_macro(doc: &GdmlDocument, engine: &EvalEngine) -> Result<RootMacro> {
+    let function = macro_name(&doc.filename);
+    let mut g = Generator::new(doc, engine);
+    g.section("Isotopes and elements");
+    for iso in &doc.materials.isotopes {
+        g.isotope(iso);
+    }
+    for el in &doc.materials.elements {
+        g.element(&el.name);
+    }
+    g.section("Materials");
+    for m in &doc.materials.materials {
+        g.material(&m.name);
+    }
+    g.section("Shapes");
+    for s in &doc.solids.solids {
+        g.solid(s.name());
+    }
+    g.section("Volumes");
+    for v in &doc.structure.volumes {
+        g.volume(v);
+    }
+    g.section("Placements");
+    for v in &doc.structure.volumes {
+        g.placements(v);
+    }
+
+    let world = &doc.setup.world_ref;
+    let Some(world_vol) = g.volumes.get(world).cloned() else {
+        bail!(
+            "The world volume \"{}\" could not be generated; see the warnings for why",
+            world
+        );
+    };
+    g.line("");
+    g.line(&format!("gGeoManager->SetTopVolume({});", world_vol));
+    g.line("gGeoManager->CloseGeometry();");
+    if !g.skipped_mee.is_empty() {
+        g.warnings.push(format!(
+            "ROOT materials have no mean excitation energy; it was dropped from {}.",
+            g.skipped_mee.join(", ")
+        ));
+    }
+    g.warnings.extend(unsupported_parts(doc));
+
+    Ok(RootMacro {
+        file_name: format!("{}.C", function),
+        source: g.finish(&function),
+        warnings: g.warnings,
+    })
+}
+
+/// The macro's function name: the document's file stem as a C++ identifier.
+fn macro_name(filename: &str) -> String {
+    let file = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+    let stem = file.strip_suffix(".gdml").unwrap_or(file);
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        name = "geometry".to_string();
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "geometry_");
+    }
+    name
+}
+
+/// mm → cm.
+fn cm(mm: f64) -> String {
+    num(mm / 10.0)
+}
+
+fn deg(radians: f64) -> String {
+    num(radians.to_degrees())
+}
+
+struct Generator<'a> {
+    doc: &'a GdmlDocument,
+    engine: &'a EvalEngine,
+    body: FunctionBody,
+    includes: BTreeSet<&'static str>,
+    uses_table: bool,
+    uses_rotation: bool,
+    uses_vertex: bool,
+    /// Document name → C++ variable, `None` once it failed.
+    isotopes: HashMap<String, String>,
+    elements: HashMap<String, Option<String>>,
+    materials: HashMap<String, Option<String>>,
+    media: HashMap<String, String>,
+    shapes: HashMap<String, Option<String>>,
+    volumes: HashMap<String, String>,
+    skipped_mee: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(doc: &'a GdmlDocument, engine: &'a EvalEngine) -> Self {
+        Self {
+            doc,
+            engine,
+            body: FunctionBody::default(),
+            includes: BTreeSet::new(),
+            uses_table: false,
+            uses_rotation: false,
+            uses_vertex: false,
+            isotopes: HashMap::new(),
+            elements: HashMap::new(),
+            materials: HashMap::new(),
+            media: HashMap::new(),
+            shapes: HashMap::new(),
+            volumes: HashMap::new(),
+            skipped_mee: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// A translation `t` (mm) and GDML rotation `r` (rad) as a ROOT matrix
+    /// expression; `nullptr` stands for the identity everywhere one is taken.
+    fn matrix(&mut self, t: [f64; 3], r: [f64; 3]) -> String {
+        self.includes.insert("TGeoMatrix.h");
+        let [x, y, z] = t.map(cm);
+        if r == [0.0; 3] {
+            if t == [0.0; 3] {
+                return "nullptr".to_string();
+            }
+            return format!("new TGeoTranslation({}, {}, {})", x, y, z);
+        }
+        self.uses_rotation = true;
+        format!(
+            "new TGeoCombiTrans({}, {}, {}, rotation({}, {}, {}))",
+            x,
+            y,
+            z,
+            deg(r[0]),
+            deg(r[1]),
+            deg(r[2])
+        )
+    }
+
+    // ─── Materials ───────────────────────────────────────────────────────────
+
+    fn isotope(&mut self, iso: &Isotope) {
+        let (Some(z), Some(n), Some(a)) = (
+            iso.z.as_deref(),
+            iso.n.as_deref(),
+            molar_mass(self.engine, &iso.atom_value, &iso.atom_unit),
+        ) else {
+            self.warnings.push(format!(
+                "Isotope \"{}\" needs Z, N and an atom value; it was not generated.",
+                iso.name
+            ));
+            return;
+        };
+        let (z, n) = (self.engine.resolve_value(z), self.engine.resolve_value(n));
+        let id = self.identifier("iso_", &iso.name);
+        self.includes.insert("TGeoElement.h");
+        self.line(&format!(
+            "auto {} = new TGeoIsotope({}, {}, {}, {});",
+            id,
+            quote(&iso.name),
+            int(z),
+            int(n),
+            real(a)
+        ));
+        self.isotopes.insert(iso.name.clone(), id);
+    }
+
+    /// The variable holding element `name`, generating it on first use. An
+    /// element the document does not define is looked up in ROOT's element
+    /// table, which matches symbols and names alike.
+    fn element(&mut self, name: &str) -> Option<String> {
+        if let Some(done) = self.elements.get(name) {
+            return done.clone();
+        }
+        self.elements.insert(name.to_string(), None);
+        let id = match self.doc.materials.elements.iter().find(|e| e.name == name) {
+            Some(el) => self.define_element(el),
+            None => {
+                self.uses_table = true;
+                let id = self.identifier("el_", name);
+                self.line(&format!(
+                    "auto {} = table->FindElement({});",
+                    id,
+                    quote(name)
+                ));
+                Some(id)
+            }
+        };
+        self.elements.insert(name.to_string(), id.clone());
+        id
+    }
+
+    fn define_element(&mut self, el: &Element) -> Option<String> {
+        self.includes.insert("TGeoElement.h");
+        let symbol = quote(el.formula.as_deref().unwrap_or(&el.name));
+        if !el.fractions.is_empty() {
+            let isotopes: Vec<(String, f64)> = el
+                .fractions
+                .iter()
+                .filter_map(|f| {
+                    let iso = self.isotopes.get(&f.ref_name)?.clone();
+                    Some((iso, self.engine.resolve_value(&f.n)))
+                })
+                .collect();
+            if isotopes.len() != el.fractions.len() {
+                self.warnings.push(format!(
+                    "Element \"{}\" references an isotope that was not generated; it was \
+                     left out.",
+                    el.name
+                ));
+                return None;
+            }
+            let id = self.identifier("el_", &el.name);
+            self.line(&format!(
+                "auto {} = new TGeoElement({}, {}, {});",
+                id,
+                quote(&el.name),
+                symbol,
+                isotopes.len()
+            ));
+            for (iso, n) in isotopes {
+                self.line(&format!("{}->AddIsotope({}, {});", id, iso, real(n)));
+            }
+            return Some(id);
+        }
+        let (Some(z), Some(a)) = (
+            el.z.as_deref(),
+            molar_mass(self.engine, &el.atom_value, &el.atom_unit),
+        ) else {
+            self.warnings.push(format!(
+                "Element \"{}\" has neither Z and an atom value nor isotope fractions; it was \
+                 left out.",
+                el.name
+            ));
+            return None;
+        };
+        let id = self.identifier("el_", &el.name);
+        self.line(&format!(
+            "auto {} = new TGeoElement({}, {}, {}, {});",
+            id,
+            quote(&el.name),
+            symbol,
+            int(self.engine.resolve_value(z)),
+            real(a)
+        ));
+        Some(id)
+    }
+
+    /// The medium for material `name`, one per material.
+    fn medium(&mut self, name: &str) -> Option<String> {
+        if let Some(id) = self.media.get(name) {
+            return Some(id.clone());
+        }
+        let material = self.material(name)?;
+        let id = self.identifier("med_", name);
+        self.includes.insert("TGeoMedium.h");
+        self.line(&format!(
+            "auto {} = new TGeoMedium({}, {}, {});",
+            id,
+            quote(name),
+            self.media.len() + 1,
+            material
+        ));
+        self.media.insert(name.to_string(), id.clone());
+        Some(id)
+    }
+
+    // ─── Shapes ──────────────────────────────────────────────────────────────
+
+    /// `auto id = new Class("name", args);`
+    fn construct(&mut self, class: &'static str, name: &str, args: &[String]) -> String {
+        let id = self.identifier("shape_", name);
+        self.include(class);
+        let mut call = quote(name);
+        for a in args {
+            call.push_str(", ");
+            call.push_str(a);
+        }
+        self.line(&format!("auto {} = new {}({});", id, class, call));
+        id
+    }
+
+    fn include(&mut self, class: &'static str) {
+        let header = match class {
+            "TGeoBBox" => "TGeoBBox.h",
+            "TGeoTube" | "TGeoTubeSeg" | "TGeoCtub" => "TGeoTube.h",
+            "TGeoEltu" => "TGeoEltu.h",
+            "TGeoCone" | "TGeoConeSeg" => "TGeoCone.h",
+            "TGeoSphere" => "TGeoSphere.h",
+            "TGeoTorus" => "TGeoTorus.h",
+            "TGeoTrd2" => "TGeoTrd2.h",
+            "TGeoPara" => "TGeoPara.h",
+            "TGeoTrap" | "TGeoArb8" => "TGeoArb8.h",
+            "TGeoPcon" => "TGeoPcon.h",
+            "TGeoPgon" => "TGeoPgon.h",
+            "TGeoXtru" => "TGeoXtru.h",
+            "TGeoHype" => "TGeoHype.h",
+            "TGeoParaboloid" => "TGeoParaboloid.h",
+            "TGeoTessellated" => "TGeoTessellated.h",
+            "TGeoScaledShape" => "TGeoScaledShape.h",
+            "TGeoCompositeShape" => {
+                self.includes.insert("TGeoBoolNode.h");
+                "TGeoCompositeShape.h"
+            }
+            _ => return,
+        };
+        self.includes.insert(header);
+    }
+
+    /// `auto id = new TGeoCompositeShape("name", new TGeoNode(left, right, ..));`
+    fn composite(
+        &mut self,
+        name: &str,
+        node: &str,
+        (left, left_matrix): (&str, &str),
+        (right, right_matrix): (&str, &str),
+    ) -> String {
+        let args = [format!(
+            "new {}({}, {}, {}, {})",
+            node, left, right, left_matrix, right_matrix
+        )];
+        self.construct("TGeoCompositeShape", name, &args)
+    }
+
+    /// A closed `TGeoTessellated` from facets given as corner lists (mm),
+    /// each anticlockwise seen from outside.
+    fn tessellated(&mut self, name: &str, facets: &[Vec<[f64; 3]>]) -> String {
+        self.uses_vertex = true;
+        let id = self.construct("TGeoTessellated", name, &[facets.len().to_string()]);
+        for f in facets {
+            let corners = f
+                .iter()
+                .map(|p| format!("Vertex({}, {}, {})", cm(p[0]), cm(p[1]), cm(p[2])));
+            self.line(&format!("{}->AddFacet({});", id, list(corners)));
+        }
+        self.line(&format!("{}->CloseShape();", id));
+        id
+    }
+}
+
+impl<'a> Emitter<'a> for Generator<'a> {
+    fn doc(&self) -> &'a GdmlDocument {
+        self.doc
+    }
+
+    fn body(&mut self) -> &mut FunctionBody {
+        &mut self.body
+    }
+
+    fn warnings(&mut self) -> &mut Vec<String> {
+        &mut self.warnings
+    }
+
+    fn materials(&mut self) -> &mut HashMap<String, Option<String>> {
+        &mut self.materials
+    }
+
+    fn solids(&mut self) -> &mut HashMap<String, Option<String>> {
+        &mut self.shapes
+    }
+
+    fn define_material(&mut self, m: &Material) -> Option<String> {
+        let Some(rho) = density(self.engine, m) else {
+            self.warnings.push(format!(
+                "Material \"{}\" has no density that evaluates; it was left out.",
+                m.name
+            ));
+            return None;
+        };
+        self.includes.insert("TGeoMaterial.h");
+        let id = if let Some(z) = &m.z {
+            let Some(a) = molar_mass(self.engine, &m.atom_value, &m.atom_unit) else {
+                self.warnings.push(format!(
+                    "Material \"{}\" gives Z but no atom value; it was left out.",
+                    m.name
+                ));
+                return None;
+            };
+            let id = self.identifier("mat_", &m.name);
+            self.line(&format!(
+                "auto {} = new TGeoMaterial({}, {}, {}, {});",
+                id,
+                quote(&m.name),
+                real(a),
+                real(self.engine.resolve_value(z)),
+                real(rho)
+            ));
+            id
+        } else if !m.components.is_empty() {
+            let mut adds = Vec::new();
+            for c in &m.components {
+                let (n, ref_name, by_atoms) = match c {
+                    MaterialComponent::Fraction { n, ref_name } => (n, ref_name, false),
+                    MaterialComponent::Composite { n, ref_name } => (n, ref_name, true),
+                };
+                let n = self.engine.resolve_value(n);
+                // The Int_t overload counts atoms, the Double_t ones weigh.
+                let add = if by_atoms || is_element(self.doc, ref_name) {
+                    self.element(ref_name).map(|el| {
+                        if by_atoms {
+                            format!("AddElement({}, {})", el, int(n))
+                        } else {
+                            format!("AddElement({}, {})", el, real(n))
+                        }
+                    })
+                } else {
+                    self.material(ref_name)
+                        .map(|mat| format!("AddElement({}, {})", mat, real(n)))
+                };
+                match add {
+                    Some(add) => adds.push(add),
+                    None => {
+                        self.warnings.push(format!(
+                            "Material \"{}\" uses \"{}\", which was not generated; it was left \
+                             out.",
+                            m.name, ref_name
+                        ));
+                        return None;
+                    }
+                }
+            }
+            let id = self.identifier("mat_", &m.name);
+            self.line(&format!(
+                "auto {} = new TGeoMixture({}, {}, {});",
+                id,
+                quote(&m.name),
+                adds.len(),
+                real(rho)
+            ));
+            for add in adds {
+                self.line(&format!("{}->{};", id, add));
+            }
+            id
+        } else {
+            self.warnings.push(format!(
+                "Material \"{}\" has neither Z nor components; it was left out.",
+                m.name
+            ));
+            return None;
+        };
+
+        let state = match m.state.as_deref() {
+            Some("solid") => Some("kMatStateSolid"),
+            Some("liquid") => Some("kMatStateLiquid"),
+            Some("gas") => Some("kMatStateGas"),
+            _ => None,
+        };
+        if let Some(state) = state {
+            self.line(&format!("{}->SetState(TGeoMaterial::{});", id, state));
+        }
+        if let Some(t) = &m.temperature {
+            let v = self.engine.resolve_value(&t.value);
+            let kelvin = units::apply_unit(v, t.unit.as_deref().unwrap_or("K"));
+            self.line(&format!("{}->SetTemperature({});", id, real(kelvin)));
+        }
+        if let Some(p) = &m.pressure {
+            // TGeoMaterial keeps pressure in Geant4's internal units.
+            let v = self.engine.resolve_value(&p.value);
+            let internal = units::apply_unit(v, p.unit.as_deref().unwrap_or("pascal"));
+            self.line(&format!("{}->SetPressure({});", id, real(internal)));
+        }
+        if m.mee.is_some() {
+            self.skipped_mee.push(format!("\"{}\"", m.name));
+        }
+        Some(id)
+    }
+
+    /// A material from the bundled NIST table. Compounds are mixed by weight
+    /// from ROOT's elements; a material the table lists without a
+    /// composition keeps only its density.
+    fn undefined_material(&mut self, name: &str) -> Option<String> {
+        let Some(nist) = find_nist_material(name) else {
+            self.warnings.push(format!(
+                "Material \"{}\" is neither defined in the document nor a NIST material; it \
+                 was left out.",
+                name
+            ));
+            return None;
+        };
+        self.includes.insert("TGeoMaterial.h");
+        let id = self.identifier("mat_", name);
+        if let (Some(z), Some(a)) = (nist.z, nist.atom_value) {
+            self.line(&format!(
+                "auto {} = new TGeoMaterial({}, {}, {}, {});",
+                id,
+                quote(name),
+                real(a),
+                real(z as f64),
+                real(nist.density)
+            ));
+            return Some(id);
+        }
+        let elements: Option<Vec<(u32, f64)>> = nist
+            .components
+            .iter()
+            .map(|c| Some((find_nist_material(&c.ref_name)?.z?, c.n)))
+            .collect();
+        match elements {
+            Some(elements) if !elements.is_empty() => {
+                self.uses_table = true;
+                self.line(&format!(
+                    "auto {} = new TGeoMixture({}, {}, {});",
+                    id,
+                    quote(name),
+                    elements.len(),
+                    real(nist.density)
+                ));
+                for (z, w) in elements {
+                    self.line(&format!(
+                        "{}->AddElement(table->GetElement({}), {});",
+                        id,
+                        z,
+                        real(w)
+                    ));
+                }
+            }
+            _ => {
+                // G4_Galactic and the like are vacuum either way.
+                if nist.density > 1e-10 {
+                    self.warnings.push(format!(
+                        "The NIST table bundled here has no composition for \"{}\"; it was \
+                         written with its density only.",
+                        name
+                    ));
+                }
+                self.line(&format!(
+                    "auto {} = new TGeoMaterial({}, 0., 0., {});",
+                    id,
+                    quote(name),
+                    real(nist.density)
+                ));
+            }
+        }
+        Some(id)
+    }
+
+    fn define_solid(&mut self, solid: &Solid) -> Option<String> {
+        let engine = self.engine;
+        let name = solid.name();
+        Some(match solid {
+            Solid::Box(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.x, &s.y, &s.z].map(|e| cm(0.5 * v.len(e)));
+                self.construct("TGeoBBox", name, &args)
+            }
+            Solid::Tube(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let mut args = vec![
+                    cm(v.opt_len(&s.rmin)),
+                    cm(v.len(&s.rmax)),
+                    cm(0.5 * v.len(&s.z)),
+                ];
+                let dphi = v.dphi(&s.deltaphi);
+                if dphi >= 2.0 * PI {
+                    self.construct("TGeoTube", name, &args)
+                } else {
+                    let phi = v.opt_angle(&s.startphi, 0.0);
+                    args.extend([deg(phi), deg(phi + dphi)]);
+                    self.construct("TGeoTubeSeg", name, &args)
+                }
+            }
+            Solid::Cone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let mut args = vec![
+                    cm(0.5 * v.len(&s.z)),
+                    cm(v.opt_len(&s.rmin1)),
+                    cm(v.len(&s.rmax1)),
+                    cm(v.opt_len(&s.rmin2)),
+                    cm(v.len(&s.rmax2)),
+                ];
+                let dphi = v.dphi(&s.deltaphi);
+                if dphi >= 2.0 * PI {
+                    self.construct("TGeoCone", name, &args)
+                } else {
+                    let phi = v.opt_angle(&s.startphi, 0.0);
+                    args.extend([deg(phi), deg(phi + dphi)]);
+                    self.construct("TGeoConeSeg", name, &args)
+                }
+            }
+            Solid::Sphere(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let phi = v.opt_angle(&s.startphi, 0.0);
+                let theta = v.opt_angle(&s.starttheta, 0.0);
+                let args = [
+                    cm(v.opt_len(&s.rmin)),
+                    cm(v.len(&s.rmax)),
+                    deg(theta),
+                    deg(theta + v.opt_angle(&s.deltatheta, PI)),
+                    deg(phi),
+                    deg(phi + v.dphi(&s.deltaphi)),
+                ];
+                self.construct("TGeoSphere", name, &args)
+            }
+            Solid::Orb(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                self.construct("TGeoSphere", name, &["0".to_string(), cm(v.len(&s.r))])
+            }
+            Solid::Torus(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    cm(v.len(&s.rtor)),
+                    cm(v.opt_len(&s.rmin)),
+                    cm(v.len(&s.rmax)),
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                ];
+                self.construct("TGeoTorus", name, &args)
+            }
+            Solid::Trd(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.x1, &s.x2, &s.y1, &s.y2, &s.z].map(|e| cm(0.5 * v.len(e)));
+                self.construct("TGeoTrd2", name, &args)
+            }
+            Solid::Para(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    cm(0.5 * v.len(&s.x)),
+                    cm(0.5 * v.len(&s.y)),
+                    cm(0.5 * v.len(&s.z)),
+                    deg(v.opt_angle(&s.alpha, 0.0)),
+                    deg(v.opt_angle(&s.theta, 0.0)),
+                    deg(v.opt_angle(&s.phi, 0.0)),
+                ];
+                self.construct("TGeoPara", name, &args)
+            }
+            Solid::Trap(s) => {
+                // TGeoTrap takes G4Trap's arguments in G4Trap's order.
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let half = |e: &String| cm(0.5 * v.len(e));
+                let args = [
+                    half(&s.z),
+                    deg(v.opt_angle(&s.theta, 0.0)),
+                    deg(v.opt_angle(&s.phi, 0.0)),
+                    half(&s.y1),
+                    half(&s.x1),
+                    half(&s.x2),
+                    deg(v.opt_angle(&s.alpha1, 0.0)),
+                    half(&s.y2),
+                    half(&s.x3),
+                    half(&s.x4),
+                    deg(v.opt_angle(&s.alpha2, 0.0)),
+                ];
+                self.construct("TGeoTrap", name, &args)
+            }
+            Solid::CutTube(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let c = |e: &Option<String>, d: f64| e.as_deref().map_or(d, |e| v.number(e));
+                let phi = v.opt_angle(&s.startphi, 0.0);
+                let args = [
+                    cm(v.opt_len(&s.rmin)),
+                    cm(v.len(&s.rmax)),
+                    cm(0.5 * v.len(&s.z)),
+                    deg(phi),
+                    deg(phi + v.dphi(&s.deltaphi)),
+                    num(c(&s.low_x, 0.0)),
+                    num(c(&s.low_y, 0.0)),
+                    num(c(&s.low_z, -1.0)),
+                    num(c(&s.high_x, 0.0)),
+                    num(c(&s.high_y, 0.0)),
+                    num(c(&s.high_z, 1.0)),
+                ];
+                self.construct("TGeoCtub", name, &args)
+            }
+            Solid::Polycone(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    s.zplanes.len().to_string(),
+                ];
+                let id = self.construct("TGeoPcon", name, &args);
+                for (i, p) in s.zplanes.iter().enumerate() {
+                    self.line(&format!(
+                        "{}->DefineSection({}, {}, {}, {});",
+                        id,
+                        i,
+                        cm(v.len(&p.z)),
+                        cm(v.opt_len(&p.rmin)),
+                        cm(v.len(&p.rmax))
+                    ));
+                }
+                id
+            }
+            Solid::Polyhedra(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    deg(v.opt_angle(&s.startphi, 0.0)),
+                    deg(v.dphi(&s.deltaphi)),
+                    int(v.number(&s.numsides)).to_string(),
+                    s.zplanes.len().to_string(),
+                ];
+                let id = self.construct("TGeoPgon", name, &args);
+                for (i, p) in s.zplanes.iter().enumerate() {
+                    self.line(&format!(
+                        "{}->DefineSection({}, {}, {}, {});",
+                        id,
+                        i,
+                        cm(v.len(&p.z)),
+                        cm(v.opt_len(&p.rmin)),
+                        cm(v.len(&p.rmax))
+                    ));
+                }
+                id
+            }
+            Solid::Xtru(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let id = self.identifier("shape_", name);
+                self.include("TGeoXtru");
+                // TGeoXtru's constructor takes no name.
+                self.line(&format!(
+                    "auto {} = new TGeoXtru({});",
+                    id,
+                    s.sections.len()
+                ));
+                self.line(&format!("{}->SetName({});", id, quote(name)));
+                let xs = list(s.vertices.iter().map(|p| cm(v.len(&p.x))));
+                let ys = list(s.vertices.iter().map(|p| cm(v.len(&p.y))));
+                self.line(&format!("Double_t {}_x[] = {{{}}};", id, xs));
+                self.line(&format!("Double_t {}_y[] = {{{}}};", id, ys));
+                self.line(&format!(
+                    "{0}->DefinePolygon({1}, {0}_x, {0}_y);",
+                    id,
+                    s.vertices.len()
+                ));
+                for (i, z) in s.sections.iter().enumerate() {
+                    self.line(&format!(
+                        "{}->DefineSection({}, {}, {}, {}, {});",
+                        id,
+                        i,
+                        cm(v.len(&z.z_position)),
+                        cm(v.len(&z.x_offset)),
+                        cm(v.len(&z.y_offset)),
+                        real(v.number(&z.scaling_factor))
+                    ));
+                }
+                id
+            }
+            Solid::Tessellated(s) => {
+                let vertex = |n: &String| engine.position_values.get(n).copied();
+                let mut facets = Vec::new();
+                for f in &s.facets {
+                    let (corners, kind) = match f {
+                        TessellatedFacet::Triangular {
+                            vertex1,
+                            vertex2,
+                            vertex3,
+                            r#type,
+                        } => (vec![vertex1, vertex2, vertex3], r#type),
+                        TessellatedFacet::Quadrangular {
+                            vertex1,
+                            vertex2,
+                            vertex3,
+                            vertex4,
+                            r#type,
+                        } => (vec![vertex1, vertex2, vertex3, vertex4], r#type),
+                    };
+                    let Some(mut corners) =
+                        corners.into_iter().map(vertex).collect::<Option<Vec<_>>>()
+                    else {
+                        self.warnings.push(format!(
+                            "Tessellated solid \"{}\" uses a vertex that is not defined; it was \
+                             left out.",
+                            name
+                        ));
+                        return None;
+                    };
+                    // RELATIVE corners are offsets from the first one.
+                    if kind.as_deref() == Some("RELATIVE") {
+                        let first = corners[0];
+                        for c in corners.iter_mut().skip(1) {
+                            *c = [0, 1, 2].map(|i| first[i] + c[i]);
+                        }
+                    }
+                    facets.push(corners);
+                }
+                self.tessellated(name, &facets)
+            }
+            Solid::Tet(s) => {
+                let corners = [&s.vertex1, &s.vertex2, &s.vertex3, &s.vertex4]
+                    .map(|n| engine.position_values.get(n).copied());
+                let [Some(a), Some(b), Some(c), Some(d)] = corners else {
+                    self.warnings.push(format!(
+                        "Tet \"{}\" uses a vertex that is not defined; it was left out.",
+                        name
+                    ));
+                    return None;
+                };
+                let facets: Vec<Vec<[f64; 3]>> =
+                    [(a, b, c, d), (a, b, d, c), (a, c, d, b), (b, c, d, a)]
+                        .into_iter()
+                        .map(|(p, q, r, opposite)| {
+                            // Turn each face so its normal points away from the
+                            // fourth corner.
+                            let sub = |u: [f64; 3], w: [f64; 3]| [0, 1, 2].map(|i| u[i] - w[i]);
+                            let (u, w, o) = (sub(q, p), sub(r, p), sub(opposite, p));
+                            let n = [
+                                u[1] * w[2] - u[2] * w[1],
+                                u[2] * w[0] - u[0] * w[2],
+                                u[0] * w[1] - u[1] * w[0],
+                            ];
+                            if n[0] * o[0] + n[1] * o[1] + n[2] * o[2] > 0.0 {
+                                vec![p, r, q]
+                            } else {
+                                vec![p, q, r]
+                            }
+                        })
+                        .collect();
+                self.tessellated(name, &facets)
+            }
+            Solid::Ellipsoid(s) => {
+                // TGDMLParse's construction: a sphere scaled to the semi-axes,
+                // intersected with a slab when the z cuts bite.
+                let v = Values::new(engine, &s.lunit, &None);
+                let (ax, by, cz) = (v.len(&s.ax), v.len(&s.by), v.len(&s.cz));
+                let cut = |e: &Option<String>| e.as_deref().map(|e| v.len(e));
+                let (mut low, mut high) = match (cut(&s.zcut1), cut(&s.zcut2)) {
+                    (Some(a), Some(b)) if a == 0.0 && b == 0.0 => (-cz, cz),
+                    (a, b) => (a.unwrap_or(-cz).max(-cz), b.unwrap_or(cz).min(cz)),
+                };
+                if low >= high {
+                    (low, high) = (-cz, cz);
+                }
+                self.include("TGeoScaledShape");
+                self.includes.insert("TGeoMatrix.h");
+                let sphere = format!("new TGeoSphere(0, {})", cm(cz));
+                let scale = format!("new TGeoScale({}, {}, 1.)", real(ax / cz), real(by / cz));
+                self.include("TGeoSphere");
+                if low <= -cz && high >= cz {
+                    self.construct("TGeoScaledShape", name, &[sphere, scale])
+                } else {
+                    let scaled = self.construct(
+                        "TGeoScaledShape",
+                        &format!("{}_full", name),
+                        &[sphere, scale],
+                    );
+                    let slab = self.construct(
+                        "TGeoBBox",
+                        &format!("{}_cut", name),
+                        &[cm(ax), cm(by), cm(0.5 * (high - low))],
+                    );
+                    let shift = self.matrix([0.0, 0.0, 0.5 * (low + high)], [0.0; 3]);
+                    self.composite(
+                        name,
+                        "TGeoIntersection",
+                        (&scaled, "nullptr"),
+                        (&slab, &shift),
+                    )
+                }
+            }
+            Solid::Eltube(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.dx, &s.dy, &s.dz].map(|e| cm(v.len(e)));
+                self.construct("TGeoEltu", name, &args)
+            }
+            Solid::Elcone(s) => {
+                // A round cone with G4EllipticalCone's x semi-axes, squeezed
+                // in y. dx and dy are slopes, not lengths.
+                let v = Values::new(engine, &s.lunit, &None);
+                let (dx, dy) = (v.number(&s.dx), v.number(&s.dy));
+                let zmax = v.len(&s.zmax);
+                let zcut = v.len(&s.zcut).min(zmax);
+                let cone = format!(
+                    "new TGeoCone({}, 0, {}, 0, {})",
+                    cm(zcut),
+                    cm(dx * (zmax + zcut)),
+                    cm(dx * (zmax - zcut))
+                );
+                self.include("TGeoCone");
+                self.include("TGeoScaledShape");
+                self.includes.insert("TGeoMatrix.h");
+                let scale = format!("new TGeoScale(1., {}, 1.)", real(dy / dx));
+                self.construct("TGeoScaledShape", name, &[cone, scale])
+            }
+            Solid::Paraboloid(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let args = [&s.rlo, &s.rhi, &s.dz].map(|e| cm(v.len(e)));
+                self.construct("TGeoParaboloid", name, &args)
+            }
+            Solid::Hype(s) => {
+                let v = Values::new(engine, &s.lunit, &s.aunit);
+                let args = [
+                    cm(v.opt_len(&s.rmin)),
+                    deg(v.opt_angle(&s.inst, 0.0)),
+                    cm(v.len(&s.rmax)),
+                    deg(v.opt_angle(&s.outst, 0.0)),
+                    cm(0.5 * v.len(&s.z)),
+                ];
+                self.construct("TGeoHype", name, &args)
+            }
+            Solid::Arb8(s) => {
+                let v = Values::new(engine, &s.lunit, &None);
+                let corners = [
+                    (&s.v1x, &s.v1y),
+                    (&s.v2x, &s.v2y),
+                    (&s.v3x, &s.v3y),
+                    (&s.v4x, &s.v4y),
+                    (&s.v5x, &s.v5y),
+                    (&s.v6x, &s.v6y),
+                    (&s.v7x, &s.v7y),
+                    (&s.v8x, &s.v8y),
+                ]
+                .map(|(x, y)| format!("{}, {}", cm(v.len(x)), cm(v.len(y))));
+                let id = self.identifier("shape_", name);
+                self.include("TGeoArb8");
+                self.line(&format!("Double_t {}_v[] = {{{}}};", id, list(corners)));
+                self.line(&format!(
+                    "auto {0} = new TGeoArb8({1}, {2}, {0}_v);",
+                    id,
+                    quote(name),
+                    cm(v.len(&s.dz))
+                ));
+                id
+            }
+            Solid::Scaled(s) => {
+                let [sx, sy, sz] = match &s.scale_ref {
+                    Some(r) => match engine.scale_values.get(r) {
+                        Some(values) => *values,
+                        None => {
+                            self.warnings.push(format!(
+                                "Scaled solid \"{}\" references scale \"{}\", which is not \
+                                 defined; it was left out.",
+                                name, r
+                            ));
+                            return None;
+                        }
+                    },
+                    None => [&s.scale_x, &s.scale_y, &s.scale_z].map(|e| engine.resolve_value(e)),
+                };
+                let inner = self.operand(name, &s.solid_ref)?;
+                self.includes.insert("TGeoMatrix.h");
+                let scale = format!("new TGeoScale({}, {}, {})", real(sx), real(sy), real(sz));
+                self.construct("TGeoScaledShape", name, &[inner, scale])
+            }
+            Solid::MultiUnion(s) => {
+                let mut nodes = Vec::new();
+                for node in &s.nodes {
+                    let operand = self.operand(name, &node.solid_ref)?;
+                    let t = placement_position(engine, &node.position);
+                    let r = placement_rotation(engine, &node.rotation);
+                    nodes.push((operand, self.matrix(t, r)));
+                }
+                let Some((first, first_matrix)) = nodes.first().cloned() else {
+                    self.warnings.push(format!(
+                        "Multi-union \"{}\" has no nodes; it was left out.",
+                        name
+                    ));
+                    return None;
+                };
+                if nodes.len() == 1 {
+                    // A union with itself keeps the node's placement.
+                    return Some(self.composite(
+                        name,
+                        "TGeoUnion",
+                        (&first, &first_matrix),
+                        (&first, &first_matrix),
+                    ));
+                }
+                let last = nodes.len() - 1;
+                let mut acc = (first, first_matrix);
+                for (i, (operand, matrix)) in nodes.into_iter().enumerate().skip(1) {
+                    let part = if i == last {
+                        name.to_string()
+                    } else {
+                        format!("{}_part{}", name, i)
+                    };
+                    let id =
+                        self.composite(&part, "TGeoUnion", (&acc.0, &acc.1), (&operand, &matrix));
+                    acc = (id, "nullptr".to_string());
+                }
+                acc.0
+            }
+            Solid::Boolean(s) => {
+                let first = self.operand(name, &s.first_ref)?;
+                let second = self.operand(name, &s.second_ref)?;
+                let first_matrix = self.matrix(
+                    placement_position(engine, &s.first_position),
+                    placement_rotation(engine, &s.first_rotation),
+                );
+                let second_matrix = self.matrix(
+                    placement_position(engine, &s.position),
+                    placement_rotation(engine, &s.rotation),
+                );
+                let node = match s.operation {
+                    BooleanOp::Union => "TGeoUnion",
+                    BooleanOp::Subtraction => "TGeoSubtraction",
+                    BooleanOp::Intersection => "TGeoIntersection",
+                };
+                self.composite(
+                    name,
+                    node,
+                    (&first, &first_matrix),
+                    (&second, &second_matrix),
+                )
+            }
+            Solid::GenericPolycone(_)
+            | Solid::GenericPolyhedra(_)
+            | Solid::TwistedBox(_)
+            | Solid::TwistedTrd(_)
+            | Solid::TwistedTrap(_)
+            | Solid::TwistedTubs(_)
+            | Solid::Reflected(_) => {
+                self.warnings.push(format!(
+                    "Solid \"{}\" has no ROOT shape to become; it was left out.",
+                    name
+                ));
+                return None;
+            }
+        })
+    }
+}
+
+impl<'a> Generator<'a> {
+    // ─── Structure ───────────────────────────────────────────────────────────
+
+    fn volume(&mut self, v: &Volume) {
+        let shape = self.shapes.get(&v.solid_ref).cloned().flatten();
+        let medium = self.medium(&v.material_ref);
+        let (Some(shape), Some(medium)) = (shape, medium) else {
+            self.warnings.push(format!(
+                "Volume \"{}\" was left out because its solid or material could not be \
+                 generated.",
+                v.name
+            ));
+            return;
+        };
+        self.includes.insert("TGeoVolume.h");
+        let id = self.identifier("vol_", &v.name);
+        self.line(&format!(
+            "auto {} = new TGeoVolume({}, {}, {});",
+            id,
+            quote(&v.name),
+            shape,
+            medium
+        ));
+        if let Some(aux) = v.auxiliaries.iter().find(|a| a.auxtype == "color") {
+            match parse_colour(&aux.auxvalue) {
+                Some([r, g, b, a]) => {
+                    self.includes.insert("TColor.h");
+                    let [r, g, b] = [r, g, b].map(|c| (c * 255.0).round() as u8);
+                    self.line(&format!(
+                        "{}->SetLineColor(TColor::GetColor(\"#{:02X}{:02X}{:02X}\"));",
+                        id, r, g, b
+                    ));
+                    if a < 1.0 {
+                        self.line(&format!(
+                            "{}->SetTransparency({});",
+                            id,
+                            ((1.0 - a) * 100.0).round()
+                        ));
+                    }
+                }
+                None => self.warnings.push(format!(
+                    "Volume \"{}\": color \"{}\" is not RRGGBB or RRGGBBAA hex; no colour was \
+                     set.",
+                    v.name, aux.auxvalue
+                )),
+            }
+        }
+        self.volumes.insert(v.name.clone(), id);
+    }
+
+    fn placements(&mut self, v: &Volume) {
+        let Some(mother) = self.volumes.get(&v.name).cloned() else {
+            return;
+        };
+        let engine = self.engine;
+        // ROOT names a node `<volume>_<copy>` and complains about a repeated
+        // one, so placements without a copy number are counted per daughter.
+        let mut copies: HashMap<&str, i64> = HashMap::new();
+        for pv in &v.physvols {
+            let Some(daughter) = self.volumes.get(&pv.volume_ref).cloned() else {
+                self.warnings.push(match &pv.file_ref {
+                    Some(file) => format!(
+                        "Volume \"{}\": the placement of file \"{}\" was left out; load the \
+                         included file with the document to resolve it.",
+                        v.name, file.name
+                    ),
+                    None => format!(
+                        "Volume \"{}\": the placement of \"{}\" was left out because that \
+                         volume was not generated.",
+                        v.name, pv.volume_ref
+                    ),
+                });
+                continue;
+            };
+            let count = copies.entry(pv.volume_ref.as_str()).or_insert(0);
+            let copy = pv
+                .copynumber
+                .as_deref()
+                .map_or(*count, |c| int(engine.resolve_value(c)));
+            *count += 1;
+            let t = placement_position(engine, &pv.position);
+            let r = placement_rotation(engine, &pv.rotation);
+            let matrix = self.matrix(t, r);
+            self.line(&format!(
+                "{}->AddNode({}, {}, {});",
+                mother, daughter, copy, matrix
+            ));
+        }
+        if let Some(rep) = &v.replica {
+            self.replica(v, &mother, rep);
+        }
+    }
+
+    /// A replica as one node per copy, placed where
+    /// `G4ReplicaNavigation::ComputeTransformation` puts it.
+    fn replica(&mut self, v: &Volume, mother: &str, rep: &ReplicaVol) {
+        let Some(daughter) = self.volumes.get(&rep.volume_ref).cloned() else {
+            self.warnings.push(format!(
+                "Volume \"{}\": the replica of \"{}\" was left out because that volume was not \
+                 generated.",
+                v.name, rep.volume_ref
+            ));
+            return;
+        };
+        let engine = self.engine;
+        // Unit-less values are internal units, mm or rad, as Geant4 reads them.
+        let value = |expr: &str, unit: &Option<String>| {
+            let v = engine.resolve_value(expr);
+            unit.as_deref().map_or(v, |u| units::apply_unit(v, u))
+        };
+        let n = int(engine.resolve_value(&rep.number));
+        let width = value(&rep.width, &rep.width_unit);
+        self.includes.insert("TGeoMatrix.h");
+        let node = match rep.curvilinear_axis.as_deref() {
+            Some("rho") => {
+                self.warnings.push(format!(
+                    "Volume \"{}\": the radial replica of \"{}\" slices the solid itself, \
+                     which has no ROOT node form; it was left out.",
+                    v.name, rep.volume_ref
+                ));
+                return;
+            }
+            Some("phi") => {
+                // Geant4 turns copy i's frame by -(offset + width*(i + 0.5)).
+                self.uses_rotation = true;
+                let offset = value(&rep.offset, &rep.offset_unit);
+                format!(
+                    "rotation(0, 0, -({} + {}*(i + 0.5)))",
+                    deg(offset),
+                    deg(width)
+                )
+            }
+            _ => {
+                let axis = rep
+                    .direction
+                    .iter()
+                    .position(|d| {
+                        d.as_deref()
+                            .is_some_and(|e| engine.resolve_value(e).abs() > 0.0)
+                    })
+                    .unwrap_or(2);
+                // Centred in the mother; Geant4 ignores the offset here.
+                let mut t = ["0".to_string(), "0".to_string(), "0".to_string()];
+                t[axis] = format!(
+                    "{} + {}*i",
+                    cm(-0.5 * width * (n - 1).max(0) as f64),
+                    cm(width)
+                );
+                format!("new TGeoTranslation({})", list(t))
+            }
+        };
+        self.line(&format!("for (Int_t i = 0; i < {}; ++i)", n));
+        self.line(&format!(
+            "  {}->AddNode({}, i, {});",
+            mother, daughter, node
+        ));
+    }
+
+    fn finish(&mut self, function: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "// Geometry of {}, for ROOT: `root {}.C`.",
+            self.doc.filename, function
+        );
+        out.push('\n');
+        self.includes.insert("TGeoManager.h");
+        for header in &self.includes {
+            let _ = writeln!(out, "#include \"{}\"", header);
+        }
+        let _ = write!(out, "\nvoid {}()\n{{\n", function);
+        let _ = writeln!(
+            out,
+            "  new TGeoManager({}, {});",
+            quote(function),
+            quote(&format!("Geometry of {}", self.doc.filename))
+        );
+        if self.uses_table {
+            out.push_str("  auto table = gGeoManager->GetElementTable();\n");
+        }
+        if self.uses_vertex {
+            out.push_str("  using Vertex = ROOT::Geom::Vertex_t;\n");
+        }
+        if self.uses_rotation {
+            out.push_str(
+                "  // A GDML rotation, in degrees, as the daughter-to-mother matrix.\n  \
+                 auto rotation = [](Double_t x, Double_t y, Double_t z) {\n    \
+                 auto rot = new TGeoRotation();\n    \
+                 rot->RotateZ(-z);\n    \
+                 rot->RotateY(-y);\n    \
+                 rot->RotateX(-x);\n    \
+                 return rot;\n  };\n",
+            );
+        }
+        out.push('\n');
+        out.push_str(&self.body.text);
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::parser::parse_gdml_from_bytes;
+
+    fn generate(xml: &str) -> RootMacro {
+        let doc = parse_gdml_from_bytes(xml.as_bytes(), "2x2 detector.gdml".to_string()).unwrap();
+        let mut engine = EvalEngine::new();
+        engine.evaluate_all(&doc.defines).unwrap();
+        generate_root_macro(&doc, &engine).unwrap()
+    }
+
+    #[test]
+    fn a_detector_becomes_a_macro() {
+        let out = generate(
+            r#"<gdml>
+  <materials>
+    <element name="Hydrogen" formula="H" Z="1"><atom value="1.008"/></element>
+    <material name="Water" state="liquid">
+      <D value="1"/>
+      <composite n="2" ref="Hydrogen"/>
+      <composite n="1" ref="O"/>
+    </material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="1" y="1" z="1" lunit="m"/>
+    <tube name="Can" rmax="10" z="40" startphi="0" deltaphi="90" aunit="deg"/>
+    <box name="Cut" x="5" y="5" z="5"/>
+    <subtraction name="Holed">
+      <first ref="Can"/><second ref="Cut"/>
+      <position name="p" x="10"/>
+    </subtraction>
+  </solids>
+  <structure>
+    <volume name="Target">
+      <materialref ref="Water"/><solidref ref="Holed"/>
+      <auxiliary auxtype="color" auxvalue="FF000080"/>
+    </volume>
+    <volume name="World">
+      <materialref ref="G4_AIR"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Target"/><rotation name="t" z="30" unit="deg"/></physvol>
+      <physvol><volumeref ref="Target"/><position name="u" z="20" unit="cm"/></physvol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#,
+        );
+        assert_eq!(out.file_name, "geometry_2x2_detector.C");
+        let c = &out.source;
+        for expected in [
+            "void geometry_2x2_detector()",
+            "auto el_Hydrogen = new TGeoElement(\"Hydrogen\", \"H\", 1, 1.008);",
+            "auto el_O = table->FindElement(\"O\");",
+            "auto mat_Water = new TGeoMixture(\"Water\", 2, 1.);",
+            "mat_Water->AddElement(el_Hydrogen, 2);",
+            "mat_Water->SetState(TGeoMaterial::kMatStateLiquid);",
+            "auto mat_G4_AIR = new TGeoMixture(\"G4_AIR\", 4, 0.001205);",
+            "mat_G4_AIR->AddElement(table->GetElement(7), 0.755268);",
+            "auto med_Water = new TGeoMedium(\"Water\", 1, mat_Water);",
+            "new TGeoBBox(\"WorldBox\", 50, 50, 50);",
+            "new TGeoTubeSeg(\"Can\", 0, 1, 2, 0, 90);",
+            "new TGeoCompositeShape(\"Holed\", new TGeoSubtraction(shape_Can, shape_Cut, \
+             nullptr, new TGeoTranslation(1, 0, 0)));",
+            "vol_Target->SetLineColor(TColor::GetColor(\"#FF0000\"));",
+            "vol_Target->SetTransparency(50);",
+            "vol_World->AddNode(vol_Target, 0, new TGeoCombiTrans(0, 0, 0, rotation(0, 0, 30)));",
+            "vol_World->AddNode(vol_Target, 1, new TGeoTranslation(0, 0, 20));",
+            "rot->RotateZ(-z);",
+            "gGeoManager->SetTopVolume(vol_World);",
+            "#include \"TGeoCompositeShape.h\"",
+        ] {
+            assert!(c.contains(expected), "missing `{}` in:\n{}", expected, c);
+        }
+        assert!(c.find("auto mat_Water").unwrap() < c.find("auto med_Water").unwrap());
+        assert!(out.warnings.is_empty(), "{:?}", out.warnings);
+    }
+
+    #[test]
+    fn replicas_loop_and_untranslatable_solids_are_reported() {
+        let out = generate(
+            r#"<gdml>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="Slab" x="10" y="100" z="100"/>
+    <tube name="Wedge" rmax="40" z="100" deltaphi="45" aunit="deg"/>
+    <twistedbox name="Twist" PhiTwist="30" x="10" y="10" z="10" aunit="deg"/>
+  </solids>
+  <structure>
+    <volume name="Layer"><materialref ref="G4_Si"/><solidref ref="Slab"/></volume>
+    <volume name="Slice"><materialref ref="G4_Si"/><solidref ref="Wedge"/></volume>
+    <volume name="Twisted"><materialref ref="G4_Si"/><solidref ref="Twist"/></volume>
+    <volume name="Stack">
+      <materialref ref="G4_Galactic"/><solidref ref="WorldBox"/>
+      <replicavol number="10">
+        <volumeref ref="Layer"/>
+        <replicate_along_axis>
+          <direction x="1"/>
+          <width value="1" unit="cm"/>
+          <offset value="0" unit="mm"/>
+        </replicate_along_axis>
+      </replicavol>
+    </volume>
+    <volume name="World">
+      <materialref ref="G4_Galactic"/><solidref ref="WorldBox"/>
+      <physvol><volumeref ref="Stack"/></physvol>
+      <replicavol number="8">
+        <volumeref ref="Slice"/>
+        <replicate_along_axis>
+          <direction phi="1"/>
+          <width value="45" unit="deg"/>
+          <offset value="0" unit="deg"/>
+        </replicate_along_axis>
+      </replicavol>
+    </volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#,
+        );
+        let c = &out.source;
+        assert!(c.contains("auto mat_G4_Si = new TGeoMaterial(\"G4_Si\", 28.086, 14., 2.33);"));
+        assert!(c.contains("for (Int_t i = 0; i < 10; ++i)"));
+        assert!(
+            c.contains("vol_Stack->AddNode(vol_Layer, i, new TGeoTranslation(-4.5 + 1*i, 0, 0));")
+        );
+        assert!(
+            c.contains("vol_World->AddNode(vol_Slice, i, rotation(0, 0, -(0 + 45*(i + 0.5))));")
+        );
+        assert!(!c.contains("vol_Twisted"));
+        assert!(out.warnings.iter().any(|w| w.contains("\"Twist\"")));
+        assert!(out.warnings.iter().any(|w| w.contains("\"Twisted\"")));
+        // Vacuum needs no composition and earns no warning.
+        assert!(!out.warnings.iter().any(|w| w.contains("G4_Galactic")));
+    }
+}
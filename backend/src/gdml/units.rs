@@ -1,20 +1,510 @@
 //! GDML unit conversion.
 //!
-//! The internal system matches Geant4's for the quantities that reach geometry:
-//! millimetres for length, radians for angle. Geant4 resolves a `lunit`/`aunit`
-//! string through `G4UnitDefinition::GetValueOf` and raises a
-//! `FatalException` when the category does not match, so a file naming a unit
-//! that is not in the table below would not load there at all.
+//! The internal system is Geant4's, i.e. CLHEP's `SystemOfUnits`: the base
+//! units are the millimetre, nanosecond, MeV, positron charge (`eplus`),
+//! kelvin, mole, candela and radian, and every other unit is a multiple of
+//! those. That makes geometry come out in millimetres and radians, and a
+//! density written `1.032*g/cm3` the same 6.44e18 Geant4 stores.
+//!
+//! Two tables live here. [`SYSTEM_OF_UNITS`] and [`PHYSICAL_CONSTANTS`] are the
+//! identifiers `G4GDMLEvaluator` makes available inside expressions;
+//! [`unit_factor`] is `G4UnitsTable`, which resolves a `unit`/`lunit`/`aunit`
+//! attribute through `G4UnitDefinition::GetValueOf` and raises a
+//! `FatalException` when the category does not match -- so a file naming a unit
+//! that is not in that table would not load there at all.
+//!
+//! Geant4 derives the table from `e_SI`; this uses the 2019 SI value CLHEP has
+//! carried since 2.4.1. (`G4GDMLEvaluator` still passes the 1993 value to
+//! `setSystemOfUnits`, which shifts electrical and mass units in the seventh
+//! significant digit -- below anything a viewer can show.)
 //!
 //! Note that `micron` and `dm` are *not* legal GDML length units despite
 //! appearing in some documentation — Geant4's length table spells the micrometre
 //! `um`/`micrometer`, and has no decimetre.
 
-/// Which quantity a unit symbol measures.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which quantity a unit symbol measures. One variant per `G4UnitsTable`
+/// category that GDML attributes draw on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnitKind {
     Length,
+    Surface,
+    Volume,
     Angle,
+    SolidAngle,
+    Time,
+    Frequency,
+    Velocity,
+    ElectricCharge,
+    Energy,
+    Mass,
+    Density,
+    MolarMass,
+    Power,
+    Force,
+    Pressure,
+    ElectricCurrent,
+    ElectricPotential,
+    MagneticFlux,
+    MagneticFluxDensity,
+    Temperature,
+    AmountOfSubstance,
+    Activity,
+    Dose,
+    LuminousIntensity,
+}
+
+impl UnitKind {
+    /// The `G4UnitsTable` category name, as Geant4 prints it in the
+    /// "unit category mismatch" exception.
+    pub fn category(self) -> &'static str {
+        match self {
+            UnitKind::Length => "Length",
+            UnitKind::Surface => "Surface",
+            UnitKind::Volume => "Volume",
+            UnitKind::Angle => "Angle",
+            UnitKind::SolidAngle => "Solid angle",
+            UnitKind::Time => "Time",
+            UnitKind::Frequency => "Frequency",
+            UnitKind::Velocity => "Velocity",
+            UnitKind::ElectricCharge => "Electric charge",
+            UnitKind::Energy => "Energy",
+            UnitKind::Mass => "Mass",
+            UnitKind::Density => "Volumic Mass",
+            UnitKind::MolarMass => "Molar mass",
+            UnitKind::Power => "Power",
+            UnitKind::Force => "Force",
+            UnitKind::Pressure => "Pressure",
+            UnitKind::ElectricCurrent => "Electric current",
+            UnitKind::ElectricPotential => "Electric potential",
+            UnitKind::MagneticFlux => "Magnetic flux",
+            UnitKind::MagneticFluxDensity => "Magnetic flux density",
+            UnitKind::Temperature => "Temperature",
+            UnitKind::AmountOfSubstance => "Amount of substance",
+            UnitKind::Activity => "Activity",
+            UnitKind::Dose => "Dose",
+            UnitKind::LuminousIntensity => "Luminous intensity",
+        }
+    }
+}
+
+// ─── CLHEP SystemOfUnits ────────────────────────────────────────────────────
+
+pub const MILLIMETER: f64 = 1.0;
+pub const CENTIMETER: f64 = 10.0 * MILLIMETER;
+pub const METER: f64 = 1000.0 * MILLIMETER;
+pub const KILOMETER: f64 = 1000.0 * METER;
+pub const PARSEC: f64 = 3.0856775807e16 * METER;
+
+pub const RADIAN: f64 = 1.0;
+pub const DEGREE: f64 = std::f64::consts::PI / 180.0 * RADIAN;
+pub const STERADIAN: f64 = 1.0;
+
+pub const NANOSECOND: f64 = 1.0;
+pub const SECOND: f64 = 1.0e9 * NANOSECOND;
+
+pub const EPLUS: f64 = 1.0;
+pub const E_SI: f64 = 1.602176634e-19;
+pub const COULOMB: f64 = EPLUS / E_SI;
+
+pub const MEGAELECTRONVOLT: f64 = 1.0;
+pub const ELECTRONVOLT: f64 = 1.0e-6 * MEGAELECTRONVOLT;
+pub const JOULE: f64 = ELECTRONVOLT / E_SI;
+
+pub const KILOGRAM: f64 = JOULE * SECOND * SECOND / (METER * METER);
+pub const GRAM: f64 = 1.0e-3 * KILOGRAM;
+
+pub const WATT: f64 = JOULE / SECOND;
+pub const NEWTON: f64 = JOULE / METER;
+pub const PASCAL: f64 = NEWTON / (METER * METER);
+pub const ATMOSPHERE: f64 = 101325.0 * PASCAL;
+
+pub const AMPERE: f64 = COULOMB / SECOND;
+pub const MEGAVOLT: f64 = MEGAELECTRONVOLT / EPLUS;
+pub const VOLT: f64 = 1.0e-6 * MEGAVOLT;
+pub const WEBER: f64 = VOLT * SECOND;
+pub const TESLA: f64 = VOLT * SECOND / (METER * METER);
+
+pub const KELVIN: f64 = 1.0;
+pub const MOLE: f64 = 1.0;
+pub const CANDELA: f64 = 1.0;
+pub const BECQUEREL: f64 = 1.0 / SECOND;
+pub const GRAY: f64 = JOULE / KILOGRAM;
+
+/// `g/cm3`, Geant4's default density unit for `<D>`.
+pub const G_PER_CM3: f64 = GRAM / (CENTIMETER * CENTIMETER * CENTIMETER);
+
+/// Every identifier CLHEP's `SystemOfUnits.h` defines, plus the SI symbols
+/// `HepTool::Evaluator::setSystemOfUnits` adds (`K`, `mol`, `J`, `Pa`...). These
+/// are valid in any GDML expression, so `293.15*kelvin` or `1.032*g/cm3` in a
+/// `<constant>` evaluates to Geant4's internal value rather than failing.
+pub const SYSTEM_OF_UNITS: &[(&str, f64)] = &[
+    // Length, surface, volume
+    ("millimeter", MILLIMETER),
+    ("millimeter2", MILLIMETER * MILLIMETER),
+    ("millimeter3", MILLIMETER * MILLIMETER * MILLIMETER),
+    ("centimeter", CENTIMETER),
+    ("centimeter2", CENTIMETER * CENTIMETER),
+    ("centimeter3", CENTIMETER * CENTIMETER * CENTIMETER),
+    ("meter", METER),
+    ("meter2", METER * METER),
+    ("meter3", METER * METER * METER),
+    ("kilometer", KILOMETER),
+    ("kilometer2", KILOMETER * KILOMETER),
+    ("kilometer3", KILOMETER * KILOMETER * KILOMETER),
+    ("parsec", PARSEC),
+    ("micrometer", 1.0e-6 * METER),
+    ("nanometer", 1.0e-9 * METER),
+    ("angstrom", 1.0e-10 * METER),
+    ("fermi", 1.0e-15 * METER),
+    ("barn", 1.0e-28 * METER * METER),
+    ("millibarn", 1.0e-31 * METER * METER),
+    ("microbarn", 1.0e-34 * METER * METER),
+    ("nanobarn", 1.0e-37 * METER * METER),
+    ("picobarn", 1.0e-40 * METER * METER),
+    ("nm", 1.0e-9 * METER),
+    ("um", 1.0e-6 * METER),
+    ("mm", MILLIMETER),
+    ("mm2", MILLIMETER * MILLIMETER),
+    ("mm3", MILLIMETER * MILLIMETER * MILLIMETER),
+    ("cm", CENTIMETER),
+    ("cm2", CENTIMETER * CENTIMETER),
+    ("cm3", CENTIMETER * CENTIMETER * CENTIMETER),
+    ("liter", 1.0e3 * CENTIMETER * CENTIMETER * CENTIMETER),
+    ("L", 1.0e3 * CENTIMETER * CENTIMETER * CENTIMETER),
+    ("dL", 1.0e2 * CENTIMETER * CENTIMETER * CENTIMETER),
+    ("cL", 10.0 * CENTIMETER * CENTIMETER * CENTIMETER),
+    ("mL", CENTIMETER * CENTIMETER * CENTIMETER),
+    ("m", METER),
+    ("m2", METER * METER),
+    ("m3", METER * METER * METER),
+    ("km", KILOMETER),
+    ("km2", KILOMETER * KILOMETER),
+    ("km3", KILOMETER * KILOMETER * KILOMETER),
+    ("pc", PARSEC),
+    // Not in SystemOfUnits.h but long accepted here and in G4UnitsTable.
+    ("Ang", 1.0e-10 * METER),
+    ("fm", 1.0e-15 * METER),
+    // Angle
+    ("radian", RADIAN),
+    ("milliradian", 1.0e-3 * RADIAN),
+    ("degree", DEGREE),
+    ("steradian", STERADIAN),
+    ("rad", RADIAN),
+    ("mrad", 1.0e-3 * RADIAN),
+    ("sr", STERADIAN),
+    ("deg", DEGREE),
+    // Time
+    ("nanosecond", NANOSECOND),
+    ("second", SECOND),
+    ("millisecond", 1.0e-3 * SECOND),
+    ("microsecond", 1.0e-6 * SECOND),
+    ("picosecond", 1.0e-12 * SECOND),
+    ("minute", 60.0 * SECOND),
+    ("hour", 3600.0 * SECOND),
+    ("day", 86400.0 * SECOND),
+    ("year", 365.0 * 86400.0 * SECOND),
+    ("hertz", 1.0 / SECOND),
+    ("kilohertz", 1.0e3 / SECOND),
+    ("megahertz", 1.0e6 / SECOND),
+    ("ns", NANOSECOND),
+    ("s", SECOND),
+    ("ms", 1.0e-3 * SECOND),
+    ("us", 1.0e-6 * SECOND),
+    ("ps", 1.0e-12 * SECOND),
+    ("Hz", 1.0 / SECOND),
+    // Electric charge
+    ("eplus", EPLUS),
+    ("e_SI", E_SI),
+    ("coulomb", COULOMB),
+    ("C", COULOMB),
+    // Energy
+    ("megaelectronvolt", MEGAELECTRONVOLT),
+    ("electronvolt", ELECTRONVOLT),
+    ("kiloelectronvolt", 1.0e-3 * MEGAELECTRONVOLT),
+    ("gigaelectronvolt", 1.0e3 * MEGAELECTRONVOLT),
+    ("teraelectronvolt", 1.0e6 * MEGAELECTRONVOLT),
+    ("petaelectronvolt", 1.0e9 * MEGAELECTRONVOLT),
+    ("millielectronvolt", 1.0e-9 * MEGAELECTRONVOLT),
+    ("joule", JOULE),
+    ("MeV", MEGAELECTRONVOLT),
+    ("eV", ELECTRONVOLT),
+    ("keV", 1.0e-3 * MEGAELECTRONVOLT),
+    ("GeV", 1.0e3 * MEGAELECTRONVOLT),
+    ("TeV", 1.0e6 * MEGAELECTRONVOLT),
+    ("PeV", 1.0e9 * MEGAELECTRONVOLT),
+    ("J", JOULE),
+    // Mass
+    ("kilogram", KILOGRAM),
+    ("gram", GRAM),
+    ("milligram", 1.0e-3 * GRAM),
+    ("kg", KILOGRAM),
+    ("g", GRAM),
+    ("mg", 1.0e-3 * GRAM),
+    // Power, force, pressure
+    ("watt", WATT),
+    ("newton", NEWTON),
+    ("hep_pascal", PASCAL),
+    ("pascal", PASCAL),
+    ("bar", 1.0e5 * PASCAL),
+    ("atmosphere", ATMOSPHERE),
+    ("W", WATT),
+    ("N", NEWTON),
+    ("Pa", PASCAL),
+    // Electric current, potential, resistance, capacitance
+    ("ampere", AMPERE),
+    ("milliampere", 1.0e-3 * AMPERE),
+    ("microampere", 1.0e-6 * AMPERE),
+    ("nanoampere", 1.0e-9 * AMPERE),
+    ("megavolt", MEGAVOLT),
+    ("kilovolt", 1.0e-3 * MEGAVOLT),
+    ("volt", VOLT),
+    ("ohm", VOLT / AMPERE),
+    ("farad", COULOMB / VOLT),
+    ("millifarad", 1.0e-3 * COULOMB / VOLT),
+    ("microfarad", 1.0e-6 * COULOMB / VOLT),
+    ("picofarad", 1.0e-12 * COULOMB / VOLT),
+    ("A", AMPERE),
+    ("V", VOLT),
+    ("F", COULOMB / VOLT),
+    // Magnetic flux and field, inductance
+    ("weber", WEBER),
+    ("tesla", TESLA),
+    ("gauss", 1.0e-4 * TESLA),
+    ("kilogauss", 1.0e-1 * TESLA),
+    ("henry", WEBER / AMPERE),
+    ("Wb", WEBER),
+    ("T", TESLA),
+    ("H", WEBER / AMPERE),
+    // Temperature, amount of substance
+    ("kelvin", KELVIN),
+    ("mole", MOLE),
+    ("K", KELVIN),
+    ("mol", MOLE),
+    // Activity, absorbed dose
+    ("becquerel", BECQUEREL),
+    ("curie", 3.7e10 * BECQUEREL),
+    ("kilobecquerel", 1.0e3 * BECQUEREL),
+    ("megabecquerel", 1.0e6 * BECQUEREL),
+    ("gigabecquerel", 1.0e9 * BECQUEREL),
+    ("millicurie", 1.0e-3 * 3.7e10 * BECQUEREL),
+    ("microcurie", 1.0e-6 * 3.7e10 * BECQUEREL),
+    ("Bq", BECQUEREL),
+    ("kBq", 1.0e3 * BECQUEREL),
+    ("MBq", 1.0e6 * BECQUEREL),
+    ("GBq", 1.0e9 * BECQUEREL),
+    ("Ci", 3.7e10 * BECQUEREL),
+    ("mCi", 1.0e-3 * 3.7e10 * BECQUEREL),
+    ("uCi", 1.0e-6 * 3.7e10 * BECQUEREL),
+    ("gray", GRAY),
+    ("kilogray", 1.0e3 * GRAY),
+    ("milligray", 1.0e-3 * GRAY),
+    ("microgray", 1.0e-6 * GRAY),
+    ("Gy", GRAY),
+    ("kGy", 1.0e3 * GRAY),
+    ("mGy", 1.0e-3 * GRAY),
+    ("uGy", 1.0e-6 * GRAY),
+    // Luminous intensity, flux, illuminance
+    ("candela", CANDELA),
+    ("lumen", CANDELA * STERADIAN),
+    ("lux", CANDELA * STERADIAN / (METER * METER)),
+    ("cd", CANDELA),
+    ("lm", CANDELA * STERADIAN),
+    ("lx", CANDELA * STERADIAN / (METER * METER)),
+    // Miscellaneous
+    ("perCent", 0.01),
+    ("perThousand", 0.001),
+    ("perMillion", 0.000001),
+];
+
+const C_LIGHT: f64 = 2.99792458e8 * METER / SECOND;
+const H_PLANCK: f64 = 6.62607015e-34 * JOULE * SECOND;
+const HBAR_PLANCK: f64 = H_PLANCK / (2.0 * std::f64::consts::PI);
+const HBARC: f64 = HBAR_PLANCK * C_LIGHT;
+const ELECTRON_MASS_C2: f64 = 0.510998910 * MEGAELECTRONVOLT;
+const PROTON_MASS_C2: f64 = 938.272013 * MEGAELECTRONVOLT;
+const AMU_C2: f64 = 931.494028 * MEGAELECTRONVOLT;
+const MU0: f64 = 4.0 * std::f64::consts::PI * 1.0e-7 * WEBER / AMPERE / METER;
+const EPSILON0: f64 = 1.0 / (C_LIGHT * C_LIGHT * MU0);
+const ELM_COUPLING: f64 = EPLUS * EPLUS / (4.0 * std::f64::consts::PI * EPSILON0);
+const FINE_STRUCTURE_CONST: f64 = ELM_COUPLING / HBARC;
+const CLASSIC_ELECTR_RADIUS: f64 = ELM_COUPLING / ELECTRON_MASS_C2;
+const ELECTRON_COMPTON_LENGTH: f64 = HBARC / ELECTRON_MASS_C2;
+
+/// CLHEP's `PhysicalConstants.h`, in internal units.
+pub const PHYSICAL_CONSTANTS: &[(&str, f64)] = &[
+    ("Avogadro", 6.02214076e23 / MOLE),
+    ("c_light", C_LIGHT),
+    ("c_squared", C_LIGHT * C_LIGHT),
+    ("h_Planck", H_PLANCK),
+    ("hbar_Planck", HBAR_PLANCK),
+    ("hbarc", HBARC),
+    ("hbarc_squared", HBARC * HBARC),
+    ("electron_charge", -EPLUS),
+    ("e_squared", EPLUS * EPLUS),
+    ("electron_mass_c2", ELECTRON_MASS_C2),
+    ("proton_mass_c2", PROTON_MASS_C2),
+    ("neutron_mass_c2", 939.56536 * MEGAELECTRONVOLT),
+    ("amu_c2", AMU_C2),
+    ("amu", AMU_C2 / (C_LIGHT * C_LIGHT)),
+    ("mu0", MU0),
+    ("epsilon0", EPSILON0),
+    ("elm_coupling", ELM_COUPLING),
+    ("fine_structure_const", FINE_STRUCTURE_CONST),
+    ("classic_electr_radius", CLASSIC_ELECTR_RADIUS),
+    ("electron_Compton_length", ELECTRON_COMPTON_LENGTH),
+    (
+        "Bohr_radius",
+        ELECTRON_COMPTON_LENGTH / FINE_STRUCTURE_CONST,
+    ),
+    (
+        "alpha_rcl2",
+        FINE_STRUCTURE_CONST * CLASSIC_ELECTR_RADIUS * CLASSIC_ELECTR_RADIUS,
+    ),
+    (
+        "twopi_mc2_rcl2",
+        2.0 * std::f64::consts::PI
+            * ELECTRON_MASS_C2
+            * CLASSIC_ELECTR_RADIUS
+            * CLASSIC_ELECTR_RADIUS,
+    ),
+    (
+        "Bohr_magneton",
+        EPLUS * HBARC * C_LIGHT / (2.0 * ELECTRON_MASS_C2),
+    ),
+    (
+        "nuclear_magneton",
+        EPLUS * HBARC * C_LIGHT / (2.0 * PROTON_MASS_C2),
+    ),
+    ("k_Boltzmann", 8.617333e-11 * MEGAELECTRONVOLT / KELVIN),
+    ("STP_Temperature", 273.15 * KELVIN),
+    ("STP_Pressure", ATMOSPHERE),
+    ("kGasThreshold", 10.0 * 1.0e-3 * G_PER_CM3),
+    ("universe_mean_density", 1.0e-25 * G_PER_CM3),
+];
+
+// ─── G4UnitsTable ───────────────────────────────────────────────────────────
+
+/// Resolve a unit attribute to its category and value in internal units, as
+/// `G4UnitDefinition::GetValueOf` / `GetCategory` do. `None` if Geant4's unit
+/// table does not contain it.
+pub fn unit_factor(unit: &str) -> Option<(UnitKind, f64)> {
+    use UnitKind::*;
+    const CM3: f64 = CENTIMETER * CENTIMETER * CENTIMETER;
+    if let Some(mm) = length_factor(unit) {
+        return Some((Length, mm));
+    }
+    if let Some(rad) = angle_factor(unit) {
+        return Some((Angle, rad));
+    }
+    Some(match unit {
+        "km2" => (Surface, KILOMETER * KILOMETER),
+        "m2" => (Surface, METER * METER),
+        "cm2" => (Surface, CENTIMETER * CENTIMETER),
+        "mm2" => (Surface, MILLIMETER * MILLIMETER),
+        "barn" => (Surface, 1.0e-28 * METER * METER),
+        "millibarn" | "mbarn" => (Surface, 1.0e-31 * METER * METER),
+        "microbarn" | "mubarn" => (Surface, 1.0e-34 * METER * METER),
+        "nanobarn" | "nbarn" => (Surface, 1.0e-37 * METER * METER),
+        "picobarn" | "pbarn" => (Surface, 1.0e-40 * METER * METER),
+
+        "km3" => (Volume, KILOMETER * KILOMETER * KILOMETER),
+        "m3" => (Volume, METER * METER * METER),
+        "cm3" => (Volume, CM3),
+        "L" | "liter" => (Volume, 1.0e3 * CM3),
+        "dL" => (Volume, 1.0e2 * CM3),
+        "cL" => (Volume, 10.0 * CM3),
+        "mL" => (Volume, CM3),
+        "mm3" => (Volume, MILLIMETER * MILLIMETER * MILLIMETER),
+
+        "steradian" | "sr" => (SolidAngle, STERADIAN),
+        "millisteradian" | "msr" => (SolidAngle, 1.0e-3 * STERADIAN),
+
+        "second" | "s" => (Time, SECOND),
+        "millisecond" | "ms" => (Time, 1.0e-3 * SECOND),
+        "microsecond" | "us" => (Time, 1.0e-6 * SECOND),
+        "nanosecond" | "ns" => (Time, NANOSECOND),
+        "picosecond" | "ps" => (Time, 1.0e-12 * SECOND),
+        "minute" | "min" => (Time, 60.0 * SECOND),
+        "hour" | "h" => (Time, 3600.0 * SECOND),
+        "day" | "d" => (Time, 86400.0 * SECOND),
+        "year" | "y" => (Time, 365.0 * 86400.0 * SECOND),
+
+        "hertz" | "Hz" => (Frequency, 1.0 / SECOND),
+        "kilohertz" | "kHz" => (Frequency, 1.0e3 / SECOND),
+        "megahertz" | "MHz" => (Frequency, 1.0e6 / SECOND),
+
+        "cm/ns" => (Velocity, CENTIMETER / NANOSECOND),
+        "mm/ns" => (Velocity, MILLIMETER / NANOSECOND),
+        "cm/us" => (Velocity, CENTIMETER / (1.0e-6 * SECOND)),
+        "km/s" => (Velocity, KILOMETER / SECOND),
+        "cm/ms" => (Velocity, CENTIMETER / (1.0e-3 * SECOND)),
+        "m/s" => (Velocity, METER / SECOND),
+        "cm/s" => (Velocity, CENTIMETER / SECOND),
+        "mm/s" => (Velocity, MILLIMETER / SECOND),
+
+        "eplus" | "e+" => (ElectricCharge, EPLUS),
+        "coulomb" | "C" => (ElectricCharge, COULOMB),
+
+        "millielectronvolt" | "meV" => (Energy, 1.0e-9 * MEGAELECTRONVOLT),
+        "electronvolt" | "eV" => (Energy, ELECTRONVOLT),
+        "kiloelectronvolt" | "keV" => (Energy, 1.0e-3 * MEGAELECTRONVOLT),
+        "megaelectronvolt" | "MeV" => (Energy, MEGAELECTRONVOLT),
+        "gigaelectronvolt" | "GeV" => (Energy, 1.0e3 * MEGAELECTRONVOLT),
+        "teraelectronvolt" | "TeV" => (Energy, 1.0e6 * MEGAELECTRONVOLT),
+        "petaelectronvolt" | "PeV" => (Energy, 1.0e9 * MEGAELECTRONVOLT),
+        "joule" | "J" => (Energy, JOULE),
+
+        "milligram" | "mg" => (Mass, 1.0e-3 * GRAM),
+        "gram" | "g" => (Mass, GRAM),
+        "kilogram" | "kg" => (Mass, KILOGRAM),
+
+        "g/cm3" => (Density, G_PER_CM3),
+        "mg/cm3" => (Density, 1.0e-3 * G_PER_CM3),
+        "kg/m3" => (Density, KILOGRAM / (METER * METER * METER)),
+
+        "g/mole" => (MolarMass, GRAM / MOLE),
+        "kg/mole" => (MolarMass, KILOGRAM / MOLE),
+
+        "watt" | "W" => (Power, WATT),
+        "newton" | "N" => (Force, NEWTON),
+
+        "pascal" | "Pa" | "hep_pascal" => (Pressure, PASCAL),
+        "bar" => (Pressure, 1.0e5 * PASCAL),
+        "atmosphere" | "atm" => (Pressure, ATMOSPHERE),
+
+        "ampere" | "A" => (ElectricCurrent, AMPERE),
+        "milliampere" | "mA" => (ElectricCurrent, 1.0e-3 * AMPERE),
+        "microampere" | "muA" => (ElectricCurrent, 1.0e-6 * AMPERE),
+        "nanoampere" | "nA" => (ElectricCurrent, 1.0e-9 * AMPERE),
+
+        "volt" | "V" => (ElectricPotential, VOLT),
+        "kilovolt" | "kV" => (ElectricPotential, 1.0e-3 * MEGAVOLT),
+        "megavolt" | "MV" => (ElectricPotential, MEGAVOLT),
+
+        "weber" | "Wb" => (MagneticFlux, WEBER),
+        "tesla" | "T" => (MagneticFluxDensity, TESLA),
+        "kilogauss" | "kG" => (MagneticFluxDensity, 1.0e-1 * TESLA),
+        "gauss" | "G" => (MagneticFluxDensity, 1.0e-4 * TESLA),
+
+        "kelvin" | "K" => (Temperature, KELVIN),
+        "mole" | "mol" => (AmountOfSubstance, MOLE),
+
+        "becquerel" | "Bq" => (Activity, BECQUEREL),
+        "kilobecquerel" | "kBq" => (Activity, 1.0e3 * BECQUEREL),
+        "megabecquerel" | "MBq" => (Activity, 1.0e6 * BECQUEREL),
+        "gigabecquerel" | "GBq" => (Activity, 1.0e9 * BECQUEREL),
+        "curie" | "Ci" => (Activity, 3.7e10 * BECQUEREL),
+        "millicurie" | "mCi" => (Activity, 1.0e-3 * 3.7e10 * BECQUEREL),
+        "microcurie" | "uCi" => (Activity, 1.0e-6 * 3.7e10 * BECQUEREL),
+
+        "gray" | "Gy" => (Dose, GRAY),
+        "kilogray" | "kGy" => (Dose, 1.0e3 * GRAY),
+        "milligray" | "mGy" => (Dose, 1.0e-3 * GRAY),
+        "microgray" | "uGy" => (Dose, 1.0e-6 * GRAY),
+
+        "candela" | "cd" => (LuminousIntensity, CANDELA),
+        _ => return None,
+    })
 }
 
 /// Multiplier taking `unit` into millimetres, or `None` if it is not a length
@@ -29,7 +519,7 @@ pub fn length_factor(unit: &str) -> Option<f64> {
         "nm" | "nanometer" => 1.0e-6,
         "Ang" | "angstrom" => 1.0e-7,
         "fm" | "fermi" => 1.0e-12,
-        "pc" | "parsec" => 3.0856775807e19,
+        "pc" | "parsec" => PARSEC,
         // Not Geant4 units, accepted as a convenience for hand-written files.
         "in" | "inch" => 25.4,
         "ft" | "foot" => 304.8,
@@ -41,7 +531,7 @@ pub fn length_factor(unit: &str) -> Option<f64> {
 pub fn angle_factor(unit: &str) -> Option<f64> {
     Some(match unit {
         "rad" | "radian" | "radians" => 1.0,
-        "deg" | "degree" | "degrees" => std::f64::consts::PI / 180.0,
+        "deg" | "degree" | "degrees" => DEGREE,
         "mrad" | "milliradian" => 1.0e-3,
         _ => return None,
     })
@@ -49,13 +539,7 @@ pub fn angle_factor(unit: &str) -> Option<f64> {
 
 /// Classify a unit symbol, or `None` if it is unrecognised.
 pub fn unit_kind(unit: &str) -> Option<UnitKind> {
-    if length_factor(unit).is_some() {
-        Some(UnitKind::Length)
-    } else if angle_factor(unit).is_some() {
-        Some(UnitKind::Angle)
-    } else {
-        None
-    }
+    unit_factor(unit).map(|(kind, _)| kind)
 }
 
 /// Convert to millimetres. Unrecognised units pass through unchanged; callers
@@ -74,12 +558,16 @@ pub fn angle_to_rad(value: f64, unit: &str) -> f64 {
 /// `G4GDMLReadDefine::QuantityRead`, which multiplies by
 /// `G4UnitDefinition::GetValueOf(unit)` without ever consulting the `type`
 /// attribute.
+///
+/// Unrecognised units pass the value through unchanged.
 pub fn apply_unit(value: f64, unit: &str) -> f64 {
-    match unit_kind(unit) {
-        Some(UnitKind::Length) => length_to_mm(value, unit),
-        Some(UnitKind::Angle) => angle_to_rad(value, unit),
-        None => value,
-    }
+    value * unit_factor(unit).map_or(1.0, |(_, factor)| factor)
+}
+
+/// Express an internal-unit value in `unit`, e.g. a density back in `g/cm3`.
+/// Unrecognised units pass the value through unchanged.
+pub fn in_unit(value: f64, unit: &str) -> f64 {
+    value / unit_factor(unit).map_or(1.0, |(_, factor)| factor)
 }
 
 pub fn default_length_unit() -> &'static str {
@@ -126,6 +614,34 @@ mod tests {
     fn apply_unit_dispatches_without_a_type_hint() {
         assert_eq!(apply_unit(5.0, "cm"), 50.0);
         assert!((apply_unit(180.0, "deg") - std::f64::consts::PI).abs() < 1e-12);
-        assert_eq!(apply_unit(5.0, "g/cm3"), 5.0);
+        assert_eq!(apply_unit(5.0, "furlong"), 5.0);
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs())
+    }
+
+    #[test]
+    fn material_units_use_geant4_internal_values() {
+        // Geant4 stores 1 g/cm3 as 6.24150907e18 (MeV*ns^2/mm^5).
+        assert!(close(apply_unit(1.0, "g/cm3"), 6.241509074e18));
+        assert!(close(apply_unit(1000.0, "kg/m3"), apply_unit(1.0, "g/cm3")));
+        assert!(close(in_unit(apply_unit(1.032, "g/cm3"), "g/cm3"), 1.032));
+        assert!(close(apply_unit(2.5, "eV"), 2.5e-6));
+        assert_eq!(apply_unit(293.15, "K"), 293.15);
+        assert!(close(apply_unit(1.0, "atmosphere"), 101325.0 * PASCAL));
+        assert!(close(apply_unit(1.0, "bar"), 6.241509074e8));
+        assert!(close(apply_unit(1.01, "g/mole"), 1.01 * GRAM / MOLE));
+    }
+
+    #[test]
+    fn unit_attributes_report_their_geant4_category() {
+        assert_eq!(unit_kind("g/cm3"), Some(UnitKind::Density));
+        assert_eq!(unit_kind("eV"), Some(UnitKind::Energy));
+        assert_eq!(unit_kind("kelvin"), Some(UnitKind::Temperature));
+        assert_eq!(unit_kind("atm"), Some(UnitKind::Pressure));
+        assert_eq!(unit_kind("g/mole"), Some(UnitKind::MolarMass));
+        assert_eq!(unit_kind("cm"), Some(UnitKind::Length));
+        assert_eq!(UnitKind::Density.category(), "Volumic Mass");
     }
 }
@@ -0,0 +1,1999 @@
+//! Mesh booleans by surface arrangement, with exact predicates.
+//!
+//! The alternative to the BSP booleans in [`super::csg`]. Those classify
+//! every polygon against planes with a thickness of 1e-6 of the geometry's
+//! size, which is what turns a subtraction whose tool shares a face with the
+//! target into slivers, holes or nothing at all, and they re-split the whole
+//! result at every step of a boolean chain. Here:
+//!
+//! 1. Both operands are welded into one pool of vertices, so a vertex the
+//!    tool shares with the target is the same vertex in both.
+//! 2. Every pair of facets from different operands whose boxes touch is
+//!    intersected with the [`orient3d`] / [`orient2d`] predicates. Each
+//!    point where the surfaces meet gets a symbolic name -- an existing
+//!    vertex, an edge crossing a facet, or two edges crossing -- so every
+//!    facet around the point finds the same one, and an edge gets the same
+//!    points whichever facet it is seen from. Facets in one plane are
+//!    intersected in 2D and remembered as coplanar partners.
+//! 3. Each facet that was hit is cut along the intersection segments: the
+//!    faces of the planar graph they make with its sides are traced, ear
+//!    clipped and flipped to better shapes, cut loops that touch nothing
+//!    else bridged in as holes.
+//! 4. The pieces are grouped into patches that do not cross the intersection
+//!    curves. A patch that lies on a coplanar partner is `Same` or `Opposite`
+//!    by the two normals; any other patch is inside or outside the other
+//!    operand by the generalized winding number (Jacobson et al. 2013) of one
+//!    point, which stays meaningful if that operand has cracks.
+//! 5. The operation keeps patches by label, as in Zhou et al., "Mesh
+//!    Arrangements for Solid Geometry" (2016): a face the tool shares with
+//!    the target is kept once or removed, never split into slivers.
+//!
+//! Closed, manifold operands give a closed, manifold result: both sides of
+//! every edge are cut at the same points, and whole patches are kept or
+//! dropped together. The predicates are exact on the values they are given,
+//! but the input is `f32` and the output is too, so "on the plane" and "the
+//! same point" are decided at the rounding of the coordinates involved, the
+//! tolerance of [`super::integrity`]: a vertex within it of a facet's plane
+//! is on the plane, of a facet's side on the side, and a new point within a
+//! few of it of an old one is that point. Whatever rounding still leaves --
+//! triangles flat at `f32` precision -- is flipped or collapsed away before
+//! the output is written. Chains of booleans on surfaces that coincide
+//! exactly come out closed all but about once in several thousand; the
+//! [`BooleanBackend`](super::csg::BooleanBackend) checks every result and
+//! repairs that one, or falls back to the BSP boolean with a warning.
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+use tracing::debug;
+
+use super::integrity::{coincide, rounding};
+use super::predicates::{orient2d, orient3d};
+use super::types::TriangleMesh;
+
+/// Subtract `b` from `a`.
+pub fn subtract(a: &TriangleMesh, b: &TriangleMesh) -> TriangleMesh {
+    boolean(a, b, Operation::Difference)
+}
+
+/// The union of `a` and `b`.
+pub fn union(a: &TriangleMesh, b: &TriangleMesh) -> TriangleMesh {
+    boolean(a, b, Operation::Union)
+}
+
+/// The intersection of `a` and `b`.
+pub fn intersect(a: &TriangleMesh, b: &TriangleMesh) -> TriangleMesh {
+    boolean(a, b, Operation::Intersection)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+fn boolean(a: &TriangleMesh, b: &TriangleMesh, operation: Operation) -> TriangleMesh {
+    let mut arrangement = Arrangement::new(a, b);
+    let [empty_a, empty_b] = arrangement.empty_operands();
+    // Same conventions as the BSP booleans for an operand with no facets.
+    match operation {
+        Operation::Difference if empty_a || empty_b => return a.clone(),
+        Operation::Union if empty_a => return b.clone(),
+        Operation::Union if empty_b => return a.clone(),
+        Operation::Intersection if empty_a || empty_b => return TriangleMesh::new(),
+        _ => {}
+    }
+
+    arrangement.intersect_facets();
+    let pieces = arrangement.split_facets();
+    let labels = arrangement.classify(&pieces);
+    let result = arrangement.assemble(&pieces, &labels, operation);
+    debug!(
+        "Arrangement boolean: {} + {} facets, {} pieces, result has {} triangles",
+        arrangement.split,
+        arrangement.facets.len() - arrangement.split,
+        pieces.len(),
+        result.triangle_count()
+    );
+    result
+}
+
+// ─── Symbolic points ─────────────────────────────────────────────────────────
+
+type Edge = (u32, u32);
+
+fn edge(a: u32, b: u32) -> Edge {
+    (a.min(b), a.max(b))
+}
+
+/// What a point of the arrangement is, independent of the facet it was
+/// found from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    /// An input vertex.
+    Vertex(u32),
+    /// An edge passing through the inside of a facet.
+    EdgeFacet(Edge, usize),
+    /// Two edges crossing, lower edge first.
+    EdgeEdge(Edge, Edge),
+}
+
+fn edge_edge(e: Edge, f: Edge) -> Key {
+    Key::EdgeEdge(e.min(f), e.max(f))
+}
+
+/// Where a point lies on a facet. Side `k` runs from corner `k` to corner
+/// `k + 1`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Loc {
+    Corner(usize),
+    Side(usize),
+    Inside,
+}
+
+impl Loc {
+    fn on_side(self, k: usize) -> bool {
+        match self {
+            Loc::Corner(c) => c == k || c == (k + 1) % 3,
+            Loc::Side(s) => s == k,
+            Loc::Inside => false,
+        }
+    }
+}
+
+/// Where a point lies on a segment.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Along {
+    Start,
+    End,
+    Within,
+}
+
+/// A point shared by two facets, with where it lies on each.
+#[derive(Clone, Copy)]
+struct Hit {
+    point: u32,
+    on: [Loc; 2],
+}
+
+// ─── Arrangement ─────────────────────────────────────────────────────────────
+
+struct Facet {
+    corners: [u32; 3],
+    normals: [[f64; 3]; 3],
+    operand: usize,
+    /// Approximate face normal, for choosing projections and ordering.
+    normal: [f64; 3],
+    /// The coordinate dropped to project the facet into 2D.
+    axis: usize,
+    lo: [f64; 3],
+    hi: [f64; 3],
+}
+
+#[derive(Default)]
+struct Cuts {
+    inside: Vec<u32>,
+    segments: Vec<(u32, u32)>,
+    coplanar: Vec<usize>,
+}
+
+/// A triangle of the result before selection: corners and source facet.
+struct Piece {
+    corners: [u32; 3],
+    facet: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Label {
+    Inside,
+    Outside,
+    /// On a facet of the other operand that faces the same way.
+    Same,
+    /// On a facet of the other operand that faces the other way.
+    Opposite,
+}
+
+/// New points within this many roundings of an existing point, in every
+/// coordinate, are welded onto it. Points made at shallow intersections are
+/// a few `f32` ulps out, and less than one leaves needles across seams.
+const WELD_ROUNDINGS: f64 = 4.0;
+
+/// A flat triangle whose shortest edge is at most this fraction of its
+/// longest is a needle, collapsed rather than flipped.
+const NEEDLE: f64 = 0.01;
+
+/// Passes of [`Arrangement::remove_slivers`]; one nearly always does.
+const MAX_CLEANUP_ROUNDS: usize = 8;
+
+/// How much better shaped a flip must leave the worse of two triangles
+/// for [`FacetGraph::improve`] to make it.
+const IMPROVEMENT: f64 = 1e-6;
+
+struct Arrangement {
+    points: Vec<[f64; 3]>,
+    keys: HashMap<Key, u32>,
+    facets: Vec<Facet>,
+    /// Facets before this index belong to the first operand.
+    split: usize,
+    cuts: Vec<Cuts>,
+    edge_points: HashMap<Edge, Vec<u32>>,
+    /// Intersection segments that run along an edge, which the flood fill
+    /// in [`Arrangement::classify`] must not cross.
+    edge_cuts: Vec<(Edge, u32, u32)>,
+    cut_edges: HashSet<Edge>,
+    /// Spatial hash over `points`, to weld new points onto old ones.
+    cells: HashMap<[i64; 3], Vec<u32>>,
+    /// The weld at the operands' extent, the most any new point is welded
+    /// across, and the size of the cells in `cells`.
+    weld: f64,
+}
+
+impl Arrangement {
+    fn new(a: &TriangleMesh, b: &TriangleMesh) -> Self {
+        let mut arrangement = Arrangement {
+            points: Vec::new(),
+            keys: HashMap::new(),
+            facets: Vec::new(),
+            split: 0,
+            cuts: Vec::new(),
+            edge_points: HashMap::new(),
+            edge_cuts: Vec::new(),
+            cut_edges: HashSet::new(),
+            cells: HashMap::new(),
+            weld: 1.0,
+        };
+
+        // Weld both operands into one pool, with the per-coordinate `f32`
+        // tolerance of the integrity checks: primitives compute seam
+        // vertices twice, and the two copies may differ in the last bit.
+        let extent = a
+            .positions
+            .iter()
+            .chain(&b.positions)
+            .map(|v| (*v as f64).abs())
+            .filter(|v| v.is_finite())
+            .fold(0.0, f64::max);
+        let cell_size = rounding(extent);
+        let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut welded: Vec<[f32; 3]> = Vec::new();
+        let mut weld = |p: [f32; 3]| -> u32 {
+            let cell = p.map(|c| (c as f64 / cell_size).floor() as i64);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let key = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                        for &w in cells.get(&key).into_iter().flatten() {
+                            if coincide(p, welded[w as usize]) {
+                                return w;
+                            }
+                        }
+                    }
+                }
+            }
+            let w = welded.len() as u32;
+            welded.push(p);
+            cells.entry(cell).or_default().push(w);
+            w
+        };
+
+        for (operand, mesh) in [a, b].into_iter().enumerate() {
+            if operand == 1 {
+                arrangement.split = arrangement.facets.len();
+            }
+            let ids: Vec<Option<u32>> = (0..mesh.vertex_count())
+                .map(|v| {
+                    let p = read3(&mesh.positions, v);
+                    p.iter().all(|c| c.is_finite()).then(|| weld(p))
+                })
+                .collect();
+            for tri in mesh.indices.chunks_exact(3) {
+                let Some(corners) = tri
+                    .iter()
+                    .map(|&v| ids.get(v as usize).copied().flatten())
+                    .collect::<Option<Vec<u32>>>()
+                else {
+                    continue;
+                };
+                let corners = [corners[0], corners[1], corners[2]];
+                let normals = [0, 1, 2].map(|k| {
+                    let n = read3(&mesh.normals, tri[k] as usize).map(|c| c as f64);
+                    if n.iter().all(|c| c.is_finite()) {
+                        n
+                    } else {
+                        [0.0; 3]
+                    }
+                });
+                arrangement.facets.push(Facet {
+                    corners,
+                    normals,
+                    operand,
+                    normal: [0.0; 3],
+                    axis: 0,
+                    lo: [0.0; 3],
+                    hi: [0.0; 3],
+                });
+            }
+        }
+        arrangement.points = welded.iter().map(|p| p.map(|c| c as f64)).collect();
+        arrangement.weld = WELD_ROUNDINGS * cell_size;
+        for id in 0..arrangement.points.len() as u32 {
+            let cell = arrangement.cell(arrangement.points[id as usize]);
+            arrangement.cells.entry(cell).or_default().push(id);
+        }
+
+        // Drop facets that collapsed in the weld or are exactly flat, then
+        // fill in the geometry of the rest.
+        let points = &arrangement.points;
+        let mut kept = Vec::with_capacity(arrangement.facets.len());
+        let mut split = arrangement.split;
+        for (f, mut facet) in std::mem::take(&mut arrangement.facets)
+            .into_iter()
+            .enumerate()
+        {
+            let [a, b, c] = facet.corners;
+            let [pa, pb, pc] = facet.corners.map(|v| points[v as usize]);
+            let flat = a == b
+                || b == c
+                || c == a
+                || (0..3).all(|axis| {
+                    orient2d(project(pa, axis), project(pb, axis), project(pc, axis)) == 0.0
+                });
+            if flat {
+                if f < arrangement.split {
+                    split -= 1;
+                }
+                continue;
+            }
+            facet.normal = cross(sub(pb, pa), sub(pc, pa));
+            facet.axis = dominant_axis(facet.normal);
+            for k in 0..3 {
+                facet.lo[k] = pa[k].min(pb[k]).min(pc[k]);
+                facet.hi[k] = pa[k].max(pb[k]).max(pc[k]);
+            }
+            for n in &mut facet.normals {
+                if norm(*n) == 0.0 {
+                    *n = normalize(facet.normal);
+                }
+            }
+            kept.push(facet);
+        }
+        arrangement.facets = kept;
+        arrangement.split = split;
+        arrangement.cuts = (0..arrangement.facets.len())
+            .map(|_| Cuts::default())
+            .collect();
+        arrangement
+    }
+
+    fn empty_operands(&self) -> [bool; 2] {
+        [self.split == 0, self.split == self.facets.len()]
+    }
+
+    fn operand_facets(&self, operand: usize) -> std::ops::Range<usize> {
+        if operand == 0 {
+            0..self.split
+        } else {
+            self.split..self.facets.len()
+        }
+    }
+
+    fn corner_points(&self, f: usize) -> [[f64; 3]; 3] {
+        self.facets[f].corners.map(|v| self.points[v as usize])
+    }
+
+    fn project(&self, f: usize, p: [f64; 3]) -> [f64; 2] {
+        project(p, self.facets[f].axis)
+    }
+
+    /// The point for `key`, created at `position` if it is new.
+    ///
+    /// A new point within [`WELD_ROUNDINGS`] roundings of an existing one is
+    /// that point, rounding taken at the `f32` precision of the edges and
+    /// facet it was made from: the error in a point made at an intersection
+    /// scales with those, not with its own coordinates, and two points that
+    /// close only leave slivers thinner than the output can represent.
+    fn intern(&mut self, key: Key, position: impl FnOnce(&Self) -> [f64; 3]) -> u32 {
+        if let Key::Vertex(v) = key {
+            return v;
+        }
+        if let Some(&id) = self.keys.get(&key) {
+            return id;
+        }
+        let p = position(self);
+        let made_from: Vec<u32> = match key {
+            Key::Vertex(v) => vec![v],
+            Key::EdgeFacet((a, b), f) => [a, b].into_iter().chain(self.facets[f].corners).collect(),
+            Key::EdgeEdge((a, b), (c, d)) => vec![a, b, c, d],
+        };
+        let weld = WELD_ROUNDINGS * self.rounding_near(&made_from);
+        let id = self.nearby(p, weld).unwrap_or_else(|| {
+            let id = self.points.len() as u32;
+            self.points.push(p);
+            self.cells.entry(self.cell(p)).or_default().push(id);
+            id
+        });
+        self.keys.insert(key, id);
+        id
+    }
+
+    fn cell(&self, p: [f64; 3]) -> [i64; 3] {
+        p.map(|c| (c / self.weld).floor() as i64)
+    }
+
+    /// Rounding at the `f32` precision of the largest coordinate of points
+    /// `ids`.
+    fn rounding_near(&self, ids: &[u32]) -> f64 {
+        let magnitude = ids
+            .iter()
+            .flat_map(|&id| self.points[id as usize])
+            .fold(0.0f64, |m, c| m.max(c.abs()));
+        rounding(magnitude)
+    }
+
+    /// The closest existing point within `weld` of `p` in every coordinate.
+    fn nearby(&self, p: [f64; 3], weld: f64) -> Option<u32> {
+        let cell = self.cell(p);
+        let mut best: Option<(f64, u32)> = None;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let key = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    for &w in self.cells.get(&key).into_iter().flatten() {
+                        let q = self.points[w as usize];
+                        let distance = norm(sub(p, q));
+                        if (0..3).all(|k| (p[k] - q[k]).abs() <= weld)
+                            && best.is_none_or(|(d, _)| distance < d)
+                        {
+                            best = Some((distance, w));
+                        }
+                    }
+                }
+            }
+        }
+        best.map(|(_, w)| w)
+    }
+
+    // ── Step 2: facet-facet intersection ────────────────────────────────────
+
+    /// Pairs of facets, first operand first, whose bounding boxes touch.
+    fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut order: Vec<usize> = (0..self.facets.len()).collect();
+        order.sort_by(|&f, &g| {
+            self.facets[f].lo[0]
+                .total_cmp(&self.facets[g].lo[0])
+                .then(f.cmp(&g))
+        });
+        let mut active: [Vec<usize>; 2] = [Vec::new(), Vec::new()];
+        let mut pairs = Vec::new();
+        for f in order {
+            let facet = &self.facets[f];
+            let other = 1 - facet.operand;
+            active[other].retain(|&g| self.facets[g].hi[0] >= facet.lo[0]);
+            for &g in &active[other] {
+                let near = &self.facets[g];
+                if (1..3).all(|k| near.lo[k] <= facet.hi[k] && facet.lo[k] <= near.hi[k]) {
+                    pairs.push(if facet.operand == 0 { (f, g) } else { (g, f) });
+                }
+            }
+            active[facet.operand].push(f);
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    fn intersect_facets(&mut self) {
+        for (t, s) in self.candidate_pairs() {
+            self.intersect_pair(t, s);
+        }
+
+        // Order the points on every edge from its lower vertex to its higher.
+        let points = &self.points;
+        for (&(a, b), list) in self.edge_points.iter_mut() {
+            let (pa, pb) = (points[a as usize], points[b as usize]);
+            let dir = sub(pb, pa);
+            list.retain(|&p| p != a && p != b);
+            list.sort_by(|&p, &q| {
+                dot(sub(points[p as usize], pa), dir)
+                    .total_cmp(&dot(sub(points[q as usize], pa), dir))
+                    .then(p.cmp(&q))
+            });
+            list.dedup();
+        }
+
+        for &(e, p, q) in &self.edge_cuts {
+            let mut chain = vec![e.0];
+            chain.extend(self.edge_points.get(&e).into_iter().flatten().copied());
+            chain.push(e.1);
+            let (Some(i), Some(j)) = (
+                chain.iter().position(|&v| v == p),
+                chain.iter().position(|&v| v == q),
+            ) else {
+                continue;
+            };
+            for w in chain[i.min(j)..=i.max(j)].windows(2) {
+                self.cut_edges.insert(edge(w[0], w[1]));
+            }
+        }
+    }
+
+    fn intersect_pair(&mut self, t: usize, s: usize) {
+        let tp = self.corner_points(t);
+        let sp = self.corner_points(s);
+        let t_signs = tp.map(|p| self.side(s, p));
+        if one_side(t_signs) {
+            return;
+        }
+        let s_signs = sp.map(|p| self.side(t, p));
+        if one_side(s_signs) {
+            return;
+        }
+
+        if t_signs.iter().all(|&o| o == 0.0) {
+            self.intersect_coplanar(t, s);
+            return;
+        }
+
+        let mut hits = Vec::new();
+        self.edges_through(t, s, t_signs, false, &mut hits);
+        self.edges_through(s, t, s_signs, true, &mut hits);
+        let mut unique: Vec<Hit> = Vec::new();
+        for hit in hits {
+            if !unique.iter().any(|u| u.point == hit.point) {
+                unique.push(hit);
+            }
+        }
+        for hit in &unique {
+            self.register(t, hit.point, hit.on[0]);
+            self.register(s, hit.point, hit.on[1]);
+        }
+        if unique.len() < 2 {
+            return;
+        }
+
+        // The hits all lie on the line where the two planes meet; the
+        // intersection runs between the outermost two.
+        let direction = cross(self.facets[t].normal, self.facets[s].normal);
+        let along = |h: &Hit| dot(self.points[h.point as usize], direction);
+        let first = *unique
+            .iter()
+            .min_by(|a, b| along(a).total_cmp(&along(b)))
+            .unwrap();
+        let last = *unique
+            .iter()
+            .max_by(|a, b| along(a).total_cmp(&along(b)))
+            .unwrap();
+        self.add_segment(t, (first.point, first.on[0]), (last.point, last.on[0]));
+        self.add_segment(s, (first.point, first.on[1]), (last.point, last.on[1]));
+    }
+
+    /// Where the edges of facet `f` meet facet `g`, whose plane the corners
+    /// of `f` lie on the sides given by `signs`.
+    fn edges_through(
+        &mut self,
+        f: usize,
+        g: usize,
+        signs: [f64; 3],
+        swapped: bool,
+        hits: &mut Vec<Hit>,
+    ) {
+        let gp = self.corner_points(g);
+        let gc = self.facets[g].corners;
+        let mut push = |on_f: Loc, on_g: Loc, point: u32| {
+            hits.push(Hit {
+                point,
+                on: if swapped { [on_g, on_f] } else { [on_f, on_g] },
+            })
+        };
+
+        for k in 0..3 {
+            let (i, j) = (k, (k + 1) % 3);
+            let (u, v) = (self.facets[f].corners[i], self.facets[f].corners[j]);
+            let (su, sv) = (signs[i], signs[j]);
+            if su == 0.0 && sv == 0.0 {
+                for (point, along, on_g) in self.clip_segment(u, v, g) {
+                    let on_f = match along {
+                        Along::Start => Loc::Corner(i),
+                        Along::End => Loc::Corner(j),
+                        Along::Within => Loc::Side(k),
+                    };
+                    push(on_f, on_g, point);
+                }
+                continue;
+            }
+            if su == 0.0 || sv == 0.0 {
+                let (corner, w) = if su == 0.0 { (i, u) } else { (j, v) };
+                if let Some(on_g) = self.locate(g, w) {
+                    push(Loc::Corner(corner), on_g, w);
+                }
+                continue;
+            }
+            if (su > 0.0) == (sv > 0.0) {
+                continue;
+            }
+
+            // The edge crosses the plane of `g` strictly between its ends;
+            // the three tetrahedra it makes with the sides of `g` say where.
+            let (pu, pv) = (self.points[u as usize], self.points[v as usize]);
+            let sides = [0, 1, 2].map(|m| orient3d(pu, pv, gp[m], gp[(m + 1) % 3]));
+            if sides.iter().any(|&o| o > 0.0) && sides.iter().any(|&o| o < 0.0) {
+                continue;
+            }
+            let zeros: Vec<usize> = (0..3).filter(|&m| sides[m] == 0.0).collect();
+            let crossing = |this: &Self| plane_crossing(this.corner_points(g), pu, pv);
+            let (key, on_g) = match zeros[..] {
+                [] => (Key::EdgeFacet(edge(u, v), g), Loc::Inside),
+                [m] => (
+                    edge_edge(edge(u, v), edge(gc[m], gc[(m + 1) % 3])),
+                    Loc::Side(m),
+                ),
+                [0, 1] => (Key::Vertex(gc[1]), Loc::Corner(1)),
+                [1, 2] => (Key::Vertex(gc[2]), Loc::Corner(2)),
+                [0, 2] => (Key::Vertex(gc[0]), Loc::Corner(0)),
+                _ => continue,
+            };
+            let point = self.intern(key, crossing);
+            push(Loc::Side(k), on_g, point);
+        }
+    }
+
+    /// Both facets lie in one plane: each one's sides, clipped to the other,
+    /// cut it.
+    fn intersect_coplanar(&mut self, t: usize, s: usize) {
+        self.cuts[t].coplanar.push(s);
+        self.cuts[s].coplanar.push(t);
+        for (f, g) in [(t, s), (s, t)] {
+            for k in 0..3 {
+                let (i, j) = (k, (k + 1) % 3);
+                let (u, v) = (self.facets[f].corners[i], self.facets[f].corners[j]);
+                let mut ends: Vec<(u32, Loc, Loc)> = Vec::new();
+                for (point, along, on_g) in self.clip_segment(u, v, g) {
+                    let on_f = match along {
+                        Along::Start => Loc::Corner(i),
+                        Along::End => Loc::Corner(j),
+                        Along::Within => Loc::Side(k),
+                    };
+                    self.register(f, point, on_f);
+                    self.register(g, point, on_g);
+                    if !ends.iter().any(|e| e.0 == point) {
+                        ends.push((point, on_f, on_g));
+                    }
+                }
+                if ends.len() < 2 {
+                    continue;
+                }
+                let (pu, pv) = (self.points[u as usize], self.points[v as usize]);
+                let dir = sub(pv, pu);
+                let along = |e: &(u32, Loc, Loc)| dot(sub(self.points[e.0 as usize], pu), dir);
+                let first = *ends
+                    .iter()
+                    .min_by(|a, b| along(a).total_cmp(&along(b)))
+                    .unwrap();
+                let last = *ends
+                    .iter()
+                    .max_by(|a, b| along(a).total_cmp(&along(b)))
+                    .unwrap();
+                self.add_segment(f, (first.0, first.1), (last.0, last.1));
+                self.add_segment(g, (first.0, first.2), (last.0, last.2));
+            }
+        }
+    }
+
+    /// Which side of facet `f`'s plane `p` is on: exactly, unless `p` is
+    /// within rounding of the plane, when it is on it.
+    fn side(&self, f: usize, p: [f64; 3]) -> f64 {
+        let corners = self.corner_points(f);
+        let n = normalize(self.facets[f].normal);
+        let magnitude = corners
+            .iter()
+            .chain([&p])
+            .flatten()
+            .fold(0.0f64, |m, c| m.max(c.abs()));
+        if dot(sub(p, corners[0]), n).abs() <= rounding(magnitude) {
+            return 0.0;
+        }
+        orient3d(corners[0], corners[1], corners[2], p)
+    }
+
+    /// The points where segment `u`-`v`, which lies in the plane of facet
+    /// `g`, meets the closed facet. Decided in 2D, exactly, since the
+    /// segment's ends and the facet's corners are all input vertices.
+    fn clip_segment(&mut self, u: u32, v: u32, g: usize) -> Vec<(u32, Along, Loc)> {
+        let mut out = Vec::new();
+        for (w, along) in [(u, Along::Start), (v, Along::End)] {
+            if let Some(loc) = self.locate(g, w) {
+                out.push((w, along, loc));
+            }
+        }
+
+        let corners = self.facets[g].corners;
+        let (pu, pv) = (
+            self.project(g, self.points[u as usize]),
+            self.project(g, self.points[v as usize]),
+        );
+        let q = corners.map(|c| self.project(g, self.points[c as usize]));
+        for m in 0..3 {
+            let c = corners[m];
+            if c != u && c != v && orient2d(pu, pv, q[m]) == 0.0 && strictly_between(pu, pv, q[m]) {
+                out.push((c, Along::Within, Loc::Corner(m)));
+            }
+        }
+        for m in 0..3 {
+            let n = (m + 1) % 3;
+            let (a, b) = (corners[m], corners[n]);
+            if a == u || a == v || b == u || b == v {
+                continue;
+            }
+            let (o1, o2) = (orient2d(pu, pv, q[m]), orient2d(pu, pv, q[n]));
+            let (o3, o4) = (orient2d(q[m], q[n], pu), orient2d(q[m], q[n], pv));
+            if opposite(o1, o2) && opposite(o3, o4) {
+                let key = edge_edge(edge(u, v), edge(a, b));
+                let point = self.intern(key, |this| {
+                    let t = (o3 / (o3 - o4)).clamp(0.0, 1.0);
+                    lerp(this.points[u as usize], this.points[v as usize], t)
+                });
+                out.push((point, Along::Within, Loc::Side(m)));
+            }
+        }
+        out
+    }
+
+    /// Where input vertex `w`, which lies in the plane of facet `g`, is on
+    /// the closed facet, if it is on it at all.
+    fn locate(&self, g: usize, w: u32) -> Option<Loc> {
+        let corners = self.facets[g].corners;
+        if let Some(c) = corners.iter().position(|&c| c == w) {
+            return Some(Loc::Corner(c));
+        }
+        let q = corners.map(|c| self.project(g, self.points[c as usize]));
+        let p = self.project(g, self.points[w as usize]);
+        let tolerance = self.rounding_near(&[corners[0], corners[1], corners[2], w]);
+        let orientation = orient2d(q[0], q[1], q[2]).signum();
+        let sides = [0, 1, 2].map(|m| {
+            let o = orientation * orient2d(q[m], q[(m + 1) % 3], p);
+            let length = norm([
+                q[(m + 1) % 3][0] - q[m][0],
+                q[(m + 1) % 3][1] - q[m][1],
+                0.0,
+            ]);
+            if o.abs() <= tolerance * length {
+                0.0
+            } else {
+                o
+            }
+        });
+        if sides.iter().any(|&o| o < 0.0) {
+            return None;
+        }
+        match (0..3).filter(|&m| sides[m] == 0.0).collect::<Vec<_>>()[..] {
+            [] => Some(Loc::Inside),
+            [m] => Some(Loc::Side(m)),
+            [0, 1] => Some(Loc::Corner(1)),
+            [1, 2] => Some(Loc::Corner(2)),
+            [0, 2] => Some(Loc::Corner(0)),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self, f: usize, point: u32, loc: Loc) {
+        let corners = self.facets[f].corners;
+        if corners.contains(&point) {
+            return;
+        }
+        match loc {
+            Loc::Corner(_) => {}
+            Loc::Side(k) => self
+                .edge_points
+                .entry(edge(corners[k], corners[(k + 1) % 3]))
+                .or_default()
+                .push(point),
+            Loc::Inside => {
+                if !self.cuts[f].inside.contains(&point) {
+                    self.cuts[f].inside.push(point);
+                }
+            }
+        }
+    }
+
+    fn add_segment(&mut self, f: usize, (p, lp): (u32, Loc), (q, lq): (u32, Loc)) {
+        if p == q {
+            return;
+        }
+        let corners = self.facets[f].corners;
+        // A point can reach here through another facet's test; where it is
+        // a corner of this one, that is what counts.
+        let corner_or = |id: u32, loc: Loc| match corners.iter().position(|&c| c == id) {
+            Some(i) => Loc::Corner(i),
+            None => loc,
+        };
+        let (lp, lq) = (corner_or(p, lp), corner_or(q, lq));
+        if let Some(k) = (0..3).find(|&k| lp.on_side(k) && lq.on_side(k)) {
+            let e = edge(corners[k], corners[(k + 1) % 3]);
+            self.edge_cuts.push((e, p, q));
+        } else {
+            self.cuts[f].segments.push((p, q));
+        }
+    }
+
+    // ── Step 3: re-triangulation ────────────────────────────────────────────
+
+    fn split_facets(&mut self) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        for f in 0..self.facets.len() {
+            let corners = self.facets[f].corners;
+            let sides: [Vec<u32>; 3] = [0, 1, 2].map(|k| {
+                let (a, b) = (corners[k], corners[(k + 1) % 3]);
+                let mut list = self
+                    .edge_points
+                    .get(&edge(a, b))
+                    .cloned()
+                    .unwrap_or_default();
+                if a > b {
+                    list.reverse();
+                }
+                list
+            });
+            let cuts = &self.cuts[f];
+            if sides.iter().all(|s| s.is_empty())
+                && cuts.inside.is_empty()
+                && cuts.segments.is_empty()
+            {
+                pieces.push(Piece { corners, facet: f });
+                continue;
+            }
+
+            // A point welded onto two sides closes off a spike round the
+            // corner between them, thinner than rounding. The spike goes,
+            // and its points become that one.
+            let mut outline: Vec<u32> = (0..3)
+                .flat_map(|k| std::iter::once(corners[k]).chain(sides[k].iter().copied()))
+                .collect();
+            let mut alias: HashMap<u32, u32> = HashMap::new();
+            while let Some((i, j)) = first_repeat(&outline) {
+                let point = outline[i];
+                let (kept, spike): (Vec<u32>, Vec<u32>) = if j - i <= outline.len() - j + i {
+                    let kept = [&outline[..=i], &outline[j + 1..]].concat();
+                    (kept, outline[i + 1..j].to_vec())
+                } else {
+                    let spike = [&outline[j + 1..], &outline[..i]].concat();
+                    (outline[i..j].to_vec(), spike)
+                };
+                for id in spike {
+                    alias.insert(id, point);
+                }
+                outline = kept;
+            }
+            if outline.len() < 3 {
+                continue;
+            }
+            let resolve = |mut id: u32| {
+                for _ in 0..alias.len() {
+                    match alias.get(&id) {
+                        Some(&to) => id = to,
+                        None => break,
+                    }
+                }
+                id
+            };
+
+            let mut graph = FacetGraph::default();
+            let ring: Vec<usize> = outline
+                .iter()
+                .map(|&id| graph.vertex(id, self.facet_2d(f, self.points[id as usize])))
+                .collect();
+            let mut segments = Vec::new();
+            for &(p, q) in &self.cuts[f].segments {
+                let (p, q) = (resolve(p), resolve(q));
+                let a = graph.vertex(p, self.facet_2d(f, self.points[p as usize]));
+                let b = graph.vertex(q, self.facet_2d(f, self.points[q as usize]));
+                segments.push((a, b));
+            }
+
+            let (tris, cuts) = graph.triangulate(&ring, &segments);
+            for (a, b) in cuts {
+                self.cut_edges.insert(edge(graph.ids[a], graph.ids[b]));
+            }
+            for tri in tris {
+                pieces.push(Piece {
+                    corners: tri.map(|i| graph.ids[i]),
+                    facet: f,
+                });
+            }
+        }
+        pieces
+    }
+
+    /// Facet `f`'s projection, mirrored if need be so the facet runs
+    /// counter-clockwise.
+    fn facet_2d(&self, f: usize, p: [f64; 3]) -> [f64; 2] {
+        let facet = &self.facets[f];
+        let q = project(p, facet.axis);
+        if facet.normal[facet.axis] < 0.0 {
+            [q[1], q[0]]
+        } else {
+            q
+        }
+    }
+
+    // ── Step 4: classification ──────────────────────────────────────────────
+
+    fn classify(&self, pieces: &[Piece]) -> Vec<Label> {
+        let status: Vec<Option<Label>> = pieces.iter().map(|p| self.coplanar_status(p)).collect();
+
+        // Patches: pieces of one operand joined across edges that are not
+        // cut, with the same coplanar status.
+        let mut parent: Vec<usize> = (0..pieces.len()).collect();
+        let mut by_edge: HashMap<(Edge, usize), Vec<usize>> = HashMap::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            let operand = self.facets[piece.facet].operand;
+            let c = piece.corners;
+            for (a, b) in [(c[0], c[1]), (c[1], c[2]), (c[2], c[0])] {
+                let e = edge(a, b);
+                if !self.cut_edges.contains(&e) {
+                    by_edge.entry((e, operand)).or_default().push(i);
+                }
+            }
+        }
+        let mut keys: Vec<&(Edge, usize)> = by_edge.keys().collect();
+        keys.sort_unstable();
+        for key in keys {
+            let around = &by_edge[key];
+            for &j in &around[1..] {
+                let i = around[0];
+                if status[i] == status[j] {
+                    let (ri, rj) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                    parent[ri.max(rj)] = ri.min(rj);
+                }
+            }
+        }
+
+        // One sample per patch: its largest piece.
+        let mut sample: HashMap<usize, (usize, f64)> = HashMap::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            let root = find_root(&mut parent, i);
+            let [a, b, c] = piece.corners.map(|v| self.points[v as usize]);
+            let area = norm(cross(sub(b, a), sub(c, a)));
+            let entry = sample.entry(root).or_insert((i, area));
+            if area > entry.1 {
+                *entry = (i, area);
+            }
+        }
+        let mut patch_label: HashMap<usize, Label> = HashMap::new();
+        for (&root, &(i, _)) in &sample {
+            let label = status[i].unwrap_or_else(|| {
+                let piece = &pieces[i];
+                let [a, b, c] = piece.corners.map(|v| self.points[v as usize]);
+                let centre = [0, 1, 2].map(|k| (a[k] + b[k] + c[k]) / 3.0);
+                let other = 1 - self.facets[piece.facet].operand;
+                if self.winding_number(other, centre) > 0.5 {
+                    Label::Inside
+                } else {
+                    Label::Outside
+                }
+            });
+            patch_label.insert(root, label);
+        }
+        (0..pieces.len())
+            .map(|i| patch_label[&find_root(&mut parent, i)])
+            .collect()
+    }
+
+    /// `Same` or `Opposite` when the piece lies on a coplanar facet of the
+    /// other operand.
+    fn coplanar_status(&self, piece: &Piece) -> Option<Label> {
+        let f = piece.facet;
+        let partners = &self.cuts[f].coplanar;
+        if partners.is_empty() {
+            return None;
+        }
+        let [a, b, c] = piece.corners.map(|v| self.points[v as usize]);
+        let centre = [0, 1, 2].map(|k| (a[k] + b[k] + c[k]) / 3.0);
+        partners.iter().find_map(|&g| {
+            let q = self.corner_points(g).map(|p| self.project(g, p));
+            let p = self.project(g, centre);
+            let orientation = orient2d(q[0], q[1], q[2]).signum();
+            let inside = (0..3).all(|m| orientation * orient2d(q[m], q[(m + 1) % 3], p) > 0.0);
+            inside.then(|| {
+                if dot(self.facets[f].normal, self.facets[g].normal) > 0.0 {
+                    Label::Same
+                } else {
+                    Label::Opposite
+                }
+            })
+        })
+    }
+
+    /// Generalized winding number of `operand` at `p`: 1 inside a closed,
+    /// outward-facing surface, 0 outside, and in between near holes.
+    fn winding_number(&self, operand: usize, p: [f64; 3]) -> f64 {
+        let range = self.operand_facets(operand);
+        let outside_box = (0..3).any(|k| {
+            range.clone().all(|f| p[k] < self.facets[f].lo[k])
+                || range.clone().all(|f| p[k] > self.facets[f].hi[k])
+        });
+        if outside_box {
+            return 0.0;
+        }
+        let mut total = 0.0;
+        for f in range {
+            let [a, b, c] = self.corner_points(f).map(|q| sub(q, p));
+            let (la, lb, lc) = (norm(a), norm(b), norm(c));
+            let det = dot(a, cross(b, c));
+            let den = la * lb * lc + dot(a, b) * lc + dot(b, c) * la + dot(c, a) * lb;
+            total += 2.0 * det.atan2(den);
+        }
+        total / (4.0 * PI)
+    }
+
+    // ── Step 5: selection ───────────────────────────────────────────────────
+
+    fn assemble(&self, pieces: &[Piece], labels: &[Label], operation: Operation) -> TriangleMesh {
+        let mut mesh = TriangleMesh::new();
+        let mut vertices: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+        let mut directed: HashMap<(u32, u32), i32> = HashMap::new();
+
+        let mut kept: Vec<([u32; 3], usize, bool)> = Vec::new();
+        for (piece, &label) in pieces.iter().zip(labels) {
+            let first = self.facets[piece.facet].operand == 0;
+            let (keep, flip) = match (operation, first, label) {
+                (Operation::Union, true, Label::Outside | Label::Same) => (true, false),
+                (Operation::Union, false, Label::Outside) => (true, false),
+                (Operation::Intersection, true, Label::Inside | Label::Same) => (true, false),
+                (Operation::Intersection, false, Label::Inside) => (true, false),
+                (Operation::Difference, true, Label::Outside | Label::Opposite) => (true, false),
+                (Operation::Difference, false, Label::Inside) => (true, true),
+                _ => (false, false),
+            };
+            if !keep {
+                continue;
+            }
+            let mut corners = piece.corners;
+            if flip {
+                corners.swap(1, 2);
+            }
+            kept.push((corners, piece.facet, flip));
+        }
+        self.remove_slivers(&mut kept);
+
+        // A triangle with nothing across any of its edges is a stray
+        // coplanar piece; without it, nothing is left open.
+        let edges: HashSet<(u32, u32)> = kept
+            .iter()
+            .flat_map(|(t, _, _)| [0, 1, 2].map(|k| (t[k], t[(k + 1) % 3])))
+            .collect();
+        kept.retain(|(t, _, _)| (0..3).any(|k| edges.contains(&(t[(k + 1) % 3], t[k]))));
+
+        for (corners, f, flip) in kept {
+            for k in 0..3 {
+                *directed
+                    .entry((corners[k], corners[(k + 1) % 3]))
+                    .or_default() += 1;
+            }
+            for id in corners {
+                let mut normal = self.normal_at(f, id);
+                if flip {
+                    normal = normal.map(|c| -c);
+                }
+                let normal = normal.map(|c| c as f32);
+                let index = *vertices
+                    .entry((id, normal.map(f32::to_bits)))
+                    .or_insert_with(|| {
+                        let p = self.points[id as usize];
+                        mesh.positions.extend(p.map(|c| c as f32));
+                        mesh.normals.extend(normal);
+                        (mesh.positions.len() / 3 - 1) as u32
+                    });
+                mesh.indices.push(index);
+            }
+        }
+
+        let unmatched = directed
+            .iter()
+            .filter(|(&(a, b), &n)| directed.get(&(b, a)).copied().unwrap_or(0) != n)
+            .count();
+        if unmatched > 0 {
+            debug!(
+                "Arrangement boolean: {} edges without a matching twin",
+                unmatched
+            );
+        }
+        mesh
+    }
+
+    /// Removes the triangles that are flat at `f32` precision, which points
+    /// made at shallow intersections leave behind: a point that sits on the
+    /// long edge of a piece, within rounding, or next to another point, just
+    /// beyond the weld.
+    ///
+    /// A needle -- one edge much shorter than the rest -- has that edge
+    /// collapsed, where the collapse keeps the surface manifold (the two
+    /// ends share no neighbours but the two triangles on the edge). A cap
+    /// has its long edge flipped with the triangle across it; its third
+    /// point lies on that edge, so the two new triangles cover what the
+    /// neighbour did. Neither moves the surface by more than the sliver is
+    /// thin.
+    fn remove_slivers(&self, tris: &mut Vec<([u32; 3], usize, bool)>) {
+        let at = |id: u32| self.points[id as usize].map(|c| c as f32 as f64);
+        let length = |a: u32, b: u32| norm(sub(at(a), at(b)));
+        let flat = |t: [u32; 3]| {
+            let [a, b, c] = t.map(at);
+            let longest = length(t[0], t[1])
+                .max(length(t[1], t[2]))
+                .max(length(t[2], t[0]));
+            let magnitude = [a, b, c]
+                .iter()
+                .flatten()
+                .fold(0.0f64, |m, v| m.max(v.abs()));
+            t[0] == t[1]
+                || t[1] == t[2]
+                || t[2] == t[0]
+                || norm(cross(sub(b, a), sub(c, a))) <= rounding(magnitude) * longest
+        };
+
+        for _ in 0..MAX_CLEANUP_ROUNDS {
+            let mut owner: HashMap<(u32, u32), usize> = HashMap::new();
+            let mut around: HashMap<u32, Vec<usize>> = HashMap::new();
+            for (i, (t, _, _)) in tris.iter().enumerate() {
+                for k in 0..3 {
+                    owner.insert((t[k], t[(k + 1) % 3]), i);
+                    around.entry(t[k]).or_default().push(i);
+                }
+            }
+            let neighbours = |v: u32, tris: &[([u32; 3], usize, bool)]| -> HashSet<u32> {
+                around[&v]
+                    .iter()
+                    .flat_map(|&i| tris[i].0)
+                    .filter(|&w| w != v)
+                    .collect()
+            };
+
+            let mut touched: HashSet<u32> = HashSet::new();
+            let mut removed: HashSet<usize> = HashSet::new();
+            for i in 0..tris.len() {
+                let t = tris[i].0;
+                if removed.contains(&i) || !flat(t) || t.iter().any(|v| touched.contains(v)) {
+                    continue;
+                }
+                let edges = [0, 1, 2].map(|k| length(t[k], t[(k + 1) % 3]));
+                let by_length = |k: &usize, l: &usize| edges[*k].total_cmp(&edges[*l]);
+                let shortest = (0..3).min_by(by_length).unwrap_or(0);
+                let longest = (0..3).max_by(by_length).unwrap_or(0);
+
+                if edges[shortest] <= NEEDLE * edges[longest] {
+                    // Collapse the short edge (u, v) into u.
+                    let (u, v) = (t[shortest], t[(shortest + 1) % 3]);
+                    let Some(&j) = owner.get(&(v, u)) else {
+                        continue;
+                    };
+                    let opposite = |tri: [u32; 3]| tri.into_iter().find(|&w| w != u && w != v);
+                    let link: HashSet<u32> = neighbours(u, tris)
+                        .intersection(&neighbours(v, tris))
+                        .copied()
+                        .collect();
+                    let expected: HashSet<u32> = [opposite(t), opposite(tris[j].0)]
+                        .into_iter()
+                        .flatten()
+                        .collect();
+                    if link != expected
+                        || around[&v]
+                            .iter()
+                            .chain(&around[&u])
+                            .any(|k| touched.iter().any(|w| tris[*k].0.contains(w)))
+                    {
+                        continue;
+                    }
+                    removed.insert(i);
+                    removed.insert(j);
+                    for &k in &around[&v] {
+                        if k != i && k != j {
+                            tris[k].0 = tris[k].0.map(|w| if w == v { u } else { w });
+                        }
+                    }
+                    touched.extend(neighbours(u, tris));
+                    touched.extend(neighbours(v, tris));
+                    touched.extend([u, v]);
+                } else {
+                    // Flip the long edge (a, b), with c opposite it.
+                    let (a, b, c) = (t[longest], t[(longest + 1) % 3], t[(longest + 2) % 3]);
+                    let Some(&j) = owner.get(&(b, a)) else {
+                        continue;
+                    };
+                    let u = tris[j].0;
+                    let Some(d) = u.into_iter().find(|&w| w != a && w != b) else {
+                        continue;
+                    };
+                    if removed.contains(&j)
+                        || touched.contains(&d)
+                        || d == c
+                        || owner.contains_key(&(c, d))
+                        || owner.contains_key(&(d, c))
+                    {
+                        continue;
+                    }
+                    let (first, second) = ([a, d, c], [d, b, c]);
+                    if flat(first) || flat(second) {
+                        continue;
+                    }
+                    // Both new triangles lie in the neighbour's facet, c on
+                    // its edge.
+                    let (_, facet, flip) = tris[j];
+                    tris[i] = (first, facet, flip);
+                    tris[j] = (second, facet, flip);
+                    touched.extend([a, b, c, d]);
+                }
+            }
+            if touched.is_empty() {
+                break;
+            }
+            let mut index = 0;
+            tris.retain(|_| {
+                index += 1;
+                !removed.contains(&(index - 1))
+            });
+        }
+    }
+
+    /// The shading normal of facet `f` at point `id`, interpolated from its
+    /// corners.
+    fn normal_at(&self, f: usize, id: u32) -> [f64; 3] {
+        let facet = &self.facets[f];
+        if let Some(k) = facet.corners.iter().position(|&c| c == id) {
+            return facet.normals[k];
+        }
+        let q = facet
+            .corners
+            .map(|c| self.facet_2d(f, self.points[c as usize]));
+        let p = self.facet_2d(f, self.points[id as usize]);
+        let area = orient2d(q[0], q[1], q[2]);
+        let weights =
+            [0, 1, 2].map(|k| (orient2d(q[(k + 1) % 3], q[(k + 2) % 3], p) / area).max(0.0));
+        let mut n = [0.0; 3];
+        for (weight, normal) in weights.into_iter().zip(facet.normals) {
+            for (c, v) in n.iter_mut().zip(normal) {
+                *c += weight * v;
+            }
+        }
+        if norm(n) == 0.0 {
+            normalize(facet.normal)
+        } else {
+            normalize(n)
+        }
+    }
+}
+
+// ─── Subdivision of one facet ───────────────────────────────────────────────
+
+/// The planar graph of one facet in its 2D frame: the boundary,
+/// counter-clockwise, and the cuts through it.
+///
+/// Faces are traced from the cyclic order of the edges around each vertex.
+/// That partitions the half-edges into cycles however the coordinates are
+/// rounded -- rounding can make a face's triangles overlap, but never leave
+/// an edge with one side -- so the output stays manifold even where the
+/// points made at intersections are not quite where they should be. Each
+/// face is then ear-clipped, after bridging any island inside it.
+#[derive(Default)]
+struct FacetGraph {
+    ids: Vec<u32>,
+    pts: Vec<[f64; 2]>,
+    index: HashMap<u32, usize>,
+}
+
+impl FacetGraph {
+    fn vertex(&mut self, id: u32, p: [f64; 2]) -> usize {
+        *self.index.entry(id).or_insert_with(|| {
+            self.ids.push(id);
+            self.pts.push(p);
+            self.pts.len() - 1
+        })
+    }
+
+    fn orient(&self, a: usize, b: usize, c: usize) -> f64 {
+        orient2d(self.pts[a], self.pts[b], self.pts[c])
+    }
+
+    /// Triangles covering the facet, and the cuts that are edges of them.
+    fn triangulate(
+        &self,
+        ring: &[usize],
+        segments: &[(usize, usize)],
+    ) -> (Vec<[usize; 3]>, Vec<(usize, usize)>) {
+        let n = self.pts.len();
+        let mut on_ring: Vec<Option<(usize, usize)>> = vec![None; n];
+        for (i, &v) in ring.iter().enumerate() {
+            let prev = ring[(i + ring.len() - 1) % ring.len()];
+            on_ring[v] = Some((prev, ring[(i + 1) % ring.len()]));
+        }
+
+        let mut cuts: Vec<(usize, usize)> = segments
+            .iter()
+            .filter(|(a, b)| a != b)
+            .map(|&(a, b)| (a.min(b), a.max(b)))
+            .filter(|&(a, b)| !matches!(on_ring[a], Some((p, q)) if p == b || q == b))
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        // A cut that ends inside the facet bounds nothing.
+        loop {
+            let mut degree = vec![0; n];
+            for &(a, b) in &cuts {
+                degree[a] += 1;
+                degree[b] += 1;
+            }
+            let before = cuts.len();
+            cuts.retain(|&(a, b)| {
+                (on_ring[a].is_some() || degree[a] > 1) && (on_ring[b].is_some() || degree[b] > 1)
+            });
+            if cuts.len() == before {
+                break;
+            }
+        }
+
+        // Neighbours of each vertex, counter-clockwise. On the boundary
+        // they run from the next boundary vertex round to the previous one,
+        // whatever rounding says, so a face never leaves the facet.
+        let mut around: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for &(a, b) in &cuts {
+            around[a].push(b);
+            around[b].push(a);
+        }
+        for &v in ring {
+            let (prev, next) = on_ring[v].unwrap();
+            around[v].push(prev);
+            around[v].push(next);
+        }
+        for (v, neighbours) in around.iter_mut().enumerate() {
+            let here = self.pts[v];
+            let angle = |w: usize| {
+                let p = self.pts[w];
+                (p[1] - here[1]).atan2(p[0] - here[0])
+            };
+            let key = |w: usize| match on_ring[v] {
+                Some((_, next)) if w == next => f64::NEG_INFINITY,
+                Some((prev, _)) if w == prev => f64::INFINITY,
+                Some((_, next)) => (angle(w) - angle(next)).rem_euclid(2.0 * PI),
+                None => angle(w),
+            };
+            neighbours.sort_by(|&a, &b| key(a).total_cmp(&key(b)).then(a.cmp(&b)));
+        }
+        let position: HashMap<(usize, usize), usize> = around
+            .iter()
+            .enumerate()
+            .flat_map(|(v, ws)| ws.iter().enumerate().map(move |(i, &w)| ((v, w), i)))
+            .collect();
+
+        // Trace the faces: from each half-edge, turn to the neighbour just
+        // clockwise of the way back.
+        let mut half_edges: Vec<(usize, usize)> = (0..ring.len())
+            .map(|i| (ring[i], ring[(i + 1) % ring.len()]))
+            .collect();
+        half_edges.extend(cuts.iter().flat_map(|&(a, b)| [(a, b), (b, a)]));
+        let mut visited: HashSet<(usize, usize)> = HashSet::new();
+        let mut cycles: Vec<Vec<usize>> = Vec::new();
+        for &start in &half_edges {
+            let mut h = start;
+            let mut cycle = Vec::new();
+            while visited.insert(h) {
+                cycle.push(h.0);
+                let (u, v) = h;
+                let ws = &around[v];
+                h = (v, ws[(position[&(v, u)] + ws.len() - 1) % ws.len()]);
+            }
+            if cycle.len() >= 3 {
+                cycles.push(cycle);
+            }
+        }
+
+        // Cycles of a cut loop that touches nothing else: the one with the
+        // least (most negative) area is its outline, a hole in whichever
+        // face encloses it.
+        let mut parent: Vec<usize> = (0..n).collect();
+        let edges = half_edges.iter().copied();
+        for (a, b) in edges {
+            let (ra, rb) = (find_root(&mut parent, a), find_root(&mut parent, b));
+            parent[ra.max(rb)] = ra.min(rb);
+        }
+        let component: Vec<usize> = cycles
+            .iter()
+            .map(|c| find_root(&mut parent, c[0]))
+            .collect();
+        let boundary = find_root(&mut parent, ring[0]);
+        let areas: Vec<f64> = cycles.iter().map(|c| self.area(c)).collect();
+        let mut outline: HashMap<usize, usize> = HashMap::new();
+        for (c, &root) in component.iter().enumerate() {
+            if root != boundary {
+                let best = outline.entry(root).or_insert(c);
+                if areas[c] < areas[*best] {
+                    *best = c;
+                }
+            }
+        }
+        let is_hole = |c: usize| outline.get(&component[c]) == Some(&c);
+
+        let mut holes: Vec<Vec<usize>> = vec![Vec::new(); cycles.len()];
+        for h in (0..cycles.len()).filter(|&c| is_hole(c)) {
+            let p = self.pts[cycles[h][0]];
+            let enclosing = (0..cycles.len())
+                .filter(|&c| !is_hole(c) && component[c] != component[h])
+                .filter(|&c| self.contains(&cycles[c], p))
+                .min_by(|&a, &b| areas[a].abs().total_cmp(&areas[b].abs()))
+                .or_else(|| {
+                    (0..cycles.len())
+                        .filter(|&c| component[c] == boundary)
+                        .max_by(|&a, &b| areas[a].total_cmp(&areas[b]))
+                });
+            if let Some(c) = enclosing {
+                holes[c].push(h);
+            }
+        }
+
+        let mut tris = Vec::new();
+        for c in (0..cycles.len()).filter(|&c| !is_hole(c)) {
+            let mut polygon = cycles[c].clone();
+            let mut inner: Vec<&Vec<usize>> = holes[c].iter().map(|&h| &cycles[h]).collect();
+            inner.sort_by(|a, b| self.rightmost(b).total_cmp(&self.rightmost(a)));
+            for hole in inner {
+                self.bridge(&mut polygon, hole);
+            }
+            let first = tris.len();
+            let sides: HashSet<(usize, usize)> = (0..polygon.len())
+                .map(|k| (polygon[k], polygon[(k + 1) % polygon.len()]))
+                .collect();
+            self.ear_clip(polygon, &mut tris);
+            self.improve(&mut tris[first..], &sides);
+        }
+        (tris, cuts)
+    }
+
+    fn area(&self, cycle: &[usize]) -> f64 {
+        let mut twice = 0.0;
+        for i in 0..cycle.len() {
+            let (a, b) = (self.pts[cycle[i]], self.pts[cycle[(i + 1) % cycle.len()]]);
+            twice += a[0] * b[1] - a[1] * b[0];
+        }
+        twice * 0.5
+    }
+
+    fn rightmost(&self, cycle: &[usize]) -> f64 {
+        cycle
+            .iter()
+            .map(|&v| self.pts[v][0])
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    fn contains(&self, cycle: &[usize], p: [f64; 2]) -> bool {
+        let mut inside = false;
+        for i in 0..cycle.len() {
+            let (a, b) = (self.pts[cycle[i]], self.pts[cycle[(i + 1) % cycle.len()]]);
+            if (a[1] > p[1]) != (b[1] > p[1]) {
+                let x = a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+                if p[0] < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    fn crosses(&self, a: usize, b: usize, c: usize, d: usize) -> bool {
+        if a == c || a == d || b == c || b == d {
+            return false;
+        }
+        opposite(self.orient(a, b, c), self.orient(a, b, d))
+            && opposite(self.orient(c, d, a), self.orient(c, d, b))
+    }
+
+    /// Splice `hole` into `polygon` along an edge from its rightmost vertex
+    /// to the nearest polygon vertex the edge can reach without crossing or
+    /// touching anything.
+    fn bridge(&self, polygon: &mut Vec<usize>, hole: &[usize]) {
+        let start = (0..hole.len())
+            .max_by(|&a, &b| self.pts[hole[a]][0].total_cmp(&self.pts[hole[b]][0]))
+            .unwrap_or(0);
+        let h = hole[start];
+        let blocked = |o: usize| {
+            let edges = |ring: &[usize]| {
+                (0..ring.len())
+                    .map(|k| (ring[k], ring[(k + 1) % ring.len()]))
+                    .collect::<Vec<_>>()
+            };
+            // Passing through a vertex is blocked as much as crossing an
+            // edge is.
+            let through = |v: &usize| {
+                self.orient(h, o, *v) == 0.0
+                    && strictly_between(self.pts[h], self.pts[o], self.pts[*v])
+            };
+            edges(polygon)
+                .into_iter()
+                .chain(edges(hole))
+                .any(|(c, d)| self.crosses(h, o, c, d))
+                || polygon.iter().chain(hole).any(through)
+        };
+        let distance = |o: usize| {
+            let (p, q) = (self.pts[h], self.pts[o]);
+            (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2)
+        };
+        let j = (0..polygon.len())
+            .min_by(|&a, &b| {
+                blocked(polygon[a])
+                    .cmp(&blocked(polygon[b]))
+                    .then(distance(polygon[a]).total_cmp(&distance(polygon[b])))
+            })
+            .unwrap_or(0);
+        let mut spliced = polygon[..=j].to_vec();
+        spliced.extend(&hole[start..]);
+        spliced.extend(&hole[..=start]);
+        spliced.extend(&polygon[j..]);
+        *polygon = spliced;
+    }
+
+    /// Whether `a`, `b`, `c` are collinear at the `f32` precision of the
+    /// output, as the integrity checks would see them.
+    fn thin(&self, a: usize, b: usize, c: usize) -> bool {
+        let magnitude = [a, b, c]
+            .iter()
+            .flat_map(|&v| self.pts[v])
+            .fold(0.0f64, |m, x| m.max(x.abs()));
+        self.orient(a, b, c).abs()
+            <= rounding(magnitude) * self.longest_edge_squared(a, b, c).sqrt()
+    }
+
+    /// Ear clipping that always gives `n - 2` triangles on the polygon's
+    /// edges. Of the clean ears the best shaped goes first, which keeps runs
+    /// of points along one side of the facet out of the same triangle; when
+    /// rounding leaves no clean ear, the most convex one goes. An ear is not
+    /// clean when it is thin at output precision or would leave the rest of
+    /// the polygon so.
+    fn ear_clip(&self, mut polygon: Vec<usize>, tris: &mut Vec<[usize; 3]>) {
+        while polygon.len() > 3 {
+            let n = polygon.len();
+            let corner = |i: usize| (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            let flat_after = |i: usize| {
+                let rest: Vec<usize> = (0..n).filter(|&k| k != i).map(|k| polygon[k]).collect();
+                (0..rest.len()).all(|k| {
+                    self.thin(
+                        rest[k],
+                        rest[(k + 1) % rest.len()],
+                        rest[(k + 2) % rest.len()],
+                    )
+                })
+            };
+            let mut fallback: Option<(f64, usize)> = None;
+            let mut ear: Option<(f64, usize)> = None;
+            for i in 0..n {
+                let (a, b, c) = corner(i);
+                if a == b || b == c || c == a {
+                    continue;
+                }
+                let o = self.orient(a, b, c);
+                if fallback.is_none_or(|(best, _)| o > best) {
+                    fallback = Some((o, i));
+                }
+                if o <= 0.0 || self.thin(a, b, c) {
+                    continue;
+                }
+                let quality = o / self.longest_edge_squared(a, b, c);
+                if ear.is_some_and(|(best, _)| quality <= best) {
+                    continue;
+                }
+                // A point on the new edge, within rounding, blocks the ear
+                // too: clipping it would leave a spike there.
+                let blocked = polygon.iter().any(|&p| {
+                    p != a
+                        && p != b
+                        && p != c
+                        && self.orient(a, b, p) >= 0.0
+                        && self.orient(b, c, p) >= 0.0
+                        && (self.orient(c, a, p) >= 0.0 || self.thin(c, a, p))
+                });
+                if !blocked && !flat_after(i) {
+                    ear = Some((quality, i));
+                }
+            }
+            let Some((_, i)) = ear.or(fallback) else {
+                return;
+            };
+            let (a, b, c) = corner(i);
+            tris.push([a, b, c]);
+            polygon.remove(i);
+        }
+        if let [a, b, c] = polygon[..] {
+            if a != b && b != c && c != a {
+                tris.push([a, b, c]);
+            }
+        }
+    }
+
+    /// Flips diagonals of a clipped polygon while that makes the worse of
+    /// the two triangles on them better shaped. Clipping the best ears first
+    /// can leave a run of points to be fanned from one far corner that sees
+    /// them almost edge-on, thin at output precision, where another corner
+    /// sees them well. The polygon's `sides` stay. Every flip raises the
+    /// sorted shapes of the triangles, so the flipping ends.
+    fn improve(&self, tris: &mut [[usize; 3]], sides: &HashSet<(usize, usize)>) {
+        let shape =
+            |[a, b, c]: [usize; 3]| self.orient(a, b, c) / self.longest_edge_squared(a, b, c);
+        let mut owner: HashMap<(usize, usize), usize> = HashMap::new();
+        for (i, t) in tris.iter().enumerate() {
+            for k in 0..3 {
+                owner.insert((t[k], t[(k + 1) % 3]), i);
+            }
+        }
+        let mut pending: Vec<(usize, usize)> = owner.keys().copied().collect();
+        while let Some((a, b)) = pending.pop() {
+            if sides.contains(&(a, b)) || sides.contains(&(b, a)) {
+                continue;
+            }
+            let (Some(&i), Some(&j)) = (owner.get(&(a, b)), owner.get(&(b, a))) else {
+                continue;
+            };
+            let third = |t: [usize; 3]| t.into_iter().find(|&v| v != a && v != b);
+            let (Some(c), Some(d)) = (third(tris[i]), third(tris[j])) else {
+                continue;
+            };
+            let (first, second) = ([c, a, d], [d, b, c]);
+            if c == d
+                || owner.contains_key(&(c, d))
+                || owner.contains_key(&(d, c))
+                || self.orient(c, a, d) <= 0.0
+                || self.orient(d, b, c) <= 0.0
+                || shape(first).min(shape(second))
+                    <= shape(tris[i]).min(shape(tris[j])) * (1.0 + IMPROVEMENT)
+            {
+                continue;
+            }
+            for t in [tris[i], tris[j]] {
+                for k in 0..3 {
+                    owner.remove(&(t[k], t[(k + 1) % 3]));
+                }
+            }
+            tris[i] = first;
+            tris[j] = second;
+            for (t, at) in [(first, i), (second, j)] {
+                for k in 0..3 {
+                    owner.insert((t[k], t[(k + 1) % 3]), at);
+                }
+            }
+            pending.extend([(a, d), (d, b), (b, c), (c, a)]);
+        }
+    }
+
+    fn longest_edge_squared(&self, a: usize, b: usize, c: usize) -> f64 {
+        let d = |p: usize, q: usize| {
+            let (p, q) = (self.pts[p], self.pts[q]);
+            (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2)
+        };
+        d(a, b).max(d(b, c)).max(d(c, a)).max(f64::MIN_POSITIVE)
+    }
+}
+
+/// The positions of the first point to come round twice in `ids`.
+fn first_repeat(ids: &[u32]) -> Option<(usize, usize)> {
+    let mut seen: HashMap<u32, usize> = HashMap::new();
+    for (j, &id) in ids.iter().enumerate() {
+        if let Some(&i) = seen.get(&id) {
+            return Some((i, j));
+        }
+        seen.insert(id, j);
+    }
+    None
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// ─── Math helpers ────────────────────────────────────────────────────────────
+
+/// True when all three corners are strictly on one side of a plane.
+fn one_side(signs: [f64; 3]) -> bool {
+    signs.iter().all(|&s| s > 0.0) || signs.iter().all(|&s| s < 0.0)
+}
+
+/// True when `a` and `b` are non-zero with opposite signs.
+fn opposite(a: f64, b: f64) -> bool {
+    (a > 0.0 && b < 0.0) || (a < 0.0 && b > 0.0)
+}
+
+/// Drop coordinate `axis`, keeping the other two in cyclic order so the
+/// projection preserves orientation when the normal's `axis` component is
+/// positive.
+fn project(p: [f64; 3], axis: usize) -> [f64; 2] {
+    [p[(axis + 1) % 3], p[(axis + 2) % 3]]
+}
+
+/// Whether `p`, on the line through `a` and `b`, lies strictly between them.
+/// Coordinates are compared directly so the test is exact.
+fn strictly_between(a: [f64; 2], b: [f64; 2], p: [f64; 2]) -> bool {
+    let k = if (b[0] - a[0]).abs() >= (b[1] - a[1]).abs() {
+        0
+    } else {
+        1
+    };
+    (a[k] < p[k] && p[k] < b[k]) || (b[k] < p[k] && p[k] < a[k])
+}
+
+/// Where segment `u`-`v` crosses the plane of `facet`.
+fn plane_crossing(facet: [[f64; 3]; 3], u: [f64; 3], v: [f64; 3]) -> [f64; 3] {
+    let n = cross(sub(facet[1], facet[0]), sub(facet[2], facet[0]));
+    let du = dot(n, sub(u, facet[0]));
+    let dv = dot(n, sub(v, facet[0]));
+    let t = if du == dv {
+        0.5
+    } else {
+        (du / (du - dv)).clamp(0.0, 1.0)
+    };
+    lerp(u, v, t)
+}
+
+fn dominant_axis(n: [f64; 3]) -> usize {
+    let a = n.map(f64::abs);
+    if a[0] >= a[1] && a[0] >= a[2] {
+        0
+    } else if a[1] >= a[2] {
+        1
+    } else {
+        2
+    }
+}
+
+fn read3(values: &[f32], vertex: usize) -> [f32; 3] {
+    [0, 1, 2].map(|k| values.get(vertex * 3 + k).copied().unwrap_or(f32::NAN))
+}
+
+fn lerp(a: [f64; 3], b: [f64; 3], t: f64) -> [f64; 3] {
+    [0, 1, 2].map(|k| a[k] + (b[k] - a[k]) * t)
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let n = norm(a);
+    if n == 0.0 {
+        a
+    } else {
+        a.map(|c| c / n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::csg::transform_mesh;
+    use crate::mesh::integrity::{analyze, MeshReport};
+    use crate::mesh::primitives::{box_mesh, sphere_mesh, tube_mesh};
+
+    fn cube(size: f64, at: [f64; 3]) -> TriangleMesh {
+        transform_mesh(
+            &box_mesh::tessellate_box(size, size, size),
+            at,
+            [0.0, 0.0, 0.0],
+        )
+    }
+
+    /// The result must be closed, consistently wound and free of crossings,
+    /// with the expected volume.
+    fn assert_solid(mesh: &TriangleMesh, volume: f64, what: &str) -> MeshReport {
+        let report = analyze(mesh);
+        assert!(
+            report.watertight && report.consistently_oriented && report.self_intersections == 0,
+            "{}: {:?}",
+            what,
+            report.problems()
+        );
+        let err = (report.volume - volume).abs() / volume.abs().max(1.0);
+        assert!(
+            err < 1e-4,
+            "{}: volume {} expected {}",
+            what,
+            report.volume,
+            volume
+        );
+        report
+    }
+
+    #[test]
+    fn tool_sharing_a_face_with_the_target() {
+        // A slot cut down from the top face: the tool's top is the target's.
+        let target = cube(100.0, [0.0; 3]);
+        let tool = transform_mesh(
+            &box_mesh::tessellate_box(20.0, 20.0, 50.0),
+            [0.0, 0.0, 25.0],
+            [0.0, 0.0, 0.0],
+        );
+        assert_solid(&subtract(&target, &tool), 1e6 - 20_000.0, "slot");
+        assert_solid(&intersect(&target, &tool), 20_000.0, "slot core");
+        assert_solid(&union(&target, &tool), 1e6, "slot union");
+
+        // Two blocks side by side fuse into one, without the shared wall.
+        let side = cube(100.0, [100.0, 0.0, 0.0]);
+        let fused = assert_solid(&union(&target, &side), 2e6, "fused blocks");
+        assert!(fused.triangles <= 24, "{} triangles", fused.triangles);
+        assert_solid(&subtract(&target, &side), 1e6, "touching subtract");
+        assert_eq!(intersect(&target, &side).triangle_count(), 0);
+
+        // Identical operands.
+        assert_solid(&intersect(&target, &target), 1e6, "self intersection");
+        assert_solid(&union(&target, &target), 1e6, "self union");
+        assert_eq!(subtract(&target, &target).triangle_count(), 0);
+    }
+
+    #[test]
+    fn rotated_coplanar_chain_far_from_origin() {
+        // The case the BSP plane thickness is tuned for, carried on through
+        // a chain of booleans. Far out, the `f32` input itself is only good
+        // to about a part in a thousand of the volume.
+        for rot in [
+            [0.3, 0.4, 0.5],
+            [PI / 2.0, 0.0, PI / 2.0],
+            [-0.3, 0.38, 2.71],
+        ] {
+            for (offset, tolerance) in [(0.0, 1e-4), (1_000.0, 1e-3), (1_000_000.0, 1e-2)] {
+                let place = |mesh: &TriangleMesh| transform_mesh(mesh, [offset; 3], rot);
+                let a = place(&cube(100.0, [0.0; 3]));
+                let b = place(&cube(100.0, [50.0, 0.0, 0.0]));
+                let c = place(&cube(100.0, [0.0, 50.0, 0.0]));
+                let result = subtract(&subtract(&a, &b), &c);
+                let report = analyze(&result);
+                assert!(
+                    report.watertight && report.consistently_oriented,
+                    "{:?} at {}: {:?}",
+                    rot,
+                    offset,
+                    report.problems()
+                );
+                let err = (report.volume - 250_000.0).abs() / 250_000.0;
+                assert!(
+                    err < tolerance,
+                    "{:?} at {}: volume {}",
+                    rot,
+                    offset,
+                    report.volume
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn curved_operands_stay_closed() {
+        let sphere = sphere_mesh::tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, PI, 32, 16);
+        let bore = tube_mesh::tessellate_tube(0.0, 10.0, 200.0, 0.0, 2.0 * PI, 32);
+        let sphere_volume = analyze(&sphere).volume;
+        let bore_volume = analyze(&bore).volume;
+
+        let drilled = subtract(&sphere, &bore);
+        let report = analyze(&drilled);
+        assert!(
+            report.watertight && report.consistently_oriented,
+            "{:?}",
+            report.problems()
+        );
+        assert!(report.volume < sphere_volume && report.volume > sphere_volume - bore_volume);
+
+        // A cavity fully inside comes out wound inward.
+        let hollow = subtract(&cube(200.0, [0.0; 3]), &sphere);
+        assert_solid(&hollow, 8e6 - sphere_volume, "cavity");
+    }
+
+    #[test]
+    fn bores_drilled_one_after_another() {
+        // Bores exactly as deep as the plate: every cap lies on a face the
+        // earlier bores have already cut up, and some of those cuts run
+        // through the next bore's rim vertices.
+        let mut plate = box_mesh::tessellate_box(100.0, 100.0, 10.0);
+        let bore = tube_mesh::tessellate_tube(0.0, 3.0, 10.0, 0.0, 2.0 * PI, 24);
+        let section = 0.5 * 24.0 * 9.0 * (2.0 * PI / 24.0).sin();
+        for i in 0..16 {
+            let at = [
+                (i % 4) as f64 * 25.0 - 37.5,
+                (i / 4) as f64 * 25.0 - 37.5,
+                0.0,
+            ];
+            plate = subtract(&plate, &transform_mesh(&bore, at, [0.0; 3]));
+            let volume = 1e5 - (i + 1) as f64 * section * 10.0;
+            assert_solid(&plate, volume, &format!("bore {}", i));
+        }
+    }
+
+    #[test]
+    fn random_placements_stay_closed() {
+        // Boxes, spheres and cylinders at random, half of them snapped to a
+        // grid and right angles so that faces and edges coincide.
+        let mut seed: u64 = 12345;
+        let mut random = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64
+        };
+        for case in 0..40 {
+            let shape = |random: &mut dyn FnMut() -> f64| match (random() * 3.0) as u32 {
+                0 => box_mesh::tessellate_box(
+                    40.0 + 60.0 * random(),
+                    40.0 + 60.0 * random(),
+                    40.0 + 60.0 * random(),
+                ),
+                1 => {
+                    let r = 20.0 + 40.0 * random();
+                    let n = 8 + (random() * 24.0) as u32;
+                    sphere_mesh::tessellate_sphere(0.0, r, 0.0, 2.0 * PI, 0.0, PI, n, n / 2)
+                }
+                _ => tube_mesh::tessellate_tube(
+                    0.0,
+                    10.0 + 20.0 * random(),
+                    50.0 + 100.0 * random(),
+                    0.0,
+                    2.0 * PI,
+                    8 + (random() * 24.0) as u32,
+                ),
+            };
+            let a = shape(&mut random);
+            let b = shape(&mut random);
+            let snap = random() < 0.5;
+            let position = [0; 3].map(|_| {
+                if snap {
+                    ((random() * 4.0).floor() - 2.0) * 10.0
+                } else {
+                    (random() - 0.5) * 60.0
+                }
+            });
+            let rotation = [0; 3].map(|_| {
+                if snap {
+                    (random() * 4.0).floor() * PI / 2.0
+                } else {
+                    random() * 2.0 * PI
+                }
+            });
+            let b = transform_mesh(&b, position, rotation);
+
+            let mut volumes = Vec::new();
+            for (name, result) in [
+                ("subtract", subtract(&a, &b)),
+                ("union", union(&a, &b)),
+                ("intersect", intersect(&a, &b)),
+            ] {
+                let report = analyze(&result);
+                assert!(
+                    result.triangle_count() == 0
+                        || (report.watertight && report.consistently_oriented),
+                    "case {} {}: {:?}",
+                    case,
+                    name,
+                    report.problems()
+                );
+                volumes.push(report.volume);
+            }
+            // a - b, a | b and a & b make up a and b between them.
+            let (va, vb) = (analyze(&a).volume, analyze(&b).volume);
+            let [vs, vu, vi] = [volumes[0], volumes[1], volumes[2]];
+            assert!((vs + vi - va).abs() < 1e-4 * va.max(vb), "case {}", case);
+            assert!(
+                (vu + vi - va - vb).abs() < 1e-4 * va.max(vb),
+                "case {}",
+                case
+            );
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! The placed scene baked into a few large draw batches.
+//!
+//! One draw object per scene node stops working long before the hundred
+//! thousand placements a replica can expand to. Baking groups the instances
+//! two ways instead:
+//!
+//! - Solids with many facets are drawn with GPU instancing: one batch per
+//!   solid and material, holding a world matrix per instance. The mesh is the
+//!   one `/api/document/meshes` already serves.
+//! - Small solids cost more in draw calls than in vertices, so their meshes
+//!   are carried to world space and merged, one buffer per material (split
+//!   when it grows past [`MAX_MERGED_VERTICES`]). A per-triangle map back to
+//!   the instance keeps picking exact.
+//!
+//! Batches carry the material name, aux colour and density, from which the
+//! viewer picks colours as it does for single nodes.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::placement::{for_each_instance, Placement};
+use super::types::TriangleMesh;
+use crate::gdml::model::SceneNode;
+
+/// Solids of at most this many facets are merged rather than instanced.
+pub const DEFAULT_MERGE_TRIANGLES: usize = 256;
+
+/// Largest merged buffer, in vertices. Past this a material starts another
+/// buffer, which keeps each one within what a single upload handles well.
+pub const MAX_MERGED_VERTICES: usize = 1 << 20;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Bake {
+    pub instanced: Vec<InstancedBatch>,
+    pub merged: Vec<MergedBatch>,
+}
+
+/// What a batch is drawn in; the same for every instance in it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Appearance {
+    pub material_name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstancedBatch {
+    /// Key into the meshes of `/api/document/meshes`.
+    pub solid_name: String,
+    #[serde(flatten)]
+    pub appearance: Appearance,
+    /// g/cm3.
+    pub density: Option<f64>,
+    /// The scene graph's instance path of each instance.
+    pub instance_ids: Vec<String>,
+    /// A column-major 4x4 world matrix per instance, 16 values each, in the
+    /// order of `instance_ids`.
+    pub matrices: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedBatch {
+    #[serde(flatten)]
+    pub appearance: Appearance,
+    /// g/cm3.
+    pub density: Option<f64>,
+    /// World-space.
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub instance_ids: Vec<String>,
+    /// For each triangle, the index into `instance_ids` it came from.
+    pub triangle_instances: Vec<u32>,
+}
+
+/// Bake every instance below `root` that has a mesh. Solids of at most
+/// `merge_triangles` facets are merged; the rest are instanced.
+pub fn bake(
+    root: &SceneNode,
+    meshes: &HashMap<String, TriangleMesh>,
+    merge_triangles: usize,
+) -> Bake {
+    let mut out = Bake::default();
+    let mut instanced: HashMap<(&str, Appearance), usize> = HashMap::new();
+    let mut merged: HashMap<Appearance, usize> = HashMap::new();
+
+    for_each_instance(root, |node, placement| {
+        let Some((name, mesh)) = meshes.get_key_value(&node.solid_name) else {
+            return;
+        };
+        if mesh.triangle_count() == 0 {
+            return;
+        }
+        let appearance = Appearance {
+            material_name: node.material_name.clone(),
+            color: node.color.clone(),
+        };
+
+        if mesh.triangle_count() > merge_triangles {
+            let key = (name.as_str(), appearance);
+            let i = *instanced.entry(key.clone()).or_insert_with(|| {
+                out.instanced.push(InstancedBatch {
+                    solid_name: name.clone(),
+                    appearance: key.1,
+                    density: node.density,
+                    instance_ids: Vec::new(),
+                    matrices: Vec::new(),
+                });
+                out.instanced.len() - 1
+            });
+            let batch = &mut out.instanced[i];
+            batch.instance_ids.push(node.instance_id.clone());
+            batch
+                .matrices
+                .extend(placement.to_columns().map(|v| v as f32));
+            return;
+        }
+
+        let open = merged.get(&appearance).copied().filter(|&i| {
+            out.merged[i].positions.len() / 3 + mesh.vertex_count() <= MAX_MERGED_VERTICES
+        });
+        let i = open.unwrap_or_else(|| {
+            out.merged.push(MergedBatch {
+                appearance: appearance.clone(),
+                density: node.density,
+                positions: Vec::new(),
+                normals: Vec::new(),
+                indices: Vec::new(),
+                instance_ids: Vec::new(),
+                triangle_instances: Vec::new(),
+            });
+            merged.insert(appearance, out.merged.len() - 1);
+            out.merged.len() - 1
+        });
+        append(&mut out.merged[i], node, placement, mesh);
+    });
+    out
+}
+
+fn append(batch: &mut MergedBatch, node: &SceneNode, placement: &Placement, mesh: &TriangleMesh) {
+    let base = (batch.positions.len() / 3) as u32;
+    let instance = batch.instance_ids.len() as u32;
+    batch.instance_ids.push(node.instance_id.clone());
+    for (p, n) in mesh
+        .positions
+        .chunks_exact(3)
+        .zip(mesh.normals.chunks_exact(3))
+    {
+        let p = placement.apply([p[0] as f64, p[1] as f64, p[2] as f64]);
+        let n = placement.rotate([n[0] as f64, n[1] as f64, n[2] as f64]);
+        batch.positions.extend(p.map(|c| c as f32));
+        batch.normals.extend(n.map(|c| c as f32));
+    }
+    batch.indices.extend(mesh.indices.iter().map(|&i| base + i));
+    batch
+        .triangle_instances
+        .extend(std::iter::repeat_n(instance, mesh.triangle_count()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use crate::mesh::primitives::tube_mesh::tessellate_tube;
+
+    fn node(name: &str, solid: &str, material: &str, x: f64) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            instance_id: format!("/World/{}", name),
+            volume_name: name.to_string(),
+            solid_name: solid.to_string(),
+            material_name: material.to_string(),
+            color: None,
+            density: None,
+            position: [x, 0.0, 0.0],
+            rotation: [0.0; 3],
+            is_world: false,
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn small_solids_merge_and_large_ones_are_instanced() {
+        let mut meshes = HashMap::new();
+        meshes.insert(
+            "WorldBox".to_string(),
+            tessellate_box(1000.0, 1000.0, 1000.0),
+        );
+        meshes.insert("Cell".to_string(), tessellate_box(2.0, 2.0, 2.0));
+        // 512 facets, past the merge threshold.
+        let pipe = tessellate_tube(5.0, 8.0, 10.0, 0.0, 2.0 * std::f64::consts::PI, 64);
+        meshes.insert("Pipe".to_string(), pipe);
+        let scene = SceneNode {
+            is_world: true,
+            children: vec![
+                node("Cell0", "Cell", "Iron", 10.0),
+                node("Pipe0", "Pipe", "Iron", -50.0),
+                node("Cell1", "Cell", "Iron", 20.0),
+                node("Gap", "Cell", "Air", 30.0),
+                node("Pipe1", "Pipe", "Iron", 50.0),
+            ],
+            ..node("World", "WorldBox", "Air", 0.0)
+        };
+
+        let baked = bake(&scene, &meshes, DEFAULT_MERGE_TRIANGLES);
+
+        assert_eq!(baked.instanced.len(), 1);
+        let pipes = &baked.instanced[0];
+        assert_eq!(pipes.solid_name, "Pipe");
+        assert_eq!(pipes.instance_ids, ["/World/Pipe0", "/World/Pipe1"]);
+        assert_eq!(pipes.matrices.len(), 32);
+        assert_eq!(pipes.matrices[12..16], [-50.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pipes.matrices[16 + 12], 50.0);
+
+        // The world box is the scene's, not an instance; the cells split by
+        // material.
+        assert_eq!(baked.merged.len(), 2);
+        let iron = &baked.merged[0];
+        assert_eq!(iron.appearance.material_name, "Iron");
+        assert_eq!(iron.instance_ids, ["/World/Cell0", "/World/Cell1"]);
+        assert_eq!(iron.indices.len(), 2 * 36);
+        assert_eq!(iron.triangle_instances.len(), 2 * 12);
+        assert!(iron.triangle_instances[..12].iter().all(|&i| i == 0));
+        assert!(iron.triangle_instances[12..].iter().all(|&i| i == 1));
+        // The second cell's facets use its own, shifted vertices.
+        let v = iron.indices[36] as usize;
+        assert!((19.0..=21.0).contains(&iron.positions[v * 3]));
+        assert_eq!(baked.merged[1].appearance.material_name, "Air");
+
+        // With nothing small enough to merge, everything is instanced.
+        let baked = bake(&scene, &meshes, 0);
+        assert!(baked.merged.is_empty());
+        assert_eq!(baked.instanced.len(), 3);
+    }
+}
@@ -0,0 +1,516 @@
+//! Bounding boxes of solids, of their placed instances and of the world.
+//!
+//! A curved surface's facets lie inside it, so a box around the vertices of
+//! a tube or sphere comes out short wherever no vertex falls on the widest
+//! point. Curved primitives therefore have their local box worked out from
+//! their parameters. Faceted primitives take the box of their mesh, which
+//! is exact for them, as do booleans and the other composite solids, for
+//! which no cheap formula exists.
+//!
+//! Placed in the world, a local box turns into an oriented box. The
+//! axis-aligned box around that one is what culling and framing use.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use super::placement::{for_each_instance, Placement};
+use super::tessellator::{
+    resolve, resolve_delta_phi, resolve_opt_with_aunit, resolve_opt_with_lunit, resolve_with_aunit,
+    resolve_with_lunit,
+};
+use super::types::TriangleMesh;
+use crate::eval::engine::EvalEngine;
+use crate::gdml::model::{SceneNode, Solid, SolidSection};
+
+/// An axis-aligned box, in mm.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Aabb {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Aabb {
+    /// Contains nothing; the identity of [`Aabb::union`].
+    pub const EMPTY: Aabb = Aabb {
+        min: [f64::INFINITY; 3],
+        max: [f64::NEG_INFINITY; 3],
+    };
+
+    /// Centred on the origin with the given half-extents.
+    pub fn symmetric(half: [f64; 3]) -> Aabb {
+        Aabb {
+            min: half.map(|h| -h),
+            max: half,
+        }
+    }
+
+    pub fn of_mesh(mesh: &TriangleMesh) -> Aabb {
+        let mut b = Aabb::EMPTY;
+        for p in mesh.positions.chunks_exact(3) {
+            b.include([p[0] as f64, p[1] as f64, p[2] as f64]);
+        }
+        b
+    }
+
+    /// Empty, or with a NaN or infinite bound.
+    pub fn is_empty(&self) -> bool {
+        !(0..3).all(|i| self.min[i] <= self.max[i] && (self.max[i] - self.min[i]).is_finite())
+    }
+
+    pub fn include(&mut self, p: [f64; 3]) {
+        for (i, c) in p.into_iter().enumerate() {
+            self.min[i] = self.min[i].min(c);
+            self.max[i] = self.max[i].max(c);
+        }
+    }
+
+    pub fn union(mut self, other: Aabb) -> Aabb {
+        self.include(other.min);
+        self.include(other.max);
+        self
+    }
+
+    pub fn center(&self) -> [f64; 3] {
+        [0, 1, 2].map(|i| 0.5 * (self.min[i] + self.max[i]))
+    }
+
+    pub fn half_extents(&self) -> [f64; 3] {
+        [0, 1, 2].map(|i| 0.5 * (self.max[i] - self.min[i]))
+    }
+
+    /// This box carried by `placement`.
+    pub(crate) fn placed(&self, placement: &Placement) -> Obb {
+        let r = placement.r;
+        Obb {
+            center: placement.apply(self.center()),
+            half_extents: self.half_extents(),
+            axes: [0, 1, 2].map(|k| [r[0][k], r[1][k], r[2][k]]),
+        }
+    }
+}
+
+/// An oriented box, in mm.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Obb {
+    pub center: [f64; 3],
+    /// Along each of `axes`.
+    pub half_extents: [f64; 3],
+    /// The box's x, y and z axes as unit vectors.
+    pub axes: [[f64; 3]; 3],
+}
+
+impl Obb {
+    /// The smallest axis-aligned box around this one.
+    pub fn aabb(&self) -> Aabb {
+        let reach = [0, 1, 2].map(|i| {
+            (0..3)
+                .map(|k| self.axes[k][i].abs() * self.half_extents[k])
+                .sum::<f64>()
+        });
+        Aabb {
+            min: [0, 1, 2].map(|i| self.center[i] - reach[i]),
+            max: [0, 1, 2].map(|i| self.center[i] + reach[i]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundsSource {
+    /// From the solid's parameters.
+    Analytic,
+    /// From the vertices of its mesh.
+    Mesh,
+}
+
+/// A solid's box in its own frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SolidBounds {
+    #[serde(flatten)]
+    pub aabb: Aabb,
+    pub source: BoundsSource,
+}
+
+/// The box of one scene node's solid where the node is placed.
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceBounds {
+    /// The scene graph's instance path.
+    pub instance_id: String,
+    pub solid_name: String,
+    pub obb: Obb,
+    pub aabb: Aabb,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SceneBounds {
+    pub instances: Vec<InstanceBounds>,
+    /// Everything placed, the world volume included; `None` when nothing
+    /// has a box.
+    pub world: Option<Aabb>,
+}
+
+/// The local box of every solid in `meshes` that has facets. A solid that
+/// tessellates to nothing gets none, whatever its parameters say.
+pub fn solid_bounds(
+    solids: &SolidSection,
+    engine: &EvalEngine,
+    meshes: &HashMap<String, TriangleMesh>,
+) -> HashMap<String, SolidBounds> {
+    let by_name: HashMap<&str, &Solid> = solids.solids.iter().map(|s| (s.name(), s)).collect();
+    meshes
+        .iter()
+        .filter(|(_, mesh)| mesh.triangle_count() > 0)
+        .filter_map(|(name, mesh)| {
+            let analytic = by_name
+                .get(name.as_str())
+                .and_then(|s| analytic(s, engine))
+                .filter(|b| !b.is_empty());
+            let bounds = match analytic {
+                Some(aabb) => SolidBounds {
+                    aabb,
+                    source: BoundsSource::Analytic,
+                },
+                None => SolidBounds {
+                    aabb: Aabb::of_mesh(mesh),
+                    source: BoundsSource::Mesh,
+                },
+            };
+            (!bounds.aabb.is_empty()).then(|| (name.clone(), bounds))
+        })
+        .collect()
+}
+
+/// The placed box of every node below `root` whose solid has one, the world
+/// volume first, and their union.
+pub fn scene_bounds(root: &SceneNode, solids: &HashMap<String, SolidBounds>) -> SceneBounds {
+    let mut out = SceneBounds::default();
+    let mut place = |node: &SceneNode, placement: &Placement| {
+        let Some(local) = solids.get(&node.solid_name) else {
+            return;
+        };
+        let obb = local.aabb.placed(placement);
+        let aabb = obb.aabb();
+        out.world = Some(out.world.unwrap_or(Aabb::EMPTY).union(aabb));
+        out.instances.push(InstanceBounds {
+            instance_id: node.instance_id.clone(),
+            solid_name: node.solid_name.clone(),
+            obb,
+            aabb,
+        });
+    };
+    if root.is_world {
+        place(root, &Placement::of_node(root));
+    }
+    for_each_instance(root, place);
+    out
+}
+
+/// The local box of a curved primitive, evaluated as the tessellator does.
+fn analytic(solid: &Solid, engine: &EvalEngine) -> Option<Aabb> {
+    let lunit = |u: &Option<String>| u.as_deref().unwrap_or("mm").to_string();
+    let aunit = |u: &Option<String>| u.as_deref().unwrap_or("rad").to_string();
+    let bounds = match solid {
+        Solid::Box(s) => {
+            let l = lunit(&s.lunit);
+            Aabb::symmetric([&s.x, &s.y, &s.z].map(|e| 0.5 * resolve_with_lunit(engine, e, &l)))
+        }
+        Solid::Tube(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let hz = 0.5 * resolve_with_lunit(engine, &s.z, &l);
+            sector(
+                resolve_opt_with_lunit(engine, &s.rmin, &l),
+                resolve_with_lunit(engine, &s.rmax, &l),
+                resolve_opt_with_aunit(engine, &s.startphi, &a),
+                resolve_delta_phi(engine, &s.deltaphi, &a),
+                [-hz, hz],
+            )
+        }
+        Solid::Cone(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let hz = 0.5 * resolve_with_lunit(engine, &s.z, &l);
+            let start = resolve_opt_with_aunit(engine, &s.startphi, &a);
+            let delta = resolve_delta_phi(engine, &s.deltaphi, &a);
+            // The wall runs straight between the two end rings, so the ends
+            // bound it.
+            let end = |rmin: &Option<String>, rmax: &String, z: f64| {
+                sector(
+                    resolve_opt_with_lunit(engine, rmin, &l),
+                    resolve_with_lunit(engine, rmax, &l),
+                    start,
+                    delta,
+                    [z, z],
+                )
+            };
+            end(&s.rmin1, &s.rmax1, -hz).union(end(&s.rmin2, &s.rmax2, hz))
+        }
+        Solid::Sphere(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let rmin = resolve_opt_with_lunit(engine, &s.rmin, &l);
+            let rmax = resolve_with_lunit(engine, &s.rmax, &l);
+            let t0 = resolve_opt_with_aunit(engine, &s.starttheta, &a).clamp(0.0, PI);
+            let t1 = match &s.deltatheta {
+                Some(e) => t0 + resolve_with_aunit(engine, e, &a),
+                None => t0 + PI,
+            }
+            .clamp(t0, PI);
+            // Over [0, pi] the cosine falls and the sine peaks at the equator.
+            let sin_max = if t0 <= FRAC_PI_2 && FRAC_PI_2 <= t1 {
+                1.0
+            } else {
+                t0.sin().max(t1.sin())
+            };
+            let z = [
+                (rmin * t1.cos()).min(rmax * t1.cos()),
+                (rmin * t0.cos()).max(rmax * t0.cos()),
+            ];
+            sector(
+                rmin * t0.sin().min(t1.sin()),
+                rmax * sin_max,
+                resolve_opt_with_aunit(engine, &s.startphi, &a),
+                resolve_delta_phi(engine, &s.deltaphi, &a),
+                z,
+            )
+        }
+        Solid::Orb(s) => {
+            let l = lunit(&s.lunit);
+            Aabb::symmetric([resolve_with_lunit(engine, &s.r, &l); 3])
+        }
+        Solid::Torus(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let rmax = resolve_with_lunit(engine, &s.rmax, &l);
+            let rtor = resolve_with_lunit(engine, &s.rtor, &l);
+            sector(
+                (rtor - rmax).max(0.0),
+                rtor + rmax,
+                resolve_opt_with_aunit(engine, &s.startphi, &a),
+                resolve_delta_phi(engine, &s.deltaphi, &a),
+                [-rmax, rmax],
+            )
+        }
+        Solid::Polycone(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let start = resolve_opt_with_aunit(engine, &s.startphi, &a);
+            let delta = resolve_delta_phi(engine, &s.deltaphi, &a);
+            s.zplanes.iter().fold(Aabb::EMPTY, |b, zp| {
+                let z = resolve_with_lunit(engine, &zp.z, &l);
+                b.union(sector(
+                    resolve_opt_with_lunit(engine, &zp.rmin, &l),
+                    resolve_with_lunit(engine, &zp.rmax, &l),
+                    start,
+                    delta,
+                    [z, z],
+                ))
+            })
+        }
+        Solid::GenericPolycone(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let start = resolve_opt_with_aunit(engine, &s.startphi, &a);
+            let delta = resolve_delta_phi(engine, &s.deltaphi, &a);
+            // Each point of the contour sweeps an arc; the straight sides
+            // between them stay within those arcs' box.
+            s.rzpoints.iter().fold(Aabb::EMPTY, |b, rz| {
+                let r = resolve_with_lunit(engine, &rz.r, &l);
+                let z = resolve_with_lunit(engine, &rz.z, &l);
+                b.union(sector(r, r, start, delta, [z, z]))
+            })
+        }
+        Solid::Eltube(s) => {
+            let l = lunit(&s.lunit);
+            Aabb::symmetric([&s.dx, &s.dy, &s.dz].map(|e| resolve_with_lunit(engine, e, &l)))
+        }
+        Solid::Ellipsoid(s) => {
+            let l = lunit(&s.lunit);
+            let [ax, by, cz] = [&s.ax, &s.by, &s.cz].map(|e| resolve_with_lunit(engine, e, &l));
+            let cut = |e: &Option<String>, default: f64| match e {
+                Some(e) => resolve_with_lunit(engine, e, &l).clamp(-cz, cz),
+                None => default,
+            };
+            let (z0, z1) = (cut(&s.zcut1, -cz), cut(&s.zcut2, cz));
+            // The section is widest at the cut nearest the equator.
+            let z = if z0 <= 0.0 && 0.0 <= z1 {
+                0.0
+            } else {
+                z0.abs().min(z1.abs())
+            };
+            let scale = (1.0 - (z / cz).powi(2)).max(0.0).sqrt();
+            Aabb {
+                min: [-ax * scale, -by * scale, z0],
+                max: [ax * scale, by * scale, z1],
+            }
+        }
+        Solid::Elcone(s) => {
+            let l = lunit(&s.lunit);
+            let zmax = resolve_with_lunit(engine, &s.zmax, &l);
+            let zcut = resolve_with_lunit(engine, &s.zcut, &l).min(zmax);
+            // Widest at the bottom cut.
+            let reach = zmax + zcut;
+            Aabb::symmetric([
+                resolve(engine, &s.dx) * reach,
+                resolve(engine, &s.dy) * reach,
+                zcut,
+            ])
+        }
+        Solid::Paraboloid(s) => {
+            let l = lunit(&s.lunit);
+            let r =
+                resolve_with_lunit(engine, &s.rlo, &l).max(resolve_with_lunit(engine, &s.rhi, &l));
+            Aabb::symmetric([r, r, resolve_with_lunit(engine, &s.dz, &l)])
+        }
+        Solid::Hype(s) => {
+            let (l, a) = (lunit(&s.lunit), aunit(&s.aunit));
+            let rmax = resolve_with_lunit(engine, &s.rmax, &l);
+            let outst = resolve_opt_with_aunit(engine, &s.outst, &a);
+            let hz = 0.5 * resolve_with_lunit(engine, &s.z, &l);
+            // The outer hyperboloid is widest at the ends.
+            let r = rmax.hypot(hz * outst.tan());
+            Aabb::symmetric([r, r, hz])
+        }
+        _ => return None,
+    };
+    Some(bounds)
+}
+
+/// The box of the annular sector between radii `rmin` and `rmax` over
+/// `start..start + delta`, spanning `z`.
+fn sector(rmin: f64, rmax: f64, start: f64, delta: f64, z: [f64; 2]) -> Aabb {
+    let mut b = Aabb::EMPTY;
+    let mut at = |r: f64, phi: f64| {
+        let (s, c) = phi.sin_cos();
+        b.include([r * c, r * s, z[0]]);
+        b.include([r * c, r * s, z[1]]);
+    };
+    for phi in [start, start + delta] {
+        at(rmin, phi);
+        at(rmax, phi);
+    }
+    // The outer arc reaches furthest where it crosses an axis.
+    let mut k = (start / FRAC_PI_2).ceil();
+    while k * FRAC_PI_2 <= start + delta && k - start / FRAC_PI_2 <= 4.0 {
+        at(rmax, k * FRAC_PI_2);
+        k += 1.0;
+    }
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::model::TubeSolid;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use crate::mesh::primitives::tube_mesh::tessellate_tube;
+
+    fn assert_near(a: [f64; 3], b: [f64; 3]) {
+        assert!(
+            (0..3).all(|i| (a[i] - b[i]).abs() < 1e-9),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn tube_bounds_reach_past_its_facets() {
+        let tube = |name: &str, deltaphi: Option<&str>| {
+            Solid::Tube(TubeSolid {
+                name: name.to_string(),
+                rmin: Some("5".to_string()),
+                rmax: "10".to_string(),
+                z: "40".to_string(),
+                startphi: None,
+                deltaphi: deltaphi.map(str::to_string),
+                aunit: Some("deg".to_string()),
+                lunit: None,
+            })
+        };
+        let solids = SolidSection {
+            solids: vec![tube("Full", None), tube("Quarter", Some("90"))],
+            optical_surfaces: Vec::new(),
+        };
+        let mut meshes = HashMap::new();
+        // Seven steps put no vertex at -x.
+        meshes.insert(
+            "Full".to_string(),
+            tessellate_tube(5.0, 10.0, 40.0, 0.0, 2.0 * PI, 7),
+        );
+        meshes.insert(
+            "Quarter".to_string(),
+            tessellate_tube(5.0, 10.0, 40.0, 0.0, FRAC_PI_2, 7),
+        );
+        meshes.insert("Mesh".to_string(), tessellate_box(2.0, 4.0, 6.0));
+
+        let bounds = solid_bounds(&solids, &EvalEngine::new(), &meshes);
+
+        let full = bounds["Full"];
+        assert_eq!(full.source, BoundsSource::Analytic);
+        assert_near(full.aabb.min, [-10.0, -10.0, -20.0]);
+        assert_near(full.aabb.max, [10.0, 10.0, 20.0]);
+        assert!(Aabb::of_mesh(&meshes["Full"]).min[0] > -9.9);
+
+        let quarter = bounds["Quarter"].aabb;
+        assert_near(quarter.min, [0.0, 0.0, -20.0]);
+        assert_near(quarter.max, [10.0, 10.0, 20.0]);
+
+        let mesh = bounds["Mesh"];
+        assert_eq!(mesh.source, BoundsSource::Mesh);
+        assert_near(mesh.aabb.max, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn placed_boxes_turn_with_their_nodes() {
+        let node = |name: &str, position: [f64; 3], rotation: [f64; 3]| SceneNode {
+            name: name.to_string(),
+            instance_id: format!("/World/{}", name),
+            volume_name: name.to_string(),
+            solid_name: "Slab".to_string(),
+            material_name: "Iron".to_string(),
+            color: None,
+            density: None,
+            position,
+            rotation,
+            is_world: false,
+            children: Vec::new(),
+        };
+        let scene = SceneNode {
+            solid_name: "Missing".to_string(),
+            is_world: true,
+            children: vec![
+                node("Flat", [100.0, 0.0, 0.0], [0.0; 3]),
+                node("Turned", [0.0, 0.0, 50.0], [0.0, 0.0, FRAC_PI_2]),
+                node("Tilted", [0.0; 3], [PI / 4.0, 0.0, 0.0]),
+            ],
+            ..node("World", [0.0; 3], [0.0; 3])
+        };
+        let mut solids = HashMap::new();
+        solids.insert(
+            "Slab".to_string(),
+            SolidBounds {
+                aabb: Aabb::symmetric([10.0, 2.0, 1.0]),
+                source: BoundsSource::Mesh,
+            },
+        );
+
+        let out = scene_bounds(&scene, &solids);
+
+        // The world's solid has no box, so only the three slabs are listed.
+        assert_eq!(out.instances.len(), 3);
+        let flat = &out.instances[0];
+        assert_near(flat.aabb.min, [90.0, -2.0, -1.0]);
+        assert_near(flat.aabb.max, [110.0, 2.0, 1.0]);
+
+        // A quarter turn about z swaps the slab's x and y extents.
+        let turned = &out.instances[1];
+        assert_near(turned.obb.center, [0.0, 0.0, 50.0]);
+        assert_near(turned.obb.half_extents, [10.0, 2.0, 1.0]);
+        assert_near(turned.aabb.max, [2.0, 10.0, 51.0]);
+
+        // At 45 degrees about x the box is wider than the slab in y and z.
+        let tilted = &out.instances[2];
+        let reach = 3.0 / 2f64.sqrt();
+        assert_near(tilted.aabb.max, [10.0, reach, reach]);
+
+        let world = out.world.unwrap();
+        assert_near(world.min, [-10.0, -10.0, -reach]);
+        assert_near(world.max, [110.0, 10.0, 51.0]);
+    }
+}
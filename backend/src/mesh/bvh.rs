@@ -0,0 +1,573 @@
+//! Bounding-volume hierarchies for spatial queries on the placed scene.
+//!
+//! Two levels, as a replica-heavy scene needs: one hierarchy over each
+//! solid's facets in its own frame, shared by every placement of the solid,
+//! and one over the world-space boxes of the placements. A ray is carried
+//! into an instance's frame instead of the facets into the world, so a solid
+//! placed ten thousand times is indexed once.
+//!
+//! [`SceneIndex`] lives with the loaded document. Edits bring it up to date
+//! with [`SceneIndex::update`], which rebuilds the facet hierarchies only of
+//! the solids whose meshes changed; the placement level is cheap next to
+//! them and is always rebuilt whole.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::bounds::Aabb;
+use super::placement::{for_each_instance, Placement};
+use super::types::TriangleMesh;
+use crate::gdml::model::SceneNode;
+
+/// Entries per leaf.
+const LEAF_SIZE: usize = 4;
+
+/// Hits closer than this to the ray's origin, in mm, are ignored, so a ray
+/// cast from a surface does not find that surface again.
+const MIN_DISTANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    /// For a leaf, its first entry in `order`; for an inner node, the index
+    /// of its right child. The left child directly follows the node.
+    start: u32,
+    /// Entries of a leaf; 0 for an inner node.
+    count: u32,
+}
+
+/// A binary hierarchy over boxes, split at the median along the longest
+/// axis of their centres.
+#[derive(Debug, Clone, Default)]
+struct Hierarchy {
+    nodes: Vec<Node>,
+    /// Entry indices, leaf by leaf.
+    order: Vec<u32>,
+}
+
+impl Hierarchy {
+    fn build(boxes: &[Aabb]) -> Hierarchy {
+        let mut order: Vec<u32> = (0..boxes.len() as u32).collect();
+        let mut nodes = Vec::with_capacity(2 * boxes.len() / LEAF_SIZE + 1);
+        if !boxes.is_empty() {
+            split(boxes, &mut order, 0, &mut nodes);
+        }
+        Hierarchy { nodes, order }
+    }
+
+    /// The box around everything, or `None` for an empty hierarchy.
+    fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|n| n.bounds)
+    }
+
+    /// Offer `hit` every entry whose box the ray meets closer than the
+    /// nearest hit so far. `hit` returns the entry's distance along the ray
+    /// when it is hit, and the search narrows to that.
+    fn nearest(&self, ray: &Ray, t_max: f64, mut hit: impl FnMut(u32, f64) -> Option<f64>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut best = t_max;
+        let mut stack = vec![0u32];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i as usize];
+            if ray.enters(&node.bounds, best).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(i + 1);
+                continue;
+            }
+            let entries = &self.order[node.start as usize..(node.start + node.count) as usize];
+            for &entry in entries {
+                if let Some(t) = hit(entry, best) {
+                    best = best.min(t);
+                }
+            }
+        }
+    }
+}
+
+/// Build the subtree over `order`, whose entries start at `offset` in the
+/// full order; returns its root.
+fn split(boxes: &[Aabb], order: &mut [u32], offset: usize, nodes: &mut Vec<Node>) -> u32 {
+    let bounds = order
+        .iter()
+        .fold(Aabb::EMPTY, |b, &i| b.union(boxes[i as usize]));
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        start: offset as u32,
+        count: order.len() as u32,
+    });
+
+    let centre = |i: u32| boxes[i as usize].center();
+    let mut spread = Aabb::EMPTY;
+    for &i in order.iter() {
+        spread.include(centre(i));
+    }
+    let extent = [0, 1, 2].map(|k| spread.max[k] - spread.min[k]);
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap_or(0);
+    if order.len() <= LEAF_SIZE || extent[axis].is_nan() || extent[axis] <= 0.0 {
+        return index as u32;
+    }
+
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |&a, &b| centre(a)[axis].total_cmp(&centre(b)[axis]));
+    let (left, right) = order.split_at_mut(mid);
+    split(boxes, left, offset, nodes);
+    let right = split(boxes, right, offset + mid, nodes);
+    nodes[index].start = right;
+    nodes[index].count = 0;
+    index as u32
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ray {
+    origin: [f64; 3],
+    /// Unit length, so distances along the ray are in mm.
+    dir: [f64; 3],
+    inv_dir: [f64; 3],
+}
+
+impl Ray {
+    fn new(origin: [f64; 3], dir: [f64; 3]) -> Ray {
+        Ray {
+            origin,
+            dir,
+            inv_dir: dir.map(|c| 1.0 / c),
+        }
+    }
+
+    /// Where the ray enters `b`, if it does before `t_max`.
+    fn enters(&self, b: &Aabb, t_max: f64) -> Option<f64> {
+        let (mut t0, mut t1) = (0.0f64, t_max);
+        for i in 0..3 {
+            let a = (b.min[i] - self.origin[i]) * self.inv_dir[i];
+            let c = (b.max[i] - self.origin[i]) * self.inv_dir[i];
+            // A NaN, from a ray in a box face's plane, constrains nothing.
+            t0 = t0.max(a.min(c));
+            t1 = t1.min(a.max(c));
+        }
+        (t0 <= t1).then_some(t0)
+    }
+}
+
+/// A hierarchy over one mesh's facets, in the mesh's frame.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleBvh {
+    hierarchy: Hierarchy,
+}
+
+impl TriangleBvh {
+    pub fn build(mesh: &TriangleMesh) -> TriangleBvh {
+        let boxes: Vec<Aabb> = (0..mesh.triangle_count())
+            .map(|f| {
+                let mut b = Aabb::EMPTY;
+                for p in triangle(mesh, f).into_iter().flatten() {
+                    b.include(p);
+                }
+                b
+            })
+            .collect();
+        TriangleBvh {
+            hierarchy: Hierarchy::build(&boxes),
+        }
+    }
+
+    /// The box around all the facets, or `None` without any.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.hierarchy.bounds()
+    }
+
+    /// The nearest facet of `mesh` that the ray from `origin` along the unit
+    /// vector `dir` meets within `t_max`, with its distance. `mesh` must be
+    /// the one the hierarchy was built from.
+    pub fn intersect(
+        &self,
+        mesh: &TriangleMesh,
+        origin: [f64; 3],
+        dir: [f64; 3],
+        t_max: f64,
+    ) -> Option<(usize, f64)> {
+        let ray = Ray::new(origin, dir);
+        let mut nearest = None;
+        self.hierarchy.nearest(&ray, t_max, |f, best| {
+            let t = triangle(mesh, f as usize).and_then(|p| ray_triangle(&ray, p, best))?;
+            nearest = Some((f as usize, t));
+            Some(t)
+        });
+        nearest
+    }
+}
+
+/// Offer `pair` every facet of `a` and facet of `b`, placed as given, whose
+/// boxes are closer than the nearest distance found so far, starting from
+/// `max_distance`. `pair` returns the facets' distance when it is below the
+/// one it is given, and the search narrows to that; at 0 it stops.
+pub(crate) fn nearest_pairs(
+    a: (&TriangleBvh, &Placement),
+    b: (&TriangleBvh, &Placement),
+    max_distance: f64,
+    mut pair: impl FnMut(u32, u32, f64) -> Option<f64>,
+) {
+    let (ha, hb) = (&a.0.hierarchy, &b.0.hierarchy);
+    if ha.nodes.is_empty() || hb.nodes.is_empty() {
+        return;
+    }
+    let world_a = |i: u32| ha.nodes[i as usize].bounds.placed(a.1).aabb();
+    let world_b = |j: u32| hb.nodes[j as usize].bounds.placed(b.1).aabb();
+    let mut best = max_distance;
+    let mut stack = vec![(0u32, 0u32)];
+    while let Some((i, j)) = stack.pop() {
+        let (box_a, box_b) = (world_a(i), world_b(j));
+        if box_distance(&box_a, &box_b) >= best {
+            continue;
+        }
+        let (na, nb) = (&ha.nodes[i as usize], &hb.nodes[j as usize]);
+        // Open the inner node with the larger box, or the only one.
+        let open_a = match (na.count == 0, nb.count == 0) {
+            (false, false) => {
+                for &fa in &ha.order[na.start as usize..(na.start + na.count) as usize] {
+                    for &fb in &hb.order[nb.start as usize..(nb.start + nb.count) as usize] {
+                        if let Some(d) = pair(fa, fb, best) {
+                            best = best.min(d);
+                            if best <= 0.0 {
+                                return;
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => diagonal(&box_a) >= diagonal(&box_b),
+        };
+        if open_a {
+            stack.push((na.start, j));
+            stack.push((i + 1, j));
+        } else {
+            stack.push((i, nb.start));
+            stack.push((i, j + 1));
+        }
+    }
+}
+
+/// The gap between two boxes; 0 where they touch or overlap.
+pub(super) fn box_distance(a: &Aabb, b: &Aabb) -> f64 {
+    let gap = [0, 1, 2].map(|k| (a.min[k] - b.max[k]).max(b.min[k] - a.max[k]).max(0.0));
+    dot(gap, gap).sqrt()
+}
+
+fn diagonal(b: &Aabb) -> f64 {
+    let d = sub(b.max, b.min);
+    dot(d, d)
+}
+
+/// The corners of facet `f`, or `None` if it indexes past the positions.
+pub(super) fn triangle(mesh: &TriangleMesh, f: usize) -> Option<[[f64; 3]; 3]> {
+    let tri = mesh.indices.get(3 * f..3 * f + 3)?;
+    let mut out = [[0.0; 3]; 3];
+    for (corner, &v) in out.iter_mut().zip(tri) {
+        let p = mesh.positions.get(3 * v as usize..3 * v as usize + 3)?;
+        *corner = [p[0] as f64, p[1] as f64, p[2] as f64];
+    }
+    Some(out)
+}
+
+/// Möller-Trumbore, from either side.
+fn ray_triangle(ray: &Ray, p: [[f64; 3]; 3], t_max: f64) -> Option<f64> {
+    let e1 = sub(p[1], p[0]);
+    let e2 = sub(p[2], p[0]);
+    let h = cross(ray.dir, e2);
+    let det = dot(e1, h);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let s = sub(ray.origin, p[0]);
+    let u = dot(s, h) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = cross(s, e1);
+    let v = dot(ray.dir, q) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = dot(e2, q) / det;
+    (t > MIN_DISTANCE && t < t_max).then_some(t)
+}
+
+/// The nearest surface a ray meets in the placed scene.
+#[derive(Debug, Clone, Serialize)]
+pub struct RayHit {
+    /// The scene graph's instance path.
+    pub instance_id: String,
+    pub solid_name: String,
+    pub volume_name: String,
+    pub material_name: String,
+    /// In g/cm3, when the material's density could be evaluated.
+    pub density: Option<f64>,
+    /// From the ray's origin, in mm.
+    pub distance: f64,
+    pub point: [f64; 3],
+    /// The facet's unit normal in world space, pointing out of the solid.
+    pub normal: [f64; 3],
+    /// The facet's index in the solid's mesh.
+    pub triangle: usize,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedInstance {
+    instance_id: String,
+    solid_name: String,
+    volume_name: String,
+    material_name: String,
+    density: Option<f64>,
+    to_world: Placement,
+    to_local: Placement,
+}
+
+/// Spatial index of every placed instance that has a mesh. The world volume
+/// is left out, as it is from the view.
+#[derive(Debug, Clone, Default)]
+pub struct SceneIndex {
+    solids: HashMap<String, TriangleBvh>,
+    instances: Vec<IndexedInstance>,
+    hierarchy: Hierarchy,
+}
+
+impl SceneIndex {
+    pub fn build(root: &SceneNode, meshes: &HashMap<String, TriangleMesh>) -> SceneIndex {
+        let mut index = SceneIndex::default();
+        index.update(root, meshes, &[]);
+        index
+    }
+
+    /// Bring the index in line with `root` and `meshes` after an edit. The
+    /// facet hierarchies of `remeshed` solids and of solids not yet indexed
+    /// are built, those of solids without a mesh dropped, and the rest kept.
+    pub fn update(
+        &mut self,
+        root: &SceneNode,
+        meshes: &HashMap<String, TriangleMesh>,
+        remeshed: &[&str],
+    ) {
+        self.solids
+            .retain(|name, _| meshes.contains_key(name) && !remeshed.contains(&name.as_str()));
+        for (name, mesh) in meshes {
+            if !self.solids.contains_key(name) && mesh.triangle_count() > 0 {
+                self.solids.insert(name.clone(), TriangleBvh::build(mesh));
+            }
+        }
+
+        self.instances.clear();
+        let mut boxes = Vec::new();
+        for_each_instance(root, |node, placement| {
+            let Some(local) = self
+                .solids
+                .get(&node.solid_name)
+                .and_then(TriangleBvh::bounds)
+            else {
+                return;
+            };
+            boxes.push(local.placed(placement).aabb());
+            self.instances.push(IndexedInstance {
+                instance_id: node.instance_id.clone(),
+                solid_name: node.solid_name.clone(),
+                volume_name: node.volume_name.clone(),
+                material_name: node.material_name.clone(),
+                density: node.density,
+                to_world: *placement,
+                to_local: placement.inverse(),
+            });
+        });
+        self.hierarchy = Hierarchy::build(&boxes);
+    }
+
+    /// The facet hierarchy of a solid, in its own frame.
+    pub fn solid(&self, name: &str) -> Option<&TriangleBvh> {
+        self.solids.get(name)
+    }
+
+    /// Placed instances in the index.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// The nearest hit of the ray from `origin` along `direction`, within
+    /// `max_distance`. `None` when nothing is hit or `direction` is zero.
+    pub fn ray(
+        &self,
+        meshes: &HashMap<String, TriangleMesh>,
+        origin: [f64; 3],
+        direction: [f64; 3],
+        max_distance: f64,
+    ) -> Option<RayHit> {
+        let len = dot(direction, direction).sqrt();
+        if len <= 0.0 || !len.is_finite() {
+            return None;
+        }
+        let dir = direction.map(|c| c / len);
+        let ray = Ray::new(origin, dir);
+
+        let mut nearest: Option<(usize, usize, f64)> = None;
+        self.hierarchy.nearest(&ray, max_distance, |i, best| {
+            let inst = &self.instances[i as usize];
+            let mesh = meshes.get(&inst.solid_name)?;
+            let bvh = self.solids.get(&inst.solid_name)?;
+            let local_origin = inst.to_local.apply(origin);
+            let local_dir = inst.to_local.rotate(dir);
+            let (f, t) = bvh.intersect(mesh, local_origin, local_dir, best)?;
+            nearest = Some((i as usize, f, t));
+            Some(t)
+        });
+
+        let (i, f, t) = nearest?;
+        let inst = &self.instances[i];
+        let p = triangle(&meshes[&inst.solid_name], f)?;
+        let n = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+        let n_len = dot(n, n).sqrt();
+        Some(RayHit {
+            instance_id: inst.instance_id.clone(),
+            solid_name: inst.solid_name.clone(),
+            volume_name: inst.volume_name.clone(),
+            material_name: inst.material_name.clone(),
+            density: inst.density,
+            distance: t,
+            point: [0, 1, 2].map(|k| origin[k] + t * dir[k]),
+            normal: inst.to_world.rotate(n.map(|c| c / n_len)),
+            triangle: f,
+        })
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use crate::mesh::primitives::sphere_mesh::tessellate_sphere;
+    use std::f64::consts::PI;
+
+    fn node(name: &str, solid: &str, position: [f64; 3], rotation: [f64; 3]) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            instance_id: format!("/World/{}", name),
+            volume_name: name.to_string(),
+            solid_name: solid.to_string(),
+            material_name: "Iron".to_string(),
+            color: None,
+            density: None,
+            position,
+            rotation,
+            is_world: false,
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn facet_hierarchy_finds_what_brute_force_finds() {
+        let sphere = tessellate_sphere(4.0, 10.0, 0.0, 2.0 * PI, 0.0, PI, 24, 12);
+        let bvh = TriangleBvh::build(&sphere);
+        let mut hits = 0;
+        // Rays from a spread of points towards a spread of targets, some
+        // missing, some starting inside the shell.
+        for k in 0..200 {
+            let a = k as f64 * 0.37;
+            let origin = [
+                15.0 * a.cos(),
+                15.0 * (1.3 * a).sin(),
+                7.0 * (0.7 * a).cos(),
+            ];
+            let origin = if k % 5 == 0 {
+                origin.map(|c| c / 2.5)
+            } else {
+                origin
+            };
+            let target = [3.0 * (2.1 * a).sin(), 11.0 * (0.5 * a).cos(), 2.0 * a.sin()];
+            let d = sub(target, origin);
+            let dir = d.map(|c| c / dot(d, d).sqrt());
+
+            let ray = Ray::new(origin, dir);
+            let brute = (0..sphere.triangle_count())
+                .filter_map(|f| ray_triangle(&ray, triangle(&sphere, f)?, f64::INFINITY))
+                .min_by(f64::total_cmp);
+            let found = bvh.intersect(&sphere, origin, dir, f64::INFINITY);
+            match (brute, found) {
+                (None, None) => {}
+                (Some(t), Some((_, u))) => {
+                    assert!((t - u).abs() < 1e-9, "ray {}", k);
+                    hits += 1;
+                }
+                other => panic!("ray {}: {:?}", k, other),
+            }
+        }
+        assert!(hits > 150, "{} hits", hits);
+    }
+
+    #[test]
+    fn rays_pick_the_nearest_placed_instance() {
+        let mut meshes = HashMap::new();
+        meshes.insert("Slab".to_string(), tessellate_box(2.0, 20.0, 20.0));
+        let scene = SceneNode {
+            is_world: true,
+            children: vec![
+                node("Far", "Slab", [50.0, 0.0, 0.0], [0.0; 3]),
+                node("Near", "Slab", [20.0, 0.0, 0.0], [0.0; 3]),
+                // Turned a quarter about z, so its thin side faces y.
+                node("Side", "Slab", [0.0, 30.0, 0.0], [0.0, 0.0, PI / 2.0]),
+            ],
+            ..node("World", "Missing", [0.0; 3], [0.0; 3])
+        };
+        let index = SceneIndex::build(&scene, &meshes);
+        assert_eq!(index.instance_count(), 3);
+
+        let hit = index
+            .ray(&meshes, [0.0; 3], [1.0, 0.0, 0.0], f64::INFINITY)
+            .unwrap();
+        assert_eq!(hit.instance_id, "/World/Near");
+        assert!((hit.distance - 19.0).abs() < 1e-9);
+        assert!((hit.normal[0] + 1.0).abs() < 1e-9, "{:?}", hit.normal);
+        assert!(index
+            .ray(&meshes, [0.0; 3], [1.0, 0.0, 0.0], 10.0)
+            .is_none());
+
+        // The turned slab is 2 mm thick along y and meets the ray at 29.
+        let hit = index
+            .ray(&meshes, [0.0; 3], [0.0, 3.0, 0.0], f64::INFINITY)
+            .unwrap();
+        assert_eq!(hit.instance_id, "/World/Side");
+        assert!((hit.distance - 29.0).abs() < 1e-9);
+        assert!((hit.normal[1] + 1.0).abs() < 1e-9, "{:?}", hit.normal);
+        assert!(index.ray(&meshes, [0.0; 3], [0.0; 3], 1.0).is_none());
+
+        // A thicker slab after an edit: only its facets are indexed again.
+        meshes.insert("Slab".to_string(), tessellate_box(10.0, 20.0, 20.0));
+        let mut index = index;
+        index.update(&scene, &meshes, &["Slab"]);
+        let hit = index
+            .ray(&meshes, [0.0; 3], [1.0, 0.0, 0.0], f64::INFINITY)
+            .unwrap();
+        assert!((hit.distance - 15.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,443 @@
+//! Clearances: where placed volumes come closer than a tolerance.
+//!
+//! Two kinds of neighbour are measured, both surface to surface in world
+//! space: daughters of the same mother against each other, and each daughter
+//! against its mother's surface, which from inside is the wall it must clear.
+//! Facets that cross count as distance 0, so an overlap of the surfaces shows
+//! as no clearance at all. A volume wholly inside a sibling has its surface
+//! clear of the sibling's and is measured by that gap; telling it from a
+//! real gap is an overlap check's job, not this one's.
+//!
+//! Candidates are narrowed twice: siblings by their world boxes, grown by the
+//! tolerance, and then facets through the solids' hierarchies in
+//! [`SceneIndex`], which the search only ever opens within the tolerance.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::bounds::Aabb;
+use super::bvh::{box_distance, nearest_pairs, triangle, SceneIndex, TriangleBvh};
+use super::placement::Placement;
+use super::types::TriangleMesh;
+use crate::gdml::model::SceneNode;
+
+/// Tolerance in mm when none is given.
+pub const DEFAULT_CLEARANCE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Neighbour {
+    /// `second` is a daughter of the same mother as `first`.
+    Sibling,
+    /// `second` is the mother of `first`.
+    Mother,
+}
+
+/// Two volumes closer than the tolerance, and where.
+#[derive(Debug, Clone, Serialize)]
+pub struct Clearance {
+    pub kind: Neighbour,
+    /// Instance paths.
+    pub first: String,
+    pub second: String,
+    /// Between the surfaces, in mm.
+    pub distance: f64,
+    /// The closest points, on `first` and on `second`, in world space.
+    pub first_point: [f64; 3],
+    pub second_point: [f64; 3],
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClearanceReport {
+    /// Closest first.
+    pub clearances: Vec<Clearance>,
+    /// Pairs whose facets were searched.
+    pub pairs_checked: usize,
+}
+
+/// A node with a mesh, where it is placed.
+struct Placed<'a> {
+    node: &'a SceneNode,
+    placement: Placement,
+    bvh: &'a TriangleBvh,
+    mesh: &'a TriangleMesh,
+    aabb: Aabb,
+}
+
+/// Every sibling pair and daughter-mother pair below `root` whose surfaces
+/// come closer than `tolerance` to each other.
+pub fn clearances(
+    root: &SceneNode,
+    meshes: &HashMap<String, TriangleMesh>,
+    index: &SceneIndex,
+    tolerance: f64,
+) -> ClearanceReport {
+    let mut report = ClearanceReport::default();
+    visit(
+        root,
+        Placement::of_node(root),
+        meshes,
+        index,
+        tolerance,
+        &mut report,
+    );
+    report
+        .clearances
+        .sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    report
+}
+
+fn placed<'a>(
+    node: &'a SceneNode,
+    placement: Placement,
+    meshes: &'a HashMap<String, TriangleMesh>,
+    index: &'a SceneIndex,
+) -> Option<Placed<'a>> {
+    let mesh = meshes.get(&node.solid_name)?;
+    let bvh = index.solid(&node.solid_name)?;
+    let aabb = bvh.bounds()?.placed(&placement).aabb();
+    Some(Placed {
+        node,
+        placement,
+        bvh,
+        mesh,
+        aabb,
+    })
+}
+
+fn visit(
+    node: &SceneNode,
+    placement: Placement,
+    meshes: &HashMap<String, TriangleMesh>,
+    index: &SceneIndex,
+    tolerance: f64,
+    report: &mut ClearanceReport,
+) {
+    let mother = placed(node, placement, meshes, index);
+    let daughters: Vec<(Placement, Option<Placed>)> = node
+        .children
+        .iter()
+        .map(|child| {
+            let p = placement.then(&Placement::of_node(child));
+            (p, placed(child, p, meshes, index))
+        })
+        .collect();
+
+    let mut check = |a: &Placed, b: &Placed, kind: Neighbour| {
+        report.pairs_checked += 1;
+        if let Some((distance, first_point, second_point)) = closest(a, b, tolerance) {
+            report.clearances.push(Clearance {
+                kind,
+                first: a.node.instance_id.clone(),
+                second: b.node.instance_id.clone(),
+                distance,
+                first_point,
+                second_point,
+            });
+        }
+    };
+
+    let mut placed_daughters: Vec<&Placed> =
+        daughters.iter().filter_map(|d| d.1.as_ref()).collect();
+    if let Some(mother) = &mother {
+        for daughter in &placed_daughters {
+            check(daughter, mother, Neighbour::Mother);
+        }
+    }
+    // Sweep along x over the boxes grown by the tolerance.
+    placed_daughters.sort_by(|a, b| a.aabb.min[0].total_cmp(&b.aabb.min[0]));
+    for (i, a) in placed_daughters.iter().enumerate() {
+        for b in &placed_daughters[i + 1..] {
+            if b.aabb.min[0] > a.aabb.max[0] + tolerance {
+                break;
+            }
+            if box_distance(&a.aabb, &b.aabb) <= tolerance {
+                check(a, b, Neighbour::Sibling);
+            }
+        }
+    }
+
+    for (child, (p, _)) in node.children.iter().zip(&daughters) {
+        visit(child, *p, meshes, index, tolerance, report);
+    }
+}
+
+/// The closest points of two placed meshes, if they come closer than
+/// `tolerance`.
+fn closest(a: &Placed, b: &Placed, tolerance: f64) -> Option<(f64, [f64; 3], [f64; 3])> {
+    let world =
+        |p: &Placed, f: u32| triangle(p.mesh, f as usize).map(|t| t.map(|v| p.placement.apply(v)));
+    let mut found = None;
+    nearest_pairs(
+        (a.bvh, &a.placement),
+        (b.bvh, &b.placement),
+        tolerance,
+        |fa, fb, best| {
+            let (ta, tb) = (world(a, fa)?, world(b, fb)?);
+            if box_distance(&corners_box(ta), &corners_box(tb)) >= best {
+                return None;
+            }
+            let (d, pa, pb) = triangle_distance(ta, tb);
+            if d >= best {
+                return None;
+            }
+            found = Some((d, pa, pb));
+            Some(d)
+        },
+    );
+    found
+}
+
+fn corners_box(t: [[f64; 3]; 3]) -> Aabb {
+    let mut b = Aabb::EMPTY;
+    for p in t {
+        b.include(p);
+    }
+    b
+}
+
+/// The distance between two triangles and its closest points, 0 with a
+/// common point where they cross.
+fn triangle_distance(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> (f64, [f64; 3], [f64; 3]) {
+    for (s, t) in [(a, b), (b, a)] {
+        for k in 0..3 {
+            if let Some(x) = segment_crosses(s[k], s[(k + 1) % 3], t) {
+                return (0.0, x, x);
+            }
+        }
+    }
+
+    let mut best = (f64::INFINITY, a[0], b[0]);
+    let mut consider = |pa: [f64; 3], pb: [f64; 3]| {
+        let d = sub(pa, pb);
+        let d = dot(d, d).sqrt();
+        if d < best.0 {
+            best = (d, pa, pb);
+        }
+    };
+    for k in 0..3 {
+        consider(a[k], closest_on_triangle(a[k], b));
+        consider(closest_on_triangle(b[k], a), b[k]);
+        for m in 0..3 {
+            let (pa, pb) = closest_on_segments(a[k], a[(k + 1) % 3], b[m], b[(m + 1) % 3]);
+            consider(pa, pb);
+        }
+    }
+    best
+}
+
+/// Where segment `p`-`q` passes through triangle `t`, if it does.
+fn segment_crosses(p: [f64; 3], q: [f64; 3], t: [[f64; 3]; 3]) -> Option<[f64; 3]> {
+    let d = sub(q, p);
+    let e1 = sub(t[1], t[0]);
+    let e2 = sub(t[2], t[0]);
+    let h = cross(d, e2);
+    let det = dot(e1, h);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let s = sub(p, t[0]);
+    let u = dot(s, h) / det;
+    let r = cross(s, e1);
+    let v = dot(d, r) / det;
+    let along = dot(e2, r) / det;
+    let inside = (0.0..=1.0).contains(&u) && v >= 0.0 && u + v <= 1.0;
+    (inside && (0.0..=1.0).contains(&along)).then(|| [0, 1, 2].map(|k| p[k] + along * d[k]))
+}
+
+/// The point of triangle `t` closest to `p` (Ericson, Real-Time Collision
+/// Detection, 5.1.5).
+fn closest_on_triangle(p: [f64; 3], t: [[f64; 3]; 3]) -> [f64; 3] {
+    let [a, b, c] = t;
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    let ap = sub(p, a);
+    let (d1, d2) = (dot(ab, ap), dot(ac, ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = sub(p, b);
+    let (d3, d4) = (dot(ab, bp), dot(ac, bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return along(a, ab, d1 / (d1 - d3));
+    }
+    let cp = sub(p, c);
+    let (d5, d6) = (dot(ab, cp), dot(ac, cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return along(a, ac, d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return along(b, sub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = va + vb + vc;
+    if denom == 0.0 || denom.is_nan() {
+        // No area: its edges have been measured already.
+        return a;
+    }
+    let (v, w) = (vb / denom, vc / denom);
+    [0, 1, 2].map(|k| a[k] + ab[k] * v + ac[k] * w)
+}
+
+/// The closest points of segments `p1`-`q1` and `p2`-`q2` (Ericson, 5.1.9).
+fn closest_on_segments(
+    p1: [f64; 3],
+    q1: [f64; 3],
+    p2: [f64; 3],
+    q2: [f64; 3],
+) -> ([f64; 3], [f64; 3]) {
+    const EPS: f64 = 1e-18;
+    let d1 = sub(q1, p1);
+    let d2 = sub(q2, p2);
+    let r = sub(p1, p2);
+    let (a, e, f) = (dot(d1, d1), dot(d2, d2), dot(d2, r));
+    let (s, t) = if a <= EPS && e <= EPS {
+        (0.0, 0.0)
+    } else if a <= EPS {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = dot(d1, r);
+        if e <= EPS {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = dot(d1, d2);
+            let denom = a * e - b * b;
+            let s = if denom > 0.0 {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+    (along(p1, d1, s), along(p2, d2, t))
+}
+
+fn along(p: [f64; 3], d: [f64; 3], s: f64) -> [f64; 3] {
+    [0, 1, 2].map(|k| p[k] + s * d[k])
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use std::f64::consts::FRAC_PI_4;
+
+    fn node(name: &str, solid: &str, x: f64, turn: f64) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            instance_id: format!("/World/{}", name),
+            volume_name: name.to_string(),
+            solid_name: solid.to_string(),
+            material_name: "Iron".to_string(),
+            color: None,
+            density: None,
+            position: [x, 0.0, 0.0],
+            rotation: [0.0, 0.0, turn],
+            is_world: false,
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn triangle_distances() {
+        let flat = [[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [0.0, 10.0, 0.0]];
+        let above = flat.map(|p| [p[0], p[1], 0.3]);
+        let (d, pa, pb) = triangle_distance(flat, above);
+        assert!((d - 0.3).abs() < 1e-12);
+        assert!((pb[2] - pa[2] - 0.3).abs() < 1e-12);
+
+        // Standing through the first one.
+        let crossing = [[1.0, 1.0, -1.0], [1.0, 1.0, 1.0], [3.0, 1.0, 1.0]];
+        assert_eq!(triangle_distance(flat, crossing).0, 0.0);
+
+        // Edge to edge, skew: the x edge of one over the y edge of the other.
+        let a = [[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [5.0, -5.0, 0.0]];
+        let b = [[2.0, -3.0, 2.0], [2.0, 3.0, 2.0], [2.0, 0.0, 9.0]];
+        let (d, pa, pb) = triangle_distance(a, b);
+        assert!((d - 2.0).abs() < 1e-12, "{}", d);
+        assert!((pa[0] - 2.0).abs() < 1e-12 && pb[1].abs() < 1e-12);
+    }
+
+    #[test]
+    fn close_siblings_and_walls_are_reported() {
+        let mut meshes = HashMap::new();
+        meshes.insert("Hall".to_string(), tessellate_box(100.0, 100.0, 100.0));
+        meshes.insert("Cube".to_string(), tessellate_box(10.0, 10.0, 10.0));
+        let scene = SceneNode {
+            is_world: true,
+            children: vec![
+                // 0.2 mm off the hall's -x wall.
+                node("A", "Cube", -44.8, 0.0),
+                // 0.3 mm from A.
+                node("B", "Cube", -34.5, 0.0),
+                // Turned on its edge, reaching to 5 * sqrt(2) past 42.
+                node("C", "Cube", 42.0, FRAC_PI_4),
+            ],
+            ..node("World", "Hall", 0.0, 0.0)
+        };
+        let index = SceneIndex::build(&scene, &meshes);
+
+        let report = clearances(&scene, &meshes, &index, 1.0);
+        let found: Vec<(&str, &str, Neighbour)> = report
+            .clearances
+            .iter()
+            .map(|c| (c.first.as_str(), c.second.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("/World/A", "/World/World", Neighbour::Mother),
+                ("/World/A", "/World/B", Neighbour::Sibling),
+                ("/World/C", "/World/World", Neighbour::Mother),
+            ]
+        );
+        let [a_wall, a_b, c_wall] = &report.clearances[..] else {
+            unreachable!()
+        };
+        assert!((a_wall.distance - 0.2).abs() < 1e-4);
+        assert!((a_wall.first_point[0] + 49.8).abs() < 1e-4);
+        assert!((a_wall.second_point[0] + 50.0).abs() < 1e-4);
+        assert!((a_b.distance - 0.3).abs() < 1e-4);
+        let reach = 42.0 + 50f64.sqrt();
+        assert!((c_wall.distance - (50.0 - reach)).abs() < 1e-4);
+        assert!((c_wall.first_point[0] - reach).abs() < 1e-4);
+        assert!(c_wall.first_point[1].abs() < 1e-4);
+
+        // Below a tighter tolerance only the closest remain.
+        let report = clearances(&scene, &meshes, &index, 0.25);
+        assert_eq!(report.clearances.len(), 1);
+        assert_eq!(report.clearances[0].second, "/World/World");
+    }
+}
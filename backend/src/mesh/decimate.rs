@@ -0,0 +1,452 @@
+//! Quadric-error edge collapse (Garland & Heckbert, 1997).
+//!
+//! Every vertex carries the sum of the squared-distance quadrics of the planes
+//! of its facets. Collapsing an edge merges the two quadrics, and the cost of
+//! the merged vertex at a position is its summed squared distance to all
+//! those planes, so the cheapest edges are the ones lying in flat or gently
+//! curved regions. The merged vertex goes to whichever of the two ends or
+//! the midpoint costs least: the optimum of the quadric can lie off the
+//! surface of a curved solid, while the ends are on it.
+//!
+//! Collapses that would fold a facet over, or pinch the surface where the two
+//! ends share more neighbours than the facets between them (the link
+//! condition), are skipped, so a closed manifold mesh stays closed and
+//! manifold. Open edges get an extra plane at right angles to their facet, so
+//! a hole keeps its outline rather than growing.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::integrity;
+use super::types::TriangleMesh;
+
+/// Weight of the plane that pins an open edge, relative to a facet plane.
+const BOUNDARY_WEIGHT: f64 = 100.0;
+
+/// Smallest cosine between a facet's normal before and after a collapse; a
+/// facet turning further than about 78° is taken to fold over.
+const MIN_NORMAL_COS: f64 = 0.2;
+
+/// Facets meeting at more than this angle (about 40°) keep separate normals
+/// at their common vertices, so the edges of a box stay sharp while a
+/// cylinder shades smoothly.
+const CREASE_COS: f64 = 0.766;
+
+/// Symmetric 4x4 matrix, upper triangle by rows.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Squared distance to the plane `n . p + d = 0`, `n` of unit length.
+    fn plane(n: [f64; 3], d: f64, weight: f64) -> Self {
+        let [a, b, c] = n;
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|v| v * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let q = &self.0;
+        let [x, y, z] = p;
+        let e = q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        e.max(0.0)
+    }
+}
+
+/// A collapse of `from` into `into` at `position`, valid while neither
+/// vertex has changed since it was costed.
+struct Candidate {
+    cost: f64,
+    into: u32,
+    from: u32,
+    position: [f64; 3],
+    stamps: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    /// Reversed, so the binary heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Decimator {
+    positions: Vec<[f64; 3]>,
+    quadrics: Vec<Quadric>,
+    stamps: Vec<u32>,
+    removed: Vec<bool>,
+    faces: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    /// Facets using each vertex; may list dead facets until cleaned.
+    incident: Vec<Vec<usize>>,
+}
+
+/// A simplification of `mesh` with at most `target` facets, or with as few as
+/// can be had without moving the surface by more than `max_error` mm.
+///
+/// The result is welded, so vertices are shared wherever the surface is
+/// smooth and split only along creases.
+pub fn decimate(mesh: &TriangleMesh, target: usize, max_error: f64) -> TriangleMesh {
+    let (weld, welded, _) = integrity::weld(mesh);
+    let faces: Vec<[u32; 3]> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|t| [0, 1, 2].map(|k| weld.get(t[k] as usize).copied().unwrap_or(0)))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .collect();
+    let mut d = Decimator {
+        positions: welded.iter().map(|p| p.map(|c| c as f64)).collect(),
+        quadrics: vec![Quadric::default(); welded.len()],
+        stamps: vec![0; welded.len()],
+        removed: vec![false; welded.len()],
+        alive: vec![true; faces.len()],
+        incident: vec![Vec::new(); welded.len()],
+        faces,
+    };
+    d.build_quadrics();
+    d.collapse_until(target, max_error * max_error);
+    d.output()
+}
+
+impl Decimator {
+    fn build_quadrics(&mut self) {
+        let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                self.incident[v as usize].push(f);
+            }
+            for (a, b) in edge_pairs(*face) {
+                edges.entry((a.min(b), a.max(b))).or_default().push(f);
+            }
+            let Some(n) = unit(self.normal(*face)) else {
+                continue;
+            };
+            let q = Quadric::plane(n, -dot(n, self.positions[face[0] as usize]), 1.0);
+            for &v in face {
+                self.quadrics[v as usize].add(&q);
+            }
+        }
+        for ((a, b), users) in &edges {
+            if users.len() != 1 {
+                continue;
+            }
+            let (pa, pb) = (self.positions[*a as usize], self.positions[*b as usize]);
+            let Some(facet) = unit(self.normal(self.faces[users[0]])) else {
+                continue;
+            };
+            let Some(n) = unit(cross(sub(pb, pa), facet)) else {
+                continue;
+            };
+            let q = Quadric::plane(n, -dot(n, pa), BOUNDARY_WEIGHT);
+            self.quadrics[*a as usize].add(&q);
+            self.quadrics[*b as usize].add(&q);
+        }
+    }
+
+    fn collapse_until(&mut self, target: usize, max_cost: f64) {
+        let mut heap = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        for face in &self.faces {
+            for (a, b) in edge_pairs(*face) {
+                if seen.insert((a.min(b), a.max(b))) {
+                    heap.push(self.candidate(a, b));
+                }
+            }
+        }
+
+        let mut live = self.faces.len();
+        while live > target {
+            let Some(c) = heap.pop() else {
+                break;
+            };
+            if c.cost > max_cost {
+                break;
+            }
+            let (u, v) = (c.into as usize, c.from as usize);
+            if self.removed[u] || self.removed[v] || c.stamps != (self.stamps[u], self.stamps[v]) {
+                continue;
+            }
+            if !self.can_collapse(c.into, c.from, c.position) {
+                continue;
+            }
+            live -= self.collapse(c.into, c.from, c.position);
+            for w in self.neighbours(c.into) {
+                heap.push(self.candidate(c.into, w));
+            }
+        }
+    }
+
+    fn candidate(&self, a: u32, b: u32) -> Candidate {
+        let mut q = self.quadrics[a as usize];
+        q.add(&self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let mid = [0, 1, 2].map(|i| 0.5 * (pa[i] + pb[i]));
+        let (cost, position) = [pa, pb, mid]
+            .into_iter()
+            .map(|p| (q.error(p), p))
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .unwrap_or((f64::INFINITY, mid));
+        Candidate {
+            cost,
+            into: a,
+            from: b,
+            position,
+            stamps: (self.stamps[a as usize], self.stamps[b as usize]),
+        }
+    }
+
+    fn live_faces(&self, v: u32) -> impl Iterator<Item = usize> + '_ {
+        self.incident[v as usize]
+            .iter()
+            .copied()
+            .filter(|&f| self.alive[f])
+    }
+
+    fn neighbours(&self, v: u32) -> HashSet<u32> {
+        self.live_faces(v)
+            .flat_map(|f| self.faces[f])
+            .filter(|&w| w != v)
+            .collect()
+    }
+
+    fn can_collapse(&self, u: u32, v: u32, p: [f64; 3]) -> bool {
+        let shared: Vec<usize> = self
+            .live_faces(u)
+            .filter(|&f| self.faces[f].contains(&v))
+            .collect();
+        if shared.is_empty() {
+            return false;
+        }
+        // Link condition: the only neighbours the two ends may have in common
+        // are the far corners of the facets between them.
+        let common = self.neighbours(u).intersection(&self.neighbours(v)).count();
+        let opposite: HashSet<u32> = shared
+            .iter()
+            .flat_map(|&f| self.faces[f])
+            .filter(|&w| w != u && w != v)
+            .collect();
+        if common != opposite.len() {
+            return false;
+        }
+
+        // No remaining facet may fold over or lose its area.
+        let moved: HashSet<usize> = self.live_faces(u).chain(self.live_faces(v)).collect();
+        moved.into_iter().all(|f| {
+            let face = self.faces[f];
+            if face.contains(&u) && face.contains(&v) {
+                return true;
+            }
+            let before = self.normal(face);
+            let corners = face.map(|w| {
+                if w == u || w == v {
+                    p
+                } else {
+                    self.positions[w as usize]
+                }
+            });
+            let after = cross(sub(corners[1], corners[0]), sub(corners[2], corners[0]));
+            let (nb, na) = (norm(before), norm(after));
+            na > 0.0 && dot(before, after) >= MIN_NORMAL_COS * nb * na
+        })
+    }
+
+    /// Merge `v` into `u` at `p`; returns the number of facets removed.
+    fn collapse(&mut self, u: u32, v: u32, p: [f64; 3]) -> usize {
+        let mut removed = 0;
+        for f in std::mem::take(&mut self.incident[v as usize]) {
+            if !self.alive[f] {
+                continue;
+            }
+            if self.faces[f].contains(&u) {
+                self.alive[f] = false;
+                removed += 1;
+            } else {
+                for w in self.faces[f].iter_mut() {
+                    if *w == v {
+                        *w = u;
+                    }
+                }
+                self.incident[u as usize].push(f);
+            }
+        }
+        let alive = &self.alive;
+        self.incident[u as usize].retain(|&f| alive[f]);
+        self.positions[u as usize] = p;
+        let q = self.quadrics[v as usize];
+        self.quadrics[u as usize].add(&q);
+        self.removed[v as usize] = true;
+        self.stamps[u as usize] += 1;
+        self.stamps[v as usize] += 1;
+        removed
+    }
+
+    fn normal(&self, face: [u32; 3]) -> [f64; 3] {
+        let [a, b, c] = face.map(|v| self.positions[v as usize]);
+        cross(sub(b, a), sub(c, a))
+    }
+
+    /// The surviving facets, each corner shaded with the facets around it
+    /// that meet its own facet at less than the crease angle.
+    fn output(&self) -> TriangleMesh {
+        let faces: Vec<usize> = (0..self.faces.len()).filter(|&f| self.alive[f]).collect();
+        // Area-weighted normal (the cross product itself) and unit normal.
+        let normals: HashMap<usize, ([f64; 3], [f64; 3])> = faces
+            .iter()
+            .filter_map(|&f| {
+                let n = self.normal(self.faces[f]);
+                unit(n).map(|u| (f, (n, u)))
+            })
+            .collect();
+
+        let mut out = TriangleMesh::new();
+        let mut emitted: HashMap<u32, Vec<([f32; 3], u32)>> = HashMap::new();
+        for &f in &faces {
+            let Some(&(_, own)) = normals.get(&f) else {
+                continue;
+            };
+            for v in self.faces[f] {
+                let mut sum = [0.0; 3];
+                for g in self.live_faces(v) {
+                    if let Some(&(weighted, n)) = normals.get(&g) {
+                        if dot(n, own) >= CREASE_COS {
+                            sum = [0, 1, 2].map(|i| sum[i] + weighted[i]);
+                        }
+                    }
+                }
+                let normal = unit(sum).unwrap_or(own).map(|c| c as f32);
+                let cluster = emitted.entry(v).or_default();
+                let index = match cluster.iter().find(|(n, _)| same_normal(*n, normal)) {
+                    Some(&(_, i)) => i,
+                    None => {
+                        let i = out.vertex_count() as u32;
+                        out.positions
+                            .extend(self.positions[v as usize].map(|c| c as f32));
+                        out.normals.extend_from_slice(&normal);
+                        cluster.push((normal, i));
+                        i
+                    }
+                };
+                out.indices.push(index);
+            }
+        }
+        out
+    }
+}
+
+fn edge_pairs([a, b, c]: [u32; 3]) -> [(u32, u32); 3] {
+    [(a, b), (b, c), (c, a)]
+}
+
+fn same_normal(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() <= 1e-5)
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn unit(a: [f64; 3]) -> Option<[f64; 3]> {
+    let n = norm(a);
+    (n > 0.0 && n.is_finite()).then(|| a.map(|c| c / n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::integrity::analyze;
+    use crate::mesh::primitives::{box_mesh, sphere_mesh};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn a_box_has_nothing_to_give() {
+        let cube = box_mesh::tessellate_box(10.0, 10.0, 10.0);
+        let out = decimate(&cube, 0, 0.01);
+        assert_eq!(out.triangle_count(), 12);
+        let report = analyze(&out);
+        assert!(report.is_clean(), "{:?}", report.problems());
+        assert!((report.volume - 1000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn a_sphere_stays_closed_and_close() {
+        let sphere = sphere_mesh::tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, PI, 64, 32);
+        let before = analyze(&sphere);
+        let out = decimate(&sphere, sphere.triangle_count() / 4, 2.0);
+        let after = analyze(&out);
+
+        assert!(out.triangle_count() <= sphere.triangle_count() / 4);
+        assert!(after.is_clean(), "{:?}", after.problems());
+        assert!((after.volume - before.volume).abs() < 0.02 * before.volume);
+        // Corners stay on or near the sphere.
+        for p in out.positions.chunks_exact(3) {
+            let r = p.iter().map(|c| (*c as f64).powi(2)).sum::<f64>().sqrt();
+            assert!((r - 50.0).abs() < 2.0, "vertex at radius {}", r);
+        }
+    }
+
+    #[test]
+    fn the_error_bound_stops_the_collapse() {
+        let sphere = sphere_mesh::tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, PI, 64, 32);
+        let coarse = decimate(&sphere, 0, 1.0);
+        let fine = decimate(&sphere, 0, 0.01);
+        assert!(coarse.triangle_count() < fine.triangle_count());
+        assert!(fine.triangle_count() > sphere.triangle_count() / 2);
+    }
+}
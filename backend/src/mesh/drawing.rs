@@ -0,0 +1,455 @@
+//! Line drawings of a [`Section`]: SVG and DXF, each with a material legend.
+//!
+//! Both are drawn at full scale in mm, with the plane's `v` axis pointing up.
+//! Materials get the colours the viewer gives them, so a drawing can be read
+//! against the 3D view.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::section::{Section, SectionInstance};
+
+/// Fallback palette for materials with neither an aux colour nor a density,
+/// the viewer's.
+const PALETTE: [[u8; 3]; 16] = [
+    [0x64, 0xB5, 0xF6],
+    [0x81, 0xC7, 0x84],
+    [0xFF, 0xB7, 0x4D],
+    [0xCE, 0x93, 0xD8],
+    [0xFF, 0x8A, 0x65],
+    [0x4D, 0xD0, 0xE1],
+    [0xAE, 0xD5, 0x81],
+    [0xF0, 0x62, 0x92],
+    [0xFF, 0xD5, 0x4F],
+    [0x9F, 0xA8, 0xDA],
+    [0xBC, 0xAA, 0xA4],
+    [0xB0, 0xBE, 0xC5],
+    [0xEF, 0x9A, 0x9A],
+    [0x4F, 0xC3, 0xF7],
+    [0xE6, 0xEE, 0x9C],
+    [0x80, 0xCB, 0xC4],
+];
+
+/// One legend entry per material, in order of first appearance.
+struct LegendEntry<'a> {
+    material: &'a str,
+    rgb: [u8; 3],
+}
+
+fn legend(section: &Section) -> Vec<LegendEntry<'_>> {
+    let mut seen = HashSet::new();
+    section
+        .instances
+        .iter()
+        .filter(|i| seen.insert(i.material_name.as_str()))
+        .map(|i| LegendEntry {
+            material: &i.material_name,
+            rgb: instance_rgb(i),
+        })
+        .collect()
+}
+
+/// The colour the viewer draws `instance` in: its aux colour unless that is
+/// near black, else a colour for its density, else one hashed from the
+/// material name.
+fn instance_rgb(instance: &SectionInstance) -> [u8; 3] {
+    if let Some(aux) = instance.color.as_deref() {
+        let channel = |i: usize| {
+            aux.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        if let (Some(r), Some(g), Some(b)) = (channel(0), channel(2), channel(4)) {
+            let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+            if luminance > 20.0 {
+                return [r, g, b];
+            }
+        }
+    }
+    if let Some(density) = instance.density.filter(|d| *d > 0.0) {
+        return density_rgb(density);
+    }
+    // The viewer's string hash, which works in JavaScript numbers: the shift
+    // wraps to 32 bits but the sum does not.
+    let mut hash = 0.0f64;
+    for unit in instance.material_name.encode_utf16() {
+        let shifted = (hash as i64 as i32).wrapping_shl(5) as f64;
+        hash = unit as f64 + (shifted - hash);
+    }
+    let n = PALETTE.len() as f64;
+    PALETTE[(((hash % n) + n) % n) as usize]
+}
+
+/// Light materials in cool bright colours, heavy ones in warm dark colours,
+/// on a log scale from 0.001 to 22 g/cm3.
+fn density_rgb(density: f64) -> [u8; 3] {
+    let d = density.clamp(0.001, 22.0);
+    let t = (d.ln() + 6.9) / (3.1 + 6.9);
+    let hue = if t < 0.5 {
+        200.0 - t * 2.0 * 155.0
+    } else if t < 0.8 {
+        45.0 - (t - 0.5) / 0.3 * 45.0
+    } else {
+        360.0 - (t - 0.8) / 0.2 * 80.0
+    };
+    let saturation = (60.0 + t * 15.0).round() / 100.0;
+    let lightness = (75.0 - t * 25.0).round() / 100.0;
+    hsl_to_rgb(hue.round(), saturation, lightness)
+}
+
+fn hsl_to_rgb(hue: f64, s: f64, l: f64) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Smallest and largest drawing coordinates, or a unit square for an empty
+/// section.
+fn bounds(section: &Section) -> ([f64; 2], [f64; 2]) {
+    let mut lo = [f64::INFINITY; 2];
+    let mut hi = [f64::NEG_INFINITY; 2];
+    for p in section
+        .instances
+        .iter()
+        .flat_map(|i| &i.polylines)
+        .flat_map(|p| &p.points)
+    {
+        for i in 0..2 {
+            lo[i] = lo[i].min(p[i]);
+            hi[i] = hi[i].max(p[i]);
+        }
+    }
+    if lo[0] > hi[0] {
+        return ([0.0, 0.0], [1.0, 1.0]);
+    }
+    (lo, hi)
+}
+
+/// Height of legend text: a fiftieth of the drawing's larger side.
+fn text_height(lo: [f64; 2], hi: [f64; 2]) -> f64 {
+    ((hi[0] - lo[0]).max(hi[1] - lo[1]) / 50.0).max(1e-3)
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The section as SVG. Each instance is one group holding a filled path of its
+/// closed loops, holes cut out with the even-odd rule, and a stroked path for
+/// any open outlines; the legend sits to the right.
+pub fn to_svg(section: &Section) -> String {
+    let (lo, hi) = bounds(section);
+    let h = text_height(lo, hi);
+    let entries = legend(section);
+    let legend_width = 12.0 * h
+        + entries
+            .iter()
+            .map(|e| e.material.chars().count())
+            .max()
+            .unwrap_or(0) as f64
+            * 0.6
+            * h;
+    let margin = 2.0 * h;
+    let left = lo[0] - margin;
+    let top = -hi[1] - margin;
+    let width = hi[0] - lo[0] + 2.0 * margin + legend_width;
+    let height = (hi[1] - lo[1] + 2.0 * margin).max((entries.len() as f64 * 1.5 + 3.0) * h);
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}mm" height="{height}mm" viewBox="{left} {top} {width} {height}">"#
+    );
+    for instance in &section.instances {
+        let [r, g, b] = instance_rgb(instance);
+        let colour = format!("#{:02x}{:02x}{:02x}", r, g, b);
+        let _ = writeln!(
+            svg,
+            r#"  <g id="{}" data-volume="{}" data-material="{}">"#,
+            escape_xml(&instance.instance_id),
+            escape_xml(&instance.volume_name),
+            escape_xml(&instance.material_name)
+        );
+        let mut filled = String::new();
+        let mut open = String::new();
+        for polyline in &instance.polylines {
+            let d = if polyline.closed {
+                &mut filled
+            } else {
+                &mut open
+            };
+            // SVG's y runs down; `0.0 - y` keeps zero from printing as -0.
+            for (i, p) in polyline.points.iter().enumerate() {
+                let _ = write!(
+                    d,
+                    "{}{} {} ",
+                    if i == 0 { "M" } else { "L" },
+                    p[0],
+                    0.0 - p[1]
+                );
+            }
+            if polyline.closed {
+                d.push_str("Z ");
+            }
+        }
+        if !filled.is_empty() {
+            let _ = writeln!(
+                svg,
+                r#"    <path d="{}" fill="{colour}" fill-opacity="0.35" fill-rule="evenodd" stroke="{colour}" stroke-width="1" vector-effect="non-scaling-stroke"/>"#,
+                filled.trim_end()
+            );
+        }
+        if !open.is_empty() {
+            let _ = writeln!(
+                svg,
+                r#"    <path d="{}" fill="none" stroke="{colour}" stroke-width="1" stroke-dasharray="4 2" vector-effect="non-scaling-stroke"/>"#,
+                open.trim_end()
+            );
+        }
+        let _ = writeln!(svg, "  </g>");
+    }
+
+    let x = hi[0] + margin;
+    let _ = writeln!(
+        svg,
+        r#"  <g id="legend" font-family="sans-serif" font-size="{h}">"#
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        let [r, g, b] = entry.rgb;
+        let y = top + margin + i as f64 * 1.5 * h;
+        let _ = writeln!(
+            svg,
+            r##"    <rect x="{x}" y="{y}" width="{h}" height="{h}" fill="#{r:02x}{g:02x}{b:02x}" stroke="black" stroke-width="0.5" vector-effect="non-scaling-stroke"/>"##
+        );
+        let _ = writeln!(
+            svg,
+            r#"    <text x="{}" y="{}">{}</text>"#,
+            x + 1.5 * h,
+            y + 0.85 * h,
+            escape_xml(entry.material)
+        );
+    }
+    let _ = writeln!(svg, "  </g>");
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The section as an AutoCAD R12 DXF: one layer per material, coloured with
+/// the nearest of the standard colour indices, every outline a 2D POLYLINE on
+/// its material's layer, and the legend as swatches and TEXT on layer
+/// `LEGEND`.
+pub fn to_dxf(section: &Section) -> String {
+    let (lo, hi) = bounds(section);
+    let h = text_height(lo, hi);
+    let entries = legend(section);
+    let layers: HashMap<&str, String> = entries
+        .iter()
+        .map(|e| (e.material, layer_name(e.material)))
+        .collect();
+
+    let mut dxf = Dxf::default();
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "HEADER");
+    dxf.pair(9, "$ACADVER");
+    dxf.pair(1, "AC1009");
+    dxf.pair(0, "ENDSEC");
+
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "TABLES");
+    dxf.pair(0, "TABLE");
+    dxf.pair(2, "LAYER");
+    dxf.pair(70, entries.len() + 1);
+    dxf.layer("LEGEND", 7);
+    for entry in &entries {
+        dxf.layer(&layers[entry.material], nearest_aci(entry.rgb));
+    }
+    dxf.pair(0, "ENDTAB");
+    dxf.pair(0, "ENDSEC");
+
+    dxf.pair(0, "SECTION");
+    dxf.pair(2, "ENTITIES");
+    for instance in &section.instances {
+        let layer = &layers[instance.material_name.as_str()];
+        for polyline in &instance.polylines {
+            dxf.polyline(layer, &polyline.points, polyline.closed);
+        }
+    }
+    let x = hi[0] + 2.0 * h;
+    for (i, entry) in entries.iter().enumerate() {
+        let y = hi[1] - (i as f64 * 1.5 + 1.0) * h;
+        let swatch = [[x, y], [x + h, y], [x + h, y + h], [x, y + h]];
+        dxf.polyline(&layers[entry.material], &swatch, true);
+        dxf.pair(0, "TEXT");
+        dxf.pair(8, "LEGEND");
+        dxf.pair(10, x + 1.5 * h);
+        dxf.pair(20, y);
+        dxf.pair(30, 0.0);
+        dxf.pair(40, h);
+        dxf.pair(1, entry.material);
+    }
+    dxf.pair(0, "ENDSEC");
+    dxf.pair(0, "EOF");
+    dxf.out
+}
+
+/// DXF layer names may not hold `<>/\":;?*|=,` or backquotes.
+fn layer_name(material: &str) -> String {
+    let name: String = material
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_$.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "MATERIAL".to_string()
+    } else {
+        name
+    }
+}
+
+/// The closest of the fully saturated hues of the AutoCAD colour index, in
+/// steps of 15 degrees (indices 10, 20, ... 240), or grey for colours with
+/// hardly any saturation.
+fn nearest_aci(rgb: [u8; 3]) -> u32 {
+    let [r, g, b] = rgb.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max - min < 0.1 {
+        return if max > 0.5 { 9 } else { 8 };
+    }
+    let d = max - min;
+    let hue = if max == r {
+        60.0 * ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+    10 + 10 * ((hue / 15.0).round() as u32 % 24)
+}
+
+#[derive(Default)]
+struct Dxf {
+    out: String,
+}
+
+impl Dxf {
+    fn pair(&mut self, code: u32, value: impl std::fmt::Display) {
+        let _ = write!(self.out, "{}\n{}\n", code, value);
+    }
+
+    fn layer(&mut self, name: &str, colour: u32) {
+        self.pair(0, "LAYER");
+        self.pair(2, name);
+        self.pair(70, 0);
+        self.pair(62, colour);
+        self.pair(6, "CONTINUOUS");
+    }
+
+    fn polyline(&mut self, layer: &str, points: &[[f64; 2]], closed: bool) {
+        self.pair(0, "POLYLINE");
+        self.pair(8, layer);
+        self.pair(66, 1);
+        self.pair(70, u32::from(closed));
+        for p in points {
+            self.pair(0, "VERTEX");
+            self.pair(8, layer);
+            self.pair(10, p[0]);
+            self.pair(20, p[1]);
+            self.pair(30, 0.0);
+        }
+        self.pair(0, "SEQEND");
+        self.pair(8, layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::section::Polyline;
+
+    fn square(material: &str, x: f64, color: Option<&str>) -> SectionInstance {
+        SectionInstance {
+            instance_id: format!("/World/{}", material),
+            volume_name: material.to_string(),
+            solid_name: "Square".to_string(),
+            material_name: material.to_string(),
+            color: color.map(str::to_string),
+            density: None,
+            polylines: vec![Polyline {
+                points: vec![[x, 0.0], [x + 10.0, 0.0], [x + 10.0, 10.0], [x, 10.0]],
+                closed: true,
+            }],
+        }
+    }
+
+    fn section() -> Section {
+        Section {
+            origin: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            u: [1.0, 0.0, 0.0],
+            v: [0.0, 1.0, 0.0],
+            instances: vec![
+                square("G4_Fe", 0.0, Some("ff0000")),
+                square("G4_Fe", 20.0, None),
+                square("Lead<Pb>", 40.0, None),
+            ],
+        }
+    }
+
+    #[test]
+    fn colours_follow_the_viewer() {
+        let mut instance = square("G4_AIR", 0.0, Some("000000"));
+        // Near-black aux colours are skipped; the name hash picks the palette.
+        assert_eq!(instance_rgb(&instance), PALETTE[8]);
+        instance.density = Some(1.0);
+        // hsl(16, 70%, 58%)
+        assert_eq!(instance_rgb(&instance), [223, 113, 73]);
+        instance.color = Some("80ff00".to_string());
+        assert_eq!(instance_rgb(&instance), [0x80, 0xff, 0x00]);
+        assert_eq!(nearest_aci([255, 0, 0]), 10);
+        assert_eq!(nearest_aci([0, 0, 255]), 170);
+    }
+
+    #[test]
+    fn drawings_carry_every_outline_and_a_legend() {
+        let section = section();
+
+        let svg = to_svg(&section);
+        assert_eq!(svg.matches("<path ").count(), 3);
+        assert!(svg.contains(r#"d="M0 0 L10 0 L10 -10 L0 -10 Z""#));
+        assert!(svg.contains(r##"fill="#ff0000""##));
+        assert_eq!(svg.matches("<text ").count(), 2, "one entry per material");
+        assert!(svg.contains(">Lead&lt;Pb&gt;</text>"));
+
+        let dxf = to_dxf(&section);
+        let lines: Vec<&str> = dxf.lines().collect();
+        assert_eq!(lines.len() % 2, 0);
+        assert!(lines
+            .iter()
+            .step_by(2)
+            .all(|code| code.parse::<u32>().is_ok()));
+        // Three outlines and two legend swatches.
+        assert_eq!(dxf.matches("\nPOLYLINE\n").count(), 5);
+        assert_eq!(dxf.matches("\nVERTEX\n").count(), 20);
+        assert!(dxf.contains("\n2\nLead_Pb_\n70\n0\n62\n"));
+        assert!(dxf.ends_with("0\nEOF\n"));
+    }
+}
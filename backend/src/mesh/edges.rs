@@ -0,0 +1,183 @@
+//! Feature edges: the lines a CAD view outlines a solid with.
+//!
+//! An edge is a feature when it bounds the surface (one facet or more than
+//! two), or when its facets meet at more than the crease angle *and* the mesh
+//! gives them different vertex normals along it. The second condition is what
+//! leaves out the tessellation's own edges across a curved surface: the
+//! primitives share one normal between the facets of a smooth surface however
+//! coarse the steps, and split it only where surfaces meet. Meshes without
+//! that information, with one normal per facet, fall back to the angle alone.
+
+use std::collections::HashMap;
+
+use super::integrity::weld;
+use super::types::TriangleMesh;
+
+/// Dihedral angle in degrees above which two facets meet at a crease.
+pub const DEFAULT_CREASE_ANGLE: f64 = 30.0;
+
+/// Vertex normals at least this close (about 2.6 degrees) count as one
+/// smooth-shading normal.
+const SMOOTH_COS: f64 = 0.999;
+
+/// A facet on a welded edge, with the original vertices it has at the edge's
+/// lower and higher welded end.
+type Incidence = (usize, [u32; 2]);
+
+/// Bring `edges` in line with `meshes` after an edit, at the default crease
+/// angle: edges of solids that are gone or were `remeshed` are dropped, and
+/// missing ones are found.
+pub fn update(
+    edges: &mut HashMap<String, Vec<u32>>,
+    meshes: &HashMap<String, TriangleMesh>,
+    remeshed: &[&str],
+) {
+    edges.retain(|name, _| meshes.contains_key(name) && !remeshed.contains(&name.as_str()));
+    for (name, mesh) in meshes {
+        if !edges.contains_key(name) {
+            edges.insert(name.clone(), feature_edges(mesh, DEFAULT_CREASE_ANGLE));
+        }
+    }
+}
+
+/// The feature edges of `mesh`, as pairs of indices into its positions: a
+/// line-segment index buffer. Edges come out in a stable order.
+pub fn feature_edges(mesh: &TriangleMesh, crease_angle: f64) -> Vec<u32> {
+    let (weld, welded_positions, _) = weld(mesh);
+    let crease_cos = crease_angle.clamp(0.0, 180.0).to_radians().cos();
+
+    let mut edges: HashMap<(u32, u32), Vec<Incidence>> = HashMap::new();
+    let mut facet_normals: Vec<[f64; 3]> = Vec::new();
+    for tri in mesh.indices.chunks_exact(3) {
+        let [Some(a), Some(b), Some(c)] = [0, 1, 2].map(|k| weld.get(tri[k] as usize).copied())
+        else {
+            continue;
+        };
+        let w = [a, b, c];
+        let p = [0, 1, 2].map(|k| welded_positions[w[k] as usize].map(f64::from));
+        let Some(normal) = facet_normal(p) else {
+            continue;
+        };
+        facet_normals.push(normal);
+        let facet = facet_normals.len() - 1;
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            let (lo, hi) = if w[a] < w[b] { (a, b) } else { (b, a) };
+            edges
+                .entry((w[lo], w[hi]))
+                .or_default()
+                .push((facet, [tri[lo], tri[hi]]));
+        }
+    }
+
+    let normal = |v: u32| -> [f64; 3] {
+        let i = v as usize * 3;
+        match mesh.normals.get(i..i + 3) {
+            Some(n) => [n[0] as f64, n[1] as f64, n[2] as f64],
+            None => [0.0; 3],
+        }
+    };
+    let smooth = |a: u32, b: u32| a == b || dot(normal(a), normal(b)) >= SMOOTH_COS;
+
+    let mut features: Vec<(u32, u32)> = edges
+        .iter()
+        .filter(|(_, facets)| match facets[..] {
+            [(f, [a0, a1]), (g, [b0, b1])] => {
+                dot(facet_normals[f], facet_normals[g]) < crease_cos
+                    && !(smooth(a0, b0) && smooth(a1, b1))
+            }
+            _ => true,
+        })
+        .map(|(_, facets)| (facets[0].1[0], facets[0].1[1]))
+        .collect();
+    features.sort_unstable();
+    features.into_iter().flat_map(|(a, b)| [a, b]).collect()
+}
+
+/// Unit normal of a facet, or `None` for one without area.
+fn facet_normal(p: [[f64; 3]; 3]) -> Option<[f64; 3]> {
+    let u = [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]];
+    let v = [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let len = dot(n, n).sqrt();
+    (len.is_finite() && len > 0.0).then(|| n.map(|c| c / len))
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use crate::mesh::primitives::sphere_mesh::tessellate_sphere;
+    use crate::mesh::primitives::tube_mesh::tessellate_tube;
+    use std::f64::consts::PI;
+
+    /// Every facet on its own three vertices, with the facet's normal.
+    fn flat_shaded(mesh: &TriangleMesh) -> TriangleMesh {
+        let mut out = TriangleMesh::new();
+        for tri in mesh.indices.chunks_exact(3) {
+            let p = [0, 1, 2].map(|k| {
+                let i = tri[k] as usize * 3;
+                [0, 1, 2].map(|c| mesh.positions[i + c] as f64)
+            });
+            let n = facet_normal(p).unwrap();
+            for q in p {
+                out.indices.push(out.vertex_count() as u32);
+                out.positions.extend(q.map(|c| c as f32));
+                out.normals.extend(n.map(|c| c as f32));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn box_has_its_twelve_edges() {
+        let cube = tessellate_box(10.0, 20.0, 30.0);
+        let edges = feature_edges(&cube, DEFAULT_CREASE_ANGLE);
+        assert_eq!(edges.len(), 24);
+        // No face diagonals: every edge runs along one axis.
+        for pair in edges.chunks_exact(2) {
+            let p = |v: u32| &cube.positions[v as usize * 3..v as usize * 3 + 3];
+            let differ = (0..3).filter(|&c| p(pair[0])[c] != p(pair[1])[c]).count();
+            assert_eq!(differ, 1);
+        }
+    }
+
+    #[test]
+    fn tessellation_seams_are_left_out() {
+        // Eight steps turn 45 degrees each, past the crease angle, yet the
+        // shared normals mark the wall as one smooth surface.
+        let tube = tessellate_tube(5.0, 8.0, 10.0, 0.0, 2.0 * PI, 8);
+        let rims = 4 * 8;
+        assert_eq!(feature_edges(&tube, DEFAULT_CREASE_ANGLE).len(), 2 * rims);
+        assert!(feature_edges(
+            &tessellate_sphere(0.0, 10.0, 0.0, 2.0 * PI, 0.0, PI, 16, 8),
+            30.0
+        )
+        .is_empty());
+
+        // With a normal per facet only the angle tells: the 45 degree steps
+        // show, but not at a threshold above them.
+        let flat = flat_shaded(&tube);
+        assert_eq!(
+            feature_edges(&flat, DEFAULT_CREASE_ANGLE).len(),
+            2 * (rims + 2 * 8)
+        );
+        assert_eq!(feature_edges(&flat, 50.0).len(), 2 * rims);
+    }
+
+    #[test]
+    fn open_edges_are_features() {
+        let mut cube = tessellate_box(10.0, 10.0, 10.0);
+        cube.indices.truncate(cube.indices.len() - 6);
+        // Above the box's right angles only the missing face's open sides
+        // remain.
+        assert_eq!(feature_edges(&cube, 120.0).len(), 2 * 4);
+    }
+}
@@ -0,0 +1,866 @@
+//! Topology checks for triangle meshes, and a repair step.
+//!
+//! A `<tessellated>` solid is only as good as the facets in the file, and the
+//! BSP booleans in [`super::csg`] leave T-junctions and slivers where faces are
+//! split. Geant4 needs a closed, outward-facing surface to tell inside from
+//! outside; the viewer and the booleans need the same. [`analyze`] reports
+//! what stands in the way: edges with one facet (holes), edges with more than
+//! two (non-manifold), facets wound against their neighbours, facets with no
+//! area, facets that pass through each other, and vertices stored twice.
+//!
+//! Meshes here are not indexed for topology: every primitive splits its
+//! vertices along creases so each face gets its own normals, and tessellated
+//! solids give every facet three or four vertices of its own. Topology is
+//! therefore built on welded positions. Vertices are computed in `f64` and
+//! stored as `f32`, so two that should coincide differ by the rounding of each
+//! coordinate on its own; the weld allows a couple of `f32` steps per
+//! coordinate rather than a distance scaled to the whole mesh. The latter
+//! would fuse the faces of the 20 nm foils in the sample detectors, which are
+//! 100 mm across but sit at z = ±1e-5 mm, where `f32` is still very fine.
+//!
+//! A vertex counts as a duplicate only when it repeats both the position and
+//! the normal of another; a crease split is not one. Duplicates -- every
+//! closed ring of a primitive repeats its seam -- cost memory, not
+//! correctness, so they are counted but not listed among the problems.
+//!
+//! Orientation is propagated across manifold edges: two facets sharing an edge
+//! agree when they run along it in opposite directions. A closed component is
+//! then taken to face outward when its signed volume is positive, so a surface
+//! wound inside out as a whole is reported as flipped too. An open component
+//! has no inside, and the majority winding is taken as intended.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use super::types::TriangleMesh;
+
+/// `f32` steps a coordinate may be off by and still weld.
+const ROUNDING_ULPS: f64 = 2.0;
+
+/// Absolute floor for the weld tolerance, for coordinates at or near zero.
+const MIN_WELD: f64 = 1e-9;
+
+/// How far, as a fraction of the coordinates' magnitude, an edge has to pass
+/// through a facet to count as crossing it. Ten times the plane thickness of
+/// the booleans (`EPSILON_SCALE` in `csg.rs`), whose output wobbles by that
+/// much where they split a face.
+const CROSSING_SCALE: f64 = 1e-5;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MeshReport {
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Vertices repeating the position and normal of an earlier one.
+    pub duplicate_vertices: usize,
+    /// Facets whose height is within the rounding of their coordinates.
+    pub degenerate_triangles: usize,
+    /// Edges bordered by a single facet.
+    pub open_edges: usize,
+    /// Edges shared by more than two facets.
+    pub non_manifold_edges: usize,
+    /// Facets wound against their component, or all of an inside-out closed one.
+    pub flipped_triangles: usize,
+    /// Facets whose stored normals point against their winding.
+    pub normals_against_winding: usize,
+    /// Pairs of facets without a common vertex that pass through each other.
+    pub self_intersections: usize,
+    /// No open and no non-manifold edges.
+    pub watertight: bool,
+    /// No flipped facets and no normals against the winding.
+    pub consistently_oriented: bool,
+    /// Signed volume in mm^3; meaningful when the mesh is watertight.
+    pub volume: f64,
+}
+
+impl MeshReport {
+    /// Nothing to report but duplicate vertices.
+    pub fn is_clean(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// The non-zero counts as phrases, e.g. `"3 open edges"`.
+    pub fn problems(&self) -> Vec<String> {
+        let counts = [
+            (self.open_edges, "open edge"),
+            (self.non_manifold_edges, "non-manifold edge"),
+            (self.flipped_triangles, "flipped facet"),
+            (
+                self.normals_against_winding,
+                "facet with normals against its winding",
+            ),
+            (self.degenerate_triangles, "degenerate facet"),
+            (self.self_intersections, "self-intersecting facet pair"),
+        ];
+        counts
+            .iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, what)| {
+                if *n == 1 {
+                    format!("1 {}", what)
+                } else if let Some(rest) = what.strip_prefix("facet with") {
+                    format!("{} facets with{}", n, rest)
+                } else {
+                    format!("{} {}s", n, what)
+                }
+            })
+            .collect()
+    }
+}
+
+/// What [`repair`] does. Every step is on unless switched off.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct RepairOptions {
+    /// Merge vertices sharing a position and a normal.
+    pub weld: bool,
+    /// Flip the facets [`analyze`] reports as flipped, and turn stored normals
+    /// that point against the winding.
+    pub reorient: bool,
+    /// Drop facets without area.
+    pub remove_degenerate: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            weld: true,
+            reorient: true,
+            remove_degenerate: true,
+        }
+    }
+}
+
+/// Check `mesh`; see the module documentation for what each count means.
+pub fn analyze(mesh: &TriangleMesh) -> MeshReport {
+    let topo = Topology::build(mesh);
+    let flipped = topo.orientation();
+    let normals_against_winding = (0..topo.tris.len())
+        .filter(|&t| topo.normals_oppose_winding(mesh, t))
+        .count();
+    let open_edges = topo.edges.values().filter(|uses| uses.len() == 1).count();
+    let non_manifold_edges = topo.edges.values().filter(|uses| uses.len() > 2).count();
+    let flipped_triangles = flipped.iter().filter(|f| **f).count();
+
+    MeshReport {
+        vertices: mesh.vertex_count(),
+        triangles: mesh.triangle_count(),
+        duplicate_vertices: mesh.vertex_count() - topo.distinct_vertices(mesh),
+        degenerate_triangles: topo.degenerate.iter().filter(|d| **d).count(),
+        open_edges,
+        non_manifold_edges,
+        flipped_triangles,
+        normals_against_winding,
+        self_intersections: topo.self_intersections(),
+        watertight: open_edges == 0 && non_manifold_edges == 0,
+        consistently_oriented: flipped_triangles == 0 && normals_against_winding == 0,
+        volume: topo.signed_volume(|_| false),
+    }
+}
+
+/// Whether every edge of `mesh` borders exactly two facets: the `watertight`
+/// of [`analyze`] without the rest, cheap enough to run on every boolean.
+pub fn is_watertight(mesh: &TriangleMesh) -> bool {
+    Topology::build(mesh)
+        .edges
+        .values()
+        .all(|uses| uses.len() == 2)
+}
+
+/// A copy of `mesh` with the steps of `options` applied. Vertices no facet
+/// uses any more are dropped.
+pub fn repair(mesh: &TriangleMesh, options: &RepairOptions) -> TriangleMesh {
+    let topo = Topology::build(mesh);
+    let flipped = if options.reorient {
+        topo.orientation()
+    } else {
+        vec![false; topo.tris.len()]
+    };
+
+    let mut out = TriangleMesh::new();
+    // Output vertex for (source vertex, normal negated), or with welding for
+    // (welded position, normal), normals compared with a small tolerance.
+    let mut by_source: HashMap<(u32, bool), u32> = HashMap::new();
+    let mut by_weld: HashMap<u32, Vec<([f32; 3], u32)>> = HashMap::new();
+
+    for (t, &flip) in flipped.iter().enumerate() {
+        if options.remove_degenerate && topo.degenerate[t] {
+            continue;
+        }
+        let mut corners = [0usize, 1, 2].map(|k| mesh.indices[t * 3 + k]);
+        // A facet is turned by swapping two corners, which also turns its
+        // stored normals if they agreed with the winding before.
+        let mut negate = flip;
+        if flip {
+            corners.swap(1, 2);
+        }
+        if options.reorient && topo.normals_oppose_winding(mesh, t) {
+            negate = !negate;
+        }
+        for v in corners {
+            let mut normal = read3(&mesh.normals, v);
+            if negate {
+                normal = normal.map(|c| -c);
+            }
+            let index = if options.weld {
+                let welded = topo.weld[v as usize];
+                let cluster = by_weld.entry(welded).or_default();
+                match cluster.iter().find(|(n, _)| same_normal(*n, normal)) {
+                    Some((_, i)) => *i,
+                    None => {
+                        let i =
+                            push_vertex(&mut out, topo.welded_positions[welded as usize], normal);
+                        cluster.push((normal, i));
+                        i
+                    }
+                }
+            } else {
+                *by_source
+                    .entry((v, negate))
+                    .or_insert_with(|| push_vertex(&mut out, read3(&mesh.positions, v), normal))
+            };
+            out.indices.push(index);
+        }
+    }
+    out
+}
+
+/// Rounds of [`close_t_junctions`]; a split can expose a junction a coarser
+/// neighbour hid.
+const T_JUNCTION_ROUNDS: usize = 4;
+
+/// Split facets at the vertices lying on their open edges: the T-junctions
+/// the BSP booleans leave where one side of a cut was split further than the
+/// other. A surface closed in space but not in its edges comes out closed;
+/// facets without such a vertex are left as they are.
+///
+/// Only endpoints of open edges are candidates, and a vertex counts as on an
+/// edge within the crossing tolerance, the wobble of the BSP splits. Endpoints
+/// that close to each other are moved together before any facet is split.
+pub fn close_t_junctions(mesh: &TriangleMesh) -> TriangleMesh {
+    let mut mesh = mesh.clone();
+    for _ in 0..T_JUNCTION_ROUNDS {
+        let topo = Topology::build(&mesh);
+        let open: Vec<((u32, u32), usize)> = topo
+            .edges
+            .iter()
+            .filter(|(_, uses)| uses.len() == 1)
+            .map(|(e, uses)| (*e, uses[0].0))
+            .collect();
+        let mut candidates: Vec<u32> = open.iter().flat_map(|((a, b), _)| [*a, *b]).collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        // Endpoints closer than the tolerance are one vertex split in two;
+        // inserted on each other's edges they would only leave slivers, so
+        // they are moved together first.
+        let mut snap: HashMap<u32, [f32; 3]> = HashMap::new();
+        for (i, &v) in candidates.iter().enumerate() {
+            if snap.contains_key(&v) {
+                continue;
+            }
+            let p = topo.position(v);
+            for &w in &candidates[i + 1..] {
+                let q = topo.position(w);
+                let magnitude = p.iter().chain(q.iter()).fold(0.0f64, |m, c| m.max(c.abs()));
+                if !snap.contains_key(&w) && distance(p, q) <= CROSSING_SCALE * magnitude + MIN_WELD
+                {
+                    snap.insert(w, topo.welded_positions[v as usize]);
+                }
+            }
+        }
+        if !snap.is_empty() {
+            for (v, w) in topo.weld.iter().enumerate() {
+                if let Some(position) = snap.get(w) {
+                    mesh.positions[v * 3..v * 3 + 3].copy_from_slice(position);
+                }
+            }
+            continue;
+        }
+
+        // Welded vertices to insert on each side of each facet, with their
+        // position along the side.
+        let mut inserts: HashMap<usize, [Vec<(f64, u32)>; 3]> = HashMap::new();
+        for &((a, b), t) in &open {
+            let tri = topo.tris[t];
+            let Some(side) = (0..3).find(|&k| {
+                let (from, to) = (tri[k], tri[(k + 1) % 3]);
+                (from.min(to), from.max(to)) == (a, b)
+            }) else {
+                continue;
+            };
+            let (from, to) = (topo.position(tri[side]), topo.position(tri[(side + 1) % 3]));
+            let along = sub(to, from);
+            let length2 = dot(along, along);
+            let magnitude = from
+                .iter()
+                .chain(to.iter())
+                .fold(0.0f64, |m, c| m.max(c.abs()));
+            let slack = CROSSING_SCALE * magnitude + MIN_WELD;
+            for &w in &candidates {
+                if w == a || w == b {
+                    continue;
+                }
+                let p = topo.position(w);
+                let s = dot(sub(p, from), along) / length2;
+                if !(s > 0.0 && s < 1.0) {
+                    continue;
+                }
+                let foot = add(from, along.map(|c| c * s));
+                if distance(p, foot) <= slack {
+                    inserts.entry(t).or_default()[side].push((s, w));
+                }
+            }
+        }
+        if inserts.is_empty() {
+            break;
+        }
+
+        let mut out = TriangleMesh {
+            positions: mesh.positions.clone(),
+            normals: mesh.normals.clone(),
+            indices: Vec::with_capacity(mesh.indices.len()),
+        };
+        for t in 0..mesh.triangle_count() {
+            let corners = [0, 1, 2].map(|k| mesh.indices[t * 3 + k]);
+            let Some(sides) = inserts.get_mut(&t) else {
+                out.indices.extend_from_slice(&corners);
+                continue;
+            };
+            let normal = corners
+                .iter()
+                .map(|&v| to_f64(read3(&mesh.normals, v)))
+                .fold([0.0; 3], add);
+            let length = norm(normal).max(f64::MIN_POSITIVE);
+            let normal = normal.map(|c| (c / length) as f32);
+            // The facet's outline with the new vertices on it, fanned from its
+            // centroid so no piece is flat however the vertices fall.
+            let mut outline = Vec::new();
+            for (k, side) in sides.iter_mut().enumerate() {
+                outline.push(corners[k]);
+                side.sort_by(|x, y| x.0.total_cmp(&y.0));
+                for &(_, w) in side.iter() {
+                    outline.push(push_vertex(
+                        &mut out,
+                        topo.welded_positions[w as usize],
+                        normal,
+                    ));
+                }
+            }
+            let centroid = corners
+                .iter()
+                .map(|&v| to_f64(read3(&mesh.positions, v)))
+                .fold([0.0; 3], add)
+                .map(|c| (c / 3.0) as f32);
+            let centre = push_vertex(&mut out, centroid, normal);
+            for i in 0..outline.len() {
+                out.indices.extend_from_slice(&[
+                    centre,
+                    outline[i],
+                    outline[(i + 1) % outline.len()],
+                ]);
+            }
+        }
+        mesh = out;
+    }
+    mesh
+}
+
+fn push_vertex(mesh: &mut TriangleMesh, position: [f32; 3], normal: [f32; 3]) -> u32 {
+    let index = mesh.vertex_count() as u32;
+    mesh.positions.extend_from_slice(&position);
+    mesh.normals.extend_from_slice(&normal);
+    index
+}
+
+fn read3(values: &[f32], vertex: u32) -> [f32; 3] {
+    let i = vertex as usize * 3;
+    match values.get(i..i + 3) {
+        Some(v) => [v[0], v[1], v[2]],
+        None => [0.0; 3],
+    }
+}
+
+fn same_normal(a: [f32; 3], b: [f32; 3]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() <= 1e-5)
+}
+
+/// The welded view of a mesh the checks run on.
+struct Topology {
+    /// Welded vertex of every source vertex.
+    weld: Vec<u32>,
+    welded_positions: Vec<[f32; 3]>,
+    /// Facets as welded vertices, in source order.
+    tris: Vec<[u32; 3]>,
+    degenerate: Vec<bool>,
+    /// Facets using each undirected edge of non-degenerate facets, with
+    /// whether they run along it from the lower welded index to the higher.
+    edges: HashMap<(u32, u32), Vec<(usize, bool)>>,
+    /// The largest weld tolerance anywhere in the mesh.
+    tolerance: f64,
+}
+
+/// How far apart two values of about `magnitude` can be after rounding.
+pub(super) fn rounding(magnitude: f64) -> f64 {
+    ROUNDING_ULPS * f32::EPSILON as f64 * magnitude.abs() + MIN_WELD
+}
+
+pub(super) fn coincide(p: [f32; 3], q: [f32; 3]) -> bool {
+    (0..3).all(|i| {
+        let (a, b) = (p[i] as f64, q[i] as f64);
+        (a - b).abs() <= rounding(a.abs().max(b.abs()))
+    })
+}
+
+/// Welded vertex of every vertex of `mesh`, the welded positions, and the
+/// largest weld tolerance anywhere in it.
+pub(super) fn weld(mesh: &TriangleMesh) -> (Vec<u32>, Vec<[f32; 3]>, f64) {
+    let extent = mesh
+        .positions
+        .iter()
+        .map(|v| (*v as f64).abs())
+        .filter(|v| v.is_finite())
+        .fold(0.0, f64::max);
+    let tolerance = rounding(extent);
+
+    // Spatial hash with cells of the largest tolerance; a match can sit in
+    // any neighbouring cell.
+    let mut cells: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut welded_positions: Vec<[f32; 3]> = Vec::new();
+    let mut weld = Vec::with_capacity(mesh.vertex_count());
+    for v in 0..mesh.vertex_count() as u32 {
+        let p = read3(&mesh.positions, v);
+        let cell = p.map(|c| (c as f64 / tolerance).floor() as i64);
+        let mut found = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let key = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    for &w in cells.get(&key).into_iter().flatten() {
+                        let q = welded_positions[w as usize];
+                        if coincide(p, q) {
+                            found = Some(w);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        let w = found.unwrap_or_else(|| {
+            let w = welded_positions.len() as u32;
+            welded_positions.push(p);
+            cells.entry(cell).or_default().push(w);
+            w
+        });
+        weld.push(w);
+    }
+    (weld, welded_positions, tolerance)
+}
+
+impl Topology {
+    fn build(mesh: &TriangleMesh) -> Self {
+        let (weld, welded_positions, tolerance) = weld(mesh);
+
+        let mut tris = Vec::with_capacity(mesh.triangle_count());
+        let mut degenerate = Vec::with_capacity(mesh.triangle_count());
+        let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+        for t in 0..mesh.triangle_count() {
+            let tri = [0, 1, 2].map(|k| {
+                weld.get(mesh.indices[t * 3 + k] as usize)
+                    .copied()
+                    .unwrap_or(0)
+            });
+            let [a, b, c] = tri.map(|w| to_f64(welded_positions[w as usize]));
+            let doubled_area = norm(cross(sub(b, a), sub(c, a)));
+            let longest = distance(a, b).max(distance(b, c)).max(distance(c, a));
+            let magnitude = [a, b, c]
+                .iter()
+                .flat_map(|p| p.iter())
+                .fold(0.0f64, |m, c| m.max(c.abs()));
+            let flat = tri[0] == tri[1]
+                || tri[1] == tri[2]
+                || tri[2] == tri[0]
+                || doubled_area <= rounding(magnitude) * longest;
+            if !flat {
+                for (from, to) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                    edges
+                        .entry((from.min(to), from.max(to)))
+                        .or_default()
+                        .push((t, from < to));
+                }
+            }
+            tris.push(tri);
+            degenerate.push(flat);
+        }
+
+        Self {
+            weld,
+            welded_positions,
+            tris,
+            degenerate,
+            edges,
+            tolerance,
+        }
+    }
+
+    fn position(&self, w: u32) -> [f64; 3] {
+        to_f64(self.welded_positions[w as usize])
+    }
+
+    /// Source vertices left after merging those with the same welded position
+    /// and normal.
+    fn distinct_vertices(&self, mesh: &TriangleMesh) -> usize {
+        let mut seen: HashMap<u32, Vec<[f32; 3]>> = HashMap::new();
+        let mut distinct = 0;
+        for (v, &w) in self.weld.iter().enumerate() {
+            let normal = read3(&mesh.normals, v as u32);
+            let cluster = seen.entry(w).or_default();
+            if !cluster.iter().any(|n| same_normal(*n, normal)) {
+                cluster.push(normal);
+                distinct += 1;
+            }
+        }
+        distinct
+    }
+
+    fn normals_oppose_winding(&self, mesh: &TriangleMesh, t: usize) -> bool {
+        if self.degenerate[t] {
+            return false;
+        }
+        let corners = [0, 1, 2].map(|k| mesh.indices[t * 3 + k]);
+        let [a, b, c] = corners.map(|v| to_f64(read3(&mesh.positions, v)));
+        let geometric = cross(sub(b, a), sub(c, a));
+        let stored = corners
+            .iter()
+            .map(|&v| to_f64(read3(&mesh.normals, v)))
+            .fold([0.0; 3], add);
+        // Normals that cancel out say nothing about the side.
+        norm(stored) > 1e-9 && dot(geometric, stored) < 0.0
+    }
+
+    /// Whether each facet is wound against the orientation chosen for its
+    /// component.
+    fn orientation(&self) -> Vec<bool> {
+        let n = self.tris.len();
+        let mut parity: Vec<Option<bool>> = vec![None; n];
+        // Facets of each component, and whether it is closed.
+        let mut components: Vec<(Vec<usize>, bool)> = Vec::new();
+        for start in 0..n {
+            if parity[start].is_some() || self.degenerate[start] {
+                continue;
+            }
+            parity[start] = Some(false);
+            let mut component = vec![start];
+            let mut closed = true;
+            let mut queue = VecDeque::from([start]);
+            while let Some(t) = queue.pop_front() {
+                let tri = self.tris[t];
+                for (from, to) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                    let uses = &self.edges[&(from.min(to), from.max(to))];
+                    if uses.len() != 2 {
+                        closed = false;
+                        continue;
+                    }
+                    let here = uses.iter().find(|(u, _)| *u == t).is_some_and(|u| u.1);
+                    let Some(&(other, there)) = uses.iter().find(|(u, _)| *u != t) else {
+                        continue;
+                    };
+                    if parity[other].is_none() {
+                        // Running the same way along the edge means the two
+                        // disagree; `other` gets the opposite parity.
+                        parity[other] = Some(parity[t].unwrap_or(false) ^ (here == there));
+                        component.push(other);
+                        queue.push_back(other);
+                    }
+                }
+            }
+            components.push((component, closed));
+        }
+
+        let odd = |t: usize| parity[t] == Some(true);
+        let mut flipped = vec![false; n];
+        for (i, (component, closed)) in components.iter().enumerate() {
+            let turn = if *closed {
+                // The bore of a hollow sphere is a closed surface of its own
+                // that faces inward: a shell inside an odd number of others
+                // must enclose negative volume.
+                let others = components
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, (_, c))| *j != i && *c)
+                    .map(|(_, (tris, _))| tris.as_slice());
+                let probe = self.centroid(component[0]);
+                let depth = others.filter(|tris| self.encloses(tris, probe)).count();
+                let outward = self.signed_volume_of(component, odd) > 0.0;
+                outward == (depth % 2 == 1)
+            } else {
+                component.iter().filter(|&&t| odd(t)).count() * 2 > component.len()
+            };
+            for &t in component {
+                flipped[t] = odd(t) != turn;
+            }
+        }
+        flipped
+    }
+
+    fn centroid(&self, t: usize) -> [f64; 3] {
+        self.tris[t]
+            .map(|w| self.position(w))
+            .iter()
+            .fold([0.0; 3], |acc, p| add(acc, *p))
+            .map(|c| c / 3.0)
+    }
+
+    /// Whether the closed surface `tris` encloses `point`: a ray from it
+    /// crosses the surface an odd number of times. The direction is skewed
+    /// so it does not run along the edges of axis-aligned or regular meshes.
+    fn encloses(&self, tris: &[usize], point: [f64; 3]) -> bool {
+        let dir = [0.5773, 0.6157, 0.5362];
+        let crossings = tris
+            .iter()
+            .filter(|&&t| {
+                let [a, b, c] = self.tris[t].map(|w| self.position(w));
+                let (e1, e2) = (sub(b, a), sub(c, a));
+                let h = cross(dir, e2);
+                let det = dot(e1, h);
+                if det == 0.0 {
+                    return false;
+                }
+                let s = sub(point, a);
+                let u = dot(s, h) / det;
+                let q = cross(s, e1);
+                let v = dot(dir, q) / det;
+                (0.0..=1.0).contains(&u) && v >= 0.0 && u + v <= 1.0 && dot(e2, q) / det > 0.0
+            })
+            .count();
+        crossings % 2 == 1
+    }
+
+    fn signed_volume(&self, flip: impl Fn(usize) -> bool) -> f64 {
+        let all: Vec<usize> = (0..self.tris.len()).collect();
+        self.signed_volume_of(&all, flip)
+    }
+
+    /// Divergence-theorem volume of `tris`, taken about their centroid so
+    /// large offsets from the origin cost no precision.
+    fn signed_volume_of(&self, tris: &[usize], flip: impl Fn(usize) -> bool) -> f64 {
+        if tris.is_empty() {
+            return 0.0;
+        }
+        let centre = tris
+            .iter()
+            .map(|&t| self.position(self.tris[t][0]))
+            .fold([0.0; 3], add)
+            .map(|c| c / tris.len() as f64);
+        tris.iter()
+            .filter(|&&t| !self.degenerate[t])
+            .map(|&t| {
+                let [a, b, c] = self.tris[t].map(|w| sub(self.position(w), centre));
+                let v = dot(cross(a, b), c) / 6.0;
+                if flip(t) {
+                    -v
+                } else {
+                    v
+                }
+            })
+            .sum()
+    }
+
+    /// Pairs of non-degenerate facets with no welded vertex in common where
+    /// an edge of one passes through the interior of the other. Candidates
+    /// come from a sweep over bounding boxes along x.
+    fn self_intersections(&self) -> usize {
+        let boxes: Vec<(usize, [f64; 3], [f64; 3])> = (0..self.tris.len())
+            .filter(|&t| !self.degenerate[t])
+            .map(|t| {
+                let p = self.tris[t].map(|w| self.position(w));
+                let lo = [0, 1, 2].map(|k| p[0][k].min(p[1][k]).min(p[2][k]));
+                let hi = [0, 1, 2].map(|k| p[0][k].max(p[1][k]).max(p[2][k]));
+                (t, lo, hi)
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..boxes.len()).collect();
+        order.sort_by(|&i, &j| boxes[i].1[0].total_cmp(&boxes[j].1[0]));
+
+        let mut count = 0;
+        for (k, &i) in order.iter().enumerate() {
+            let (a, lo_a, hi_a) = boxes[i];
+            for &j in &order[k + 1..] {
+                let (b, lo_b, hi_b) = boxes[j];
+                if lo_b[0] > hi_a[0] + self.tolerance {
+                    break;
+                }
+                let overlap = (1..3).all(|d| {
+                    lo_b[d] <= hi_a[d] + self.tolerance && lo_a[d] <= hi_b[d] + self.tolerance
+                });
+                let (ta, tb) = (self.tris[a], self.tris[b]);
+                if !overlap || ta.iter().any(|w| tb.contains(w)) {
+                    continue;
+                }
+                let pa = ta.map(|w| self.position(w));
+                let pb = tb.map(|w| self.position(w));
+                let magnitude = pa
+                    .iter()
+                    .chain(pb.iter())
+                    .flat_map(|p| p.iter())
+                    .fold(0.0f64, |m, c| m.max(c.abs()));
+                let slack = CROSSING_SCALE * magnitude + MIN_WELD;
+                if edges_cross(&pa, &pb, slack) || edges_cross(&pb, &pa, slack) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+/// Whether an edge of `a` passes through the interior of facet `b`, by more
+/// than `slack`: both ends of the edge clear the plane of `b`, and the point
+/// where it crosses clears every border of `b`. Contacts within the slack --
+/// a shared edge, a T-junction, the slivers a boolean leaves where it split a
+/// face -- are touches, not crossings.
+fn edges_cross(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3], slack: f64) -> bool {
+    let normal = cross(sub(b[1], b[0]), sub(b[2], b[0]));
+    let len = norm(normal);
+    if len == 0.0 {
+        return false;
+    }
+    let normal = normal.map(|c| c / len);
+    let height = |p: [f64; 3]| dot(normal, sub(p, b[0]));
+    (0..3).any(|k| {
+        let (p, q) = (a[k], a[(k + 1) % 3]);
+        let (hp, hq) = (height(p), height(q));
+        if !((hp > slack && hq < -slack) || (hp < -slack && hq > slack)) {
+            return false;
+        }
+        let x = add(p, sub(q, p).map(|c| c * hp / (hp - hq)));
+        // Inside `b` by more than `slack` from each border.
+        (0..3).all(|i| {
+            let (u, v) = (b[i], b[(i + 1) % 3]);
+            let inward = cross(normal, sub(v, u));
+            let l = norm(inward);
+            l > 0.0 && dot(inward, sub(x, u)) / l > slack
+        })
+    })
+}
+
+fn to_f64(p: [f32; 3]) -> [f64; 3] {
+    p.map(|c| c as f64)
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    norm(sub(a, b))
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+
+    fn cube() -> TriangleMesh {
+        tessellate_box(10.0, 10.0, 10.0)
+    }
+
+    /// Reverse the winding of facet `t` without touching its normals.
+    fn turn(mesh: &mut TriangleMesh, t: usize) {
+        mesh.indices.swap(t * 3 + 1, t * 3 + 2);
+    }
+
+    #[test]
+    fn primitive_box_is_clean() {
+        let report = analyze(&cube());
+        assert!(report.is_clean(), "{:?}", report.problems());
+        assert!(report.watertight && report.consistently_oriented);
+        assert!((report.volume - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn holes_flips_and_slivers_are_found_and_repaired() {
+        let mut mesh = cube();
+        turn(&mut mesh, 0);
+        // A sliver: all three corners on one vertex of the cube.
+        let v = mesh.indices[0];
+        mesh.indices.extend([v, v, v]);
+        let report = analyze(&mesh);
+        assert_eq!(report.flipped_triangles, 1);
+        assert_eq!(report.normals_against_winding, 1);
+        assert_eq!(report.degenerate_triangles, 1);
+        assert!(report.watertight);
+
+        let fixed = repair(&mesh, &RepairOptions::default());
+        let after = analyze(&fixed);
+        assert!(after.is_clean(), "{:?}", after.problems());
+        assert_eq!(fixed.triangle_count(), 12);
+        // The four corners of each face were already shared; nothing to weld.
+        assert_eq!(fixed.vertex_count(), 24);
+
+        let mut open = cube();
+        open.indices.truncate(open.indices.len() - 3);
+        let report = analyze(&open);
+        assert_eq!(report.open_edges, 3);
+        assert!(!report.watertight);
+        assert_eq!(report.problems(), ["3 open edges"]);
+    }
+
+    #[test]
+    fn inside_out_and_intersecting_surfaces() {
+        let mut inside_out = cube();
+        for t in 0..inside_out.triangle_count() {
+            turn(&mut inside_out, t);
+        }
+        inside_out.normals.iter_mut().for_each(|n| *n = -*n);
+        let report = analyze(&inside_out);
+        assert_eq!(report.flipped_triangles, 12);
+        assert_eq!(report.normals_against_winding, 0);
+        let fixed = repair(&inside_out, &RepairOptions::default());
+        assert!(analyze(&fixed).volume > 0.0);
+
+        // Two cubes through each other, as one mesh.
+        let mut pair = cube();
+        let shifted = crate::mesh::csg::transform_mesh(&cube(), [4.0, 3.0, 2.0], [0.0; 3]);
+        let base = pair.vertex_count() as u32;
+        pair.positions.extend(&shifted.positions);
+        pair.normals.extend(&shifted.normals);
+        pair.indices
+            .extend(shifted.indices.iter().map(|i| i + base));
+        let report = analyze(&pair);
+        assert!(report.watertight);
+        assert!(report.self_intersections > 0);
+
+        // Duplicated vertices weld away.
+        let mut doubled = cube();
+        let base = doubled.vertex_count() as u32;
+        doubled.positions.extend(cube().positions);
+        doubled.normals.extend(cube().normals);
+        for i in doubled.indices.iter_mut().skip(18) {
+            *i += base;
+        }
+        assert_eq!(analyze(&doubled).duplicate_vertices, 24);
+        let welded = repair(&doubled, &RepairOptions::default());
+        assert_eq!(welded.vertex_count(), 24);
+        assert!(analyze(&welded).is_clean());
+    }
+}
@@ -0,0 +1,282 @@
+//! Coarser meshes of each solid, for drawing it when it is small on screen.
+//!
+//! Curved primitives are tessellated again at half the steps per level (see
+//! [`MeshQuality::coarser`]), which keeps every vertex on the true surface.
+//! Everything else -- `<tessellated>` solids, booleans and the other
+//! composites -- is decimated from the level before with [`decimate`], at a
+//! quarter of its facets and a deviation bound that grows fourfold per level.
+//! Flat primitives are already as coarse as their shape allows and get no
+//! coarser levels.
+//!
+//! Each level says from which screen size on it is drawn, the screen size
+//! being the solid's bounding-sphere diameter over the viewport height. Half
+//! the steps give about four times the chord deviation, so the thresholds
+//! fall fourfold per level to keep the deviation at about the same number of
+//! pixels. The coarsest level has a threshold of zero.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::decimate::decimate;
+use super::quality::MeshQuality;
+use super::tessellator;
+use super::types::TriangleMesh;
+use crate::eval::engine::EvalEngine;
+use crate::gdml::model::{Solid, SolidSection};
+
+/// Levels per solid, the finest included.
+pub const LEVELS: u32 = 4;
+
+/// Screen size from which the finest level is drawn, when there are coarser
+/// ones.
+const FINEST_SCREEN_SIZE: f64 = 0.5;
+
+/// A level is kept only with at most this share of the facets of the level
+/// before; one that saves less is not worth switching to.
+const MIN_REDUCTION: f64 = 0.75;
+
+/// Deviation bound of the first decimated level, as a fraction of the
+/// solid's bounding-box diagonal.
+const DECIMATION_ERROR: f64 = 0.002;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Level {
+    /// Drawn at this screen size and above, up to the next finer level's.
+    pub min_screen_size: f64,
+    #[serde(flatten)]
+    pub mesh: TriangleMesh,
+}
+
+/// The levels of one solid besides the mesh it already has.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Lods {
+    /// Screen size from which the existing mesh is drawn.
+    pub min_screen_size: f64,
+    /// Coarser levels, finest first.
+    pub levels: Vec<Level>,
+}
+
+/// Levels for every solid in `meshes`. The meshes themselves are the finest
+/// level and are left as they are.
+pub fn build(
+    solids: &SolidSection,
+    engine: &EvalEngine,
+    quality: &MeshQuality,
+    meshes: &HashMap<String, TriangleMesh>,
+) -> HashMap<String, Lods> {
+    let mut lods = HashMap::new();
+    update(&mut lods, solids, engine, quality, meshes, &[]);
+    lods
+}
+
+/// Bring `lods` in line with `meshes` after an edit: levels of solids that
+/// are gone or were `remeshed` are dropped, and missing ones are built.
+pub fn update(
+    lods: &mut HashMap<String, Lods>,
+    solids: &SolidSection,
+    engine: &EvalEngine,
+    quality: &MeshQuality,
+    meshes: &HashMap<String, TriangleMesh>,
+    remeshed: &[&str],
+) {
+    lods.retain(|name, _| meshes.contains_key(name) && !remeshed.contains(&name.as_str()));
+    let by_name: HashMap<&str, &Solid> = solids.solids.iter().map(|s| (s.name(), s)).collect();
+    for (name, mesh) in meshes {
+        if !lods.contains_key(name) {
+            let solid = by_name.get(name.as_str()).copied();
+            lods.insert(name.clone(), solid_lods(solid, engine, quality, mesh));
+        }
+    }
+}
+
+fn solid_lods(
+    solid: Option<&Solid>,
+    engine: &EvalEngine,
+    quality: &MeshQuality,
+    finest: &TriangleMesh,
+) -> Lods {
+    let curved = solid.filter(|s| is_curved(s));
+    if solid.is_some_and(is_flat) {
+        return Lods::default();
+    }
+
+    let diagonal = diagonal(finest);
+    let mut meshes: Vec<TriangleMesh> = Vec::new();
+    for level in 1..LEVELS {
+        let previous = meshes.last().unwrap_or(finest);
+        let coarser = match curved {
+            Some(solid) => {
+                match tessellator::tessellate_primitive(solid, engine, &quality.coarser(level)) {
+                    Ok(mesh) => mesh,
+                    Err(_) => break,
+                }
+            }
+            None => {
+                let bound = diagonal * DECIMATION_ERROR * 4f64.powi(level as i32 - 1);
+                decimate(previous, previous.triangle_count() / 4, bound)
+            }
+        };
+        let limit = previous.triangle_count() as f64 * MIN_REDUCTION;
+        if coarser.triangle_count() == 0 || coarser.triangle_count() as f64 > limit {
+            break;
+        }
+        meshes.push(coarser);
+    }
+
+    let coarsest = meshes.len();
+    let threshold = |level: usize| {
+        if level == coarsest {
+            0.0
+        } else {
+            FINEST_SCREEN_SIZE / 4f64.powi(level as i32)
+        }
+    };
+    Lods {
+        min_screen_size: threshold(0),
+        levels: meshes
+            .into_iter()
+            .enumerate()
+            .map(|(i, mesh)| Level {
+                min_screen_size: threshold(i + 1),
+                mesh,
+            })
+            .collect(),
+    }
+}
+
+/// Solids whose curved surfaces come from a step count.
+fn is_curved(solid: &Solid) -> bool {
+    matches!(
+        solid,
+        Solid::Tube(_)
+            | Solid::Cone(_)
+            | Solid::Sphere(_)
+            | Solid::Polycone(_)
+            | Solid::Orb(_)
+            | Solid::Torus(_)
+            | Solid::CutTube(_)
+            | Solid::Ellipsoid(_)
+            | Solid::Eltube(_)
+            | Solid::GenericPolycone(_)
+            | Solid::Hype(_)
+            | Solid::Elcone(_)
+            | Solid::Paraboloid(_)
+            | Solid::TwistedTubs(_)
+            | Solid::TwistedBox(_)
+            | Solid::TwistedTrap(_)
+            | Solid::TwistedTrd(_)
+    )
+}
+
+/// Primitives bounded by planes alone.
+fn is_flat(solid: &Solid) -> bool {
+    matches!(
+        solid,
+        Solid::Box(_)
+            | Solid::Trd(_)
+            | Solid::Trap(_)
+            | Solid::Para(_)
+            | Solid::Arb8(_)
+            | Solid::Tet(_)
+            | Solid::Polyhedra(_)
+            | Solid::GenericPolyhedra(_)
+            | Solid::Xtru(_)
+    )
+}
+
+fn diagonal(mesh: &TriangleMesh) -> f64 {
+    let mut lo = [f64::INFINITY; 3];
+    let mut hi = [f64::NEG_INFINITY; 3];
+    for p in mesh.positions.chunks_exact(3) {
+        for i in 0..3 {
+            lo[i] = lo[i].min(p[i] as f64);
+            hi[i] = hi[i].max(p[i] as f64);
+        }
+    }
+    let d = (0..3).map(|i| (hi[i] - lo[i]).powi(2)).sum::<f64>().sqrt();
+    if d.is_finite() {
+        d
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdml::model::{BooleanOp, BooleanSolid, BoxSolid, TubeSolid};
+    use crate::mesh::csg::BooleanBackend;
+    use crate::mesh::integrity::analyze;
+
+    fn solids() -> SolidSection {
+        SolidSection {
+            solids: vec![
+                Solid::Box(BoxSolid {
+                    name: "Block".to_string(),
+                    x: "100".to_string(),
+                    y: "100".to_string(),
+                    z: "100".to_string(),
+                    lunit: None,
+                }),
+                Solid::Tube(TubeSolid {
+                    name: "Bore".to_string(),
+                    rmin: None,
+                    rmax: "20".to_string(),
+                    z: "200".to_string(),
+                    startphi: None,
+                    deltaphi: None,
+                    aunit: None,
+                    lunit: None,
+                }),
+                Solid::Boolean(BooleanSolid {
+                    name: "Drilled".to_string(),
+                    operation: BooleanOp::Subtraction,
+                    first_ref: "Block".to_string(),
+                    second_ref: "Bore".to_string(),
+                    position: None,
+                    rotation: None,
+                    first_position: None,
+                    first_rotation: None,
+                }),
+            ],
+            optical_surfaces: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn levels_get_coarser_and_thresholds_fall_to_zero() {
+        let solids = solids();
+        let engine = EvalEngine::new();
+        let quality = MeshQuality::fixed(64);
+        let (meshes, _) =
+            tessellator::tessellate_all_solids(&solids, &engine, &quality, BooleanBackend::Exact)
+                .unwrap();
+        let lods = build(&solids, &engine, &quality, &meshes);
+
+        assert!(lods["Block"].levels.is_empty());
+        assert_eq!(lods["Block"].min_screen_size, 0.0);
+
+        for name in ["Bore", "Drilled"] {
+            let lods = &lods[name];
+            assert!(!lods.levels.is_empty(), "{} has no coarser levels", name);
+            let mut triangles = meshes[name].triangle_count();
+            let mut threshold = lods.min_screen_size;
+            for level in &lods.levels {
+                assert!(level.mesh.triangle_count() < triangles);
+                assert!(level.min_screen_size < threshold);
+                triangles = level.mesh.triangle_count();
+                threshold = level.min_screen_size;
+                let report = analyze(&level.mesh);
+                assert!(report.is_clean(), "{}: {:?}", name, report.problems());
+            }
+            assert_eq!(threshold, 0.0);
+        }
+
+        // The bore is tessellated again at 32, 16 and 8 segments, so its
+        // coarsest level is an eight-sided prism of 32 facets.
+        assert_eq!(
+            lods["Bore"].levels.last().unwrap().mesh.triangle_count(),
+            32
+        );
+    }
+}
@@ -1,4 +1,18 @@
+pub mod arrangement;
+pub mod bake;
+pub mod bounds;
+pub mod bvh;
+pub mod clearance;
 pub mod csg;
+pub mod decimate;
+pub mod drawing;
+pub mod edges;
+pub mod integrity;
+pub mod lod;
+pub mod placement;
+pub mod predicates;
 pub mod primitives;
+pub mod quality;
+pub mod section;
 pub mod tessellator;
 pub mod types;
@@ -0,0 +1,112 @@
+//! World placements of the scene graph's instances.
+//!
+//! The scene graph gives every node its position and rotation within its
+//! mother; the viewer composes them on the GPU. Work on the whole placed
+//! geometry on the server -- sections, baked batches -- composes them here,
+//! in the same convention.
+
+use crate::gdml::model::SceneNode;
+
+/// A rotation followed by a translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Placement {
+    /// Row-major.
+    pub(crate) r: [[f64; 3]; 3],
+    pub(crate) t: [f64; 3],
+}
+
+impl Placement {
+    pub(crate) const IDENTITY: Placement = Placement {
+        r: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        t: [0.0; 3],
+    };
+
+    /// A scene node's placement within its mother. GDML rotations are applied
+    /// inverted, Rx(-x)·Ry(-y)·Rz(-z), as Geant4's reader and the viewer do.
+    pub(crate) fn of_node(node: &SceneNode) -> Self {
+        let finite = |v: f64| if v.is_finite() { v } else { 0.0 };
+        let [x, y, z] = node.rotation.map(|a| -finite(a));
+        let (sx, cx) = x.sin_cos();
+        let (sy, cy) = y.sin_cos();
+        let (sz, cz) = z.sin_cos();
+        let rx = [[1.0, 0.0, 0.0], [0.0, cx, -sx], [0.0, sx, cx]];
+        let ry = [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]];
+        let rz = [[cz, -sz, 0.0], [sz, cz, 0.0], [0.0, 0.0, 1.0]];
+        Placement {
+            r: mul(mul(rx, ry), rz),
+            t: node.position.map(finite),
+        }
+    }
+
+    /// This placement applied after `inner`.
+    pub(crate) fn then(&self, inner: &Placement) -> Placement {
+        Placement {
+            r: mul(self.r, inner.r),
+            t: add(self.rotate(inner.t), self.t),
+        }
+    }
+
+    /// `v` turned by the rotation alone, as for a normal.
+    pub(crate) fn rotate(&self, v: [f64; 3]) -> [f64; 3] {
+        [dot(self.r[0], v), dot(self.r[1], v), dot(self.r[2], v)]
+    }
+
+    pub(crate) fn apply(&self, p: [f64; 3]) -> [f64; 3] {
+        add(self.rotate(p), self.t)
+    }
+
+    /// The placement that undoes this one.
+    pub(crate) fn inverse(&self) -> Placement {
+        let r = self.r;
+        let rt = [0, 1, 2].map(|i| [r[0][i], r[1][i], r[2][i]]);
+        let t = [dot(rt[0], self.t), dot(rt[1], self.t), dot(rt[2], self.t)];
+        Placement {
+            r: rt,
+            t: t.map(|c| -c),
+        }
+    }
+
+    /// The 4x4 matrix in column-major order, as three.js and WebGL take it.
+    pub(crate) fn to_columns(self) -> [f64; 16] {
+        let (r, t) = (self.r, self.t);
+        [
+            r[0][0], r[1][0], r[2][0], 0.0, //
+            r[0][1], r[1][1], r[2][1], 0.0, //
+            r[0][2], r[1][2], r[2][2], 0.0, //
+            t[0], t[1], t[2], 1.0,
+        ]
+    }
+}
+
+/// Call `f` with every instance below `root` and its world placement, parents
+/// before children. The world volume itself is left out, as in the viewer.
+pub(crate) fn for_each_instance(root: &SceneNode, mut f: impl FnMut(&SceneNode, &Placement)) {
+    fn visit(node: &SceneNode, mother: &Placement, f: &mut impl FnMut(&SceneNode, &Placement)) {
+        let placement = mother.then(&Placement::of_node(node));
+        if !node.is_world {
+            f(node, &placement);
+        }
+        for child in &node.children {
+            visit(child, &placement, f);
+        }
+    }
+    visit(root, &Placement::IDENTITY, &mut f);
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn mul(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
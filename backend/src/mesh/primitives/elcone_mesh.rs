@@ -9,7 +9,14 @@ use std::f64::consts::PI;
 ///
 /// At height z, the elliptical cross-section has semi-axes:
 ///   rx(z) = dx * (zmax - z), ry(z) = dy * (zmax - z)
-pub fn tessellate_elcone(dx: f64, dy: f64, zmax: f64, zcut: f64, segments: u32) -> TriangleMesh {
+pub fn tessellate_elcone(
+    dx: f64,
+    dy: f64,
+    zmax: f64,
+    zcut: f64,
+    segments: u32,
+    z_segments: u32,
+) -> TriangleMesh {
     let mut positions: Vec<f32> = Vec::new();
     let mut normals: Vec<f32> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
@@ -24,7 +31,7 @@ pub fn tessellate_elcone(dx: f64, dy: f64, zmax: f64, zcut: f64, segments: u32)
 
     let zcut = zcut.min(zmax);
     let phi_seg = segments.max(3); // guard divisor against 0 (defense in depth)
-    let z_seg = (segments / 2).max(2);
+    let z_seg = z_segments.max(2);
     let dphi = 2.0 * PI / phi_seg as f64;
     let dz = 2.0 * zcut / z_seg as f64;
 
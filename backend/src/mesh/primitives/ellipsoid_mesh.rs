@@ -13,6 +13,7 @@ pub fn tessellate_ellipsoid(
     zcut1: f64,
     zcut2: f64,
     segments: u32,
+    theta_segments: u32,
 ) -> TriangleMesh {
     let mut positions: Vec<f32> = Vec::new();
     let mut normals: Vec<f32> = Vec::new();
@@ -43,7 +44,7 @@ pub fn tessellate_ellipsoid(
     let theta_end = (zcut1 / cz).clamp(-1.0, 1.0).acos(); // bottom cut -> larger theta
 
     let phi_segs = segments.max(3); // guard divisor against 0 (defense in depth)
-    let theta_segs = (segments / 2).max(2);
+    let theta_segs = theta_segments.max(2);
     let dphi = 2.0 * PI / phi_segs as f64;
     let dtheta = (theta_end - theta_start) / theta_segs as f64;
 
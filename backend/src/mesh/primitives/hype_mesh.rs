@@ -15,6 +15,7 @@ pub fn tessellate_hype(
     outst: f64,
     hz: f64,
     segments: u32,
+    z_segments: u32,
 ) -> TriangleMesh {
     let mut positions: Vec<f32> = Vec::new();
     let mut normals: Vec<f32> = Vec::new();
@@ -29,7 +30,7 @@ pub fn tessellate_hype(
     }
 
     let phi_seg = segments.max(3); // guard divisor against 0 (defense in depth)
-    let z_seg = (segments / 2).max(2);
+    let z_seg = z_segments.max(2);
     let dphi = 2.0 * PI / phi_seg as f64;
     let dz = 2.0 * hz / z_seg as f64;
 
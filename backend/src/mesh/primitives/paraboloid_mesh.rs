@@ -9,7 +9,13 @@ use std::f64::consts::PI;
 ///
 /// Parabolic profile: r²(z) = k1 + k2*z
 /// where k1 = (rhi²+rlo²)/2, k2 = (rhi²-rlo²)/(2*dz)
-pub fn tessellate_paraboloid(rlo: f64, rhi: f64, dz: f64, segments: u32) -> TriangleMesh {
+pub fn tessellate_paraboloid(
+    rlo: f64,
+    rhi: f64,
+    dz: f64,
+    segments: u32,
+    z_segments: u32,
+) -> TriangleMesh {
     let mut positions: Vec<f32> = Vec::new();
     let mut normals: Vec<f32> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
@@ -39,7 +45,7 @@ pub fn tessellate_paraboloid(rlo: f64, rhi: f64, dz: f64, segments: u32) -> Tria
     };
 
     let phi_seg = segments.max(3); // guard divisor against 0 (defense in depth)
-    let z_seg = (segments / 2).max(2);
+    let z_seg = z_segments.max(2);
     let dphi = 2.0 * PI / phi_seg as f64;
     let dz_step = 2.0 * dz / z_seg as f64;
 
@@ -12,10 +12,10 @@ pub fn tessellate_sphere(
     starttheta: f64,
     deltatheta: f64,
     segments: u32,
+    theta_segments: u32,
 ) -> TriangleMesh {
-    let seg = segments.max(4);
-    let phi_seg = seg;
-    let theta_seg = seg / 2;
+    let phi_seg = segments.max(4);
+    let theta_seg = theta_segments.max(2);
     let has_hole = rmin > 1e-10;
     let full_phi = (deltaphi - 2.0 * PI).abs() < 1e-6;
     let full_theta = starttheta.abs() < 1e-6 && (deltatheta - PI).abs() < 1e-6;
@@ -8,7 +8,8 @@ use std::f64::consts::PI;
 /// - `rtor`: distance from torus center to tube center
 /// - `startphi`: starting angle around the ring axis (radians)
 /// - `deltaphi`: angular sweep around the ring axis (radians)
-/// - `segments`: number of subdivisions for both ring and tube
+/// - `segments`: number of subdivisions around the ring
+/// - `tube_segments`: number of subdivisions around the tube cross-section
 pub fn tessellate_torus(
     rmin: f64,
     rmax: f64,
@@ -16,14 +17,15 @@ pub fn tessellate_torus(
     startphi: f64,
     deltaphi: f64,
     segments: u32,
+    tube_segments: u32,
 ) -> TriangleMesh {
     let mut positions: Vec<f32> = Vec::new();
     let mut normals: Vec<f32> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
 
-    let segments = segments.max(3); // guard divisor against 0 (defense in depth)
-    let ring_segs = segments;
-    let tube_segs = segments;
+    // guard divisors against 0 (defense in depth)
+    let ring_segs = segments.max(3);
+    let tube_segs = tube_segments.max(3);
     let full_ring = (deltaphi - 2.0 * PI).abs() < 1e-6;
 
     // Generate a surface of revolution for a given tube radius
@@ -71,6 +71,7 @@ pub fn tessellate_twisted_tubs(
     deltaphi: f64,
     twist_angle: f64,
     segments: u32,
+    z_segments: u32,
 ) -> TriangleMesh {
     let mut positions = Vec::new();
     let mut normals = Vec::new();
@@ -89,7 +90,7 @@ pub fn tessellate_twisted_tubs(
     let full_circle = (deltaphi - 2.0 * PI).abs() < 1e-6;
 
     let phi_segs = segments.max(3);
-    let z_segs = segments.max(3);
+    let z_segs = z_segments.max(3);
     let dphi = deltaphi / phi_segs as f64;
     let dz = (z_pos - z_neg) / z_segs as f64;
 
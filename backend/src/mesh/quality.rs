@@ -0,0 +1,217 @@
+//! How finely curved surfaces are tessellated.
+//!
+//! A single segment count gives a 2 m barrel and a 1 mm fibre the same angular
+//! step, so the barrel shows facets a centimetre deep while the fibre spends
+//! hundreds of triangles on a surface nobody can resolve. [`MeshQuality`]
+//! instead bounds how far a chord may stray from the true surface and how
+//! large an angle one step may turn through, and every solid works out its own
+//! step counts from its evaluated radii.
+//!
+//! Without either bound the fixed segment count applies everywhere, exactly as
+//! before.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::DEFAULT_MESH_SEGMENTS;
+
+/// Fewest steps around an arc; fewer than three does not enclose anything.
+const MIN_SEGMENTS: u32 = 3;
+
+/// Most steps along any one direction. Spheres and tori are O(n^2) in it, so
+/// this is what keeps a request from blowing up memory.
+const MAX_SEGMENTS: u32 = 512;
+
+/// Tessellation settings for one document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshQuality {
+    /// Steps across each swept angle when neither tolerance is set. Comes from
+    /// the request's own `segments` field, not from this object.
+    #[serde(skip)]
+    pub segments: u32,
+    /// Largest distance in mm between a chord and the arc it replaces.
+    pub chord_tolerance: Option<f64>,
+    /// Largest angle in degrees one step may turn through.
+    pub max_angle: Option<f64>,
+    /// Fixed segment counts for named solids, taking precedence over both
+    /// tolerances.
+    pub overrides: HashMap<String, u32>,
+}
+
+impl Default for MeshQuality {
+    fn default() -> Self {
+        Self::fixed(DEFAULT_MESH_SEGMENTS)
+    }
+}
+
+impl MeshQuality {
+    /// The same segment count for every solid.
+    pub fn fixed(segments: u32) -> Self {
+        Self {
+            segments,
+            chord_tolerance: None,
+            max_angle: None,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Settings for a level of detail `level` steps coarser: half the steps per
+    /// level, which is four times the chord deviation and twice the angle.
+    pub fn coarser(&self, level: u32) -> Self {
+        let halve = |n: u32| n.checked_shr(level).unwrap_or(0).max(MIN_SEGMENTS);
+        let scale = 2f64.powi(level as i32);
+        Self {
+            segments: halve(self.segments),
+            chord_tolerance: self.chord_tolerance.map(|t| t * scale * scale),
+            max_angle: self.max_angle.map(|a| a * scale),
+            overrides: self
+                .overrides
+                .iter()
+                .map(|(name, &n)| (name.clone(), halve(n)))
+                .collect(),
+        }
+    }
+
+    /// The settings that apply to the solid called `name`.
+    ///
+    /// Non-positive or non-finite tolerances are ignored rather than
+    /// rejected: they come straight from a request body, and a zero would ask
+    /// for infinitely many steps.
+    pub fn detail(&self, name: &str) -> Detail {
+        if let Some(&segments) = self.overrides.get(name) {
+            return Detail::fixed(segments);
+        }
+        let valid = |v: Option<f64>| v.filter(|v| v.is_finite() && *v > 0.0);
+        Detail {
+            segments: self.segments.clamp(MIN_SEGMENTS, MAX_SEGMENTS),
+            chord_tolerance: valid(self.chord_tolerance),
+            max_angle: valid(self.max_angle).map(f64::to_radians),
+        }
+    }
+}
+
+/// Step counts for one solid, from [`MeshQuality::detail`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detail {
+    segments: u32,
+    chord_tolerance: Option<f64>,
+    /// In radians.
+    max_angle: Option<f64>,
+}
+
+impl Detail {
+    /// `segments` steps across every sweep, whatever the radius.
+    pub fn fixed(segments: u32) -> Self {
+        Self {
+            segments: segments.clamp(MIN_SEGMENTS, MAX_SEGMENTS),
+            chord_tolerance: None,
+            max_angle: None,
+        }
+    }
+
+    /// Steps for an arc of `radius` mm swept through `sweep` radians: the
+    /// azimuth of a solid of revolution, the tube of a torus, the twist of a
+    /// twisted solid.
+    pub fn arc(&self, radius: f64, sweep: f64) -> u32 {
+        match self.steps(radius, sweep) {
+            Some(n) => n.clamp(MIN_SEGMENTS, MAX_SEGMENTS),
+            None => self.segments,
+        }
+    }
+
+    /// Steps along a profile that bends no tighter than `radius` mm and turns
+    /// through `sweep` radians in all: the meridian of a sphere or ellipsoid,
+    /// the z slices of a hyperboloid or paraboloid.
+    ///
+    /// A fixed count gives these half the steps of [`arc`](Self::arc), as they
+    /// span at most half a turn. A straight profile (infinite radius or no
+    /// sweep) needs no intermediate slices at all.
+    pub fn meridian(&self, radius: f64, sweep: f64) -> u32 {
+        match self.steps(radius, sweep) {
+            Some(n) => n.clamp(2, MAX_SEGMENTS),
+            None => self.segments / 2,
+        }
+    }
+
+    /// The steps both tolerances allow, or `None` when neither is set.
+    fn steps(&self, radius: f64, sweep: f64) -> Option<u32> {
+        if self.chord_tolerance.is_none() && self.max_angle.is_none() {
+            return None;
+        }
+        let mut step = self.max_angle.unwrap_or(f64::INFINITY);
+        if let Some(tolerance) = self.chord_tolerance {
+            // A chord subtending `t` sits r(1 - cos(t/2)) inside the arc.
+            if radius.is_finite() && radius > 0.0 {
+                let cos_half = (1.0 - tolerance / radius).max(-1.0);
+                step = step.min(2.0 * cos_half.acos());
+            }
+        }
+        let sweep = if sweep.is_finite() { sweep.abs() } else { 0.0 };
+        if sweep == 0.0 {
+            return Some(0);
+        }
+        Some((sweep / step).ceil().min(MAX_SEGMENTS as f64) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn quality(chord_tolerance: Option<f64>, max_angle: Option<f64>) -> MeshQuality {
+        MeshQuality {
+            chord_tolerance,
+            max_angle,
+            ..MeshQuality::fixed(32)
+        }
+    }
+
+    #[test]
+    fn without_tolerances_every_arc_gets_the_fixed_count() {
+        let detail = MeshQuality::fixed(32).detail("any");
+        assert_eq!(detail.arc(1000.0, 2.0 * PI), 32);
+        assert_eq!(detail.arc(0.5, PI / 4.0), 32);
+        assert_eq!(detail.meridian(10.0, PI), 16);
+    }
+
+    #[test]
+    fn chord_tolerance_scales_steps_with_radius() {
+        let detail = quality(Some(0.1), None).detail("any");
+        let barrel = detail.arc(1000.0, 2.0 * PI);
+        let fibre = detail.arc(0.5, 2.0 * PI);
+        assert_eq!(fibre, 5);
+        assert!(barrel > 200, "{} steps on a 1 m radius", barrel);
+
+        // Every chord of the barrel stays within the tolerance.
+        let step = 2.0 * PI / barrel as f64;
+        assert!(1000.0 * (1.0 - (step / 2.0).cos()) <= 0.1);
+    }
+
+    #[test]
+    fn max_angle_bounds_the_step_on_small_radii() {
+        let detail = quality(Some(0.1), Some(10.0)).detail("any");
+        assert_eq!(detail.arc(0.5, 2.0 * PI), 36);
+        assert_eq!(detail.arc(0.5, PI / 2.0), 9);
+        assert_eq!(detail.meridian(0.5, PI), 18);
+        assert_eq!(detail.meridian(f64::INFINITY, 0.0), 2);
+    }
+
+    #[test]
+    fn overrides_win_and_everything_is_clamped() {
+        let mut q = quality(Some(1e-9), None);
+        q.overrides.insert("fibre".to_string(), 7);
+        q.overrides.insert("huge".to_string(), 100_000);
+        assert_eq!(q.detail("fibre").arc(1000.0, 2.0 * PI), 7);
+        assert_eq!(q.detail("huge").arc(1.0, 2.0 * PI), MAX_SEGMENTS);
+        assert_eq!(q.detail("other").arc(1000.0, 2.0 * PI), MAX_SEGMENTS);
+        assert_eq!(MeshQuality::fixed(0).detail("any").arc(1.0, PI), 3);
+    }
+
+    #[test]
+    fn invalid_tolerances_fall_back_to_the_fixed_count() {
+        let detail = quality(Some(0.0), Some(f64::NAN)).detail("any");
+        assert_eq!(detail.arc(1000.0, 2.0 * PI), 32);
+    }
+}
@@ -0,0 +1,547 @@
+//! Cross-sections of the placed geometry by a plane.
+//!
+//! Each placed solid's mesh is carried to world space through the scene graph
+//! and cut with a [`Plane`]. Every facet the plane passes through contributes
+//! one segment, running between the two edges it crosses; the segments are
+//! then chained through those shared edges, so a closed mesh gives closed
+//! loops whatever the floating-point position of the crossings. Loops run
+//! anticlockwise round material and clockwise round holes, seen from the side
+//! the normal points to.
+//!
+//! Vertices within the tolerance of the plane count as lying on its positive
+//! side. Each facet then decides its crossings the same way as its
+//! neighbours, and a face lying in the plane adds nothing of its own: its
+//! outline comes from the facets next to it.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::csg::Plane;
+use super::integrity::weld;
+use super::placement::{for_each_instance, Placement};
+use super::types::TriangleMesh;
+use crate::gdml::model::SceneNode;
+
+/// Distance tolerance as a fraction of the largest world coordinate of the
+/// instance, as for the BSP booleans' coplanarity tolerance.
+const EPSILON_SCALE: f64 = 1e-6;
+
+/// Floor for the scaled tolerance.
+const MIN_EPSILON: f64 = 1e-9;
+
+/// A plane section of the whole scene, in the plane's own 2D coordinates.
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    /// World point the 2D coordinates are measured from.
+    pub origin: [f64; 3],
+    /// Unit normal of the plane.
+    pub normal: [f64; 3],
+    /// World direction of the 2D x axis.
+    pub u: [f64; 3],
+    /// World direction of the 2D y axis, the world's y (or z, for planes
+    /// facing along y) projected into the plane.
+    pub v: [f64; 3],
+    pub instances: Vec<SectionInstance>,
+}
+
+/// The outlines one placed solid leaves in the plane.
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionInstance {
+    pub instance_id: String,
+    pub volume_name: String,
+    pub solid_name: String,
+    pub material_name: String,
+    pub color: Option<String>,
+    /// g/cm3, as in the scene graph.
+    pub density: Option<f64>,
+    pub polylines: Vec<Polyline>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Polyline {
+    /// In mm along `u` and `v`. A closed polyline does not repeat its first
+    /// point.
+    pub points: Vec<[f64; 2]>,
+    /// False only where the mesh itself is open.
+    pub closed: bool,
+}
+
+/// Cut every placed solid below `root` with the plane through `origin` facing
+/// along `normal`. The world volume itself is left out, as in the viewer.
+pub fn section(
+    root: &SceneNode,
+    meshes: &HashMap<String, TriangleMesh>,
+    origin: [f64; 3],
+    normal: [f64; 3],
+) -> Result<Section> {
+    let Some(plane) = Plane::through(origin, normal) else {
+        bail!("The section plane needs a finite origin and a non-zero normal");
+    };
+    let (u, v) = basis(plane.normal);
+    let mut cutter = Cutter {
+        plane: &plane,
+        origin,
+        u,
+        v,
+        meshes,
+        welded: HashMap::new(),
+        instances: Vec::new(),
+    };
+    for_each_instance(root, |node, placement| cutter.cut_instance(node, placement));
+    Ok(Section {
+        origin,
+        normal: plane.normal,
+        u,
+        v,
+        instances: cutter.instances,
+    })
+}
+
+/// In-plane axes for `normal`, such that `u`, `v`, `normal` are right-handed:
+/// the section is seen as if looking back along the normal.
+fn basis(normal: [f64; 3]) -> ([f64; 3], [f64; 3]) {
+    let up = if normal[1].abs() < 0.9 {
+        [0.0, 1.0, 0.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    let v = normalize(sub(up, scale(normal, dot(normal, up))));
+    (cross(v, normal), v)
+}
+
+/// A solid's mesh with coincident vertices merged, so facets share edges.
+struct Welded {
+    positions: Vec<[f64; 3]>,
+    triangles: Vec<[u32; 3]>,
+    centre: [f64; 3],
+    radius: f64,
+}
+
+impl Welded {
+    fn new(mesh: &TriangleMesh) -> Self {
+        let (weld, positions, _) = weld(mesh);
+        let positions: Vec<[f64; 3]> = positions.iter().map(|p| p.map(f64::from)).collect();
+        let triangles = mesh
+            .indices
+            .chunks_exact(3)
+            .filter_map(|t| {
+                let t = [0, 1, 2].map(|i| weld.get(t[i] as usize).copied());
+                let [Some(a), Some(b), Some(c)] = t else {
+                    return None;
+                };
+                (a != b && b != c && c != a).then_some([a, b, c])
+            })
+            .collect();
+
+        let mut lo = [f64::INFINITY; 3];
+        let mut hi = [f64::NEG_INFINITY; 3];
+        for p in &positions {
+            for i in 0..3 {
+                lo[i] = lo[i].min(p[i]);
+                hi[i] = hi[i].max(p[i]);
+            }
+        }
+        let centre = scale(add(lo, hi), 0.5);
+        let radius = positions
+            .iter()
+            .map(|&p| norm(sub(p, centre)))
+            .fold(0.0, f64::max);
+        Welded {
+            positions,
+            triangles,
+            centre,
+            radius,
+        }
+    }
+}
+
+struct Cutter<'a> {
+    plane: &'a Plane,
+    origin: [f64; 3],
+    u: [f64; 3],
+    v: [f64; 3],
+    meshes: &'a HashMap<String, TriangleMesh>,
+    welded: HashMap<&'a str, Welded>,
+    instances: Vec<SectionInstance>,
+}
+
+impl<'a> Cutter<'a> {
+    fn cut_instance(&mut self, node: &SceneNode, placement: &Placement) {
+        let Some((name, mesh)) = self.meshes.get_key_value(&node.solid_name) else {
+            return;
+        };
+        let welded = self
+            .welded
+            .entry(name.as_str())
+            .or_insert_with(|| Welded::new(mesh));
+        if welded.triangles.is_empty() {
+            return;
+        }
+        // Rotations keep distances, so the bounding sphere rejects most
+        // instances without transforming a single vertex.
+        let centre = placement.apply(welded.centre);
+        if self.plane.distance(centre).abs() > welded.radius * (1.0 + EPSILON_SCALE) {
+            return;
+        }
+
+        let world: Vec<[f64; 3]> = welded
+            .positions
+            .iter()
+            .map(|&p| placement.apply(p))
+            .collect();
+        let extent = world
+            .iter()
+            .flatten()
+            .map(|c| c.abs())
+            .filter(|c| c.is_finite())
+            .fold(0.0, f64::max);
+        let eps = (extent * EPSILON_SCALE).max(MIN_EPSILON);
+        let distances: Vec<f64> = world
+            .iter()
+            .map(|&p| {
+                let d = self.plane.distance(p);
+                if d.abs() <= eps {
+                    0.0
+                } else {
+                    d
+                }
+            })
+            .collect();
+
+        let loops = cut(&welded.triangles, &world, &distances, self.plane.normal);
+        let polylines: Vec<Polyline> = loops
+            .into_iter()
+            .filter_map(|(points, closed)| self.project(&points, closed, eps))
+            .collect();
+        if polylines.is_empty() {
+            return;
+        }
+        self.instances.push(SectionInstance {
+            instance_id: node.instance_id.clone(),
+            volume_name: node.volume_name.clone(),
+            solid_name: node.solid_name.clone(),
+            material_name: node.material_name.clone(),
+            color: node.color.clone(),
+            density: node.density,
+            polylines,
+        });
+    }
+
+    /// The plane coordinates of `points`, without the repeats that facets
+    /// meeting at a vertex in the plane leave behind.
+    fn project(&self, points: &[[f64; 3]], closed: bool, eps: f64) -> Option<Polyline> {
+        let mut out: Vec<[f64; 2]> = Vec::with_capacity(points.len());
+        for &p in points {
+            let d = sub(p, self.origin);
+            let q = [dot(d, self.u), dot(d, self.v)];
+            if out.last().is_none_or(|&last| !same(last, q, eps)) {
+                out.push(q);
+            }
+        }
+        if closed && out.len() > 1 && same(out[0], out[out.len() - 1], eps) {
+            out.pop();
+        }
+        drop_collinear(&mut out, closed, eps);
+        let enough = if closed { 3 } else { 2 };
+        (out.len() >= enough).then_some(Polyline {
+            points: out,
+            closed,
+        })
+    }
+}
+
+/// Remove points lying on the straight line through their neighbours, such
+/// as where the plane crosses the diagonal of a flat quad.
+fn drop_collinear(points: &mut Vec<[f64; 2]>, closed: bool, eps: f64) {
+    let mut kept: Vec<[f64; 2]> = Vec::with_capacity(points.len());
+    for (i, &p) in points.iter().enumerate() {
+        let next = match points.get(i + 1) {
+            Some(&next) => Some(next),
+            None if closed => kept.first().copied(),
+            None => None,
+        };
+        match (kept.last(), next) {
+            (Some(&prev), Some(next)) if between(prev, p, next, eps) => {}
+            _ => kept.push(p),
+        }
+    }
+    // The first point of a loop has the last for its predecessor.
+    if closed && kept.len() > 3 && between(kept[kept.len() - 1], kept[0], kept[1], eps) {
+        kept.remove(0);
+    }
+    *points = kept;
+}
+
+/// Whether `p` lies on the segment from `a` to `b`, within `eps`.
+fn between(a: [f64; 2], p: [f64; 2], b: [f64; 2], eps: f64) -> bool {
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let e = [p[0] - a[0], p[1] - a[1]];
+    let len = d[0].hypot(d[1]);
+    let off_line = (d[0] * e[1] - d[1] * e[0]).abs();
+    d[0] * e[0] + d[1] * e[1] > 0.0 && e[0].hypot(e[1]) < len && off_line <= eps * len
+}
+
+fn same(a: [f64; 2], b: [f64; 2], eps: f64) -> bool {
+    (a[0] - b[0]).abs() <= eps && (a[1] - b[1]).abs() <= eps
+}
+
+/// Where the plane crosses the edge between two welded vertices.
+type EdgeKey = (u32, u32);
+
+fn edge_key(a: u32, b: u32) -> EdgeKey {
+    (a.min(b), a.max(b))
+}
+
+/// The world-space polylines `triangles` leave in the plane, each with
+/// whether it is closed.
+fn cut(
+    triangles: &[[u32; 3]],
+    world: &[[f64; 3]],
+    distances: &[f64],
+    normal: [f64; 3],
+) -> Vec<(Vec<[f64; 3]>, bool)> {
+    let mut crossings: HashMap<EdgeKey, [f64; 3]> = HashMap::new();
+    let mut segments: Vec<[EdgeKey; 2]> = Vec::new();
+    for &[a, b, c] in triangles {
+        let above = |v: u32| distances[v as usize] >= 0.0;
+        let mut ends: Vec<EdgeKey> = Vec::with_capacity(2);
+        for (p, q) in [(a, b), (b, c), (c, a)] {
+            if above(p) == above(q) {
+                continue;
+            }
+            let key = edge_key(p, q);
+            crossings.entry(key).or_insert_with(|| {
+                let (below, top) = if above(p) { (q, p) } else { (p, q) };
+                let (db, dt) = (distances[below as usize], distances[top as usize]);
+                let t = db / (db - dt);
+                let (pb, pt) = (world[below as usize], world[top as usize]);
+                add(pb, scale(sub(pt, pb), t))
+            });
+            ends.push(key);
+        }
+        let [start, end] = ends[..] else {
+            continue;
+        };
+
+        // Material on the left: the segment runs along normal x facet normal.
+        let (pa, pb, pc) = (world[a as usize], world[b as usize], world[c as usize]);
+        let facet = cross(sub(pb, pa), sub(pc, pa));
+        let along = cross(normal, facet);
+        if dot(sub(crossings[&end], crossings[&start]), along) >= 0.0 {
+            segments.push([start, end]);
+        } else {
+            segments.push([end, start]);
+        }
+    }
+
+    chain(&segments)
+        .into_iter()
+        .map(|(keys, closed)| (keys.iter().map(|k| crossings[k]).collect(), closed))
+        .collect()
+}
+
+/// Join segments sharing an end into polylines. Segments are joined whichever
+/// way they run, so a flipped facet cannot break a loop; each polyline then
+/// takes the direction most of its segments agree on.
+fn chain(segments: &[[EdgeKey; 2]]) -> Vec<(Vec<EdgeKey>, bool)> {
+    let mut incident: HashMap<EdgeKey, Vec<usize>> = HashMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        incident.entry(s[0]).or_default().push(i);
+        incident.entry(s[1]).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    let next_from = |key: EdgeKey, used: &mut Vec<bool>| -> Option<(EdgeKey, bool)> {
+        let i = *incident.get(&key)?.iter().find(|&&i| !used[i])?;
+        used[i] = true;
+        let [s, e] = segments[i];
+        Some(if s == key { (e, true) } else { (s, false) })
+    };
+
+    let mut polylines = Vec::new();
+    for first in 0..segments.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let [start, end] = segments[first];
+        let mut forward = vec![start, end];
+        let mut agree: i64 = 1;
+        let mut closed = false;
+        while let Some((key, along)) = next_from(*forward.last().unwrap(), &mut used) {
+            agree += if along { 1 } else { -1 };
+            if key == start {
+                closed = true;
+                break;
+            }
+            forward.push(key);
+        }
+        if !closed {
+            // Open: extend backwards from the start as well.
+            let mut backward = Vec::new();
+            let mut at = start;
+            while let Some((key, along)) = next_from(at, &mut used) {
+                agree += if along { -1 } else { 1 };
+                backward.push(key);
+                at = key;
+            }
+            backward.reverse();
+            backward.extend(forward);
+            forward = backward;
+        }
+        if agree < 0 {
+            forward.reverse();
+        }
+        polylines.push((forward, closed));
+    }
+    polylines
+}
+
+// ─── Math helpers ────────────────────────────────────────────────────────────
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    scale(a, 1.0 / norm(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives::box_mesh::tessellate_box;
+    use crate::mesh::primitives::tube_mesh::tessellate_tube;
+
+    fn node(name: &str, solid: &str, position: [f64; 3], rotation: [f64; 3]) -> SceneNode {
+        SceneNode {
+            name: name.to_string(),
+            instance_id: format!("/{}", name),
+            volume_name: name.to_string(),
+            solid_name: solid.to_string(),
+            material_name: "G4_Fe".to_string(),
+            color: None,
+            density: Some(7.874),
+            position,
+            rotation,
+            is_world: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn world(children: Vec<SceneNode>) -> SceneNode {
+        SceneNode {
+            is_world: true,
+            children,
+            ..node("World", "WorldBox", [0.0; 3], [0.0; 3])
+        }
+    }
+
+    /// Signed area, positive for anticlockwise loops.
+    fn area(points: &[[f64; 2]]) -> f64 {
+        let n = points.len();
+        (0..n)
+            .map(|i| {
+                let (p, q) = (points[i], points[(i + 1) % n]);
+                p[0] * q[1] - q[0] * p[1]
+            })
+            .sum::<f64>()
+            / 2.0
+    }
+
+    #[test]
+    fn placed_box_leaves_its_rotated_outline() {
+        let mut meshes = HashMap::new();
+        meshes.insert(
+            "WorldBox".to_string(),
+            tessellate_box(1000.0, 1000.0, 1000.0),
+        );
+        meshes.insert("Slab".to_string(), tessellate_box(10.0, 20.0, 30.0));
+        // Turned a quarter about z inside a mother that is itself shifted.
+        let mut mother = node("Mother", "", [100.0, 0.0, 0.0], [0.0; 3]);
+        mother.children = vec![node(
+            "Slab",
+            "Slab",
+            [0.0, 50.0, 0.0],
+            [0.0, 0.0, std::f64::consts::FRAC_PI_2],
+        )];
+        let scene = world(vec![mother]);
+
+        let out = section(&scene, &meshes, [0.0; 3], [0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(out.u, [1.0, 0.0, 0.0]);
+        assert_eq!(out.instances.len(), 1, "neither world nor mother");
+        let polylines = &out.instances[0].polylines;
+        assert_eq!(polylines.len(), 1);
+        let outline = &polylines[0];
+        assert!(outline.closed);
+        assert!((area(&outline.points) - 200.0).abs() < 1e-6);
+
+        let (mut lo, mut hi) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for p in &outline.points {
+            for i in 0..2 {
+                lo[i] = lo[i].min(p[i]);
+                hi[i] = hi[i].max(p[i]);
+            }
+        }
+        let close =
+            |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4;
+        assert!(close(lo, [90.0, 45.0]), "{:?}", lo);
+        assert!(close(hi, [110.0, 55.0]), "{:?}", hi);
+
+        // A plane missing the slab leaves nothing.
+        let out = section(&scene, &meshes, [0.0, 0.0, 20.0], [0.0, 0.0, 1.0]).unwrap();
+        assert!(out.instances.is_empty());
+        assert!(section(&scene, &meshes, [0.0; 3], [0.0; 3]).is_err());
+    }
+
+    #[test]
+    fn tube_gives_an_outer_loop_and_a_hole() {
+        let mut meshes = HashMap::new();
+        let tube = tessellate_tube(5.0, 8.0, 10.0, 0.0, 2.0 * std::f64::consts::PI, 32);
+        meshes.insert("Pipe".to_string(), tube);
+        let scene = world(vec![node("Pipe", "Pipe", [0.0; 3], [0.0; 3])]);
+
+        // Across the axis: two rings, the hole running the other way.
+        let out = section(&scene, &meshes, [0.0; 3], [0.0, 0.0, 1.0]).unwrap();
+        let polylines = &out.instances[0].polylines;
+        assert_eq!(polylines.len(), 2);
+        let mut areas: Vec<f64> = polylines.iter().map(|p| area(&p.points)).collect();
+        areas.sort_by(f64::total_cmp);
+        assert!(areas[0] < 0.0 && areas[1] > 0.0, "{:?}", areas);
+        assert!(polylines.iter().all(|p| p.closed && p.points.len() == 32));
+
+        // Along the axis, through the end faces' edges: two rectangles of the
+        // wall, 3 x 10 mm each.
+        let out = section(&scene, &meshes, [0.0; 3], [1.0, 0.0, 0.0]).unwrap();
+        let polylines = &out.instances[0].polylines;
+        assert_eq!(polylines.len(), 2);
+        for p in polylines {
+            assert!(p.closed);
+            assert!((area(&p.points) - 30.0).abs() < 1e-3, "{}", area(&p.points));
+        }
+    }
+}
@@ -2,13 +2,14 @@ use anyhow::Result;
 use std::collections::{HashMap, HashSet};
 use std::f64::consts::PI;
 
-use super::csg;
+use super::csg::{self, BooleanBackend};
 use super::primitives::{
     arb8_mesh, box_mesh, cone_mesh, cut_tube_mesh, elcone_mesh, ellipsoid_mesh, eltube_mesh,
     generic_polycone_mesh, hype_mesh, paraboloid_mesh, polycone_mesh, polyhedra_mesh, sphere_mesh,
     torus_mesh, trap_mesh, trd_mesh, tube_mesh, twisted_box_mesh, twisted_trap_mesh,
     twisted_tubs_mesh, xtru_mesh,
 };
+use super::quality::{Detail, MeshQuality};
 use super::types::TriangleMesh;
 use crate::eval::engine::EvalEngine;
 use crate::gdml::model::*;
@@ -17,12 +18,9 @@ use crate::gdml::units;
 pub fn tessellate_all_solids(
     solids: &SolidSection,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
 ) -> Result<(HashMap<String, TriangleMesh>, Vec<String>)> {
-    // Clamp the subdivision count to a safe range. `segments` comes straight from
-    // the request body; 0 would make `2*PI/segments` divide by zero (NaN geometry)
-    // and an unbounded value would blow up memory (sphere/torus are O(segments^2)).
-    let segments = segments.clamp(3, 512);
     let mut meshes = HashMap::new();
     let mut warnings = Vec::new();
 
@@ -34,7 +32,7 @@ pub fn tessellate_all_solids(
         let name = solid.name().to_string();
         match solid {
             Solid::Boolean(_) | Solid::Scaled(_) | Solid::Reflected(_) | Solid::MultiUnion(_) => {} // skip for phase 2
-            _ => match tessellate_solid(solid, engine, segments) {
+            _ => match tessellate_solid(solid, engine, quality.detail(&name)) {
                 Ok(mesh) => {
                     meshes.insert(name, mesh);
                 }
@@ -57,7 +55,8 @@ pub fn tessellate_all_solids(
                     &solid_map,
                     &mut meshes,
                     engine,
-                    segments,
+                    quality,
+                    booleans,
                     &mut resolving,
                 ) {
                     Ok(mesh) => {
@@ -77,7 +76,8 @@ pub fn tessellate_all_solids(
                     &solid_map,
                     &mut meshes,
                     engine,
-                    segments,
+                    quality,
+                    booleans,
                     &mut resolving,
                 ) {
                     Ok(mesh) => {
@@ -98,7 +98,8 @@ pub fn tessellate_all_solids(
                     &solid_map,
                     &mut meshes,
                     engine,
-                    segments,
+                    quality,
+                    booleans,
                     &mut resolving,
                 ) {
                     Ok(mesh) => {
@@ -118,7 +119,8 @@ pub fn tessellate_all_solids(
                     &solid_map,
                     &mut meshes,
                     engine,
-                    segments,
+                    quality,
+                    booleans,
                     &mut resolving,
                 ) {
                     Ok(mesh) => {
@@ -139,35 +141,45 @@ pub fn tessellate_all_solids(
     Ok((meshes, warnings))
 }
 
-fn tessellate_solid(solid: &Solid, engine: &EvalEngine, segments: u32) -> Result<TriangleMesh> {
+/// Mesh a single non-composite solid, as phase 1 of [`tessellate_all_solids`]
+/// does.
+pub fn tessellate_primitive(
+    solid: &Solid,
+    engine: &EvalEngine,
+    quality: &MeshQuality,
+) -> Result<TriangleMesh> {
+    tessellate_solid(solid, engine, quality.detail(solid.name()))
+}
+
+fn tessellate_solid(solid: &Solid, engine: &EvalEngine, detail: Detail) -> Result<TriangleMesh> {
     match solid {
         Solid::Box(s) => tessellate_box_solid(s, engine),
-        Solid::Tube(s) => tessellate_tube_solid(s, engine, segments),
-        Solid::Cone(s) => tessellate_cone_solid(s, engine, segments),
-        Solid::Sphere(s) => tessellate_sphere_solid(s, engine, segments),
+        Solid::Tube(s) => tessellate_tube_solid(s, engine, detail),
+        Solid::Cone(s) => tessellate_cone_solid(s, engine, detail),
+        Solid::Sphere(s) => tessellate_sphere_solid(s, engine, detail),
         Solid::Trd(s) => tessellate_trd_solid(s, engine),
-        Solid::Polycone(s) => tessellate_polycone_solid(s, engine, segments),
+        Solid::Polycone(s) => tessellate_polycone_solid(s, engine, detail),
         Solid::Xtru(s) => tessellate_xtru_solid(s, engine),
-        Solid::Orb(s) => tessellate_orb_solid(s, engine, segments),
-        Solid::Torus(s) => tessellate_torus_solid(s, engine, segments),
+        Solid::Orb(s) => tessellate_orb_solid(s, engine, detail),
+        Solid::Torus(s) => tessellate_torus_solid(s, engine, detail),
         Solid::Trap(s) => tessellate_trap_solid(s, engine),
         Solid::Para(s) => tessellate_para_solid(s, engine),
-        Solid::CutTube(s) => tessellate_cut_tube_solid(s, engine, segments),
+        Solid::CutTube(s) => tessellate_cut_tube_solid(s, engine, detail),
         Solid::Polyhedra(s) => tessellate_polyhedra_solid(s, engine),
         Solid::Tessellated(s) => tessellate_tessellated_solid(s, engine),
-        Solid::Ellipsoid(s) => tessellate_ellipsoid_solid(s, engine, segments),
-        Solid::Eltube(s) => tessellate_eltube_solid(s, engine, segments),
+        Solid::Ellipsoid(s) => tessellate_ellipsoid_solid(s, engine, detail),
+        Solid::Eltube(s) => tessellate_eltube_solid(s, engine, detail),
         Solid::Tet(s) => tessellate_tet_solid(s, engine),
-        Solid::GenericPolycone(s) => tessellate_generic_polycone_solid(s, engine, segments),
-        Solid::Hype(s) => tessellate_hype_solid(s, engine, segments),
-        Solid::Elcone(s) => tessellate_elcone_solid(s, engine, segments),
-        Solid::Paraboloid(s) => tessellate_paraboloid_solid(s, engine, segments),
+        Solid::GenericPolycone(s) => tessellate_generic_polycone_solid(s, engine, detail),
+        Solid::Hype(s) => tessellate_hype_solid(s, engine, detail),
+        Solid::Elcone(s) => tessellate_elcone_solid(s, engine, detail),
+        Solid::Paraboloid(s) => tessellate_paraboloid_solid(s, engine, detail),
         Solid::GenericPolyhedra(s) => tessellate_generic_polyhedra_solid(s, engine),
         Solid::Arb8(s) => tessellate_arb8_solid(s, engine),
-        Solid::TwistedTubs(s) => tessellate_twisted_tubs_solid(s, engine, segments),
-        Solid::TwistedBox(s) => tessellate_twisted_box_solid(s, engine, segments),
-        Solid::TwistedTrap(s) => tessellate_twisted_trap_solid(s, engine, segments),
-        Solid::TwistedTrd(s) => tessellate_twisted_trd_solid(s, engine, segments),
+        Solid::TwistedTubs(s) => tessellate_twisted_tubs_solid(s, engine, detail),
+        Solid::TwistedBox(s) => tessellate_twisted_box_solid(s, engine, detail),
+        Solid::TwistedTrap(s) => tessellate_twisted_trap_solid(s, engine, detail),
+        Solid::TwistedTrd(s) => tessellate_twisted_trd_solid(s, engine, detail),
         Solid::Scaled(_) => Err(anyhow::anyhow!("Scaled solids resolved in phase 2")),
         Solid::Reflected(_) => Err(anyhow::anyhow!("Reflected solids resolved in phase 2")),
         Solid::MultiUnion(_) => Err(anyhow::anyhow!("MultiUnion solids resolved in phase 2")),
@@ -180,7 +192,8 @@ fn tessellate_scaled_solid(
     solid_map: &HashMap<&str, &Solid>,
     meshes: &mut HashMap<String, TriangleMesh>,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
     resolving: &mut HashSet<String>,
 ) -> Result<TriangleMesh> {
     if let Some(mesh) = meshes.get(&ss.name) {
@@ -200,7 +213,8 @@ fn tessellate_scaled_solid(
             solid_map,
             meshes,
             engine,
-            segments,
+            quality,
+            booleans,
             resolving,
         )?;
 
@@ -290,7 +304,8 @@ fn tessellate_multiunion_solid(
     solid_map: &HashMap<&str, &Solid>,
     meshes: &mut HashMap<String, TriangleMesh>,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
     resolving: &mut HashSet<String>,
 ) -> Result<TriangleMesh> {
     if let Some(mesh) = meshes.get(&mu.name) {
@@ -316,7 +331,8 @@ fn tessellate_multiunion_solid(
             solid_map,
             meshes,
             engine,
-            segments,
+            quality,
+            booleans,
             resolving,
         )?;
         result_mesh =
@@ -329,12 +345,17 @@ fn tessellate_multiunion_solid(
                 solid_map,
                 meshes,
                 engine,
-                segments,
+                quality,
+                booleans,
                 resolving,
             )?;
             let node_mesh =
                 apply_placement_transform(&node_mesh, &node.position, &node.rotation, engine);
-            result_mesh = csg::union(&result_mesh, &node_mesh);
+            let (mesh, note) = booleans.union(&result_mesh, &node_mesh);
+            if let Some(note) = note {
+                engine.record_warning_public(format!("multiUnion \"{}\": {}", mu.name, note));
+            }
+            result_mesh = mesh;
         }
 
         Ok(result_mesh)
@@ -349,7 +370,8 @@ fn tessellate_reflected_solid(
     solid_map: &HashMap<&str, &Solid>,
     meshes: &mut HashMap<String, TriangleMesh>,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
     resolving: &mut HashSet<String>,
 ) -> Result<TriangleMesh> {
     if let Some(mesh) = meshes.get(&rs.name) {
@@ -369,7 +391,8 @@ fn tessellate_reflected_solid(
             solid_map,
             meshes,
             engine,
-            segments,
+            quality,
+            booleans,
             resolving,
         )?;
 
@@ -403,7 +426,8 @@ fn tessellate_boolean_solid(
     solid_map: &HashMap<&str, &Solid>,
     meshes: &mut HashMap<String, TriangleMesh>,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
     resolving: &mut HashSet<String>,
 ) -> Result<TriangleMesh> {
     if let Some(mesh) = meshes.get(&bs.name) {
@@ -424,7 +448,8 @@ fn tessellate_boolean_solid(
             solid_map,
             meshes,
             engine,
-            segments,
+            quality,
+            booleans,
             resolving,
         )?;
 
@@ -434,7 +459,8 @@ fn tessellate_boolean_solid(
             solid_map,
             meshes,
             engine,
-            segments,
+            quality,
+            booleans,
             resolving,
         )?;
 
@@ -447,11 +473,14 @@ fn tessellate_boolean_solid(
             apply_placement_transform(&second_mesh, &bs.position, &bs.rotation, engine);
 
         // Perform CSG operation
-        let result = match bs.operation {
-            BooleanOp::Subtraction => csg::subtract(&first_mesh, &second_mesh),
-            BooleanOp::Union => csg::union(&first_mesh, &second_mesh),
-            BooleanOp::Intersection => csg::intersect(&first_mesh, &second_mesh),
+        let (result, note) = match bs.operation {
+            BooleanOp::Subtraction => booleans.subtract(&first_mesh, &second_mesh),
+            BooleanOp::Union => booleans.union(&first_mesh, &second_mesh),
+            BooleanOp::Intersection => booleans.intersect(&first_mesh, &second_mesh),
         };
+        if let Some(note) = note {
+            engine.record_warning_public(format!("Boolean solid \"{}\": {}", bs.name, note));
+        }
 
         Ok(result)
     })();
@@ -465,7 +494,8 @@ fn resolve_operand(
     solid_map: &HashMap<&str, &Solid>,
     meshes: &mut HashMap<String, TriangleMesh>,
     engine: &EvalEngine,
-    segments: u32,
+    quality: &MeshQuality,
+    booleans: BooleanBackend,
     resolving: &mut HashSet<String>,
 ) -> Result<TriangleMesh> {
     // Check if already tessellated
@@ -480,30 +510,35 @@ fn resolve_operand(
 
     match solid {
         Solid::Boolean(bs) => {
-            let mesh =
-                tessellate_boolean_solid(bs, solid_map, meshes, engine, segments, resolving)?;
+            let mesh = tessellate_boolean_solid(
+                bs, solid_map, meshes, engine, quality, booleans, resolving,
+            )?;
             meshes.insert(name.to_string(), mesh.clone());
             Ok(mesh)
         }
         Solid::Scaled(ss) => {
-            let mesh = tessellate_scaled_solid(ss, solid_map, meshes, engine, segments, resolving)?;
+            let mesh = tessellate_scaled_solid(
+                ss, solid_map, meshes, engine, quality, booleans, resolving,
+            )?;
             meshes.insert(name.to_string(), mesh.clone());
             Ok(mesh)
         }
         Solid::Reflected(rs) => {
-            let mesh =
-                tessellate_reflected_solid(rs, solid_map, meshes, engine, segments, resolving)?;
+            let mesh = tessellate_reflected_solid(
+                rs, solid_map, meshes, engine, quality, booleans, resolving,
+            )?;
             meshes.insert(name.to_string(), mesh.clone());
             Ok(mesh)
         }
         Solid::MultiUnion(mu) => {
-            let mesh =
-                tessellate_multiunion_solid(mu, solid_map, meshes, engine, segments, resolving)?;
+            let mesh = tessellate_multiunion_solid(
+                mu, solid_map, meshes, engine, quality, booleans, resolving,
+            )?;
             meshes.insert(name.to_string(), mesh.clone());
             Ok(mesh)
         }
         _ => {
-            let mesh = tessellate_solid(solid, engine, segments)?;
+            let mesh = tessellate_solid(solid, engine, quality.detail(name))?;
             meshes.insert(name.to_string(), mesh.clone());
             Ok(mesh)
         }
@@ -560,7 +595,7 @@ fn resolve_placement_rot(rot: &Option<PlacementRot>, engine: &EvalEngine) -> [f6
     }
 }
 
-fn resolve(engine: &EvalEngine, expr: &str) -> f64 {
+pub(super) fn resolve(engine: &EvalEngine, expr: &str) -> f64 {
     engine.resolve_value(expr)
 }
 
@@ -595,7 +630,7 @@ fn resolve_opt(engine: &EvalEngine, expr: &Option<String>) -> f64 {
 /// needs a corpus to validate against first. See
 /// `resolve_with_lunit_does_not_double_convert_length_expressions` for the
 /// behaviour this preserves.
-fn resolve_with_lunit(engine: &EvalEngine, expr: &str, lunit: &str) -> f64 {
+pub(super) fn resolve_with_lunit(engine: &EvalEngine, expr: &str, lunit: &str) -> f64 {
     let val = engine.resolve_value(expr);
     if engine.expression_uses_length_symbols(expr) {
         val
@@ -611,7 +646,11 @@ fn resolve_with_lunit(engine: &EvalEngine, expr: &str, lunit: &str) -> f64 {
     }
 }
 
-fn resolve_opt_with_lunit(engine: &EvalEngine, expr: &Option<String>, lunit: &str) -> f64 {
+pub(super) fn resolve_opt_with_lunit(
+    engine: &EvalEngine,
+    expr: &Option<String>,
+    lunit: &str,
+) -> f64 {
     match expr {
         Some(s) => resolve_with_lunit(engine, s, lunit),
         None => 0.0,
@@ -622,7 +661,7 @@ fn resolve_opt_with_lunit(engine: &EvalEngine, expr: &Option<String>, lunit: &st
 /// If the expression references any symbols that are already angle values in
 /// radians (converted `type="angle"` quantities), skip the aunit conversion to
 /// avoid double-converting. Mirrors `resolve_with_lunit`.
-fn resolve_with_aunit(engine: &EvalEngine, expr: &str, aunit: &str) -> f64 {
+pub(super) fn resolve_with_aunit(engine: &EvalEngine, expr: &str, aunit: &str) -> f64 {
     let val = engine.resolve_value(expr);
     if engine.expression_uses_angle_symbols(expr) {
         val
@@ -634,7 +673,11 @@ fn resolve_with_aunit(engine: &EvalEngine, expr: &str, aunit: &str) -> f64 {
     }
 }
 
-fn resolve_opt_with_aunit(engine: &EvalEngine, expr: &Option<String>, aunit: &str) -> f64 {
+pub(super) fn resolve_opt_with_aunit(
+    engine: &EvalEngine,
+    expr: &Option<String>,
+    aunit: &str,
+) -> f64 {
     match expr {
         Some(s) => resolve_with_aunit(engine, s, aunit),
         None => 0.0,
@@ -668,7 +711,7 @@ fn resolve_opt_with_aunit(engine: &EvalEngine, expr: &Option<String>, aunit: &st
 /// The rule is quoted from the one vendored source that states it. Geant4 puts
 /// the other solids through an equivalent phi check, so applying it beyond
 /// polycone is consistent but inferred rather than proven.
-fn resolve_delta_phi(engine: &EvalEngine, expr: &Option<String>, aunit: &str) -> f64 {
+pub(super) fn resolve_delta_phi(engine: &EvalEngine, expr: &Option<String>, aunit: &str) -> f64 {
     const TWO_PI: f64 = 2.0 * PI;
     let raw = match expr {
         Some(e) => resolve_with_aunit(engine, e, aunit),
@@ -694,7 +737,7 @@ fn tessellate_box_solid(s: &BoxSolid, engine: &EvalEngine) -> Result<TriangleMes
 fn tessellate_tube_solid(
     s: &TubeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -703,6 +746,7 @@ fn tessellate_tube_solid(
     let z = resolve_with_lunit(engine, &s.z, lunit);
     let startphi = resolve_opt_with_aunit(engine, &s.startphi, aunit);
     let deltaphi = resolve_delta_phi(engine, &s.deltaphi, aunit);
+    let segments = detail.arc(rmax, deltaphi);
     Ok(tube_mesh::tessellate_tube(
         rmin, rmax, z, startphi, deltaphi, segments,
     ))
@@ -711,7 +755,7 @@ fn tessellate_tube_solid(
 fn tessellate_cone_solid(
     s: &ConeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -722,6 +766,7 @@ fn tessellate_cone_solid(
     let z = resolve_with_lunit(engine, &s.z, lunit);
     let startphi = resolve_opt_with_aunit(engine, &s.startphi, aunit);
     let deltaphi = resolve_delta_phi(engine, &s.deltaphi, aunit);
+    let segments = detail.arc(rmax1.max(rmax2), deltaphi);
     Ok(cone_mesh::tessellate_cone(
         rmin1, rmax1, rmin2, rmax2, z, startphi, deltaphi, segments,
     ))
@@ -730,7 +775,7 @@ fn tessellate_cone_solid(
 fn tessellate_sphere_solid(
     s: &SphereSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -744,7 +789,14 @@ fn tessellate_sphere_solid(
         None => PI,
     };
     Ok(sphere_mesh::tessellate_sphere(
-        rmin, rmax, startphi, deltaphi, starttheta, deltatheta, segments,
+        rmin,
+        rmax,
+        startphi,
+        deltaphi,
+        starttheta,
+        deltatheta,
+        detail.arc(rmax, deltaphi),
+        detail.meridian(rmax, deltatheta),
     ))
 }
 
@@ -801,7 +853,7 @@ fn tessellate_arb8_solid(s: &Arb8Solid, engine: &EvalEngine) -> Result<TriangleM
 fn tessellate_twisted_tubs_solid(
     s: &TwistedTubsSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -859,6 +911,7 @@ fn tessellate_twisted_tubs_solid(
         );
     }
 
+    // Along z, the outer edge turns through the whole twist.
     Ok(twisted_tubs_mesh::tessellate_twisted_tubs(
         rmin_mid,
         rmax_mid,
@@ -866,14 +919,15 @@ fn tessellate_twisted_tubs_solid(
         z_pos,
         deltaphi,
         twist_angle,
-        segments,
+        detail.arc(rmax_mid, deltaphi),
+        detail.arc(rmax_mid, twist_angle),
     ))
 }
 
 fn tessellate_twisted_box_solid(
     s: &TwistedBoxSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -881,6 +935,7 @@ fn tessellate_twisted_box_solid(
     let x = resolve_with_lunit(engine, &s.x, lunit);
     let y = resolve_with_lunit(engine, &s.y, lunit);
     let z = resolve_with_lunit(engine, &s.z, lunit);
+    let segments = detail.arc(0.5 * x.hypot(y), phi_twist);
     Ok(twisted_box_mesh::tessellate_twisted_box(
         phi_twist, x, y, z, segments,
     ))
@@ -889,7 +944,7 @@ fn tessellate_twisted_box_solid(
 fn tessellate_twisted_trap_solid(
     s: &TwistedTrapSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -904,6 +959,8 @@ fn tessellate_twisted_trap_solid(
     let x3 = resolve_with_lunit(engine, &s.x3, lunit);
     let x4 = resolve_with_lunit(engine, &s.x4, lunit);
     let alph = resolve_with_aunit(engine, &s.alph, aunit);
+    let corner = 0.5 * x1.max(x2).max(x3).max(x4).hypot(y1.max(y2));
+    let segments = detail.arc(corner, phi_twist);
     Ok(twisted_trap_mesh::tessellate_twisted_trap(
         phi_twist, z, theta, phi_angle, y1, x1, x2, y2, x3, x4, alph, segments,
     ))
@@ -912,7 +969,7 @@ fn tessellate_twisted_trap_solid(
 fn tessellate_twisted_trd_solid(
     s: &TwistedTrdSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -923,6 +980,7 @@ fn tessellate_twisted_trd_solid(
     let y2 = resolve_with_lunit(engine, &s.y2, lunit);
     let z = resolve_with_lunit(engine, &s.z, lunit);
     // TwistedTrd is TwistedTrap with theta=0, phi=0, alph=0, x1=x2=trd.x1, x3=x4=trd.x2
+    let segments = detail.arc(0.5 * x1.max(x2).hypot(y1.max(y2)), phi_twist);
     Ok(twisted_trap_mesh::tessellate_twisted_trap(
         phi_twist, z, 0.0, 0.0, y1, x1, x1, y2, x2, x2, 0.0, segments,
     ))
@@ -1057,7 +1115,7 @@ fn tessellate_polyhedra_solid(s: &PolyhedraSolid, engine: &EvalEngine) -> Result
 fn tessellate_cut_tube_solid(
     s: &CutTubeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -1082,6 +1140,7 @@ fn tessellate_cut_tube_solid(
             None => 1.0,
         },
     ];
+    let segments = detail.arc(rmax, deltaphi);
     Ok(cut_tube_mesh::tessellate_cut_tube(
         rmin, rmax, z, startphi, deltaphi, low_norm, high_norm, segments,
     ))
@@ -1124,7 +1183,7 @@ fn tessellate_trap_solid(s: &TrapSolid, engine: &EvalEngine) -> Result<TriangleM
 fn tessellate_torus_solid(
     s: &TorusSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -1134,11 +1193,17 @@ fn tessellate_torus_solid(
     let startphi = resolve_opt_with_aunit(engine, &s.startphi, aunit);
     let deltaphi = resolve_delta_phi(engine, &s.deltaphi, aunit);
     Ok(torus_mesh::tessellate_torus(
-        rmin, rmax, rtor, startphi, deltaphi, segments,
+        rmin,
+        rmax,
+        rtor,
+        startphi,
+        deltaphi,
+        detail.arc(rtor + rmax, deltaphi),
+        detail.arc(rmax, 2.0 * PI),
     ))
 }
 
-fn tessellate_orb_solid(s: &OrbSolid, engine: &EvalEngine, segments: u32) -> Result<TriangleMesh> {
+fn tessellate_orb_solid(s: &OrbSolid, engine: &EvalEngine, detail: Detail) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let r = resolve_with_lunit(engine, &s.r, lunit);
     Ok(sphere_mesh::tessellate_sphere(
@@ -1148,14 +1213,15 @@ fn tessellate_orb_solid(s: &OrbSolid, engine: &EvalEngine, segments: u32) -> Res
         2.0 * PI,
         0.0,
         PI,
-        segments,
+        detail.arc(r, 2.0 * PI),
+        detail.meridian(r, PI),
     ))
 }
 
 fn tessellate_ellipsoid_solid(
     s: &EllipsoidSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let ax = resolve_with_lunit(engine, &s.ax, lunit);
@@ -1170,20 +1236,31 @@ fn tessellate_ellipsoid_solid(
         Some(expr) => resolve_with_lunit(engine, expr, lunit),
         None => cz,
     };
+    // The meridian runs over the polar angle between the cuts; no arc of the
+    // ellipse bends more gently than a circle on the longest semi-axis.
+    let polar = |z: f64| (z / cz).clamp(-1.0, 1.0).acos();
+    let radius = ax.max(by);
     Ok(ellipsoid_mesh::tessellate_ellipsoid(
-        ax, by, cz, zcut1, zcut2, segments,
+        ax,
+        by,
+        cz,
+        zcut1,
+        zcut2,
+        detail.arc(radius, 2.0 * PI),
+        detail.meridian(radius.max(cz), polar(zcut1) - polar(zcut2)),
     ))
 }
 
 fn tessellate_eltube_solid(
     s: &EltubeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let dx = resolve_with_lunit(engine, &s.dx, lunit);
     let dy = resolve_with_lunit(engine, &s.dy, lunit);
     let dz = resolve_with_lunit(engine, &s.dz, lunit);
+    let segments = detail.arc(dx.max(dy), 2.0 * PI);
     Ok(eltube_mesh::tessellate_eltube(dx, dy, dz, segments))
 }
 
@@ -1275,7 +1352,7 @@ fn tessellate_tet_solid(s: &TetSolid, engine: &EvalEngine) -> Result<TriangleMes
 fn tessellate_polycone_solid(
     s: &PolyconeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -1293,6 +1370,8 @@ fn tessellate_polycone_solid(
         })
         .collect();
 
+    let rmax = planes.iter().fold(0.0, |r: f64, p| r.max(p.2));
+    let segments = detail.arc(rmax, deltaphi);
     Ok(polycone_mesh::tessellate_polycone(
         &planes, startphi, deltaphi, segments,
     ))
@@ -1301,7 +1380,7 @@ fn tessellate_polycone_solid(
 fn tessellate_generic_polycone_solid(
     s: &GenericPolyconeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -1321,6 +1400,8 @@ fn tessellate_generic_polycone_solid(
         })
         .collect();
 
+    let rmax = contour.iter().fold(0.0, |r: f64, p| r.max(p.0));
+    let segments = detail.arc(rmax, deltaphi);
     Ok(generic_polycone_mesh::tessellate_generic_polycone(
         &contour, startphi, deltaphi, segments, None,
     ))
@@ -1329,7 +1410,7 @@ fn tessellate_generic_polycone_solid(
 fn tessellate_hype_solid(
     s: &HypeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let aunit = s.aunit.as_deref().unwrap_or("rad");
@@ -1339,15 +1420,38 @@ fn tessellate_hype_solid(
     let outst = resolve_opt_with_aunit(engine, &s.outst, aunit);
     let z = resolve_with_lunit(engine, &s.z, lunit);
     let hz = z * 0.5; // Geant4 convention: z is full length, halved for constructor
+                      // r(z) = sqrt(r0^2 + z^2 tan^2) bends tightest at the waist, with radius
+                      // r0 / tan^2, and turns through twice its slope angle at the ends.
+    let profile = |r0: f64, stereo: f64| {
+        let t2 = stereo.tan().powi(2);
+        let r_end = (r0 * r0 + hz * hz * t2).sqrt();
+        let turn = if r_end > 0.0 {
+            2.0 * (hz * t2 / r_end).atan()
+        } else {
+            0.0
+        };
+        detail.meridian(r0 / t2, turn)
+    };
+    let mut z_segments = profile(rmax, outst);
+    if rmin > 1e-10 {
+        z_segments = z_segments.max(profile(rmin, inst));
+    }
+    let r_end = (rmax * rmax + (hz * outst.tan()).powi(2)).sqrt();
     Ok(hype_mesh::tessellate_hype(
-        rmin, rmax, inst, outst, hz, segments,
+        rmin,
+        rmax,
+        inst,
+        outst,
+        hz,
+        detail.arc(r_end, 2.0 * PI),
+        z_segments,
     ))
 }
 
 fn tessellate_elcone_solid(
     s: &ElconeSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     // dx, dy are dimensionless ratios — NOT scaled by lunit
@@ -1355,20 +1459,39 @@ fn tessellate_elcone_solid(
     let dy = resolve(engine, &s.dy);
     let zmax = resolve_with_lunit(engine, &s.zmax, lunit);
     let zcut = resolve_with_lunit(engine, &s.zcut, lunit);
-    Ok(elcone_mesh::tessellate_elcone(dx, dy, zmax, zcut, segments))
+    // Straight generators: the z slices add nothing but the fixed count's.
+    let radius = dx.max(dy) * (zmax + zcut);
+    Ok(elcone_mesh::tessellate_elcone(
+        dx,
+        dy,
+        zmax,
+        zcut,
+        detail.arc(radius, 2.0 * PI),
+        detail.meridian(f64::INFINITY, 0.0),
+    ))
 }
 
 fn tessellate_paraboloid_solid(
     s: &ParaboloidSolid,
     engine: &EvalEngine,
-    segments: u32,
+    detail: Detail,
 ) -> Result<TriangleMesh> {
     let lunit = s.lunit.as_deref().unwrap_or("mm");
     let rlo = resolve_with_lunit(engine, &s.rlo, lunit);
     let rhi = resolve_with_lunit(engine, &s.rhi, lunit);
     let dz = resolve_with_lunit(engine, &s.dz, lunit);
+    // As a curve z(r) the profile is a parabola of apex curvature radius
+    // k/2, k = (rhi^2 - rlo^2) / 2dz, bending least tightly at its widest.
+    let k = (rhi * rhi - rlo * rlo).abs() / (2.0 * dz);
+    let slope = |r: f64| (2.0 * r / k).atan();
+    let (r_narrow, r_wide) = (rlo.min(rhi), rlo.max(rhi));
+    let bend = 0.5 * k * (1.0 + (2.0 * r_narrow / k).powi(2)).powf(1.5);
     Ok(paraboloid_mesh::tessellate_paraboloid(
-        rlo, rhi, dz, segments,
+        rlo,
+        rhi,
+        dz,
+        detail.arc(r_wide, 2.0 * PI),
+        detail.meridian(bend, slope(r_wide) - slope(r_narrow)),
     ))
 }
 
@@ -1516,12 +1639,62 @@ mod tests {
                     first_rotation: None,
                 }),
             ],
+            optical_surfaces: Vec::new(),
         };
 
         let engine = EvalEngine::new();
-        let (_meshes, warnings) = tessellate_all_solids(&solids, &engine, 24).unwrap();
+        let (_meshes, warnings) = tessellate_all_solids(
+            &solids,
+            &engine,
+            &MeshQuality::fixed(24),
+            BooleanBackend::Bsp,
+        )
+        .unwrap();
         assert!(warnings
             .iter()
             .any(|w| w.contains("Cyclic boolean solid dependency detected")));
     }
+
+    #[test]
+    fn chord_tolerance_gives_each_tube_its_own_segment_count() {
+        let tube = |name: &str, rmax: &str| {
+            Solid::Tube(TubeSolid {
+                name: name.to_string(),
+                rmin: None,
+                rmax: rmax.to_string(),
+                z: "10".to_string(),
+                startphi: None,
+                deltaphi: None,
+                aunit: None,
+                lunit: None,
+            })
+        };
+        let solids = SolidSection {
+            solids: vec![
+                tube("Barrel", "1000"),
+                tube("Fibre", "0.5"),
+                tube("Pinned", "1000"),
+            ],
+            optical_surfaces: Vec::new(),
+        };
+        let engine = EvalEngine::new();
+        // A full tube of n segments has 4n triangles: side and two caps.
+        let segments = |quality: &MeshQuality| {
+            let (meshes, _) =
+                tessellate_all_solids(&solids, &engine, quality, BooleanBackend::Bsp).unwrap();
+            ["Barrel", "Fibre", "Pinned"].map(|n| meshes[n].indices.len() / 12)
+        };
+
+        assert_eq!(segments(&MeshQuality::fixed(32)), [32, 32, 32]);
+
+        let mut quality = MeshQuality {
+            chord_tolerance: Some(0.1),
+            ..MeshQuality::fixed(32)
+        };
+        quality.overrides.insert("Pinned".to_string(), 16);
+        let [barrel, fibre, pinned] = segments(&quality);
+        assert!(barrel > 200, "barrel got {} segments", barrel);
+        assert_eq!(fibre, 5);
+        assert_eq!(pinned, 16);
+    }
 }
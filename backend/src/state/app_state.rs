@@ -2,8 +2,13 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use super::local_files::LocalSource;
 use crate::eval::engine::EvalEngine;
 use crate::gdml::model::GdmlDocument;
+use crate::mesh::bvh::SceneIndex;
+use crate::mesh::csg::BooleanBackend;
+use crate::mesh::lod::Lods;
+use crate::mesh::quality::MeshQuality;
 use crate::mesh::types::TriangleMesh;
 
 pub struct LoadedDocument {
@@ -19,8 +24,26 @@ pub struct LoadedDocument {
     pub render: Option<GdmlDocument>,
     pub engine: EvalEngine,
     pub meshes: HashMap<String, TriangleMesh>,
+    /// Tessellation settings `meshes` were made with; reloads and coarser
+    /// levels of detail start from these.
+    pub quality: MeshQuality,
+    /// Boolean implementation `meshes` were made with, which an edit that
+    /// re-meshes a composite solid uses again.
+    pub booleans: BooleanBackend,
+    /// Ray queries over the placed meshes. Edits that re-mesh a solid or
+    /// change what is placed where must update it.
+    pub spatial: SceneIndex,
+    /// Coarser levels of each mesh, kept in step with `meshes` alongside
+    /// `spatial`.
+    pub lods: HashMap<String, Lods>,
+    /// Feature edges of each mesh at the default crease angle, kept in step
+    /// the same way.
+    pub edges: HashMap<String, Vec<u32>>,
     pub warnings: Vec<String>,
     pub file_path: String,
+    /// Set when the document was opened from disk in local filesystem mode;
+    /// `None` for browser uploads, which have no path to save back to.
+    pub local: Option<LocalSource>,
 }
 
 impl LoadedDocument {
@@ -29,6 +52,14 @@ impl LoadedDocument {
     pub fn geometry(&self) -> &GdmlDocument {
         self.render.as_ref().unwrap_or(&self.document)
     }
+
+    /// Record that `document` was edited since it was last read from or
+    /// written to disk. A no-op for uploads.
+    pub fn mark_edited(&mut self) {
+        if let Some(local) = self.local.as_mut() {
+            local.dirty = true;
+        }
+    }
 }
 
 pub type SharedState = Arc<RwLock<AppState>>;
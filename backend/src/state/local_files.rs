@@ -0,0 +1,406 @@
+//! Opt-in local filesystem mode.
+//!
+//! By default the backend never touches the disk: files arrive as upload
+//! bodies and leave as browser downloads. When `GDML_FS_ROOT` is set the
+//! backend may also open a GDML file by path, resolve its `<file>` references
+//! next to it, write edits back in place, and watch the whole file set so an
+//! external editor or generator script is picked up without re-uploading.
+//!
+//! Every path is confined to the configured root after canonicalisation, so a
+//! `../` in a request or in a `<file name="...">` cannot reach outside it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::gdml::parser;
+use crate::gdml::structure::normalize_include_path;
+use crate::mesh::csg::BooleanBackend;
+
+/// Where a loaded document came from when it was opened from disk.
+pub struct LocalSource {
+    /// Canonical path of the main (world) file.
+    pub main_path: PathBuf,
+    /// Content fingerprint of every file that was read, main file included.
+    /// A watcher event only triggers a reload when one of these changes, which
+    /// filters out duplicate events and the echo of our own saves.
+    pub fingerprints: HashMap<PathBuf, u64>,
+    /// Boolean implementation the document was tessellated with, likewise.
+    pub booleans: Option<BooleanBackend>,
+    /// Bumped on every automatic reload so a client can poll for changes.
+    pub revision: u64,
+    /// Set by every API edit and cleared by a save. While it is set a change
+    /// on disk is not reloaded over the edits; it is reported as a conflict.
+    pub dirty: bool,
+    /// Why the most recent automatic reload failed or was held back, if it
+    /// was. The previous document stays loaded in that case.
+    pub last_error: Option<String>,
+    /// Keeps the filesystem notifier alive; dropped with the document.
+    pub watcher: Option<FileWatcher>,
+}
+
+impl LocalSource {
+    /// The watched file set, main file first, for status reporting.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut others: Vec<PathBuf> = self
+            .fingerprints
+            .keys()
+            .filter(|p| **p != self.main_path)
+            .cloned()
+            .collect();
+        others.sort();
+        let mut files = vec![self.main_path.clone()];
+        files.extend(others);
+        files
+    }
+}
+
+/// The main file plus every file reachable through `<file>` references.
+pub struct FileSet {
+    /// Name of the main file, as used for `GdmlDocument::filename`.
+    pub main_name: String,
+    /// File contents keyed by the name the merge looks them up by: the main
+    /// file's own name, and each child's normalised include path.
+    pub contents: HashMap<String, String>,
+    /// Canonical path of every file read, keyed the same way as `contents`.
+    pub paths: HashMap<String, PathBuf>,
+    /// `<file>` references that could not be read.
+    pub missing: Vec<String>,
+}
+
+impl FileSet {
+    pub fn fingerprints(&self) -> HashMap<PathBuf, u64> {
+        self.paths
+            .iter()
+            .filter_map(|(name, path)| {
+                self.contents
+                    .get(name)
+                    .map(|c| (path.clone(), fingerprint(c.as_bytes())))
+            })
+            .collect()
+    }
+}
+
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Resolve `requested` against `root` and make sure the result stays inside it.
+///
+/// Relative paths are taken relative to the root. The file must exist:
+/// canonicalisation is what collapses `..` and symlinks, and it needs a real
+/// path to do that.
+pub fn resolve_under_root(root: &Path, requested: &Path) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Local file root '{}' is not accessible", root.display()))?;
+    let joined = if requested.is_absolute() {
+        requested.to_path_buf()
+    } else {
+        root.join(requested)
+    };
+    let resolved = joined
+        .canonicalize()
+        .with_context(|| format!("Cannot open '{}'", joined.display()))?;
+    if !resolved.starts_with(&root) {
+        bail!(
+            "'{}' is outside the local file root '{}'",
+            requested.display(),
+            root.display()
+        );
+    }
+    Ok(resolved)
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).with_context(|| format!("Cannot read '{}'", path.display()))?;
+    // Same decoding the browser applies to an uploaded file, so a document
+    // opened from disk parses exactly as the uploaded copy would.
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Read `main_path` and every file its `<file>` references reach, recursively.
+///
+/// Each reference is resolved relative to the file it appears in, and keyed by
+/// its normalised path relative to the main file's directory -- the same key
+/// the merge computes, so the lookup is exact. A reference that cannot be read
+/// is left out of the set; the merge then reports it as not provided, the same
+/// as a multi-file upload missing a file.
+pub fn read_file_set(root: &Path, main_path: &Path) -> Result<FileSet> {
+    let main_name = main_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("'{}' has no file name", main_path.display()))?
+        .to_string();
+    let base_dir = main_path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+    let mut contents = HashMap::new();
+    let mut paths = HashMap::new();
+    let mut pending = VecDeque::from([(main_name.clone(), main_path.to_path_buf())]);
+    let mut seen = HashSet::from([main_name.clone()]);
+    let mut missing = Vec::new();
+
+    while let Some((name, path)) = pending.pop_front() {
+        let content = match read_text(&path) {
+            Ok(c) => c,
+            Err(e) if name == main_name => return Err(e),
+            Err(_) => {
+                missing.push(name);
+                continue;
+            }
+        };
+        let doc = parser::parse_gdml_from_bytes(content.as_bytes(), name.clone())
+            .map_err(|e| anyhow!("Parse error in {}: {}", name, e))?;
+        for vol in &doc.structure.volumes {
+            for pv in &vol.physvols {
+                let Some(fref) = &pv.file_ref else { continue };
+                let key = normalize_include_path(&name, &fref.name);
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+                match resolve_under_root(root, &base_dir.join(&key)) {
+                    Ok(child) => pending.push_back((key, child)),
+                    Err(_) => missing.push(key),
+                }
+            }
+        }
+        contents.insert(name.clone(), content);
+        paths.insert(name, path);
+    }
+
+    Ok(FileSet {
+        main_name,
+        contents,
+        paths,
+        missing,
+    })
+}
+
+/// Write `content` over `path`, keeping the previous version as `<path>.bak`.
+///
+/// The new content goes to a temporary sibling first and is renamed over the
+/// original, so an interrupted save never leaves a truncated GDML file behind.
+/// Returns the backup path, or `None` when there was no previous version.
+pub fn save_with_backup(path: &Path, content: &str) -> Result<Option<PathBuf>> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let backup = if path.exists() {
+        std::fs::copy(path, &backup)
+            .with_context(|| format!("Cannot create backup '{}'", backup.display()))?;
+        Some(backup)
+    } else {
+        None
+    };
+    std::fs::write(&tmp, content).with_context(|| format!("Cannot write '{}'", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Cannot replace '{}'", path.display()))?;
+    Ok(backup)
+}
+
+/// Where the module at `relative` (to the main file's directory) lives on
+/// disk. Its directory is created when missing -- a subtree split into a new
+/// file may name one that does not exist yet -- but never outside `root`.
+///
+/// The path is checked lexically, then through whatever part of it already
+/// exists with symlinks resolved before anything is created, and the file
+/// itself when it is a symlink: a link inside the root may lead out of it.
+pub fn module_target(root: &Path, main_dir: &Path, relative: &str) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Local file root '{}' is not accessible", root.display()))?;
+    let outside = || {
+        anyhow!(
+            "'{}' is outside the local file root '{}'",
+            relative,
+            root.display()
+        )
+    };
+    let joined = if relative.starts_with('/') {
+        normalize_include_path("", relative)
+    } else {
+        normalize_include_path("", &format!("{}/{}", main_dir.display(), relative))
+    };
+    let target = PathBuf::from(joined);
+    let (Some(parent), Some(file)) = (target.parent(), target.file_name()) else {
+        bail!("'{}' is not a file path", relative);
+    };
+    if !parent.starts_with(&root) {
+        return Err(outside());
+    }
+    // `symlink_metadata` stops at a link, dangling or not, which is then
+    // resolved rather than created through.
+    let existing = parent
+        .ancestors()
+        .find(|p| p.symlink_metadata().is_ok())
+        .unwrap_or(&root);
+    let existing = existing
+        .canonicalize()
+        .with_context(|| format!("Cannot resolve '{}'", existing.display()))?;
+    if !existing.starts_with(&root) {
+        return Err(outside());
+    }
+    std::fs::create_dir_all(parent)
+        .with_context(|| format!("Cannot create '{}'", parent.display()))?;
+    let parent = parent.canonicalize()?;
+    if !parent.starts_with(&root) {
+        return Err(outside());
+    }
+    let path = parent.join(file);
+    match path.symlink_metadata() {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            let resolved = path
+                .canonicalize()
+                .with_context(|| format!("Cannot resolve '{}'", path.display()))?;
+            if !resolved.starts_with(&root) {
+                return Err(outside());
+            }
+            Ok(resolved)
+        }
+        _ => Ok(path),
+    }
+}
+
+/// A live filesystem notifier for one document's file set.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+/// Watch `files` and send a unit message whenever any of them changes.
+///
+/// The parent directories are watched rather than the files themselves: most
+/// editors save by writing a new file and renaming it over the old one, which
+/// silently ends a watch held on the old inode.
+pub fn watch_files(files: &[PathBuf], notify_tx: UnboundedSender<()>) -> Result<FileWatcher> {
+    let wanted: HashSet<PathBuf> = files.iter().cloned().collect();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else { return };
+        if event.kind.is_access() {
+            return;
+        }
+        if event.paths.iter().any(|p| wanted.contains(p)) {
+            let _ = notify_tx.send(());
+        }
+    })
+    .context("Cannot start filesystem watcher")?;
+
+    let dirs: HashSet<&Path> = files.iter().filter_map(|f| f.parent()).collect();
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Cannot watch '{}'", dir.display()))?;
+    }
+    Ok(FileWatcher { _watcher: watcher })
+}
+
+/// A fresh, empty directory under the system temp dir for a test to use as
+/// its local file root.
+#[cfg(test)]
+pub(crate) fn scratch_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "gdml-studio-{}-{}-{}",
+        tag,
+        std::process::id(),
+        fingerprint(format!("{:?}", std::time::SystemTime::now()).as_bytes())
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOTHER: &str = r#"<gdml><structure>
+        <volume name="World"><materialref ref="G4_AIR"/><solidref ref="W"/>
+          <physvol><file name="parts/child.gdml"/></physvol>
+        </volume></structure><setup name="Default" version="1.0"><world ref="World"/></setup></gdml>"#;
+    const CHILD: &str = r#"<gdml><structure>
+        <volume name="Child"><materialref ref="G4_AIR"/><solidref ref="C"/></volume>
+        </structure><setup name="Default" version="1.0"><world ref="Child"/></setup></gdml>"#;
+
+    #[test]
+    fn paths_outside_the_root_are_rejected() {
+        let root = scratch_dir("escape");
+        let inner = root.join("inner");
+        std::fs::create_dir_all(&inner).unwrap();
+        std::fs::write(root.join("outside.gdml"), "<gdml/>").unwrap();
+
+        assert!(resolve_under_root(&inner, Path::new("../outside.gdml")).is_err());
+        assert!(resolve_under_root(&root, Path::new("inner/../outside.gdml")).is_ok());
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn file_references_resolve_next_to_the_main_file() {
+        let root = scratch_dir("fileset");
+        std::fs::create_dir_all(root.join("parts")).unwrap();
+        std::fs::write(root.join("mother.gdml"), MOTHER).unwrap();
+        std::fs::write(root.join("parts/child.gdml"), CHILD).unwrap();
+
+        let main = resolve_under_root(&root, Path::new("mother.gdml")).unwrap();
+        let set = read_file_set(&root, &main).unwrap();
+        assert_eq!(set.main_name, "mother.gdml");
+        assert!(set.contents.contains_key("parts/child.gdml"));
+        assert_eq!(set.paths["parts/child.gdml"], root.join("parts/child.gdml"));
+        assert_eq!(set.fingerprints().len(), 2);
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn save_keeps_previous_version_as_backup() {
+        let root = scratch_dir("save");
+        let path = root.join("model.gdml");
+        std::fs::write(&path, "old").unwrap();
+
+        let backup = save_with_backup(&path, "new").unwrap().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "old");
+        assert!(!root.join("model.gdml.tmp").exists());
+
+        let fresh = module_target(&root, &root, "modules/part.gdml").unwrap();
+        assert_eq!(save_with_backup(&fresh, "part").unwrap(), None);
+        assert!(module_target(&root, &root, "../escaped.gdml").is_err());
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn module_targets_do_not_follow_symlinks_out_of_the_root() {
+        use std::os::unix::fs::symlink;
+        let root = scratch_dir("symlink-root");
+        let outside = scratch_dir("symlink-outside");
+        symlink(&outside, root.join("linked")).unwrap();
+        std::fs::write(outside.join("target.gdml"), "outside").unwrap();
+        symlink(outside.join("target.gdml"), root.join("escape.gdml")).unwrap();
+        std::fs::write(root.join("real.gdml"), "inside").unwrap();
+        symlink(root.join("real.gdml"), root.join("alias.gdml")).unwrap();
+
+        let err = module_target(&root, &root, "linked/new/part.gdml").unwrap_err();
+        assert!(
+            err.to_string().contains("outside the local file root"),
+            "{err}"
+        );
+        assert!(
+            !outside.join("new").exists(),
+            "created a directory outside the root"
+        );
+        assert!(module_target(&root, &root, "linked/part.gdml").is_err());
+        assert!(module_target(&root, &root, "escape.gdml").is_err());
+        assert_eq!(
+            module_target(&root, &root, "alias.gdml").unwrap(),
+            root.join("real.gdml")
+        );
+        std::fs::remove_dir_all(&root).ok();
+        std::fs::remove_dir_all(&outside).ok();
+    }
+}
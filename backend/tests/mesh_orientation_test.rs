@@ -18,6 +18,7 @@
 
 use std::f64::consts::PI;
 
+use gdml_studio_backend::mesh::integrity::analyze;
 use gdml_studio_backend::mesh::primitives::{
     arb8_mesh::tessellate_arb8, box_mesh::tessellate_box, cone_mesh::tessellate_cone,
     cut_tube_mesh::tessellate_cut_tube, elcone_mesh::tessellate_elcone,
@@ -197,7 +198,7 @@ fn cone_quarter_hollow() {
 
 #[test]
 fn sphere_quarter_phi() {
-    let m = tessellate_sphere(0.0, 10.0, 0.0, PI / 2.0, 0.0, PI, SEG);
+    let m = tessellate_sphere(0.0, 10.0, 0.0, PI / 2.0, 0.0, PI, SEG, SEG / 2);
     let expected = (4.0 / 3.0) * PI * 1000.0 / 4.0;
     assert_volume(&m, expected, 0.02, "sphere quarter");
     assert_normals_match_winding(&m, "sphere quarter");
@@ -206,7 +207,7 @@ fn sphere_quarter_phi() {
 #[test]
 fn sphere_shell_segment() {
     // Hollow shell, partial phi AND partial theta.
-    let m = tessellate_sphere(5.0, 10.0, 0.5, PI / 2.0, 0.4, PI / 3.0, SEG);
+    let m = tessellate_sphere(5.0, 10.0, 0.5, PI / 2.0, 0.4, PI / 3.0, SEG, SEG / 2);
     // V = (dphi) * (r2^3 - r1^3)/3 * (cos(t0) - cos(t0+dt))
     let expected =
         (PI / 2.0) * (1000.0 - 125.0) / 3.0 * ((0.4f64).cos() - (0.4f64 + PI / 3.0).cos());
@@ -231,7 +232,7 @@ fn solid_sector_volume(r: f64, dphi: f64, t0: f64, dt: f64) -> f64 {
 #[test]
 fn sphere_solid_narrow_theta_cut() {
     // Previously -60% (the disk sat above the missing cone).
-    let m = tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, PI / 4.0, SEG);
+    let m = tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, PI / 4.0, SEG, SEG / 2);
     let expected = solid_sector_volume(50.0, 2.0 * PI, 0.0, PI / 4.0);
     assert_volume(&m, expected, 0.02, "sphere solid narrow theta cut");
     assert_normals_match_winding(&m, "sphere solid narrow theta cut");
@@ -240,7 +241,7 @@ fn sphere_solid_narrow_theta_cut() {
 #[test]
 fn sphere_solid_wide_theta_cut() {
     // Past PI/2 the sign flips: previously +12.5%.
-    let m = tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, 2.0 * PI / 3.0, SEG);
+    let m = tessellate_sphere(0.0, 50.0, 0.0, 2.0 * PI, 0.0, 2.0 * PI / 3.0, SEG, SEG / 2);
     let expected = solid_sector_volume(50.0, 2.0 * PI, 0.0, 2.0 * PI / 3.0);
     assert_volume(&m, expected, 0.02, "sphere solid wide theta cut");
     assert_normals_match_winding(&m, "sphere solid wide theta cut");
@@ -252,7 +253,7 @@ fn sphere_solid_theta_and_phi_cut() {
     // its apex at the origin, not a strip against the z-axis. The two coincide
     // only when theta spans the full 0..PI, which every prior test did.
     let (t0, dt, dphi) = (PI / 6.0, PI / 2.0, PI / 2.0);
-    let m = tessellate_sphere(0.0, 50.0, 0.0, dphi, t0, dt, SEG);
+    let m = tessellate_sphere(0.0, 50.0, 0.0, dphi, t0, dt, SEG, SEG / 2);
     let expected = solid_sector_volume(50.0, dphi, t0, dt);
     assert_volume(&m, expected, 0.02, "sphere solid theta+phi cut");
     assert_normals_match_winding(&m, "sphere solid theta+phi cut");
@@ -362,7 +363,7 @@ fn polyhedra_quarter() {
 
 #[test]
 fn torus_quarter() {
-    let m = tessellate_torus(0.0, 3.0, 10.0, 0.0, PI / 2.0, SEG);
+    let m = tessellate_torus(0.0, 3.0, 10.0, 0.0, PI / 2.0, SEG, SEG);
     // Pappus: V = (pi * r^2) * (R * dphi)
     let expected = PI * 9.0 * 10.0 * (PI / 2.0);
     assert_volume(&m, expected, 0.02, "torus quarter");
@@ -395,7 +396,7 @@ fn cut_tube_quarter_straight_cuts() {
 fn twisted_tubs_quarter_no_twist() {
     // Zero twist collapses the hyperboloid to a plain tube: the exact limit,
     // since tanStereo is proportional to tan(twist/2).
-    let m = tessellate_twisted_tubs(0.0, 10.0, -10.0, 10.0, PI / 2.0, 0.0, SEG);
+    let m = tessellate_twisted_tubs(0.0, 10.0, -10.0, 10.0, PI / 2.0, 0.0, SEG, SEG);
     assert_volume(
         &m,
         0.5 * (PI / 2.0) * 100.0 * 20.0,
@@ -407,7 +408,7 @@ fn twisted_tubs_quarter_no_twist() {
 
 #[test]
 fn ellipsoid_full_and_cut() {
-    let m = tessellate_ellipsoid(5.0, 8.0, 10.0, -10.0, 10.0, SEG);
+    let m = tessellate_ellipsoid(5.0, 8.0, 10.0, -10.0, 10.0, SEG, SEG / 2);
     assert_volume(
         &m,
         (4.0 / 3.0) * PI * 5.0 * 8.0 * 10.0,
@@ -418,7 +419,7 @@ fn ellipsoid_full_and_cut() {
 
     // Cut ellipsoid: V = pi*a*b * [(z2 - z1) - (z2^3 - z1^3)/(3 c^2)]
     let (z1, z2) = (-5.0_f64, 7.0_f64);
-    let m = tessellate_ellipsoid(5.0, 8.0, 10.0, z1, z2, SEG);
+    let m = tessellate_ellipsoid(5.0, 8.0, 10.0, z1, z2, SEG, SEG / 2);
     let expected = PI * 5.0 * 8.0 * ((z2 - z1) - (z2.powi(3) - z1.powi(3)) / (3.0 * 100.0));
     assert_volume(&m, expected, 0.02, "ellipsoid cut");
     assert_normals_match_winding(&m, "ellipsoid cut");
@@ -453,15 +454,21 @@ fn twisted_box_volume() {
 #[test]
 fn remaining_primitives_closed_and_outward() {
     assert_closed_outward(
-        &tessellate_hype(3.0, 6.0, 0.2, 0.3, 20.0, SEG),
+        &tessellate_hype(3.0, 6.0, 0.2, 0.3, 20.0, SEG, SEG / 2),
         "hype hollow",
     );
     assert_closed_outward(
-        &tessellate_hype(0.0, 6.0, 0.0, 0.3, 20.0, SEG),
+        &tessellate_hype(0.0, 6.0, 0.0, 0.3, 20.0, SEG, SEG / 2),
         "hype solid",
     );
-    assert_closed_outward(&tessellate_paraboloid(2.0, 5.0, 10.0, SEG), "paraboloid");
-    assert_closed_outward(&tessellate_elcone(0.5, 0.8, 10.0, 5.0, SEG), "elcone");
+    assert_closed_outward(
+        &tessellate_paraboloid(2.0, 5.0, 10.0, SEG, SEG / 2),
+        "paraboloid",
+    );
+    assert_closed_outward(
+        &tessellate_elcone(0.5, 0.8, 10.0, 5.0, SEG, SEG / 2),
+        "elcone",
+    );
     assert_closed_outward(
         &tessellate_trap(20.0, 0.1, 0.2, 12.0, 10.0, 8.0, 0.05, 10.0, 9.0, 7.0, 0.05),
         "trap",
@@ -575,7 +582,7 @@ fn twisted_tubs_is_a_hyperboloid_not_a_cylinder() {
     let z_half = 10.0;
     let twist = PI / 3.0; // 60 degrees
 
-    let m = tessellate_twisted_tubs(0.0, r_mid, -z_half, z_half, 2.0 * PI, twist, 256);
+    let m = tessellate_twisted_tubs(0.0, r_mid, -z_half, z_half, 2.0 * PI, twist, 256, 256);
 
     // Solid of revolution of r(z)^2 = r_mid^2 + z^2 tan^2:
     //   V = pi * INT(-h..h) r(z)^2 dz = 2*pi*h*(r_mid^2 + h^2 tan^2 / 3)
@@ -606,3 +613,124 @@ fn twisted_tubs_is_a_hyperboloid_not_a_cylinder() {
         "end radius {max_r:.4}, expected r_mid/cos(twist/2) = {want_end:.4}"
     );
 }
+
+#[test]
+fn primitives_pass_the_runtime_integrity_check() {
+    // `mesh::integrity` is what the checks above became at runtime: every
+    // primitive, including sectors, bores and twists, must come out closed,
+    // outward and free of crossings. Facets collapsed onto a pole or the axis
+    // are allowed; they have no area and no effect on the surface.
+    let meshes: Vec<(&str, TriangleMesh)> = vec![
+        ("box", tessellate_box(10.0, 20.0, 30.0)),
+        ("tube", tessellate_tube(5.0, 10.0, 20.0, 0.7, PI / 2.0, SEG)),
+        (
+            "cone",
+            tessellate_cone(2.0, 10.0, 2.0, 5.0, 20.0, 0.3, PI / 2.0, SEG),
+        ),
+        (
+            "sphere",
+            tessellate_sphere(4.0, 10.0, 0.0, 2.0 * PI, 0.0, PI, SEG, SEG / 2),
+        ),
+        (
+            "sphere sector",
+            tessellate_sphere(4.0, 10.0, 0.2, 1.0, 0.3, 1.2, SEG, SEG / 2),
+        ),
+        (
+            "torus",
+            tessellate_torus(2.0, 5.0, 20.0, 0.0, 2.0 * PI, SEG, SEG),
+        ),
+        ("trd", tessellate_trd(10.0, 20.0, 5.0, 8.0, 30.0)),
+        (
+            "trap",
+            tessellate_trap(30.0, 0.2, 0.3, 10.0, 8.0, 8.0, 0.1, 14.0, 11.2, 11.2, 0.1),
+        ),
+        (
+            "ellipsoid",
+            tessellate_ellipsoid(10.0, 15.0, 20.0, -5.0, 12.0, SEG, SEG / 2),
+        ),
+        ("eltube", tessellate_eltube(10.0, 5.0, 20.0, SEG)),
+        (
+            "elcone",
+            tessellate_elcone(0.5, 0.3, 20.0, 10.0, SEG, SEG / 2),
+        ),
+        (
+            "paraboloid",
+            tessellate_paraboloid(5.0, 15.0, 20.0, SEG, SEG / 2),
+        ),
+        (
+            "hype",
+            tessellate_hype(5.0, 10.0, 0.3, 0.5, 20.0, SEG, SEG / 2),
+        ),
+        (
+            "polycone",
+            tessellate_polycone(
+                &[(-10.0, 0.0, 5.0), (0.0, 2.0, 10.0), (10.0, 2.0, 4.0)],
+                0.0,
+                PI,
+                SEG,
+            ),
+        ),
+        (
+            "polyhedra",
+            tessellate_polyhedra(&[(-10.0, 2.0, 5.0), (10.0, 3.0, 8.0)], 0.0, 2.0 * PI, 6),
+        ),
+        (
+            "generic polycone",
+            tessellate_generic_polycone(
+                &[(1.0, -10.0), (8.0, -5.0), (5.0, 10.0), (1.0, 10.0)],
+                0.0,
+                2.0 * PI,
+                SEG,
+                None,
+            ),
+        ),
+        (
+            "xtru",
+            tessellate_xtru(
+                &[
+                    (-5.0, -5.0),
+                    (-5.0, 5.0),
+                    (0.0, 2.0),
+                    (5.0, 5.0),
+                    (5.0, -5.0),
+                ],
+                &[(-10.0, 0.0, 0.0, 1.0), (10.0, 2.0, 1.0, 0.5)],
+            ),
+        ),
+        (
+            "arb8",
+            tessellate_arb8(
+                10.0,
+                [
+                    [-5.0, -5.0],
+                    [-5.0, 5.0],
+                    [5.0, 5.0],
+                    [5.0, -5.0],
+                    [-3.0, -3.0],
+                    [-3.0, 3.0],
+                    [3.0, 3.0],
+                    [3.0, -3.0],
+                ],
+            ),
+        ),
+        (
+            "twisted box",
+            tessellate_twisted_box(PI / 6.0, 10.0, 15.0, 20.0, SEG),
+        ),
+        (
+            "twisted tubs",
+            tessellate_twisted_tubs(5.0, 10.0, -10.0, 10.0, PI, PI / 3.0, SEG, SEG),
+        ),
+    ];
+    for (name, mesh) in &meshes {
+        let report = analyze(mesh);
+        assert!(report.watertight, "{name}: {:?}", report.problems());
+        assert!(
+            report.consistently_oriented,
+            "{name}: {:?}",
+            report.problems()
+        );
+        assert_eq!(report.self_intersections, 0, "{name}");
+        assert!(report.volume > 0.0, "{name}: volume {}", report.volume);
+    }
+}
@@ -26,10 +26,15 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use gdml_studio_backend::eval::engine::EvalEngine;
+use gdml_studio_backend::gdml::constraints::{check_solids, Severity};
 use gdml_studio_backend::gdml::materials::serialize_gdml;
 use gdml_studio_backend::gdml::model::Solid;
 use gdml_studio_backend::gdml::parser::parse_gdml_from_bytes;
+use gdml_studio_backend::gdml::schema;
+use gdml_studio_backend::gdml::surfaces::check_surfaces;
 
+use gdml_studio_backend::mesh::csg::BooleanBackend;
+use gdml_studio_backend::mesh::quality::MeshQuality;
 use gdml_studio_backend::mesh::tessellator::tessellate_all_solids;
 
 use quick_xml::events::Event;
@@ -546,7 +551,13 @@ fn scaleref_is_resolved_and_not_rewritten() {
     // The reference must actually scale the mesh: a 10mm box doubled is 20mm.
     let mut engine = EvalEngine::new();
     engine.evaluate_all(&doc.defines).unwrap();
-    let (meshes, _) = tessellate_all_solids(&doc.solids, &engine, 32).unwrap();
+    let (meshes, _) = tessellate_all_solids(
+        &doc.solids,
+        &engine,
+        &MeshQuality::fixed(32),
+        BooleanBackend::Bsp,
+    )
+    .unwrap();
     let big = meshes.get("Big").expect("scaled solid not tessellated");
     let extent = big
         .positions
@@ -566,6 +577,119 @@ fn scaleref_is_resolved_and_not_rewritten() {
     assert_tokens_preserved(src, &out);
 }
 
+#[test]
+fn matrix_defines_are_evaluated_and_written_in_place() {
+    // <matrix> was kept only as a raw element, so material property bindings
+    // pointed at data nothing could read. It is now a define: evaluated like
+    // Geant4 (entries become `name_i_j`, `name[i,j]` indexes from 1) and
+    // written back where the source declared it.
+    let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <constant name="n0" value="1.33"/>
+    <matrix name="RINDEX_W" coldim="2" values="1.5*eV n0
+                                              3.0*eV n0+0.01"/>
+    <matrix name="YIELD" coldim="1" values="100/MeV"/>
+    <constant name="n_high" value="RINDEX_W[2,2]"/>
+  </define>
+  <materials>
+    <material name="Water" state="liquid">
+      <property name="RINDEX" ref="RINDEX_W"/>
+      <D value="1"/><atom value="18"/>
+    </material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="10" y="10" z="10" lunit="mm"/>
+  </solids>
+  <structure>
+    <volume name="World"><materialref ref="Water"/><solidref ref="WorldBox"/></volume>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+
+    let doc = parse_gdml_from_bytes(src.as_bytes(), "matrix.gdml".to_string()).unwrap();
+    assert_eq!(doc.defines.matrices.len(), 2, "<matrix> defines not parsed");
+    assert!(doc.raw_unknown.is_empty(), "matrix still captured as raw");
+
+    let mut engine = EvalEngine::new();
+    engine.evaluate_all(&doc.defines).unwrap();
+    assert!(engine.take_warnings().is_empty());
+    let rindex = &engine.matrix_values["RINDEX_W"];
+    assert_eq!(rindex.rows(), 2);
+    assert!((rindex.get(1, 0).unwrap() - 3.0e-6).abs() < 1e-15);
+    assert_eq!(engine.context.get("RINDEX_W_0_1"), Some(1.33));
+    assert_eq!(engine.context.get("YIELD_0"), Some(100.0));
+    let n_high = engine.context.get("n_high").unwrap();
+    assert!((n_high - 1.34).abs() < 1e-12, "RINDEX_W[2,2] = {n_high}");
+
+    let out = serialize_gdml(&doc).unwrap();
+    let matrix_at = out.find("<matrix name=\"RINDEX_W\"").expect("matrix lost");
+    assert!(
+        out.find("name=\"n0\"").unwrap() < matrix_at
+            && matrix_at < out.find("name=\"n_high\"").unwrap(),
+        "matrix moved out of declaration order:\n{out}"
+    );
+    assert_tokens_preserved(src, &out);
+}
+
+#[test]
+fn optical_surfaces_are_typed_and_round_trip() {
+    let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gdml>
+  <define>
+    <matrix name="REFL" coldim="2" values="1.5*eV 0.98 3.0*eV 0.97"/>
+  </define>
+  <materials>
+    <material name="Vacuum" state="gas"><D value="1e-25"/><atom value="1.008"/></material>
+  </materials>
+  <solids>
+    <box name="WorldBox" x="100" y="100" z="100"/>
+    <box name="CrystalBox" x="10" y="10" z="10"/>
+    <opticalsurface name="Teflon" model="unified" finish="3" type="dielectric_dielectric" value="0.1">
+      <property name="REFLECTIVITY" ref="REFL"/>
+    </opticalsurface>
+  </solids>
+  <structure>
+    <volume name="Crystal"><materialref ref="Vacuum"/><solidref ref="CrystalBox"/></volume>
+    <volume name="World">
+      <materialref ref="Vacuum"/><solidref ref="WorldBox"/>
+      <physvol name="crystal_pv"><volumeref ref="Crystal"/></physvol>
+      <physvol><volumeref ref="Crystal"/><position name="p" x="20"/></physvol>
+    </volume>
+    <skinsurface name="CrystalWrap" surfaceproperty="Teflon"><volumeref ref="Crystal"/></skinsurface>
+    <bordersurface name="Gap" surfaceproperty="Teflon">
+      <physvolref ref="crystal_pv"/>
+      <physvolref ref="Crystal_PV"/>
+    </bordersurface>
+  </structure>
+  <setup name="Default" version="1.0"><world ref="World"/></setup>
+</gdml>"#;
+
+    let doc = parse_gdml_from_bytes(src.as_bytes(), "optics.gdml".to_string()).unwrap();
+    assert!(doc.raw_unknown.is_empty(), "surfaces still captured as raw");
+    let teflon = &doc.solids.optical_surfaces[0];
+    assert_eq!(teflon.finish, "3", "finish must be kept as written");
+    assert_eq!(teflon.properties[0].ref_name.as_deref(), Some("REFL"));
+    assert_eq!(doc.structure.skin_surfaces[0].volume_ref, "Crystal");
+    assert_eq!(
+        doc.structure.border_surfaces[0].physvol_refs,
+        ["crystal_pv", "Crystal_PV"]
+    );
+
+    // The unnamed placement is found under Geant4's generated name.
+    let mut engine = EvalEngine::new();
+    engine.evaluate_all(&doc.defines).unwrap();
+    let issues = check_surfaces(&doc, &engine);
+    assert!(issues.is_empty(), "{issues:?}");
+
+    let out = serialize_gdml(&doc).unwrap();
+    assert!(
+        out.find("</volume>").unwrap() < out.find("<skinsurface").unwrap(),
+        "surfaces must follow the volumes they name:\n{out}"
+    );
+    assert_tokens_preserved(src, &out);
+}
+
 #[test]
 fn doctype_survives_and_entities_are_reported() {
     // The declaration must round-trip -- losing it permanently breaks any file
@@ -654,6 +778,55 @@ fn export_is_idempotent_across_the_corpus() {
     }
 }
 
+#[test]
+fn corpus_and_its_export_satisfy_the_schema() {
+    // Every sample loads in Geant4, so a schema issue in one is a wrong rule,
+    // and one in its export is the writer producing something Geant4 refuses.
+    let mut failures = Vec::new();
+    for path in sample_files() {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let src = std::fs::read_to_string(&path).unwrap();
+        let exported = round_trip(src.as_bytes(), &name);
+        for issue in schema::validate(&src) {
+            failures.push(format!("{name}: {issue}"));
+        }
+        for issue in schema::validate(&exported) {
+            failures.push(format!("{name} (export): {issue}"));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "schema issues:\n  {}",
+        failures.join("\n  ")
+    );
+}
+
+#[test]
+fn corpus_solids_pass_the_geant4_constructor_checks() {
+    // Same reasoning as the schema: every sample builds in Geant4, so an
+    // error here is a check stricter than the constructor it mirrors.
+    let mut failures = Vec::new();
+    for path in sample_files() {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let src = std::fs::read(&path).unwrap();
+        let doc = parse_gdml_from_bytes(&src, name.clone()).unwrap();
+        let mut engine = EvalEngine::new();
+        if engine.evaluate_all(&doc.defines).is_err() {
+            continue;
+        }
+        for (solid, issues) in check_solids(&doc.solids, &engine) {
+            for issue in issues.iter().filter(|i| i.severity == Severity::Error) {
+                failures.push(format!("{name}: {solid}: {}", issue.message));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "constructor check failures:\n  {}",
+        failures.join("\n  ")
+    );
+}
+
 #[test]
 fn export_drops_nothing_from_the_corpus() {
     // The net that catches regressions in constructs with no dedicated fixture.